@@ -0,0 +1,433 @@
+//! access_control.rs — PIN-code door lock built from keypad, display, actuator and KV store
+//!
+//! Keys: digits enter the PIN, `#` submits, `*` clears. Pressing `*` with nothing entered
+//! starts the change-PIN flow. After `max_attempts` wrong PINs the keypad is locked out.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let actuator = ServoLock::new(servo, 0.0, 90.0);
+//! let mut lock = AccessControl::new(keypad, lcd, actuator, &mut store, AccessControlConfig::default());
+//! lock.run().await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_hal::digital::OutputPin;
+
+use crate::{
+    HeaplessString, KeyInput, KvStore, KvStoreError, Servo, TextDisplay, constant_time_eq,
+};
+
+/// Longest supported PIN
+pub const ACCESS_CONTROL_MAX_PIN_LEN: usize = 8;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum AccessControlError {
+    #[error("Failed to access PIN storage: {0}")]
+    Store(#[from] KvStoreError),
+    #[error("Failed to drive lock actuator")]
+    Actuator,
+    #[error("PIN must be {min} to {max} digits")]
+    InvalidPin { min: usize, max: usize },
+}
+
+/// Something that physically locks and unlocks
+#[allow(async_fn_in_trait)]
+pub trait LockActuator {
+    async fn lock(&mut self) -> Result<(), AccessControlError>;
+    async fn unlock(&mut self) -> Result<(), AccessControlError>;
+}
+
+/// Servo-driven latch
+pub struct ServoLock<'a> {
+    servo: Servo<'a>,
+    locked_deg: f32,
+    unlocked_deg: f32,
+}
+
+impl<'a> ServoLock<'a> {
+    pub fn new(servo: Servo<'a>, locked_deg: f32, unlocked_deg: f32) -> Self {
+        Self {
+            servo,
+            locked_deg,
+            unlocked_deg,
+        }
+    }
+}
+
+impl LockActuator for ServoLock<'_> {
+    async fn lock(&mut self) -> Result<(), AccessControlError> {
+        self.servo
+            .set_angle(self.locked_deg)
+            .map_err(|_| AccessControlError::Actuator)
+    }
+
+    async fn unlock(&mut self) -> Result<(), AccessControlError> {
+        self.servo
+            .set_angle(self.unlocked_deg)
+            .map_err(|_| AccessControlError::Actuator)
+    }
+}
+
+/// Solenoid or electric strike behind a relay
+pub struct RelayLock<P: OutputPin> {
+    pin: P,
+    active_high: bool,
+}
+
+impl<P: OutputPin> RelayLock<P> {
+    /// `active_high`: the relay is energised (unlocked) when the pin is high.
+    pub fn new(pin: P, active_high: bool) -> Self {
+        Self { pin, active_high }
+    }
+
+    fn set_energised(&mut self, energised: bool) -> Result<(), AccessControlError> {
+        let result = if energised == self.active_high {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        };
+        result.map_err(|_| AccessControlError::Actuator)
+    }
+}
+
+impl<P: OutputPin> LockActuator for RelayLock<P> {
+    async fn lock(&mut self) -> Result<(), AccessControlError> {
+        self.set_energised(false)
+    }
+
+    async fn unlock(&mut self) -> Result<(), AccessControlError> {
+        self.set_energised(true)
+    }
+}
+
+/// Access control configuration
+#[derive(Debug, Clone)]
+pub struct AccessControlConfig {
+    /// KV store key holding the PIN
+    pub pin_key: &'static str,
+    /// PIN used until one is stored
+    pub default_pin: &'static str,
+    pub min_pin_len: usize,
+    /// Wrong PINs in a row before lockout
+    pub max_attempts: u8,
+    pub lockout: Duration,
+    /// How long the lock stays open
+    pub unlock_time: Duration,
+    /// Entry is abandoned after this long without a key press
+    pub entry_timeout: Duration,
+    pub submit_key: char,
+    pub clear_key: char,
+}
+
+impl Default for AccessControlConfig {
+    fn default() -> Self {
+        Self {
+            pin_key: "lock/pin",
+            default_pin: "1234",
+            min_pin_len: 4,
+            max_attempts: 3,
+            lockout: Duration::from_secs(30),
+            unlock_time: Duration::from_secs(5),
+            entry_timeout: Duration::from_secs(10),
+            submit_key: '#',
+            clear_key: '*',
+        }
+    }
+}
+
+type Pin = HeaplessString<ACCESS_CONTROL_MAX_PIN_LEN>;
+
+/// Outcome of reading a PIN from the keypad
+enum Entry {
+    Pin(Pin),
+    /// Clear key on an empty entry
+    Menu,
+    TimedOut,
+}
+
+/// Outcome of checking an entered PIN
+#[derive(Debug, PartialEq, Eq)]
+enum Attempt {
+    Granted,
+    /// Wrong PIN; how many wrong ones in a row so far
+    Denied(u8),
+    /// Wrong PIN that used up the last attempt
+    LockedOut,
+}
+
+/// Keypad door lock
+pub struct AccessControl<'s, K, D, A, S>
+where
+    K: KeyInput,
+    D: TextDisplay,
+    A: LockActuator,
+    S: KvStore,
+{
+    keys: K,
+    display: D,
+    actuator: A,
+    store: &'s mut S,
+    config: AccessControlConfig,
+    failed_attempts: u8,
+}
+
+impl<'s, K, D, A, S> AccessControl<'s, K, D, A, S>
+where
+    K: KeyInput,
+    D: TextDisplay,
+    A: LockActuator,
+    S: KvStore,
+{
+    pub fn new(
+        keys: K,
+        display: D,
+        actuator: A,
+        store: &'s mut S,
+        config: AccessControlConfig,
+    ) -> Self {
+        Self {
+            keys,
+            display,
+            actuator,
+            store,
+            config,
+            failed_attempts: 0,
+        }
+    }
+
+    /// Replace the stored PIN.
+    pub fn set_pin(&mut self, pin: &str) -> Result<(), AccessControlError> {
+        if pin.len() < self.config.min_pin_len
+            || pin.len() > ACCESS_CONTROL_MAX_PIN_LEN
+            || !pin.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(AccessControlError::InvalidPin {
+                min: self.config.min_pin_len,
+                max: ACCESS_CONTROL_MAX_PIN_LEN,
+            });
+        }
+        self.store.set(self.config.pin_key, pin.as_bytes())?;
+        Ok(())
+    }
+
+    /// Check `candidate` against the stored PIN (or the default if none is stored).
+    pub fn verify_pin(&mut self, candidate: &str) -> Result<bool, AccessControlError> {
+        let mut buf = [0u8; ACCESS_CONTROL_MAX_PIN_LEN];
+        let stored = match self.store.get(self.config.pin_key, &mut buf)? {
+            Some(len) => &buf[..len],
+            None => self.config.default_pin.as_bytes(),
+        };
+        Ok(constant_time_eq(stored, candidate.as_bytes()))
+    }
+
+    /// Serve the keypad forever.
+    pub async fn run(&mut self) -> ! {
+        let _ = self.actuator.lock().await;
+        loop {
+            match self.read_pin("Enter PIN:").await {
+                Entry::Pin(pin) => self.attempt(pin.as_str()).await,
+                Entry::Menu => self.change_pin().await,
+                Entry::TimedOut => {}
+            }
+        }
+    }
+
+    async fn attempt(&mut self, pin: &str) {
+        if !self.admit(pin).await {
+            return;
+        }
+        self.show("Access granted");
+        if self.actuator.unlock().await.is_ok() {
+            Timer::after(self.config.unlock_time).await;
+        }
+        let _ = self.actuator.lock().await;
+    }
+
+    /// Check a PIN typed at the keypad. Wrong ones are reported and count towards the
+    /// lockout, whichever prompt they were typed at.
+    async fn admit(&mut self, pin: &str) -> bool {
+        let attempt = self.check_attempt(pin);
+        let failed = match attempt {
+            Attempt::Granted => return true,
+            Attempt::Denied(failed) => failed,
+            Attempt::LockedOut => self.config.max_attempts,
+        };
+
+        let mut msg: HeaplessString<32> = HeaplessString::new();
+        let _ = write!(
+            msg,
+            "Wrong PIN\n{}/{} attempts",
+            failed, self.config.max_attempts
+        );
+        self.show(msg.as_str());
+        Timer::after_secs(2).await;
+
+        if attempt == Attempt::LockedOut {
+            self.lockout().await;
+        }
+        false
+    }
+
+    fn check_attempt(&mut self, pin: &str) -> Attempt {
+        if self.verify_pin(pin).unwrap_or(false) {
+            self.failed_attempts = 0;
+            return Attempt::Granted;
+        }
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        if self.failed_attempts >= self.config.max_attempts {
+            self.failed_attempts = 0;
+            return Attempt::LockedOut;
+        }
+        Attempt::Denied(self.failed_attempts)
+    }
+
+    async fn lockout(&mut self) {
+        let mut remaining = self.config.lockout.as_secs();
+        while remaining > 0 {
+            let mut msg: HeaplessString<32> = HeaplessString::new();
+            let _ = write!(msg, "Locked out\nWait {}s", remaining);
+            self.show(msg.as_str());
+            Timer::after_secs(1).await;
+            remaining -= 1;
+        }
+    }
+
+    async fn change_pin(&mut self) {
+        let Entry::Pin(current) = self.read_pin("Current PIN:").await else {
+            return;
+        };
+        if !self.admit(current.as_str()).await {
+            return;
+        }
+        let Entry::Pin(new_pin) = self.read_pin("New PIN:").await else {
+            return;
+        };
+        let Entry::Pin(confirm) = self.read_pin("Confirm PIN:").await else {
+            return;
+        };
+
+        let msg = if new_pin != confirm {
+            "PINs differ"
+        } else if self.set_pin(new_pin.as_str()).is_ok() {
+            "PIN changed"
+        } else {
+            "Invalid PIN"
+        };
+        self.show(msg);
+        Timer::after_secs(2).await;
+    }
+
+    /// Collect digits until the submit key, echoing `*` for each one.
+    async fn read_pin(&mut self, prompt: &str) -> Entry {
+        let mut pin = Pin::new();
+        loop {
+            let mut screen: HeaplessString<32> = HeaplessString::new();
+            let _ = writeln!(screen, "{}", prompt);
+            for _ in 0..pin.len() {
+                let _ = screen.push('*');
+            }
+            self.show(screen.as_str());
+
+            let Ok(key) = with_timeout(self.config.entry_timeout, self.keys.wait_for_key()).await
+            else {
+                return Entry::TimedOut;
+            };
+
+            if key == self.config.submit_key {
+                if !pin.is_empty() {
+                    return Entry::Pin(pin);
+                }
+            } else if key == self.config.clear_key {
+                if pin.is_empty() {
+                    return Entry::Menu;
+                }
+                pin.clear();
+            } else if key.is_ascii_digit() {
+                let _ = pin.push(key);
+            }
+        }
+    }
+
+    fn show(&mut self, msg: &str) {
+        let _ = self.display.display_str(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockTextDisplay;
+
+    struct NoKeys;
+
+    impl KeyInput for NoKeys {
+        async fn wait_for_key(&mut self) -> char {
+            unreachable!("no keys in this test")
+        }
+    }
+
+    struct NoLock;
+
+    impl LockActuator for NoLock {
+        async fn lock(&mut self) -> Result<(), AccessControlError> {
+            Ok(())
+        }
+
+        async fn unlock(&mut self) -> Result<(), AccessControlError> {
+            Ok(())
+        }
+    }
+
+    /// Holds just the PIN
+    #[derive(Default)]
+    struct Store {
+        pin: Option<Pin>,
+    }
+
+    impl KvStore for Store {
+        fn get(&mut self, _key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvStoreError> {
+            Ok(self.pin.as_ref().map(|pin| {
+                buf[..pin.len()].copy_from_slice(pin.as_str().as_bytes());
+                pin.len()
+            }))
+        }
+
+        fn set(&mut self, _key: &str, value: &[u8]) -> Result<(), KvStoreError> {
+            let pin = core::str::from_utf8(value).map_err(|_| KvStoreError::ValueTooLong)?;
+            self.pin = Some(pin.try_into().map_err(|_| KvStoreError::ValueTooLong)?);
+            Ok(())
+        }
+
+        fn remove(&mut self, _key: &str) -> Result<(), KvStoreError> {
+            self.pin = None;
+            Ok(())
+        }
+
+        fn remove_prefix(&mut self, _prefix: &str) -> Result<usize, KvStoreError> {
+            Ok(0)
+        }
+    }
+
+    /// The current-PIN prompt of the change-PIN menu goes through the same check as the
+    /// entry prompt, so guessing there runs into the lockout too.
+    #[test]
+    fn wrong_pins_lock_out() {
+        let mut store = Store::default();
+        let mut lock = AccessControl::new(
+            NoKeys,
+            MockTextDisplay::new(2, 16),
+            NoLock,
+            &mut store,
+            AccessControlConfig::default(),
+        );
+        assert_eq!(lock.check_attempt("0000"), Attempt::Denied(1));
+        assert_eq!(lock.check_attempt("1234"), Attempt::Granted);
+        assert_eq!(lock.check_attempt("0000"), Attempt::Denied(1));
+        assert_eq!(lock.check_attempt("1111"), Attempt::Denied(2));
+        assert_eq!(lock.check_attempt("2222"), Attempt::LockedOut);
+        assert_eq!(lock.check_attempt("3333"), Attempt::Denied(1));
+    }
+}
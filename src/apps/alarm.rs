@@ -0,0 +1,382 @@
+//! alarm.rs — weekly alarms for [`crate::Clock`], persisted in a [`KvStore`]
+//!
+//! # Example
+//!
+//! ```ignore
+//! static ALARM_CONTROL: AlarmControl = AlarmControl::new();
+//!
+//! clock.load_alarms(&mut store)?;
+//! clock.alarms_mut().set(0, Alarm::new(7, 30, WeekdaySet::WORKDAYS))?;
+//! clock.save_alarms(&mut store)?;
+//!
+//! // Button tasks call ALARM_CONTROL.snooze() / ALARM_CONTROL.dismiss()
+//! let mut buzzer = PinAlarmSink::new(Output::new(p.PIN_15, Level::Low), true);
+//! clock.run_with_alarms(&mut buzzer, &ALARM_CONTROL).await;
+//! ```
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embedded_hal::digital::OutputPin;
+
+use crate::{DateTime, KvStore, KvStoreError, Weekday};
+
+/// Number of alarm slots
+pub const ALARM_SLOTS: usize = 8;
+/// KV store key holding the alarm table
+pub const ALARM_STORE_KEY: &str = "clock/alarms";
+
+const ALARM_RECORD_LEN: usize = 4;
+const FLAG_ENABLED: u8 = 0x01;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum AlarmError {
+    #[error("Alarm slot out of range")]
+    InvalidSlot,
+    #[error("Alarm time out of range")]
+    InvalidTime,
+    #[error("Failed to access alarm storage: {0}")]
+    Store(#[from] KvStoreError),
+}
+
+/// Set of weekdays an alarm repeats on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct WeekdaySet(u8);
+
+impl WeekdaySet {
+    pub const NONE: WeekdaySet = WeekdaySet(0);
+    pub const EVERY_DAY: WeekdaySet = WeekdaySet(0x7F);
+    pub const WORKDAYS: WeekdaySet = WeekdaySet(0x1F);
+    pub const WEEKEND: WeekdaySet = WeekdaySet(0x60);
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & 0x7F)
+    }
+
+    /// Bit 0 = Monday … bit 6 = Sunday
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.index()) != 0
+    }
+
+    pub fn with(self, day: Weekday) -> Self {
+        Self(self.0 | (1 << day.index()))
+    }
+
+    pub fn without(self, day: Weekday) -> Self {
+        Self(self.0 & !(1 << day.index()))
+    }
+}
+
+/// Alarm at a local time on selected weekdays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+    pub days: WeekdaySet,
+    pub enabled: bool,
+}
+
+impl Alarm {
+    /// Enabled alarm
+    pub fn new(hour: u8, minute: u8, days: WeekdaySet) -> Self {
+        Self {
+            hour,
+            minute,
+            days,
+            enabled: true,
+        }
+    }
+
+    /// Whether the alarm goes off during the minute of `local`.
+    pub fn matches(&self, local: &DateTime) -> bool {
+        self.enabled
+            && self.hour == local.hour
+            && self.minute == local.minute
+            && self.days.contains(local.weekday())
+    }
+
+    fn to_bytes(self) -> [u8; ALARM_RECORD_LEN] {
+        let flags = if self.enabled { FLAG_ENABLED } else { 0 };
+        [self.hour, self.minute, self.days.bits(), flags]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let alarm = Self {
+            hour: bytes[0],
+            minute: bytes[1],
+            days: WeekdaySet::from_bits(bytes[2]),
+            enabled: bytes[3] & FLAG_ENABLED != 0,
+        };
+        (alarm.hour < 24 && alarm.minute < 60).then_some(alarm)
+    }
+}
+
+/// Alarm table plus snooze state
+#[derive(Debug, Clone, Default)]
+pub struct Alarms {
+    slots: [Option<Alarm>; ALARM_SLOTS],
+    /// Slot and UTC time of a pending snoozed alarm
+    snoozed: Option<(usize, u64)>,
+    /// Minute (UTC seconds / 60) in which each slot last fired, so each fires once
+    last_fired_minute: [Option<u64>; ALARM_SLOTS],
+}
+
+impl Alarms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Alarm> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn set(&mut self, slot: usize, alarm: Alarm) -> Result<(), AlarmError> {
+        if alarm.hour >= 24 || alarm.minute >= 60 {
+            return Err(AlarmError::InvalidTime);
+        }
+        *self.slots.get_mut(slot).ok_or(AlarmError::InvalidSlot)? = Some(alarm);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, slot: usize) -> Result<(), AlarmError> {
+        *self.slots.get_mut(slot).ok_or(AlarmError::InvalidSlot)? = None;
+        if matches!(self.snoozed, Some((s, _)) if s == slot) {
+            self.snoozed = None;
+        }
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, slot: usize, enabled: bool) -> Result<(), AlarmError> {
+        let alarm = self
+            .slots
+            .get_mut(slot)
+            .ok_or(AlarmError::InvalidSlot)?
+            .as_mut()
+            .ok_or(AlarmError::InvalidSlot)?;
+        alarm.enabled = enabled;
+        Ok(())
+    }
+
+    /// Occupied slots and their alarms
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Alarm)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, alarm)| alarm.as_ref().map(|a| (slot, a)))
+    }
+
+    /// Re-fire `slot` at `utc_unix`.
+    pub fn snooze(&mut self, slot: usize, utc_unix: u64) {
+        self.snoozed = Some((slot, utc_unix));
+    }
+
+    /// Drop a pending snooze.
+    pub fn cancel_snooze(&mut self) {
+        self.snoozed = None;
+    }
+
+    /// Slot of an alarm that should fire now, if any. Each alarm fires at most once per
+    /// minute, so alarms set for the same minute come one call after the other; a due
+    /// snooze takes precedence.
+    pub fn due(&mut self, utc_unix: u64, local: &DateTime) -> Option<usize> {
+        if let Some((slot, at)) = self.snoozed
+            && utc_unix >= at
+        {
+            self.snoozed = None;
+            return Some(slot);
+        }
+
+        let minute = utc_unix / 60;
+        let (slot, _) = self.iter().find(|&(slot, alarm)| {
+            alarm.matches(local) && self.last_fired_minute[slot] != Some(minute)
+        })?;
+        self.last_fired_minute[slot] = Some(minute);
+        Some(slot)
+    }
+
+    /// Load the table saved by [`Alarms::save`]; a missing entry leaves all slots empty.
+    pub fn load(&mut self, store: &mut impl KvStore) -> Result<(), AlarmError> {
+        let mut buf = [0u8; ALARM_SLOTS * ALARM_RECORD_LEN];
+        self.slots = [None; ALARM_SLOTS];
+        let Some(len) = store.get(ALARM_STORE_KEY, &mut buf)? else {
+            return Ok(());
+        };
+
+        for (slot, record) in buf[..len].chunks_exact(ALARM_RECORD_LEN).enumerate() {
+            // Unused slots are stored as 0xFF
+            if record[0] != 0xFF {
+                self.slots[slot] = Alarm::from_bytes(record);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, store: &mut impl KvStore) -> Result<(), AlarmError> {
+        let mut buf = [0xFFu8; ALARM_SLOTS * ALARM_RECORD_LEN];
+        for (slot, alarm) in self.iter() {
+            let start = slot * ALARM_RECORD_LEN;
+            buf[start..start + ALARM_RECORD_LEN].copy_from_slice(&alarm.to_bytes());
+        }
+        store.set(ALARM_STORE_KEY, &buf)?;
+        Ok(())
+    }
+}
+
+/// What happens when an alarm goes off: buzzer, relay, or any user callback
+#[allow(async_fn_in_trait)]
+pub trait AlarmSink {
+    /// Alarm in `slot` started ringing.
+    async fn start(&mut self, slot: usize);
+
+    /// Alarm was snoozed, dismissed or timed out.
+    async fn stop(&mut self);
+}
+
+/// Active buzzer or relay on a GPIO
+pub struct PinAlarmSink<P: OutputPin> {
+    pin: P,
+    active_high: bool,
+}
+
+impl<P: OutputPin> PinAlarmSink<P> {
+    pub fn new(pin: P, active_high: bool) -> Self {
+        Self { pin, active_high }
+    }
+
+    fn set_active(&mut self, active: bool) {
+        let _ = if active == self.active_high {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        };
+    }
+}
+
+impl<P: OutputPin> AlarmSink for PinAlarmSink<P> {
+    async fn start(&mut self, _slot: usize) {
+        self.set_active(true);
+    }
+
+    async fn stop(&mut self) {
+        self.set_active(false);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AlarmCommand {
+    Snooze,
+    Dismiss,
+}
+
+/// Snooze/dismiss requests from buttons or other tasks to a ringing alarm
+pub struct AlarmControl {
+    signal: Signal<CriticalSectionRawMutex, AlarmCommand>,
+}
+
+impl AlarmControl {
+    pub const fn new() -> Self {
+        Self {
+            signal: Signal::new(),
+        }
+    }
+
+    pub fn snooze(&self) {
+        self.signal.signal(AlarmCommand::Snooze);
+    }
+
+    pub fn dismiss(&self) {
+        self.signal.signal(AlarmCommand::Dismiss);
+    }
+
+    /// Forget presses made while no alarm was ringing.
+    pub fn reset(&self) {
+        self.signal.reset();
+    }
+
+    pub async fn wait(&self) -> AlarmCommand {
+        self.signal.wait().await
+    }
+}
+
+impl Default for AlarmControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(hour: u8, minute: u8) -> DateTime {
+        // 2024-07-01 is a Monday
+        DateTime {
+            year: 2024,
+            month: 7,
+            day: 1,
+            hour,
+            minute,
+            second: 0,
+        }
+    }
+
+    #[test]
+    fn fires_once_per_matching_minute() {
+        let mut alarms = Alarms::new();
+        alarms
+            .set(2, Alarm::new(7, 30, WeekdaySet::WORKDAYS))
+            .unwrap();
+        alarms
+            .set(3, Alarm::new(7, 30, WeekdaySet::WEEKEND))
+            .unwrap();
+
+        let now = local(7, 30).to_unix();
+        assert_eq!(alarms.due(now, &local(7, 30)), Some(2));
+        assert_eq!(alarms.due(now + 20, &local(7, 30)), None);
+        assert_eq!(alarms.due(now + 60, &local(7, 31)), None);
+    }
+
+    #[test]
+    fn alarms_in_the_same_minute_all_fire() {
+        let mut alarms = Alarms::new();
+        alarms
+            .set(1, Alarm::new(7, 30, WeekdaySet::EVERY_DAY))
+            .unwrap();
+        alarms
+            .set(4, Alarm::new(7, 30, WeekdaySet::WORKDAYS))
+            .unwrap();
+
+        let now = local(7, 30).to_unix();
+        assert_eq!(alarms.due(now, &local(7, 30)), Some(1));
+        assert_eq!(alarms.due(now + 5, &local(7, 30)), Some(4));
+        assert_eq!(alarms.due(now + 10, &local(7, 30)), None);
+    }
+
+    #[test]
+    fn snooze_refires() {
+        let mut alarms = Alarms::new();
+        alarms
+            .set(0, Alarm::new(6, 0, WeekdaySet::EVERY_DAY))
+            .unwrap();
+        let now = local(6, 0).to_unix();
+        assert_eq!(alarms.due(now, &local(6, 0)), Some(0));
+        alarms.snooze(0, now + 540);
+        assert_eq!(alarms.due(now + 539, &local(6, 8)), None);
+        assert_eq!(alarms.due(now + 540, &local(6, 9)), Some(0));
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let alarm = Alarm {
+            hour: 23,
+            minute: 59,
+            days: WeekdaySet::NONE.with(Weekday::Sunday),
+            enabled: false,
+        };
+        assert_eq!(Alarm::from_bytes(&alarm.to_bytes()), Some(alarm));
+        assert_eq!(Alarm::from_bytes(&[24, 0, 0, 1]), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! animation.rs — keyframe animation player for servos
+//!
+//! Keyframes are plain `const` data: at `time_ms`, `channel` should be at `angle_deg`.
+//! Between keyframes of the same channel the angle is interpolated, linearly unless the
+//! later keyframe is [`eased`](Keyframe::eased).
+//!
+//! # Example
+//!
+//! ```ignore
+//! const WAVE: &[Keyframe] = &[
+//!     Keyframe::new(0, 0, 0.0),
+//!     Keyframe::new(0, 1, 90.0),
+//!     Keyframe::new(500, 0, 90.0),
+//!     Keyframe::new(1000, 0, 0.0),
+//!     Keyframe::new(1000, 1, 45.0),
+//! ];
+//! static CONTROL: AnimationControl = AnimationControl::new();
+//!
+//! let mut player = AnimationPlayer::new(Animation::new(WAVE), PlaybackMode::Loop);
+//! CONTROL.play();
+//! player.run(&mut [&mut arm, &mut head], &CONTROL).await;
+//! ```
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{Easing, ServoChannel, ServoError};
+
+/// Default time between servo updates (one 50 Hz servo frame)
+pub const ANIMATION_FRAME: Duration = Duration::from_millis(20);
+
+/// Target angle for one channel at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Keyframe {
+    pub time_ms: u32,
+    /// Index into the channel slice passed to the player
+    pub channel: u8,
+    pub angle_deg: f32,
+    /// Curve of the move from the previous keyframe of the channel to this one
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    pub const fn new(time_ms: u32, channel: u8, angle_deg: f32) -> Self {
+        Self {
+            time_ms,
+            channel,
+            angle_deg,
+            easing: Easing::Linear,
+        }
+    }
+
+    /// Reach this keyframe along `easing`.
+    pub const fn eased(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
+}
+
+/// Keyframe sequence sorted by `time_ms`
+#[derive(Debug, Clone, Copy)]
+pub struct Animation<'k> {
+    keyframes: &'k [Keyframe],
+    duration_ms: u32,
+}
+
+impl<'k> Animation<'k> {
+    /// `keyframes` must be sorted by time. The animation lasts until the last keyframe.
+    pub const fn new(keyframes: &'k [Keyframe]) -> Self {
+        let mut duration_ms = 0;
+        let mut i = 0;
+        while i < keyframes.len() {
+            if keyframes[i].time_ms > duration_ms {
+                duration_ms = keyframes[i].time_ms;
+            }
+            i += 1;
+        }
+        Self {
+            keyframes,
+            duration_ms,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms as u64)
+    }
+
+    /// Angle of `channel` at `time_ms`, or `None` if the channel has no keyframes. Before
+    /// its first keyframe a channel holds the first angle; after the last, the last angle.
+    pub fn angle_at(&self, channel: u8, time_ms: u32) -> Option<f32> {
+        let mut before: Option<&Keyframe> = None;
+        for keyframe in self.keyframes.iter().filter(|k| k.channel == channel) {
+            if keyframe.time_ms <= time_ms {
+                before = Some(keyframe);
+                continue;
+            }
+            let Some(prev) = before else {
+                return Some(keyframe.angle_deg);
+            };
+            let span = (keyframe.time_ms - prev.time_ms) as f32;
+            let t = keyframe
+                .easing
+                .apply_f32((time_ms - prev.time_ms) as f32 / span);
+            return Some(prev.angle_deg + (keyframe.angle_deg - prev.angle_deg) * t);
+        }
+        before.map(|k| k.angle_deg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PlaybackMode {
+    Once,
+    Loop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AnimationCommand {
+    /// Start or resume
+    Play,
+    Pause,
+    /// Pause and rewind to the start
+    Stop,
+    SetMode(PlaybackMode),
+}
+
+/// Play/pause requests from other tasks to a running [`AnimationPlayer`]
+pub struct AnimationControl {
+    signal: Signal<CriticalSectionRawMutex, AnimationCommand>,
+}
+
+impl AnimationControl {
+    pub const fn new() -> Self {
+        Self {
+            signal: Signal::new(),
+        }
+    }
+
+    pub fn play(&self) {
+        self.signal.signal(AnimationCommand::Play);
+    }
+
+    pub fn pause(&self) {
+        self.signal.signal(AnimationCommand::Pause);
+    }
+
+    pub fn stop(&self) {
+        self.signal.signal(AnimationCommand::Stop);
+    }
+
+    pub fn set_mode(&self, mode: PlaybackMode) {
+        self.signal.signal(AnimationCommand::SetMode(mode));
+    }
+}
+
+impl Default for AnimationControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drives servo channels from an [`Animation`]
+pub struct AnimationPlayer<'k> {
+    animation: Animation<'k>,
+    mode: PlaybackMode,
+    frame: Duration,
+    /// Playback position when paused
+    position: Duration,
+    /// Instant corresponding to position zero while playing
+    started_at: Option<Instant>,
+}
+
+impl<'k> AnimationPlayer<'k> {
+    /// The player starts paused at the beginning.
+    pub fn new(animation: Animation<'k>, mode: PlaybackMode) -> Self {
+        Self {
+            animation,
+            mode,
+            frame: ANIMATION_FRAME,
+            position: Duration::MIN,
+            started_at: None,
+        }
+    }
+
+    /// Change the servo update interval.
+    pub fn set_frame(&mut self, frame: Duration) {
+        self.frame = frame;
+    }
+
+    pub fn set_mode(&mut self, mode: PlaybackMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_animation(&mut self, animation: Animation<'k>) {
+        self.animation = animation;
+        self.stop();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn play(&mut self) {
+        if self.started_at.is_none() {
+            if self.position >= self.animation.duration() {
+                self.position = Duration::MIN;
+            }
+            self.started_at = Some(Instant::now() - self.position);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.position = self.position();
+        self.started_at = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.started_at = None;
+        self.position = Duration::MIN;
+    }
+
+    /// Current playback position, wrapped in loop mode and clamped to the end otherwise.
+    pub fn position(&self) -> Duration {
+        let Some(started_at) = self.started_at else {
+            return self.position;
+        };
+        let elapsed = started_at.elapsed();
+        let duration = self.animation.duration();
+        match self.mode {
+            PlaybackMode::Loop if duration > Duration::MIN => {
+                Duration::from_ticks(elapsed.as_ticks() % duration.as_ticks())
+            }
+            _ => elapsed.min(duration),
+        }
+    }
+
+    /// Move every channel to its angle at the current position. Channels without
+    /// keyframes are left alone.
+    pub fn update(&mut self, channels: &mut [&mut dyn ServoChannel]) -> Result<(), ServoError> {
+        let time_ms = self.position().as_millis() as u32;
+        for (index, channel) in channels.iter_mut().enumerate() {
+            if let Some(angle) = self.animation.angle_at(index as u8, time_ms) {
+                channel.set_angle(angle)?;
+            }
+        }
+
+        if self.mode == PlaybackMode::Once
+            && self.is_playing()
+            && self.position() >= self.animation.duration()
+        {
+            self.pause();
+        }
+        Ok(())
+    }
+
+    /// Play the animation once from the start and return when it ends.
+    pub async fn play_once(
+        &mut self,
+        channels: &mut [&mut dyn ServoChannel],
+    ) -> Result<(), ServoError> {
+        self.mode = PlaybackMode::Once;
+        self.stop();
+        self.play();
+        while self.is_playing() {
+            self.update(channels)?;
+            Timer::after(self.frame).await;
+        }
+        Ok(())
+    }
+
+    /// Serve `control` forever, updating the servos every frame while playing.
+    pub async fn run(
+        &mut self,
+        channels: &mut [&mut dyn ServoChannel],
+        control: &AnimationControl,
+    ) -> ! {
+        loop {
+            let command = if self.is_playing() {
+                let _ = self.update(channels);
+                match select(control.signal.wait(), Timer::after(self.frame)).await {
+                    Either::First(command) => Some(command),
+                    Either::Second(()) => None,
+                }
+            } else {
+                Some(control.signal.wait().await)
+            };
+
+            match command {
+                Some(AnimationCommand::Play) => self.play(),
+                Some(AnimationCommand::Pause) => self.pause(),
+                Some(AnimationCommand::Stop) => self.stop(),
+                Some(AnimationCommand::SetMode(mode)) => {
+                    let position = self.position();
+                    self.mode = mode;
+                    if let Some(started_at) = self.started_at.as_mut() {
+                        *started_at = Instant::now() - position;
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYFRAMES: &[Keyframe] = &[
+        Keyframe::new(0, 0, 0.0),
+        Keyframe::new(100, 1, 90.0),
+        Keyframe::new(200, 0, 90.0),
+        Keyframe::new(400, 0, 30.0),
+    ];
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let animation = Animation::new(KEYFRAMES);
+        assert_eq!(animation.duration(), Duration::from_millis(400));
+        assert_eq!(animation.angle_at(0, 0), Some(0.0));
+        assert_eq!(animation.angle_at(0, 100), Some(45.0));
+        assert_eq!(animation.angle_at(0, 300), Some(60.0));
+        assert_eq!(animation.angle_at(0, 1000), Some(30.0));
+    }
+
+    #[test]
+    fn eases_into_keyframe() {
+        const EASED: &[Keyframe] = &[
+            Keyframe::new(0, 0, 0.0),
+            Keyframe::new(100, 0, 100.0).eased(Easing::EaseOutCubic),
+        ];
+        let animation = Animation::new(EASED);
+        let eased = animation.angle_at(0, 50).unwrap();
+        assert!((eased - 87.5).abs() < 0.01);
+        assert_eq!(animation.angle_at(0, 100), Some(100.0));
+    }
+
+    #[test]
+    fn holds_first_and_last_angle() {
+        let animation = Animation::new(KEYFRAMES);
+        assert_eq!(animation.angle_at(1, 0), Some(90.0));
+        assert_eq!(animation.angle_at(1, 300), Some(90.0));
+        assert_eq!(animation.angle_at(2, 50), None);
+    }
+}
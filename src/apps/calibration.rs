@@ -0,0 +1,862 @@
+//! calibration.rs — step-by-step calibration on a text display, saved to the KV store
+//!
+//! A [`CalibrationWizard`] walks through a [`CalibrationProcedure`]: each step shows its
+//! prompt, the user adjusts and confirms with a [`CalibrationInput`] (an encoder, a button
+//! or a keypad), the procedure takes its measurement, and the result goes to a [`KvStore`]
+//! once every step is done. Cancelling leaves the stored calibration untouched; a failed
+//! measurement is shown and the step repeated.
+//!
+//! Procedures for the crate's drivers: [`SoilMoistureCalibrator`] (dry and wet points),
+//! [`TouchCalibrator`] (three-point affine), [`ServoEndpointCalibrator`] (pulse limits,
+//! trimmed by hand), [`ServoTrimCalibrator`] (per-servo trims on top of its spec) and
+//! [`JoystickCalibrator`] (centre of an analog stick).
+//!
+//! Inputs: a [`RotaryEncoder`] turns to adjust and presses to confirm; a [`Button`]
+//! confirms with a short press and cancels with a long one; any [`KeyInput`] confirms with
+//! `#` or Enter, cancels with `*` or Escape and adjusts with `+`/`A` and `-`/`B`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut wizard = CalibrationWizard::new(lcd, encoder);
+//! let mut probe = SoilMoistureCalibrator::new(&mut soil, "soil/cal");
+//! match wizard.run(&mut probe, &mut store).await? {
+//!     CalibrationOutcome::Saved => info!("calibrated"),
+//!     CalibrationOutcome::Cancelled => {}
+//! }
+//! ```
+
+use core::fmt::Write;
+
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::Input;
+use embassy_rp::spi;
+use embassy_time::{Duration, Timer};
+
+use crate::{
+    ADC_MAX_RAW, AnalogInput, Button, ButtonEvent, EncoderEvent, HeaplessString, KeyInput, KvStore,
+    KvStoreError, RotaryEncoder, Servo, ServoCalibration, ServoSpec, SoilMoisture, TextDisplay,
+    TouchCalibration, Xpt2046,
+};
+
+pub const CALIBRATION_MAX_PROMPT_LEN: usize = 64;
+/// Pulse change per detent or key press of a [`ServoEndpointCalibrator`]
+pub const CALIBRATION_DEFAULT_SERVO_STEP_US: u32 = 10;
+
+/// How long a failure message stays up before the step repeats
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+/// Raw touch samples averaged per target
+const TOUCH_SAMPLES: u32 = 8;
+/// ADC samples averaged for the joystick centre
+const JOYSTICK_SAMPLES: u16 = 32;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum CalibrationError {
+    // Shown under "Failed:", so each fits a 16-column line
+    #[error("Storage error")]
+    Store(#[from] KvStoreError),
+    #[error("Measure failed")]
+    Device,
+    #[error("Unusable points")]
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CalibrationAction {
+    Confirm,
+    Cancel,
+    /// Nudge the current value, positive up
+    Adjust(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CalibrationOutcome {
+    Saved,
+    Cancelled,
+}
+
+/// User input driving a [`CalibrationWizard`]
+#[allow(async_fn_in_trait)]
+pub trait CalibrationInput {
+    async fn next_action(&mut self) -> CalibrationAction;
+}
+
+/// Turn to adjust, press to confirm. There is no cancel.
+impl CalibrationInput for RotaryEncoder<'_> {
+    async fn next_action(&mut self) -> CalibrationAction {
+        match self.wait_for_event().await {
+            EncoderEvent::Turn(detents) => CalibrationAction::Adjust(detents),
+            EncoderEvent::Press => CalibrationAction::Confirm,
+        }
+    }
+}
+
+/// Short press confirms, long press cancels. Nothing can be adjusted.
+impl CalibrationInput for Button<Input<'_>> {
+    async fn next_action(&mut self) -> CalibrationAction {
+        match self.wait_for_event().await {
+            ButtonEvent::ShortPress => CalibrationAction::Confirm,
+            ButtonEvent::LongPress(_) => CalibrationAction::Cancel,
+        }
+    }
+}
+
+impl<K: KeyInput> CalibrationInput for K {
+    async fn next_action(&mut self) -> CalibrationAction {
+        loop {
+            match self.wait_for_key().await {
+                '#' | '\n' | '\r' => return CalibrationAction::Confirm,
+                '*' | '\x1b' => return CalibrationAction::Cancel,
+                '+' | 'A' => return CalibrationAction::Adjust(1),
+                '-' | 'B' => return CalibrationAction::Adjust(-1),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The steps of one calibration and where its result is kept
+#[allow(async_fn_in_trait)]
+pub trait CalibrationProcedure {
+    /// Shown above every prompt, with the step number
+    fn title(&self) -> &str;
+
+    fn steps(&self) -> usize;
+
+    /// Instructions for `step`, with the current value if it can be adjusted
+    fn prompt(&self, step: usize, out: &mut impl Write);
+
+    /// `step` measures by itself, e.g. waits for a touch, instead of on confirm.
+    fn is_automatic(&self, _step: usize) -> bool {
+        false
+    }
+
+    /// Prepare `step`, e.g. move a servo to where the step starts.
+    async fn begin(&mut self, _step: usize) -> Result<(), CalibrationError> {
+        Ok(())
+    }
+
+    /// Change the value `step` is tuning by `delta` units.
+    async fn adjust(&mut self, _step: usize, _delta: i32) -> Result<(), CalibrationError> {
+        Ok(())
+    }
+
+    /// Take the measurement of `step`.
+    async fn capture(&mut self, step: usize) -> Result<(), CalibrationError>;
+
+    /// Check the result of all steps and store it.
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError>;
+}
+
+/// Runs [`CalibrationProcedure`]s on a display with an input
+pub struct CalibrationWizard<D: TextDisplay, I: CalibrationInput> {
+    display: D,
+    input: I,
+}
+
+impl<D: TextDisplay, I: CalibrationInput> CalibrationWizard<D, I> {
+    pub fn new(display: D, input: I) -> Self {
+        Self { display, input }
+    }
+
+    pub fn into_inner(self) -> (D, I) {
+        (self.display, self.input)
+    }
+
+    /// Go through every step of `procedure` and save the result to `store`. Errors of
+    /// [`CalibrationProcedure::save`] are returned, after showing them.
+    pub async fn run<P: CalibrationProcedure>(
+        &mut self,
+        procedure: &mut P,
+        store: &mut impl KvStore,
+    ) -> Result<CalibrationOutcome, CalibrationError> {
+        let mut step = 0;
+        while step < procedure.steps() {
+            procedure.begin(step).await?;
+            let captured = if procedure.is_automatic(step) {
+                self.show_step(procedure, step);
+                match select(procedure.capture(step), self.wait_for_cancel()).await {
+                    Either::First(captured) => captured,
+                    Either::Second(()) => return Ok(CalibrationOutcome::Cancelled),
+                }
+            } else {
+                if !self.wait_for_confirm(procedure, step).await? {
+                    return Ok(CalibrationOutcome::Cancelled);
+                }
+                procedure.capture(step).await
+            };
+            match captured {
+                Ok(()) => step += 1,
+                Err(error) => self.fail(&error).await,
+            }
+        }
+        if let Err(error) = procedure.save(store) {
+            self.fail(&error).await;
+            return Err(error);
+        }
+        self.show("Calibration\nsaved");
+        Ok(CalibrationOutcome::Saved)
+    }
+
+    /// Show the step and apply adjustments until confirmed; `false` if cancelled.
+    async fn wait_for_confirm<P: CalibrationProcedure>(
+        &mut self,
+        procedure: &mut P,
+        step: usize,
+    ) -> Result<bool, CalibrationError> {
+        loop {
+            self.show_step(procedure, step);
+            match self.input.next_action().await {
+                CalibrationAction::Confirm => return Ok(true),
+                CalibrationAction::Cancel => return Ok(false),
+                CalibrationAction::Adjust(delta) => procedure.adjust(step, delta).await?,
+            }
+        }
+    }
+
+    async fn wait_for_cancel(&mut self) {
+        while self.input.next_action().await != CalibrationAction::Cancel {}
+    }
+
+    fn show_step<P: CalibrationProcedure>(&mut self, procedure: &P, step: usize) {
+        let mut screen: HeaplessString<CALIBRATION_MAX_PROMPT_LEN> = HeaplessString::new();
+        let _ = writeln!(
+            screen,
+            "{} {}/{}",
+            procedure.title(),
+            step + 1,
+            procedure.steps()
+        );
+        procedure.prompt(step, &mut screen);
+        self.show(screen.as_str());
+    }
+
+    async fn fail(&mut self, error: &CalibrationError) {
+        let mut screen: HeaplessString<CALIBRATION_MAX_PROMPT_LEN> = HeaplessString::new();
+        let _ = write!(screen, "Failed:\n{error}");
+        self.show(screen.as_str());
+        Timer::after(MESSAGE_TIME).await;
+    }
+
+    /// Show `content` with each line cut to the display width.
+    fn show(&mut self, content: &str) {
+        let max_chars = self.display.max_chars_per_line();
+        let mut screen: HeaplessString<CALIBRATION_MAX_PROMPT_LEN> = HeaplessString::new();
+        for (i, line) in content.lines().enumerate() {
+            if i > 0 {
+                let _ = screen.push('\n');
+            }
+            for c in line.chars().take(max_chars) {
+                let _ = screen.push(c);
+            }
+        }
+        let _ = self.display.display_str(screen.as_str());
+    }
+}
+
+/// Dry and wet points of a [`SoilMoisture`] probe
+pub struct SoilMoistureCalibrator<'p, 'a> {
+    probe: &'p mut SoilMoisture<'a>,
+    key: &'p str,
+}
+
+impl<'p, 'a> SoilMoistureCalibrator<'p, 'a> {
+    pub fn new(probe: &'p mut SoilMoisture<'a>, key: &'p str) -> Self {
+        Self { probe, key }
+    }
+}
+
+impl CalibrationProcedure for SoilMoistureCalibrator<'_, '_> {
+    fn title(&self) -> &str {
+        "Soil probe"
+    }
+
+    fn steps(&self) -> usize {
+        2
+    }
+
+    fn prompt(&self, step: usize, out: &mut impl Write) {
+        let _ = match step {
+            0 => write!(out, "Dry: in air,\nthen confirm"),
+            _ => write!(out, "Wet: in water,\nthen confirm"),
+        };
+    }
+
+    async fn capture(&mut self, step: usize) -> Result<(), CalibrationError> {
+        let result = match step {
+            0 => self.probe.calibrate_dry().await,
+            _ => self.probe.calibrate_wet().await,
+        };
+        result.map(|_| ()).map_err(|_| CalibrationError::Device)
+    }
+
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+        let calibration = self.probe.calibration();
+        if calibration.dry_raw == calibration.wet_raw {
+            return Err(CalibrationError::Invalid);
+        }
+        self.probe.save_calibration(store, self.key)?;
+        Ok(())
+    }
+}
+
+/// Three-point calibration of an [`Xpt2046`] touch screen. Each step waits for a tap on
+/// its target, 10 % in from a corner; draw a mark there if the screen can.
+pub struct TouchCalibrator<'t, 'd, T: spi::Instance, M: spi::Mode> {
+    touch: &'t mut Xpt2046<'d, T, M>,
+    key: &'t str,
+    targets: [(u16, u16); 3],
+    raw: [(u16, u16); 3],
+}
+
+impl<'t, 'd, T: spi::Instance, M: spi::Mode> TouchCalibrator<'t, 'd, T, M> {
+    pub fn new(touch: &'t mut Xpt2046<'d, T, M>, key: &'t str) -> Self {
+        let (width, height) = (touch.config().width, touch.config().height);
+        let (left, top) = (width / 10, height / 10);
+        let (right, bottom) = (width - 1 - left, height - 1 - top);
+        Self {
+            touch,
+            key,
+            targets: [(left, top), (right, top), (left, bottom)],
+            raw: [(0, 0); 3],
+        }
+    }
+
+    /// Screen positions to tap, in step order
+    pub fn targets(&self) -> [(u16, u16); 3] {
+        self.targets
+    }
+
+    /// Average of a few firm samples, once the screen is pressed
+    async fn read_press(&mut self) -> Result<(u16, u16), CalibrationError> {
+        let interval = self.touch.config().sample_interval;
+        let (mut sum_x, mut sum_y, mut count) = (0u32, 0u32, 0u32);
+        while count < TOUCH_SAMPLES {
+            let sample = self
+                .touch
+                .read_raw()
+                .map_err(|_| CalibrationError::Device)?;
+            match sample {
+                Some((x, y, _)) => {
+                    sum_x += x as u32;
+                    sum_y += y as u32;
+                    count += 1;
+                }
+                // Lifted early: start over
+                None if count > 0 => (sum_x, sum_y, count) = (0, 0, 0),
+                None => {}
+            }
+            Timer::after(interval).await;
+        }
+        while self.touch.is_touched() {
+            Timer::after(interval).await;
+        }
+        Ok(((sum_x / count) as u16, (sum_y / count) as u16))
+    }
+}
+
+impl<T: spi::Instance, M: spi::Mode> CalibrationProcedure for TouchCalibrator<'_, '_, T, M> {
+    fn title(&self) -> &str {
+        "Touch"
+    }
+
+    fn steps(&self) -> usize {
+        3
+    }
+
+    fn prompt(&self, step: usize, out: &mut impl Write) {
+        let corner = ["top left", "top right", "bottom left"][step.min(2)];
+        let (x, y) = self.targets[step.min(2)];
+        let _ = write!(out, "Tap {x},{y}\n({corner})");
+    }
+
+    fn is_automatic(&self, _step: usize) -> bool {
+        true
+    }
+
+    async fn capture(&mut self, step: usize) -> Result<(), CalibrationError> {
+        let raw = self.read_press().await?;
+        self.raw[step.min(2)] = raw;
+        Ok(())
+    }
+
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+        let points = core::array::from_fn(|i| (self.raw[i], self.targets[i]));
+        let calibration = TouchCalibration::from_points(points).ok_or(CalibrationError::Invalid)?;
+        self.touch.set_calibration(calibration);
+        self.touch.save_calibration(store, self.key)?;
+        Ok(())
+    }
+}
+
+/// Pulse widths a servo really reaches its ends at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ServoEndpoints {
+    pub pulse_min_us: u32,
+    pub pulse_max_us: u32,
+}
+
+impl ServoEndpoints {
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&self.pulse_min_us.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.pulse_max_us.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Self {
+            pulse_min_us: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            pulse_max_us: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Endpoints stored under `key`, if any
+    pub fn load(store: &mut impl KvStore, key: &str) -> Result<Option<Self>, KvStoreError> {
+        Ok(store.get_array::<8>(key)?.map(Self::from_bytes))
+    }
+
+    /// `spec` with these pulse widths, for a [`ServoConfig`](crate::ServoConfig)
+    pub fn apply(&self, spec: &ServoSpec) -> ServoSpec {
+        ServoSpec {
+            pulse_min_us: self.pulse_min_us,
+            pulse_max_us: self.pulse_max_us,
+            ..*spec
+        }
+    }
+}
+
+/// Find a servo's end points by hand: each step drives the servo to one end of its spec's
+/// range, and adjusting nudges the pulse until the horn sits where the angle should be.
+pub struct ServoEndpointCalibrator<'s, 'a> {
+    servo: &'s mut Servo<'a>,
+    key: &'s str,
+    step_us: u32,
+    endpoints: ServoEndpoints,
+}
+
+impl<'s, 'a> ServoEndpointCalibrator<'s, 'a> {
+    pub fn new(servo: &'s mut Servo<'a>, key: &'s str) -> Self {
+        let (pulse_min_us, pulse_max_us) = servo.pulse_range_us();
+        Self {
+            servo,
+            key,
+            step_us: CALIBRATION_DEFAULT_SERVO_STEP_US,
+            endpoints: ServoEndpoints {
+                pulse_min_us,
+                pulse_max_us,
+            },
+        }
+    }
+
+    /// Pulse change per adjustment unit
+    pub fn set_step_us(&mut self, step_us: u32) {
+        self.step_us = step_us.max(1);
+    }
+
+    pub fn endpoints(&self) -> ServoEndpoints {
+        self.endpoints
+    }
+
+    fn pulse_mut(&mut self, step: usize) -> &mut u32 {
+        match step {
+            0 => &mut self.endpoints.pulse_min_us,
+            _ => &mut self.endpoints.pulse_max_us,
+        }
+    }
+
+    fn drive(&mut self, step: usize) -> Result<(), CalibrationError> {
+        let pulse = *self.pulse_mut(step);
+        self.servo
+            .set_pulse_us(pulse)
+            .map_err(|_| CalibrationError::Device)
+    }
+}
+
+impl CalibrationProcedure for ServoEndpointCalibrator<'_, '_> {
+    fn title(&self) -> &str {
+        "Servo"
+    }
+
+    fn steps(&self) -> usize {
+        2
+    }
+
+    fn prompt(&self, step: usize, out: &mut impl Write) {
+        let (end, pulse) = match step {
+            0 => ("Min", self.endpoints.pulse_min_us),
+            _ => ("Max", self.endpoints.pulse_max_us),
+        };
+        let _ = write!(out, "{end} end: {pulse} us\nAdjust, confirm");
+    }
+
+    async fn begin(&mut self, step: usize) -> Result<(), CalibrationError> {
+        self.drive(step)
+    }
+
+    async fn adjust(&mut self, step: usize, delta: i32) -> Result<(), CalibrationError> {
+        let change = delta.unsigned_abs().saturating_mul(self.step_us);
+        let previous = *self.pulse_mut(step);
+        let pulse = self.pulse_mut(step);
+        *pulse = if delta < 0 {
+            pulse.saturating_sub(change).max(1)
+        } else {
+            pulse.saturating_add(change)
+        };
+        if self.drive(step).is_err() {
+            // Past the end of the frame: stay where it was
+            *self.pulse_mut(step) = previous;
+        }
+        Ok(())
+    }
+
+    async fn capture(&mut self, _step: usize) -> Result<(), CalibrationError> {
+        Ok(())
+    }
+
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+        if self.endpoints.pulse_min_us == self.endpoints.pulse_max_us {
+            return Err(CalibrationError::Invalid);
+        }
+        store.set(self.key, &self.endpoints.to_bytes())?;
+        Ok(())
+    }
+}
+
+/// Tune a servo's [`ServoCalibration`] by hand: the servo goes to its min angle, its max
+/// angle and the middle in turn, and adjusting trims the pulse there until the horn sits
+/// right. Usually run through [`Servo::calibrate_interactive`].
+pub struct ServoTrimCalibrator<'s, 'a> {
+    servo: &'s mut Servo<'a>,
+    key: &'s str,
+    step_us: u32,
+    calibration: ServoCalibration,
+}
+
+impl<'s, 'a> ServoTrimCalibrator<'s, 'a> {
+    /// Starts from the servo's current calibration.
+    pub fn new(servo: &'s mut Servo<'a>, key: &'s str) -> Self {
+        let calibration = servo.calibration();
+        Self {
+            servo,
+            key,
+            step_us: CALIBRATION_DEFAULT_SERVO_STEP_US,
+            calibration,
+        }
+    }
+
+    /// Trim change per adjustment unit
+    pub fn set_step_us(&mut self, step_us: u32) {
+        self.step_us = step_us.max(1);
+    }
+
+    pub fn calibration(&self) -> ServoCalibration {
+        self.calibration
+    }
+
+    fn trim_mut(&mut self, step: usize) -> &mut i16 {
+        match step {
+            0 => &mut self.calibration.min_trim_us,
+            1 => &mut self.calibration.max_trim_us,
+            _ => &mut self.calibration.center_offset_us,
+        }
+    }
+}
+
+impl CalibrationProcedure for ServoTrimCalibrator<'_, '_> {
+    fn title(&self) -> &str {
+        "Servo trim"
+    }
+
+    fn steps(&self) -> usize {
+        3
+    }
+
+    fn prompt(&self, step: usize, out: &mut impl Write) {
+        let (point, trim) = match step {
+            0 => ("Min", self.calibration.min_trim_us),
+            1 => ("Max", self.calibration.max_trim_us),
+            _ => ("Mid", self.calibration.center_offset_us),
+        };
+        let _ = write!(out, "{point}: {trim:+} us\nAdjust, confirm");
+    }
+
+    async fn begin(&mut self, step: usize) -> Result<(), CalibrationError> {
+        let (min, max) = self.servo.angle_range();
+        let angle = match step {
+            0 => min,
+            1 => max,
+            _ => min + (max - min) / 2.0,
+        };
+        self.servo
+            .set_angle(angle)
+            .map_err(|_| CalibrationError::Device)
+    }
+
+    async fn adjust(&mut self, step: usize, delta: i32) -> Result<(), CalibrationError> {
+        let change = delta.saturating_mul(self.step_us.min(i16::MAX as u32) as i32);
+        let trim = self.trim_mut(step);
+        *trim = (*trim as i32 + change).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.servo
+            .set_calibration(self.calibration)
+            .map_err(|_| CalibrationError::Device)
+    }
+
+    async fn capture(&mut self, _step: usize) -> Result<(), CalibrationError> {
+        Ok(())
+    }
+
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+        let (pulse_min_us, pulse_max_us) = self.servo.pulse_range_us();
+        if pulse_min_us == pulse_max_us {
+            return Err(CalibrationError::Invalid);
+        }
+        self.servo.save_calibration(store, self.key)?;
+        Ok(())
+    }
+}
+
+/// Resting position of a two-axis analog stick, in raw ADC counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct JoystickCenter {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Default for JoystickCenter {
+    /// The middle of the ADC range
+    fn default() -> Self {
+        Self {
+            x: ADC_MAX_RAW / 2,
+            y: ADC_MAX_RAW / 2,
+        }
+    }
+}
+
+impl JoystickCenter {
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let mut bytes = [0u8; 4];
+        bytes[..2].copy_from_slice(&self.x.to_le_bytes());
+        bytes[2..].copy_from_slice(&self.y.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            x: u16::from_le_bytes([bytes[0], bytes[1]]),
+            y: u16::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Centre stored under `key`, if any
+    pub fn load(store: &mut impl KvStore, key: &str) -> Result<Option<Self>, KvStoreError> {
+        Ok(store.get_array::<4>(key)?.map(Self::from_bytes))
+    }
+}
+
+/// Centring of an analog stick on two ADC inputs
+pub struct JoystickCalibrator<'j, 'a> {
+    x: &'j mut AnalogInput<'a>,
+    y: &'j mut AnalogInput<'a>,
+    key: &'j str,
+    center: Option<JoystickCenter>,
+}
+
+impl<'j, 'a> JoystickCalibrator<'j, 'a> {
+    pub fn new(x: &'j mut AnalogInput<'a>, y: &'j mut AnalogInput<'a>, key: &'j str) -> Self {
+        Self {
+            x,
+            y,
+            key,
+            center: None,
+        }
+    }
+
+    /// Measured centre, once captured
+    pub fn center(&self) -> Option<JoystickCenter> {
+        self.center
+    }
+}
+
+impl CalibrationProcedure for JoystickCalibrator<'_, '_> {
+    fn title(&self) -> &str {
+        "Joystick"
+    }
+
+    fn steps(&self) -> usize {
+        1
+    }
+
+    fn prompt(&self, _step: usize, out: &mut impl Write) {
+        let _ = write!(out, "Let go of stick,\nthen confirm");
+    }
+
+    async fn capture(&mut self, _step: usize) -> Result<(), CalibrationError> {
+        let x = self.x.read_average(JOYSTICK_SAMPLES).await;
+        let y = self.y.read_average(JOYSTICK_SAMPLES).await;
+        let (Ok(x), Ok(y)) = (x, y) else {
+            return Err(CalibrationError::Device);
+        };
+        self.center = Some(JoystickCenter { x, y });
+        Ok(())
+    }
+
+    fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+        let center = self.center.ok_or(CalibrationError::Invalid)?;
+        store.set(self.key, &center.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::MockTextDisplay;
+
+    /// Keys typed in order
+    struct Keys(&'static [char]);
+
+    impl KeyInput for Keys {
+        async fn wait_for_key(&mut self) -> char {
+            let (&key, rest) = self.0.split_first().expect("out of keys");
+            self.0 = rest;
+            key
+        }
+    }
+
+    /// One value in a slot
+    struct Store {
+        value: Option<[u8; 4]>,
+    }
+
+    impl KvStore for Store {
+        fn get(&mut self, _key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvStoreError> {
+            Ok(self.value.map(|value| {
+                buf[..4].copy_from_slice(&value);
+                4
+            }))
+        }
+
+        fn set(&mut self, _key: &str, value: &[u8]) -> Result<(), KvStoreError> {
+            self.value = Some(value.try_into().map_err(|_| KvStoreError::ValueTooLong)?);
+            Ok(())
+        }
+
+        fn remove(&mut self, _key: &str) -> Result<(), KvStoreError> {
+            self.value = None;
+            Ok(())
+        }
+
+        fn remove_prefix(&mut self, _prefix: &str) -> Result<usize, KvStoreError> {
+            Ok(0)
+        }
+    }
+
+    /// Two adjustable levels
+    struct Levels {
+        levels: [i32; 2],
+        captured: [bool; 2],
+    }
+
+    impl CalibrationProcedure for Levels {
+        fn title(&self) -> &str {
+            "Levels"
+        }
+
+        fn steps(&self) -> usize {
+            2
+        }
+
+        fn prompt(&self, step: usize, out: &mut impl Write) {
+            let _ = write!(out, "Level {}", self.levels[step]);
+        }
+
+        async fn adjust(&mut self, step: usize, delta: i32) -> Result<(), CalibrationError> {
+            self.levels[step] += delta;
+            Ok(())
+        }
+
+        async fn capture(&mut self, step: usize) -> Result<(), CalibrationError> {
+            self.captured[step] = true;
+            Ok(())
+        }
+
+        fn save(&mut self, store: &mut impl KvStore) -> Result<(), CalibrationError> {
+            let bytes = [self.levels[0] as u8, self.levels[1] as u8, 0, 0];
+            store.set("levels", &bytes)?;
+            Ok(())
+        }
+    }
+
+    fn levels() -> Levels {
+        Levels {
+            levels: [10, 20],
+            captured: [false; 2],
+        }
+    }
+
+    #[test]
+    fn adjusts_confirms_and_saves() {
+        let screen = MockTextDisplay::new(2, 16);
+        let mut wizard = CalibrationWizard::new(screen, Keys(&['+', '+', '#', '-', 'x', '#']));
+        let mut procedure = levels();
+        let mut store = Store { value: None };
+        let outcome = block_on(wizard.run(&mut procedure, &mut store)).unwrap();
+        assert_eq!(outcome, CalibrationOutcome::Saved);
+        assert_eq!(procedure.captured, [true, true]);
+        assert_eq!(store.value, Some([12, 19, 0, 0]));
+        let (screen, _) = wizard.into_inner();
+        assert_eq!(screen.content(), "Calibration\nsaved");
+    }
+
+    #[test]
+    fn cancel_keeps_stored_calibration() {
+        let screen = MockTextDisplay::new(2, 16);
+        let mut wizard = CalibrationWizard::new(screen, Keys(&['#', '+', '*']));
+        let mut procedure = levels();
+        let mut store = Store {
+            value: Some([1, 2, 3, 4]),
+        };
+        let outcome = block_on(wizard.run(&mut procedure, &mut store)).unwrap();
+        assert_eq!(outcome, CalibrationOutcome::Cancelled);
+        assert_eq!(procedure.captured, [true, false]);
+        assert_eq!(store.value, Some([1, 2, 3, 4]));
+        let (screen, _) = wizard.into_inner();
+        assert_eq!(screen.content(), "Levels 2/2\nLevel 21");
+    }
+
+    #[test]
+    fn clips_lines_to_the_display() {
+        let screen = MockTextDisplay::new(2, 16);
+        let mut wizard = CalibrationWizard::new(screen, Keys(&[]));
+        wizard.show("Soil moisture probe 1/2\nDry: in air");
+        let (screen, _) = wizard.into_inner();
+        assert_eq!(screen.content(), "Soil moisture pr\nDry: in air");
+
+        for error in [
+            CalibrationError::Store(KvStoreError::ValueTooLong),
+            CalibrationError::Device,
+            CalibrationError::Invalid,
+        ] {
+            let mut text: HeaplessString<64> = HeaplessString::new();
+            let _ = write!(text, "{error}");
+            assert!(text.len() <= 16, "{error}");
+        }
+    }
+
+    #[test]
+    fn endpoints_round_trip() {
+        let endpoints = ServoEndpoints {
+            pulse_min_us: 540,
+            pulse_max_us: 2430,
+        };
+        assert_eq!(ServoEndpoints::from_bytes(endpoints.to_bytes()), endpoints);
+        let spec = endpoints.apply(ServoSpec::makerhawk_mg995());
+        assert_eq!((spec.pulse_min_us, spec.pulse_max_us), (540, 2430));
+        assert_eq!(spec.frame_us, 20_000);
+        let center = JoystickCenter { x: 2010, y: 2100 };
+        assert_eq!(JoystickCenter::from_bytes(center.to_bytes()), center);
+    }
+}
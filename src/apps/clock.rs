@@ -0,0 +1,311 @@
+//! clock.rs — desk clock: a wall time source, a time zone and a text display
+//!
+//! The wall time comes from a [`WallTimeSource`]; the waits between redraws and the alarm
+//! timeout run on an [`Uptime`], [`SystemUptime`] unless the clock was made with
+//! [`Clock::with_uptime`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! let rtc = Ds3231::new(i2c);
+//! let config = ClockConfig {
+//!     time_zone: TimeZone::new(60, DstRule::Eu),
+//!     ..Default::default()
+//! };
+//! let mut clock = Clock::new(rtc, lcd, config);
+//! clock.run().await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_futures::select::{Either, select};
+use embassy_time::Duration;
+
+use crate::{
+    AlarmCommand, AlarmControl, AlarmError, AlarmSink, Alarms, DateTime, Ds3231, Ds3231Error,
+    HeaplessString, KvStore, SntpError, SystemUptime, TextDisplay, TimeZone, Uptime, WallClock,
+    format_hh_mm,
+};
+
+/// Source of the current UTC time
+pub trait WallTimeSource {
+    type Error;
+
+    /// Seconds since the Unix epoch (UTC)
+    fn unix_time(&mut self) -> Result<u64, Self::Error>;
+}
+
+impl<I: embedded_hal::i2c::I2c> WallTimeSource for Ds3231<I> {
+    type Error = Ds3231Error;
+
+    fn unix_time(&mut self) -> Result<u64, Self::Error> {
+        Ds3231::unix_time(self)
+    }
+}
+
+impl WallTimeSource for WallClock {
+    type Error = SntpError;
+
+    fn unix_time(&mut self) -> Result<u64, Self::Error> {
+        WallClock::unix_time(self).ok_or(SntpError::NotSynced)
+    }
+}
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum ClockError {
+    #[error("Failed to read the time source")]
+    TimeSource,
+    #[error("Failed to update the display")]
+    Display,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HourFormat {
+    H24,
+    H12,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClockConfig {
+    pub time_zone: TimeZone,
+    pub hour_format: HourFormat,
+    pub show_seconds: bool,
+    /// Weekday and date on the second line, if the display has one
+    pub show_date: bool,
+    /// Blink the hour/minute colon at 1 Hz
+    pub blink_colon: bool,
+    /// How long a snoozed alarm waits before ringing again
+    pub snooze: Duration,
+    /// A ringing alarm stops by itself after this long
+    pub alarm_timeout: Duration,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            time_zone: TimeZone::UTC,
+            hour_format: HourFormat::H24,
+            show_seconds: false,
+            show_date: true,
+            blink_colon: true,
+            snooze: Duration::from_secs(9 * 60),
+            alarm_timeout: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// Clock face on a [`TextDisplay`]
+pub struct Clock<S: WallTimeSource, D: TextDisplay, U: Uptime = SystemUptime> {
+    source: S,
+    display: D,
+    config: ClockConfig,
+    colon_visible: bool,
+    alarms: Alarms,
+    uptime: U,
+}
+
+impl<S: WallTimeSource, D: TextDisplay> Clock<S, D> {
+    pub fn new(source: S, display: D, config: ClockConfig) -> Self {
+        Self::with_uptime(source, display, config, SystemUptime)
+    }
+}
+
+impl<S: WallTimeSource, D: TextDisplay, U: Uptime> Clock<S, D, U> {
+    pub fn with_uptime(source: S, display: D, config: ClockConfig, uptime: U) -> Self {
+        Self {
+            source,
+            display,
+            config,
+            colon_visible: true,
+            alarms: Alarms::new(),
+            uptime,
+        }
+    }
+
+    pub fn config(&self) -> &ClockConfig {
+        &self.config
+    }
+
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) {
+        self.config.time_zone = time_zone;
+    }
+
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn display(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn alarms(&self) -> &Alarms {
+        &self.alarms
+    }
+
+    pub fn alarms_mut(&mut self) -> &mut Alarms {
+        &mut self.alarms
+    }
+
+    pub fn load_alarms(&mut self, store: &mut impl KvStore) -> Result<(), AlarmError> {
+        self.alarms.load(store)
+    }
+
+    pub fn save_alarms(&self, store: &mut impl KvStore) -> Result<(), AlarmError> {
+        self.alarms.save(store)
+    }
+
+    pub fn unix_time(&mut self) -> Result<u64, ClockError> {
+        self.source.unix_time().map_err(|_| ClockError::TimeSource)
+    }
+
+    /// Current local date/time
+    pub fn local_time(&mut self) -> Result<DateTime, ClockError> {
+        let now = self.unix_time()?;
+        Ok(self.config.time_zone.to_local(now))
+    }
+
+    /// Draw the current time once.
+    pub fn render(&mut self) -> Result<(), ClockError> {
+        let local = self.local_time()?;
+        let content = self.format(&local);
+        self.display
+            .display_str(content.as_str())
+            .map_err(|_| ClockError::Display)
+    }
+
+    /// Keep the display up to date: every minute, or more often when seconds or a blinking
+    /// colon are shown.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.tick().await;
+        }
+    }
+
+    /// Like [`Clock::run`], and also ring `sink` when an alarm is due. A ringing alarm is
+    /// ended through `control` (snooze or dismiss) or after `alarm_timeout`.
+    pub async fn run_with_alarms(
+        &mut self,
+        sink: &mut impl AlarmSink,
+        control: &AlarmControl,
+    ) -> ! {
+        loop {
+            if let Ok(now) = self.unix_time() {
+                let local = self.config.time_zone.to_local(now);
+                if let Some(slot) = self.alarms.due(now, &local) {
+                    self.ring(slot, sink, control).await;
+                    // Another alarm may be set for the same minute
+                    continue;
+                }
+            }
+            self.tick().await;
+        }
+    }
+
+    async fn ring(&mut self, slot: usize, sink: &mut impl AlarmSink, control: &AlarmControl) {
+        control.reset();
+        sink.start(slot).await;
+        let _ = self.display.display_str("ALARM\nSnooze / Stop");
+
+        let timeout = self.uptime.sleep(self.config.alarm_timeout);
+        let command = select(timeout, control.wait()).await;
+        sink.stop().await;
+        if let Either::Second(AlarmCommand::Snooze) = command
+            && let Ok(now) = self.unix_time()
+        {
+            self.alarms.snooze(slot, now + self.config.snooze.as_secs());
+        }
+    }
+
+    /// Draw once, then wait until the display next needs updating.
+    async fn tick(&mut self) {
+        match self.render() {
+            Ok(()) => {}
+            Err(ClockError::TimeSource) => {
+                let _ = self.display.display_str("--:--");
+                self.uptime.sleep(Duration::from_secs(1)).await;
+                return;
+            }
+            Err(ClockError::Display) => {}
+        }
+
+        if self.config.blink_colon {
+            self.colon_visible = !self.colon_visible;
+            self.uptime.sleep(Duration::from_millis(500)).await;
+        } else if self.config.show_seconds {
+            self.uptime.sleep(Duration::from_secs(1)).await;
+        } else {
+            let second = self.unix_time().map(|t| t % 60).unwrap_or(0);
+            self.uptime.sleep(Duration::from_secs(60 - second)).await;
+        }
+    }
+
+    fn format(&self, local: &DateTime) -> HeaplessString<64> {
+        let mut out = HeaplessString::new();
+        let colon = if self.colon_visible || !self.config.blink_colon {
+            ':'
+        } else {
+            ' '
+        };
+
+        let (hour, suffix) = match self.config.hour_format {
+            HourFormat::H24 => (local.hour, ""),
+            HourFormat::H12 => {
+                let hour12 = if local.hour % 12 == 0 {
+                    12
+                } else {
+                    local.hour % 12
+                };
+                (hour12, if local.hour < 12 { " AM" } else { " PM" })
+            }
+        };
+        let _ = out.push_str(format_hh_mm(hour, local.minute, colon).as_str());
+        if self.config.show_seconds {
+            let _ = write!(out, "{}{:02}", colon, local.second);
+        }
+        let _ = out.push_str(suffix);
+
+        if self.config.show_date && self.display.max_lines() > 1 {
+            let _ = write!(
+                out,
+                "\n{} {}-{:02}-{:02}",
+                local.weekday().short_name(),
+                local.year,
+                local.month,
+                local.day
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockTextDisplay, MockUptime};
+
+    struct FixedTime(u64);
+
+    impl WallTimeSource for FixedTime {
+        type Error = ();
+
+        fn unix_time(&mut self) -> Result<u64, ()> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn tick_sleeps_until_the_next_minute() {
+        let time = MockUptime::new();
+        let config = ClockConfig {
+            blink_colon: false,
+            show_date: false,
+            ..Default::default()
+        };
+        let display = MockTextDisplay::new(1, 16);
+        let mut clock =
+            Clock::with_uptime(FixedTime(12 * 3600 + 34 * 60 + 45), display, config, &time);
+        embassy_futures::block_on(clock.tick());
+        assert_eq!(clock.display().content(), "12:34");
+        assert_eq!(time.now().as_secs(), 15);
+    }
+}
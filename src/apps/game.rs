@@ -0,0 +1,436 @@
+//! game.rs — frame loop and input for small games on the SH1106 OLED
+//!
+//! A [`Game`] only says how the world moves on by one tick and how it looks. The
+//! [`GameLoop`] does the rest:
+//!
+//! - **Fixed timestep**: [`Game::update`] runs every [`GameLoopConfig::tick`], so the
+//!   game speed doesn't depend on how long drawing takes. After a stall the loop catches up
+//!   by a few updates at most, then carries on from the present instead of fast-forwarding.
+//! - **Input snapshots**: a [`GameInputSource`] (buttons, an analog stick, or both as a
+//!   tuple) is read once per frame into a [`GameInput`] with held buttons, fresh presses and
+//!   releases, and the stick position.
+//! - **No flicker**: every frame is drawn into the display's RAM framebuffer and sent in a
+//!   single flush, so the panel never shows a half-drawn or cleared screen.
+//!
+//! # Example
+//!
+//! ```ignore
+//! struct Dot { x: i32, y: i32 }
+//!
+//! impl Game for Dot {
+//!     fn update(&mut self, input: &GameInput) -> GameStatus {
+//!         self.x += input.stick_x as i32 / 500;
+//!         self.y += input.stick_y as i32 / 500;
+//!         if input.was_pressed(GameButton::Start) { GameStatus::Over } else { GameStatus::Running }
+//!     }
+//!
+//!     fn draw<D: DrawTarget<Color = BinaryColor>>(&self, canvas: &mut D) -> Result<(), D::Error> {
+//!         Pixel(Point::new(self.x, self.y), BinaryColor::On).draw(canvas)
+//!     }
+//! }
+//!
+//! let pad = [(GameButton::Start, Button::new(Input::new(p.PIN_15, Pull::Up)))];
+//! let stick = GameJoystick::new(x_adc, y_adc, JoystickCenter::load(&mut store, "joy")?.unwrap_or_default());
+//! let mut game_loop = GameLoop::new((pad, stick), GameLoopConfig::default());
+//! game_loop.run(&mut Dot { x: 64, y: 32 }, &mut oled).await?;
+//! ```
+
+use embassy_rp::gpio::Input;
+use embassy_rp::spi;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::DrawTarget;
+
+use crate::{
+    ADC_MAX_RAW, AnalogInput, Button, InlandSh1106OledDisplay, InlandSh1106OledError,
+    JoystickCenter,
+};
+
+/// Default time per update, about 30 per second
+pub const GAME_DEFAULT_TICK: Duration = Duration::from_millis(33);
+/// Default limit of updates run back to back to catch up after a stall
+pub const GAME_DEFAULT_MAX_CATCH_UP: u32 = 4;
+/// Default stick deflection, in per mille, below which it counts as centred
+pub const GAME_DEFAULT_DEAD_ZONE: u16 = 300;
+
+/// Full stick deflection in [`GameInput::stick_x`] and [`GameInput::stick_y`]
+pub const GAME_STICK_MAX: i16 = 1000;
+
+/// ADC samples averaged per stick reading
+const STICK_SAMPLES: u16 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum GameButton {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl GameButton {
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Raw input state at one instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct GameInputSample {
+    /// One bit per [`GameButton`] held down
+    pub buttons: u8,
+    /// Stick position, -1000 (left, up) to 1000 (right, down)
+    pub stick_x: i16,
+    pub stick_y: i16,
+}
+
+impl GameInputSample {
+    pub fn press(&mut self, button: GameButton) {
+        self.buttons |= button.bit();
+    }
+
+    /// Both samples together: buttons held in either, the stick that is deflected more.
+    pub fn merge(self, other: Self) -> Self {
+        let stick = |a: i16, b: i16| if a.abs() >= b.abs() { a } else { b };
+        Self {
+            buttons: self.buttons | other.buttons,
+            stick_x: stick(self.stick_x, other.stick_x),
+            stick_y: stick(self.stick_y, other.stick_y),
+        }
+    }
+}
+
+/// Input for one [`Game::update`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct GameInput {
+    held: u8,
+    pressed: u8,
+    released: u8,
+    /// Stick position, -1000 (left) to 1000 (right)
+    pub stick_x: i16,
+    /// Stick position, -1000 (up) to 1000 (down)
+    pub stick_y: i16,
+}
+
+impl GameInput {
+    pub fn is_held(&self, button: GameButton) -> bool {
+        self.held & button.bit() != 0
+    }
+
+    /// Went down since the previous frame
+    pub fn was_pressed(&self, button: GameButton) -> bool {
+        self.pressed & button.bit() != 0
+    }
+
+    /// Went up since the previous frame
+    pub fn was_released(&self, button: GameButton) -> bool {
+        self.released & button.bit() != 0
+    }
+}
+
+/// Turns samples into [`GameInput`]s with the changes since the previous one
+#[derive(Debug, Clone, Default)]
+struct InputTracker {
+    held: u8,
+}
+
+impl InputTracker {
+    fn next(&mut self, sample: GameInputSample) -> GameInput {
+        let previous = core::mem::replace(&mut self.held, sample.buttons);
+        GameInput {
+            held: sample.buttons,
+            pressed: sample.buttons & !previous,
+            released: previous & !sample.buttons,
+            stick_x: sample.stick_x,
+            stick_y: sample.stick_y,
+        }
+    }
+}
+
+/// Where a [`GameLoop`] reads its input from
+#[allow(async_fn_in_trait)]
+pub trait GameInputSource {
+    async fn sample(&mut self) -> GameInputSample;
+}
+
+/// Buttons mapped to game buttons. Several may share one game button.
+impl<const N: usize> GameInputSource for [(GameButton, Button<Input<'_>>); N] {
+    async fn sample(&mut self) -> GameInputSample {
+        let mut sample = GameInputSample::default();
+        for (button, pin) in self.iter_mut() {
+            if pin.is_pressed() {
+                sample.press(*button);
+            }
+        }
+        sample
+    }
+}
+
+impl<A: GameInputSource, B: GameInputSource> GameInputSource for (A, B) {
+    async fn sample(&mut self) -> GameInputSample {
+        let a = self.0.sample().await;
+        a.merge(self.1.sample().await)
+    }
+}
+
+/// Two-axis analog stick. Reports its position, and the direction buttons when pushed
+/// past the dead zone.
+pub struct GameJoystick<'a> {
+    x: AnalogInput<'a>,
+    y: AnalogInput<'a>,
+    center: JoystickCenter,
+    dead_zone: u16,
+    invert_y: bool,
+}
+
+impl<'a> GameJoystick<'a> {
+    /// `center` from a [`JoystickCalibrator`](crate::JoystickCalibrator), or the middle of
+    /// the ADC range.
+    pub fn new(x: AnalogInput<'a>, y: AnalogInput<'a>, center: JoystickCenter) -> Self {
+        Self {
+            x,
+            y,
+            center,
+            dead_zone: GAME_DEFAULT_DEAD_ZONE,
+            invert_y: false,
+        }
+    }
+
+    /// Deflection in per mille that counts as a direction press
+    pub fn set_dead_zone(&mut self, per_mille: u16) {
+        self.dead_zone = per_mille.min(GAME_STICK_MAX as u16);
+    }
+
+    /// For sticks that read higher when pushed up
+    pub fn set_invert_y(&mut self, invert: bool) {
+        self.invert_y = invert;
+    }
+}
+
+impl GameInputSource for GameJoystick<'_> {
+    async fn sample(&mut self) -> GameInputSample {
+        let x = self.x.read_average(STICK_SAMPLES).await;
+        let y = self.y.read_average(STICK_SAMPLES).await;
+        // A failed read counts as centred
+        let stick_x = x.map_or(0, |raw| stick_axis(raw, self.center.x));
+        let stick_y = y.map_or(0, |raw| stick_axis(raw, self.center.y));
+        let stick_y = if self.invert_y { -stick_y } else { stick_y };
+
+        let mut sample = GameInputSample {
+            buttons: 0,
+            stick_x,
+            stick_y,
+        };
+        let dead_zone = self.dead_zone as i16;
+        let directions = [
+            (stick_x < -dead_zone, GameButton::Left),
+            (stick_x > dead_zone, GameButton::Right),
+            (stick_y < -dead_zone, GameButton::Up),
+            (stick_y > dead_zone, GameButton::Down),
+        ];
+        for (pushed, button) in directions {
+            if pushed {
+                sample.press(button);
+            }
+        }
+        sample
+    }
+}
+
+/// `raw` relative to `center`, scaled per side to -1000..=1000
+fn stick_axis(raw: u16, center: u16) -> i16 {
+    let raw = raw.min(ADC_MAX_RAW) as i32;
+    let center = center.clamp(1, ADC_MAX_RAW - 1) as i32;
+    let span = if raw < center {
+        center
+    } else {
+        ADC_MAX_RAW as i32 - center
+    };
+    ((raw - center) * GAME_STICK_MAX as i32 / span) as i16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum GameStatus {
+    Running,
+    /// Draw once more and leave the loop.
+    Over,
+}
+
+/// A game run by a [`GameLoop`]
+pub trait Game {
+    /// Advance the game by one tick.
+    fn update(&mut self, input: &GameInput) -> GameStatus;
+
+    /// Draw the current state on a cleared `canvas`.
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, canvas: &mut D) -> Result<(), D::Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct GameLoopConfig {
+    /// Game time per [`Game::update`]
+    pub tick: Duration,
+    /// Most updates run back to back after a stall; the rest of the lag is dropped.
+    pub max_catch_up: u32,
+}
+
+impl Default for GameLoopConfig {
+    fn default() -> Self {
+        Self {
+            tick: GAME_DEFAULT_TICK,
+            max_catch_up: GAME_DEFAULT_MAX_CATCH_UP,
+        }
+    }
+}
+
+/// Fixed-timestep schedule of updates
+#[derive(Debug, Clone)]
+struct GameClock {
+    config: GameLoopConfig,
+    /// When the next update is due
+    next: Instant,
+}
+
+impl GameClock {
+    fn new(config: GameLoopConfig, now: Instant) -> Self {
+        Self { config, next: now }
+    }
+
+    /// Updates due at `now`, at least one if the frame was woken at its time. Moves the
+    /// schedule past them.
+    fn due(&mut self, now: Instant) -> u32 {
+        let tick = self.config.tick.max(Duration::from_ticks(1));
+        let mut updates = 0;
+        while self.next <= now {
+            if updates == self.config.max_catch_up.max(1) {
+                // Too far behind: drop the lag
+                self.next = now + tick;
+                break;
+            }
+            updates += 1;
+            self.next += tick;
+        }
+        updates
+    }
+}
+
+/// Runs a [`Game`] on an SH1106 with input from a [`GameInputSource`]
+pub struct GameLoop<S: GameInputSource> {
+    input: S,
+    config: GameLoopConfig,
+}
+
+impl<S: GameInputSource> GameLoop<S> {
+    pub fn new(input: S, config: GameLoopConfig) -> Self {
+        Self { input, config }
+    }
+
+    pub fn input_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+
+    /// Update and draw `game` until it is [`GameStatus::Over`].
+    pub async fn run<G: Game, T: spi::Instance, M: spi::Mode>(
+        &mut self,
+        game: &mut G,
+        display: &mut InlandSh1106OledDisplay<'_, T, M>,
+    ) -> Result<(), InlandSh1106OledError> {
+        let mut clock = GameClock::new(self.config.clone(), Instant::now());
+        let mut tracker = InputTracker::default();
+        loop {
+            let mut input = tracker.next(self.input.sample().await);
+            let mut status = GameStatus::Running;
+            for _ in 0..clock.due(Instant::now()) {
+                status = game.update(&input);
+                if status == GameStatus::Over {
+                    break;
+                }
+                // Presses and releases count once, in the first update of a frame
+                input = tracker.next(GameInputSample {
+                    buttons: tracker.held,
+                    stick_x: input.stick_x,
+                    stick_y: input.stick_y,
+                });
+            }
+
+            let canvas = display.display_mut();
+            canvas.clear();
+            // Drawing into the framebuffer can't fail
+            let _ = game.draw(canvas);
+            display.flush()?;
+
+            if status == GameStatus::Over {
+                return Ok(());
+            }
+            Timer::at(clock.next).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_keeps_fixed_steps_and_drops_long_stalls() {
+        let ms = Duration::from_millis;
+        let start = Instant::from_millis(1000);
+        let config = GameLoopConfig {
+            tick: ms(10),
+            max_catch_up: 3,
+        };
+        let mut clock = GameClock::new(config, start);
+        assert_eq!(clock.due(start), 1);
+        assert_eq!(clock.due(start + ms(5)), 0);
+        assert_eq!(clock.due(start + ms(10)), 1);
+        // A slow frame: two updates to catch up
+        assert_eq!(clock.due(start + ms(31)), 2);
+        assert_eq!(clock.next, start + ms(40));
+        // A long stall: three updates, then carry on from now
+        assert_eq!(clock.due(start + ms(500)), 3);
+        assert_eq!(clock.next, start + ms(510));
+    }
+
+    #[test]
+    fn tracker_reports_edges_once() {
+        let mut tracker = InputTracker::default();
+        let mut sample = GameInputSample::default();
+        sample.press(GameButton::A);
+        let input = tracker.next(sample);
+        assert!(input.is_held(GameButton::A) && input.was_pressed(GameButton::A));
+
+        let input = tracker.next(sample);
+        assert!(input.is_held(GameButton::A) && !input.was_pressed(GameButton::A));
+
+        let input = tracker.next(GameInputSample::default());
+        assert!(!input.is_held(GameButton::A) && input.was_released(GameButton::A));
+        assert!(!input.was_pressed(GameButton::B));
+    }
+
+    #[test]
+    fn stick_scales_each_side_and_merges() {
+        assert_eq!(stick_axis(2047, 2047), 0);
+        assert_eq!(stick_axis(0, 2047), -1000);
+        assert_eq!(stick_axis(4095, 2047), 1000);
+        // Off-centre stick: both sides still reach full scale
+        assert_eq!(stick_axis(0, 1800), -1000);
+        assert_eq!(stick_axis(4095, 1800), 1000);
+        assert_eq!(stick_axis(900, 1800), -500);
+
+        let mut buttons = GameInputSample::default();
+        buttons.press(GameButton::Start);
+        let stick = GameInputSample {
+            buttons: GameButton::Left.bit(),
+            stick_x: -800,
+            stick_y: 100,
+        };
+        let merged = buttons.merge(stick);
+        assert_eq!(
+            merged.buttons,
+            GameButton::Start.bit() | GameButton::Left.bit()
+        );
+        assert_eq!((merged.stick_x, merged.stick_y), (-800, 100));
+    }
+}
@@ -0,0 +1,407 @@
+//! gcode.rs — minimal G-code interpreter with an async motion queue
+//!
+//! Supported commands:
+//!
+//! | Command            | Meaning                                          |
+//! |--------------------|--------------------------------------------------|
+//! | `G0`/`G1 X.. F..`  | Linear move of axes X Y Z A B C, optional feed   |
+//! | `G4 P<ms>`/`S<s>`  | Dwell                                            |
+//! | `G28`              | Move all axes to their home position             |
+//! | `G90` / `G91`      | Absolute / relative positioning                  |
+//! | `F<units/min>`     | Set the feed rate                                |
+//!
+//! Comments after `;` or inside `( )` are ignored. Lines are parsed where they arrive (USB
+//! serial, TCP) and queued; a single executor task performs the moves in order.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static QUEUE: MotionQueue<16> = MotionQueue::new();
+//!
+//! // Input task
+//! QUEUE.submit_line("G1 X45 Y10 F600").await?;
+//!
+//! // Executor task: axis 0 = X, axis 1 = Y
+//! let mut executor = MotionExecutor::new();
+//! executor.run(&mut [&mut pan_servo, &mut tilt_servo], &QUEUE).await;
+//! ```
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{LogModule, ServoChannel};
+
+/// Axis letters in index order
+pub const GCODE_AXES: [char; 6] = ['X', 'Y', 'Z', 'A', 'B', 'C'];
+pub const GCODE_MAX_AXES: usize = GCODE_AXES.len();
+
+/// Feed rate until an `F` word is seen, in units per minute
+const DEFAULT_FEED: f32 = 1200.0;
+const MOTION_FRAME: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum GcodeError {
+    #[error("Unsupported command")]
+    UnsupportedCommand,
+    #[error("Malformed word")]
+    InvalidWord,
+    #[error("Missing parameter")]
+    MissingParameter,
+    #[error("Feed rate must be positive")]
+    InvalidFeed,
+}
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum MotionError {
+    #[error("Failed to drive axis {0}")]
+    Axis(usize),
+}
+
+/// One parsed command
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum MotionCommand {
+    /// Coordinated linear move; `None` axes keep their position
+    Move {
+        target: [Option<f32>; GCODE_MAX_AXES],
+        /// Units per minute
+        feed: Option<f32>,
+    },
+    Dwell(Duration),
+    Home,
+    SetAbsolute(bool),
+    /// Units per minute
+    SetFeed(f32),
+}
+
+/// Parse one line. Blank and comment-only lines yield `None`.
+pub fn parse_gcode_line(line: &str) -> Result<Option<MotionCommand>, GcodeError> {
+    let line = line.split(';').next().unwrap_or("");
+
+    let mut command: Option<(char, u16)> = None;
+    let mut target = [None; GCODE_MAX_AXES];
+    let mut feed = None;
+    let mut p = None;
+    let mut s = None;
+
+    let mut in_comment = false;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        if in_comment {
+            in_comment = c != ')';
+            continue;
+        }
+        if c == '(' {
+            in_comment = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let letter = c.to_ascii_uppercase();
+        let end = rest
+            .find(|ch: char| !(ch.is_ascii_digit() || ch == '.' || ch == '-' || ch == '+'))
+            .unwrap_or(rest.len());
+        let number = &rest[..end];
+        rest = &rest[end..];
+        let value: f32 = number.parse().map_err(|_| GcodeError::InvalidWord)?;
+
+        match letter {
+            'G' | 'M' => {
+                if command.is_some() || value < 0.0 || value.fract() != 0.0 {
+                    return Err(GcodeError::InvalidWord);
+                }
+                command = Some((letter, value as u16));
+            }
+            'F' => feed = Some(value),
+            'P' => p = Some(value),
+            'S' => s = Some(value),
+            _ => {
+                let axis = GCODE_AXES
+                    .iter()
+                    .position(|&a| a == letter)
+                    .ok_or(GcodeError::InvalidWord)?;
+                target[axis] = Some(value);
+            }
+        }
+    }
+
+    if let Some(f) = feed
+        && f <= 0.0
+    {
+        return Err(GcodeError::InvalidFeed);
+    }
+
+    let command = match command {
+        None if target.iter().any(Option::is_some) => MotionCommand::Move { target, feed },
+        None => match feed {
+            Some(f) => MotionCommand::SetFeed(f),
+            None => return Ok(None),
+        },
+        Some(('G', 0 | 1)) => MotionCommand::Move { target, feed },
+        Some(('G', 4)) => {
+            let ms = match (p, s) {
+                (Some(ms), _) => ms,
+                (None, Some(secs)) => secs * 1000.0,
+                (None, None) => return Err(GcodeError::MissingParameter),
+            };
+            MotionCommand::Dwell(Duration::from_millis(ms.max(0.0) as u64))
+        }
+        Some(('G', 28)) => MotionCommand::Home,
+        Some(('G', 90)) => MotionCommand::SetAbsolute(true),
+        Some(('G', 91)) => MotionCommand::SetAbsolute(false),
+        Some(_) => return Err(GcodeError::UnsupportedCommand),
+    };
+    Ok(Some(command))
+}
+
+/// Something the executor can position: a servo (degrees), a stepper (mm or steps), …
+pub trait MotionAxis {
+    fn set_position(&mut self, position: f32) -> Result<(), MotionError>;
+}
+
+impl<T: ServoChannel> MotionAxis for T {
+    fn set_position(&mut self, position: f32) -> Result<(), MotionError> {
+        self.set_angle(position).map_err(|_| MotionError::Axis(0))
+    }
+}
+
+/// Parsed commands waiting for the executor
+pub struct MotionQueue<const N: usize> {
+    channel: Channel<CriticalSectionRawMutex, MotionCommand, N>,
+}
+
+impl<const N: usize> MotionQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            channel: Channel::new(),
+        }
+    }
+
+    /// Queue a command, waiting while the queue is full.
+    pub async fn submit(&self, command: MotionCommand) {
+        self.channel.send(command).await;
+    }
+
+    /// Parse and queue a line. Parse errors are returned right away so the sender can be
+    /// told; blank lines are accepted and ignored.
+    pub async fn submit_line(&self, line: &str) -> Result<(), GcodeError> {
+        if let Some(command) = parse_gcode_line(line)? {
+            self.submit(command).await;
+        }
+        Ok(())
+    }
+
+    /// Commands not yet executed
+    pub fn pending(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Drop all queued commands (e.g. emergency stop).
+    pub fn clear(&self) {
+        self.channel.clear();
+    }
+}
+
+impl<const N: usize> Default for MotionQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Executes [`MotionCommand`]s; axis `i` of the command drives `axes[i]`.
+pub struct MotionExecutor {
+    position: [f32; GCODE_MAX_AXES],
+    home: [f32; GCODE_MAX_AXES],
+    /// Units per minute
+    feed: f32,
+    absolute: bool,
+}
+
+impl MotionExecutor {
+    /// All axes start at 0 (the home position), absolute mode.
+    pub fn new() -> Self {
+        Self {
+            position: [0.0; GCODE_MAX_AXES],
+            home: [0.0; GCODE_MAX_AXES],
+            feed: DEFAULT_FEED,
+            absolute: true,
+        }
+    }
+
+    /// Position `G28` returns to, also the assumed start position.
+    pub fn set_home(&mut self, home: [f32; GCODE_MAX_AXES]) {
+        self.home = home;
+        self.position = home;
+    }
+
+    pub fn position(&self) -> &[f32; GCODE_MAX_AXES] {
+        &self.position
+    }
+
+    pub async fn execute(
+        &mut self,
+        command: MotionCommand,
+        axes: &mut [&mut dyn MotionAxis],
+    ) -> Result<(), MotionError> {
+        match command {
+            MotionCommand::Move { target, feed } => {
+                if let Some(feed) = feed {
+                    self.feed = feed;
+                }
+                let mut end = self.position;
+                for (axis, value) in target.iter().enumerate() {
+                    if let Some(value) = value {
+                        end[axis] = if self.absolute {
+                            *value
+                        } else {
+                            self.position[axis] + value
+                        };
+                    }
+                }
+                self.move_linear(end, axes).await
+            }
+            MotionCommand::Dwell(duration) => {
+                Timer::after(duration).await;
+                Ok(())
+            }
+            MotionCommand::Home => self.move_linear(self.home, axes).await,
+            MotionCommand::SetAbsolute(absolute) => {
+                self.absolute = absolute;
+                Ok(())
+            }
+            MotionCommand::SetFeed(feed) => {
+                self.feed = feed;
+                Ok(())
+            }
+        }
+    }
+
+    /// Execute queued commands forever. Failed commands are logged and skipped.
+    pub async fn run<const N: usize>(
+        &mut self,
+        axes: &mut [&mut dyn MotionAxis],
+        queue: &MotionQueue<N>,
+    ) -> ! {
+        loop {
+            let command = queue.channel.receive().await;
+            if let Err(e) = self.execute(command, axes).await {
+                crate::log!(LogModule::App, Warn, "G-code command failed: {}", e);
+            }
+        }
+    }
+
+    /// Interpolate all axes along a straight line at the current feed rate.
+    async fn move_linear(
+        &mut self,
+        end: [f32; GCODE_MAX_AXES],
+        axes: &mut [&mut dyn MotionAxis],
+    ) -> Result<(), MotionError> {
+        let start = self.position;
+        let distance = libm::sqrtf(
+            start
+                .iter()
+                .zip(end.iter())
+                .map(|(a, b)| (b - a) * (b - a))
+                .sum(),
+        );
+        let duration_s = distance / (self.feed / 60.0);
+        let started_at = Instant::now();
+
+        loop {
+            let elapsed_s = started_at.elapsed().as_micros() as f32 / 1_000_000.0;
+            let t = if duration_s > 0.0 {
+                (elapsed_s / duration_s).min(1.0)
+            } else {
+                1.0
+            };
+            for (i, pos) in self.position.iter_mut().enumerate() {
+                *pos = start[i] + (end[i] - start[i]) * t;
+            }
+            self.apply(axes)?;
+            if t >= 1.0 {
+                break;
+            }
+            Timer::after(MOTION_FRAME).await;
+        }
+        self.position = end;
+        Ok(())
+    }
+
+    fn apply(&self, axes: &mut [&mut dyn MotionAxis]) -> Result<(), MotionError> {
+        for (i, axis) in axes.iter_mut().enumerate().take(GCODE_MAX_AXES) {
+            axis.set_position(self.position[i])
+                .map_err(|_| MotionError::Axis(i))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MotionExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_moves() {
+        let cmd = parse_gcode_line("G1 X10.5 y-2 F600 ; comment").unwrap();
+        let mut target = [None; GCODE_MAX_AXES];
+        target[0] = Some(10.5);
+        target[1] = Some(-2.0);
+        assert_eq!(
+            cmd,
+            Some(MotionCommand::Move {
+                target,
+                feed: Some(600.0)
+            })
+        );
+        assert_eq!(
+            parse_gcode_line("X1 (implicit move)").unwrap(),
+            Some(MotionCommand::Move {
+                target: [Some(1.0), None, None, None, None, None],
+                feed: None
+            })
+        );
+    }
+
+    #[test]
+    fn parses_other_commands() {
+        assert_eq!(
+            parse_gcode_line("G4 P250").unwrap(),
+            Some(MotionCommand::Dwell(Duration::from_millis(250)))
+        );
+        assert_eq!(
+            parse_gcode_line("G4 S1.5").unwrap(),
+            Some(MotionCommand::Dwell(Duration::from_millis(1500)))
+        );
+        assert_eq!(parse_gcode_line("g28").unwrap(), Some(MotionCommand::Home));
+        assert_eq!(
+            parse_gcode_line("G91").unwrap(),
+            Some(MotionCommand::SetAbsolute(false))
+        );
+        assert_eq!(
+            parse_gcode_line("F3000").unwrap(),
+            Some(MotionCommand::SetFeed(3000.0))
+        );
+        assert_eq!(parse_gcode_line("  ; only a comment").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(
+            parse_gcode_line("G2 X1"),
+            Err(GcodeError::UnsupportedCommand)
+        );
+        assert_eq!(parse_gcode_line("G1 Q5"), Err(GcodeError::InvalidWord));
+        assert_eq!(parse_gcode_line("G1 X"), Err(GcodeError::InvalidWord));
+        assert_eq!(parse_gcode_line("G4"), Err(GcodeError::MissingParameter));
+        assert_eq!(parse_gcode_line("G1 X1 F0"), Err(GcodeError::InvalidFeed));
+    }
+}
@@ -0,0 +1,21 @@
+mod access_control;
+mod alarm;
+mod animation;
+mod calibration;
+mod clock;
+mod game;
+mod gcode;
+mod morse;
+mod stopwatch;
+mod ui;
+
+pub use access_control::*;
+pub use alarm::*;
+pub use animation::*;
+pub use calibration::*;
+pub use clock::*;
+pub use game::*;
+pub use gcode::*;
+pub use morse::*;
+pub use stopwatch::*;
+pub use ui::*;
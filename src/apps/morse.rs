@@ -0,0 +1,324 @@
+//! morse.rs — Morse code transmitter (LED, buzzer, relay) and key decoder
+//!
+//! Timing follows the PARIS standard: a dot lasts `1200 / wpm` ms, a dash three dots, the
+//! gap between symbols one dot, between characters three dots and between words seven.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut tx = MorseTransmitter::new(Output::new(p.PIN_25, Level::Low), 15);
+//! tx.send("SOS").await?;
+//!
+//! let mut rx = MorseReceiver::new(Input::new(p.PIN_14, Pull::Up), 15);
+//! loop {
+//!     let c = rx.read_char().await;
+//!     info!("{}", c);
+//! }
+//! ```
+
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use embedded_hal::digital::OutputPin;
+
+use crate::HeaplessString;
+
+/// Longest supported symbol (punctuation uses up to 7 elements)
+const MAX_SYMBOL_LEN: usize = 8;
+/// Key presses shorter than this are contact bounce
+const DEBOUNCE: Duration = Duration::from_millis(10);
+
+const MORSE_TABLE: &[(char, &str)] = &[
+    ('A', ".-"),
+    ('B', "-..."),
+    ('C', "-.-."),
+    ('D', "-.."),
+    ('E', "."),
+    ('F', "..-."),
+    ('G', "--."),
+    ('H', "...."),
+    ('I', ".."),
+    ('J', ".---"),
+    ('K', "-.-"),
+    ('L', ".-.."),
+    ('M', "--"),
+    ('N', "-."),
+    ('O', "---"),
+    ('P', ".--."),
+    ('Q', "--.-"),
+    ('R', ".-."),
+    ('S', "..."),
+    ('T', "-"),
+    ('U', "..-"),
+    ('V', "...-"),
+    ('W', ".--"),
+    ('X', "-..-"),
+    ('Y', "-.--"),
+    ('Z', "--.."),
+    ('0', "-----"),
+    ('1', ".----"),
+    ('2', "..---"),
+    ('3', "...--"),
+    ('4', "....-"),
+    ('5', "....."),
+    ('6', "-...."),
+    ('7', "--..."),
+    ('8', "---.."),
+    ('9', "----."),
+    ('.', ".-.-.-"),
+    (',', "--..--"),
+    ('?', "..--.."),
+    ('\'', ".----."),
+    ('!', "-.-.--"),
+    ('/', "-..-."),
+    ('(', "-.--."),
+    (')', "-.--.-"),
+    ('&', ".-..."),
+    (':', "---..."),
+    (';', "-.-.-."),
+    ('=', "-...-"),
+    ('+', ".-.-."),
+    ('-', "-....-"),
+    ('_', "..--.-"),
+    ('"', ".-..-."),
+    ('$', "...-..-"),
+    ('@', ".--.-."),
+];
+
+/// Dots and dashes for `c` (case-insensitive), or `None` if it has no Morse code.
+pub fn morse_encode(c: char) -> Option<&'static str> {
+    let c = c.to_ascii_uppercase();
+    MORSE_TABLE
+        .iter()
+        .find(|(ch, _)| *ch == c)
+        .map(|(_, code)| *code)
+}
+
+/// Character for a string of dots and dashes.
+pub fn morse_decode(code: &str) -> Option<char> {
+    MORSE_TABLE
+        .iter()
+        .find(|(_, c)| *c == code)
+        .map(|(ch, _)| *ch)
+}
+
+/// Length of one dot at `wpm` words per minute.
+pub fn morse_dot_duration(wpm: u8) -> Duration {
+    Duration::from_millis(1200 / wpm.max(1) as u64)
+}
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum MorseError {
+    #[error("Failed to drive Morse output")]
+    Output,
+}
+
+/// Something that can be keyed on and off: LED, active buzzer, relay, or a tone generator.
+pub trait MorseKey {
+    fn set_key(&mut self, down: bool) -> Result<(), MorseError>;
+}
+
+impl<P: OutputPin> MorseKey for P {
+    fn set_key(&mut self, down: bool) -> Result<(), MorseError> {
+        let result = if down {
+            self.set_high()
+        } else {
+            self.set_low()
+        };
+        result.map_err(|_| MorseError::Output)
+    }
+}
+
+pub struct MorseTransmitter<K: MorseKey> {
+    key: K,
+    dot: Duration,
+}
+
+impl<K: MorseKey> MorseTransmitter<K> {
+    pub fn new(key: K, wpm: u8) -> Self {
+        Self {
+            key,
+            dot: morse_dot_duration(wpm),
+        }
+    }
+
+    pub fn set_wpm(&mut self, wpm: u8) {
+        self.dot = morse_dot_duration(wpm);
+    }
+
+    /// Send `text`. Characters without a Morse code are skipped; whitespace separates words.
+    pub async fn send(&mut self, text: &str) -> Result<(), MorseError> {
+        let mut first = true;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                // A character gap has already been sent after the previous character.
+                if !first {
+                    Timer::after(self.dot * 4).await;
+                }
+                continue;
+            }
+            let Some(code) = morse_encode(c) else {
+                continue;
+            };
+            self.send_code(code).await?;
+            Timer::after(self.dot * 3).await;
+            first = false;
+        }
+        Ok(())
+    }
+
+    /// Send a raw dot/dash sequence (e.g. prosigns such as `"...-.-"` for SK).
+    pub async fn send_code(&mut self, code: &str) -> Result<(), MorseError> {
+        for (i, symbol) in code.chars().enumerate() {
+            if i > 0 {
+                Timer::after(self.dot).await;
+            }
+            let length = if symbol == '-' {
+                self.dot * 3
+            } else {
+                self.dot
+            };
+            self.key.set_key(true)?;
+            Timer::after(length).await;
+            self.key.set_key(false)?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns key press and gap durations into characters. Thresholds sit halfway between the
+/// nominal lengths, so sloppy hand keying is tolerated.
+#[derive(Debug, Clone)]
+pub struct MorseDecoder {
+    dot: Duration,
+    symbol: HeaplessString<MAX_SYMBOL_LEN>,
+}
+
+impl MorseDecoder {
+    pub fn new(wpm: u8) -> Self {
+        Self {
+            dot: morse_dot_duration(wpm),
+            symbol: HeaplessString::new(),
+        }
+    }
+
+    pub fn set_wpm(&mut self, wpm: u8) {
+        self.dot = morse_dot_duration(wpm);
+    }
+
+    /// Gap after which the current character is complete
+    pub fn char_gap(&self) -> Duration {
+        self.dot * 2
+    }
+
+    /// Gap after which a word space is emitted
+    pub fn word_gap(&self) -> Duration {
+        self.dot * 5
+    }
+
+    /// Record a key press that lasted `held`.
+    pub fn press(&mut self, held: Duration) {
+        let symbol = if held < self.dot * 2 { '.' } else { '-' };
+        // Overlong symbols can't decode anyway; they end up as '?'.
+        let _ = self.symbol.push(symbol);
+    }
+
+    /// Whether a character is being keyed
+    pub fn in_progress(&self) -> bool {
+        !self.symbol.is_empty()
+    }
+
+    /// Complete the current character: `None` if nothing was keyed, `'?'` if the symbol is
+    /// not valid Morse.
+    pub fn finish(&mut self) -> Option<char> {
+        if self.symbol.is_empty() {
+            return None;
+        }
+        let c = morse_decode(self.symbol.as_str()).unwrap_or('?');
+        self.symbol.clear();
+        Some(c)
+    }
+}
+
+/// Decodes Morse keyed on an active-low button or straight key
+pub struct MorseReceiver<'d> {
+    input: Input<'d>,
+    decoder: MorseDecoder,
+    /// A character ended and a word space may follow
+    after_char: bool,
+}
+
+impl<'d> MorseReceiver<'d> {
+    /// `input` must be pulled up, with the key connecting it to GND.
+    pub fn new(input: Input<'d>, wpm: u8) -> Self {
+        Self {
+            input,
+            decoder: MorseDecoder::new(wpm),
+            after_char: false,
+        }
+    }
+
+    pub fn decoder(&mut self) -> &mut MorseDecoder {
+        &mut self.decoder
+    }
+
+    /// Wait for the next decoded character; word gaps produce `' '`.
+    pub async fn read_char(&mut self) -> char {
+        loop {
+            if self.decoder.in_progress() {
+                let gap = self.decoder.char_gap();
+                if with_timeout(gap, self.input.wait_for_low()).await.is_err() {
+                    self.after_char = true;
+                    if let Some(c) = self.decoder.finish() {
+                        return c;
+                    }
+                    continue;
+                }
+            } else if self.after_char {
+                self.after_char = false;
+                let gap = self.decoder.word_gap() - self.decoder.char_gap();
+                if with_timeout(gap, self.input.wait_for_low()).await.is_err() {
+                    return ' ';
+                }
+            } else {
+                self.input.wait_for_low().await;
+            }
+
+            let pressed_at = Instant::now();
+            self.input.wait_for_high().await;
+            let held = pressed_at.elapsed();
+            if held >= DEBOUNCE {
+                self.decoder.press(held);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_round_trip() {
+        for (c, code) in MORSE_TABLE {
+            assert_eq!(morse_encode(*c), Some(*code));
+            assert_eq!(morse_decode(code), Some(*c));
+        }
+        assert_eq!(morse_encode('s'), Some("..."));
+        assert_eq!(morse_encode('#'), None);
+    }
+
+    #[test]
+    fn decoder_classifies_presses() {
+        // 20 WPM: 60 ms dot
+        let mut decoder = MorseDecoder::new(20);
+        decoder.press(Duration::from_millis(70));
+        decoder.press(Duration::from_millis(150));
+        assert_eq!(decoder.finish(), Some('A'));
+        assert_eq!(decoder.finish(), None);
+
+        for _ in 0..7 {
+            decoder.press(Duration::from_millis(200));
+        }
+        assert_eq!(decoder.finish(), Some('?'));
+    }
+}
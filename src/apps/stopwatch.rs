@@ -0,0 +1,279 @@
+//! stopwatch.rs — stopwatch and countdown timer with `mm:ss.t` display rendering
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut timer = CountdownTimer::new(Duration::from_secs(90));
+//! timer.start();
+//! loop {
+//!     match select(timer.wait_expired(), Timer::after_millis(100)).await {
+//!         Either::First(_) => break,
+//!         Either::Second(_) => timer.render(&mut lcd, "Tea")?,
+//!     }
+//! }
+//! ```
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{HeaplessString, HeaplessVec, SystemUptime, TextDisplay, Uptime};
+
+/// Laps kept by [`Stopwatch`]; older laps are dropped
+pub const STOPWATCH_MAX_LAPS: usize = 10;
+
+/// Format as `mm:ss.t`. Minutes grow past two digits instead of wrapping.
+pub fn format_mm_ss_t(duration: Duration) -> HeaplessString<16> {
+    let tenths = duration.as_millis() / 100;
+    let mut out = HeaplessString::new();
+    let _ = write!(
+        out,
+        "{:02}:{:02}.{}",
+        tenths / 600,
+        (tenths / 10) % 60,
+        tenths % 10
+    );
+    out
+}
+
+/// Elapsed-time stopwatch with laps
+#[derive(Debug, Clone, Default)]
+pub struct Stopwatch<U: Uptime = SystemUptime> {
+    started_at: Option<Instant>,
+    accumulated: Duration,
+    last_lap_split: Duration,
+    laps: HeaplessVec<Duration, STOPWATCH_MAX_LAPS>,
+    uptime: U,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<U: Uptime> Stopwatch<U> {
+    /// Stopwatch reading time from `uptime`, e.g. a [`MockUptime`](crate::MockUptime).
+    pub fn with_uptime(uptime: U) -> Self {
+        Self {
+            started_at: None,
+            accumulated: Duration::MIN,
+            last_lap_split: Duration::MIN,
+            laps: HeaplessVec::new(),
+            uptime,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Start or resume.
+    pub fn start(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(self.uptime.now());
+        }
+    }
+
+    /// Pause, keeping the elapsed time.
+    pub fn stop(&mut self) {
+        if let Some(started_at) = self.started_at.take() {
+            self.accumulated += self.uptime.elapsed_since(started_at);
+        }
+    }
+
+    /// Stop and clear the elapsed time and laps.
+    pub fn reset(&mut self) {
+        self.started_at = None;
+        self.accumulated = Duration::MIN;
+        self.last_lap_split = Duration::MIN;
+        self.laps.clear();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match self.started_at {
+            Some(started_at) => self.accumulated + self.uptime.elapsed_since(started_at),
+            None => self.accumulated,
+        }
+    }
+
+    /// Record a lap and return its length (time since the previous lap).
+    pub fn lap(&mut self) -> Duration {
+        let split = self.elapsed();
+        let lap = split - self.last_lap_split;
+        self.last_lap_split = split;
+        if self.laps.len() == STOPWATCH_MAX_LAPS {
+            self.laps.dequeue_front();
+        }
+        let _ = self.laps.push(lap);
+        lap
+    }
+
+    /// Recorded laps, oldest first
+    pub fn laps(&self) -> &[Duration] {
+        self.laps.as_slice()
+    }
+
+    /// Show `label` and the elapsed time, followed by the most recent laps if the display
+    /// has room.
+    pub fn render<D: TextDisplay>(&self, display: &mut D, label: &str) -> Result<(), D::Error> {
+        let mut content: HeaplessString<128> = HeaplessString::new();
+        let _ = write!(content, "{} {}", label, format_mm_ss_t(self.elapsed()));
+
+        let lap_lines = display.max_lines().saturating_sub(1);
+        for (i, lap) in self.laps().iter().enumerate().rev().take(lap_lines) {
+            let _ = write!(content, "\nL{} {}", i + 1, format_mm_ss_t(*lap));
+        }
+        display.display_str(content.as_str())
+    }
+}
+
+/// Countdown timer that can be paused and awaited
+#[derive(Debug, Clone)]
+pub struct CountdownTimer<U: Uptime = SystemUptime> {
+    duration: Duration,
+    started_at: Option<Instant>,
+    /// Time left when last paused
+    remaining: Duration,
+    uptime: U,
+}
+
+impl CountdownTimer {
+    pub fn new(duration: Duration) -> Self {
+        Self::with_uptime(duration, SystemUptime)
+    }
+
+    /// Wait until the countdown reaches zero. Never completes while paused.
+    pub async fn wait_expired(&self) {
+        match self.started_at {
+            Some(started_at) => Timer::at(started_at + self.remaining).await,
+            None if self.remaining == Duration::MIN => {}
+            None => core::future::pending().await,
+        }
+    }
+}
+
+impl<U: Uptime> CountdownTimer<U> {
+    /// Countdown reading time from `uptime`, e.g. a [`MockUptime`](crate::MockUptime).
+    pub fn with_uptime(duration: Duration, uptime: U) -> Self {
+        Self {
+            duration,
+            started_at: None,
+            remaining: duration,
+            uptime,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some() && !self.is_expired()
+    }
+
+    /// Start or resume.
+    pub fn start(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(self.uptime.now());
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.remaining = self.remaining();
+        self.started_at = None;
+    }
+
+    /// Stop and rewind to the full duration.
+    pub fn reset(&mut self) {
+        self.started_at = None;
+        self.remaining = self.duration;
+    }
+
+    /// Stop and rewind to a new duration.
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+        self.reset();
+    }
+
+    pub fn remaining(&self) -> Duration {
+        match self.started_at {
+            Some(started_at) => self
+                .remaining
+                .checked_sub(self.uptime.elapsed_since(started_at))
+                .unwrap_or(Duration::MIN),
+            None => self.remaining,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Duration::MIN
+    }
+
+    /// Show `label` and the time left.
+    pub fn render<D: TextDisplay>(&self, display: &mut D, label: &str) -> Result<(), D::Error> {
+        let mut content: HeaplessString<64> = HeaplessString::new();
+        let remaining = self.remaining();
+        if display.max_lines() > 1 {
+            let _ = write!(content, "{}\n{}", label, format_mm_ss_t(remaining));
+        } else {
+            let _ = write!(content, "{} {}", label, format_mm_ss_t(remaining));
+        }
+        if remaining == Duration::MIN {
+            let _ = content.push_str(" DONE");
+        }
+        display.display_str(content.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockUptime;
+
+    #[test]
+    fn formats_minutes_seconds_tenths() {
+        assert_eq!(format_mm_ss_t(Duration::MIN).as_str(), "00:00.0");
+        assert_eq!(
+            format_mm_ss_t(Duration::from_millis(1_999)).as_str(),
+            "00:01.9"
+        );
+        assert_eq!(
+            format_mm_ss_t(Duration::from_millis(754_300)).as_str(),
+            "12:34.3"
+        );
+        assert_eq!(
+            format_mm_ss_t(Duration::from_secs(100 * 60)).as_str(),
+            "100:00.0"
+        );
+    }
+
+    #[test]
+    fn stopwatch_laps_with_mock_time() {
+        let time = MockUptime::new();
+        let mut stopwatch = Stopwatch::with_uptime(&time);
+        stopwatch.start();
+        time.advance(Duration::from_secs(3));
+        assert_eq!(stopwatch.lap(), Duration::from_secs(3));
+        stopwatch.stop();
+        time.advance(Duration::from_secs(10));
+        stopwatch.start();
+        time.advance(Duration::from_secs(2));
+        assert_eq!(stopwatch.lap(), Duration::from_secs(2));
+        assert_eq!(stopwatch.elapsed(), Duration::from_secs(5));
+        stopwatch.reset();
+        assert_eq!(stopwatch.elapsed(), Duration::MIN);
+        assert!(stopwatch.laps().is_empty());
+    }
+
+    #[test]
+    fn countdown_pauses_and_expires() {
+        let time = MockUptime::new();
+        let mut timer = CountdownTimer::with_uptime(Duration::from_secs(60), &time);
+        timer.start();
+        time.advance(Duration::from_secs(20));
+        timer.pause();
+        time.advance(Duration::from_secs(100));
+        assert_eq!(timer.remaining(), Duration::from_secs(40));
+        timer.start();
+        time.advance(Duration::from_secs(40));
+        assert!(timer.is_expired());
+        assert!(!timer.is_running());
+    }
+}
@@ -0,0 +1,851 @@
+//! ui.rs — multi-page text UIs: a page stack, input routing and redraws
+//!
+//! A device UI is a set of [`Page`]s (a dashboard, menus, detail screens) addressed by
+//! their index, a [`PageId`]. The [`Ui`] keeps a stack of open pages; only the top one is
+//! shown and gets input. A page answers each [`UiEvent`] with a [`PageAction`] to open
+//! another page, go back or stay. An event a page ignores falls through to the [`Ui`]:
+//! [`UiEvent::Back`] closes the page, anything else is dropped.
+//!
+//! [`Ui::run`] owns the display while it runs. It renders the top page after every event,
+//! every [`Page::refresh_interval`] (for live values) and on a [`UiControl`] request, and
+//! only sends the screen when its text changed, so idle redraws don't flicker. Other tasks
+//! use the [`UiControl`] to push pages (an alarm screen), go home or force a redraw after
+//! drawing on the display themselves.
+//!
+//! Always-on OLEDs burn in. With a [`ScreensaverConfig`], the [`Ui`] blanks the screen, or
+//! keeps moving the text around by a character cell, after a while without input. Any
+//! input brings the page back; that first event only wakes the screen and goes no further,
+//! so a press in the dark can't trigger anything.
+//!
+//! Given a [`HapticsControl`] ([`Ui::set_haptics`]), input is felt as well as seen: a tick
+//! per turn, a click per press.
+//!
+//! Inputs: a [`RotaryEncoder`] turns and presses to select; a [`Button`] moves on with a
+//! short press and selects with a long one (there is no back, so give menus a way out); any
+//! [`KeyInput`] selects with `#` or Enter, goes back with `*` or Escape, moves with
+//! `+`/`A` and `-`/`B` and passes other keys through.
+//!
+//! # Example
+//!
+//! ```ignore
+//! const HOME: PageId = 0;
+//! const SETTINGS: PageId = 1;
+//! const BRIGHTNESS: PageId = 2;
+//! const ITEMS: &[MenuItem] = &[MenuItem::new("Settings", SETTINGS)];
+//! static UI: UiControl = UiControl::new();
+//!
+//! let mut menu = MenuPage::new("Menu", ITEMS);
+//! let mut pages: [&mut dyn Page; 3] = [&mut menu, &mut settings, &mut brightness];
+//! let mut ui = Ui::new(&mut pages, HOME);
+//! ui.set_screensaver(Some(ScreensaverConfig::default()));
+//! ui.run(&mut lcd, &mut encoder, &UI).await;
+//! ```
+
+use core::fmt::{self, Write};
+use core::future::pending;
+
+use embassy_futures::select::{Either, Either3, select, select3};
+use embassy_rp::gpio::Input;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    Button, ButtonEvent, EncoderEvent, HapticEffect, HapticsControl, HeaplessString, HeaplessVec,
+    KeyInput, RotaryEncoder, SystemUptime, TextDisplay, Uptime,
+};
+
+/// Pages a [`Ui`] can have open at once, including the home page
+pub const UI_MAX_DEPTH: usize = 8;
+/// Longest screen text a page can render, newlines included
+pub const UI_MAX_SCREEN_LEN: usize = 255;
+
+/// Default idle time before the screensaver starts
+pub const SCREENSAVER_DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Default time between moves of [`ScreensaverMode::Shift`]
+pub const SCREENSAVER_DEFAULT_SHIFT_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Input events queued while the [`Ui`] is busy redrawing
+const INPUT_QUEUE_LEN: usize = 4;
+
+/// Offsets in characters and lines [`ScreensaverMode::Shift`] cycles through
+const SHIFT_OFFSETS: [(usize, usize); 4] = [(1, 0), (1, 1), (0, 1), (0, 0)];
+
+/// Index of a page in the slice given to [`Ui::new`]
+pub type PageId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum UiEvent {
+    /// Move through choices, positive forward
+    Turn(i32),
+    Select,
+    Back,
+    /// Any other key of a keypad or keyboard
+    Key(char),
+}
+
+/// What a [`Page`] wants done after an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PageAction {
+    /// Not for this page; [`UiEvent::Back`] then closes it
+    Ignored,
+    /// Stay on this page
+    Handled,
+    /// Open a page on top of this one.
+    Push(PageId),
+    /// Open a page in place of this one.
+    Replace(PageId),
+    /// Close this page.
+    Pop,
+    /// Close every page above the home page.
+    Home,
+}
+
+/// Size of the display, in text lines and characters per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ScreenSize {
+    pub lines: usize,
+    pub columns: usize,
+}
+
+/// One screen of a [`Ui`]
+pub trait Page {
+    /// Draw the page as text, lines separated by `\n`, to fit `screen`.
+    fn render(&mut self, screen: ScreenSize, out: &mut dyn Write) -> fmt::Result;
+
+    fn handle(&mut self, event: UiEvent) -> PageAction;
+
+    /// Redraw this often while shown, for pages with live values
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The page is now on top, either newly opened or uncovered.
+    fn on_enter(&mut self) {}
+
+    /// The page is no longer on top, either closed or covered.
+    fn on_leave(&mut self) {}
+}
+
+/// User input driving a [`Ui`]
+#[allow(async_fn_in_trait)]
+pub trait UiInput {
+    async fn next_event(&mut self) -> UiEvent;
+}
+
+/// Turn to move, press to select. There is no back.
+impl UiInput for RotaryEncoder<'_> {
+    async fn next_event(&mut self) -> UiEvent {
+        match self.wait_for_event().await {
+            EncoderEvent::Turn(detents) => UiEvent::Turn(detents),
+            EncoderEvent::Press => UiEvent::Select,
+        }
+    }
+}
+
+/// Short press moves on, long press selects. There is no back.
+impl UiInput for Button<Input<'_>> {
+    async fn next_event(&mut self) -> UiEvent {
+        match self.wait_for_event().await {
+            ButtonEvent::ShortPress => UiEvent::Turn(1),
+            ButtonEvent::LongPress(_) => UiEvent::Select,
+        }
+    }
+}
+
+impl<K: KeyInput> UiInput for K {
+    async fn next_event(&mut self) -> UiEvent {
+        match self.wait_for_key().await {
+            '#' | '\n' | '\r' => UiEvent::Select,
+            '*' | '\x1b' => UiEvent::Back,
+            '+' | 'A' => UiEvent::Turn(1),
+            '-' | 'B' => UiEvent::Turn(-1),
+            key => UiEvent::Key(key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum UiCommand {
+    /// Resend the screen even if unchanged, e.g. after something else drew on it
+    Redraw,
+    Show(PageId),
+    Home,
+    /// Handle an event as if it came from the input
+    Event(UiEvent),
+    /// End the screensaver, as input would
+    Wake,
+}
+
+/// Requests from other tasks to a running [`Ui`]
+pub struct UiControl {
+    signal: Signal<CriticalSectionRawMutex, UiCommand>,
+}
+
+impl UiControl {
+    pub const fn new() -> Self {
+        Self {
+            signal: Signal::new(),
+        }
+    }
+
+    pub fn redraw(&self) {
+        self.signal.signal(UiCommand::Redraw);
+    }
+
+    /// Open `page` on top of the current one.
+    pub fn show(&self, page: PageId) {
+        self.signal.signal(UiCommand::Show(page));
+    }
+
+    pub fn home(&self) {
+        self.signal.signal(UiCommand::Home);
+    }
+
+    pub fn send(&self, event: UiEvent) {
+        self.signal.signal(UiCommand::Event(event));
+    }
+
+    /// End the screensaver without an input event, e.g. on motion in the room.
+    pub fn wake(&self) {
+        self.signal.signal(UiCommand::Wake);
+    }
+}
+
+impl Default for UiControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ScreensaverMode {
+    /// Clear the screen
+    Blank,
+    /// Keep the page up, moved by a character and a line every `shift_interval`
+    Shift,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreensaverConfig {
+    /// Time without input before the screensaver starts
+    pub timeout: Duration,
+    pub mode: ScreensaverMode,
+    pub shift_interval: Duration,
+}
+
+impl Default for ScreensaverConfig {
+    fn default() -> Self {
+        Self {
+            timeout: SCREENSAVER_DEFAULT_TIMEOUT,
+            mode: ScreensaverMode::Blank,
+            shift_interval: SCREENSAVER_DEFAULT_SHIFT_INTERVAL,
+        }
+    }
+}
+
+/// What the screen shows at a point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScreenState {
+    Awake,
+    Blank,
+    /// The page, moved right by `columns` and down by `lines`
+    Shifted {
+        columns: usize,
+        lines: usize,
+    },
+}
+
+/// Idle tracking for a [`ScreensaverConfig`]
+#[derive(Debug, Clone)]
+struct Screensaver {
+    config: Option<ScreensaverConfig>,
+    last_input: Instant,
+}
+
+impl Screensaver {
+    fn new(config: Option<ScreensaverConfig>, now: Instant) -> Self {
+        Self {
+            config,
+            last_input: now,
+        }
+    }
+
+    /// Time since the screensaver started, if it has
+    fn asleep_for(&self, now: Instant) -> Option<(&ScreensaverConfig, Duration)> {
+        let config = self.config.as_ref()?;
+        let idle = now.saturating_duration_since(self.last_input);
+        (idle >= config.timeout).then(|| (config, idle - config.timeout))
+    }
+
+    fn state(&self, now: Instant) -> ScreenState {
+        match self.asleep_for(now) {
+            None => ScreenState::Awake,
+            Some((config, _)) if config.mode == ScreensaverMode::Blank => ScreenState::Blank,
+            Some((config, asleep)) => {
+                let moves = asleep.as_ticks() / config.shift_interval.as_ticks().max(1);
+                let (columns, lines) = SHIFT_OFFSETS[(moves % SHIFT_OFFSETS.len() as u64) as usize];
+                ScreenState::Shifted { columns, lines }
+            }
+        }
+    }
+
+    /// When [`state`](Self::state) next changes without input
+    fn next_change(&self, now: Instant) -> Option<Instant> {
+        let config = self.config.as_ref()?;
+        let asleep_at = self.last_input + config.timeout;
+        match self.asleep_for(now) {
+            None => Some(asleep_at),
+            Some(_) if config.mode == ScreensaverMode::Blank => None,
+            Some((_, asleep)) => {
+                let interval = config.shift_interval.as_ticks().max(1);
+                let moves = asleep.as_ticks() / interval + 1;
+                Some(asleep_at + Duration::from_ticks(moves * interval))
+            }
+        }
+    }
+
+    /// Note input at `now`; `true` if it ended the screensaver.
+    fn wake(&mut self, now: Instant) -> bool {
+        let was_asleep = self.state(now) != ScreenState::Awake;
+        self.last_input = now;
+        was_asleep
+    }
+}
+
+/// What an input event feels like
+fn haptic_feedback(event: UiEvent) -> HapticEffect {
+    match event {
+        UiEvent::Turn(_) => HapticEffect::Tick,
+        UiEvent::Select | UiEvent::Back | UiEvent::Key(_) => HapticEffect::Click,
+    }
+}
+
+/// `text` moved right by `columns` and down by `lines`, cut to fit `screen`
+fn write_shifted(
+    out: &mut dyn Write,
+    text: &str,
+    (columns, lines): (usize, usize),
+    screen: ScreenSize,
+) -> fmt::Result {
+    for _ in 0..lines.min(screen.lines) {
+        out.write_char('\n')?;
+    }
+    let rows = screen.lines.saturating_sub(lines);
+    for (index, line) in text.split('\n').take(rows).enumerate() {
+        if index > 0 {
+            out.write_char('\n')?;
+        }
+        let shifted = core::iter::repeat_n(' ', columns).chain(line.chars());
+        shifted
+            .take(screen.columns)
+            .try_for_each(|c| out.write_char(c))?;
+    }
+    Ok(())
+}
+
+/// A stack of [`Page`]s sharing one display and one input
+pub struct Ui<'s, 'p, U: Uptime = SystemUptime> {
+    pages: &'s mut [&'p mut dyn Page],
+    stack: HeaplessVec<PageId, UI_MAX_DEPTH>,
+    /// Text last sent to the display
+    shown: HeaplessString<UI_MAX_SCREEN_LEN>,
+    /// The display doesn't show `shown`, e.g. after a failed write
+    stale: bool,
+    screensaver: Screensaver,
+    haptics: Option<&'s HapticsControl>,
+    uptime: U,
+}
+
+impl<'s, 'p> Ui<'s, 'p> {
+    /// Start on `home`, which stays at the bottom of the stack.
+    ///
+    /// # Panics
+    ///
+    /// If `home` is not an index into `pages`.
+    pub fn new(pages: &'s mut [&'p mut dyn Page], home: PageId) -> Self {
+        Self::with_uptime(pages, home, SystemUptime)
+    }
+}
+
+impl<'s, 'p, U: Uptime> Ui<'s, 'p, U> {
+    /// Like [`Ui::new`], with the screensaver reading time from `uptime`, e.g. a
+    /// [`MockUptime`](crate::MockUptime).
+    pub fn with_uptime(pages: &'s mut [&'p mut dyn Page], home: PageId, uptime: U) -> Self {
+        assert!(home < pages.len(), "home page out of range");
+        let mut stack = HeaplessVec::new();
+        let _ = stack.push(home);
+        pages[home].on_enter();
+        Self {
+            pages,
+            stack,
+            shown: HeaplessString::new(),
+            stale: true,
+            screensaver: Screensaver::new(None, Instant::MIN),
+            haptics: None,
+            uptime,
+        }
+    }
+
+    /// Give feedback on `haptics` for every input event, including one that only wakes
+    /// the screen.
+    pub fn set_haptics(&mut self, haptics: Option<&'s HapticsControl>) {
+        self.haptics = haptics;
+    }
+
+    /// Blank or move the screen after a while without input; `None` (the default) keeps
+    /// it on. Counts as input.
+    pub fn set_screensaver(&mut self, config: Option<ScreensaverConfig>) {
+        self.screensaver = Screensaver::new(config, self.uptime.now());
+    }
+
+    /// The page on top
+    pub fn current(&self) -> PageId {
+        self.stack.as_slice()[self.stack.len() - 1]
+    }
+
+    /// Number of open pages, 1 when only home is
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Route `event` to the page on top and follow its answer.
+    pub fn handle(&mut self, event: UiEvent) {
+        let current = self.current();
+        match self.pages[current].handle(event) {
+            PageAction::Ignored if event == UiEvent::Back => self.pop(),
+            PageAction::Ignored | PageAction::Handled => {}
+            PageAction::Push(page) => self.push(page),
+            PageAction::Replace(page) => self.replace(page),
+            PageAction::Pop => self.pop(),
+            PageAction::Home => self.home(),
+        }
+    }
+
+    /// Open `page` on top. Unknown pages and pushes past [`UI_MAX_DEPTH`] are ignored.
+    pub fn push(&mut self, page: PageId) {
+        if page < self.pages.len() && self.stack.len() < UI_MAX_DEPTH {
+            self.switch(|stack| {
+                let _ = stack.push(page);
+            });
+        }
+    }
+
+    /// Replace the page on top with `page`. The home page can be replaced too.
+    pub fn replace(&mut self, page: PageId) {
+        if page < self.pages.len() {
+            self.switch(|stack| {
+                stack.pop();
+                let _ = stack.push(page);
+            });
+        }
+    }
+
+    /// Close the page on top, unless it is the home page.
+    pub fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            self.switch(|stack| {
+                stack.pop();
+            });
+        }
+    }
+
+    pub fn home(&mut self) {
+        if self.stack.len() > 1 {
+            self.switch(|stack| {
+                while stack.len() > 1 {
+                    stack.pop();
+                }
+            });
+        }
+    }
+
+    fn switch(&mut self, change: impl FnOnce(&mut HeaplessVec<PageId, UI_MAX_DEPTH>)) {
+        let previous = self.current();
+        self.pages[previous].on_leave();
+        change(&mut self.stack);
+        let current = self.current();
+        self.pages[current].on_enter();
+    }
+
+    /// Render the page on top into `out`.
+    pub fn render(&mut self, screen: ScreenSize, out: &mut dyn Write) -> fmt::Result {
+        let current = self.current();
+        self.pages[current].render(screen, out)
+    }
+
+    /// Draw the page on top, as the screensaver has it, if its text changed since the
+    /// last call, or `force`d.
+    pub fn redraw<D: TextDisplay>(&mut self, display: &mut D, force: bool) {
+        let screen = ScreenSize {
+            lines: display.max_lines(),
+            columns: display.max_chars_per_line(),
+        };
+        let mut text: HeaplessString<UI_MAX_SCREEN_LEN> = HeaplessString::new();
+        // Text that doesn't fit is cut off rather than dropped
+        match self.screensaver.state(self.uptime.now()) {
+            ScreenState::Awake => {
+                let _ = self.render(screen, &mut text);
+            }
+            ScreenState::Blank => {}
+            ScreenState::Shifted { columns, lines } => {
+                let mut page: HeaplessString<UI_MAX_SCREEN_LEN> = HeaplessString::new();
+                let _ = self.render(screen, &mut page);
+                let _ = write_shifted(&mut text, page.as_str(), (columns, lines), screen);
+            }
+        }
+        if force || self.stale || text != self.shown {
+            self.stale = display.display_str(text.as_str()).is_err();
+            self.shown = text;
+        }
+    }
+
+    /// Show the pages on `display` and serve `input` and `control` forever.
+    pub async fn run<D: TextDisplay, I: UiInput>(
+        &mut self,
+        display: &mut D,
+        input: &mut I,
+        control: &UiControl,
+    ) -> ! {
+        // Input is read without pause, so a press during a redraw or tick isn't lost
+        let events = Channel::new();
+        let forward = forward_input(input, &events);
+        let serve = self.serve(display, &events, control);
+        match select(forward, serve).await {
+            Either::First(never) | Either::Second(never) => never,
+        }
+    }
+
+    async fn serve<D: TextDisplay>(
+        &mut self,
+        display: &mut D,
+        events: &Channel<NoopRawMutex, UiEvent, INPUT_QUEUE_LEN>,
+        control: &UiControl,
+    ) -> ! {
+        let mut force = true;
+        loop {
+            self.redraw(display, force);
+            force = false;
+
+            let now = self.uptime.now();
+            // A blank screen has nothing to refresh
+            let refresh = match self.screensaver.state(now) {
+                ScreenState::Blank => None,
+                _ => self.pages[self.current()].refresh_interval(),
+            };
+            let wake_at = [
+                refresh.map(|interval| now + interval),
+                self.screensaver.next_change(now),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let tick = async {
+                match wake_at {
+                    Some(at) => Timer::at(at).await,
+                    None => pending().await,
+                }
+            };
+            match select3(events.receive(), control.signal.wait(), tick).await {
+                Either3::First(event) => {
+                    if let Some(haptics) = self.haptics {
+                        haptics.play(haptic_feedback(event));
+                    }
+                    if !self.screensaver.wake(self.uptime.now()) {
+                        self.handle(event);
+                    }
+                }
+                Either3::Second(UiCommand::Redraw) => force = true,
+                Either3::Second(command) => {
+                    self.screensaver.wake(self.uptime.now());
+                    match command {
+                        UiCommand::Show(page) => self.push(page),
+                        UiCommand::Home => self.home(),
+                        UiCommand::Event(event) => self.handle(event),
+                        UiCommand::Redraw | UiCommand::Wake => {}
+                    }
+                }
+                Either3::Third(()) => {}
+            }
+        }
+    }
+}
+
+async fn forward_input<I: UiInput>(
+    input: &mut I,
+    events: &Channel<NoopRawMutex, UiEvent, INPUT_QUEUE_LEN>,
+) -> ! {
+    loop {
+        events.send(input.next_event().await).await;
+    }
+}
+
+/// One entry of a [`MenuPage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct MenuItem<'a> {
+    pub label: &'a str,
+    /// Opened on select
+    pub page: PageId,
+}
+
+impl<'a> MenuItem<'a> {
+    pub const fn new(label: &'a str, page: PageId) -> Self {
+        Self { label, page }
+    }
+}
+
+/// A list of [`MenuItem`]s under a title, scrolled to keep the selection in view. Turning
+/// moves the `>` marker; select opens the item's page.
+pub struct MenuPage<'a> {
+    title: &'a str,
+    items: &'a [MenuItem<'a>],
+    selected: usize,
+}
+
+impl<'a> MenuPage<'a> {
+    pub fn new(title: &'a str, items: &'a [MenuItem<'a>]) -> Self {
+        Self {
+            title,
+            items,
+            selected: 0,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.items.len().saturating_sub(1));
+    }
+}
+
+impl Page for MenuPage<'_> {
+    fn render(&mut self, screen: ScreenSize, out: &mut dyn Write) -> fmt::Result {
+        let mut rows = screen.lines;
+        if !self.title.is_empty() {
+            write_clipped(out, self.title, screen.columns)?;
+            rows = rows.saturating_sub(1);
+        }
+        let first = (self.selected + 1).saturating_sub(rows.max(1));
+        let mut new_line = !self.title.is_empty();
+        for (index, item) in self.items.iter().enumerate().skip(first).take(rows) {
+            if new_line {
+                out.write_char('\n')?;
+            }
+            new_line = true;
+            out.write_char(if index == self.selected { '>' } else { ' ' })?;
+            write_clipped(out, item.label, screen.columns.saturating_sub(1))?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, event: UiEvent) -> PageAction {
+        match (event, self.items.get(self.selected)) {
+            (UiEvent::Turn(delta), _) => {
+                let moved = self.selected as i64 + delta as i64;
+                self.select(moved.max(0) as usize);
+                PageAction::Handled
+            }
+            (UiEvent::Select, Some(item)) => PageAction::Push(item.page),
+            _ => PageAction::Ignored,
+        }
+    }
+}
+
+fn write_clipped(out: &mut dyn Write, text: &str, columns: usize) -> fmt::Result {
+    text.chars()
+        .take(columns)
+        .try_for_each(|c| out.write_char(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockTextDisplay, MockUptime};
+
+    const SCREEN: ScreenSize = ScreenSize {
+        lines: 2,
+        columns: 16,
+    };
+
+    /// Counts turns and its own entries; `Back` is left to the [`Ui`].
+    #[derive(Default)]
+    struct Counter {
+        count: i32,
+        entered: u32,
+        left: u32,
+    }
+
+    impl Page for Counter {
+        fn render(&mut self, _screen: ScreenSize, out: &mut dyn Write) -> fmt::Result {
+            write!(out, "Count {}", self.count)
+        }
+
+        fn handle(&mut self, event: UiEvent) -> PageAction {
+            match event {
+                UiEvent::Turn(delta) => {
+                    self.count += delta;
+                    PageAction::Handled
+                }
+                UiEvent::Select => PageAction::Home,
+                UiEvent::Key('r') => PageAction::Replace(0),
+                _ => PageAction::Ignored,
+            }
+        }
+
+        fn on_enter(&mut self) {
+            self.entered += 1;
+        }
+
+        fn on_leave(&mut self) {
+            self.left += 1;
+        }
+    }
+
+    fn render(ui: &mut Ui<'_, '_>) -> HeaplessString<64> {
+        let mut out = HeaplessString::new();
+        ui.render(SCREEN, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn menu_opens_pages_and_back_returns() {
+        const ITEMS: &[MenuItem] = &[MenuItem::new("Counter", 1), MenuItem::new("Other", 2)];
+        let mut menu = MenuPage::new("Menu", ITEMS);
+        let mut counter = Counter::default();
+        let mut other = Counter::default();
+        {
+            let mut pages: [&mut dyn Page; 3] = [&mut menu, &mut counter, &mut other];
+            let mut ui = Ui::new(&mut pages, 0);
+            assert_eq!(render(&mut ui).as_str(), "Menu\n>Counter");
+
+            ui.handle(UiEvent::Select);
+            assert_eq!(ui.current(), 1);
+            ui.handle(UiEvent::Turn(3));
+            assert_eq!(render(&mut ui).as_str(), "Count 3");
+
+            ui.handle(UiEvent::Back);
+            assert_eq!((ui.current(), ui.depth()), (0, 1));
+            ui.handle(UiEvent::Back);
+            assert_eq!(ui.depth(), 1);
+
+            ui.handle(UiEvent::Turn(5));
+            assert_eq!(render(&mut ui).as_str(), "Menu\n>Other");
+            ui.handle(UiEvent::Select);
+            assert_eq!(ui.current(), 2);
+            ui.handle(UiEvent::Key('r'));
+            assert_eq!((ui.current(), ui.depth()), (0, 2));
+            ui.push(1);
+            ui.handle(UiEvent::Select);
+            assert_eq!((ui.current(), ui.depth()), (0, 1));
+        }
+        assert_eq!((counter.count, counter.entered, counter.left), (3, 2, 2));
+        assert_eq!((other.entered, other.left), (1, 1));
+    }
+
+    #[test]
+    fn pushes_beyond_pages_or_depth_are_ignored() {
+        let mut home = Counter::default();
+        let mut page = Counter::default();
+        let mut pages: [&mut dyn Page; 2] = [&mut home, &mut page];
+        let mut ui = Ui::new(&mut pages, 0);
+        ui.push(2);
+        assert_eq!(ui.depth(), 1);
+        for _ in 0..UI_MAX_DEPTH + 2 {
+            ui.push(1);
+        }
+        assert_eq!(ui.depth(), UI_MAX_DEPTH);
+        ui.home();
+        assert_eq!((ui.current(), ui.depth()), (0, 1));
+    }
+
+    #[test]
+    fn screensaver_blanks_or_shifts_when_idle() {
+        let start = Instant::from_secs(100);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut blank = Screensaver::new(Some(ScreensaverConfig::default()), start);
+        assert_eq!(blank.state(at(59)), ScreenState::Awake);
+        assert_eq!(blank.next_change(at(59)), Some(at(60)));
+        assert_eq!(blank.state(at(60)), ScreenState::Blank);
+        assert_eq!(blank.next_change(at(61)), None);
+        assert!(blank.wake(at(70)));
+        assert!(!blank.wake(at(71)));
+        assert_eq!(blank.state(at(130)), ScreenState::Awake);
+
+        let shift = ScreensaverConfig {
+            mode: ScreensaverMode::Shift,
+            ..ScreensaverConfig::default()
+        };
+        let shift = Screensaver::new(Some(shift), start);
+        let shifted = |columns, lines| ScreenState::Shifted { columns, lines };
+        assert_eq!(shift.state(at(60)), shifted(1, 0));
+        assert_eq!(shift.next_change(at(60)), Some(at(80)));
+        assert_eq!(shift.state(at(85)), shifted(1, 1));
+        assert_eq!(shift.state(at(120)), shifted(0, 0));
+        assert_eq!(shift.state(at(140)), shifted(1, 0));
+
+        let off = Screensaver::new(None, start);
+        assert_eq!(off.state(at(1000)), ScreenState::Awake);
+        assert_eq!(off.next_change(at(1000)), None);
+    }
+
+    #[test]
+    fn redraw_blanks_after_idle_time() {
+        let time = MockUptime::new();
+        let mut home = Counter::default();
+        let mut pages: [&mut dyn Page; 1] = [&mut home];
+        let mut ui = Ui::with_uptime(&mut pages, 0, &time);
+        ui.set_screensaver(Some(ScreensaverConfig::default()));
+        let mut screen = MockTextDisplay::new(SCREEN.lines, SCREEN.columns);
+        ui.redraw(&mut screen, false);
+        assert_eq!(screen.content(), "Count 0");
+
+        time.advance(SCREENSAVER_DEFAULT_TIMEOUT - Duration::from_millis(1));
+        ui.redraw(&mut screen, false);
+        assert_eq!(screen.content(), "Count 0");
+        time.advance(Duration::from_millis(1));
+        ui.redraw(&mut screen, false);
+        assert_eq!(screen.content(), "");
+    }
+
+    #[test]
+    fn shifted_text_still_fits() {
+        let mut out: HeaplessString<64> = HeaplessString::new();
+        write_shifted(&mut out, "Temp 23.5C\nHumidity 45.0%", (1, 0), SCREEN).unwrap();
+        assert_eq!(out.as_str(), " Temp 23.5C\n Humidity 45.0%");
+        out.clear();
+        write_shifted(&mut out, "0123456789abcdef\nsecond", (1, 1), SCREEN).unwrap();
+        assert_eq!(out.as_str(), "\n 0123456789abcde");
+    }
+
+    #[test]
+    fn menu_scrolls_to_selection() {
+        const ITEMS: &[MenuItem] = &[
+            MenuItem::new("One", 1),
+            MenuItem::new("Two", 2),
+            MenuItem::new("Three", 3),
+            MenuItem::new("A long fourth item", 4),
+        ];
+        let mut menu = MenuPage::new("", ITEMS);
+        let mut out: HeaplessString<64> = HeaplessString::new();
+        menu.render(SCREEN, &mut out).unwrap();
+        assert_eq!(out.as_str(), ">One\n Two");
+
+        assert_eq!(menu.handle(UiEvent::Turn(2)), PageAction::Handled);
+        out.clear();
+        menu.render(SCREEN, &mut out).unwrap();
+        assert_eq!(out.as_str(), " Two\n>Three");
+
+        menu.handle(UiEvent::Turn(1));
+        assert_eq!(menu.handle(UiEvent::Select), PageAction::Push(4));
+        out.clear();
+        menu.render(SCREEN, &mut out).unwrap();
+        assert_eq!(out.as_str(), " Three\n>A long fourth i");
+
+        menu.handle(UiEvent::Turn(-10));
+        assert_eq!(menu.selected(), 0);
+        assert_eq!(menu.handle(UiEvent::Back), PageAction::Ignored);
+    }
+}
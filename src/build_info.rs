@@ -0,0 +1,100 @@
+//! build_info.rs — firmware identity and boot banner
+//!
+//! [`build_info!`](crate::build_info) captures the *calling* crate's name and version (and a
+//! `GIT_HASH` env var if set at build time, e.g. `GIT_HASH=$(git rev-parse --short HEAD)`),
+//! so every firmware can show the same startup banner.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut lcd = InlandKs0061I2cDisplay::new_with_default_address(i2c)?;
+//! boot_banner_with_build_info(&mut lcd, &build_info!())?;
+//! Timer::after_secs(2).await;
+//! ```
+
+use core::fmt::Write;
+
+use crate::{HeaplessString, TextDisplay};
+
+/// Identity of a firmware build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+}
+
+/// Build info of the crate the macro is invoked in.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::BuildInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: option_env!("GIT_HASH"),
+        }
+    };
+}
+
+/// Build info of darkpicolib itself
+pub const DARKPICOLIB_BUILD_INFO: BuildInfo = build_info!();
+
+const BANNER_MAX_LINES: usize = 4;
+const BANNER_LINE_CAPACITY: usize = 32;
+const BANNER_CAPACITY: usize = (BANNER_LINE_CAPACITY + 1) * BANNER_MAX_LINES;
+
+/// Show firmware name and version on any [`TextDisplay`].
+pub fn boot_banner<D: TextDisplay>(
+    display: &mut D,
+    name: &str,
+    version: &str,
+) -> Result<(), D::Error> {
+    show_banner(display, name, version, None)
+}
+
+/// Show a [`BuildInfo`] (name, version and git hash when available) on any [`TextDisplay`].
+pub fn boot_banner_with_build_info<D: TextDisplay>(
+    display: &mut D,
+    info: &BuildInfo,
+) -> Result<(), D::Error> {
+    show_banner(display, info.name, info.version, info.git_hash)
+}
+
+fn show_banner<D: TextDisplay>(
+    display: &mut D,
+    name: &str,
+    version: &str,
+    git_hash: Option<&str>,
+) -> Result<(), D::Error> {
+    let mut lines = [const { HeaplessString::<BANNER_LINE_CAPACITY>::new() }; BANNER_MAX_LINES];
+    let _ = write!(lines[0], "{}", name);
+    let _ = write!(lines[1], "v{}", version);
+
+    if display.max_lines() >= BANNER_MAX_LINES {
+        if let Some(hash) = git_hash {
+            let _ = write!(lines[2], "git {}", hash);
+        }
+        let _ = write!(lines[3], "darkpicolib v{}", DARKPICOLIB_BUILD_INFO.version);
+    } else if let Some(hash) = git_hash {
+        // Small displays: squeeze the hash next to the version.
+        let _ = write!(lines[1], " {}", hash);
+    }
+
+    let max_chars = display.max_chars_per_line();
+    let mut content: HeaplessString<BANNER_CAPACITY> = HeaplessString::new();
+    let mut shown = 0;
+    for line in lines.iter().filter(|line| !line.is_empty()) {
+        if shown == display.max_lines() {
+            break;
+        }
+        if shown > 0 {
+            let _ = content.push('\n');
+        }
+        for c in line.as_str().chars().take(max_chars) {
+            let _ = content.push(c);
+        }
+        shown += 1;
+    }
+
+    display.display_str(content.as_str())
+}
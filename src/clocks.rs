@@ -0,0 +1,115 @@
+//! clocks.rs — system clock selection at init, and the real clock rates for drivers
+//!
+//! [`init_with_system_clock`] replaces `embassy_rp::init(Default::default())` when the
+//! firmware wants a different CPU speed. Drivers in this crate that derive timing from the
+//! system clock ([`DcMotor`](crate::DcMotor), the WiFi PIO SPI,
+//! [`ServoConfig::with_system_clock`](crate::ServoConfig::with_system_clock)) read the actual
+//! rate via [`sys_clock_hz`] instead of assuming 125 MHz. Hardware SPI/I2C/UART drivers from
+//! embassy-rp compute their dividers from the live clock when created, so create them after
+//! init (the usual order) and they stay correct.
+//!
+//! Trade-offs of the presets:
+//!
+//! * 48 MHz — roughly a third of the active current of 125 MHz; USB still works (it has its
+//!   own PLL), but everything CPU-bound (displays, crypto, float math) is 2.6x slower.
+//! * 125 MHz — the embassy-rp default and what most examples assume.
+//! * 133 MHz — the original RP2040 datasheet maximum at the default core voltage.
+//! * 200 MHz — supported since the RP2040 datasheet update, with the core at 1.15 V. More
+//!   current and heat; XIP flash runs at 100 MHz, which all Pico flash chips handle.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let p = init_with_system_clock(SystemClock::Mhz200)?;
+//! info!("running at {} MHz", sys_clock_hz() / 1_000_000);
+//! ```
+
+use embassy_rp::clocks::{ClockConfig, CoreVoltage, clk_peri_freq, clk_sys_freq};
+use fixed::FixedU32;
+use fixed::types::extra::U8;
+
+/// Fastest PIO state machine clock for the CYW43 SPI (its 31.25 MHz bus clock at 2 cycles
+/// per bit), as with the cyw43-pio default divider at 125 MHz
+const CYW43_MAX_PIO_CLOCK_HZ: u64 = 62_500_000;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum ClocksError {
+    #[error("System clock of {0} Hz can't be generated")]
+    UnsupportedFrequency(u32),
+}
+
+/// System clock presets; see the module docs for the trade-offs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SystemClock {
+    Mhz48,
+    Mhz125,
+    Mhz133,
+    Mhz200,
+    /// Any frequency the PLL can produce, in Hz
+    Custom(u32),
+}
+
+impl SystemClock {
+    pub const fn hz(self) -> u32 {
+        match self {
+            Self::Mhz48 => 48_000_000,
+            Self::Mhz125 => 125_000_000,
+            Self::Mhz133 => 133_000_000,
+            Self::Mhz200 => 200_000_000,
+            Self::Custom(hz) => hz,
+        }
+    }
+
+    /// Clock configuration for this frequency, raising the core voltage above 133 MHz.
+    pub fn clock_config(self) -> Result<ClockConfig, ClocksError> {
+        let hz = self.hz();
+        let mut config =
+            ClockConfig::system_freq(hz).map_err(|_| ClocksError::UnsupportedFrequency(hz))?;
+        if hz > 133_000_000 {
+            config.core_voltage = CoreVoltage::V1_15;
+        }
+        Ok(config)
+    }
+}
+
+/// Initialize embassy-rp with the system clock at `clock`.
+pub fn init_with_system_clock(clock: SystemClock) -> Result<embassy_rp::Peripherals, ClocksError> {
+    let config = embassy_rp::config::Config::new(clock.clock_config()?);
+    Ok(embassy_rp::init(config))
+}
+
+/// Current system clock; also the PWM and PIO clock
+pub fn sys_clock_hz() -> u32 {
+    clk_sys_freq()
+}
+
+/// Current peripheral clock (SPI, UART)
+pub fn peri_clock_hz() -> u32 {
+    clk_peri_freq()
+}
+
+/// PIO clock divider for the CYW43 SPI at the current system clock: the cyw43-pio default
+/// at 125 MHz, slower when overclocked so the WiFi chip's bus limit is respected.
+pub fn cyw43_clock_divider() -> FixedU32<U8> {
+    FixedU32::from_bits(cyw43_divider_bits(sys_clock_hz()))
+}
+
+fn cyw43_divider_bits(sys_hz: u32) -> u32 {
+    // 8 fractional bits, rounded up so the PIO never runs faster than allowed; at least 1.0
+    let bits = (sys_hz as u64 * 256).div_ceil(CYW43_MAX_PIO_CLOCK_HZ);
+    bits.max(256) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cyw43_divider() {
+        // Same as cyw43_pio::DEFAULT_CLOCK_DIVIDER (2.0) at the default clock
+        assert_eq!(cyw43_divider_bits(125_000_000), 0x200);
+        assert_eq!(cyw43_divider_bits(48_000_000), 0x100);
+        let bits = cyw43_divider_bits(200_000_000);
+        assert!(200_000_000u64 * 256 / bits as u64 <= CYW43_MAX_PIO_CLOCK_HZ);
+    }
+}
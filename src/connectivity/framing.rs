@@ -0,0 +1,280 @@
+//! framing.rs — COBS frames with a CRC over any byte stream
+//!
+//! UART, TCP and USB CDC carry bytes, not messages. [`FramedWrite`] turns each message into
+//! a frame: the payload and its CRC-16 ([`crc16`], big-endian) are COBS-encoded, which
+//! removes every zero byte, and a single `0x00` ends the frame. [`FramedRead`] splits the
+//! stream at the zeros, decodes each frame in place inside its buffer and returns the
+//! payload as a slice of it, without copying. A corrupted or truncated frame fails its CRC
+//! and is reported; the reader is back in sync at the next zero, so one bad frame never
+//! takes the following ones with it.
+//!
+//! Both sides work over the `embedded-io-async` traits, so they fit a buffered UART, an
+//! embassy-net `TcpSocket` or anything else implementing them.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let (rx, tx) = uart.split();
+//! let mut frames = FramedRead::<_, 128>::new(rx);
+//! let mut out = FramedWrite::new(tx);
+//! loop {
+//!     match frames.read_frame().await {
+//!         Ok(payload) => out.write_frame(&handle(payload)).await?,
+//!         Err(FrameError::Crc) => continue, // noise on the line
+//!         Err(e) => return Err(e),
+//!     }
+//! }
+//! ```
+
+use embedded_io_async::{Read, Write};
+
+use crate::crc16;
+
+/// Bytes a payload of `len` bytes takes on the wire, CRC and delimiter included
+pub const fn framed_len(len: usize) -> usize {
+    let encoded = len + CRC_LEN;
+    encoded + encoded / MAX_BLOCK_DATA + 2
+}
+
+const CRC_LEN: usize = 2;
+const DELIMITER: u8 = 0x00;
+/// Most data bytes in one COBS block
+const MAX_BLOCK_DATA: usize = 254;
+/// Code of a full block, which has no implied zero after it
+const FULL_BLOCK: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum FrameError {
+    #[error("Read or write failed")]
+    Io,
+    #[error("Stream ended")]
+    EndOfStream,
+    #[error("Frame longer than the read buffer")]
+    TooLong,
+    #[error("Invalid COBS encoding")]
+    Encoding,
+    #[error("CRC mismatch")]
+    Crc,
+}
+
+/// Reads frames into a buffer of `N` bytes, which must hold a whole encoded frame
+/// ([`framed_len`] of the largest payload).
+pub struct FramedRead<R, const N: usize> {
+    reader: R,
+    buf: [u8; N],
+    /// Start of the next frame
+    start: usize,
+    /// Bytes before this were searched for a delimiter
+    scanned: usize,
+    /// End of the received bytes
+    end: usize,
+    /// Skipping an oversized frame up to its delimiter
+    discarding: bool,
+}
+
+impl<R: Read, const N: usize> FramedRead<R, N> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: [0; N],
+            start: 0,
+            scanned: 0,
+            end: 0,
+            discarding: false,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Bytes already received after the current frame are dropped.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Wait for the next frame and return its payload, borrowed from the read buffer.
+    pub async fn read_frame(&mut self) -> Result<&[u8], FrameError> {
+        loop {
+            if let Some(pos) = self.buf[self.scanned..self.end]
+                .iter()
+                .position(|&b| b == DELIMITER)
+            {
+                let frame_start = self.start;
+                let frame_end = self.scanned + pos;
+                self.start = frame_end + 1;
+                self.scanned = self.start;
+                if core::mem::take(&mut self.discarding) {
+                    return Err(FrameError::TooLong);
+                }
+                if frame_end == frame_start {
+                    // Empty frames are used to flush a line; skip them
+                    continue;
+                }
+                let len = cobs_decode_in_place(&mut self.buf[frame_start..frame_end])
+                    .ok_or(FrameError::Encoding)?;
+                let payload = check_crc(&self.buf[frame_start..frame_start + len])?;
+                return Ok(payload);
+            }
+            self.scanned = self.end;
+            if self.start > 0 {
+                self.buf.copy_within(self.start..self.end, 0);
+                self.end -= self.start;
+                self.scanned = self.end;
+                self.start = 0;
+            }
+            if self.end == N {
+                self.discarding = true;
+                self.end = 0;
+                self.scanned = 0;
+            }
+            let read = self
+                .reader
+                .read(&mut self.buf[self.end..])
+                .await
+                .map_err(|_| FrameError::Io)?;
+            if read == 0 {
+                return Err(FrameError::EndOfStream);
+            }
+            self.end += read;
+        }
+    }
+}
+
+pub struct FramedWrite<W> {
+    writer: W,
+}
+
+impl<W: Write> FramedWrite<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Send `payload` as one frame and flush it. Payloads of any length are fine; only the
+    /// reader's buffer limits them.
+    pub async fn write_frame(&mut self, payload: &[u8]) -> Result<(), FrameError> {
+        let crc = crc16(payload).to_be_bytes();
+        // Code, data and room for the delimiter
+        let mut block = [0u8; MAX_BLOCK_DATA + 2];
+        let mut len = 0;
+        for &byte in payload.iter().chain(crc.iter()) {
+            if byte == 0 {
+                block[0] = len as u8 + 1;
+                self.write(&block[..=len]).await?;
+                len = 0;
+                continue;
+            }
+            len += 1;
+            block[len] = byte;
+            if len == MAX_BLOCK_DATA {
+                block[0] = FULL_BLOCK;
+                self.write(&block[..=len]).await?;
+                len = 0;
+            }
+        }
+        block[0] = len as u8 + 1;
+        block[len + 1] = DELIMITER;
+        self.write(&block[..len + 2]).await?;
+        self.writer.flush().await.map_err(|_| FrameError::Io)
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), FrameError> {
+        self.writer
+            .write_all(data)
+            .await
+            .map_err(|_| FrameError::Io)
+    }
+}
+
+/// Decode a COBS frame (without its delimiter) in place; returns the decoded length, or
+/// `None` if a block runs past the end.
+fn cobs_decode_in_place(buf: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < buf.len() {
+        let code = buf[read];
+        if code == 0 {
+            return None;
+        }
+        read += 1;
+        let len = code as usize - 1;
+        if read + len > buf.len() {
+            return None;
+        }
+        buf.copy_within(read..read + len, write);
+        write += len;
+        read += len;
+        if code != FULL_BLOCK && read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    Some(write)
+}
+
+/// Payload of a decoded frame whose trailing CRC matches
+fn check_crc(frame: &[u8]) -> Result<&[u8], FrameError> {
+    let Some(split) = frame.len().checked_sub(CRC_LEN) else {
+        return Err(FrameError::Crc);
+    };
+    let (payload, crc) = frame.split_at(split);
+    if crc16(payload).to_be_bytes() != crc {
+        return Err(FrameError::Crc);
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    /// Frame `payload` into `wire`; returns the encoded length.
+    fn encode(payload: &[u8], wire: &mut [u8]) -> usize {
+        let capacity = wire.len();
+        let mut out = FramedWrite::new(&mut *wire);
+        block_on(out.write_frame(payload)).unwrap();
+        capacity - out.into_inner().len()
+    }
+
+    #[test]
+    fn round_trips_zeros_and_long_payloads() {
+        let mut long = [0u8; 600];
+        for (i, byte) in long.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+        let payloads: [&[u8]; 5] = [b"", b"\x00", b"hello\x00world\x00", &[0xAB; 254], &long];
+        for payload in payloads {
+            let mut wire = [0u8; 700];
+            let len = encode(payload, &mut wire);
+            assert!(len <= framed_len(payload.len()));
+            assert!(!wire[..len - 1].contains(&0));
+            assert_eq!(wire[len - 1], 0);
+            let mut frames = FramedRead::<_, 700>::new(&wire[..len]);
+            assert_eq!(block_on(frames.read_frame()), Ok(payload));
+        }
+    }
+
+    #[test]
+    fn resyncs_after_bad_frames() {
+        let mut wire = [0u8; 64];
+        let first = encode(b"one", &mut wire);
+        wire[1] ^= 0x01;
+        let second = first + encode(&[b'x'; 20], &mut wire[first..]);
+        let third = second + encode(b"three", &mut wire[second..]);
+        let mut frames = FramedRead::<_, 16>::new(&wire[..third]);
+        assert_eq!(block_on(frames.read_frame()), Err(FrameError::Crc));
+        assert_eq!(block_on(frames.read_frame()), Err(FrameError::TooLong));
+        assert_eq!(block_on(frames.read_frame()), Ok(&b"three"[..]));
+        assert_eq!(block_on(frames.read_frame()), Err(FrameError::EndOfStream));
+    }
+}
@@ -0,0 +1,223 @@
+//! heartbeat.rs — periodic presence messages with device metadata
+//!
+//! A [`Heartbeat`] publishes a small JSON status to `device/<id>/heartbeat` every interval
+//! while the WiFi link holds an address, through any [`TelemetrySink`] (an MQTT client or
+//! an HTTP poster), so a fleet can be watched without per-project code:
+//!
+//! ```text
+//! {"uptime_s":3600,"version":"0.1.0","ip":"192.168.1.20","rssi":-61,"free_ram":81234,"interval_s":60}
+//! ```
+//!
+//! `ip` and `rssi` are `null` while unknown; the RSSI comes from whoever talks to the radio,
+//! through [`Heartbeat::report_rssi`]. Presence follows the MQTT last-will pattern: register
+//! [`HEARTBEAT_OFFLINE`] on [`Heartbeat::status_topic`] as the will when the client
+//! connects, and the heartbeat sends [`HEARTBEAT_ONLINE`] there whenever the link comes
+//! up. The sink should publish the status topic retained. Over HTTP, where there is no
+//! will, a monitor can call a device offline after a few missed `interval_s`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static HEARTBEAT: StaticCell<Heartbeat> = StaticCell::new();
+//!
+//! let id = DeviceId::read(&mut flash)?.to_hex();
+//! let heartbeat = HEARTBEAT.init(Heartbeat::new(id.as_str(), build_info!(), Duration::from_secs(60))?);
+//! mqtt.set_will(heartbeat.status_topic().as_str(), HEARTBEAT_OFFLINE.as_bytes(), true);
+//!
+//! // Roaming task
+//! heartbeat.report_rssi(bss.rssi);
+//!
+//! // Uplink task
+//! heartbeat.run(&mut mqtt, &mut wifi.ip_watcher().unwrap()).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_futures::select::{Either, select};
+use embassy_net::Ipv4Cidr;
+use embassy_time::{Duration, Instant, Timer};
+use portable_atomic::{AtomicI16, Ordering};
+
+use crate::{BuildInfo, HeaplessString, IpWatcher, TelemetrySink, free_ram};
+
+pub const HEARTBEAT_MAX_TOPIC_LEN: usize = 64;
+pub const HEARTBEAT_MAX_PAYLOAD_LEN: usize = 192;
+/// Status payload while the device is up
+pub const HEARTBEAT_ONLINE: &str = "online";
+/// Status payload of the last will
+pub const HEARTBEAT_OFFLINE: &str = "offline";
+
+/// Stored RSSI meaning "not reported yet"
+const RSSI_UNKNOWN: i16 = i16::MIN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum HeartbeatError {
+    #[error("Topic longer than HEARTBEAT_MAX_TOPIC_LEN")]
+    TopicTooLong,
+}
+
+pub struct Heartbeat {
+    /// `device/<id>/`
+    prefix: HeaplessString<HEARTBEAT_MAX_TOPIC_LEN>,
+    build: BuildInfo,
+    interval: Duration,
+    rssi: AtomicI16,
+}
+
+impl Heartbeat {
+    /// Heartbeat for `device_id` (e.g. [`DeviceId::to_hex`](crate::DeviceId::to_hex)),
+    /// reporting the version of `build`.
+    pub fn new(
+        device_id: &str,
+        build: BuildInfo,
+        interval: Duration,
+    ) -> Result<Self, HeartbeatError> {
+        let mut prefix = HeaplessString::new();
+        write!(prefix, "device/{device_id}/").map_err(|_| HeartbeatError::TopicTooLong)?;
+        // Both topics must fit too
+        if prefix.len() + "heartbeat".len() > HEARTBEAT_MAX_TOPIC_LEN {
+            return Err(HeartbeatError::TopicTooLong);
+        }
+        Ok(Self {
+            prefix,
+            build,
+            interval,
+            rssi: AtomicI16::new(RSSI_UNKNOWN),
+        })
+    }
+
+    /// `device/<id>/status`, for [`HEARTBEAT_ONLINE`] and the last will
+    pub fn status_topic(&self) -> HeaplessString<HEARTBEAT_MAX_TOPIC_LEN> {
+        self.topic("status")
+    }
+
+    /// `device/<id>/heartbeat`
+    pub fn heartbeat_topic(&self) -> HeaplessString<HEARTBEAT_MAX_TOPIC_LEN> {
+        self.topic("heartbeat")
+    }
+
+    /// Signal strength of the current network in dBm, included from the next heartbeat on.
+    pub fn report_rssi(&self, rssi: i16) {
+        self.rssi
+            .store(rssi.max(RSSI_UNKNOWN + 1), Ordering::Relaxed);
+    }
+
+    /// Heartbeat JSON for the given state; the rest comes from the heartbeat itself.
+    pub fn payload(
+        &self,
+        uptime: Duration,
+        address: Option<Ipv4Cidr>,
+        free_ram: usize,
+    ) -> HeaplessString<HEARTBEAT_MAX_PAYLOAD_LEN> {
+        let mut payload = HeaplessString::new();
+        let _ = write!(
+            payload,
+            r#"{{"uptime_s":{},"version":"{}","#,
+            uptime.as_secs(),
+            self.build.version
+        );
+        let _ = match address {
+            Some(address) => write!(payload, r#""ip":"{}","#, address.address()),
+            None => write!(payload, r#""ip":null,"#),
+        };
+        let _ = match self.rssi.load(Ordering::Relaxed) {
+            RSSI_UNKNOWN => write!(payload, r#""rssi":null,"#),
+            rssi => write!(payload, r#""rssi":{rssi},"#),
+        };
+        let _ = write!(
+            payload,
+            r#""free_ram":{},"interval_s":{}}}"#,
+            free_ram,
+            self.interval.as_secs()
+        );
+        payload
+    }
+
+    /// Publish the status when the link comes up and a heartbeat every interval while it
+    /// is up, forever. A failed send is not retried; the next heartbeat follows anyway.
+    pub async fn run<S: TelemetrySink>(&self, sink: &mut S, link: &mut IpWatcher) -> ! {
+        let mut address = None;
+        loop {
+            if address.is_none() {
+                address = link.changed().await;
+                if address.is_some() {
+                    let _ = sink
+                        .send(self.status_topic().as_str(), HEARTBEAT_ONLINE.as_bytes())
+                        .await;
+                }
+                continue;
+            }
+            let payload = self.payload(
+                Duration::from_secs(Instant::now().as_secs()),
+                address,
+                free_ram(),
+            );
+            let _ = sink
+                .send(self.heartbeat_topic().as_str(), payload.as_str().as_bytes())
+                .await;
+            if let Either::Second(changed) =
+                select(Timer::after(self.interval), link.changed()).await
+            {
+                // A new address means a reconnect, so announce it again
+                if changed.is_some() && changed != address {
+                    let _ = sink
+                        .send(self.status_topic().as_str(), HEARTBEAT_ONLINE.as_bytes())
+                        .await;
+                }
+                address = changed;
+            }
+        }
+    }
+
+    fn topic(&self, name: &str) -> HeaplessString<HEARTBEAT_MAX_TOPIC_LEN> {
+        let mut topic = self.prefix.clone();
+        let _ = topic.push_str(name);
+        topic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_net::Ipv4Address;
+
+    use super::*;
+    use crate::DARKPICOLIB_BUILD_INFO;
+
+    #[test]
+    fn topics_and_payload() {
+        let heartbeat = Heartbeat::new(
+            "e66138",
+            BuildInfo {
+                version: "1.2.3",
+                ..DARKPICOLIB_BUILD_INFO
+            },
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(heartbeat.status_topic().as_str(), "device/e66138/status");
+        assert_eq!(
+            heartbeat.heartbeat_topic().as_str(),
+            "device/e66138/heartbeat"
+        );
+        assert_eq!(
+            heartbeat
+                .payload(Duration::from_secs(90), None, 1024)
+                .as_str(),
+            r#"{"uptime_s":90,"version":"1.2.3","ip":null,"rssi":null,"free_ram":1024,"interval_s":60}"#
+        );
+        heartbeat.report_rssi(-61);
+        let address = Ipv4Cidr::new(Ipv4Address::new(192, 168, 1, 20), 24);
+        assert_eq!(
+            heartbeat
+                .payload(Duration::from_secs(90), Some(address), 1024)
+                .as_str(),
+            r#"{"uptime_s":90,"version":"1.2.3","ip":"192.168.1.20","rssi":-61,"free_ram":1024,"interval_s":60}"#
+        );
+    }
+
+    #[test]
+    fn rejects_long_ids() {
+        let id = core::str::from_utf8(&[b'x'; 60]).unwrap();
+        assert!(Heartbeat::new(id, DARKPICOLIB_BUILD_INFO, Duration::from_secs(1)).is_err());
+    }
+}
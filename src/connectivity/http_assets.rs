@@ -0,0 +1,205 @@
+//! http_assets.rs — web UI files embedded in the firmware and served by the HTTP server
+//!
+//! [`include_assets!`](crate::include_assets) builds an [`Assets`] table from files next to
+//! the calling source file. Gzip the files at build time (`gzip -9k index.html`) and list
+//! the `.gz` names: they are served with `Content-Encoding: gzip`, and the content type comes
+//! from the extension in front of `.gz`. Compressed, a typical single-page UI takes a few
+//! KiB of flash.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static UI: Assets = include_assets! {
+//!     "/" => "../web/index.html.gz",
+//!     "/app.js" => "../web/app.js.gz",
+//!     "/style.css" => "../web/style.css.gz",
+//!     "/favicon.ico" => "../web/favicon.ico",
+//! };
+//!
+//! // In a handler, after the API routes:
+//! if let Some(response) = UI.serve(request) {
+//!     return response;
+//! }
+//! ```
+
+use crate::{Method, Request, Response, Status};
+
+/// Builds an [`Assets`] table from `"/url/path" => "file"` pairs. File paths are relative to
+/// the invoking source file; a `.gz` suffix marks pre-compressed data.
+#[macro_export]
+macro_rules! include_assets {
+    ($($path:literal => $file:literal),* $(,)?) => {
+        $crate::Assets::new(&[
+            $($crate::Asset::new($path, $file, include_bytes!($file))),*
+        ])
+    };
+}
+
+/// One embedded file
+#[derive(Debug, Clone, Copy)]
+pub struct Asset {
+    path: &'static str,
+    content_type: &'static str,
+    gzip: bool,
+    data: &'static [u8],
+}
+
+impl Asset {
+    /// `file` is only used for its extension (`.gz` marks gzipped data).
+    pub const fn new(path: &'static str, file: &'static str, data: &'static [u8]) -> Self {
+        let name = file.as_bytes();
+        let gzip = ends_with(name, b".gz");
+        let name = if gzip {
+            name.split_at(name.len() - 3).0
+        } else {
+            name
+        };
+        Self {
+            path,
+            content_type: content_type(name),
+            gzip,
+            data,
+        }
+    }
+
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+
+    pub fn is_gzip(&self) -> bool {
+        self.gzip
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+
+    pub fn response(&self) -> Response<'static> {
+        let response = Response::new(Status::OK, self.content_type, self.data)
+            .with_header("Cache-Control", "no-cache");
+        if self.gzip {
+            response.with_header("Content-Encoding", "gzip")
+        } else {
+            response
+        }
+    }
+}
+
+/// Table of embedded files, looked up by URL path
+#[derive(Debug, Clone, Copy)]
+pub struct Assets {
+    assets: &'static [Asset],
+}
+
+impl Assets {
+    pub const fn new(assets: &'static [Asset]) -> Self {
+        Self { assets }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&'static Asset> {
+        self.assets.iter().find(|asset| asset.path == path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static Asset> {
+        self.assets.iter()
+    }
+
+    /// The asset for a `GET`/`HEAD` of its path, `None` for anything else.
+    pub fn serve(&self, request: &Request<'_>) -> Option<Response<'static>> {
+        if !matches!(request.method, Method::Get | Method::Head) {
+            return None;
+        }
+        self.get(request.path).map(Asset::response)
+    }
+}
+
+const fn ends_with(name: &[u8], suffix: &[u8]) -> bool {
+    if name.len() < suffix.len() {
+        return false;
+    }
+    let offset = name.len() - suffix.len();
+    let mut i = 0;
+    while i < suffix.len() {
+        if name[offset + i].to_ascii_lowercase() != suffix[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const CONTENT_TYPES: &[(&[u8], &str)] = &[
+    (b".html", "text/html; charset=utf-8"),
+    (b".htm", "text/html; charset=utf-8"),
+    (b".css", "text/css; charset=utf-8"),
+    (b".js", "text/javascript; charset=utf-8"),
+    (b".mjs", "text/javascript; charset=utf-8"),
+    (b".json", "application/json"),
+    (b".svg", "image/svg+xml"),
+    (b".png", "image/png"),
+    (b".jpg", "image/jpeg"),
+    (b".jpeg", "image/jpeg"),
+    (b".ico", "image/x-icon"),
+    (b".txt", "text/plain; charset=utf-8"),
+    (b".wasm", "application/wasm"),
+    (b".woff2", "font/woff2"),
+];
+
+const fn content_type(name: &[u8]) -> &'static str {
+    let mut i = 0;
+    while i < CONTENT_TYPES.len() {
+        if ends_with(name, CONTENT_TYPES[i].0) {
+            return CONTENT_TYPES[i].1;
+        }
+        i += 1;
+    }
+    "application/octet-stream"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ASSETS: Assets = Assets::new(&[
+        Asset::new("/", "web/index.HTML.gz", &[0x1f, 0x8b]),
+        Asset::new("/favicon.ico", "web/favicon.ico", &[0, 0, 1, 0]),
+        Asset::new("/data.bin", "web/data.bin", &[]),
+    ]);
+
+    #[test]
+    fn detects_type_and_encoding() {
+        let index = ASSETS.get("/").unwrap();
+        assert!(index.is_gzip());
+        assert_eq!(index.content_type(), "text/html; charset=utf-8");
+        let icon = ASSETS.get("/favicon.ico").unwrap();
+        assert!(!icon.is_gzip());
+        assert_eq!(icon.content_type(), "image/x-icon");
+        let data = ASSETS.get("/data.bin").unwrap();
+        assert_eq!(data.content_type(), "application/octet-stream");
+    }
+
+    #[test]
+    fn serves_get_requests() {
+        let get = Request::parse(b"GET /?x=1 HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        let response = ASSETS.serve(&get).unwrap();
+        assert_eq!(response.body, &[0x1f, 0x8b]);
+        assert!(
+            response
+                .headers()
+                .any(|h| h == ("Content-Encoding", "gzip"))
+        );
+
+        let post = Request::parse(b"POST / HTTP/1.1\r\n\r\n").unwrap().unwrap();
+        assert!(ASSETS.serve(&post).is_none());
+        let missing = Request::parse(b"GET /nope HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert!(ASSETS.serve(&missing).is_none());
+    }
+}
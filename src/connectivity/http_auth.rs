@@ -0,0 +1,271 @@
+//! http_auth.rs — HTTP Basic and Bearer authentication for the embedded server
+//!
+//! An [`Authenticator`] decides whether a request carries valid credentials. Wrap a handler
+//! in [`Protected`] to require them on some path prefixes (or all paths), or call
+//! [`require`] inside a handler for individual routes. Rejected requests get
+//! `401 Unauthorized` with the matching `WWW-Authenticate` challenge. Secrets are compared
+//! in constant time.
+//!
+//! Basic auth sends the password in every request, readable by anyone on the LAN unless
+//! the network itself is trusted; prefer tokens derived per device (e.g.
+//! [`DeviceId::derive_token`](crate::DeviceId::derive_token)).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let token = id.derive_token(device_secret!(), "http");
+//! let mut handler = Protected::new(BearerAuth::new(token.as_str()), Api, &["/api/"]);
+//! HttpServer::new(wifi.stack, HttpServerConfig::default()).run(&mut handler).await;
+//!
+//! // Or per route inside a handler:
+//! if let Err(denied) = require(&self.admin, request) {
+//!     return denied;
+//! }
+//! ```
+
+use core::fmt::Write;
+
+use crate::{HeaplessString, HttpHandler, Request, Response, Status, constant_time_eq};
+
+/// Longest `user:password` accepted in a Basic `Authorization` header
+const BASIC_MAX_CREDENTIALS_LEN: usize = 96;
+
+/// Checks the credentials of a request
+pub trait Authenticator {
+    fn authenticate(&self, request: &Request<'_>) -> bool;
+
+    /// `WWW-Authenticate` value sent with a 401
+    fn challenge(&self) -> &str;
+}
+
+/// Fixed user name and password (RFC 7617)
+pub struct BasicAuth<'c> {
+    user: &'c str,
+    password: &'c str,
+    challenge: HeaplessString<64>,
+}
+
+impl<'c> BasicAuth<'c> {
+    /// `realm` is shown by browsers in the login prompt.
+    pub fn new(user: &'c str, password: &'c str, realm: &str) -> Self {
+        let mut challenge = HeaplessString::new();
+        let _ = write!(challenge, "Basic realm=\"{}\"", realm);
+        Self {
+            user,
+            password,
+            challenge,
+        }
+    }
+}
+
+impl Authenticator for BasicAuth<'_> {
+    fn authenticate(&self, request: &Request<'_>) -> bool {
+        let Some(encoded) = authorization(request, "Basic") else {
+            return false;
+        };
+        let mut decoded = [0u8; BASIC_MAX_CREDENTIALS_LEN];
+        let Some(len) = base64_decode(encoded, &mut decoded) else {
+            return false;
+        };
+        let Some(colon) = decoded[..len].iter().position(|&b| b == b':') else {
+            return false;
+        };
+        let user_ok = constant_time_eq(&decoded[..colon], self.user.as_bytes());
+        let password_ok = constant_time_eq(&decoded[colon + 1..len], self.password.as_bytes());
+        user_ok & password_ok
+    }
+
+    fn challenge(&self) -> &str {
+        self.challenge.as_str()
+    }
+}
+
+/// One fixed bearer token (RFC 6750)
+pub struct BearerAuth<'t> {
+    token: &'t str,
+}
+
+impl<'t> BearerAuth<'t> {
+    pub fn new(token: &'t str) -> Self {
+        Self { token }
+    }
+}
+
+impl Authenticator for BearerAuth<'_> {
+    fn authenticate(&self, request: &Request<'_>) -> bool {
+        authorization(request, "Bearer")
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()))
+    }
+
+    fn challenge(&self) -> &str {
+        "Bearer"
+    }
+}
+
+/// Bearer tokens checked by a callback, e.g. against a list of issued tokens. The callback
+/// should compare with [`constant_time_eq`].
+pub struct BearerValidator<F: Fn(&str) -> bool> {
+    validate: F,
+}
+
+impl<F: Fn(&str) -> bool> BearerValidator<F> {
+    pub fn new(validate: F) -> Self {
+        Self { validate }
+    }
+}
+
+impl<F: Fn(&str) -> bool> Authenticator for BearerValidator<F> {
+    fn authenticate(&self, request: &Request<'_>) -> bool {
+        authorization(request, "Bearer").is_some_and(|token| (self.validate)(token))
+    }
+
+    fn challenge(&self) -> &str {
+        "Bearer"
+    }
+}
+
+/// `Ok` if `request` is authenticated, otherwise the 401 response to return.
+// The response goes straight back to the server; there is no allocator to box it.
+#[allow(clippy::result_large_err)]
+pub fn require<'a, A: Authenticator>(
+    auth: &'a A,
+    request: &Request<'_>,
+) -> Result<(), Response<'a>> {
+    if auth.authenticate(request) {
+        Ok(())
+    } else {
+        Err(Response::text(Status::UNAUTHORIZED, "Unauthorized")
+            .with_header("WWW-Authenticate", auth.challenge()))
+    }
+}
+
+/// Handler that requires authentication for paths starting with one of `prefixes` (all
+/// paths if empty) before passing requests on.
+pub struct Protected<'p, A: Authenticator, H: HttpHandler> {
+    auth: A,
+    handler: H,
+    prefixes: &'p [&'p str],
+}
+
+impl<'p, A: Authenticator, H: HttpHandler> Protected<'p, A, H> {
+    pub fn new(auth: A, handler: H, prefixes: &'p [&'p str]) -> Self {
+        Self {
+            auth,
+            handler,
+            prefixes,
+        }
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    fn is_protected(&self, path: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| path.starts_with(p))
+    }
+}
+
+impl<A: Authenticator, H: HttpHandler> HttpHandler for Protected<'_, A, H> {
+    async fn handle<'a>(
+        &'a mut self,
+        request: &Request<'_>,
+        scratch: &'a mut [u8],
+    ) -> Response<'a> {
+        if self.is_protected(request.path)
+            && let Err(denied) = require(&self.auth, request)
+        {
+            return denied;
+        }
+        self.handler.handle(request, scratch).await
+    }
+}
+
+/// Credentials of `scheme` from the `Authorization` header
+fn authorization<'a>(request: &Request<'a>, scheme: &str) -> Option<&'a str> {
+    let (given, credentials) = request.header("Authorization")?.split_once(' ')?;
+    given
+        .eq_ignore_ascii_case(scheme)
+        .then(|| credentials.trim())
+}
+
+/// Standard base64 (with or without padding) into `out`; `None` if invalid or too long
+fn base64_decode(input: &str, out: &mut [u8]) -> Option<usize> {
+    let input = input.trim_end_matches('=').as_bytes();
+    let mut len = 0;
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &c in input {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            *out.get_mut(len)? = (bits >> bit_count) as u8;
+            len += 1;
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &[u8]) -> Request<'_> {
+        Request::parse(raw).unwrap().unwrap()
+    }
+
+    #[test]
+    fn decodes_base64() {
+        let mut out = [0u8; 16];
+        let len = base64_decode("dXNlcjpwYXNz", &mut out).unwrap();
+        assert_eq!(&out[..len], b"user:pass");
+        let len = base64_decode("YQ==", &mut out).unwrap();
+        assert_eq!(&out[..len], b"a");
+        assert!(base64_decode("a*b", &mut out).is_none());
+        assert!(base64_decode("dXNlcjpwYXNzdXNlcjpwYXNz", &mut out).is_none());
+    }
+
+    #[test]
+    fn basic_auth() {
+        let auth = BasicAuth::new("user", "pass", "pico");
+        assert_eq!(auth.challenge(), "Basic realm=\"pico\"");
+        assert!(auth.authenticate(&request(
+            b"GET / HTTP/1.1\r\nAuthorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        )));
+        // user:pasS
+        assert!(!auth.authenticate(&request(
+            b"GET / HTTP/1.1\r\nAuthorization: Basic dXNlcjpwYXNT\r\n\r\n"
+        )));
+        assert!(!auth.authenticate(&request(b"GET / HTTP/1.1\r\n\r\n")));
+    }
+
+    #[test]
+    fn bearer_auth() {
+        let auth = BearerAuth::new("s3cret");
+        assert!(auth.authenticate(&request(
+            b"GET / HTTP/1.1\r\nauthorization: bearer s3cret\r\n\r\n"
+        )));
+        assert!(!auth.authenticate(&request(
+            b"GET / HTTP/1.1\r\nAuthorization: Bearer s3cre\r\n\r\n"
+        )));
+
+        let validator = BearerValidator::new(|token| token.starts_with("ok-"));
+        assert!(validator.authenticate(&request(
+            b"GET / HTTP/1.1\r\nAuthorization: Bearer ok-1\r\n\r\n"
+        )));
+        let denied = require(&validator, &request(b"GET / HTTP/1.1\r\n\r\n")).unwrap_err();
+        assert_eq!(denied.status, Status::UNAUTHORIZED);
+        assert_eq!(
+            denied.headers().next(),
+            Some(("WWW-Authenticate", "Bearer"))
+        );
+    }
+}
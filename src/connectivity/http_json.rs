@@ -0,0 +1,124 @@
+//! http_json.rs — JSON request bodies and responses for REST handlers
+//!
+//! [`Request::json`] checks the `Content-Type` and deserializes the body, returning a ready
+//! `415 Unsupported Media Type` or `400 Bad Request` response on failure, so a handler can
+//! bail out with `?`-like brevity. [`Response::json`] serializes a value into the handler's
+//! scratch buffer. Both use `serde-json-core`: no allocation, and `&str` fields borrow
+//! straight from the request body (as long as they contain no escapes).
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct SetLed { on: bool, brightness: u8 }
+//!
+//! #[derive(Serialize)]
+//! struct LedState { on: bool, brightness: u8 }
+//!
+//! async fn handle<'a>(&'a mut self, request: &Request<'_>, scratch: &'a mut [u8]) -> Response<'a> {
+//!     match (request.method, request.path) {
+//!         (Method::Put, "/api/led") => {
+//!             let cmd: SetLed = match request.json() {
+//!                 Ok(cmd) => cmd,
+//!                 Err(rejection) => return rejection,
+//!             };
+//!             self.led.set(cmd.on, cmd.brightness);
+//!             Response::json(Status::OK, &self.led.state(), scratch)
+//!         }
+//!         _ => Response::empty(Status::NOT_FOUND),
+//!     }
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Request, Response, Status};
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+impl<'a> Request<'a> {
+    /// Deserialize the body as JSON, or the 415/400 response to send back.
+    #[allow(clippy::result_large_err)]
+    pub fn json<T: Deserialize<'a>>(&self) -> Result<T, Response<'static>> {
+        if !self.header("Content-Type").is_some_and(is_json) {
+            return Err(json_error(
+                Status::UNSUPPORTED_MEDIA_TYPE,
+                r#"{"error":"expected application/json"}"#,
+            ));
+        }
+        serde_json_core::from_slice(self.body)
+            .map(|(value, _)| value)
+            .map_err(|_| json_error(Status::BAD_REQUEST, r#"{"error":"invalid JSON"}"#))
+    }
+}
+
+impl<'a> Response<'a> {
+    /// `value` serialized into `scratch`; a 500 if it doesn't fit.
+    pub fn json<T: Serialize + ?Sized>(status: Status, value: &T, scratch: &'a mut [u8]) -> Self {
+        match serde_json_core::to_slice(value, scratch) {
+            Ok(len) => Self::new(status, JSON_CONTENT_TYPE, &scratch[..len]),
+            Err(_) => json_error(
+                Status::INTERNAL_SERVER_ERROR,
+                r#"{"error":"response too large"}"#,
+            ),
+        }
+    }
+}
+
+fn json_error(status: Status, body: &'static str) -> Response<'static> {
+    Response::new(status, JSON_CONTENT_TYPE, body.as_bytes())
+}
+
+/// `application/json` or a `+json` type, ignoring parameters such as `charset`
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    media_type.eq_ignore_ascii_case(JSON_CONTENT_TYPE)
+        || media_type
+            .get(media_type.len().saturating_sub(5)..)
+            .is_some_and(|suffix| suffix.eq_ignore_ascii_case("+json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &[u8]) -> Request<'_> {
+        Request::parse(raw).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parses_json_bodies() {
+        let req = request(
+            b"PUT /api HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 10\r\n\r\n[\"led\", 3]",
+        );
+        assert_eq!(req.json::<(&str, u8)>().ok(), Some(("led", 3)));
+        assert!(is_json("application/merge-patch+JSON"));
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let form = request(
+            b"POST /api HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\n[]",
+        );
+        let rejection = form.json::<(u8,)>().err().unwrap();
+        assert_eq!(rejection.status, Status::UNSUPPORTED_MEDIA_TYPE);
+
+        let broken = request(
+            b"POST /api HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n[x",
+        );
+        let rejection = broken.json::<(u8,)>().err().unwrap();
+        assert_eq!(rejection.status, Status::BAD_REQUEST);
+        assert_eq!(rejection.content_type, JSON_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn serializes_responses() {
+        let mut scratch = [0u8; 32];
+        let response = Response::json(Status::OK, &(true, "on"), &mut scratch);
+        assert_eq!(response.body, br#"[true,"on"]"#);
+
+        let mut tiny = [0u8; 4];
+        let response = Response::json(Status::OK, &(true, "on"), &mut tiny);
+        assert_eq!(response.status, Status::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,163 @@
+//! http_logs.rs — download data logger files over HTTP
+//!
+//! `GET <prefix>/<NAME>?offset=<n>` answers with up to one scratch buffer of the file
+//! starting at byte `n` (default 0) as `text/csv`. Files are larger than the server's
+//! buffer, so clients fetch with increasing offsets until the body comes back empty.
+//! Files that don't exist (or are empty) answer `404 Not Found`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! impl HttpHandler for Api {
+//!     async fn handle<'a>(&'a mut self, request: &Request<'_>, scratch: &'a mut [u8]) -> Response<'a> {
+//!         if let Some(response) = serve_log_file(&mut self.logs, "/logs", request, scratch) {
+//!             return response;
+//!         }
+//!         Response::empty(Status::NOT_FOUND)
+//!     }
+//! }
+//! ```
+
+use crate::{LogStorage, Method, Request, Response, Status};
+
+/// Longest accepted file name (8.3)
+const MAX_NAME_LEN: usize = 12;
+
+/// Serve a chunk of a log file for requests under `prefix`; `None` for other paths.
+/// Names are limited to 8.3 characters so a request can't leave the log directory.
+pub fn serve_log_file<'a, S: LogStorage>(
+    storage: &mut S,
+    prefix: &str,
+    request: &Request<'_>,
+    scratch: &'a mut [u8],
+) -> Option<Response<'a>> {
+    let name = request.path.strip_prefix(prefix)?.strip_prefix('/')?;
+    if !matches!(request.method, Method::Get | Method::Head) {
+        return Some(Response::empty(Status::METHOD_NOT_ALLOWED));
+    }
+    if !is_log_name(name) {
+        return Some(Response::empty(Status::NOT_FOUND));
+    }
+    let Some(offset) = query_offset(request.query) else {
+        return Some(Response::empty(Status::BAD_REQUEST));
+    };
+    let read = match storage.file_len(name) {
+        Ok(0) => return Some(Response::empty(Status::NOT_FOUND)),
+        Ok(_) => storage.read(name, offset, scratch),
+        Err(e) => Err(e),
+    };
+    Some(match read {
+        Ok(len) => Response::new(Status::OK, "text/csv", &scratch[..len])
+            .with_header("Cache-Control", "no-store"),
+        Err(_) => Response::empty(Status::SERVICE_UNAVAILABLE),
+    })
+}
+
+fn is_log_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_')
+}
+
+/// `offset=<n>` from the query string; 0 if absent, `None` if malformed.
+fn query_offset(query: Option<&str>) -> Option<usize> {
+    let Some(query) = query else {
+        return Some(0);
+    };
+    match query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("offset="))
+    {
+        Some(value) => value.parse().ok(),
+        None => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogError;
+
+    /// One file, `26101600.CSV`
+    struct OneFile(&'static [u8]);
+
+    impl LogStorage for OneFile {
+        fn append(&mut self, _name: &str, _data: &[u8]) -> Result<(), DataLogError> {
+            Err(DataLogError::Storage)
+        }
+
+        fn file_len(&mut self, name: &str) -> Result<usize, DataLogError> {
+            Ok(if name == "26101600.CSV" {
+                self.0.len()
+            } else {
+                0
+            })
+        }
+
+        fn read(
+            &mut self,
+            name: &str,
+            offset: usize,
+            buf: &mut [u8],
+        ) -> Result<usize, DataLogError> {
+            if name != "26101600.CSV" {
+                return Ok(0);
+            }
+            let data = self.0.get(offset..).unwrap_or(&[]);
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn serves_chunks_and_404s_missing_files() {
+        let mut storage = OneFile(b"time,temp [dC]\n");
+        let mut scratch = [0u8; 8];
+
+        let first = Request::parse(b"GET /logs/26101600.CSV HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        let response = serve_log_file(&mut storage, "/logs", &first, &mut scratch).unwrap();
+        assert_eq!(response.status, Status::OK);
+        assert_eq!(response.body, b"time,tem");
+
+        let last = Request::parse(b"GET /logs/26101600.CSV?offset=16 HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        let response = serve_log_file(&mut storage, "/logs", &last, &mut scratch).unwrap();
+        assert_eq!(response.status, Status::OK);
+        assert!(response.body.is_empty());
+
+        let missing = Request::parse(b"GET /logs/26101700.CSV HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        let response = serve_log_file(&mut storage, "/logs", &missing, &mut scratch).unwrap();
+        assert_eq!(response.status, Status::NOT_FOUND);
+
+        let other = Request::parse(b"GET /api HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert!(serve_log_file(&mut storage, "/logs", &other, &mut scratch).is_none());
+    }
+
+    #[test]
+    fn log_names() {
+        assert!(is_log_name("26101600.CSV"));
+        assert!(!is_log_name("../config.txt"));
+        assert!(!is_log_name(".hidden"));
+        assert!(!is_log_name("a/b"));
+        assert!(!is_log_name("VERYLONGNAME.CSV"));
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(query_offset(None), Some(0));
+        assert_eq!(query_offset(Some("offset=512")), Some(512));
+        assert_eq!(query_offset(Some("x=1&offset=3")), Some(3));
+        assert_eq!(query_offset(Some("offset=-1")), None);
+    }
+}
@@ -0,0 +1,470 @@
+//! http_server.rs — minimal HTTP/1.1 server for device web UIs and REST endpoints
+//!
+//! One request per connection (`Connection: close`), with the request head and body read
+//! into a fixed buffer of [`HTTP_BUFFER_LEN`] bytes. An [`HttpHandler`] gets the parsed
+//! [`Request`] and a scratch buffer to build its [`Response`] body in. [`HttpServer::run`]
+//! serves one connection at a time; run it from several tasks to serve clients in parallel
+//! (each task uses one of the stack's sockets). Clients that go quiet for
+//! [`HttpServerConfig::idle_timeout`] are dropped, and a shared [`NetLimiter`] can cap
+//! connections per client.
+//!
+//! # Example
+//!
+//! ```ignore
+//! struct Api;
+//!
+//! impl HttpHandler for Api {
+//!     async fn handle<'a>(&'a mut self, request: &Request<'_>, scratch: &'a mut [u8]) -> Response<'a> {
+//!         match (request.method, request.path) {
+//!             (Method::Get, "/") => Response::text(Status::OK, "hello"),
+//!             _ => Response::empty(Status::NOT_FOUND),
+//!         }
+//!     }
+//! }
+//!
+//! HttpServer::new(wifi.stack, HttpServerConfig::default()).run(&mut Api).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_net::Stack;
+use embassy_net::tcp::TcpSocket;
+use embassy_time::{Duration, with_timeout};
+
+use crate::{HeaplessVec, LogModule, NetLimiter};
+
+/// Largest request (head and body) and response body scratch space in bytes
+pub const HTTP_BUFFER_LEN: usize = 1024;
+/// Most request headers kept; further ones are ignored
+pub const HTTP_MAX_HEADERS: usize = 16;
+/// Most extra headers on a response
+pub const HTTP_MAX_RESPONSE_HEADERS: usize = 4;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum HttpError {
+    #[error("Malformed request")]
+    BadRequest,
+    #[error("Request too large")]
+    TooLarge,
+    #[error("Connection error")]
+    Connection,
+    #[error("Client idle for too long")]
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl Method {
+    fn parse(method: &str) -> Option<Self> {
+        Some(match method {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "PATCH" => Self::Patch,
+            "DELETE" => Self::Delete,
+            "OPTIONS" => Self::Options,
+            _ => return None,
+        })
+    }
+}
+
+/// Response status code and reason phrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Status {
+    pub code: u16,
+    pub reason: &'static str,
+}
+
+impl Status {
+    pub const OK: Self = Self::new(200, "OK");
+    pub const CREATED: Self = Self::new(201, "Created");
+    pub const NO_CONTENT: Self = Self::new(204, "No Content");
+    pub const BAD_REQUEST: Self = Self::new(400, "Bad Request");
+    pub const UNAUTHORIZED: Self = Self::new(401, "Unauthorized");
+    pub const FORBIDDEN: Self = Self::new(403, "Forbidden");
+    pub const NOT_FOUND: Self = Self::new(404, "Not Found");
+    pub const METHOD_NOT_ALLOWED: Self = Self::new(405, "Method Not Allowed");
+    pub const PAYLOAD_TOO_LARGE: Self = Self::new(413, "Payload Too Large");
+    pub const UNSUPPORTED_MEDIA_TYPE: Self = Self::new(415, "Unsupported Media Type");
+    pub const TOO_MANY_REQUESTS: Self = Self::new(429, "Too Many Requests");
+    pub const INTERNAL_SERVER_ERROR: Self = Self::new(500, "Internal Server Error");
+    pub const SERVICE_UNAVAILABLE: Self = Self::new(503, "Service Unavailable");
+
+    pub const fn new(code: u16, reason: &'static str) -> Self {
+        Self { code, reason }
+    }
+}
+
+/// A parsed request; all parts borrow from the receive buffer
+pub struct Request<'a> {
+    pub method: Method,
+    /// Path without the query string, e.g. `/api/led`
+    pub path: &'a str,
+    /// Query string without the `?`
+    pub query: Option<&'a str>,
+    pub body: &'a [u8],
+    headers: HeaplessVec<(&'a str, &'a str), HTTP_MAX_HEADERS>,
+}
+
+impl<'a> Request<'a> {
+    /// Parse a request from `buf`. `Ok(None)` means the head or body isn't complete yet.
+    pub fn parse(buf: &'a [u8]) -> Result<Option<Self>, HttpError> {
+        let Some(head_len) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return Ok(None);
+        };
+        let head = core::str::from_utf8(&buf[..head_len]).map_err(|_| HttpError::BadRequest)?;
+        let mut lines = head.split("\r\n");
+
+        let mut request_line = lines.next().unwrap_or("").split(' ');
+        let method = request_line
+            .next()
+            .and_then(Method::parse)
+            .ok_or(HttpError::BadRequest)?;
+        let target = request_line.next().ok_or(HttpError::BadRequest)?;
+        if !request_line
+            .next()
+            .is_some_and(|v| v.starts_with("HTTP/1."))
+        {
+            return Err(HttpError::BadRequest);
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+
+        let mut headers = HeaplessVec::new();
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(HttpError::BadRequest)?;
+            let _ = headers.push((name.trim(), value.trim()));
+        }
+
+        let mut request = Self {
+            method,
+            path,
+            query,
+            body: &[],
+            headers,
+        };
+        let body_start = head_len + 4;
+        let body_end = body_start
+            .checked_add(request.content_length()?)
+            .filter(|&end| end <= HTTP_BUFFER_LEN)
+            .ok_or(HttpError::TooLarge)?;
+        if buf.len() < body_end {
+            return Ok(None);
+        }
+        request.body = &buf[body_start..body_end];
+        Ok(Some(request))
+    }
+
+    /// Value of header `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.headers.iter().copied()
+    }
+
+    fn content_length(&self) -> Result<usize, HttpError> {
+        match self.header("Content-Length") {
+            Some(len) => len.parse().map_err(|_| HttpError::BadRequest),
+            None => Ok(0),
+        }
+    }
+}
+
+/// A response; the body and headers borrow from the handler or its scratch buffer
+pub struct Response<'a> {
+    pub status: Status,
+    pub content_type: &'a str,
+    pub body: &'a [u8],
+    headers: HeaplessVec<(&'a str, &'a str), HTTP_MAX_RESPONSE_HEADERS>,
+}
+
+impl<'a> Response<'a> {
+    pub fn new(status: Status, content_type: &'a str, body: &'a [u8]) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+            headers: HeaplessVec::new(),
+        }
+    }
+
+    pub fn text(status: Status, body: &'a str) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body.as_bytes())
+    }
+
+    pub fn empty(status: Status) -> Self {
+        Self::new(status, "text/plain", &[])
+    }
+
+    /// Add a header; beyond [`HTTP_MAX_RESPONSE_HEADERS`] it is dropped.
+    pub fn with_header(mut self, name: &'a str, value: &'a str) -> Self {
+        let _ = self.headers.push((name, value));
+        self
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.headers.iter().copied()
+    }
+
+    /// Status line and headers, including `Content-Length` and `Connection: close`
+    fn write_head<W: Write>(&self, out: &mut W) -> core::fmt::Result {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\n",
+            self.status.code, self.status.reason
+        )?;
+        write!(out, "Content-Type: {}\r\n", self.content_type)?;
+        for (name, value) in self.headers() {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(
+            out,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        )
+    }
+}
+
+/// Application side of the server
+#[allow(async_fn_in_trait)]
+pub trait HttpHandler {
+    /// Answer `request`. `scratch` ([`HTTP_BUFFER_LEN`] bytes) may hold the response body.
+    async fn handle<'a>(&'a mut self, request: &Request<'_>, scratch: &'a mut [u8])
+    -> Response<'a>;
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpServerConfig {
+    pub port: u16,
+    /// Longest wait for the client to send more data or accept what was sent
+    pub idle_timeout: Duration,
+}
+
+impl Default for HttpServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 80,
+            idle_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+pub struct HttpServer<'s> {
+    stack: Stack<'s>,
+    config: HttpServerConfig,
+    limiter: Option<&'s NetLimiter>,
+}
+
+impl<'s> HttpServer<'s> {
+    pub fn new(stack: Stack<'s>, config: HttpServerConfig) -> Self {
+        Self {
+            stack,
+            config,
+            limiter: None,
+        }
+    }
+
+    /// Check every connection against `limiter`; rejected clients get a 429.
+    pub fn with_limiter(mut self, limiter: &'s NetLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Accept and serve connections one after another, forever.
+    pub async fn run<H: HttpHandler>(&self, handler: &mut H) -> ! {
+        let mut rx_buffer = [0u8; HTTP_BUFFER_LEN];
+        let mut tx_buffer = [0u8; HTTP_BUFFER_LEN];
+        let mut request_buffer = [0u8; HTTP_BUFFER_LEN];
+        let mut scratch = [0u8; HTTP_BUFFER_LEN];
+        loop {
+            let mut socket = TcpSocket::new(self.stack, &mut rx_buffer, &mut tx_buffer);
+            socket.set_timeout(Some(self.config.idle_timeout));
+            if socket.accept(self.config.port).await.is_err() {
+                continue;
+            }
+            let result = match self.limiter.map(|l| l.admit_socket(&socket)).transpose() {
+                Ok(_permit) => {
+                    serve_connection(
+                        &mut socket,
+                        handler,
+                        &mut request_buffer,
+                        &mut scratch,
+                        self.config.idle_timeout,
+                    )
+                    .await
+                }
+                Err(e) => {
+                    crate::log!(LogModule::Http, Warn, "HTTP: {}", e);
+                    let response =
+                        Response::empty(Status::TOO_MANY_REQUESTS).with_header("Retry-After", "1");
+                    send_response(&mut socket, &response, false).await
+                }
+            };
+            if let Err(e) = result {
+                crate::log!(LogModule::Http, Warn, "HTTP: {}", e);
+            }
+            socket.close();
+            let _ = socket.flush().await;
+        }
+    }
+}
+
+async fn serve_connection<H: HttpHandler>(
+    socket: &mut TcpSocket<'_>,
+    handler: &mut H,
+    buf: &mut [u8],
+    scratch: &mut [u8],
+    idle_timeout: Duration,
+) -> Result<(), HttpError> {
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            send_response(socket, &Response::empty(Status::PAYLOAD_TOO_LARGE), false).await?;
+            return Err(HttpError::TooLarge);
+        }
+        let read = with_timeout(idle_timeout, socket.read(&mut buf[len..]))
+            .await
+            .map_err(|_| HttpError::Timeout)?
+            .map_err(|_| HttpError::Connection)?;
+        if read == 0 {
+            return Err(HttpError::Connection);
+        }
+        len += read;
+        match Request::parse(&buf[..len]) {
+            Ok(Some(_)) => break,
+            Ok(None) => {}
+            Err(e) => {
+                let status = match e {
+                    HttpError::TooLarge => Status::PAYLOAD_TOO_LARGE,
+                    _ => Status::BAD_REQUEST,
+                };
+                send_response(socket, &Response::empty(status), false).await?;
+                return Err(e);
+            }
+        }
+    }
+
+    let Ok(Some(request)) = Request::parse(&buf[..len]) else {
+        return Err(HttpError::BadRequest);
+    };
+    let response = handler.handle(&request, scratch).await;
+    send_response(socket, &response, request.method == Method::Head).await
+}
+
+async fn send_response(
+    socket: &mut TcpSocket<'_>,
+    response: &Response<'_>,
+    head_only: bool,
+) -> Result<(), HttpError> {
+    let mut head: HeaplessVec<u8, 512> = HeaplessVec::new();
+    response
+        .write_head(&mut ByteWriter(&mut head))
+        .map_err(|_| HttpError::TooLarge)?;
+    write_all(socket, &head).await?;
+    if !head_only {
+        write_all(socket, response.body).await?;
+    }
+    Ok(())
+}
+
+async fn write_all(socket: &mut TcpSocket<'_>, mut data: &[u8]) -> Result<(), HttpError> {
+    while !data.is_empty() {
+        let written = socket
+            .write(data)
+            .await
+            .map_err(|_| HttpError::Connection)?;
+        if written == 0 {
+            return Err(HttpError::Connection);
+        }
+        data = &data[written..];
+    }
+    Ok(())
+}
+
+/// `fmt::Write` into a byte vector (`HeaplessString` holds at most 255 bytes)
+struct ByteWriter<'b, const N: usize>(&'b mut HeaplessVec<u8, N>);
+
+impl<const N: usize> Write for ByteWriter<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0
+            .extend_from_slice(s.as_bytes())
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_request_with_body() {
+        let raw = b"POST /api/led?on=1 HTTP/1.1\r\nHost: pico\r\ncontent-length: 5\r\n\r\nhello";
+        let request = Request::parse(raw).unwrap().unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, "/api/led");
+        assert_eq!(request.query, Some("on=1"));
+        assert_eq!(request.header("Content-Length"), Some("5"));
+        assert_eq!(request.header("host"), Some("pico"));
+        assert_eq!(request.body, b"hello");
+
+        assert!(Request::parse(&raw[..raw.len() - 1]).unwrap().is_none());
+        assert!(
+            Request::parse(b"GET / HTTP/1.1\r\nHost: pico\r\n")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert!(Request::parse(b"BREW / HTTP/1.1\r\n\r\n").is_err());
+        assert!(Request::parse(b"GET /\r\n\r\n").is_err());
+        assert!(Request::parse(b"GET / HTTP/1.1\r\nbogus\r\n\r\n").is_err());
+        assert!(Request::parse(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_bodies_larger_than_the_buffer() {
+        assert!(matches!(
+            Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 4294967295\r\n\r\n"),
+            Err(HttpError::TooLarge)
+        ));
+        assert!(matches!(
+            Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n"),
+            Err(HttpError::TooLarge)
+        ));
+        assert!(matches!(
+            Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 1024\r\n\r\n"),
+            Err(HttpError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn writes_response_head() {
+        let response = Response::text(Status::OK, "hi").with_header("Cache-Control", "no-store");
+        let mut head: HeaplessVec<u8, 512> = HeaplessVec::new();
+        response.write_head(&mut ByteWriter(&mut head)).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&head).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Cache-Control: no-store\r\nContent-Length: 2\r\nConnection: close\r\n\r\n"
+        );
+    }
+}
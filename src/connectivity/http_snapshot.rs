@@ -0,0 +1,179 @@
+//! http_snapshot.rs — serve camera frames over HTTP as uncompressed BMP images
+//!
+//! Each GET captures a fresh frame from a [`FrameCapture`] source and sends it as a 16-bit
+//! BMP, which every browser shows without any encoder on the device. The frame is
+//! captured straight into the response buffer behind the BMP header, so the only RAM needed
+//! is [`snapshot_buffer_len`] bytes (38.5 KiB for QQVGA).
+//!
+//! # Example
+//!
+//! ```ignore
+//! static BUF: StaticCell<[u8; snapshot_buffer_len(160, 120)]> = StaticCell::new();
+//! let mut snapshot = CameraSnapshot::new(camera, "/snapshot.bmp", BUF.init([0; _]))?;
+//!
+//! // As the whole handler, or from a larger one:
+//! if let Some(response) = snapshot.serve(request).await {
+//!     return response;
+//! }
+//! ```
+
+use crate::{CameraError, FrameCapture, HttpHandler, Method, Request, Response, Status};
+
+/// BMP file and info headers plus the RGB565 channel masks
+pub const BMP_RGB565_HEADER_LEN: usize = 14 + 40 + 12;
+
+/// Buffer size for a `width` x `height` snapshot, header included
+pub const fn snapshot_buffer_len(width: u16, height: u16) -> usize {
+    BMP_RGB565_HEADER_LEN + width as usize * height as usize * 2
+}
+
+/// Serves snapshots from `camera` at one path
+pub struct CameraSnapshot<'b, C: FrameCapture> {
+    camera: C,
+    path: &'b str,
+    buffer: &'b mut [u8],
+}
+
+impl<'b, C: FrameCapture> CameraSnapshot<'b, C> {
+    /// `buffer` needs [`snapshot_buffer_len`] bytes for the camera's frame size.
+    pub fn new(camera: C, path: &'b str, buffer: &'b mut [u8]) -> Result<Self, CameraError> {
+        let (width, height) = camera.frame_size();
+        let needed = snapshot_buffer_len(width, height);
+        if buffer.len() < needed {
+            return Err(CameraError::BufferTooSmall {
+                actual: buffer.len(),
+                needed,
+            });
+        }
+        Ok(Self {
+            camera,
+            path,
+            buffer,
+        })
+    }
+
+    pub fn camera(&mut self) -> &mut C {
+        &mut self.camera
+    }
+
+    /// Capture and return a snapshot for a GET or HEAD of the snapshot path; `None` for
+    /// anything else. A failed capture answers 503.
+    pub async fn serve<'a>(&'a mut self, request: &Request<'_>) -> Option<Response<'a>> {
+        if !matches!(request.method, Method::Get | Method::Head) || request.path != self.path {
+            return None;
+        }
+        let (width, height) = self.camera.frame_size();
+        let len = snapshot_buffer_len(width, height);
+        let (header, pixels) = self.buffer[..len].split_at_mut(BMP_RGB565_HEADER_LEN);
+        if self.camera.capture(pixels).await.is_err() {
+            return Some(Response::empty(Status::SERVICE_UNAVAILABLE));
+        }
+        header.copy_from_slice(&bmp_rgb565_header(width, height));
+        // Cameras deliver big-endian pixels, BMP stores them little-endian
+        for pixel in pixels.chunks_exact_mut(2) {
+            pixel.swap(0, 1);
+        }
+        Some(
+            Response::new(Status::OK, "image/bmp", &self.buffer[..len])
+                .with_header("Cache-Control", "no-store"),
+        )
+    }
+}
+
+impl<C: FrameCapture> HttpHandler for CameraSnapshot<'_, C> {
+    async fn handle<'a>(
+        &'a mut self,
+        request: &Request<'_>,
+        _scratch: &'a mut [u8],
+    ) -> Response<'a> {
+        match self.serve(request).await {
+            Some(response) => response,
+            None => Response::empty(Status::NOT_FOUND),
+        }
+    }
+}
+
+/// Header of a top-down 16-bit BMP with RGB565 bit fields. `width` must be even so rows
+/// need no padding.
+fn bmp_rgb565_header(width: u16, height: u16) -> [u8; BMP_RGB565_HEADER_LEN] {
+    let image_len = width as u32 * height as u32 * 2;
+    let mut header = [0u8; BMP_RGB565_HEADER_LEN];
+    let fields: [&[u8]; 17] = [
+        b"BM",
+        &(BMP_RGB565_HEADER_LEN as u32 + image_len).to_le_bytes(),
+        &0u32.to_le_bytes(),
+        &(BMP_RGB565_HEADER_LEN as u32).to_le_bytes(),
+        &40u32.to_le_bytes(),
+        &(width as i32).to_le_bytes(),
+        // Negative height: rows are stored top to bottom
+        &(-(height as i32)).to_le_bytes(),
+        &1u16.to_le_bytes(),
+        &16u16.to_le_bytes(),
+        // BI_BITFIELDS
+        &3u32.to_le_bytes(),
+        &image_len.to_le_bytes(),
+        // 72 DPI, no palette
+        &2835u32.to_le_bytes(),
+        &2835u32.to_le_bytes(),
+        &0u64.to_le_bytes(),
+        &0xf800u32.to_le_bytes(),
+        &0x07e0u32.to_le_bytes(),
+        &0x001fu32.to_le_bytes(),
+    ];
+    let mut offset = 0;
+    for field in fields {
+        header[offset..offset + field.len()].copy_from_slice(field);
+        offset += field.len();
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestPattern;
+
+    impl FrameCapture for TestPattern {
+        fn frame_size(&self) -> (u16, u16) {
+            (2, 2)
+        }
+
+        async fn capture(&mut self, frame: &mut [u8]) -> Result<(), CameraError> {
+            frame[..8].copy_from_slice(&[0xf8, 0x00, 0x07, 0xe0, 0x00, 0x1f, 0xff, 0xff]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bmp_header_layout() {
+        let header = bmp_rgb565_header(160, 120);
+        assert_eq!(&header[..2], b"BM");
+        assert_eq!(header[2..6], (66u32 + 38_400).to_le_bytes());
+        assert_eq!(header[10..14], 66u32.to_le_bytes());
+        assert_eq!(header[22..26], (-120i32).to_le_bytes());
+        assert_eq!(header[28..30], 16u16.to_le_bytes());
+        assert_eq!(header[54..58], 0xf800u32.to_le_bytes());
+        assert_eq!(header[62..66], 0x001fu32.to_le_bytes());
+    }
+
+    #[test]
+    fn serves_snapshot_path_only() {
+        let mut buffer = [0u8; snapshot_buffer_len(2, 2)];
+        let mut snapshot = CameraSnapshot::new(TestPattern, "/snap.bmp", &mut buffer).unwrap();
+        let other = Request::parse(b"GET /other HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert!(embassy_futures::block_on(snapshot.serve(&other)).is_none());
+
+        let get = Request::parse(b"GET /snap.bmp HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        let response = embassy_futures::block_on(snapshot.serve(&get)).unwrap();
+        assert_eq!(response.status, Status::OK);
+        assert_eq!(response.body.len(), 66 + 8);
+        assert_eq!(&response.body[66..70], &[0x00, 0xf8, 0xe0, 0x07]);
+
+        assert!(CameraSnapshot::new(TestPattern, "/", &mut [0u8; 10]).is_err());
+    }
+}
@@ -1,3 +1,35 @@
+mod framing;
+mod heartbeat;
+mod http_assets;
+mod http_auth;
+mod http_json;
+mod http_logs;
+mod http_server;
+mod http_snapshot;
+mod modbus;
+mod modbus_tcp;
+mod mqtt_commands;
+mod net_limits;
+mod radio_link;
+mod sntp;
+mod telemetry_queue;
 mod wifi;
+mod wifi_profiles;
 
+pub use framing::*;
+pub use heartbeat::*;
+pub use http_assets::*;
+pub use http_auth::*;
+pub use http_json::*;
+pub use http_logs::*;
+pub use http_server::*;
+pub use http_snapshot::*;
+pub use modbus::*;
+pub use modbus_tcp::*;
+pub use mqtt_commands::*;
+pub use net_limits::*;
+pub use radio_link::*;
+pub use sntp::*;
+pub use telemetry_queue::*;
 pub use wifi::*;
+pub use wifi_profiles::*;
@@ -0,0 +1,337 @@
+//! modbus.rs — Modbus register and coil access, independent of the transport
+//!
+//! [`ModbusClient`] implements the function codes (building request PDUs and checking
+//! responses) once; a transport only provides [`ModbusClient::transact`], which carries one
+//! PDU to a unit and returns its answer. Application code written against the trait works
+//! unchanged over any transport, e.g. [`ModbusTcp`](crate::ModbusTcp).
+//!
+//! # Example
+//!
+//! ```ignore
+//! async fn poll_meter(bus: &mut impl ModbusClient) -> Result<u16, ModbusError> {
+//!     let mut regs = [0u16; 2];
+//!     bus.read_holding_registers(1, 0x0000, &mut regs).await?;
+//!     bus.write_register(1, 0x0010, 1).await?;
+//!     Ok(regs[0])
+//! }
+//! ```
+
+/// Largest protocol data unit (function code and data)
+pub const MODBUS_MAX_PDU_LEN: usize = 253;
+/// Most registers one read request may ask for
+pub const MODBUS_MAX_READ_REGISTERS: usize = 125;
+/// Most registers one write request may carry
+pub const MODBUS_MAX_WRITE_REGISTERS: usize = 123;
+/// Most coils or discrete inputs one read request may ask for
+pub const MODBUS_MAX_READ_BITS: usize = 2000;
+
+const READ_COILS: u8 = 0x01;
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+/// Set in the function code of an exception response
+const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Exception code returned by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ModbusException {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    GatewayPathUnavailable,
+    GatewayTargetFailedToRespond,
+    Other(u8),
+}
+
+impl ModbusException {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x01 => Self::IllegalFunction,
+            0x02 => Self::IllegalDataAddress,
+            0x03 => Self::IllegalDataValue,
+            0x04 => Self::ServerDeviceFailure,
+            0x05 => Self::Acknowledge,
+            0x06 => Self::ServerDeviceBusy,
+            0x0a => Self::GatewayPathUnavailable,
+            0x0b => Self::GatewayTargetFailedToRespond,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum ModbusError {
+    #[error("Connection error")]
+    Connection,
+    #[error("No response in time")]
+    Timeout,
+    #[error("Server exception: {0:?}")]
+    Exception(ModbusException),
+    #[error("Malformed response")]
+    InvalidResponse,
+    #[error("Register or coil count out of range")]
+    InvalidCount,
+}
+
+/// Modbus master; the provided methods share their PDU handling across transports
+#[allow(async_fn_in_trait)]
+pub trait ModbusClient {
+    /// Send `request` (a PDU) to `unit` and read the response PDU into `response`,
+    /// returning its length.
+    async fn transact(
+        &mut self,
+        unit: u8,
+        request: &[u8],
+        response: &mut [u8; MODBUS_MAX_PDU_LEN],
+    ) -> Result<usize, ModbusError>;
+
+    async fn read_coils(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [bool],
+    ) -> Result<(), ModbusError> {
+        read_bits(self, unit, READ_COILS, address, out).await
+    }
+
+    async fn read_discrete_inputs(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [bool],
+    ) -> Result<(), ModbusError> {
+        read_bits(self, unit, READ_DISCRETE_INPUTS, address, out).await
+    }
+
+    async fn read_holding_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        read_registers(self, unit, READ_HOLDING_REGISTERS, address, out).await
+    }
+
+    async fn read_input_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        read_registers(self, unit, READ_INPUT_REGISTERS, address, out).await
+    }
+
+    async fn write_coil(&mut self, unit: u8, address: u16, on: bool) -> Result<(), ModbusError> {
+        let value = if on { 0xff00 } else { 0x0000 };
+        write_single(self, unit, WRITE_SINGLE_COIL, address, value).await
+    }
+
+    async fn write_register(
+        &mut self,
+        unit: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), ModbusError> {
+        write_single(self, unit, WRITE_SINGLE_REGISTER, address, value).await
+    }
+
+    async fn write_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError> {
+        let mut request = [0u8; MODBUS_MAX_PDU_LEN];
+        let len = write_registers_request(address, values, &mut request)?;
+        let mut response = [0u8; MODBUS_MAX_PDU_LEN];
+        let response_len = self.transact(unit, &request[..len], &mut response).await?;
+        let data = response_data(WRITE_MULTIPLE_REGISTERS, &response[..response_len])?;
+        check_echo(data, &request[1..5])
+    }
+}
+
+async fn read_bits<C: ModbusClient + ?Sized>(
+    client: &mut C,
+    unit: u8,
+    function: u8,
+    address: u16,
+    out: &mut [bool],
+) -> Result<(), ModbusError> {
+    let request = read_request(function, address, out.len(), MODBUS_MAX_READ_BITS)?;
+    let mut response = [0u8; MODBUS_MAX_PDU_LEN];
+    let len = client.transact(unit, &request, &mut response).await?;
+    decode_bits(response_data(function, &response[..len])?, out)
+}
+
+async fn read_registers<C: ModbusClient + ?Sized>(
+    client: &mut C,
+    unit: u8,
+    function: u8,
+    address: u16,
+    out: &mut [u16],
+) -> Result<(), ModbusError> {
+    let request = read_request(function, address, out.len(), MODBUS_MAX_READ_REGISTERS)?;
+    let mut response = [0u8; MODBUS_MAX_PDU_LEN];
+    let len = client.transact(unit, &request, &mut response).await?;
+    decode_registers(response_data(function, &response[..len])?, out)
+}
+
+async fn write_single<C: ModbusClient + ?Sized>(
+    client: &mut C,
+    unit: u8,
+    function: u8,
+    address: u16,
+    value: u16,
+) -> Result<(), ModbusError> {
+    let [a_hi, a_lo] = address.to_be_bytes();
+    let [v_hi, v_lo] = value.to_be_bytes();
+    let request = [function, a_hi, a_lo, v_hi, v_lo];
+    let mut response = [0u8; MODBUS_MAX_PDU_LEN];
+    let len = client.transact(unit, &request, &mut response).await?;
+    check_echo(response_data(function, &response[..len])?, &request[1..])
+}
+
+fn read_request(
+    function: u8,
+    address: u16,
+    count: usize,
+    max: usize,
+) -> Result<[u8; 5], ModbusError> {
+    if count == 0 || count > max {
+        return Err(ModbusError::InvalidCount);
+    }
+    let [a_hi, a_lo] = address.to_be_bytes();
+    let [c_hi, c_lo] = (count as u16).to_be_bytes();
+    Ok([function, a_hi, a_lo, c_hi, c_lo])
+}
+
+fn write_registers_request(
+    address: u16,
+    values: &[u16],
+    out: &mut [u8; MODBUS_MAX_PDU_LEN],
+) -> Result<usize, ModbusError> {
+    if values.is_empty() || values.len() > MODBUS_MAX_WRITE_REGISTERS {
+        return Err(ModbusError::InvalidCount);
+    }
+    out[0] = WRITE_MULTIPLE_REGISTERS;
+    out[1..3].copy_from_slice(&address.to_be_bytes());
+    out[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+    out[5] = (values.len() * 2) as u8;
+    for (chunk, value) in out[6..].chunks_exact_mut(2).zip(values) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    Ok(6 + values.len() * 2)
+}
+
+/// Data after the function code, or the server's exception
+fn response_data(function: u8, response: &[u8]) -> Result<&[u8], ModbusError> {
+    match response {
+        [f, code, ..] if *f == function | EXCEPTION_FLAG => {
+            Err(ModbusError::Exception(ModbusException::from_code(*code)))
+        }
+        [f, data @ ..] if *f == function => Ok(data),
+        _ => Err(ModbusError::InvalidResponse),
+    }
+}
+
+fn decode_registers(data: &[u8], out: &mut [u16]) -> Result<(), ModbusError> {
+    match data {
+        [count, values @ ..]
+            if *count as usize == out.len() * 2 && values.len() == out.len() * 2 =>
+        {
+            for (value, bytes) in out.iter_mut().zip(values.chunks_exact(2)) {
+                *value = u16::from_be_bytes([bytes[0], bytes[1]]);
+            }
+            Ok(())
+        }
+        _ => Err(ModbusError::InvalidResponse),
+    }
+}
+
+fn decode_bits(data: &[u8], out: &mut [bool]) -> Result<(), ModbusError> {
+    let byte_count = out.len().div_ceil(8);
+    match data {
+        [count, bits @ ..] if *count as usize == byte_count && bits.len() == byte_count => {
+            for (i, bit) in out.iter_mut().enumerate() {
+                *bit = bits[i / 8] & (1 << (i % 8)) != 0;
+            }
+            Ok(())
+        }
+        _ => Err(ModbusError::InvalidResponse),
+    }
+}
+
+/// Write responses repeat the address and value/count of the request.
+fn check_echo(data: &[u8], expected: &[u8]) -> Result<(), ModbusError> {
+    if data == expected {
+        Ok(())
+    } else {
+        Err(ModbusError::InvalidResponse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_requests() {
+        assert_eq!(
+            read_request(READ_HOLDING_REGISTERS, 0x006b, 3, MODBUS_MAX_READ_REGISTERS),
+            Ok([0x03, 0x00, 0x6b, 0x00, 0x03])
+        );
+        assert_eq!(
+            read_request(READ_COILS, 0, 2001, MODBUS_MAX_READ_BITS),
+            Err(ModbusError::InvalidCount)
+        );
+
+        let mut out = [0u8; MODBUS_MAX_PDU_LEN];
+        let len = write_registers_request(0x0001, &[0x000a, 0x0102], &mut out).unwrap();
+        assert_eq!(
+            &out[..len],
+            &[0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0a, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn decodes_responses() {
+        let mut regs = [0u16; 3];
+        let data = response_data(0x03, &[0x03, 0x06, 0x02, 0x2b, 0x00, 0x00, 0x00, 0x64]).unwrap();
+        decode_registers(data, &mut regs).unwrap();
+        assert_eq!(regs, [0x022b, 0x0000, 0x0064]);
+
+        let mut coils = [false; 10];
+        decode_bits(&[0x02, 0b0000_0101, 0b0000_0010], &mut coils).unwrap();
+        assert_eq!(
+            coils,
+            [
+                true, false, true, false, false, false, false, false, false, true
+            ]
+        );
+
+        let mut short = [0u16; 4];
+        assert_eq!(
+            decode_registers(data, &mut short),
+            Err(ModbusError::InvalidResponse)
+        );
+    }
+
+    #[test]
+    fn reports_exceptions() {
+        assert_eq!(
+            response_data(0x03, &[0x83, 0x02]),
+            Err(ModbusError::Exception(ModbusException::IllegalDataAddress))
+        );
+        assert_eq!(
+            response_data(0x03, &[0x04, 0x00]),
+            Err(ModbusError::InvalidResponse)
+        );
+        assert_eq!(check_echo(&[0x00, 0x01], &[0x00, 0x01]), Ok(()));
+    }
+}
@@ -0,0 +1,194 @@
+//! modbus_tcp.rs — Modbus TCP client over the WiFi stack
+//!
+//! [`ModbusTcp`] implements [`ModbusClient`] by wrapping each PDU in an MBAP header and
+//! exchanging it over one TCP connection to the server (port 502 by default). The
+//! connection is opened on first use, and dropped after any error or timeout so the next
+//! request starts clean.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut rx = [0u8; 512];
+//! let mut tx = [0u8; 512];
+//! let plc = IpAddress::v4(192, 168, 1, 50);
+//! let mut modbus = ModbusTcp::new(wifi.stack, &mut rx, &mut tx, plc, ModbusTcpConfig::default());
+//!
+//! let mut regs = [0u16; 4];
+//! modbus.read_input_registers(1, 0x0000, &mut regs).await?;
+//! ```
+
+use embassy_net::tcp::{State, TcpSocket};
+use embassy_net::{IpAddress, Stack};
+use embassy_time::{Duration, with_timeout};
+
+use crate::{MODBUS_MAX_PDU_LEN, ModbusClient, ModbusError};
+
+/// Transaction id, protocol id, length and unit id
+const MBAP_HEADER_LEN: usize = 7;
+
+#[derive(Debug, Clone)]
+pub struct ModbusTcpConfig {
+    pub port: u16,
+    /// Longest wait for connecting or for a complete response
+    pub timeout: Duration,
+}
+
+impl Default for ModbusTcpConfig {
+    fn default() -> Self {
+        Self {
+            port: 502,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+pub struct ModbusTcp<'a> {
+    socket: TcpSocket<'a>,
+    server: IpAddress,
+    config: ModbusTcpConfig,
+    transaction: u16,
+}
+
+impl<'a> ModbusTcp<'a> {
+    /// `rx_buffer` and `tx_buffer` back the TCP socket; 300 bytes each fit any request.
+    pub fn new(
+        stack: Stack<'a>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+        server: IpAddress,
+        config: ModbusTcpConfig,
+    ) -> Self {
+        Self {
+            socket: TcpSocket::new(stack, rx_buffer, tx_buffer),
+            server,
+            config,
+            transaction: 0,
+        }
+    }
+
+    /// Connect now instead of on the first request.
+    pub async fn connect(&mut self) -> Result<(), ModbusError> {
+        if self.socket.state() == State::Established {
+            return Ok(());
+        }
+        self.socket.abort();
+        let endpoint = (self.server, self.config.port);
+        match with_timeout(self.config.timeout, self.socket.connect(endpoint)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => Err(ModbusError::Connection),
+            Err(_) => {
+                self.socket.abort();
+                Err(ModbusError::Timeout)
+            }
+        }
+    }
+
+    pub async fn close(&mut self) {
+        self.socket.close();
+        let _ = self.socket.flush().await;
+    }
+
+    async fn exchange(
+        &mut self,
+        unit: u8,
+        request: &[u8],
+        response: &mut [u8; MODBUS_MAX_PDU_LEN],
+    ) -> Result<usize, ModbusError> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut frame = [0u8; MBAP_HEADER_LEN + MODBUS_MAX_PDU_LEN];
+        let header = mbap_header(self.transaction, unit, request.len());
+        frame[..MBAP_HEADER_LEN].copy_from_slice(&header);
+        frame[MBAP_HEADER_LEN..MBAP_HEADER_LEN + request.len()].copy_from_slice(request);
+        write_all(&mut self.socket, &frame[..MBAP_HEADER_LEN + request.len()]).await?;
+
+        let mut header = [0u8; MBAP_HEADER_LEN];
+        read_exact(&mut self.socket, &mut header).await?;
+        let len = parse_mbap_header(&header, self.transaction, unit)?;
+        read_exact(&mut self.socket, &mut response[..len]).await?;
+        Ok(len)
+    }
+}
+
+impl ModbusClient for ModbusTcp<'_> {
+    async fn transact(
+        &mut self,
+        unit: u8,
+        request: &[u8],
+        response: &mut [u8; MODBUS_MAX_PDU_LEN],
+    ) -> Result<usize, ModbusError> {
+        self.connect().await?;
+        let result = with_timeout(self.config.timeout, self.exchange(unit, request, response))
+            .await
+            .unwrap_or(Err(ModbusError::Timeout));
+        if result.is_err() {
+            // A late answer would otherwise be taken for the next request's
+            self.socket.abort();
+        }
+        result
+    }
+}
+
+fn mbap_header(transaction: u16, unit: u8, pdu_len: usize) -> [u8; MBAP_HEADER_LEN] {
+    let [t_hi, t_lo] = transaction.to_be_bytes();
+    let [l_hi, l_lo] = (pdu_len as u16 + 1).to_be_bytes();
+    [t_hi, t_lo, 0, 0, l_hi, l_lo, unit]
+}
+
+/// Length of the PDU that follows `header`
+fn parse_mbap_header(
+    header: &[u8; MBAP_HEADER_LEN],
+    transaction: u16,
+    unit: u8,
+) -> Result<usize, ModbusError> {
+    let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let valid = u16::from_be_bytes([header[0], header[1]]) == transaction
+        && header[2..4] == [0, 0]
+        && header[6] == unit
+        && (2..=MODBUS_MAX_PDU_LEN + 1).contains(&len);
+    if valid {
+        Ok(len - 1)
+    } else {
+        Err(ModbusError::InvalidResponse)
+    }
+}
+
+async fn write_all(socket: &mut TcpSocket<'_>, mut data: &[u8]) -> Result<(), ModbusError> {
+    while !data.is_empty() {
+        match socket.write(data).await {
+            Ok(0) | Err(_) => return Err(ModbusError::Connection),
+            Ok(written) => data = &data[written..],
+        }
+    }
+    Ok(())
+}
+
+async fn read_exact(socket: &mut TcpSocket<'_>, buf: &mut [u8]) -> Result<(), ModbusError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match socket.read(&mut buf[filled..]).await {
+            Ok(0) | Err(_) => return Err(ModbusError::Connection),
+            Ok(read) => filled += read,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_mbap_headers() {
+        let header = mbap_header(0x0102, 0x11, 5);
+        assert_eq!(header, [0x01, 0x02, 0x00, 0x00, 0x00, 0x06, 0x11]);
+        assert_eq!(parse_mbap_header(&header, 0x0102, 0x11), Ok(5));
+        assert_eq!(
+            parse_mbap_header(&header, 0x0103, 0x11),
+            Err(ModbusError::InvalidResponse)
+        );
+        assert_eq!(
+            parse_mbap_header(&[0x01, 0x02, 0x00, 0x00, 0x01, 0x00, 0x11], 0x0102, 0x11),
+            Err(ModbusError::InvalidResponse)
+        );
+    }
+}
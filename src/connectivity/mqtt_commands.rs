@@ -0,0 +1,348 @@
+//! mqtt_commands.rs — typed remote commands over MQTT
+//!
+//! A [`CommandRouter`] subscribes to `device/<id>/cmd/+`, where the last topic level names
+//! the command and the payload carries its arguments as JSON. It parses each message into
+//! a [`Command`], calls the matching method of the firmware's [`DeviceActions`] and
+//! publishes the outcome to `device/<id>/result/<command>` (`result/_unknown` for names it
+//! doesn't know), so every project built on the crate can be driven by the same dashboard
+//! or script:
+//!
+//! | Command           | Payload                                   | Action                 |
+//! |-------------------|-------------------------------------------|------------------------|
+//! | `set_servo_angle` | `{"servo":0,"angle":90.0}`                | `set_servo_angle`      |
+//! | `display_text`    | `{"text":"Hello"}`                        | `display_text` on 0    |
+//! | `reboot`          | none, or `{}`                             | `reboot`               |
+//! | `start_ota`       | `{"url":"http://...","sha256":"..."}`     | `start_ota`            |
+//!
+//! Results are `{"ok":true}` or `{"ok":false,"error":"<reason>"}`. Strings in a payload
+//! borrow from the message, so they must not contain JSON escapes. The router talks to the
+//! broker through an [`MqttClient`], implemented over whichever MQTT library the firmware
+//! uses.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // `Bench` implements `DeviceActions`, see device_actions.rs
+//! let router = CommandRouter::new(DeviceId::read(&mut flash)?.to_hex().as_str())?;
+//! router.run(&mut mqtt, &mut Bench { servo, lcd }).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
+use serde::Deserialize;
+
+use crate::{ActionError, DeviceActions, HeaplessString, HeaplessVec, LogModule};
+
+pub const COMMAND_MAX_TOPIC_LEN: usize = 64;
+pub const COMMAND_MAX_PAYLOAD_LEN: usize = 256;
+/// Longest result payload
+pub const COMMAND_MAX_RESULT_LEN: usize = 48;
+
+/// Wait after a failed subscribe or receive before subscribing again
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const TOPIC_ROOT: &str = "device/";
+/// Result topic of the command with the longest name, after the prefix
+const LONGEST_RESULT_SUFFIX: &str = "result/set_servo_angle";
+/// Stands in for the command name in the result topic of unknown commands, whose names
+/// may not fit
+const UNKNOWN_COMMAND_NAME: &str = "_unknown";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum CommandError {
+    #[error("unknown command")]
+    UnknownCommand,
+    #[error("invalid payload")]
+    InvalidPayload,
+    #[error("topic too long")]
+    TopicTooLong,
+    #[error("{0}")]
+    Action(#[from] ActionError),
+}
+
+/// One parsed command
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Command<'a> {
+    SetServoAngle {
+        servo: u8,
+        angle: f32,
+    },
+    DisplayText {
+        text: &'a str,
+    },
+    Reboot,
+    StartOta {
+        url: &'a str,
+        /// Expected SHA-256 of the image, as hex
+        sha256: Option<&'a str>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ServoArgs {
+    servo: u8,
+    angle: f32,
+}
+
+#[derive(Deserialize)]
+struct TextArgs<'a> {
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OtaArgs<'a> {
+    url: &'a str,
+    sha256: Option<&'a str>,
+}
+
+impl<'a> Command<'a> {
+    /// Parse the command `name` (the last topic level) with its JSON `payload`.
+    pub fn parse(name: &str, payload: &'a [u8]) -> Result<Self, CommandError> {
+        Ok(match name {
+            "set_servo_angle" => {
+                let args: ServoArgs = json(payload)?;
+                Self::SetServoAngle {
+                    servo: args.servo,
+                    angle: args.angle,
+                }
+            }
+            "display_text" => Self::DisplayText {
+                text: json::<TextArgs>(payload)?.text,
+            },
+            "reboot" => Self::Reboot,
+            "start_ota" => {
+                let args: OtaArgs = json(payload)?;
+                Self::StartOta {
+                    url: args.url,
+                    sha256: args.sha256,
+                }
+            }
+            _ => return Err(CommandError::UnknownCommand),
+        })
+    }
+}
+
+fn json<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<T, CommandError> {
+    serde_json_core::from_slice(payload)
+        .map(|(value, _)| value)
+        .map_err(|_| CommandError::InvalidPayload)
+}
+
+/// A received MQTT message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttMessage {
+    pub topic: HeaplessString<COMMAND_MAX_TOPIC_LEN>,
+    pub payload: HeaplessVec<u8, COMMAND_MAX_PAYLOAD_LEN>,
+}
+
+/// Connection to an MQTT broker, kept connected by the client itself
+#[allow(async_fn_in_trait)]
+pub trait MqttClient {
+    type Error;
+
+    async fn subscribe(&mut self, filter: &str) -> Result<(), Self::Error>;
+
+    /// Wait for the next message of a subscription.
+    async fn receive(&mut self) -> Result<MqttMessage, Self::Error>;
+
+    async fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<(), Self::Error>;
+}
+
+pub struct CommandRouter {
+    /// `device/<id>/`
+    prefix: HeaplessString<COMMAND_MAX_TOPIC_LEN>,
+}
+
+impl CommandRouter {
+    /// Router for the topics of `device_id`, e.g. [`DeviceId::to_hex`](crate::DeviceId::to_hex).
+    pub fn new(device_id: &str) -> Result<Self, CommandError> {
+        let mut prefix = HeaplessString::new();
+        // Writing truncates, so check the length instead
+        let _ = write!(prefix, "{TOPIC_ROOT}{device_id}/");
+        if prefix.len() + LONGEST_RESULT_SUFFIX.len() > COMMAND_MAX_TOPIC_LEN {
+            return Err(CommandError::TopicTooLong);
+        }
+        Ok(Self { prefix })
+    }
+
+    /// `device/<id>/cmd/+`
+    pub fn filter(&self) -> HeaplessString<COMMAND_MAX_TOPIC_LEN> {
+        self.topic("cmd", "+")
+    }
+
+    /// Command name of a topic under `device/<id>/cmd/`
+    pub fn command_name<'t>(&self, topic: &'t str) -> Option<&'t str> {
+        topic
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix("cmd/")
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+    }
+
+    /// Execute the command of one message; returns the result topic and payload, or
+    /// `None` if the topic isn't a command of this device.
+    pub async fn handle<A: DeviceActions>(
+        &self,
+        actions: &mut A,
+        topic: &str,
+        payload: &[u8],
+    ) -> Option<(
+        HeaplessString<COMMAND_MAX_TOPIC_LEN>,
+        HeaplessString<COMMAND_MAX_RESULT_LEN>,
+    )> {
+        let name = self.command_name(topic)?;
+        let mut result = HeaplessString::new();
+        let outcome = execute(actions, name, payload).await;
+        let _ = match outcome {
+            Ok(()) => write!(result, r#"{{"ok":true}}"#),
+            Err(e) => write!(result, r#"{{"ok":false,"error":"{e}"}}"#),
+        };
+        let reply_name = match outcome {
+            Err(CommandError::UnknownCommand) => UNKNOWN_COMMAND_NAME,
+            _ => name,
+        };
+        Some((self.topic("result", reply_name), result))
+    }
+
+    /// Subscribe and serve commands forever, subscribing again after a client error.
+    pub async fn run<C: MqttClient, A: DeviceActions>(&self, client: &mut C, actions: &mut A) -> ! {
+        loop {
+            if client.subscribe(self.filter().as_str()).await.is_err() {
+                Timer::after(RETRY_DELAY).await;
+                continue;
+            }
+            while let Ok(message) = client.receive().await {
+                let Some((topic, result)) = self
+                    .handle(actions, message.topic.as_str(), message.payload.as_slice())
+                    .await
+                else {
+                    continue;
+                };
+                if client
+                    .publish(topic.as_str(), result.as_str().as_bytes())
+                    .await
+                    .is_err()
+                {
+                    crate::log!(
+                        LogModule::App,
+                        Warn,
+                        "command result for {} lost",
+                        topic.as_str()
+                    );
+                }
+            }
+            Timer::after(RETRY_DELAY).await;
+        }
+    }
+
+    /// `device/<id>/<kind>/<name>`; [`new`](Self::new) made sure it fits for every known
+    /// command name
+    fn topic(&self, kind: &str, name: &str) -> HeaplessString<COMMAND_MAX_TOPIC_LEN> {
+        let mut topic = self.prefix.clone();
+        let _ = write!(topic, "{kind}/{name}");
+        topic
+    }
+}
+
+async fn execute<A: DeviceActions>(
+    actions: &mut A,
+    name: &str,
+    payload: &[u8],
+) -> Result<(), CommandError> {
+    let result = match Command::parse(name, payload)? {
+        Command::SetServoAngle { servo, angle } => actions.set_servo_angle(servo, angle).await,
+        Command::DisplayText { text } => actions.display_text(0, text).await,
+        Command::Reboot => actions.reboot().await,
+        Command::StartOta { url, sha256 } => actions.start_ota(url, sha256).await,
+    };
+    result.map_err(CommandError::Action)
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::device_actions::tests::Bench;
+
+    /// Result topic and payload, concatenated with a space
+    fn respond(bench: &mut Bench, topic: &str, payload: &str) -> Option<HeaplessString<128>> {
+        let router = CommandRouter::new("e66138").unwrap();
+        let (topic, result) = block_on(router.handle(bench, topic, payload.as_bytes()))?;
+        let mut response = HeaplessString::new();
+        let _ = write!(response, "{topic} {result}");
+        Some(response)
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("set_servo_angle", br#"{"servo":1,"angle":45.5}"#),
+            Ok(Command::SetServoAngle {
+                servo: 1,
+                angle: 45.5
+            })
+        );
+        assert_eq!(
+            Command::parse("start_ota", br#"{"url":"http://ota/fw.bin"}"#),
+            Ok(Command::StartOta {
+                url: "http://ota/fw.bin",
+                sha256: None
+            })
+        );
+        assert_eq!(Command::parse("reboot", b""), Ok(Command::Reboot));
+        assert_eq!(
+            Command::parse("display_text", br#"{"txt":"x"}"#),
+            Err(CommandError::InvalidPayload)
+        );
+        assert_eq!(
+            Command::parse("format_flash", b"{}"),
+            Err(CommandError::UnknownCommand)
+        );
+    }
+
+    #[test]
+    fn routes_and_reports() {
+        let mut bench = Bench::default();
+        let response = respond(
+            &mut bench,
+            "device/e66138/cmd/set_servo_angle",
+            r#"{"servo":0,"angle":90}"#,
+        );
+        assert_eq!(
+            response.as_ref().map(HeaplessString::as_str),
+            Some(r#"device/e66138/result/set_servo_angle {"ok":true}"#)
+        );
+        assert_eq!(bench.angle, 90.0);
+        let response = respond(&mut bench, "device/e66138/cmd/reboot", "");
+        assert_eq!(
+            response.as_ref().map(HeaplessString::as_str),
+            Some(r#"device/e66138/result/reboot {"ok":false,"error":"not supported"}"#)
+        );
+        assert!(respond(&mut bench, "device/other/cmd/reboot", "").is_none());
+        assert!(respond(&mut bench, "device/e66138/cmd/a/b", "").is_none());
+    }
+
+    #[test]
+    fn unknown_commands_reply_on_a_fixed_topic() {
+        let mut bench = Bench::default();
+        let response = respond(
+            &mut bench,
+            "device/e66138/cmd/calibrate_all_the_servos_now",
+            "",
+        );
+        assert_eq!(
+            response.as_ref().map(HeaplessString::as_str),
+            Some(r#"device/e66138/result/_unknown {"ok":false,"error":"unknown command"}"#)
+        );
+    }
+
+    #[test]
+    fn rejects_device_ids_too_long_for_result_topics() {
+        // 34 characters leave room for "device/<id>/result/set_servo_angle"
+        assert!(CommandRouter::new("0123456789abcdef0123456789abcdef01").is_ok());
+        assert_eq!(
+            CommandRouter::new("0123456789abcdef0123456789abcdef012").err(),
+            Some(CommandError::TopicTooLong)
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! net_limits.rs — per-client connection limits and rate limiting for TCP servers
+//!
+//! The stack only has a handful of sockets, so one misbehaving client on the LAN can keep
+//! every server task busy. A [`NetLimiter`] shared by all server tasks (usually a `static`)
+//! tracks up to [`NET_MAX_TRACKED_CLIENTS`] remote addresses; each gets a cap on concurrent
+//! connections and a [`TokenBucket`] of new connections. Admitting a connection returns a
+//! [`ConnectionPermit`] that frees the slot when dropped.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static LIMITS: NetLimiter = NetLimiter::new(NetLimitsConfig::DEFAULT);
+//!
+//! // HTTP: rejected clients get `429 Too Many Requests`
+//! HttpServer::new(wifi.stack, HttpServerConfig::default())
+//!     .with_limiter(&LIMITS)
+//!     .run(&mut api)
+//!     .await;
+//!
+//! // Any other TCP server, right after `accept`:
+//! let _permit = match LIMITS.admit_socket(&socket) {
+//!     Ok(permit) => permit,
+//!     Err(_) => { socket.abort(); continue; }
+//! };
+//! ```
+
+use core::cell::RefCell;
+
+use embassy_net::IpAddress;
+use embassy_net::tcp::TcpSocket;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant};
+
+/// Remote addresses a [`NetLimiter`] keeps state for
+pub const NET_MAX_TRACKED_CLIENTS: usize = 8;
+
+/// Token bucket values are kept in thousandths of a token
+const MILLI: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum NetLimitError {
+    #[error("Too many connections from this client")]
+    TooManyConnections,
+    #[error("Connection rate limit exceeded")]
+    RateLimited,
+    #[error("Too many clients")]
+    TooManyClients,
+    #[error("Socket has no remote endpoint")]
+    NotConnected,
+}
+
+/// Allows bursts of `capacity` events, refilled at `per_second`
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: u32,
+    per_second: u32,
+    millitokens: u64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub const fn new(capacity: u32, per_second: u32) -> Self {
+        Self {
+            capacity,
+            per_second,
+            millitokens: capacity as u64 * MILLI,
+            last: Instant::from_ticks(0),
+        }
+    }
+
+    /// Take one token if available.
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    /// Whole tokens left
+    pub fn available(&self) -> u32 {
+        (self.millitokens / MILLI) as u32
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.millitokens < MILLI {
+            return false;
+        }
+        self.millitokens -= MILLI;
+        true
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed_ms = now.saturating_duration_since(self.last).as_millis();
+        // Only consume whole milliseconds so slow rates don't lose their remainder
+        self.last += Duration::from_millis(elapsed_ms);
+        let added = elapsed_ms.saturating_mul(self.per_second as u64);
+        self.millitokens = self
+            .millitokens
+            .saturating_add(added)
+            .min(self.capacity as u64 * MILLI);
+    }
+
+    fn is_full(&self) -> bool {
+        self.millitokens >= self.capacity as u64 * MILLI
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NetLimitsConfig {
+    /// Concurrent connections from one address
+    pub max_connections_per_client: u8,
+    /// New connections one address may open back to back
+    pub burst: u32,
+    /// Sustained new connections per second from one address
+    pub per_second: u32,
+}
+
+impl NetLimitsConfig {
+    pub const DEFAULT: Self = Self {
+        max_connections_per_client: 2,
+        burst: 10,
+        per_second: 5,
+    };
+}
+
+impl Default for NetLimitsConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Client {
+    address: IpAddress,
+    connections: u8,
+    bucket: TokenBucket,
+}
+
+/// Connection limits shared by server tasks
+pub struct NetLimiter {
+    config: NetLimitsConfig,
+    clients: Mutex<CriticalSectionRawMutex, RefCell<[Option<Client>; NET_MAX_TRACKED_CLIENTS]>>,
+}
+
+impl NetLimiter {
+    pub const fn new(config: NetLimitsConfig) -> Self {
+        Self {
+            config,
+            clients: Mutex::new(RefCell::new([const { None }; NET_MAX_TRACKED_CLIENTS])),
+        }
+    }
+
+    pub fn config(&self) -> &NetLimitsConfig {
+        &self.config
+    }
+
+    /// Count a new connection from `address` against its limits.
+    pub fn admit(&self, address: IpAddress) -> Result<ConnectionPermit<'_>, NetLimitError> {
+        self.admit_at(address, Instant::now())
+    }
+
+    /// [`admit`](Self::admit) the remote end of an accepted socket.
+    pub fn admit_socket(
+        &self,
+        socket: &TcpSocket<'_>,
+    ) -> Result<ConnectionPermit<'_>, NetLimitError> {
+        let remote = socket
+            .remote_endpoint()
+            .ok_or(NetLimitError::NotConnected)?;
+        self.admit(remote.addr)
+    }
+
+    /// Open connections from `address`
+    pub fn connections(&self, address: IpAddress) -> u8 {
+        self.clients.lock(|clients| {
+            clients
+                .borrow()
+                .iter()
+                .flatten()
+                .find(|c| c.address == address)
+                .map_or(0, |c| c.connections)
+        })
+    }
+
+    fn admit_at(
+        &self,
+        address: IpAddress,
+        now: Instant,
+    ) -> Result<ConnectionPermit<'_>, NetLimitError> {
+        self.clients.lock(|clients| {
+            let mut clients = clients.borrow_mut();
+            let slot = match clients
+                .iter()
+                .position(|c| c.as_ref().is_some_and(|c| c.address == address))
+            {
+                Some(slot) => slot,
+                None => {
+                    let slot =
+                        free_slot(&mut clients[..], now).ok_or(NetLimitError::TooManyClients)?;
+                    clients[slot] = Some(Client {
+                        address,
+                        connections: 0,
+                        bucket: TokenBucket::new(self.config.burst, self.config.per_second),
+                    });
+                    slot
+                }
+            };
+            let Some(client) = clients[slot].as_mut() else {
+                return Err(NetLimitError::TooManyClients);
+            };
+            if client.connections >= self.config.max_connections_per_client {
+                return Err(NetLimitError::TooManyConnections);
+            }
+            if !client.bucket.try_take_at(now) {
+                return Err(NetLimitError::RateLimited);
+            }
+            client.connections += 1;
+            Ok(ConnectionPermit {
+                limiter: self,
+                address,
+            })
+        })
+    }
+
+    fn release(&self, address: IpAddress) {
+        self.clients.lock(|clients| {
+            if let Some(client) = clients
+                .borrow_mut()
+                .iter_mut()
+                .flatten()
+                .find(|c| c.address == address)
+            {
+                client.connections = client.connections.saturating_sub(1);
+            }
+        });
+    }
+}
+
+/// An empty slot, or one whose client has no connections and a full bucket again (so
+/// forgetting it loses nothing). Clients still being rate limited are never evicted.
+fn free_slot(clients: &mut [Option<Client>], now: Instant) -> Option<usize> {
+    if let Some(slot) = clients.iter().position(Option::is_none) {
+        return Some(slot);
+    }
+    clients.iter_mut().position(|c| {
+        c.as_mut().is_some_and(|c| {
+            c.bucket.refill(now);
+            c.connections == 0 && c.bucket.is_full()
+        })
+    })
+}
+
+/// An admitted connection; dropping it frees the client's connection slot
+pub struct ConnectionPermit<'l> {
+    limiter: &'l NetLimiter,
+    address: IpAddress,
+}
+
+impl ConnectionPermit<'_> {
+    pub fn address(&self) -> IpAddress {
+        self.address
+    }
+}
+
+impl Drop for ConnectionPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(self.address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    fn ip(last: u8) -> IpAddress {
+        IpAddress::v4(192, 168, 1, last)
+    }
+
+    #[test]
+    fn bucket_bursts_then_refills() {
+        let mut bucket = TokenBucket::new(3, 2);
+        assert!((0..3).all(|_| bucket.try_take_at(at(1000))));
+        assert!(!bucket.try_take_at(at(1000)));
+        // 2 per second: one token after 500 ms, accumulated from 1 ms steps
+        for ms in 1001..1500 {
+            assert!(!bucket.try_take_at(at(ms)));
+        }
+        assert!(bucket.try_take_at(at(1500)));
+        bucket.refill(at(60_000));
+        assert_eq!(bucket.available(), 3);
+    }
+
+    #[test]
+    fn limits_connections_per_client() {
+        let limiter = NetLimiter::new(NetLimitsConfig::DEFAULT);
+        let a = limiter.admit_at(ip(2), at(0)).unwrap();
+        let _b = limiter.admit_at(ip(2), at(0)).unwrap();
+        assert_eq!(
+            limiter.admit_at(ip(2), at(0)).err(),
+            Some(NetLimitError::TooManyConnections)
+        );
+        assert!(limiter.admit_at(ip(3), at(0)).is_ok());
+        drop(a);
+        assert_eq!(limiter.connections(ip(2)), 1);
+        assert!(limiter.admit_at(ip(2), at(0)).is_ok());
+    }
+
+    #[test]
+    fn rate_limits_and_evicts_idle_clients() {
+        let limiter = NetLimiter::new(NetLimitsConfig {
+            max_connections_per_client: 1,
+            burst: 1,
+            per_second: 1,
+        });
+        drop(limiter.admit_at(ip(2), at(0)).unwrap());
+        assert_eq!(
+            limiter.admit_at(ip(2), at(10)).err(),
+            Some(NetLimitError::RateLimited)
+        );
+        for last in 3..3 + NET_MAX_TRACKED_CLIENTS as u8 - 1 {
+            drop(limiter.admit_at(ip(last), at(10)).unwrap());
+        }
+        // Table full of clients that are still limited
+        assert_eq!(
+            limiter.admit_at(ip(100), at(20)).err(),
+            Some(NetLimitError::TooManyClients)
+        );
+        // Once their buckets refill they can be forgotten
+        assert!(limiter.admit_at(ip(100), at(2000)).is_ok());
+    }
+}
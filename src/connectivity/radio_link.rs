@@ -0,0 +1,301 @@
+//! radio_link.rs — addressed datagrams with acknowledgments over a packet radio
+//!
+//! [`RadioLink`] prefixes every packet with a 4-byte header (destination, source, sequence
+//! number, flags). Unicast datagrams are acknowledged by the receiver and retransmitted by
+//! the sender until acknowledged or out of retries; broadcasts (to
+//! [`RADIO_BROADCAST_ADDRESS`]) are sent once. Retransmissions that were already delivered
+//! are acknowledged again but not passed up twice.
+//!
+//! Any radio implementing [`PacketRadio`] works, e.g. [`Sx127x`](crate::Sx127x).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut link = RadioLink::new(radio, 0x02, RadioLinkConfig::default());
+//!
+//! // Sensor node
+//! link.send(0x01, &reading.to_le_bytes()).await?;
+//!
+//! // Gateway
+//! let mut buf = [0u8; RADIO_LINK_MAX_PAYLOAD_LEN];
+//! let datagram = link.receive(&mut buf).await?;
+//! info!("from {}: {=[u8]}", datagram.source, &buf[..datagram.len]);
+//! ```
+
+use embassy_time::{Duration, Instant};
+
+use crate::HeaplessVec;
+
+/// Destination that every node accepts; never acknowledged
+pub const RADIO_BROADCAST_ADDRESS: u8 = 0xff;
+/// Destination, source, sequence number and flags
+pub const RADIO_LINK_HEADER_LEN: usize = 4;
+/// Largest frame handled, header included
+pub const RADIO_LINK_MAX_FRAME_LEN: usize = 255;
+pub const RADIO_LINK_MAX_PAYLOAD_LEN: usize = RADIO_LINK_MAX_FRAME_LEN - RADIO_LINK_HEADER_LEN;
+/// Senders whose last sequence number is remembered for duplicate detection
+const RECENT_SENDERS: usize = 8;
+
+const FLAG_ACK_REQUEST: u8 = 0x01;
+const FLAG_ACK: u8 = 0x02;
+
+/// A radio that sends and receives whole packets
+#[allow(async_fn_in_trait)]
+pub trait PacketRadio {
+    /// Largest packet the radio can carry
+    const MAX_PACKET_LEN: usize;
+
+    async fn transmit(&mut self, packet: &[u8]) -> Result<(), RadioLinkError>;
+
+    /// Next packet received within `timeout` (forever if `None`) into `buf`, returning its
+    /// length, or `None` on timeout. Corrupted packets are skipped.
+    async fn receive(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, RadioLinkError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum RadioLinkError {
+    #[error("Radio error")]
+    Radio,
+    #[error("Payload too large for the radio")]
+    PayloadTooLarge,
+    #[error("No acknowledgment after all retries")]
+    NoAck,
+}
+
+#[derive(Debug, Clone)]
+pub struct RadioLinkConfig {
+    /// How long to wait for an acknowledgment before retransmitting
+    pub ack_timeout: Duration,
+    /// Retransmissions after the first attempt
+    pub retries: u8,
+}
+
+impl Default for RadioLinkConfig {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_millis(500),
+            retries: 3,
+        }
+    }
+}
+
+/// A delivered datagram; the payload is in the caller's buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Datagram {
+    pub source: u8,
+    pub destination: u8,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    destination: u8,
+    source: u8,
+    sequence: u8,
+    flags: u8,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; RADIO_LINK_HEADER_LEN] {
+        [self.destination, self.source, self.sequence, self.flags]
+    }
+
+    fn from_bytes(frame: &[u8]) -> Option<Self> {
+        match frame {
+            [destination, source, sequence, flags, ..] => Some(Self {
+                destination: *destination,
+                source: *source,
+                sequence: *sequence,
+                flags: *flags,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct RadioLink<R: PacketRadio> {
+    radio: R,
+    address: u8,
+    config: RadioLinkConfig,
+    sequence: u8,
+    recent: HeaplessVec<(u8, u8), RECENT_SENDERS>,
+}
+
+impl<R: PacketRadio> RadioLink<R> {
+    pub fn new(radio: R, address: u8, config: RadioLinkConfig) -> Self {
+        Self {
+            radio,
+            address,
+            config,
+            sequence: 0,
+            recent: HeaplessVec::new(),
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Send `payload` to `destination`, waiting for its acknowledgment unless it is a
+    /// broadcast. Datagrams arriving meanwhile are dropped.
+    pub async fn send(&mut self, destination: u8, payload: &[u8]) -> Result<(), RadioLinkError> {
+        let wants_ack = destination != RADIO_BROADCAST_ADDRESS;
+        let flags = if wants_ack { FLAG_ACK_REQUEST } else { 0 };
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence = self.sequence;
+        let mut frame = [0u8; RADIO_LINK_MAX_FRAME_LEN];
+        let len = self.frame(&mut frame, destination, sequence, flags, payload)?;
+        if !wants_ack {
+            return self.radio.transmit(&frame[..len]).await;
+        }
+
+        let mut reply = [0u8; RADIO_LINK_MAX_FRAME_LEN];
+        for _ in 0..=self.config.retries {
+            self.radio.transmit(&frame[..len]).await?;
+            let deadline = Instant::now() + self.config.ack_timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let Some(reply_len) = self.radio.receive(&mut reply, Some(remaining)).await? else {
+                    break;
+                };
+                let is_ack = Header::from_bytes(&reply[..reply_len]).is_some_and(|h| {
+                    h.flags & FLAG_ACK != 0
+                        && h.destination == self.address
+                        && h.source == destination
+                        && h.sequence == sequence
+                });
+                if is_ack {
+                    return Ok(());
+                }
+            }
+        }
+        Err(RadioLinkError::NoAck)
+    }
+
+    /// Send `payload` once without asking for an acknowledgment.
+    pub async fn send_unacked(
+        &mut self,
+        destination: u8,
+        payload: &[u8],
+    ) -> Result<(), RadioLinkError> {
+        self.sequence = self.sequence.wrapping_add(1);
+        let mut frame = [0u8; RADIO_LINK_MAX_FRAME_LEN];
+        let len = self.frame(&mut frame, destination, self.sequence, 0, payload)?;
+        self.radio.transmit(&frame[..len]).await
+    }
+
+    /// Wait for the next new datagram for this node (or broadcast), acknowledging it if the
+    /// sender asked for that. The payload is copied into `buf` (truncated if too short).
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<Datagram, RadioLinkError> {
+        let mut frame = [0u8; RADIO_LINK_MAX_FRAME_LEN];
+        loop {
+            let Some(len) = self.radio.receive(&mut frame, None).await? else {
+                continue;
+            };
+            let Some(header) = Header::from_bytes(&frame[..len]) else {
+                continue;
+            };
+            let for_us =
+                header.destination == self.address || header.destination == RADIO_BROADCAST_ADDRESS;
+            if !for_us || header.flags & FLAG_ACK != 0 {
+                continue;
+            }
+            if header.flags & FLAG_ACK_REQUEST != 0 && header.destination == self.address {
+                let ack = Header {
+                    destination: header.source,
+                    source: self.address,
+                    sequence: header.sequence,
+                    flags: FLAG_ACK,
+                };
+                self.radio.transmit(&ack.to_bytes()).await?;
+            }
+            if !remember(&mut self.recent, header.source, header.sequence) {
+                continue;
+            }
+            let payload = &frame[RADIO_LINK_HEADER_LEN..len];
+            let copied = payload.len().min(buf.len());
+            buf[..copied].copy_from_slice(&payload[..copied]);
+            return Ok(Datagram {
+                source: header.source,
+                destination: header.destination,
+                len: copied,
+            });
+        }
+    }
+
+    fn frame(
+        &self,
+        frame: &mut [u8; RADIO_LINK_MAX_FRAME_LEN],
+        destination: u8,
+        sequence: u8,
+        flags: u8,
+        payload: &[u8],
+    ) -> Result<usize, RadioLinkError> {
+        let len = RADIO_LINK_HEADER_LEN + payload.len();
+        if len > R::MAX_PACKET_LEN.min(RADIO_LINK_MAX_FRAME_LEN) {
+            return Err(RadioLinkError::PayloadTooLarge);
+        }
+        let header = Header {
+            destination,
+            source: self.address,
+            sequence,
+            flags,
+        };
+        frame[..RADIO_LINK_HEADER_LEN].copy_from_slice(&header.to_bytes());
+        frame[RADIO_LINK_HEADER_LEN..len].copy_from_slice(payload);
+        Ok(len)
+    }
+}
+
+/// Record `sequence` as the latest from `source`; `false` if it was already the latest
+/// (a retransmission whose acknowledgment got lost).
+fn remember(recent: &mut HeaplessVec<(u8, u8), RECENT_SENDERS>, source: u8, sequence: u8) -> bool {
+    if let Some(entry) = recent.iter_mut().find(|(s, _)| *s == source) {
+        let is_new = entry.1 != sequence;
+        entry.1 = sequence;
+        return is_new;
+    }
+    if recent.len() == recent.capacity() {
+        recent.dequeue_front();
+    }
+    let _ = recent.push((source, sequence));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header {
+            destination: 1,
+            source: 2,
+            sequence: 200,
+            flags: FLAG_ACK_REQUEST,
+        };
+        assert_eq!(Header::from_bytes(&header.to_bytes()), Some(header));
+        assert_eq!(Header::from_bytes(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn drops_duplicates() {
+        let mut recent = HeaplessVec::new();
+        assert!(remember(&mut recent, 5, 1));
+        assert!(!remember(&mut recent, 5, 1));
+        assert!(remember(&mut recent, 5, 2));
+        for source in 10..10 + RECENT_SENDERS as u8 {
+            assert!(remember(&mut recent, source, 1));
+        }
+        // The oldest sender was forgotten
+        assert!(remember(&mut recent, 5, 2));
+    }
+}
@@ -0,0 +1,140 @@
+//! sntp.rs — SNTP client and a software wall clock kept in sync with it
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut clock = WallClock::new();
+//! clock.sync_sntp(wifi.stack, DEFAULT_SNTP_SERVER).await?;
+//! let now = clock.unix_time().unwrap();
+//! ```
+
+use embassy_net::Stack;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_time::{Duration, Instant, with_timeout};
+
+pub const DEFAULT_SNTP_SERVER: &str = "pool.ntp.org";
+
+const SNTP_PORT: u16 = 123;
+const SNTP_PACKET_LEN: usize = 48;
+/// LI = 0, version 4, mode 3 (client)
+const SNTP_CLIENT_HEADER: u8 = 0x23;
+/// Seconds between 1900-01-01 (NTP epoch) and 1970-01-01 (Unix epoch)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+const SNTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum SntpError {
+    #[error("Failed to resolve SNTP server")]
+    Dns,
+    #[error("UDP socket error")]
+    Socket,
+    #[error("SNTP server did not answer")]
+    Timeout,
+    #[error("Invalid SNTP response")]
+    InvalidResponse,
+    #[error("Clock has not been synchronized yet")]
+    NotSynced,
+}
+
+/// Query `server` once and return the current Unix time in microseconds.
+pub async fn sntp_query(stack: Stack<'_>, server: &str) -> Result<u64, SntpError> {
+    let address = *stack
+        .dns_query(server, DnsQueryType::A)
+        .await
+        .map_err(|_| SntpError::Dns)?
+        .first()
+        .ok_or(SntpError::Dns)?;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0u8; SNTP_PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0u8; SNTP_PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).map_err(|_| SntpError::Socket)?;
+
+    let mut packet = [0u8; SNTP_PACKET_LEN];
+    packet[0] = SNTP_CLIENT_HEADER;
+    let sent_at = Instant::now();
+    socket
+        .send_to(&packet, (address, SNTP_PORT))
+        .await
+        .map_err(|_| SntpError::Socket)?;
+
+    let (len, _) = with_timeout(SNTP_TIMEOUT, socket.recv_from(&mut packet))
+        .await
+        .map_err(|_| SntpError::Timeout)?
+        .map_err(|_| SntpError::Socket)?;
+    let round_trip = sent_at.elapsed();
+
+    let mode = packet[0] & 0x07;
+    let stratum = packet[1];
+    if len < SNTP_PACKET_LEN || mode != 4 || stratum == 0 {
+        return Err(SntpError::InvalidResponse);
+    }
+
+    // Transmit timestamp: 32-bit seconds + 32-bit fraction
+    let secs = u32::from_be_bytes([packet[40], packet[41], packet[42], packet[43]]) as u64;
+    let fraction = u32::from_be_bytes([packet[44], packet[45], packet[46], packet[47]]) as u64;
+    let unix_secs = secs
+        .checked_sub(NTP_UNIX_OFFSET)
+        .ok_or(SntpError::InvalidResponse)?;
+    let micros = unix_secs * 1_000_000 + ((fraction * 1_000_000) >> 32);
+
+    // The server stamped the reply roughly half a round trip ago.
+    Ok(micros + round_trip.as_micros() / 2)
+}
+
+/// Wall-clock time derived from the monotonic timer plus a reference point set by SNTP (or
+/// any other source).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallClock {
+    /// Unix time in microseconds at the reference instant
+    reference: Option<(u64, Instant)>,
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        Self { reference: None }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    /// Time since the last sync, or `None` if never synced.
+    pub fn since_sync(&self) -> Option<Duration> {
+        self.reference.map(|(_, at)| at.elapsed())
+    }
+
+    pub fn set_unix_micros(&mut self, micros: u64) {
+        self.reference = Some((micros, Instant::now()));
+    }
+
+    pub fn set_unix_time(&mut self, secs: u64) {
+        self.set_unix_micros(secs * 1_000_000);
+    }
+
+    pub fn unix_micros(&self) -> Option<u64> {
+        self.reference
+            .map(|(micros, at)| micros + at.elapsed().as_micros())
+    }
+
+    /// Seconds since the Unix epoch, or `None` until the first sync.
+    pub fn unix_time(&self) -> Option<u64> {
+        self.unix_micros().map(|micros| micros / 1_000_000)
+    }
+
+    /// Set the clock from an SNTP server.
+    pub async fn sync_sntp(&mut self, stack: Stack<'_>, server: &str) -> Result<(), SntpError> {
+        let micros = sntp_query(stack, server).await?;
+        self.set_unix_micros(micros);
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+//! telemetry_queue.rs — store-and-forward queue for MQTT/HTTP telemetry
+//!
+//! Producers [`push`](TelemetryQueue::push) payloads at any time; one drain task
+//! ([`TelemetryQueue::run`]) follows the WiFi link through an [`IpWatcher`] and, while an
+//! address is held, hands messages to a [`TelemetrySink`] oldest first. A failed send
+//! keeps the message at the head and is retried, so order is preserved across outages.
+//!
+//! Each message names a [`Delivery`]: [`Delivery::All`] messages (events, readings) are
+//! all kept; a [`Delivery::Latest`] message (state such as "pump on") replaces the
+//! queued message of the same topic, so a reconnect doesn't replay stale states. When the
+//! queue is full the oldest message is dropped and counted. Before a planned reboot or
+//! deep sleep the backlog can be saved to a [`KvStore`] and restored on the next boot.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static TELEMETRY: TelemetryQueue<32> = TelemetryQueue::new();
+//!
+//! // Sensor task
+//! TELEMETRY.push("greenhouse/temp", b"21.5", Delivery::All)?;
+//! TELEMETRY.push("greenhouse/pump", b"on", Delivery::Latest)?;
+//!
+//! // Uplink task
+//! let mut link = wifi.ip_watcher().unwrap();
+//! TELEMETRY.run(&mut mqtt, &mut link).await;
+//! ```
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::{HeaplessString, HeaplessVec, IpWatcher, KV_MAX_VALUE_LEN, KvStore, KvStoreError};
+
+pub const TELEMETRY_MAX_TOPIC_LEN: usize = 48;
+pub const TELEMETRY_MAX_PAYLOAD_LEN: usize = 192;
+
+/// Wait after a failed send before trying again
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Key prefix of saved messages
+const STORE_PREFIX: &str = "tq/";
+// Saved message: delivery, topic length, topic, payload
+const STORED_HEADER_LEN: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("Topic longer than TELEMETRY_MAX_TOPIC_LEN")]
+    TopicTooLong,
+    #[error("Payload longer than TELEMETRY_MAX_PAYLOAD_LEN")]
+    PayloadTooLong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Delivery {
+    /// Deliver every message
+    All,
+    /// Only the newest message of the topic matters
+    Latest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryMessage {
+    /// Queue order; increases with every push
+    pub seq: u32,
+    pub topic: HeaplessString<TELEMETRY_MAX_TOPIC_LEN>,
+    pub payload: HeaplessVec<u8, TELEMETRY_MAX_PAYLOAD_LEN>,
+    pub delivery: Delivery,
+}
+
+/// Where queued messages go, e.g. an MQTT client or an HTTP poster
+#[allow(async_fn_in_trait)]
+pub trait TelemetrySink {
+    type Error;
+
+    async fn send(&mut self, topic: &str, payload: &[u8]) -> Result<(), Self::Error>;
+}
+
+struct Backlog<const N: usize> {
+    messages: [Option<TelemetryMessage>; N],
+    next_seq: u32,
+    dropped: u32,
+}
+
+impl<const N: usize> Backlog<N> {
+    const fn new() -> Self {
+        Self {
+            messages: [const { None }; N],
+            next_seq: 0,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, mut message: TelemetryMessage) {
+        if message.delivery == Delivery::Latest {
+            for slot in self.messages.iter_mut() {
+                if slot.as_ref().is_some_and(|m| m.topic == message.topic) {
+                    *slot = None;
+                }
+            }
+        }
+        message.seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let slot = match self.messages.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.dropped = self.dropped.saturating_add(1);
+                self.oldest_index().unwrap_or(0)
+            }
+        };
+        if N > 0 {
+            self.messages[slot] = Some(message);
+        }
+    }
+
+    fn oldest_index(&self) -> Option<usize> {
+        // Sequence numbers wrap, so compare their distance from the next one
+        self.messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| m.as_ref().map(|m| (i, self.next_seq.wrapping_sub(m.seq))))
+            .max_by_key(|&(_, age)| age)
+            .map(|(i, _)| i)
+    }
+
+    fn front(&self) -> Option<TelemetryMessage> {
+        self.oldest_index().and_then(|i| self.messages[i].clone())
+    }
+
+    fn nth_oldest(&self, n: usize) -> Option<TelemetryMessage> {
+        let mut order: [(u32, usize); N] = core::array::from_fn(|i| {
+            let age = self.messages[i]
+                .as_ref()
+                .map_or(0, |m| self.next_seq.wrapping_sub(m.seq));
+            (age, i)
+        });
+        // Oldest first; empty slots (age 0) last
+        order.sort_unstable_by_key(|&(age, _)| core::cmp::Reverse(age));
+        order
+            .iter()
+            .filter_map(|&(_, i)| self.messages[i].as_ref())
+            .nth(n)
+            .cloned()
+    }
+
+    fn remove(&mut self, seq: u32) {
+        for slot in self.messages.iter_mut() {
+            if slot.as_ref().is_some_and(|m| m.seq == seq) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.messages.iter().flatten().count()
+    }
+}
+
+/// Up to `N` messages waiting for the network
+pub struct TelemetryQueue<const N: usize> {
+    backlog: Mutex<CriticalSectionRawMutex, RefCell<Backlog<N>>>,
+    queued: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl<const N: usize> TelemetryQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            backlog: Mutex::new(RefCell::new(Backlog::new())),
+            queued: Signal::new(),
+        }
+    }
+
+    /// Queue a message; drops the oldest one if the queue is full.
+    pub fn push(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        delivery: Delivery,
+    ) -> Result<(), TelemetryError> {
+        let message = TelemetryMessage {
+            seq: 0,
+            topic: topic.try_into().map_err(|_| TelemetryError::TopicTooLong)?,
+            payload: {
+                let mut buf = HeaplessVec::const_new();
+                buf.extend_from_slice(payload)
+                    .map_err(|_| TelemetryError::PayloadTooLong)?;
+                buf
+            },
+            delivery,
+        };
+        self.backlog.lock(|b| b.borrow_mut().push(message));
+        self.queued.signal(());
+        Ok(())
+    }
+
+    /// Messages waiting to be sent
+    pub fn len(&self) -> usize {
+        self.backlog.lock(|b| b.borrow().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Messages lost because the queue was full
+    pub fn dropped(&self) -> u32 {
+        self.backlog.lock(|b| b.borrow().dropped)
+    }
+
+    /// Oldest message, left in the queue
+    pub fn front(&self) -> Option<TelemetryMessage> {
+        self.backlog.lock(|b| b.borrow().front())
+    }
+
+    /// Remove a message once it has been delivered. A no-op if it was replaced meanwhile.
+    pub fn remove(&self, seq: u32) {
+        self.backlog.lock(|b| b.borrow_mut().remove(seq));
+    }
+
+    /// Deliver queued messages whenever `link` reports an address, forever.
+    pub async fn run<S: TelemetrySink>(&self, sink: &mut S, link: &mut IpWatcher) -> ! {
+        let mut online = false;
+        loop {
+            if !online {
+                online = link.changed().await.is_some();
+                continue;
+            }
+            let Some(message) = self.front() else {
+                if let Either::Second(address) = select(self.queued.wait(), link.changed()).await {
+                    online = address.is_some();
+                }
+                continue;
+            };
+            match sink
+                .send(message.topic.as_str(), message.payload.as_slice())
+                .await
+            {
+                Ok(()) => self.remove(message.seq),
+                Err(_) => {
+                    if let Either::Second(address) =
+                        select(Timer::after(RETRY_DELAY), link.changed()).await
+                    {
+                        online = address.is_some();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace any saved backlog in `store` with the current queue, e.g. before a
+    /// planned reboot; returns how many messages were saved.
+    pub fn save(&self, store: &mut impl KvStore) -> Result<usize, KvStoreError> {
+        store.remove_prefix(STORE_PREFIX)?;
+        let mut saved = 0;
+        while let Some(message) = self.backlog.lock(|b| b.borrow().nth_oldest(saved)) {
+            let mut value = [0u8; KV_MAX_VALUE_LEN];
+            let len = encode_message(&message, &mut value);
+            store.set(store_key(saved).as_str(), &value[..len])?;
+            saved += 1;
+        }
+        Ok(saved)
+    }
+
+    /// Queue the messages saved by [`save`](Self::save) behind anything already queued
+    /// and remove them from `store`; returns how many were restored.
+    pub fn restore(&self, store: &mut impl KvStore) -> Result<usize, KvStoreError> {
+        let mut restored = 0;
+        let mut value = [0u8; KV_MAX_VALUE_LEN];
+        while let Some(len) = store.get(store_key(restored).as_str(), &mut value)? {
+            if let Some(message) = decode_message(&value[..len]) {
+                self.backlog.lock(|b| b.borrow_mut().push(message));
+            }
+            restored += 1;
+        }
+        store.remove_prefix(STORE_PREFIX)?;
+        if restored > 0 {
+            self.queued.signal(());
+        }
+        Ok(restored)
+    }
+}
+
+impl<const N: usize> Default for TelemetryQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn store_key(index: usize) -> HeaplessString<8> {
+    let mut key = HeaplessString::new();
+    let _ = write!(key, "{STORE_PREFIX}{index}");
+    key
+}
+
+fn encode_message(message: &TelemetryMessage, out: &mut [u8; KV_MAX_VALUE_LEN]) -> usize {
+    let topic = message.topic.as_str().as_bytes();
+    let payload = message.payload.as_slice();
+    out[0] = (message.delivery == Delivery::Latest) as u8;
+    out[1] = topic.len() as u8;
+    let payload_start = STORED_HEADER_LEN + topic.len();
+    out[STORED_HEADER_LEN..payload_start].copy_from_slice(topic);
+    out[payload_start..payload_start + payload.len()].copy_from_slice(payload);
+    payload_start + payload.len()
+}
+
+fn decode_message(value: &[u8]) -> Option<TelemetryMessage> {
+    let (&[delivery, topic_len], rest) = value.split_first_chunk::<STORED_HEADER_LEN>()?;
+    let (topic, payload) = rest.split_at_checked(topic_len as usize)?;
+    let mut message = TelemetryMessage {
+        seq: 0,
+        topic: core::str::from_utf8(topic).ok()?.try_into().ok()?,
+        payload: HeaplessVec::const_new(),
+        delivery: if delivery == 1 {
+            Delivery::Latest
+        } else {
+            Delivery::All
+        },
+    };
+    message.payload.extend_from_slice(payload).ok()?;
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str, payload: &[u8], delivery: Delivery) -> TelemetryMessage {
+        let mut message = TelemetryMessage {
+            seq: 0,
+            topic: topic.try_into().unwrap(),
+            payload: HeaplessVec::const_new(),
+            delivery,
+        };
+        message.payload.extend_from_slice(payload).unwrap();
+        message
+    }
+
+    fn topics<const N: usize>(
+        backlog: &Backlog<N>,
+    ) -> [Option<HeaplessString<TELEMETRY_MAX_TOPIC_LEN>>; N] {
+        core::array::from_fn(|i| backlog.nth_oldest(i).map(|m| m.topic))
+    }
+
+    #[test]
+    fn keeps_order_and_replaces_latest_state() {
+        let mut backlog = Backlog::<4>::new();
+        backlog.push(message("temp", b"21", Delivery::All));
+        backlog.push(message("pump", b"on", Delivery::Latest));
+        backlog.push(message("temp", b"22", Delivery::All));
+        backlog.push(message("pump", b"off", Delivery::Latest));
+        assert_eq!(backlog.len(), 3);
+        let front = backlog.front().unwrap();
+        assert_eq!(front.payload.as_slice(), b"21");
+        backlog.remove(front.seq);
+        assert_eq!(backlog.front().unwrap().payload.as_slice(), b"22");
+        assert_eq!(backlog.nth_oldest(1).unwrap().payload.as_slice(), b"off");
+        assert_eq!(backlog.dropped, 0);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest() {
+        let mut backlog = Backlog::<2>::new();
+        for topic in ["a", "b", "c"] {
+            backlog.push(message(topic, b"", Delivery::All));
+        }
+        assert_eq!(backlog.dropped, 1);
+        let topics = topics(&backlog);
+        assert_eq!(topics[0].as_ref().map(|t| t.as_str()), Some("b"));
+        assert_eq!(topics[1].as_ref().map(|t| t.as_str()), Some("c"));
+    }
+
+    #[test]
+    fn order_survives_sequence_wrap() {
+        let mut backlog = Backlog::<3>::new();
+        backlog.next_seq = u32::MAX;
+        backlog.push(message("first", b"", Delivery::All));
+        backlog.push(message("second", b"", Delivery::All));
+        assert_eq!(backlog.front().unwrap().topic.as_str(), "first");
+    }
+
+    #[test]
+    fn stored_message_roundtrip() {
+        let original = message("greenhouse/pump", b"on", Delivery::Latest);
+        let mut value = [0u8; KV_MAX_VALUE_LEN];
+        let len = encode_message(&original, &mut value);
+        assert_eq!(decode_message(&value[..len]), Some(original));
+        assert_eq!(decode_message(&[0, 9, b'a']), None);
+    }
+}
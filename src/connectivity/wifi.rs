@@ -1,19 +1,62 @@
-use cyw43_pio::{DEFAULT_CLOCK_DIVIDER, PioSpi};
-use defmt::warn;
-use embassy_net::{Stack, StackResources};
-use embassy_rp::gpio::Output;
+//! wifi.rs — CYW43 bring-up, network stack and power-down for the Pico W (and CYW43 boards)
+//!
+//! The CYW43 driver runs one interface at a time, so station and access point are exclusive.
+//! [`WifiManager::switch_to_ap`] and [`WifiManager::switch_to_station`] tear the current
+//! mode down and reconfigure the network stack (static address for the AP, the configured
+//! station setup, usually DHCP, otherwise), e.g. to provision over the AP and then join.
+//!
+//! A monitor task publishes the IPv4 address whenever it changes (DHCP lease, link loss,
+//! mode switch). [`WifiManager::wait_ip_changed`] waits for that; service tasks (mDNS, MQTT,
+//! the HTTP server) take their own [`IpWatcher`] to re-bind or re-announce.
+//!
+//! [`WifiManager::shutdown`] stops the driver tasks, powers the chip off and gives the pins
+//! back, so WiFi can be switched off for low-power periods and brought up again later with
+//! [`WifiManager::init_wifi`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut wifi = WifiManager::init_wifi(pins, Irqs, config, spawner).await;
+//! wifi.join_network(SSID, PASSWORD).await;
+//! publish(wifi.stack).await;
+//!
+//! // SAFETY: no copy of `wifi.stack` (or socket on it) outlives the shutdown.
+//! let pins = unsafe { wifi.shutdown() }.await;
+//! Timer::after_secs(3600).await;
+//! let mut wifi = WifiManager::init_wifi(pins, Irqs, config, spawner).await;
+//! ```
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::mem::MaybeUninit;
+
+use cyw43_pio::PioSpi;
+use embassy_futures::select::select;
+use embassy_net::{ConfigV4, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
+use embassy_rp::gpio::{Level, Output, Pin};
 use embassy_rp::interrupt::typelevel::Binding;
-use embassy_rp::pio::{InterruptHandler, Pio};
-use static_cell::StaticCell;
+use embassy_rp::peripherals::{PIN_23, PIN_24, PIN_25, PIN_29};
+use embassy_rp::pio::{InterruptHandler, Pio, PioPin};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::{Receiver, Watch};
+use embassy_time::{Duration, Timer, with_timeout};
+use portable_atomic::{AtomicBool, Ordering};
+
+use crate::{LogModule, cyw43_clock_divider};
 
 const CYW43_FW: &[u8] = include_bytes!("./cyw43-firmware/43439A0.bin");
 const CYW43_CLM: &[u8] = include_bytes!("./cyw43-firmware/43439A0_clm.bin");
 
-pub struct WifiPins {
-    pub pwr: embassy_rp::Peri<'static, embassy_rp::peripherals::PIN_23>,
-    pub cs: embassy_rp::Peri<'static, embassy_rp::peripherals::PIN_25>,
-    pub clk: embassy_rp::Peri<'static, embassy_rp::peripherals::PIN_29>,
-    pub dio: embassy_rp::Peri<'static, embassy_rp::peripherals::PIN_24>,
+/// CYW43 wiring. The pin types default to the Pico W (GP23 power, GP25 chip select, GP29
+/// clock, GP24 data); boards or modules wired differently name their own pins and use
+/// [`WifiManager::init_wifi_with_pins`].
+pub struct WifiPins<PWR: Pin = PIN_23, CS: Pin = PIN_25, CLK: PioPin = PIN_29, DIO: PioPin = PIN_24>
+{
+    pub pwr: embassy_rp::Peri<'static, PWR>,
+    pub cs: embassy_rp::Peri<'static, CS>,
+    pub clk: embassy_rp::Peri<'static, CLK>,
+    pub dio: embassy_rp::Peri<'static, DIO>,
     pub pio: embassy_rp::Peri<'static, embassy_rp::peripherals::PIO0>,
     pub dma: embassy_rp::Peri<'static, embassy_rp::peripherals::DMA_CH0>,
 }
@@ -29,18 +72,68 @@ pub struct PioKeepalive<'a> {
     _sm3: embassy_rp::pio::StateMachine<'a, embassy_rp::peripherals::PIO0, 3>,
 }
 
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum WifiError {
+    #[error("Failed to join network")]
+    JoinFailed,
+    #[error("No IP configuration received")]
+    ConfigTimeout,
+}
+
 pub struct WifiConfig {
     pub power_mode: cyw43::PowerManagementMode,
     pub stack_config: embassy_net::Config,
 }
 
-pub struct WifiManager {
+/// Most [`IpWatcher`]s alive at once, including the one inside [`WifiManager`]
+pub const IP_WATCHERS: usize = 4;
+/// How long [`WifiManager::try_join`] waits for the network configuration (DHCP)
+const CONFIG_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often the monitor checks for a new address while the link is up
+const IP_POLL_PERIOD: Duration = Duration::from_secs(2);
+
+/// Receives the current IPv4 address (`None` while unconfigured) whenever it changes
+pub type IpWatcher = Receiver<'static, CriticalSectionRawMutex, Option<Ipv4Cidr>, IP_WATCHERS>;
+
+static IP_ADDRESS: Watch<CriticalSectionRawMutex, Option<Ipv4Cidr>, IP_WATCHERS> = Watch::new();
+
+/// What the radio is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum WifiMode {
+    Idle,
+    Station,
+    AccessPoint,
+}
+
+/// Driver state that survives a [`WifiManager::shutdown`] so WiFi can be brought up again
+static CYW43_STATE: ReusableCell<cyw43::State> = ReusableCell::new();
+static NET_RESOURCES: ReusableCell<StackResources<6>> = ReusableCell::new();
+
+static CYW43_TASK: TaskControl = TaskControl::new();
+static NET_TASK: TaskControl = TaskControl::new();
+static IP_TASK: TaskControl = TaskControl::new();
+
+pub struct WifiManager<
+    PWR: Pin = PIN_23,
+    CS: Pin = PIN_25,
+    CLK: PioPin = PIN_29,
+    DIO: PioPin = PIN_24,
+> {
     pub control: cyw43::Control<'static>,
     pub stack: Stack<'static>,
     _pio_keepalive: PioKeepalive<'static>,
+    mode: WifiMode,
+    /// IPv4 setup from [`WifiConfig`], restored when switching back to station mode
+    station_ipv4: ConfigV4,
+    ip_watcher: IpWatcher,
+    /// Handed back by [`WifiManager::shutdown`]; the drivers use copies until then
+    pins: WifiPins<PWR, CS, CLK, DIO>,
 }
 
 impl WifiManager {
+    /// Bring up WiFi on a Pico W; the pin types make sure it is wired like the official
+    /// board.
+    #[cfg(feature = "pico-w")]
     pub async fn init_wifi(
         pins: WifiPins,
         irqs: impl Binding<
@@ -50,21 +143,53 @@ impl WifiManager {
         config: WifiConfig,
         spawner: embassy_executor::Spawner,
     ) -> WifiManager {
+        Self::init_wifi_with_pins(pins, irqs, config, spawner).await
+    }
+}
+
+impl<PWR: Pin, CS: Pin, CLK: PioPin, DIO: PioPin> WifiManager<PWR, CS, CLK, DIO> {
+    /// Bring up WiFi on a board with the CYW43 on any GPIOs (still PIO0 and DMA channel 0).
+    pub async fn init_wifi_with_pins(
+        pins: WifiPins<PWR, CS, CLK, DIO>,
+        irqs: impl Binding<
+            embassy_rp::interrupt::typelevel::PIO0_IRQ_0,
+            InterruptHandler<embassy_rp::peripherals::PIO0>,
+        >,
+        config: WifiConfig,
+        spawner: embassy_executor::Spawner,
+    ) -> Self {
+        CYW43_TASK.reset();
+        NET_TASK.reset();
+        IP_TASK.reset();
+
+        // SAFETY: the drivers get copies of the pins and are all dropped (tasks stopped,
+        // keepalive dropped) before `shutdown` hands the originals back.
+        let (pwr, cs, clk, dio, pio, dma) = unsafe {
+            (
+                pins.pwr.clone_unchecked(),
+                pins.cs.clone_unchecked(),
+                pins.clk.clone_unchecked(),
+                pins.dio.clone_unchecked(),
+                pins.pio.clone_unchecked(),
+                pins.dma.clone_unchecked(),
+            )
+        };
+
         // Create WiFi control pins from peripherals
-        let pwr = Output::new(pins.pwr, embassy_rp::gpio::Level::Low);
-        let cs = Output::new(pins.cs, embassy_rp::gpio::Level::High);
+        let pwr = Output::new(pwr, Level::Low);
+        let cs = Output::new(cs, Level::High);
 
         // 1. Initialize CYW43 WiFi chip
-        let mut pio = Pio::new(pins.pio, irqs);
+        let mut pio = Pio::new(pio, irqs);
         let spi = PioSpi::new(
             &mut pio.common,
             pio.sm0,
-            DEFAULT_CLOCK_DIVIDER,
+            cyw43_clock_divider(),
             pio.irq0,
             cs,
-            pins.dio,
-            pins.clk,
-            pins.dma,
+            dio,
+            clk,
+            dma,
         );
         let pio_keepalive = PioKeepalive {
             _common: pio.common,
@@ -77,8 +202,7 @@ impl WifiManager {
             _sm3: pio.sm3,
         };
 
-        static STATE: StaticCell<cyw43::State> = StaticCell::new();
-        let state = STATE.init(cyw43::State::new());
+        let state = CYW43_STATE.init(cyw43::State::new());
         let (net_device, mut control, runner) = cyw43::new(state, pwr, spi, CYW43_FW).await;
 
         spawner.spawn(cyw43_runner_task(runner).expect("failed to spawn cyw43_runner_task"));
@@ -90,22 +214,69 @@ impl WifiManager {
         let mut rng = embassy_rp::clocks::RoscRng;
         let seed = rng.next_u64();
 
-        static RESOURCES: StaticCell<StackResources<6>> = StaticCell::new();
+        let station_ipv4 = config.stack_config.ipv4.clone();
         let (stack, runner) = embassy_net::new(
             net_device,
             config.stack_config,
-            RESOURCES.init(StackResources::new()),
+            NET_RESOURCES.init(StackResources::new()),
             seed,
         );
 
         // Spawn the network runner task
         spawner.spawn(net_runner_task(runner).expect("failed to spawn net_runner_task"));
+        spawner.spawn(ip_monitor_task(stack).expect("failed to spawn ip_monitor_task"));
 
         WifiManager {
             control,
             stack,
             _pio_keepalive: pio_keepalive,
+            mode: WifiMode::Idle,
+            station_ipv4,
+            ip_watcher: IP_ADDRESS
+                .receiver()
+                .expect("too many IP watchers for WifiManager"),
+            pins,
+        }
+    }
+
+    /// Stop the driver tasks, power the CYW43 off and return the pins (and PIO0, DMA
+    /// channel 0) for a later re-init or other use.
+    ///
+    /// # Safety
+    ///
+    /// The network stack's storage is reused by the next [`WifiManager::init_wifi`], so
+    /// every copy of [`WifiManager::stack`] handed out before, and every socket or other
+    /// value borrowing from one, must be dropped before this is called and never used
+    /// again. A copy kept past the shutdown would alias the next stack.
+    pub async unsafe fn shutdown(self) -> WifiPins<PWR, CS, CLK, DIO> {
+        let WifiManager {
+            control,
+            _pio_keepalive,
+            mut pins,
+            ..
+        } = self;
+
+        // The net runner uses the CYW43 driver channel, so it goes first. Dropping the CYW43
+        // runner releases the PIO state machine, DMA channel and the PWR/CS outputs.
+        IP_TASK.stop().await;
+        IP_ADDRESS.sender().send(None);
+        NET_TASK.stop().await;
+        CYW43_TASK.stop().await;
+        drop(control);
+        drop(_pio_keepalive);
+
+        // Hold WL_ON low long enough for the chip to power down. Dropping the output resets
+        // the pad, whose default pull-down keeps it low.
+        let _pwr = Output::new(pins.pwr.reborrow(), Level::Low);
+        Timer::after_millis(10).await;
+
+        // SAFETY: the tasks, control and keepalive that referenced the driver state were
+        // dropped above; the caller guarantees no copy of the stack is left.
+        unsafe {
+            NET_RESOURCES.release();
+            CYW43_STATE.release();
         }
+        pins
     }
 
     pub async fn join_network(&mut self, wifi_ssid: &str, wifi_password: &str) {
@@ -117,18 +288,84 @@ impl WifiManager {
             {
                 Ok(()) => break,
                 Err(_) => {
-                    warn!("WiFi join failed, retrying...");
+                    crate::log!(LogModule::Wifi, Warn, "WiFi join failed, retrying...");
                 }
             }
         }
+        self.mode = WifiMode::Station;
         self.stack.wait_link_up().await;
         self.stack.wait_config_up().await;
     }
 
+    /// Join `ssid` once and wait for the IP configuration, without retrying.
+    pub async fn try_join(&mut self, ssid: &str, password: &str) -> Result<(), WifiError> {
+        self.control
+            .join(ssid, cyw43::JoinOptions::new(password.as_bytes()))
+            .await
+            .map_err(|_| WifiError::JoinFailed)?;
+        self.mode = WifiMode::Station;
+        with_timeout(CONFIG_TIMEOUT, self.stack.wait_config_up())
+            .await
+            .map_err(|_| WifiError::ConfigTimeout)
+    }
+
     pub async fn start_ap_wpa2(&mut self, ap_ssid: &str, ap_password: &str, channel: u8) {
         self.control
             .start_ap_wpa2(ap_ssid, ap_password, channel)
             .await;
+        self.mode = WifiMode::AccessPoint;
+    }
+
+    /// Wait until the IPv4 address differs from the one the previous call returned, and
+    /// return it (`None`: lost). The first call returns the current address if there is one.
+    pub async fn wait_ip_changed(&mut self) -> Option<Ipv4Cidr> {
+        self.ip_watcher.changed().await
+    }
+
+    /// A receiver of address changes for another task; `None` if [`IP_WATCHERS`] are
+    /// already taken.
+    pub fn ip_watcher(&self) -> Option<IpWatcher> {
+        IP_ADDRESS.receiver()
+    }
+
+    pub fn mode(&self) -> WifiMode {
+        self.mode
+    }
+
+    /// Leave the network or close the access point.
+    pub async fn disconnect(&mut self) {
+        match self.mode {
+            WifiMode::Idle => {}
+            WifiMode::Station => self.control.leave().await,
+            WifiMode::AccessPoint => self.control.close_ap().await,
+        }
+        self.mode = WifiMode::Idle;
+    }
+
+    /// Stop whatever runs now, restore the station IP setup and join `ssid` (retrying
+    /// until it succeeds, like [`WifiManager::join_network`]).
+    pub async fn switch_to_station(&mut self, ssid: &str, password: &str) {
+        self.disconnect().await;
+        self.stack.set_config_v4(self.station_ipv4.clone());
+        self.join_network(ssid, password).await;
+    }
+
+    /// Stop whatever runs now and start a WPA2 access point at `address` (e.g.
+    /// `192.168.4.1/24`). Clients need a static address unless a DHCP server runs on it.
+    pub async fn switch_to_ap(
+        &mut self,
+        ssid: &str,
+        password: &str,
+        channel: u8,
+        address: Ipv4Cidr,
+    ) {
+        self.disconnect().await;
+        self.stack.set_config_v4(ConfigV4::Static(StaticConfigV4 {
+            address,
+            gateway: None,
+            dns_servers: Default::default(),
+        }));
+        self.start_ap_wpa2(ssid, password, channel).await;
     }
 }
 
@@ -139,11 +376,100 @@ async fn cyw43_runner_task(
         Output<'static>,
         PioSpi<'static, embassy_rp::peripherals::PIO0, 0, embassy_rp::peripherals::DMA_CH0>,
     >,
-) -> ! {
-    runner.run().await
+) {
+    CYW43_TASK.run_until_stopped(runner.run()).await;
+}
+
+#[embassy_executor::task]
+async fn net_runner_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'static>>) {
+    NET_TASK.run_until_stopped(runner.run()).await;
 }
 
 #[embassy_executor::task]
-async fn net_runner_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'static>>) -> ! {
-    runner.run().await
+async fn ip_monitor_task(stack: Stack<'static>) {
+    IP_TASK.run_until_stopped(monitor_ip(stack)).await;
+}
+
+async fn monitor_ip(stack: Stack<'static>) -> ! {
+    let sender = IP_ADDRESS.sender();
+    let mut last = None;
+    loop {
+        let address = stack.config_v4().map(|config| config.address);
+        if address != last {
+            sender.send(address);
+            last = address;
+        }
+        // Losing or gaining the config wakes us at once; a lease renewed with a different
+        // address is picked up by polling.
+        if address.is_some() {
+            select(stack.wait_config_down(), Timer::after(IP_POLL_PERIOD)).await;
+        } else {
+            stack.wait_config_up().await;
+        }
+    }
+}
+
+/// Lets [`WifiManager::shutdown`] end a runner task and wait until it is gone
+struct TaskControl {
+    stop: Signal<CriticalSectionRawMutex, ()>,
+    stopped: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl TaskControl {
+    const fn new() -> Self {
+        Self {
+            stop: Signal::new(),
+            stopped: Signal::new(),
+        }
+    }
+
+    fn reset(&self) {
+        self.stop.reset();
+        self.stopped.reset();
+    }
+
+    async fn run_until_stopped(&self, task: impl Future) {
+        select(task, self.stop.wait()).await;
+        self.stopped.signal(());
+    }
+
+    async fn stop(&self) {
+        self.stop.signal(());
+        self.stopped.wait().await;
+    }
+}
+
+/// Like `StaticCell`, but can be initialized again once the previous value is unused
+struct ReusableCell<T> {
+    in_use: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: `in_use` hands out at most one reference at a time.
+unsafe impl<T> Sync for ReusableCell<T> {}
+
+impl<T> ReusableCell<T> {
+    const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    fn init(&'static self, value: T) -> &'static mut T {
+        assert!(
+            !self.in_use.swap(true, Ordering::AcqRel),
+            "WiFi is already initialized"
+        );
+        // SAFETY: no other reference exists while `in_use` was false.
+        unsafe { (*self.value.get()).write(value) }
+    }
+
+    /// # Safety
+    ///
+    /// The reference returned by the last [`ReusableCell::init`] (and anything borrowing
+    /// from it) must not be used again.
+    unsafe fn release(&'static self) {
+        self.in_use.store(false, Ordering::Release);
+    }
 }
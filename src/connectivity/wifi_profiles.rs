@@ -0,0 +1,494 @@
+//! wifi_profiles.rs — known networks with priorities, and roaming between them
+//!
+//! [`WifiProfiles`] keeps up to `N` networks (SSID, password, priority) in the encrypted
+//! [`CredentialStore`] under `wifi0`, `wifi1`, ... [`Roamer`] connects to the best visible
+//! one and rescans in the background: slowly while the signal is good, faster once it drops
+//! below [`RoamingPolicy::weak_rssi`]. It only moves to another known network when that one
+//! is at least [`RoamingPolicy::hysteresis_db`] stronger, so two similar APs don't cause
+//! flapping.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut creds = CredentialStore::for_device(&mut store, &id, device_secret!());
+//! let mut profiles = WifiProfiles::<4>::load(&mut creds)?;
+//! profiles.add(WifiProfile::new("home", "hunter22", 10)?)?;
+//! profiles.add(WifiProfile::new("garage", "hunter23", 5)?)?;
+//! profiles.save(&mut creds)?;
+//!
+//! let mut roamer = Roamer::new(profiles, RoamingPolicy::default());
+//! roamer.run(&mut wifi).await;
+//! ```
+
+use core::fmt::{self, Write};
+
+use embassy_rp::gpio::Pin;
+use embassy_rp::pio::PioPin;
+use embassy_time::{Duration, Timer};
+
+use crate::{
+    CredentialError, CredentialStore, HeaplessString, HeaplessVec, KvStore, LogModule, WifiManager,
+};
+
+/// Longest SSID in bytes
+pub const WIFI_MAX_SSID_LEN: usize = 32;
+/// Longest WPA2 passphrase in bytes
+pub const WIFI_MAX_PASSWORD_LEN: usize = 63;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum WifiProfileError {
+    #[error("Credential store error: {0}")]
+    Credentials(#[from] CredentialError),
+    #[error("SSID must be 1 to 32 bytes long")]
+    InvalidSsid,
+    #[error("Password must be at most 63 bytes long")]
+    InvalidPassword,
+    #[error("No room for another profile")]
+    Full,
+    #[error("Stored profile is corrupt")]
+    Corrupt,
+}
+
+/// One known network; higher `priority` is preferred. Debug and defmt output leave the
+/// password out.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct WifiProfile {
+    pub ssid: HeaplessString<WIFI_MAX_SSID_LEN>,
+    pub password: HeaplessString<WIFI_MAX_PASSWORD_LEN>,
+    pub priority: u8,
+}
+
+impl fmt::Debug for WifiProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WifiProfile")
+            .field("ssid", &self.ssid.as_str())
+            .field("password", &"<redacted>")
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+impl defmt::Format for WifiProfile {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "WifiProfile {{ ssid: {=str}, password: <redacted>, priority: {=u8} }}",
+            self.ssid.as_str(),
+            self.priority
+        );
+    }
+}
+
+impl WifiProfile {
+    pub fn new(ssid: &str, password: &str, priority: u8) -> Result<Self, WifiProfileError> {
+        if ssid.is_empty() {
+            return Err(WifiProfileError::InvalidSsid);
+        }
+        Ok(Self {
+            ssid: HeaplessString::try_from(ssid).map_err(|_| WifiProfileError::InvalidSsid)?,
+            password: HeaplessString::try_from(password)
+                .map_err(|_| WifiProfileError::InvalidPassword)?,
+            priority,
+        })
+    }
+
+    /// `priority`, SSID length, SSID, password
+    fn to_bytes(&self, buf: &mut [u8; 2 + WIFI_MAX_SSID_LEN + WIFI_MAX_PASSWORD_LEN]) -> usize {
+        let ssid = self.ssid.as_str().as_bytes();
+        let password = self.password.as_str().as_bytes();
+        buf[0] = self.priority;
+        buf[1] = ssid.len() as u8;
+        buf[2..2 + ssid.len()].copy_from_slice(ssid);
+        let end = 2 + ssid.len() + password.len();
+        buf[2 + ssid.len()..end].copy_from_slice(password);
+        end
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WifiProfileError> {
+        let [priority, ssid_len, rest @ ..] = bytes else {
+            return Err(WifiProfileError::Corrupt);
+        };
+        let ssid_len = *ssid_len as usize;
+        if rest.len() < ssid_len {
+            return Err(WifiProfileError::Corrupt);
+        }
+        let (ssid, password) = rest.split_at(ssid_len);
+        let ssid = core::str::from_utf8(ssid).map_err(|_| WifiProfileError::Corrupt)?;
+        let password = core::str::from_utf8(password).map_err(|_| WifiProfileError::Corrupt)?;
+        Self::new(ssid, password, *priority).map_err(|_| WifiProfileError::Corrupt)
+    }
+}
+
+/// Up to `N` known networks
+#[derive(Debug, Clone, Default)]
+pub struct WifiProfiles<const N: usize> {
+    profiles: HeaplessVec<WifiProfile, N>,
+}
+
+impl<const N: usize> WifiProfiles<N> {
+    pub fn new() -> Self {
+        Self {
+            profiles: HeaplessVec::new(),
+        }
+    }
+
+    /// Read the stored profiles; missing ones are simply absent.
+    pub fn load<S: KvStore>(creds: &mut CredentialStore<'_, S>) -> Result<Self, WifiProfileError> {
+        let mut profiles = Self::new();
+        let mut buf = [0u8; 2 + WIFI_MAX_SSID_LEN + WIFI_MAX_PASSWORD_LEN];
+        for slot in 0..N {
+            if let Some(len) = creds.get(slot_name(slot).as_str(), &mut buf)? {
+                profiles.add(WifiProfile::from_bytes(&buf[..len])?)?;
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// Store all profiles, removing stored ones beyond the current count.
+    pub fn save<S: KvStore>(
+        &self,
+        creds: &mut CredentialStore<'_, S>,
+    ) -> Result<(), WifiProfileError> {
+        let mut buf = [0u8; 2 + WIFI_MAX_SSID_LEN + WIFI_MAX_PASSWORD_LEN];
+        for slot in 0..N {
+            let name = slot_name(slot);
+            match self.profiles.get(slot) {
+                Some(profile) => {
+                    let len = profile.to_bytes(&mut buf);
+                    creds.set(name.as_str(), &buf[..len])?;
+                }
+                None => creds.remove(name.as_str())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a profile, replacing the one with the same SSID.
+    pub fn add(&mut self, profile: WifiProfile) -> Result<(), WifiProfileError> {
+        if let Some(existing) = self.profiles.iter_mut().find(|p| p.ssid == profile.ssid) {
+            *existing = profile;
+            return Ok(());
+        }
+        self.profiles
+            .push(profile)
+            .map_err(|_| WifiProfileError::Full)
+    }
+
+    pub fn remove(&mut self, ssid: &str) {
+        self.profiles
+            .retain(|profile| profile.ssid.as_str() != ssid);
+    }
+
+    pub fn get(&self, index: usize) -> Option<&WifiProfile> {
+        self.profiles.get(index)
+    }
+
+    pub fn find(&self, ssid: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.ssid.as_str() == ssid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WifiProfile> {
+        self.profiles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+}
+
+/// When to scan and when to move to another network
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct RoamingPolicy {
+    /// Below this signal (dBm) the current network counts as weak
+    pub weak_rssi: i16,
+    /// How much stronger (dB) another network must be to roam to it
+    pub hysteresis_db: i16,
+    /// Rescan period while the signal is good
+    pub scan_interval: Duration,
+    /// Rescan period while the signal is weak or there is no connection
+    pub weak_scan_interval: Duration,
+}
+
+impl Default for RoamingPolicy {
+    fn default() -> Self {
+        Self {
+            weak_rssi: -70,
+            hysteresis_db: 8,
+            scan_interval: Duration::from_secs(60),
+            weak_scan_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A known network seen in a scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+struct Candidate {
+    profile: usize,
+    priority: u8,
+    rssi: i16,
+}
+
+/// Keeps the connection on the best known network
+pub struct Roamer<const N: usize> {
+    profiles: WifiProfiles<N>,
+    policy: RoamingPolicy,
+    current: Option<usize>,
+}
+
+impl<const N: usize> Roamer<N> {
+    pub fn new(profiles: WifiProfiles<N>, policy: RoamingPolicy) -> Self {
+        Self {
+            profiles,
+            policy,
+            current: None,
+        }
+    }
+
+    pub fn profiles(&self) -> &WifiProfiles<N> {
+        &self.profiles
+    }
+
+    /// The network joined last, if still connected as far as the roamer knows
+    pub fn current(&self) -> Option<&WifiProfile> {
+        self.current.and_then(|index| self.profiles.get(index))
+    }
+
+    /// Scan, connect or roam as needed, and wait for the next scan, forever.
+    pub async fn run<PWR: Pin, CS: Pin, CLK: PioPin, DIO: PioPin>(
+        &mut self,
+        wifi: &mut WifiManager<PWR, CS, CLK, DIO>,
+    ) -> ! {
+        loop {
+            let weak = self.step(wifi).await;
+            let period = if weak {
+                self.policy.weak_scan_interval
+            } else {
+                self.policy.scan_interval
+            };
+            Timer::after(period).await;
+        }
+    }
+
+    /// One scan and decision. Returns whether the connection is weak or missing.
+    pub async fn step<PWR: Pin, CS: Pin, CLK: PioPin, DIO: PioPin>(
+        &mut self,
+        wifi: &mut WifiManager<PWR, CS, CLK, DIO>,
+    ) -> bool {
+        if !wifi.stack.is_link_up() {
+            self.current = None;
+        }
+        let visible = self.scan(wifi).await;
+        let current = self.current.map(|profile| {
+            visible
+                .iter()
+                .find(|candidate| candidate.profile == profile)
+                .copied()
+                .unwrap_or(Candidate {
+                    profile,
+                    priority: 0,
+                    rssi: i16::MIN,
+                })
+        });
+
+        if let Some(next) = choose_network(&self.policy, current, &visible) {
+            let previous = self.current.take();
+            if previous.is_some() {
+                wifi.disconnect().await;
+            }
+            if self.join(wifi, next).await {
+                return false;
+            }
+            // A weak network beats none: go back to it if the roam failed
+            if let Some(previous) = previous {
+                self.join(wifi, previous).await;
+            }
+        }
+        self.current.is_none() || current.is_none_or(|current| current.rssi < self.policy.weak_rssi)
+    }
+
+    /// Join the network of profile `index`, making it the current one on success.
+    async fn join<PWR: Pin, CS: Pin, CLK: PioPin, DIO: PioPin>(
+        &mut self,
+        wifi: &mut WifiManager<PWR, CS, CLK, DIO>,
+        index: usize,
+    ) -> bool {
+        let profile = &self.profiles.profiles[index];
+        crate::log!(
+            LogModule::Wifi,
+            Info,
+            "WiFi: joining {}",
+            profile.ssid.as_str()
+        );
+        match wifi
+            .try_join(profile.ssid.as_str(), profile.password.as_str())
+            .await
+        {
+            Ok(()) => {
+                self.current = Some(index);
+                true
+            }
+            Err(e) => {
+                crate::log!(
+                    LogModule::Wifi,
+                    Warn,
+                    "WiFi: joining {} failed: {}",
+                    profile.ssid.as_str(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Strongest sighting of each known network
+    async fn scan<PWR: Pin, CS: Pin, CLK: PioPin, DIO: PioPin>(
+        &self,
+        wifi: &mut WifiManager<PWR, CS, CLK, DIO>,
+    ) -> HeaplessVec<Candidate, N> {
+        let mut visible: HeaplessVec<Candidate, N> = HeaplessVec::new();
+        let mut scanner = wifi.control.scan(Default::default()).await;
+        while let Some(bss) = scanner.next().await {
+            let len = (bss.ssid_len as usize).min(bss.ssid.len());
+            let Ok(ssid) = core::str::from_utf8(&bss.ssid[..len]) else {
+                continue;
+            };
+            let Some(profile) = self.profiles.find(ssid) else {
+                continue;
+            };
+            match visible.iter_mut().find(|c| c.profile == profile) {
+                Some(seen) => seen.rssi = seen.rssi.max(bss.rssi),
+                None => {
+                    let _ = visible.push(Candidate {
+                        profile,
+                        priority: self.profiles.profiles[profile].priority,
+                        rssi: bss.rssi,
+                    });
+                }
+            }
+        }
+        visible
+    }
+}
+
+fn slot_name(slot: usize) -> HeaplessString<8> {
+    let mut name = HeaplessString::new();
+    let _ = write!(name, "wifi{}", slot);
+    name
+}
+
+/// The network to join, or `None` to stay. Without a connection that's the visible network
+/// with the highest priority (then signal); with a weak one, only networks at least the
+/// hysteresis stronger than it qualify.
+fn choose_network(
+    policy: &RoamingPolicy,
+    current: Option<Candidate>,
+    visible: &[Candidate],
+) -> Option<usize> {
+    let threshold = match current {
+        None => i16::MIN,
+        Some(current) if current.rssi >= policy.weak_rssi => return None,
+        Some(current) => current.rssi.saturating_add(policy.hysteresis_db),
+    };
+    visible
+        .iter()
+        .filter(|candidate| Some(candidate.profile) != current.map(|c| c.profile))
+        .filter(|candidate| candidate.rssi >= threshold)
+        .max_by_key(|candidate| (candidate.priority, candidate.rssi))
+        .map(|candidate| candidate.profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(profile: usize, priority: u8, rssi: i16) -> Candidate {
+        Candidate {
+            profile,
+            priority,
+            rssi,
+        }
+    }
+
+    #[test]
+    fn picks_priority_then_signal() {
+        let policy = RoamingPolicy::default();
+        let visible = [seen(0, 5, -50), seen(1, 10, -80), seen(2, 10, -60)];
+        assert_eq!(choose_network(&policy, None, &visible), Some(2));
+        assert_eq!(choose_network(&policy, None, &[]), None);
+    }
+
+    #[test]
+    fn roams_only_when_weak_and_past_hysteresis() {
+        let policy = RoamingPolicy::default();
+        let strong = seen(0, 5, -60);
+        assert_eq!(
+            choose_network(&policy, Some(strong), &[strong, seen(1, 5, -40)]),
+            None
+        );
+
+        let weak = seen(0, 5, -75);
+        assert_eq!(
+            choose_network(&policy, Some(weak), &[weak, seen(1, 5, -70)]),
+            None
+        );
+        assert_eq!(
+            choose_network(&policy, Some(weak), &[weak, seen(1, 5, -67)]),
+            Some(1)
+        );
+
+        let lost = seen(0, 5, i16::MIN);
+        assert_eq!(
+            choose_network(&policy, Some(lost), &[seen(1, 1, -85)]),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn profile_bytes_round_trip() {
+        let profile = WifiProfile::new("home", "hunter22", 7).unwrap();
+        let mut buf = [0u8; 2 + WIFI_MAX_SSID_LEN + WIFI_MAX_PASSWORD_LEN];
+        let len = profile.to_bytes(&mut buf);
+        assert_eq!(WifiProfile::from_bytes(&buf[..len]).unwrap(), profile);
+        assert!(WifiProfile::from_bytes(&buf[..3]).is_err());
+        assert!(WifiProfile::new("", "x", 0).is_err());
+        assert!(WifiProfile::new("a", core::str::from_utf8(&[b'x'; 64]).unwrap(), 0).is_err());
+    }
+
+    #[test]
+    fn debug_hides_password() {
+        let profile = WifiProfile::new("home", "hunter22", 7).unwrap();
+        let mut out: HeaplessString<96> = HeaplessString::new();
+        write!(out, "{profile:?}").unwrap();
+        assert_eq!(
+            out.as_str(),
+            r#"WifiProfile { ssid: "home", password: "<redacted>", priority: 7 }"#
+        );
+    }
+
+    #[test]
+    fn add_replaces_same_ssid() {
+        let mut profiles = WifiProfiles::<2>::new();
+        profiles
+            .add(WifiProfile::new("a", "1", 1).unwrap())
+            .unwrap();
+        profiles
+            .add(WifiProfile::new("b", "2", 1).unwrap())
+            .unwrap();
+        profiles
+            .add(WifiProfile::new("a", "3", 9).unwrap())
+            .unwrap();
+        assert!(matches!(
+            profiles.add(WifiProfile::new("c", "4", 1).unwrap()),
+            Err(WifiProfileError::Full)
+        ));
+        assert_eq!(profiles.get(0).unwrap().password.as_str(), "3");
+        profiles.remove("a");
+        assert_eq!(profiles.find("b"), Some(0));
+    }
+}
@@ -0,0 +1,5 @@
+mod pid;
+mod position_controller;
+
+pub use pid::*;
+pub use position_controller::*;
@@ -0,0 +1,122 @@
+//! pid.rs — PID controller with output clamping and integral anti-windup
+
+/// PID gains and limits
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct PidConfig {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    /// Output is clamped to `-output_limit..=output_limit`
+    pub output_limit: f32,
+    /// Integral term is clamped to `-integral_limit..=integral_limit`
+    pub integral_limit: f32,
+}
+
+impl Default for PidConfig {
+    fn default() -> Self {
+        Self {
+            kp: 1.0,
+            ki: 0.0,
+            kd: 0.0,
+            output_limit: 1.0,
+            integral_limit: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pid {
+    config: PidConfig,
+    integral: f32,
+    prev_measurement: Option<f32>,
+}
+
+impl Pid {
+    pub fn new(config: PidConfig) -> Self {
+        Self {
+            config,
+            integral: 0.0,
+            prev_measurement: None,
+        }
+    }
+
+    pub fn config(&self) -> &PidConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: PidConfig) {
+        self.config = config;
+    }
+
+    /// Forget the accumulated state (call before starting a new move).
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.prev_measurement = None;
+    }
+
+    /// Controller output for one step of `dt_s` seconds.
+    ///
+    /// The derivative acts on the measurement rather than the error, so setpoint jumps
+    /// don't cause output spikes.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt_s: f32) -> f32 {
+        let error = setpoint - measurement;
+        let limit = self.config.integral_limit;
+
+        if dt_s > 0.0 {
+            self.integral = (self.integral + self.config.ki * error * dt_s).clamp(-limit, limit);
+        }
+
+        let derivative = match self.prev_measurement {
+            Some(prev) if dt_s > 0.0 => -(measurement - prev) / dt_s,
+            _ => 0.0,
+        };
+        self.prev_measurement = Some(measurement);
+
+        let output = self.config.kp * error + self.integral + self.config.kd * derivative;
+        output.clamp(-self.config.output_limit, self.config.output_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proportional_output_is_clamped() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 0.5,
+            ..Default::default()
+        });
+        assert_eq!(pid.update(1.0, 0.0, 0.01), 0.5);
+        assert_eq!(pid.update(10.0, 0.0, 0.01), 1.0);
+        assert_eq!(pid.update(-10.0, 0.0, 0.01), -1.0);
+    }
+
+    #[test]
+    fn integral_accumulates_and_is_limited() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 0.0,
+            ki: 1.0,
+            integral_limit: 0.3,
+            ..Default::default()
+        });
+        assert!((pid.update(1.0, 0.0, 0.1) - 0.1).abs() < 1e-6);
+        assert!((pid.update(1.0, 0.0, 0.1) - 0.2).abs() < 1e-6);
+        for _ in 0..10 {
+            pid.update(1.0, 0.0, 0.1);
+        }
+        assert!((pid.update(1.0, 0.0, 0.1) - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn derivative_opposes_motion() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 0.0,
+            kd: 0.1,
+            ..Default::default()
+        });
+        assert_eq!(pid.update(0.0, 0.0, 0.1), 0.0);
+        // Measurement rose by 1 in 0.1 s: derivative -10, output -1
+        assert!((pid.update(0.0, 1.0, 0.1) + 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,165 @@
+//! position_controller.rs — closed-loop position control of a brushed DC motor
+//!
+//! A [`DcMotor`], a [`QuadratureEncoder`](crate::QuadratureEncoder) feeding an
+//! [`EncoderPosition`], and a [`Pid`] make a "smart servo": the setpoint ramps toward the
+//! target at `max_velocity`, the PID tracks it, and a move fails if the motor is driven but
+//! the encoder stops advancing.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut controller = PositionController::new(motor, &POSITION, PositionControllerConfig::default());
+//! controller.move_to(1200).await?;
+//! ```
+
+use embassy_time::{Duration, Instant, Ticker};
+
+use crate::{DcMotor, EncoderPosition, Pid, PidConfig};
+
+/// Motor commands below this magnitude don't count toward stall detection
+const STALL_MIN_DRIVE: f32 = 0.2;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum PositionControllerError {
+    #[error("Motor stalled at position {position}")]
+    Stalled { position: i32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionControllerConfig {
+    /// Gains from position error (ticks) to motor speed (-1.0..=1.0)
+    pub pid: PidConfig,
+    /// Fastest setpoint movement in ticks per second
+    pub max_velocity: f32,
+    /// A move is complete within this many ticks of the target…
+    pub tolerance: i32,
+    /// …once it has stayed there this long
+    pub settle_time: Duration,
+    /// Driven motor without encoder progress for this long is a stall
+    pub stall_timeout: Duration,
+    /// Control loop period
+    pub period: Duration,
+}
+
+impl Default for PositionControllerConfig {
+    fn default() -> Self {
+        Self {
+            pid: PidConfig {
+                kp: 0.01,
+                ki: 0.005,
+                kd: 0.0005,
+                output_limit: 1.0,
+                integral_limit: 0.3,
+            },
+            max_velocity: 2000.0,
+            tolerance: 2,
+            settle_time: Duration::from_millis(50),
+            stall_timeout: Duration::from_millis(300),
+            period: Duration::from_millis(5),
+        }
+    }
+}
+
+pub struct PositionController<'a, 'd> {
+    motor: DcMotor<'d>,
+    position: &'a EncoderPosition,
+    pid: Pid,
+    config: PositionControllerConfig,
+}
+
+impl<'a, 'd> PositionController<'a, 'd> {
+    pub fn new(
+        motor: DcMotor<'d>,
+        position: &'a EncoderPosition,
+        config: PositionControllerConfig,
+    ) -> Self {
+        Self {
+            motor,
+            position,
+            pid: Pid::new(config.pid),
+            config,
+        }
+    }
+
+    pub fn position(&self) -> i32 {
+        self.position.get()
+    }
+
+    /// Declare the current position to be `position` (e.g. after homing).
+    pub fn set_position(&mut self, position: i32) {
+        self.position.set(position);
+    }
+
+    pub fn set_config(&mut self, config: PositionControllerConfig) {
+        self.pid.set_config(config.pid);
+        self.config = config;
+    }
+
+    /// Brake the motor.
+    pub fn stop(&mut self) {
+        self.motor.brake();
+    }
+
+    pub async fn move_by(&mut self, delta: i32) -> Result<(), PositionControllerError> {
+        self.move_to(self.position().saturating_add(delta)).await
+    }
+
+    /// Drive to `target` ticks and brake. The motor is also braked if the future is
+    /// dropped before the move completes.
+    pub async fn move_to(&mut self, target: i32) -> Result<(), PositionControllerError> {
+        let config = &self.config;
+        let position = self.position;
+        let pid = &mut self.pid;
+        let motor = BrakeOnDrop(&mut self.motor);
+
+        pid.reset();
+        let mut setpoint = position.get() as f32;
+        let mut ticker = Ticker::every(config.period);
+        let mut last_tick = Instant::now();
+        let mut settled_since: Option<Instant> = None;
+        let mut progress = (position.get(), last_tick);
+
+        loop {
+            ticker.next().await;
+            let now = Instant::now();
+            let dt_s = (now - last_tick).as_micros() as f32 / 1_000_000.0;
+            last_tick = now;
+
+            let max_step = config.max_velocity * dt_s;
+            setpoint += (target as f32 - setpoint).clamp(-max_step, max_step);
+
+            let current = position.get();
+            let output = pid.update(setpoint, current as f32, dt_s);
+            motor.0.set_speed(output);
+
+            let at_target = setpoint == target as f32
+                && current.abs_diff(target) <= config.tolerance.unsigned_abs();
+            if at_target {
+                let since = *settled_since.get_or_insert(now);
+                if now - since >= config.settle_time {
+                    return Ok(());
+                }
+            } else {
+                settled_since = None;
+            }
+
+            let (progress_position, progress_at) = progress;
+            if output.abs() < STALL_MIN_DRIVE
+                || current.abs_diff(progress_position) > config.tolerance.unsigned_abs()
+            {
+                progress = (current, now);
+            } else if now - progress_at >= config.stall_timeout {
+                return Err(PositionControllerError::Stalled { position: current });
+            }
+        }
+    }
+}
+
+/// Brakes the motor when a move ends, however it ends.
+struct BrakeOnDrop<'m, 'd>(&'m mut DcMotor<'d>);
+
+impl Drop for BrakeOnDrop<'_, '_> {
+    fn drop(&mut self) {
+        self.0.brake();
+    }
+}
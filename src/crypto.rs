@@ -0,0 +1,254 @@
+//! crypto.rs — SHA-256, HMAC-SHA256 and AES-CTR for signing and encrypting small payloads
+//!
+//! Thin wrappers over the RustCrypto crates with fixed-size outputs, in-place encryption
+//! and no allocation, so firmware images, telemetry and session tokens are all handled with
+//! the same primitives. Tags are compared in constant time.
+//!
+//! AES-CTR gives confidentiality only; authenticate the ciphertext with HMAC (encrypt then
+//! MAC) when it can be tampered with, and never reuse a key/nonce pair.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // Verify a firmware image streamed from the network
+//! let mut hasher = Sha256Hasher::new();
+//! while let Some(chunk) = next_chunk().await {
+//!     hasher.update(chunk);
+//! }
+//! let mut expected = [0u8; SHA256_LEN];
+//! hex_decode(manifest_sha256, &mut expected)?;
+//! let ok = hasher.finish() == expected;
+//!
+//! // Sign a telemetry record
+//! let tag = hmac_sha256(&key, payload);
+//! info!("sig {}", hex_encode::<64>(&tag).as_str());
+//! ```
+
+use aes::{Aes128, Aes256};
+use ctr::Ctr128BE;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::HeaplessString;
+
+/// SHA-256 digest and HMAC-SHA256 tag length in bytes
+pub const SHA256_LEN: usize = 32;
+/// AES block, and therefore CTR nonce, length in bytes
+pub const AES_BLOCK_LEN: usize = 16;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Invalid hex string")]
+    InvalidHex,
+    #[error("Output buffer too small")]
+    BufferTooSmall,
+}
+
+/// Incremental SHA-256
+#[derive(Clone, Default)]
+pub struct Sha256Hasher {
+    inner: Sha256,
+}
+
+impl Sha256Hasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finish(self) -> [u8; SHA256_LEN] {
+        self.inner.finalize().into()
+    }
+}
+
+/// SHA-256 of `data`
+pub fn sha256(data: &[u8]) -> [u8; SHA256_LEN] {
+    Sha256::digest(data).into()
+}
+
+/// Incremental HMAC-SHA256
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Hmac<Sha256>,
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            inner: Hmac::new_from_slice(key).expect("HMAC accepts any key length"),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finish(self) -> [u8; SHA256_LEN] {
+        self.inner.finalize().into_bytes().into()
+    }
+
+    /// Constant-time check against `tag`, which may be truncated to its leading bytes
+    /// (but not below 16 bytes).
+    pub fn verify(self, tag: &[u8]) -> bool {
+        tag.len() >= 16 && self.inner.verify_truncated_left(tag).is_ok()
+    }
+}
+
+/// HMAC-SHA256 of `data` under `key`
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; SHA256_LEN] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finish()
+}
+
+/// Constant-time check of an HMAC-SHA256 `tag` (full or truncated to at least 16 bytes)
+pub fn hmac_sha256_verify(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.verify(tag)
+}
+
+/// Encrypt or decrypt `buf` in place with AES-128 in CTR mode (big-endian counter in the
+/// last bytes of `nonce`). Encryption and decryption are the same operation.
+pub fn aes128_ctr(key: &[u8; 16], nonce: &[u8; AES_BLOCK_LEN], buf: &mut [u8]) {
+    Ctr128BE::<Aes128>::new(key.into(), nonce.into()).apply_keystream(buf);
+}
+
+/// As [`aes128_ctr`] with a 256-bit key
+pub fn aes256_ctr(key: &[u8; 32], nonce: &[u8; AES_BLOCK_LEN], buf: &mut [u8]) {
+    Ctr128BE::<Aes256>::new(key.into(), nonce.into()).apply_keystream(buf);
+}
+
+/// Compare secrets (passwords, tokens) in time independent of where they differ. Only
+/// the length can leak: different lengths return `false` at once.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    core::hint::black_box(diff) == 0
+}
+
+/// Lowercase hex of `bytes`, truncated to `N` characters
+pub fn hex_encode<const N: usize>(bytes: &[u8]) -> HeaplessString<N> {
+    let mut out = HeaplessString::new();
+    for &byte in bytes {
+        let _ = out.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        let _ = out.push(HEX_DIGITS[(byte & 0x0F) as usize] as char);
+    }
+    out
+}
+
+/// Decode hex (either case) into `out` and return the number of bytes written.
+pub fn hex_decode(hex: &str, out: &mut [u8]) -> Result<usize, CryptoError> {
+    let hex = hex.as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        return Err(CryptoError::InvalidHex);
+    }
+    let len = hex.len() / 2;
+    if len > out.len() {
+        return Err(CryptoError::BufferTooSmall);
+    }
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+    }
+    Ok(len)
+}
+
+fn hex_value(digit: u8) -> Result<u8, CryptoError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(CryptoError::InvalidHex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+        let mut out = [0u8; N];
+        assert_eq!(hex_decode(hex, &mut out).unwrap(), N);
+        out
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        let expected: [u8; 32] =
+            unhex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(sha256(b"abc"), expected);
+
+        let mut hasher = Sha256Hasher::new();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finish(), expected);
+    }
+
+    #[test]
+    fn hmac_rfc4231_case_2() {
+        let data = b"what do ya want for nothing?";
+        let tag = hmac_sha256(b"Jefe", data);
+        assert_eq!(
+            hex_encode::<64>(&tag).as_str(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(hmac_sha256_verify(b"Jefe", data, &tag));
+        assert!(hmac_sha256_verify(b"Jefe", data, &tag[..16]));
+        assert!(!hmac_sha256_verify(b"Jefe", data, &tag[..8]));
+        assert!(!hmac_sha256_verify(b"jefe", data, &tag));
+    }
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(constant_time_eq(b"hunter22", b"hunter22"));
+        assert!(!constant_time_eq(b"hunter22", b"hunter23"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter22"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn aes_ctr_nist_vectors() {
+        // NIST SP 800-38A F.5.1 and F.5.5, first block
+        let nonce: [u8; 16] = unhex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+        let plain: [u8; 16] = unhex("6bc1bee22e409f96e93d7e117393172a");
+
+        let mut buf = plain;
+        aes128_ctr(&unhex("2b7e151628aed2a6abf7158809cf4f3c"), &nonce, &mut buf);
+        assert_eq!(buf, unhex::<16>("874d6191b620e3261bef6864990db6ce"));
+
+        let key: [u8; 32] =
+            unhex("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4");
+        let mut buf = plain;
+        aes256_ctr(&key, &nonce, &mut buf);
+        assert_eq!(buf, unhex::<16>("601ec313775789a5b7a7f504bbf3d228"));
+        aes256_ctr(&key, &nonce, &mut buf);
+        assert_eq!(buf, plain);
+    }
+
+    #[test]
+    fn hex_decode_errors() {
+        let mut out = [0u8; 2];
+        assert!(matches!(
+            hex_decode("abc", &mut out),
+            Err(CryptoError::InvalidHex)
+        ));
+        assert!(matches!(
+            hex_decode("zz", &mut out),
+            Err(CryptoError::InvalidHex)
+        ));
+        assert!(matches!(
+            hex_decode("aabbcc", &mut out),
+            Err(CryptoError::BufferTooSmall)
+        ));
+        assert_eq!(hex_decode("A0ff", &mut out).unwrap(), 2);
+        assert_eq!(out, [0xA0, 0xFF]);
+    }
+}
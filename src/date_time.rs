@@ -0,0 +1,299 @@
+//! date_time.rs — calendar date/time, Unix timestamp conversion and time zone/DST rules
+
+/// Day of the week
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Monday = 0 … Sunday = 6
+    pub fn from_index(index: u8) -> Self {
+        match index % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    /// Monday = 0 … Sunday = 6
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Three-letter English abbreviation
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Weekday::Monday => "Mon",
+            Weekday::Tuesday => "Tue",
+            Weekday::Wednesday => "Wed",
+            Weekday::Thursday => "Thu",
+            Weekday::Friday => "Fri",
+            Weekday::Saturday => "Sat",
+            Weekday::Sunday => "Sun",
+        }
+    }
+}
+
+/// Calendar date and time of day (no time zone attached)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1–12
+    pub month: u8,
+    /// 1–31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 to a date/time.
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86_400) as i64;
+        let rem = secs % 86_400;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00. Dates before 1970 clamp to 0.
+    pub fn to_unix(self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        let secs =
+            days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        secs.max(0) as u64
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        // 1970-01-01 was a Thursday
+        Weekday::from_index((days + 3).rem_euclid(7) as u8)
+    }
+
+    /// `false` for out-of-range fields such as February 30th or hour 24.
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's days_from_civil / civil_from_days
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Daylight saving time rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum DstRule {
+    None,
+    /// +1 h from the last Sunday of March to the last Sunday of October, switching at 01:00 UTC
+    Eu,
+    /// +1 h from the second Sunday of March to the first Sunday of November, switching at
+    /// 02:00 local time
+    UsCanada,
+}
+
+/// Local time zone: standard offset from UTC plus a DST rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TimeZone {
+    /// Standard (winter) offset from UTC in minutes, e.g. 60 for CET, -300 for EST
+    pub utc_offset_minutes: i16,
+    pub dst: DstRule,
+}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone {
+        utc_offset_minutes: 0,
+        dst: DstRule::None,
+    };
+
+    pub const fn new(utc_offset_minutes: i16, dst: DstRule) -> Self {
+        Self {
+            utc_offset_minutes,
+            dst,
+        }
+    }
+
+    /// Total offset from UTC in seconds at the given instant, DST included.
+    pub fn offset_secs(&self, utc_unix: u64) -> i64 {
+        let standard = self.utc_offset_minutes as i64 * 60;
+        if self.is_dst(utc_unix) {
+            standard + 3600
+        } else {
+            standard
+        }
+    }
+
+    pub fn is_dst(&self, utc_unix: u64) -> bool {
+        let year = DateTime::from_unix(utc_unix).year;
+        let standard = self.utc_offset_minutes as i64 * 60;
+        let (start, end) = match self.dst {
+            DstRule::None => return false,
+            DstRule::Eu => (
+                nth_sunday_utc(year, 3, None, 1),
+                nth_sunday_utc(year, 10, None, 1),
+            ),
+            // 02:00 standard time on the way in, 02:00 daylight (= 01:00 standard) on the way out
+            DstRule::UsCanada => (
+                nth_sunday_utc(year, 3, Some(2), 2) - standard,
+                nth_sunday_utc(year, 11, Some(1), 1) - standard,
+            ),
+        };
+        let now = utc_unix as i64;
+        now >= start && now < end
+    }
+
+    /// Convert a UTC timestamp to local date/time.
+    pub fn to_local(self, utc_unix: u64) -> DateTime {
+        let local = utc_unix as i64 + self.offset_secs(utc_unix);
+        DateTime::from_unix(local.max(0) as u64)
+    }
+}
+
+/// Timestamp of `hour`:00 on the `nth` Sunday (`None` = last) of `month`, treating the
+/// date as UTC.
+fn nth_sunday_utc(year: u16, month: u8, nth: Option<u8>, hour: u8) -> i64 {
+    let day = match nth {
+        Some(n) => {
+            let first = DateTime {
+                year,
+                month,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            };
+            let to_sunday = (6 - first.weekday().index()) % 7;
+            1 + to_sunday + (n - 1) * 7
+        }
+        None => {
+            let last_day = days_in_month(year, month);
+            let last = DateTime {
+                year,
+                month,
+                day: last_day,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            };
+            last_day - (last.weekday().index() + 1) % 7
+        }
+    };
+    days_from_civil(year as i64, month, day) * 86_400 + hour as i64 * 3600
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        assert_eq!(DateTime::from_unix(0), dt(1970, 1, 1, 0, 0, 0));
+        assert_eq!(DateTime::from_unix(951_782_400), dt(2000, 2, 29, 0, 0, 0));
+        let t = dt(2024, 12, 31, 23, 59, 59);
+        assert_eq!(t.to_unix(), 1_735_689_599);
+        assert_eq!(DateTime::from_unix(t.to_unix()), t);
+    }
+
+    #[test]
+    fn weekday_and_validity() {
+        assert_eq!(dt(1970, 1, 1, 0, 0, 0).weekday(), Weekday::Thursday);
+        assert_eq!(dt(2024, 3, 31, 0, 0, 0).weekday(), Weekday::Sunday);
+        assert!(dt(2024, 2, 29, 0, 0, 0).is_valid());
+        assert!(!dt(2023, 2, 29, 0, 0, 0).is_valid());
+        assert!(!dt(2023, 1, 1, 24, 0, 0).is_valid());
+    }
+
+    #[test]
+    fn eu_dst_transitions() {
+        let cet = TimeZone::new(60, DstRule::Eu);
+        // 2024: DST from 31 March 01:00 UTC to 27 October 01:00 UTC
+        assert!(!cet.is_dst(dt(2024, 3, 31, 0, 59, 59).to_unix()));
+        assert!(cet.is_dst(dt(2024, 3, 31, 1, 0, 0).to_unix()));
+        assert!(cet.is_dst(dt(2024, 10, 27, 0, 59, 59).to_unix()));
+        assert!(!cet.is_dst(dt(2024, 10, 27, 1, 0, 0).to_unix()));
+        assert_eq!(
+            cet.to_local(dt(2024, 7, 1, 12, 0, 0).to_unix()),
+            dt(2024, 7, 1, 14, 0, 0)
+        );
+    }
+
+    #[test]
+    fn us_dst_transitions() {
+        let est = TimeZone::new(-300, DstRule::UsCanada);
+        // 2024: DST from 10 March 07:00 UTC to 3 November 06:00 UTC
+        assert!(!est.is_dst(dt(2024, 3, 10, 6, 59, 59).to_unix()));
+        assert!(est.is_dst(dt(2024, 3, 10, 7, 0, 0).to_unix()));
+        assert!(est.is_dst(dt(2024, 11, 3, 5, 59, 59).to_unix()));
+        assert!(!est.is_dst(dt(2024, 11, 3, 6, 0, 0).to_unix()));
+        assert_eq!(
+            est.to_local(dt(2024, 1, 1, 12, 0, 0).to_unix()),
+            dt(2024, 1, 1, 7, 0, 0)
+        );
+    }
+}
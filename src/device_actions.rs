@@ -0,0 +1,97 @@
+//! device_actions.rs — the firmware's remotely triggered actions, shared by every transport
+//!
+//! Remote control arrives over more than one transport: typed MQTT commands
+//! ([`CommandRouter`](crate::CommandRouter)) and the hardware-in-the-loop line protocol
+//! (`TestHarness`, feature `test-harness`). Each parses its own wire format and then calls
+//! the same [`DeviceActions`], so a firmware wires its displays, servos and buttons up once
+//! and every transport reaches them. Devices are addressed by number; actions the firmware
+//! doesn't implement answer [`ActionError::Unsupported`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! struct Bench<'a> { lcd: InlandKs0061I2cDisplay<I2c<'a, I2C0, Blocking>>, servo: Servo<'a> }
+//!
+//! impl DeviceActions for Bench<'_> {
+//!     async fn set_servo_angle(&mut self, index: u8, degrees: f32) -> Result<(), ActionError> {
+//!         match index {
+//!             0 => self.servo.set_angle(degrees).map_err(|_| ActionError::Device),
+//!             _ => Err(ActionError::NoSuchDevice),
+//!         }
+//!     }
+//!     // display_text likewise; button_pressed, reboot and start_ota stay unsupported
+//! }
+//!
+//! router.run(&mut mqtt, &mut bench).await;
+//! ```
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum ActionError {
+    #[error("no such device")]
+    NoSuchDevice,
+    #[error("not supported")]
+    Unsupported,
+    #[error("device error")]
+    Device,
+}
+
+/// The firmware's peripherals and maintenance actions. Unimplemented actions answer
+/// [`ActionError::Unsupported`].
+#[allow(async_fn_in_trait)]
+pub trait DeviceActions {
+    /// Show `text` on display `index`; lines are separated by `\n`.
+    async fn display_text(&mut self, _index: u8, _text: &str) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+
+    async fn set_servo_angle(&mut self, _index: u8, _degrees: f32) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+
+    async fn button_pressed(&mut self, _index: u8) -> Result<bool, ActionError> {
+        Err(ActionError::Unsupported)
+    }
+
+    /// The answer goes out after this returns, so schedule the reset (e.g. signal a task
+    /// that resets a moment later) instead of resetting here.
+    async fn reboot(&mut self) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+
+    /// Like [`reboot`](Self::reboot), start the update elsewhere and return.
+    async fn start_ota(&mut self, _url: &str, _sha256: Option<&str>) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+}
+
+/// Fixture for the transports' tests
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::HeaplessString;
+
+    /// Display 0 and servo 0, remembering what they were last told
+    #[derive(Default)]
+    pub(crate) struct Bench {
+        pub(crate) text: HeaplessString<32>,
+        pub(crate) angle: f32,
+    }
+
+    impl DeviceActions for Bench {
+        async fn display_text(&mut self, index: u8, text: &str) -> Result<(), ActionError> {
+            if index != 0 {
+                return Err(ActionError::NoSuchDevice);
+            }
+            self.text = text.try_into().map_err(|_| ActionError::Device)?;
+            Ok(())
+        }
+
+        async fn set_servo_angle(&mut self, index: u8, degrees: f32) -> Result<(), ActionError> {
+            if index != 0 {
+                return Err(ActionError::NoSuchDevice);
+            }
+            self.angle = degrees;
+            Ok(())
+        }
+    }
+}
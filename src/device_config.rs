@@ -0,0 +1,230 @@
+//! device_config.rs — typed build-time configuration from environment variables
+//!
+//! [`device_config!`](crate::device_config) turns environment variables set at build time
+//! (`WIFI_SSID=home cargo build`, or `[env]` in `.cargo/config.toml`) into typed consts,
+//! so credentials stay out of the source and a field build is reproducible from its
+//! environment. Values are parsed and checked at compile time: a missing required
+//! variable, a number that doesn't parse or fit, or a string outside its length range
+//! fails the build instead of the device.
+//!
+//! Each entry is `NAME: kind = env "VAR"`, optionally followed by `or <default>`, with
+//! kind `str`, `bool` (`true`/`false`, `1`/`0`, `yes`/`no`, `on`/`off`) or an integer
+//! type, and an optional `[min..=max]` range (string length for `str`).
+//!
+//! # Example
+//!
+//! ```ignore
+//! device_config! {
+//!     /// Settings baked into this firmware
+//!     pub mod config {
+//!         WIFI_SSID: str[1..=32] = env "WIFI_SSID";
+//!         WIFI_PASSWORD: str[0..=63] = env "WIFI_PASSWORD" or "";
+//!         MQTT_BROKER: str = env "MQTT_BROKER" or "mqtt.local";
+//!         MQTT_PORT: u16 = env "MQTT_PORT" or 1883;
+//!         DEVICE_NAME: str[1..=24] = env "DEVICE_NAME" or "pico";
+//!         ENABLE_TELEMETRY: bool = env "ENABLE_TELEMETRY" or true;
+//!     }
+//! }
+//!
+//! wifi.join(config::WIFI_SSID, config::WIFI_PASSWORD).await?;
+//! ```
+
+/// Parse a decimal integer with an optional leading `-`; `None` if malformed or out of
+/// `i64` range.
+pub const fn parse_config_int(value: &str) -> Option<i64> {
+    let bytes = value.as_bytes();
+    let negative = !bytes.is_empty() && bytes[0] == b'-';
+    let mut i = negative as usize;
+    if i == bytes.len() {
+        return None;
+    }
+    let mut result: i64 = 0;
+    while i < bytes.len() {
+        let digit = bytes[i];
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        let digit = (digit - b'0') as i64;
+        result = match result.checked_mul(10) {
+            Some(shifted) if negative => match shifted.checked_sub(digit) {
+                Some(next) => next,
+                None => return None,
+            },
+            Some(shifted) => match shifted.checked_add(digit) {
+                Some(next) => next,
+                None => return None,
+            },
+            None => return None,
+        };
+        i += 1;
+    }
+    Some(result)
+}
+
+/// Parse `true`/`false`, `1`/`0`, `yes`/`no` or `on`/`off`, ignoring ASCII case.
+pub const fn parse_config_bool(value: &str) -> Option<bool> {
+    let value = value.as_bytes();
+    if value.eq_ignore_ascii_case(b"true")
+        || value.eq_ignore_ascii_case(b"1")
+        || value.eq_ignore_ascii_case(b"yes")
+        || value.eq_ignore_ascii_case(b"on")
+    {
+        Some(true)
+    } else if value.eq_ignore_ascii_case(b"false")
+        || value.eq_ignore_ascii_case(b"0")
+        || value.eq_ignore_ascii_case(b"no")
+        || value.eq_ignore_ascii_case(b"off")
+    {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Declare build-time configuration consts read from environment variables.
+///
+/// See the `device_config.rs` module docs for the syntax and an example.
+#[macro_export]
+macro_rules! device_config {
+    (
+        $(#[$meta:meta])*
+        $vis:vis mod $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident : $kind:ident $([$min:literal ..= $max:literal])?
+                    = env $env:literal $(or $default:literal)?;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis mod $name {
+            $(
+                $(#[$field_meta])*
+                pub const $field: $crate::__config_type!($kind) = {
+                    let value = $crate::__config_value!($kind, $env $(, $default)?);
+                    $(
+                        assert!(
+                            $crate::__config_in_range!($kind, value, $min, $max),
+                            concat!(
+                                "device_config: ", $env, " must be within ",
+                                stringify!($min), "..=", stringify!($max),
+                            )
+                        );
+                    )?
+                    value
+                };
+            )*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __config_type {
+    (str) => { &'static str };
+    ($kind:ident) => { $kind };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __config_value {
+    ($kind:ident, $env:literal) => {
+        $crate::__config_parse!($kind, $env, env!($env))
+    };
+    ($kind:ident, $env:literal, $default:literal) => {
+        match option_env!($env) {
+            Some(value) => $crate::__config_parse!($kind, $env, value),
+            None => $default,
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __config_parse {
+    (str, $env:literal, $value:expr) => {
+        $value
+    };
+    (bool, $env:literal, $value:expr) => {
+        match $crate::parse_config_bool($value) {
+            Some(value) => value,
+            None => panic!(concat!("device_config: ", $env, " must be true or false")),
+        }
+    };
+    ($int:ident, $env:literal, $value:expr) => {
+        // Compared as i128: u64::MAX and usize::MAX don't fit an i64
+        match $crate::parse_config_int($value) {
+            Some(value)
+                if value as i128 >= $int::MIN as i128 && value as i128 <= $int::MAX as i128 =>
+            {
+                value as $int
+            }
+            _ => panic!(concat!(
+                "device_config: ",
+                $env,
+                " must be a ",
+                stringify!($int)
+            )),
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __config_in_range {
+    (str, $value:expr, $min:literal, $max:literal) => {
+        $value.len() >= $min && $value.len() <= $max
+    };
+    ($kind:ident, $value:expr, $min:literal, $max:literal) => {
+        $value >= $min && $value <= $max
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::device_config! {
+        mod config {
+            NAME: str[1..=8] = env "DARKPICOLIB_TEST_UNSET_NAME" or "pico";
+            PORT: u16[1..=9999] = env "DARKPICOLIB_TEST_UNSET_PORT" or 1883;
+            ENABLED: bool = env "DARKPICOLIB_TEST_UNSET_ENABLED" or true;
+        }
+    }
+
+    #[test]
+    fn defaults_apply_when_unset() {
+        assert_eq!(
+            (config::NAME, config::PORT, config::ENABLED),
+            ("pico", 1883, true)
+        );
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse_config_int("1883"), Some(1883));
+        assert_eq!(parse_config_int("-40"), Some(-40));
+        assert_eq!(parse_config_int("-9223372036854775808"), Some(i64::MIN));
+        assert_eq!(parse_config_int("9223372036854775808"), None);
+        assert_eq!(parse_config_int(""), None);
+        assert_eq!(parse_config_int("-"), None);
+        assert_eq!(parse_config_int("12a"), None);
+        assert_eq!(parse_config_bool("ON"), Some(true));
+        assert_eq!(parse_config_bool("0"), Some(false));
+        assert_eq!(parse_config_bool("maybe"), None);
+    }
+
+    #[test]
+    fn integers_fit_their_type() {
+        assert_eq!(crate::__config_parse!(u64, "PORT", "1883"), 1883u64);
+        assert_eq!(crate::__config_parse!(usize, "PORT", "1883"), 1883usize);
+        assert_eq!(crate::__config_parse!(i8, "TEMP", "-128"), -128i8);
+        assert_eq!(crate::__config_parse!(u8, "LEVEL", "255"), 255u8);
+    }
+
+    #[test]
+    #[should_panic(expected = "LEVEL must be a u8")]
+    fn integer_out_of_range_panics() {
+        let _ = crate::__config_parse!(u8, "LEVEL", "256");
+    }
+}
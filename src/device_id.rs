@@ -0,0 +1,161 @@
+//! device_id.rs — per-board identity from the flash chip's unique ID
+//!
+//! Every Pico's QSPI flash carries a factory-programmed 64-bit unique ID. [`DeviceId`] reads
+//! it and formats it for hostnames, MQTT client IDs and the like, and derives per-device
+//! keys and tokens as HMAC-SHA256 of the ID under a secret baked in at build time (see
+//! [`device_secret!`](crate::device_secret)), so two boards never share credentials.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(p.FLASH);
+//! let id = DeviceId::read(&mut flash)?;
+//! info!("device {}", id.to_hex().as_str());
+//!
+//! let client_id = id.name("pico"); // "pico-a1b2c3"
+//! let token = id.derive_token(device_secret!(), "mqtt");
+//! ```
+
+use embassy_rp::flash::{Error as FlashError, Flash, Instance, Mode};
+
+use crate::{HeaplessString, HmacSha256, hex_encode};
+
+/// Length of the flash unique ID in bytes
+pub const DEVICE_ID_LEN: usize = 8;
+/// Characters in [`DeviceId::to_base32`]
+pub const DEVICE_ID_BASE32_LEN: usize = 13;
+/// Characters in [`DeviceId::derive_token`]
+pub const DEVICE_TOKEN_LEN: usize = 26;
+/// Longest prefix accepted by [`DeviceId::name`]
+pub const DEVICE_NAME_MAX_PREFIX_LEN: usize = 16;
+
+// RFC 4648 alphabet, lowercase so the result can go straight into a hostname
+const BASE32_DIGITS: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Secret for [`DeviceId::derive_key`] from the `DEVICE_SECRET` env var at build time,
+/// e.g. `DEVICE_SECRET=$(cat secret.txt) cargo build`. The build fails if it isn't set.
+#[macro_export]
+macro_rules! device_secret {
+    () => {
+        env!("DEVICE_SECRET").as_bytes()
+    };
+}
+
+/// Factory-unique 64-bit board ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, defmt::Format)]
+pub struct DeviceId([u8; DEVICE_ID_LEN]);
+
+impl DeviceId {
+    /// Read the unique ID from the flash chip. Briefly stalls XIP, like any flash command.
+    pub fn read<T: Instance, M: Mode, const FLASH_SIZE: usize>(
+        flash: &mut Flash<'_, T, M, FLASH_SIZE>,
+    ) -> Result<Self, FlashError> {
+        let mut bytes = [0u8; DEVICE_ID_LEN];
+        flash.blocking_unique_id(&mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    pub const fn from_bytes(bytes: [u8; DEVICE_ID_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; DEVICE_ID_LEN] {
+        &self.0
+    }
+
+    pub const fn as_u64(&self) -> u64 {
+        u64::from_be_bytes(self.0)
+    }
+
+    /// All 16 hex digits, lowercase
+    pub fn to_hex(self) -> HeaplessString<{ DEVICE_ID_LEN * 2 }> {
+        hex_encode(&self.0)
+    }
+
+    /// Base32 (RFC 4648 alphabet, lowercase, no padding), 13 characters
+    pub fn to_base32(self) -> HeaplessString<DEVICE_ID_BASE32_LEN> {
+        base32(&self.0)
+    }
+
+    /// Last three bytes as hex: short, and unique enough to tell boards on one bench apart
+    pub fn short_hex(self) -> HeaplessString<6> {
+        hex_encode(&self.0[DEVICE_ID_LEN - 3..])
+    }
+
+    /// `"<prefix>-<short hex>"`, e.g. `"pico-a1b2c3"`, for hostnames, SSIDs and client IDs.
+    /// The prefix is truncated to [`DEVICE_NAME_MAX_PREFIX_LEN`] bytes.
+    pub fn name(self, prefix: &str) -> HeaplessString<{ DEVICE_NAME_MAX_PREFIX_LEN + 7 }> {
+        let mut name = HeaplessString::new();
+        for c in prefix.chars() {
+            if name.len() + c.len_utf8() > DEVICE_NAME_MAX_PREFIX_LEN {
+                break;
+            }
+            let _ = name.push(c);
+        }
+        let _ = name.push('-');
+        let _ = name.push_str(self.short_hex().as_str());
+        name
+    }
+
+    /// 256-bit key for `purpose` (e.g. `"mqtt"`, `"ota"`): HMAC-SHA256 keyed with `secret`
+    /// over `purpose`, a zero byte and the ID. Different purposes give unrelated keys.
+    pub fn derive_key(&self, secret: &[u8], purpose: &str) -> [u8; 32] {
+        let mut mac = HmacSha256::new(secret);
+        mac.update(purpose.as_bytes());
+        mac.update(&[0]);
+        mac.update(&self.0);
+        mac.finish()
+    }
+
+    /// Printable token for `purpose`: the first 128 bits of [`DeviceId::derive_key`] in base32,
+    /// suitable as a password or provisioning code.
+    pub fn derive_token(&self, secret: &[u8], purpose: &str) -> HeaplessString<DEVICE_TOKEN_LEN> {
+        let key = self.derive_key(secret, purpose);
+        base32(&key[..16])
+    }
+}
+
+fn base32<const N: usize>(bytes: &[u8]) -> HeaplessString<N> {
+    let mut out = HeaplessString::new();
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            let _ = out.push(BASE32_DIGITS[((buffer >> bits) & 0x1F) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        let _ = out.push(BASE32_DIGITS[((buffer << (5 - bits)) & 0x1F) as usize] as char);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: DeviceId = DeviceId::from_bytes([0xE6, 0x61, 0x38, 0x52, 0x83, 0xA1, 0xB2, 0xC3]);
+
+    #[test]
+    fn hex_formats() {
+        assert_eq!(ID.to_hex().as_str(), "e661385283a1b2c3");
+        assert_eq!(ID.short_hex().as_str(), "a1b2c3");
+        assert_eq!(ID.name("pico").as_str(), "pico-a1b2c3");
+        assert_eq!(
+            ID.name("a-very-long-device-prefix").as_str(),
+            "a-very-long-devi-a1b2c3"
+        );
+    }
+
+    #[test]
+    fn base32_matches_rfc4648() {
+        // RFC 4648 test vectors, lowercase and unpadded
+        assert_eq!(base32::<16>(b"foobar").as_str(), "mzxw6ytboi");
+        assert_eq!(base32::<16>(b"fooba").as_str(), "mzxw6ytb");
+        assert_eq!(base32::<16>(b"f").as_str(), "my");
+        assert_eq!(ID.to_base32().len(), DEVICE_ID_BASE32_LEN);
+    }
+}
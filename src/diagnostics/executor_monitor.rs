@@ -0,0 +1,254 @@
+//! executor_monitor.rs — executor health: uptime, timer wake latency and loop jitter
+//!
+//! [`ExecutorMonitor::run`] sleeps for a fixed period over and over and records how late
+//! each wake-up is. On a healthy executor that is a few microseconds; a driver that blocks
+//! (busy-waits on a bus, a sync LCD update) shows up as milliseconds. [`LoopMonitor`] does
+//! the same for an application loop: call [`LoopMonitor::tick`] once per iteration and it
+//! tracks how far the iteration interval strays from the expected period.
+//!
+//! All figures are [`Gauge`]s in microseconds (uptime in seconds), so they can be added to a
+//! [`MetricsRegistry`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! static MONITOR: ExecutorMonitor = ExecutorMonitor::new(Duration::from_millis(100));
+//! // Gauges "sensor", "sensor max" and "sensor period"
+//! static SENSOR_LOOP: LoopMonitor = loop_monitor!("sensor", Duration::from_millis(500));
+//!
+//! MONITOR.register(&mut metrics)?;
+//! SENSOR_LOOP.register(&mut metrics)?;
+//! spawner.spawn(monitor_task().expect("failed to spawn monitor_task"));
+//!
+//! #[embassy_executor::task]
+//! async fn monitor_task() -> ! {
+//!     MONITOR.run().await
+//! }
+//!
+//! loop {
+//!     SENSOR_LOOP.tick();
+//!     read_sensors().await;
+//!     Timer::after_millis(500).await;
+//! }
+//! ```
+
+use embassy_time::{Duration, Timer};
+use portable_atomic::{AtomicU64, Ordering};
+
+use crate::{Gauge, MetricsRegistry, PushError, SystemUptime, Uptime};
+
+/// Measures how late the executor runs a task whose timer has expired
+pub struct ExecutorMonitor<U: Uptime = SystemUptime> {
+    period: Duration,
+    clock: U,
+    uptime: Gauge,
+    wake_latency: Gauge,
+    wake_latency_max: Gauge,
+}
+
+impl ExecutorMonitor {
+    /// Sample every `period`; shorter periods catch shorter stalls at a little CPU cost.
+    pub const fn new(period: Duration) -> Self {
+        Self::with_uptime(period, SystemUptime)
+    }
+}
+
+impl<U: Uptime> ExecutorMonitor<U> {
+    /// Like [`ExecutorMonitor::new`], reading time from `clock`, e.g. a
+    /// [`MockUptime`](crate::MockUptime).
+    pub const fn with_uptime(period: Duration, clock: U) -> Self {
+        Self {
+            period,
+            clock,
+            uptime: Gauge::new("uptime", "s"),
+            wake_latency: Gauge::new("wake", "us"),
+            wake_latency_max: Gauge::new("wake max", "us"),
+        }
+    }
+
+    /// Add the uptime, last and worst wake latency gauges to `registry`.
+    pub fn register<const N: usize>(
+        &'static self,
+        registry: &mut MetricsRegistry<N>,
+    ) -> Result<(), PushError> {
+        registry.register(&self.uptime)?;
+        registry.register(&self.wake_latency)?;
+        registry.register(&self.wake_latency_max)
+    }
+
+    pub fn uptime(&self) -> Duration {
+        Duration::from_secs(self.clock.now().as_secs())
+    }
+
+    pub fn wake_latency(&self) -> Duration {
+        Duration::from_micros(self.wake_latency.get() as u64)
+    }
+
+    /// Worst wake latency since start or the last [`ExecutorMonitor::reset`]
+    pub fn wake_latency_max(&self) -> Duration {
+        Duration::from_micros(self.wake_latency_max.get() as u64)
+    }
+
+    pub fn reset(&self) {
+        self.wake_latency_max.set(0);
+    }
+
+    /// Measure forever. Run it in its own task on the executor being monitored.
+    pub async fn run(&self) -> ! {
+        let mut expected = self.clock.now() + self.period;
+        loop {
+            Timer::at(expected).await;
+            let now = self.clock.now();
+            let late = micros_u32(now.saturating_duration_since(expected).as_micros());
+            self.wake_latency.set(late);
+            self.wake_latency_max.set_max(late);
+            self.uptime.set(now.as_secs() as u32);
+            expected = now + self.period;
+        }
+    }
+}
+
+/// Iteration timing of one application loop
+pub struct LoopMonitor<U: Uptime = SystemUptime> {
+    expected: Duration,
+    clock: U,
+    /// Microseconds since boot of the last tick, plus one (0: no tick yet)
+    last_tick: AtomicU64,
+    jitter: Gauge,
+    jitter_max: Gauge,
+    interval_max: Gauge,
+}
+
+impl LoopMonitor {
+    /// `names` label the last jitter, worst jitter and longest interval gauges;
+    /// [`loop_monitor!`](crate::loop_monitor) derives them from one name. `expected` is the
+    /// loop's nominal period.
+    pub const fn new(names: [&'static str; 3], expected: Duration) -> Self {
+        Self::with_uptime(names, expected, SystemUptime)
+    }
+}
+
+impl<U: Uptime> LoopMonitor<U> {
+    /// Like [`LoopMonitor::new`], reading time from `clock`, e.g. a
+    /// [`MockUptime`](crate::MockUptime).
+    pub const fn with_uptime(names: [&'static str; 3], expected: Duration, clock: U) -> Self {
+        let [jitter, jitter_max, interval_max] = names;
+        Self {
+            expected,
+            clock,
+            last_tick: AtomicU64::new(0),
+            jitter: Gauge::new(jitter, "us"),
+            jitter_max: Gauge::new(jitter_max, "us"),
+            interval_max: Gauge::new(interval_max, "us"),
+        }
+    }
+
+    /// Add the loop's last jitter, worst jitter and longest interval gauges to `registry`.
+    pub fn register<const N: usize>(
+        &'static self,
+        registry: &mut MetricsRegistry<N>,
+    ) -> Result<(), PushError> {
+        registry.register(&self.jitter)?;
+        registry.register(&self.jitter_max)?;
+        registry.register(&self.interval_max)
+    }
+
+    /// Mark the start of an iteration.
+    pub fn tick(&self) {
+        let now_us = self.clock.now().as_micros();
+        let last = self.last_tick.swap(now_us + 1, Ordering::Relaxed);
+        if last == 0 {
+            return;
+        }
+        let interval = now_us.saturating_sub(last - 1);
+        let jitter = micros_u32(interval.abs_diff(self.expected.as_micros()));
+        self.jitter.set(jitter);
+        self.jitter_max.set_max(jitter);
+        self.interval_max.set_max(micros_u32(interval));
+    }
+
+    /// Deviation of the last interval from the expected period
+    pub fn jitter(&self) -> Duration {
+        Duration::from_micros(self.jitter.get() as u64)
+    }
+
+    /// Worst deviation since start or the last [`LoopMonitor::reset`]
+    pub fn jitter_max(&self) -> Duration {
+        Duration::from_micros(self.jitter_max.get() as u64)
+    }
+
+    /// Longest interval since start or the last [`LoopMonitor::reset`]
+    pub fn interval_max(&self) -> Duration {
+        Duration::from_micros(self.interval_max.get() as u64)
+    }
+
+    pub fn reset(&self) {
+        self.jitter_max.set(0);
+        self.interval_max.set(0);
+    }
+}
+
+/// A [`LoopMonitor`] with gauges named `"<name>"`, `"<name> max"` and `"<name> period"`,
+/// like the executor's `"wake"` and `"wake max"`.
+#[macro_export]
+macro_rules! loop_monitor {
+    ($name:literal, $expected:expr) => {
+        $crate::LoopMonitor::new(
+            [$name, concat!($name, " max"), concat!($name, " period")],
+            $expected,
+        )
+    };
+}
+
+fn micros_u32(micros: u64) -> u32 {
+    micros.min(u32::MAX as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::Instant;
+
+    use super::*;
+    use crate::MockUptime;
+
+    #[test]
+    fn loop_jitter() {
+        let time = MockUptime::starting_at(Instant::from_millis(1_000));
+        let names = ["loop", "loop max", "loop period"];
+        let monitor = LoopMonitor::with_uptime(names, Duration::from_millis(100), &time);
+        monitor.tick();
+        assert_eq!(monitor.jitter(), Duration::from_micros(0));
+
+        time.advance(Duration::from_millis(100));
+        monitor.tick();
+        assert_eq!(monitor.jitter(), Duration::from_micros(0));
+        time.advance(Duration::from_millis(150));
+        monitor.tick();
+        assert_eq!(monitor.jitter(), Duration::from_millis(50));
+        time.advance(Duration::from_millis(90));
+        monitor.tick();
+        assert_eq!(monitor.jitter(), Duration::from_millis(10));
+
+        assert_eq!(monitor.jitter_max(), Duration::from_millis(50));
+        assert_eq!(monitor.interval_max(), Duration::from_millis(150));
+        monitor.reset();
+        assert_eq!(monitor.jitter_max(), Duration::from_micros(0));
+    }
+
+    #[test]
+    fn uptime_in_whole_seconds() {
+        let time = MockUptime::starting_at(Instant::from_millis(90_500));
+        let monitor = ExecutorMonitor::with_uptime(Duration::from_millis(100), &time);
+        assert_eq!(monitor.uptime(), Duration::from_secs(90));
+    }
+
+    #[test]
+    fn loop_gauges_have_distinct_names() {
+        static MONITOR: LoopMonitor = loop_monitor!("sensor", Duration::from_millis(500));
+        let mut registry = MetricsRegistry::<3>::new();
+        MONITOR.register(&mut registry).unwrap();
+        let units = ["sensor", "sensor max", "sensor period"]
+            .map(|name| registry.get(name).map(Gauge::unit));
+        assert_eq!(units, [Some("us"); 3]);
+    }
+}
@@ -0,0 +1,199 @@
+//! log.rs — runtime log levels and routing for the crate's own messages
+//!
+//! The drivers report through [`log!`](crate::log) instead of calling defmt directly. Every
+//! message belongs to a [`LogModule`] with its own [`LogLevel`], changeable at runtime
+//! (say, `Debug` for WiFi while chasing a reconnect problem). Enabled messages go to defmt
+//! and, if one is set, to a [`LogSink`]: a [`LogsDisplay`](crate::LogsDisplay), a UART or
+//! network logger. Sink messages are formatted with `core::fmt` and cut at
+//! [`LOG_MAX_LINE_LEN`] bytes.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static DISPLAY: StaticCell<LogsDisplay<'static, SPI0, Blocking>> = StaticCell::new();
+//!
+//! set_log_level(LogModule::Usb, LogLevel::Warn);
+//! set_log_level(LogModule::Wifi, LogLevel::Debug);
+//! set_log_sink(DISPLAY.init(LogsDisplay::new(oled)));
+//! set_log_to_defmt(false);
+//!
+//! log!(LogModule::App, Info, "pump on for {} s", seconds);
+//! ```
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+use crate::HeaplessString;
+
+#[doc(hidden)]
+pub use defmt as __defmt;
+
+/// Longest message passed to a [`LogSink`]
+pub const LOG_MAX_LINE_LEN: usize = 96;
+
+/// Severity, most severe first; a module logs messages up to its level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum LogLevel {
+    /// Nothing is logged
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Off,
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            4 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+/// Part of the crate a message comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LogModule {
+    Usb,
+    Wifi,
+    Http,
+    Storage,
+    Diagnostics,
+    /// Application code (G-code interpreter, user messages)
+    App,
+}
+
+const LOG_MODULES: usize = 6;
+
+/// Level of each module, indexed by `LogModule as usize`; all `Info` at boot
+static LEVELS: [AtomicU8; LOG_MODULES] =
+    [const { AtomicU8::new(LogLevel::Info as u8) }; LOG_MODULES];
+
+static TO_DEFMT: AtomicBool = AtomicBool::new(true);
+
+type SinkSlot = Option<&'static mut (dyn LogSink + Send)>;
+
+static SINK: Mutex<CriticalSectionRawMutex, RefCell<SinkSlot>> = Mutex::new(RefCell::new(None));
+
+/// Destination for log messages besides defmt
+pub trait LogSink {
+    fn write_log(&mut self, module: LogModule, level: LogLevel, message: &str);
+}
+
+pub fn set_log_level(module: LogModule, level: LogLevel) {
+    LEVELS[module as usize].store(level as u8, Ordering::Relaxed);
+}
+
+/// Set every module to `level`.
+pub fn set_log_level_all(level: LogLevel) {
+    for slot in &LEVELS {
+        slot.store(level as u8, Ordering::Relaxed);
+    }
+}
+
+pub fn log_level(module: LogModule) -> LogLevel {
+    LogLevel::from_u8(LEVELS[module as usize].load(Ordering::Relaxed))
+}
+
+pub fn log_enabled(module: LogModule, level: LogLevel) -> bool {
+    level != LogLevel::Off && level <= log_level(module)
+}
+
+/// Whether enabled messages also go to defmt (on by default).
+pub fn set_log_to_defmt(enabled: bool) {
+    TO_DEFMT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn log_to_defmt() -> bool {
+    TO_DEFMT.load(Ordering::Relaxed)
+}
+
+/// Route messages to `sink` as well; returns the sink it replaces.
+pub fn set_log_sink(
+    sink: &'static mut (dyn LogSink + Send),
+) -> Option<&'static mut (dyn LogSink + Send)> {
+    SINK.lock(|slot| slot.borrow_mut().replace(sink))
+}
+
+/// Stop routing messages to a sink; returns it.
+pub fn take_log_sink() -> Option<&'static mut (dyn LogSink + Send)> {
+    SINK.lock(|slot| slot.borrow_mut().take())
+}
+
+/// Pass a message to the sink, if any. Used by [`log!`](crate::log).
+///
+/// The sink is taken out while it writes, so a slow sink doesn't hold the critical section
+/// and a message logged from inside the sink (or from an interrupt meanwhile) skips it.
+#[doc(hidden)]
+pub fn __log_to_sink(module: LogModule, level: LogLevel, args: fmt::Arguments<'_>) {
+    let Some(sink) = take_log_sink() else {
+        return;
+    };
+    let mut line: HeaplessString<LOG_MAX_LINE_LEN> = HeaplessString::new();
+    let _ = line.write_fmt(args);
+    sink.write_log(module, level, line.as_str());
+    SINK.lock(|slot| {
+        let mut slot = slot.borrow_mut();
+        // Keep a sink set while this one was writing
+        if slot.is_none() {
+            *slot = Some(sink);
+        }
+    });
+}
+
+/// Log a message for a [`LogModule`] at a [`LogLevel`] given by name (`Error`, `Warn`,
+/// `Info`, `Debug`, `Trace`). Takes a format string with `{}` placeholders whose arguments
+/// implement both `defmt::Format` and `core::fmt::Display`.
+///
+/// ```ignore
+/// log!(LogModule::Wifi, Warn, "joining {} failed: {}", ssid, error);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($module:expr, $level:ident, $($arg:tt)+) => {{
+        let module = $module;
+        if $crate::log_enabled(module, $crate::LogLevel::$level) {
+            if $crate::log_to_defmt() {
+                $crate::__log_defmt!($level, $($arg)+);
+            }
+            $crate::__log_to_sink(module, $crate::LogLevel::$level, format_args!($($arg)+));
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_defmt {
+    (Error, $($arg:tt)+) => { $crate::__defmt::error!($($arg)+) };
+    (Warn, $($arg:tt)+) => { $crate::__defmt::warn!($($arg)+) };
+    (Info, $($arg:tt)+) => { $crate::__defmt::info!($($arg)+) };
+    (Debug, $($arg:tt)+) => { $crate::__defmt::debug!($($arg)+) };
+    (Trace, $($arg:tt)+) => { $crate::__defmt::trace!($($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_filter_per_module() {
+        set_log_level(LogModule::Http, LogLevel::Warn);
+        assert!(log_enabled(LogModule::Http, LogLevel::Error));
+        assert!(log_enabled(LogModule::Http, LogLevel::Warn));
+        assert!(!log_enabled(LogModule::Http, LogLevel::Info));
+        assert!(log_enabled(LogModule::Usb, LogLevel::Info));
+        assert!(!log_enabled(LogModule::Usb, LogLevel::Debug));
+        set_log_level(LogModule::Http, LogLevel::Off);
+        assert!(!log_enabled(LogModule::Http, LogLevel::Error));
+        assert!(!log_enabled(LogModule::Http, LogLevel::Off));
+    }
+}
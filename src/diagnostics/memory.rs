@@ -0,0 +1,344 @@
+//! memory.rs — RAM budget diagnostics: static RAM, stack high-water marks, buffer usage
+//!
+//! Stacks are measured by painting: unused stack is filled with a pattern once, and the
+//! deepest overwritten word later tells how much was ever used. All thread-mode and
+//! interrupt-mode embassy tasks (and interrupt handlers) share the main stack, so its
+//! high-water mark is the worst case across them; core 1 has its own stack.
+//!
+//! Subsystems declare their fixed buffers as [`BufferUsage`] statics and report fill
+//! levels, so the peak usage of e.g. socket or queue buffers shows up next to the stacks.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static RX_QUEUE: BufferUsage = BufferUsage::new("rxq", 32);
+//! static mut CORE1_STACK: Stack<4096> = Stack::new();
+//!
+//! paint_main_stack(); // first thing in main
+//! let core1 = StackRegion::paint_core1(unsafe { &mut *addr_of_mut!(CORE1_STACK) });
+//! spawn_core1(p.CORE1, unsafe { &mut *addr_of_mut!(CORE1_STACK) }, core1_main);
+//!
+//! let mut diagnostics = MemoryDiagnostics::<4>::new();
+//! diagnostics.add_stack("core1", core1)?;
+//! diagnostics.add_buffer(&RX_QUEUE)?;
+//!
+//! RX_QUEUE.record(queue.len());
+//! diagnostics.render(&mut oled)?;
+//! ```
+
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+
+use embassy_rp::multicore::Stack;
+use portable_atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{HeaplessString, PushError, TextDisplay};
+
+const PAINT: u32 = 0xC0DE_5AFE;
+/// Stack kept unpainted below the caller of [`paint_main_stack`]
+const PAINT_GUARD: usize = 256;
+
+static MAIN_STACK_PAINTED: AtomicBool = AtomicBool::new(false);
+
+// Provided by the cortex-m-rt linker script
+unsafe extern "C" {
+    static __sdata: u32;
+    static __sheap: u32;
+    static _stack_start: u32;
+}
+
+fn ram_start() -> usize {
+    (&raw const __sdata) as usize
+}
+
+fn static_end() -> usize {
+    (&raw const __sheap) as usize
+}
+
+fn stack_top() -> usize {
+    (&raw const _stack_start) as usize
+}
+
+fn current_sp() -> usize {
+    let marker = 0u8;
+    core::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Bytes of RAM from the start of `.data` to the top of the main stack
+pub fn total_ram() -> usize {
+    stack_top() - ram_start()
+}
+
+/// Bytes taken by statics (`.data`, `.bss`, `.uninit`), including embassy task storage
+pub fn static_ram() -> usize {
+    static_end() - ram_start()
+}
+
+/// Bytes between the statics and the current stack pointer, never touched so far by anyone
+pub fn free_ram() -> usize {
+    current_sp().saturating_sub(static_end())
+}
+
+/// Paint the unused part of the main stack so [`main_stack`] can report a high-water mark.
+/// Call it first thing in `main`; calling it again restarts the measurement.
+pub fn paint_main_stack() {
+    let end = current_sp().saturating_sub(PAINT_GUARD) & !3;
+    let region = StackRegion {
+        bottom: static_end(),
+        size: stack_top() - static_end(),
+    };
+    // SAFETY: everything below the stack pointer is unused by the thread; an interrupt that
+    // preempts the painting only leaves a (harmless) higher high-water mark behind.
+    unsafe { region.paint_up_to(end) };
+    MAIN_STACK_PAINTED.store(true, Ordering::Release);
+}
+
+/// The main stack, or `None` until [`paint_main_stack`] has run
+pub fn main_stack() -> Option<StackRegion> {
+    MAIN_STACK_PAINTED
+        .load(Ordering::Acquire)
+        .then(|| StackRegion {
+            bottom: static_end(),
+            size: stack_top() - static_end(),
+        })
+}
+
+/// A painted, full-descending stack that can report how deep it has ever been used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct StackRegion {
+    bottom: usize,
+    size: usize,
+}
+
+impl StackRegion {
+    /// Paint a core 1 stack. Must be called before passing it to `spawn_core1`.
+    pub fn paint_core1<const SIZE: usize>(stack: &mut Stack<SIZE>) -> Self {
+        Self::paint_slice(&mut stack.mem)
+    }
+
+    /// Paint any memory that will be used as a stack, e.g. for a custom context.
+    pub fn paint_slice(memory: &mut [MaybeUninit<u8>]) -> Self {
+        let start = memory.as_mut_ptr() as usize;
+        let bottom = start.next_multiple_of(4);
+        let region = Self {
+            bottom,
+            size: (start + memory.len()).saturating_sub(bottom) & !3,
+        };
+        // SAFETY: the region lies within `memory`, which we borrow mutably.
+        unsafe { region.paint_up_to(region.bottom + region.size) };
+        region
+    }
+
+    /// Total size in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Most bytes ever in use, counted from the top
+    pub fn high_water(&self) -> usize {
+        let words = self.size / 4;
+        let untouched = (0..words)
+            // SAFETY: the region is word aligned and was valid memory when painted; stacks
+            // are statics (or the main stack) and stay valid for the program's lifetime.
+            .take_while(|&i| unsafe {
+                core::ptr::read_volatile((self.bottom as *const u32).add(i)) == PAINT
+            })
+            .count();
+        (words - untouched) * 4
+    }
+
+    /// # Safety
+    ///
+    /// `[bottom, end)` must be unused, writable memory inside the region.
+    unsafe fn paint_up_to(&self, end: usize) {
+        let mut addr = self.bottom;
+        while addr + 4 <= end {
+            unsafe { core::ptr::write_volatile(addr as *mut u32, PAINT) };
+            addr += 4;
+        }
+    }
+}
+
+/// Fill level of a fixed-size buffer, with its peak since boot
+pub struct BufferUsage {
+    name: &'static str,
+    capacity: usize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl BufferUsage {
+    pub const fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Report the current fill level.
+    pub fn record(&self, used: usize) {
+        self.used.store(used, Ordering::Relaxed);
+        self.peak.fetch_max(used, Ordering::Relaxed);
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Stack(&'static str, StackRegion),
+    Buffer(&'static BufferUsage),
+}
+
+/// Collects memory figures for display: RAM, the main stack, and up to `N` extra stacks
+/// and buffers.
+pub struct MemoryDiagnostics<const N: usize> {
+    entries: [Option<Entry>; N],
+}
+
+impl<const N: usize> MemoryDiagnostics<N> {
+    pub const fn new() -> Self {
+        Self { entries: [None; N] }
+    }
+
+    pub fn add_stack(&mut self, name: &'static str, stack: StackRegion) -> Result<(), PushError> {
+        self.push(Entry::Stack(name, stack))
+    }
+
+    pub fn add_buffer(&mut self, buffer: &'static BufferUsage) -> Result<(), PushError> {
+        self.push(Entry::Buffer(buffer))
+    }
+
+    /// One line per figure (`name used/total`), as many as fit on `display`:
+    /// static RAM, main stack high-water mark, then the added stacks and buffers (peaks).
+    pub fn render<D: TextDisplay>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut content: HeaplessString<160> = HeaplessString::new();
+        let mut lines = 0;
+        let mut line = |name: &str, used: usize, total: usize, bytes: bool| {
+            if lines == display.max_lines() {
+                return;
+            }
+            if lines > 0 {
+                let _ = content.push('\n');
+            }
+            let _ = content.push_str(usage_line(name, used, total, bytes).as_str());
+            lines += 1;
+        };
+
+        line("RAM", static_ram(), total_ram(), true);
+        if let Some(stack) = main_stack() {
+            line("stack", stack.high_water(), stack.size(), true);
+        }
+        for entry in self.entries.iter().flatten() {
+            match entry {
+                Entry::Stack(name, stack) => line(name, stack.high_water(), stack.size(), true),
+                Entry::Buffer(buffer) => {
+                    line(buffer.name(), buffer.peak(), buffer.capacity(), false)
+                }
+            }
+        }
+        display.display_str(content.as_str())
+    }
+
+    fn push(&mut self, entry: Entry) -> Result<(), PushError> {
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(PushError)?;
+        *slot = Some(entry);
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for MemoryDiagnostics<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `name used/total` in 16 characters or less; `name` is cut to 5 characters.
+fn usage_line(name: &str, used: usize, total: usize, bytes: bool) -> HeaplessString<24> {
+    let mut line = HeaplessString::new();
+    for c in name.chars().take(5) {
+        let _ = line.push(c);
+    }
+    let _ = line.push(' ');
+    if bytes {
+        let _ = write!(line, "{}/{}", format_bytes(used), format_bytes(total));
+    } else {
+        let _ = write!(line, "{}/{}", used, total);
+    }
+    line
+}
+
+/// Compact byte count: `512B`, `6.1K`, `132K`
+pub fn format_bytes(bytes: usize) -> HeaplessString<8> {
+    let mut out = HeaplessString::new();
+    if bytes < 1024 {
+        let _ = write!(out, "{}B", bytes);
+    } else if bytes < 10 * 1024 {
+        let tenths = bytes * 10 / 1024;
+        let _ = write!(out, "{}.{}K", tenths / 10, tenths % 10);
+    } else {
+        let _ = write!(out, "{}K", bytes / 1024);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn painted_stack_high_water() {
+        let mut memory = [MaybeUninit::<u8>::uninit(); 256];
+        let region = StackRegion::paint_slice(&mut memory);
+        assert!(region.size() >= 252);
+        assert_eq!(region.high_water(), 0);
+
+        // Simulate 40 bytes of stack use from the top
+        let top = region.bottom + region.size();
+        for addr in (top - 40..top).step_by(4) {
+            unsafe { core::ptr::write_volatile(addr as *mut u32, 0) };
+        }
+        assert_eq!(region.high_water(), 40);
+    }
+
+    #[test]
+    fn buffer_usage_tracks_peak() {
+        let buffer = BufferUsage::new("rxq", 32);
+        buffer.record(12);
+        buffer.record(3);
+        assert_eq!(buffer.used(), 3);
+        assert_eq!(buffer.peak(), 12);
+    }
+
+    #[test]
+    fn formats_lines() {
+        assert_eq!(format_bytes(512).as_str(), "512B");
+        assert_eq!(format_bytes(6246).as_str(), "6.0K");
+        assert_eq!(format_bytes(135_168).as_str(), "132K");
+        assert_eq!(
+            usage_line("stack", 6246, 122_880, true).as_str(),
+            "stack 6.0K/120K"
+        );
+        assert_eq!(usage_line("wifi_rx", 12, 16, false).as_str(), "wifi_ 12/16");
+    }
+}
@@ -0,0 +1,164 @@
+//! metrics.rs — named numeric gauges collected in one registry for display or publishing
+//!
+//! A [`Gauge`] is a `static` any task can update lock-free. Subsystems register their gauges
+//! with a [`MetricsRegistry`], which the dashboard renders and telemetry iterates.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static PUMP_RUNS: Gauge = Gauge::new("pump", "runs");
+//!
+//! let mut metrics = MetricsRegistry::<8>::new();
+//! metrics.register(&PUMP_RUNS)?;
+//!
+//! PUMP_RUNS.increment();
+//! for gauge in metrics.iter() {
+//!     info!("{} = {} {}", gauge.name(), gauge.get(), gauge.unit());
+//! }
+//! metrics.render(&mut lcd)?;
+//! ```
+
+use core::fmt::Write;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use crate::{HeaplessString, PushError, TextDisplay, write_justified};
+
+/// Named value with a unit, e.g. `wake 120 us`
+pub struct Gauge {
+    name: &'static str,
+    unit: &'static str,
+    value: AtomicU32,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, unit: &'static str) -> Self {
+        Self {
+            name,
+            unit,
+            value: AtomicU32::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn unit(&self) -> &'static str {
+        self.unit
+    }
+
+    pub fn get(&self) -> u32 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, value: u32) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    /// Keep the larger of the current and the new value.
+    pub fn set_max(&self, value: u32) {
+        self.value.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub fn increment(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Up to `N` gauges
+pub struct MetricsRegistry<const N: usize> {
+    gauges: [Option<&'static Gauge>; N],
+}
+
+impl<const N: usize> MetricsRegistry<N> {
+    pub const fn new() -> Self {
+        Self { gauges: [None; N] }
+    }
+
+    pub fn register(&mut self, gauge: &'static Gauge) -> Result<(), PushError> {
+        let slot = self
+            .gauges
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(PushError)?;
+        *slot = Some(gauge);
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static Gauge> + '_ {
+        self.gauges.iter().flatten().copied()
+    }
+
+    /// First gauge called `name`
+    pub fn get(&self, name: &str) -> Option<&'static Gauge> {
+        self.iter().find(|gauge| gauge.name() == name)
+    }
+
+    /// One line per gauge, the name on the left and the value and unit on the right, as many
+    /// as fit on `display`.
+    pub fn render<D: TextDisplay>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut content: HeaplessString<160> = HeaplessString::new();
+        let max_chars = display.max_chars_per_line();
+        for (i, gauge) in self.iter().take(display.max_lines()).enumerate() {
+            if i > 0 {
+                let _ = content.push('\n');
+            }
+            let mut value: HeaplessString<24> = HeaplessString::new();
+            let _ = write!(value, "{} {}", gauge.get(), gauge.unit());
+            let mut line: HeaplessString<40> = HeaplessString::new();
+            let _ = write_justified(&mut line, gauge.name(), value.as_str(), max_chars);
+            for c in line.as_str().chars().take(max_chars) {
+                let _ = content.push(c);
+            }
+        }
+        display.display_str(content.as_str())
+    }
+}
+
+impl<const N: usize> Default for MetricsRegistry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockTextDisplay;
+
+    static A: Gauge = Gauge::new("a", "us");
+    static B: Gauge = Gauge::new("b", "runs");
+
+    #[test]
+    fn registry_holds_gauges() {
+        let mut metrics = MetricsRegistry::<2>::new();
+        metrics.register(&A).unwrap();
+        metrics.register(&B).unwrap();
+        assert_eq!(metrics.register(&A), Err(PushError));
+
+        A.set(10);
+        A.set_max(5);
+        B.increment();
+        B.increment();
+        assert_eq!(metrics.get("a").map(Gauge::get), Some(10));
+        assert_eq!(metrics.get("b").map(Gauge::get), Some(2));
+        assert!(metrics.get("c").is_none());
+        assert_eq!(metrics.iter().count(), 2);
+    }
+
+    #[test]
+    fn render_right_aligns_values() {
+        static WAKE: Gauge = Gauge::new("wake", "us");
+        static PUMP: Gauge = Gauge::new("pump", "runs");
+        let mut metrics = MetricsRegistry::<2>::new();
+        metrics.register(&WAKE).unwrap();
+        metrics.register(&PUMP).unwrap();
+        WAKE.set(120);
+        PUMP.set(3);
+
+        let mut screen = MockTextDisplay::new(2, 12);
+        metrics.render(&mut screen).unwrap();
+        assert_eq!(screen.content(), "wake  120 us\npump  3 runs");
+    }
+}
@@ -0,0 +1,15 @@
+mod executor_monitor;
+mod log;
+mod memory;
+mod metrics;
+mod self_test;
+#[cfg(feature = "test-harness")]
+mod test_harness;
+
+pub use executor_monitor::*;
+pub use log::*;
+pub use memory::*;
+pub use metrics::*;
+pub use self_test::*;
+#[cfg(feature = "test-harness")]
+pub use test_harness::*;
@@ -0,0 +1,269 @@
+//! self_test.rs — boot-time self-test with a pass/fail report and a health flag
+//!
+//! After bringing up its peripherals the firmware runs one check per device through a
+//! [`SelfTest`]: sync checks with [`SelfTest::check`], async ones (WiFi chip, sensors
+//! that need a conversion) with [`SelfTest::check_async`], which gives up after
+//! [`SELF_TEST_TIMEOUT`] so a hung bus can't stall the boot. The report goes to defmt with
+//! [`SelfTest::log`] and to a screen with [`SelfTest::render`] (failures first), and
+//! [`SelfTest::publish`] stores the verdict in a [`HealthFlag`] that watchdog or
+//! telemetry code can read later.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static HEALTH: HealthFlag = HealthFlag::new();
+//!
+//! let mut test = SelfTest::<8>::new();
+//! test.check("lcd", || probe_i2c(&mut i2c, 0x27));
+//! test.check("oled", || oled.clear());
+//! test.check_async("wifi", wifi.try_join(SSID, PASSWORD)).await;
+//! test.check("servo pwm", || servo.set_angle(90.0));
+//! test.log();
+//! test.render(&mut lcd)?;
+//! test.publish(&HEALTH);
+//!
+//! // Watchdog task: only keep feeding while healthy
+//! if HEALTH.is_healthy() { watchdog.feed(); }
+//! ```
+
+use core::fmt::{Display, Write};
+use core::future::Future;
+
+use embassy_time::{Duration, with_timeout};
+use embedded_hal::i2c::I2c;
+use portable_atomic::{AtomicBool, Ordering};
+
+use crate::{HeaplessString, LogModule, TextDisplay};
+
+/// Longest an async check may take
+pub const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Failure text kept per check; longer error messages are cut
+pub const SELF_TEST_MAX_DETAIL_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum SelfTestError {
+    #[error("No ACK")]
+    NoAck,
+    #[error("Timed out")]
+    Timeout,
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    /// Error message of a failed check
+    pub detail: HeaplessString<SELF_TEST_MAX_DETAIL_LEN>,
+}
+
+/// Overall health, shared between the self-test and whoever acts on it
+pub struct HealthFlag(AtomicBool);
+
+impl HealthFlag {
+    /// Unhealthy until a self-test publishes a pass
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Set by [`SelfTest::publish`]; runtime monitors may clear it too.
+    pub fn set(&self, healthy: bool) {
+        self.0.store(healthy, Ordering::Relaxed);
+    }
+}
+
+impl Default for HealthFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Results of up to `N` checks
+pub struct SelfTest<const N: usize> {
+    results: [Option<CheckResult>; N],
+    /// Checks that didn't fit; they count as failures
+    overflow: usize,
+}
+
+impl<const N: usize> SelfTest<N> {
+    pub const fn new() -> Self {
+        Self {
+            results: [const { None }; N],
+            overflow: 0,
+        }
+    }
+
+    /// Record the outcome of a check that already ran; returns whether it passed.
+    pub fn record<E: Display>(&mut self, name: &'static str, result: Result<(), E>) -> bool {
+        let mut detail = HeaplessString::new();
+        if let Err(e) = &result {
+            // Cut to fit
+            let _ = write!(detail, "{e}");
+        }
+        let passed = result.is_ok();
+        match self.results.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => {
+                *slot = Some(CheckResult {
+                    name,
+                    passed,
+                    detail,
+                })
+            }
+            None => self.overflow += 1,
+        }
+        passed
+    }
+
+    /// Run a sync check and record it.
+    pub fn check<E: Display>(
+        &mut self,
+        name: &'static str,
+        check: impl FnOnce() -> Result<(), E>,
+    ) -> bool {
+        self.record(name, check())
+    }
+
+    /// Run an async check with [`SELF_TEST_TIMEOUT`] and record it.
+    pub async fn check_async<E: Display>(
+        &mut self,
+        name: &'static str,
+        check: impl Future<Output = Result<(), E>>,
+    ) -> bool {
+        match with_timeout(SELF_TEST_TIMEOUT, check).await {
+            Ok(result) => self.record(name, result),
+            Err(_) => self.record(name, Err(SelfTestError::Timeout)),
+        }
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().flatten()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results().filter(|r| !r.passed)
+    }
+
+    /// `true` if every check passed
+    pub fn passed(&self) -> bool {
+        self.overflow == 0 && self.failures().next().is_none()
+    }
+
+    /// Store the verdict in `flag`.
+    pub fn publish(&self, flag: &HealthFlag) {
+        flag.set(self.passed());
+    }
+
+    /// One log line per check.
+    pub fn log(&self) {
+        for result in self.results() {
+            if result.passed {
+                crate::log!(
+                    LogModule::Diagnostics,
+                    Info,
+                    "Self-test: {} OK",
+                    result.name
+                );
+            } else {
+                crate::log!(
+                    LogModule::Diagnostics,
+                    Warn,
+                    "Self-test: {} FAILED: {}",
+                    result.name,
+                    result.detail.as_str()
+                );
+            }
+        }
+        if self.overflow > 0 {
+            crate::log!(
+                LogModule::Diagnostics,
+                Warn,
+                "Self-test: {} checks not recorded",
+                self.overflow
+            );
+        }
+    }
+
+    /// Summary line, then failed checks, then passed ones, as many as fit on `display`.
+    pub fn render<D: TextDisplay>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut content: HeaplessString<160> = HeaplessString::new();
+        let failed = self.failures().count() + self.overflow;
+        let mut summary: HeaplessString<40> = HeaplessString::new();
+        let _ = if failed == 0 {
+            write!(summary, "Self-test OK")
+        } else {
+            write!(summary, "Self-test FAIL {failed}")
+        };
+        let max_chars = display.max_chars_per_line();
+        let push_line = |content: &mut HeaplessString<160>, line: &str| {
+            if !content.is_empty() {
+                let _ = content.push('\n');
+            }
+            for c in line.chars().take(max_chars) {
+                let _ = content.push(c);
+            }
+        };
+        push_line(&mut content, summary.as_str());
+        let results = self
+            .failures()
+            .chain(self.results().filter(|r| r.passed))
+            .take(display.max_lines().saturating_sub(1));
+        for result in results {
+            let mut line: HeaplessString<40> = HeaplessString::new();
+            let _ = if result.passed {
+                write!(line, "{} OK", result.name)
+            } else {
+                write!(line, "{}: {}", result.name, result.detail.as_str())
+            };
+            push_line(&mut content, line.as_str());
+        }
+        display.display_str(content.as_str())
+    }
+}
+
+impl<const N: usize> Default for SelfTest<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that a device answers at `address` with an empty write.
+pub fn probe_i2c<I: I2c>(i2c: &mut I, address: u8) -> Result<(), SelfTestError> {
+    i2c.write(address, &[]).map_err(|_| SelfTestError::NoAck)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockTextDisplay;
+
+    #[test]
+    fn report_lists_failures_first() {
+        let mut test = SelfTest::<4>::new();
+        assert!(test.check("lcd", || Ok::<(), SelfTestError>(())));
+        assert!(!test.check("oled", || Err(SelfTestError::NoAck)));
+        assert!(test.record("servo", Ok::<(), SelfTestError>(())));
+        assert!(!test.passed());
+
+        let mut screen = MockTextDisplay::new(3, 16);
+        test.render(&mut screen).unwrap();
+        assert_eq!(screen.content(), "Self-test FAIL 1\noled: No ACK\nlcd OK");
+
+        let flag = HealthFlag::new();
+        flag.set(true);
+        test.publish(&flag);
+        assert!(!flag.is_healthy());
+    }
+
+    #[test]
+    fn overflow_fails_the_test() {
+        let mut test = SelfTest::<1>::new();
+        test.record("a", Ok::<(), SelfTestError>(()));
+        assert!(test.passed());
+        test.record("b", Ok::<(), SelfTestError>(()));
+        assert!(!test.passed());
+    }
+}
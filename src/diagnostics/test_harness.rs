@@ -0,0 +1,250 @@
+//! test_harness.rs — hardware-in-the-loop command protocol (feature `test-harness`)
+//!
+//! A host-side test runner drives the firmware's peripherals over a serial link and checks
+//! the answers, so drivers can be exercised on a real Pico in CI. The protocol is one ASCII
+//! line per command and one line per response:
+//!
+//! | Command              | Response         | Action                                    |
+//! |----------------------|------------------|-------------------------------------------|
+//! | `ping`               | `OK pong`        | Link check                                |
+//! | `version`            | `OK 0.1.0`       | darkpicolib version                       |
+//! | `display <n> <text>` | `OK`             | Show `text` on display `n` (`\n` = break) |
+//! | `servo <n> <deg>`    | `OK`             | Move servo `n`                            |
+//! | `button <n>`         | `OK 1` / `OK 0`  | Whether button `n` is pressed             |
+//!
+//! Failures answer `ERR <reason>`. The firmware maps device numbers to its drivers by
+//! implementing [`DeviceActions`], the trait MQTT commands call too; [`TestHarness::run`]
+//! serves the protocol on a UART, [`TestHarness::serve`] on any `embedded_io_async` stream
+//! (USB CDC through [`CdcIo`](crate::CdcIo), a TCP socket), and
+//! [`TestHarness::handle_line`] from anywhere else.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // `Bench` implements `DeviceActions`, see device_actions.rs
+//! let uart = Uart::new(p.UART0, p.PIN_0, p.PIN_1, Irqs, p.DMA_CH0, p.DMA_CH1, Default::default());
+//! TestHarness::new(bench).run(uart).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_rp::uart::{self, Async, Uart};
+use embassy_time::{Duration, Timer};
+
+use crate::{ActionError, DARKPICOLIB_BUILD_INFO, DeviceActions, HeaplessString, UartIo};
+
+/// Longest command line
+pub const HARNESS_MAX_LINE_LEN: usize = 128;
+/// Longest response line
+pub const HARNESS_MAX_RESPONSE_LEN: usize = 64;
+
+/// Pause after a failed read, so a disconnected stream doesn't spin
+const READ_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum HarnessError {
+    #[error("unknown command")]
+    UnknownCommand,
+    #[error("bad argument")]
+    BadArgument,
+    #[error("line too long")]
+    LineTooLong,
+    #[error("{0}")]
+    Action(#[from] ActionError),
+}
+
+/// One parsed command line
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum HarnessCommand<'a> {
+    Ping,
+    Version,
+    /// `text` still has `\n` escapes
+    Display {
+        index: u8,
+        text: &'a str,
+    },
+    Servo {
+        index: u8,
+        degrees: f32,
+    },
+    Button {
+        index: u8,
+    },
+}
+
+impl<'a> HarnessCommand<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, HarnessError> {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let (index, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let index = || index.parse::<u8>().map_err(|_| HarnessError::BadArgument);
+        Ok(match command {
+            "ping" => Self::Ping,
+            "version" => Self::Version,
+            "display" => Self::Display {
+                index: index()?,
+                text: rest,
+            },
+            "servo" => Self::Servo {
+                index: index()?,
+                degrees: rest.trim().parse().map_err(|_| HarnessError::BadArgument)?,
+            },
+            "button" => Self::Button { index: index()? },
+            _ => return Err(HarnessError::UnknownCommand),
+        })
+    }
+}
+
+pub struct TestHarness<D: DeviceActions> {
+    devices: D,
+}
+
+impl<D: DeviceActions> TestHarness<D> {
+    pub fn new(devices: D) -> Self {
+        Self { devices }
+    }
+
+    pub fn devices(&mut self) -> &mut D {
+        &mut self.devices
+    }
+
+    /// Execute one command line and return the response line (without line ending).
+    pub async fn handle_line(&mut self, line: &str) -> HeaplessString<HARNESS_MAX_RESPONSE_LEN> {
+        let mut response = HeaplessString::new();
+        let _ = match self.execute(line).await {
+            Ok(Some(value)) => write!(response, "OK {}", value.as_str()),
+            Ok(None) => write!(response, "OK"),
+            Err(e) => write!(response, "ERR {e}"),
+        };
+        response
+    }
+
+    /// Serve commands from `uart` forever.
+    pub async fn run<T: uart::Instance>(&mut self, uart: Uart<'_, T, Async>) -> ! {
+        self.serve(UartIo::new(uart)).await
+    }
+
+    /// Serve commands from any byte stream forever. After a read error or the end of the
+    /// stream, reading is retried shortly after.
+    pub async fn serve<S>(&mut self, mut io: S) -> !
+    where
+        S: embedded_io_async::Read + embedded_io_async::Write,
+    {
+        let mut line: HeaplessString<HARNESS_MAX_LINE_LEN> = HeaplessString::new();
+        let mut overflow = false;
+        loop {
+            let mut byte = [0u8];
+            if !matches!(io.read(&mut byte).await, Ok(1)) {
+                Timer::after(READ_RETRY_DELAY).await;
+                continue;
+            }
+            match byte[0] {
+                b'\r' => {}
+                b'\n' => {
+                    let mut response = if overflow {
+                        let mut response = HeaplessString::new();
+                        let _ = write!(response, "ERR {}", HarnessError::LineTooLong);
+                        response
+                    } else {
+                        self.handle_line(line.as_str()).await
+                    };
+                    let _ = response.push('\n');
+                    let _ = io.write_all(response.as_str().as_bytes()).await;
+                    let _ = io.flush().await;
+                    line.clear();
+                    overflow = false;
+                }
+                b => overflow |= line.push(b as char).is_err(),
+            }
+        }
+    }
+
+    async fn execute(&mut self, line: &str) -> Result<Option<HeaplessString<16>>, HarnessError> {
+        let mut value = HeaplessString::new();
+        match HarnessCommand::parse(line)? {
+            HarnessCommand::Ping => {
+                let _ = value.push_str("pong");
+            }
+            HarnessCommand::Version => {
+                let _ = value.push_str(DARKPICOLIB_BUILD_INFO.version);
+            }
+            HarnessCommand::Display { index, text } => {
+                let mut unescaped: HeaplessString<HARNESS_MAX_LINE_LEN> = HeaplessString::new();
+                for (i, part) in text.split("\\n").enumerate() {
+                    if i > 0 {
+                        let _ = unescaped.push('\n');
+                    }
+                    let _ = unescaped.push_str(part);
+                }
+                self.devices.display_text(index, unescaped.as_str()).await?;
+                return Ok(None);
+            }
+            HarnessCommand::Servo { index, degrees } => {
+                self.devices.set_servo_angle(index, degrees).await?;
+                return Ok(None);
+            }
+            HarnessCommand::Button { index } => {
+                let pressed = self.devices.button_pressed(index).await?;
+                let _ = value.push(if pressed { '1' } else { '0' });
+            }
+        }
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::device_actions::tests::Bench;
+
+    fn respond(harness: &mut TestHarness<Bench>, line: &str) -> HeaplessString<64> {
+        block_on(harness.handle_line(line))
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(HarnessCommand::parse("ping\r"), Ok(HarnessCommand::Ping));
+        assert_eq!(
+            HarnessCommand::parse("servo 2 45.5"),
+            Ok(HarnessCommand::Servo {
+                index: 2,
+                degrees: 45.5
+            })
+        );
+        assert_eq!(
+            HarnessCommand::parse("display 0 Hello world"),
+            Ok(HarnessCommand::Display {
+                index: 0,
+                text: "Hello world"
+            })
+        );
+        assert_eq!(
+            HarnessCommand::parse("button x"),
+            Err(HarnessError::BadArgument)
+        );
+        assert_eq!(
+            HarnessCommand::parse("reboot"),
+            Err(HarnessError::UnknownCommand)
+        );
+    }
+
+    #[test]
+    fn executes_against_devices() {
+        let mut harness = TestHarness::new(Bench::default());
+        assert_eq!(respond(&mut harness, "ping").as_str(), "OK pong");
+        assert_eq!(respond(&mut harness, "display 0 Hi\\nthere").as_str(), "OK");
+        assert_eq!(harness.devices().text.as_str(), "Hi\nthere");
+        assert_eq!(respond(&mut harness, "servo 0 90").as_str(), "OK");
+        assert_eq!(harness.devices().angle, 90.0);
+        assert_eq!(
+            respond(&mut harness, "display 3 x").as_str(),
+            "ERR no such device"
+        );
+        assert_eq!(
+            respond(&mut harness, "button 0").as_str(),
+            "ERR not supported"
+        );
+    }
+}
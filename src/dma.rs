@@ -0,0 +1,92 @@
+//! dma.rs — async DMA memory copies and peripheral streaming helpers
+//!
+//! The CPU is free (other tasks run) while the DMA channel moves the data.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut ch = p.DMA_CH1;
+//! dma_copy(ch.reborrow(), &framebuffer, &mut back_buffer).await?;
+//!
+//! // Stream ADC samples from the ADC FIFO (ADC must be in FIFO/DREQ mode)
+//! let fifo = embassy_rp::pac::ADC.fifo().as_ptr() as *const u16;
+//! unsafe { dma_read_peripheral(ch.reborrow(), fifo, &mut samples, TreqSel::ADC).await };
+//! ```
+
+use embassy_rp::Peri;
+use embassy_rp::dma::{self, Channel, Word};
+use embassy_rp::pac::dma::vals::TreqSel;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum DmaError {
+    #[error("Source and destination lengths differ")]
+    LengthMismatch,
+}
+
+/// Copy `src` into `dst` with DMA. Both slices must have the same length.
+pub async fn dma_copy<C: Channel, W: Word>(
+    ch: Peri<'_, C>,
+    src: &[W],
+    dst: &mut [W],
+) -> Result<(), DmaError> {
+    if src.len() != dst.len() {
+        return Err(DmaError::LengthMismatch);
+    }
+    if src.is_empty() {
+        return Ok(());
+    }
+    // SAFETY: both buffers stay borrowed until the transfer completes or is dropped, and
+    // dropping the transfer aborts the channel.
+    unsafe { dma::copy(ch, src, dst) }.await;
+    Ok(())
+}
+
+/// Copy as much of `src` into `dst` as fits and return the number of words copied.
+pub async fn dma_copy_prefix<C: Channel, W: Word>(
+    ch: Peri<'_, C>,
+    src: &[W],
+    dst: &mut [W],
+) -> usize {
+    let len = src.len().min(dst.len());
+    if len > 0 {
+        // SAFETY: as in `dma_copy`
+        unsafe { dma::copy(ch, &src[..len], &mut dst[..len]) }.await;
+    }
+    len
+}
+
+/// Fill `dst` from a peripheral data register, paced by the peripheral's DREQ.
+///
+/// # Safety
+///
+/// `from` must be a readable peripheral FIFO/data register of word size `W`, and `dreq`
+/// must be the matching data request line with the peripheral set up to raise it.
+pub async unsafe fn dma_read_peripheral<C: Channel, W: Word>(
+    ch: Peri<'_, C>,
+    from: *const W,
+    dst: &mut [W],
+    dreq: TreqSel,
+) {
+    if dst.is_empty() {
+        return;
+    }
+    unsafe { dma::read(ch, from, dst, dreq) }.await;
+}
+
+/// Write `src` to a peripheral data register, paced by the peripheral's DREQ.
+///
+/// # Safety
+///
+/// `to` must be a writable peripheral FIFO/data register of word size `W`, and `dreq` must
+/// be the matching data request line with the peripheral set up to raise it.
+pub async unsafe fn dma_write_peripheral<C: Channel, W: Word>(
+    ch: Peri<'_, C>,
+    src: &[W],
+    to: *mut W,
+    dreq: TreqSel,
+) {
+    if src.is_empty() {
+        return;
+    }
+    unsafe { dma::write(ch, src, to, dreq) }.await;
+}
@@ -0,0 +1,143 @@
+//! easing.rs — easing curves for motion, in fixed point
+//!
+//! An [`Easing`] maps progress through a move (0 at the start, 1 at the end) to the
+//! fraction of the distance covered. [`Easing::Linear`] moves at a constant speed and stops
+//! dead; [`Easing::EaseInOutSine`] starts and stops gently; [`Easing::EaseOutCubic`] starts
+//! fast and settles softly, like something thrown into place. The curves are
+//! [`LerpTable`]s, so they cost a table lookup in a per-frame loop on the FPU-less RP2040,
+//! or one interpolator read with [`Easing::apply_blended`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! servo.move_to_eased(90.0, 60.0, Easing::EaseInOutSine).await?;
+//!
+//! const NOD: &[Keyframe] = &[
+//!     Keyframe::new(0, 0, 0.0),
+//!     Keyframe::new(400, 0, 30.0).eased(Easing::EaseOutCubic),
+//! ];
+//! ```
+
+use fixed::types::I16F16;
+
+use crate::{Blender, LerpTable};
+
+/// (1 - cos(πt)) / 2 in 64 steps
+const EASE_IN_OUT_SINE: LerpTable<'static> = LerpTable::new(&[
+    0, 39, 158, 355, 630, 982, 1411, 1915, //
+    2494, 3146, 3869, 4662, 5522, 6448, 7438, 8488, //
+    9597, 10762, 11980, 13248, 14563, 15922, 17321, 18758, //
+    20228, 21728, 23256, 24806, 26375, 27960, 29556, 31160, //
+    32767, 34375, 35979, 37575, 39160, 40729, 42279, 43807, //
+    45307, 46777, 48214, 49613, 50972, 52287, 53555, 54773, //
+    55938, 57047, 58097, 59087, 60013, 60873, 61666, 62389, //
+    63041, 63620, 64124, 64553, 64905, 65180, 65377, 65496, //
+    65535,
+]);
+
+/// 1 - (1 - t)³ in 64 steps
+const EASE_OUT_CUBIC: LerpTable<'static> = LerpTable::new(&[
+    0, 3024, 5954, 8791, 11536, 14191, 16758, 19237, //
+    21632, 23942, 26170, 28316, 30384, 32373, 34285, 36123, //
+    37887, 39580, 41201, 42754, 44239, 45659, 47013, 48305, //
+    49535, 50705, 51817, 52872, 53871, 54816, 55709, 56551, //
+    57343, 58087, 58785, 59438, 60047, 60614, 61141, 61629, //
+    62079, 62493, 62873, 63220, 63535, 63820, 64077, 64307, //
+    64511, 64691, 64849, 64986, 65103, 65202, 65285, 65353, //
+    65407, 65449, 65481, 65504, 65519, 65528, 65533, 65535, //
+    65535,
+]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseInOutSine,
+    EaseOutCubic,
+}
+
+impl Easing {
+    /// Fraction of the distance covered at `progress`; both run from 0 to 1, and progress
+    /// outside that range is clamped.
+    pub fn apply(&self, progress: I16F16) -> I16F16 {
+        self.eased(progress, |table, x| table.sample(x))
+    }
+
+    /// [`apply`](Self::apply) on the calling core's SIO interpolator, with the same result.
+    pub fn apply_blended(&self, blend: &mut Blender, progress: I16F16) -> I16F16 {
+        self.eased(progress, |table, x| blend.sample(table, x))
+    }
+
+    /// [`apply`](Self::apply) for float progress. Linear progress passes through unrounded.
+    pub fn apply_f32(&self, progress: f32) -> f32 {
+        match self {
+            Easing::Linear => progress.clamp(0.0, 1.0),
+            _ => self
+                .apply(I16F16::saturating_from_num(progress))
+                .to_num::<f32>(),
+        }
+    }
+
+    fn eased(
+        &self,
+        progress: I16F16,
+        mut sample: impl FnMut(&LerpTable<'static>, u16) -> u16,
+    ) -> I16F16 {
+        let t = progress.clamp(I16F16::ZERO, I16F16::ONE);
+        let table = match self {
+            Easing::Linear => return t,
+            Easing::EaseInOutSine => &EASE_IN_OUT_SINE,
+            Easing::EaseOutCubic => &EASE_OUT_CUBIC,
+        };
+        if t == I16F16::ONE {
+            return I16F16::ONE;
+        }
+        // Tables run over 0..=65535, fixed-point progress over 0..=65536
+        let eased = sample(table, t.to_bits() as u16) as i32;
+        I16F16::from_bits(eased + (eased >> 15))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: I16F16, b: f32) -> bool {
+        (a.to_num::<f32>() - b).abs() < 0.0005
+    }
+
+    #[test]
+    fn curves_hit_their_ends() {
+        for easing in [Easing::Linear, Easing::EaseInOutSine, Easing::EaseOutCubic] {
+            assert_eq!(easing.apply(I16F16::ZERO), I16F16::ZERO);
+            assert_eq!(easing.apply(I16F16::ONE), I16F16::ONE);
+            assert_eq!(easing.apply(I16F16::from_num(-1)), I16F16::ZERO);
+            assert_eq!(easing.apply(I16F16::from_num(2)), I16F16::ONE);
+        }
+    }
+
+    #[test]
+    fn curves_match_their_formulas() {
+        for i in 0..=20 {
+            let t = i as f32 / 20.0;
+            let progress = I16F16::from_num(t);
+            let sine = (1.0 - libm::cosf(core::f32::consts::PI * t)) / 2.0;
+            assert!(close(Easing::EaseInOutSine.apply(progress), sine));
+            let cubic = 1.0 - libm::powf(1.0 - t, 3.0);
+            assert!(close(Easing::EaseOutCubic.apply(progress), cubic));
+        }
+        assert_eq!(Easing::Linear.apply_f32(0.25), 0.25);
+    }
+
+    #[test]
+    fn curves_never_go_back() {
+        for easing in [Easing::EaseInOutSine, Easing::EaseOutCubic] {
+            let mut last = I16F16::ZERO;
+            for bits in (0..=0x1_0000).step_by(64) {
+                let eased = easing.apply(I16F16::from_bits(bits));
+                assert!(eased >= last);
+                last = eased;
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! interpolator.rs — SIO hardware interpolators for blends, clamps and table lookups
+//!
+//! Each RP2040 core has two interpolators in its SIO block. This module dedicates INTERP0
+//! to blend mode (`a + (b - a) * alpha / 256` in one register read) and INTERP1 to clamp
+//! mode, which replaces float lerps and `clamp` calls in hot loops such as LED fades, audio
+//! mixing and easing curves.
+//!
+//! [`LerpTable`] describes a curve by evenly spaced points (e.g. [`GAMMA_22`] or the
+//! [`Easing`](crate::Easing) curves) and is sampled in software with [`LerpTable::sample`]
+//! or on the interpolator with [`Blender::sample`]; both give identical results, as do
+//! [`lerp`] and [`Blender::lerp`].
+//!
+//! The interpolators are per core and hold state between register writes, so don't use
+//! them from interrupt handlers.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let Interpolators { mut blend, mut clamp } = Interpolators::take().unwrap();
+//!
+//! let brightness = blend.sample(&GAMMA_22, fade_level);
+//! let mid = blend.lerp(duty_min, duty_max, 128);
+//! clamp.clamp_slice(&mut samples, -32768, 32767);
+//! ```
+
+use core::marker::PhantomData;
+
+use portable_atomic::{AtomicBool, Ordering};
+
+const SIO_BASE: usize = 0xd000_0000;
+const SIO_CPUID: usize = SIO_BASE;
+const INTERP_BASE: [usize; 2] = [SIO_BASE + 0x080, SIO_BASE + 0x0c0];
+
+// Register offsets within one interpolator
+const ACCUM0: usize = 0x00;
+const ACCUM1: usize = 0x04;
+const BASE0: usize = 0x08;
+const BASE1: usize = 0x0c;
+const PEEK_LANE0: usize = 0x20;
+const PEEK_LANE1: usize = 0x24;
+const CTRL_LANE0: usize = 0x2c;
+const CTRL_LANE1: usize = 0x30;
+
+// CTRL_LANEx fields: no shift, mask bits 0..=31 (the whole accumulator)
+const CTRL_MASK_FULL: u32 = 31 << 10;
+const CTRL_SIGNED: u32 = 1 << 15;
+const CTRL_BLEND: u32 = 1 << 21;
+const CTRL_CLAMP: u32 = 1 << 22;
+
+/// Whether each core's interpolators have been taken
+static TAKEN: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// 2.2 gamma curve for LED brightness: perceived level in, PWM level out (both 0..=65535)
+pub const GAMMA_22: LerpTable<'static> = LerpTable::new(&[
+    0, 32, 147, 359, 676, 1104, 1648, 2314, //
+    3104, 4022, 5072, 6255, 7574, 9033, 10632, 12375, //
+    14263, 16298, 18482, 20816, 23303, 25943, 28739, 31692, //
+    34802, 38072, 41503, 45097, 48853, 52774, 56860, 61114, //
+    65535,
+]);
+
+/// Both interpolators of the calling core
+pub struct Interpolators {
+    pub blend: Blender,
+    pub clamp: Clamper,
+}
+
+impl Interpolators {
+    /// Configure and hand out the calling core's interpolators; `None` if this core
+    /// already took them.
+    pub fn take() -> Option<Self> {
+        // SAFETY: CPUID is a read-only SIO register, 0 on core 0 and 1 on core 1.
+        let core = unsafe { core::ptr::read_volatile(SIO_CPUID as *const u32) } as usize;
+        if TAKEN[core & 1].swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some(Self {
+            blend: Blender::new(Interp::new(0)),
+            clamp: Clamper::new(Interp::new(1)),
+        })
+    }
+}
+
+/// One interpolator of the current core. Not `Send`: the other core sees its own.
+struct Interp {
+    base: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Interp {
+    fn new(index: usize) -> Self {
+        Self {
+            base: INTERP_BASE[index],
+            _not_send: PhantomData,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        // SAFETY: offset is one of the interpolator registers; we own this interpolator.
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: as in `write`; PEEK registers have no side effects.
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+}
+
+/// INTERP0 in blend mode: linear interpolation with an 8-bit weight
+pub struct Blender {
+    interp: Interp,
+    signed: bool,
+}
+
+impl Blender {
+    fn new(mut interp: Interp) -> Self {
+        interp.write(CTRL_LANE0, CTRL_BLEND | CTRL_MASK_FULL);
+        interp.write(CTRL_LANE1, CTRL_MASK_FULL);
+        Self {
+            interp,
+            signed: false,
+        }
+    }
+
+    /// `a + (b - a) * alpha / 256`; `alpha` = 0 gives `a`, 255 almost `b`.
+    pub fn lerp(&mut self, a: u32, b: u32, alpha: u8) -> u32 {
+        self.set_signed(false);
+        self.blend(a, b, alpha)
+    }
+
+    pub fn lerp_signed(&mut self, a: i32, b: i32, alpha: u8) -> i32 {
+        self.set_signed(true);
+        self.blend(a as u32, b as u32, alpha) as i32
+    }
+
+    /// Same as [`LerpTable::sample`]
+    pub fn sample(&mut self, table: &LerpTable<'_>, x: u16) -> u16 {
+        let (a, b, alpha) = table.segment(x);
+        self.lerp(a as u32, b as u32, alpha) as u16
+    }
+
+    fn set_signed(&mut self, signed: bool) {
+        if self.signed != signed {
+            let sign = if signed { CTRL_SIGNED } else { 0 };
+            self.interp.write(CTRL_LANE1, CTRL_MASK_FULL | sign);
+            self.signed = signed;
+        }
+    }
+
+    fn blend(&mut self, a: u32, b: u32, alpha: u8) -> u32 {
+        self.interp.write(BASE0, a);
+        self.interp.write(BASE1, b);
+        self.interp.write(ACCUM1, alpha as u32);
+        self.interp.read(PEEK_LANE1)
+    }
+}
+
+/// INTERP1 in clamp mode: signed clamp in one register read
+pub struct Clamper {
+    interp: Interp,
+}
+
+impl Clamper {
+    fn new(mut interp: Interp) -> Self {
+        interp.write(CTRL_LANE0, CTRL_CLAMP | CTRL_SIGNED | CTRL_MASK_FULL);
+        Self { interp }
+    }
+
+    pub fn clamp(&mut self, value: i32, min: i32, max: i32) -> i32 {
+        self.set_bounds(min, max);
+        self.apply(value)
+    }
+
+    /// Clamp every value, setting the bounds only once.
+    pub fn clamp_slice(&mut self, values: &mut [i32], min: i32, max: i32) {
+        self.set_bounds(min, max);
+        for value in values.iter_mut() {
+            *value = self.apply(*value);
+        }
+    }
+
+    fn set_bounds(&mut self, min: i32, max: i32) {
+        self.interp.write(BASE0, min as u32);
+        self.interp.write(BASE1, max as u32);
+    }
+
+    fn apply(&mut self, value: i32) -> i32 {
+        self.interp.write(ACCUM0, value as u32);
+        self.interp.read(PEEK_LANE0) as i32
+    }
+}
+
+/// Software version of [`Blender::lerp_signed`]
+pub fn lerp(a: i32, b: i32, alpha: u8) -> i32 {
+    (a as i64 + (((b as i64 - a as i64) * alpha as i64) >> 8)) as i32
+}
+
+/// A curve over 0..=65535 given by evenly spaced points (the first at 0, the last at
+/// 65535), linearly interpolated in between
+#[derive(Debug, Clone, Copy)]
+pub struct LerpTable<'a> {
+    points: &'a [u16],
+}
+
+impl<'a> LerpTable<'a> {
+    /// Panics (at compile time for `const` tables) with fewer than two points.
+    pub const fn new(points: &'a [u16]) -> Self {
+        assert!(points.len() >= 2, "a LerpTable needs at least two points");
+        Self { points }
+    }
+
+    pub fn points(&self) -> &'a [u16] {
+        self.points
+    }
+
+    pub fn sample(&self, x: u16) -> u16 {
+        let (a, b, alpha) = self.segment(x);
+        lerp(a as i32, b as i32, alpha) as u16
+    }
+
+    /// Neighbouring points around `x` and the 8-bit weight of the second
+    fn segment(&self, x: u16) -> (u16, u16, u8) {
+        let last = self.points.len() - 1;
+        if x == u16::MAX {
+            return (self.points[last], self.points[last], 0);
+        }
+        let position = x as u32 * last as u32;
+        let index = (position >> 16) as usize;
+        let alpha = (position >> 8) as u8;
+        (self.points[index], self.points[index + 1], alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn software_lerp() {
+        assert_eq!(lerp(100, 200, 0), 100);
+        assert_eq!(lerp(100, 200, 128), 150);
+        assert_eq!(lerp(200, 100, 128), 150);
+        assert_eq!(lerp(-1000, 1000, 64), -500);
+        assert_eq!(lerp(i32::MIN, i32::MAX, 255), 2_130_706_431);
+    }
+
+    #[test]
+    fn table_sampling() {
+        let ramp = LerpTable::new(&[0, 1000, 3000]);
+        assert_eq!(ramp.sample(0), 0);
+        assert_eq!(ramp.sample(16384), 500);
+        assert_eq!(ramp.sample(32768), 1000);
+        assert_eq!(ramp.sample(49152), 2000);
+        assert_eq!(ramp.sample(u16::MAX), 3000);
+
+        assert_eq!(GAMMA_22.sample(0), 0);
+        assert_eq!(GAMMA_22.sample(u16::MAX), u16::MAX);
+        assert_eq!(GAMMA_22.sample(32768), 14263);
+        let samples = (0..=u16::MAX).step_by(257).map(|x| GAMMA_22.sample(x));
+        assert!(samples.clone().zip(samples.skip(1)).all(|(a, b)| a <= b));
+    }
+}
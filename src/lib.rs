@@ -1,9 +1,45 @@
 #![no_std]
 
+mod apps;
+mod build_info;
+mod clocks;
 mod connectivity;
+mod control;
+mod crc;
+mod crypto;
+mod date_time;
+mod device_actions;
+mod device_config;
+mod device_id;
+mod diagnostics;
+mod dma;
+mod easing;
 mod heapless;
+mod interpolator;
+mod number_format;
 mod peripherals;
+mod storage;
+mod time_series;
+mod uptime;
 
+pub use apps::*;
+pub use build_info::*;
+pub use clocks::*;
 pub use connectivity::*;
+pub use control::*;
+pub use crc::*;
+pub use crypto::*;
+pub use date_time::*;
+pub use device_actions::*;
+pub use device_config::*;
+pub use device_id::*;
+pub use diagnostics::*;
+pub use dma::*;
+pub use easing::*;
 pub use heapless::*;
+pub use interpolator::*;
+pub use number_format::*;
 pub use peripherals::*;
+pub use storage::*;
+pub use time_series::*;
+pub use uptime::*;
@@ -0,0 +1,213 @@
+//! number_format.rs — fixed-point numbers, times and columns for text displays
+//!
+//! Sensor values are mostly integers with an implied decimal point: tenths of a degree,
+//! hundredths of a percent. [`Fixed`] prints them without going through `f32`, and honours
+//! the usual format options, so columns line up with a format string alone: width and fill
+//! pad it (right-aligned by default, like any number), precision rounds or extends the
+//! decimals, and `+` forces a sign.
+//!
+//! ```text
+//! {:>6.1}  Fixed::new(235, 1)    "  23.5"
+//! {:.0}    Fixed::new(10132, 1)  "1013"
+//! {:+.2}   Fixed::new(-5, 1)     "-0.50"
+//! ```
+//!
+//! [`format_value`] adds a unit, [`format_hh_mm`] formats times of day, and
+//! [`write_justified`] spreads a label and a value across one display line.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut line: HeaplessString<16> = HeaplessString::new();
+//! write_justified(&mut line, "Temp", format_value(Fixed::new(235, 1), 1, 5, "C").as_str(), 16)?;
+//! // "Temp       23.5C"
+//! ```
+
+use core::fmt::{self, Write};
+
+use crate::HeaplessString;
+
+/// Capacity of strings returned by [`format_value`]
+pub const NUMBER_FORMAT_MAX_LEN: usize = 24;
+
+/// Most decimals a [`Fixed`] keeps or prints
+const MAX_DECIMALS: u8 = 9;
+
+/// `raw / 10^decimals`, printed exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Fixed {
+    pub raw: i32,
+    pub decimals: u8,
+}
+
+impl Fixed {
+    /// `decimals` above 9 are treated as 9.
+    pub const fn new(raw: i32, decimals: u8) -> Self {
+        Self {
+            raw,
+            decimals: if decimals > MAX_DECIMALS {
+                MAX_DECIMALS
+            } else {
+                decimals
+            },
+        }
+    }
+
+    /// `value` rounded to `decimals`; out-of-range values saturate.
+    pub fn from_f32(value: f32, decimals: u8) -> Self {
+        let fixed = Self::new(0, decimals);
+        let scaled = value * pow10(fixed.decimals) as f32;
+        Self {
+            raw: libm::roundf(scaled) as i32,
+            ..fixed
+        }
+    }
+
+    /// The value with `precision` decimals, rounded half away from zero
+    fn rescale(&self, precision: u8) -> i64 {
+        let raw = self.raw as i64;
+        if precision >= self.decimals {
+            return raw * pow10(precision - self.decimals);
+        }
+        let divisor = pow10(self.decimals - precision);
+        let half = if raw < 0 { -divisor / 2 } else { divisor / 2 };
+        (raw + half) / divisor
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f
+            .precision()
+            .map_or(self.decimals, |p| p.min(MAX_DECIMALS as usize) as u8);
+        let value = self.rescale(precision);
+        let magnitude = value.unsigned_abs();
+        let scale = pow10(precision) as u64;
+        let mut digits: HeaplessString<NUMBER_FORMAT_MAX_LEN> = HeaplessString::new();
+        write!(digits, "{}", magnitude / scale)?;
+        if precision > 0 {
+            write!(
+                digits,
+                ".{:0width$}",
+                magnitude % scale,
+                width = precision as usize
+            )?;
+        }
+        // Handles width, fill, alignment, `+` and zero padding like an integer
+        f.pad_integral(value >= 0, "", digits.as_str())
+    }
+}
+
+const fn pow10(exponent: u8) -> i64 {
+    10i64.pow(exponent as u32)
+}
+
+/// `value` with `precision` decimals, right-aligned in `width` characters, then `unit`
+pub fn format_value(
+    value: Fixed,
+    precision: u8,
+    width: usize,
+    unit: &str,
+) -> HeaplessString<NUMBER_FORMAT_MAX_LEN> {
+    let mut out = HeaplessString::new();
+    let _ = write!(
+        out,
+        "{:>width$.precision$}{unit}",
+        value,
+        precision = precision as usize
+    );
+    out
+}
+
+/// `hh:mm`, e.g. `12:05`; a clock with a blinking colon passes `' '` as `separator` on
+/// alternate seconds.
+pub fn format_hh_mm(hour: u8, minute: u8, separator: char) -> HeaplessString<8> {
+    let mut out = HeaplessString::new();
+    let _ = write!(out, "{hour:02}{separator}{minute:02}");
+    out
+}
+
+/// Write `left` and `right` as one line of `width` characters, with spaces between them.
+/// `right` (usually the value) is kept whole; `left` is cut short if both don't fit.
+pub fn write_justified(out: &mut impl Write, left: &str, right: &str, width: usize) -> fmt::Result {
+    let right_len = right.chars().count();
+    let left_room = width.saturating_sub(right_len);
+    let mut left_len = 0;
+    for c in left.chars().take(left_room) {
+        out.write_char(c)?;
+        left_len += 1;
+    }
+    for _ in left_len..left_room {
+        out.write_char(' ')?;
+    }
+    out.write_str(right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(args: fmt::Arguments<'_>) -> HeaplessString<32> {
+        let mut out = HeaplessString::new();
+        out.write_fmt(args).unwrap();
+        out
+    }
+
+    #[test]
+    fn fixed_honours_width_and_precision() {
+        let temp = Fixed::new(235, 1);
+        assert_eq!(format(format_args!("{temp}")).as_str(), "23.5");
+        assert_eq!(format(format_args!("{temp:>6.1}")).as_str(), "  23.5");
+        assert_eq!(format(format_args!("{temp:<6}|")).as_str(), "23.5  |");
+        assert_eq!(format(format_args!("{temp:06.2}")).as_str(), "023.50");
+        assert_eq!(format(format_args!("{temp:.0}")).as_str(), "24");
+        assert_eq!(format(format_args!("{temp:+}")).as_str(), "+23.5");
+        let pressure = Fixed::new(10132, 1);
+        assert_eq!(format(format_args!("{pressure:.0}")).as_str(), "1013");
+        assert_eq!(
+            format(format_args!("{:.2}", Fixed::new(-5, 1))).as_str(),
+            "-0.50"
+        );
+        assert_eq!(
+            format(format_args!("{:6.1}", Fixed::new(-235, 1))).as_str(),
+            " -23.5"
+        );
+    }
+
+    #[test]
+    fn fixed_rounds_half_away_from_zero() {
+        assert_eq!(
+            format(format_args!("{:.1}", Fixed::new(-25, 2))).as_str(),
+            "-0.3"
+        );
+        assert_eq!(
+            format(format_args!("{:.1}", Fixed::new(-4, 2))).as_str(),
+            "0.0"
+        );
+        assert_eq!(
+            format(format_args!("{:.1}", Fixed::new(995, 2))).as_str(),
+            "10.0"
+        );
+        assert_eq!(Fixed::from_f32(21.46, 1), Fixed::new(215, 1));
+        assert_eq!(
+            format(format_args!("{}", Fixed::new(i32::MIN, 0))).as_str(),
+            "-2147483648"
+        );
+    }
+
+    #[test]
+    fn helpers_line_up() {
+        assert_eq!(
+            format_value(Fixed::new(235, 1), 1, 5, "C").as_str(),
+            " 23.5C"
+        );
+        assert_eq!(format_hh_mm(12, 5, ':').as_str(), "12:05");
+        assert_eq!(format_hh_mm(7, 30, ' ').as_str(), "07 30");
+        let mut line: HeaplessString<16> = HeaplessString::new();
+        write_justified(&mut line, "Temp", "23.5C", 16).unwrap();
+        assert_eq!(line.as_str(), "Temp       23.5C");
+        line.clear();
+        write_justified(&mut line, "Humidity", "100.0%", 12).unwrap();
+        assert_eq!(line.as_str(), "Humidi100.0%");
+    }
+}
@@ -0,0 +1,155 @@
+//! air_quality.rs — common API and periodic monitor for eCO2/TVOC sensors
+//!
+//! Metal-oxide gas sensors (SGP30, CCS811) learn a baseline over hours of operation. Saving
+//! that baseline and restoring it after a reboot avoids a long re-learning period, so the
+//! monitor keeps it in a [`KvStore`] under a key of the caller's choosing.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let sensor = Sgp30::new(i2c).await?;
+//! let mut monitor = AirQualityMonitor::new(sensor, AirQualityMonitorConfig::default());
+//! monitor
+//!     .run(&mut store, "sgp30/baseline", |reading| info!("eCO2 {} ppm", reading.eco2_ppm))
+//!     .await;
+//! ```
+
+use embassy_time::{Duration, Instant, Ticker};
+
+use crate::{KvStore, LogModule};
+
+/// Bytes a baseline takes in the [`KvStore`]
+pub const AIR_QUALITY_BASELINE_LEN: usize = 4;
+
+/// One air-quality measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct AirQualityReading {
+    /// Equivalent CO2 in ppm
+    pub eco2_ppm: u16,
+    /// Total volatile organic compounds in ppb
+    pub tvoc_ppb: u16,
+}
+
+/// Sensor baseline as stored in a [`KvStore`]
+pub trait AirQualityBaseline: Copy {
+    fn to_bytes(&self) -> [u8; AIR_QUALITY_BASELINE_LEN];
+    fn from_bytes(bytes: [u8; AIR_QUALITY_BASELINE_LEN]) -> Self;
+}
+
+/// The CCS811's opaque baseline word
+impl AirQualityBaseline for u16 {
+    fn to_bytes(&self) -> [u8; AIR_QUALITY_BASELINE_LEN] {
+        let [lo, hi] = self.to_le_bytes();
+        [lo, hi, 0, 0]
+    }
+
+    fn from_bytes(bytes: [u8; AIR_QUALITY_BASELINE_LEN]) -> Self {
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+}
+
+/// eCO2/TVOC sensor with a persistable baseline
+#[allow(async_fn_in_trait)]
+pub trait AirQualitySensor {
+    type Error;
+    type Baseline: AirQualityBaseline;
+
+    /// Take a measurement. Returns `Ok(None)` if no new data is available yet.
+    async fn measure(&mut self) -> Result<Option<AirQualityReading>, Self::Error>;
+
+    async fn baseline(&mut self) -> Result<Self::Baseline, Self::Error>;
+
+    async fn set_baseline(&mut self, baseline: Self::Baseline) -> Result<(), Self::Error>;
+}
+
+/// Air-quality monitor configuration
+#[derive(Debug, Clone)]
+pub struct AirQualityMonitorConfig {
+    /// Measurement period (the SGP30 requires 1 s for its baseline algorithm)
+    pub measure_interval: Duration,
+    /// How often the learned baseline is saved
+    pub baseline_save_interval: Duration,
+    /// Wait this long after start before the first baseline save (sensor warm-up)
+    pub baseline_warmup: Duration,
+}
+
+impl Default for AirQualityMonitorConfig {
+    fn default() -> Self {
+        Self {
+            measure_interval: Duration::from_secs(1),
+            baseline_save_interval: Duration::from_secs(60 * 60),
+            baseline_warmup: Duration::from_secs(12 * 60 * 60),
+        }
+    }
+}
+
+/// Periodically measures an [`AirQualitySensor`] and persists its baseline.
+pub struct AirQualityMonitor<S: AirQualitySensor> {
+    sensor: S,
+    config: AirQualityMonitorConfig,
+    last: Option<AirQualityReading>,
+}
+
+impl<S: AirQualitySensor> AirQualityMonitor<S> {
+    pub fn new(sensor: S, config: AirQualityMonitorConfig) -> Self {
+        Self {
+            sensor,
+            config,
+            last: None,
+        }
+    }
+
+    /// Most recent successful reading
+    pub fn last_reading(&self) -> Option<AirQualityReading> {
+        self.last
+    }
+
+    pub fn sensor_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    /// Restore the baseline stored under `key`, then measure forever, calling `on_reading`
+    /// for each new measurement and saving the baseline back under `key` now and then.
+    /// Measurement errors are skipped.
+    pub async fn run<F>(&mut self, store: &mut impl KvStore, key: &str, mut on_reading: F) -> !
+    where
+        F: FnMut(AirQualityReading),
+    {
+        if let Ok(Some(bytes)) = store.get_array(key) {
+            // A sensor that refuses it relearns from scratch, as without a stored baseline
+            let _ = self
+                .sensor
+                .set_baseline(S::Baseline::from_bytes(bytes))
+                .await;
+        }
+
+        let started = Instant::now();
+        let mut last_save = started;
+        let mut ticker = Ticker::every(self.config.measure_interval);
+        loop {
+            ticker.next().await;
+
+            if let Ok(Some(reading)) = self.sensor.measure().await {
+                self.last = Some(reading);
+                on_reading(reading);
+            }
+
+            let now = Instant::now();
+            if now - started >= self.config.baseline_warmup
+                && now - last_save >= self.config.baseline_save_interval
+            {
+                if let Ok(baseline) = self.sensor.baseline().await
+                    && let Err(e) = store.set(key, &baseline.to_bytes())
+                {
+                    crate::log!(
+                        LogModule::Storage,
+                        Warn,
+                        "Air quality: saving baseline failed: {}",
+                        e
+                    );
+                }
+                last_save = now;
+            }
+        }
+    }
+}
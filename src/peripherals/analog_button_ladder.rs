@@ -0,0 +1,235 @@
+//! analog_button_ladder.rs — several buttons on one ADC pin through a resistor ladder
+//!
+//! Keypad shields (like the 16x2 LCD shields with five buttons) put every button on a
+//! different tap of a resistor divider, so each press gives its own voltage on one analog
+//! pin. [`AnalogButtonLadder`] takes the expected raw reading of every button and maps a
+//! reading to the nearest one within the tolerance. Once a button is recognised the band
+//! around it widens by the hysteresis, so a reading at the edge doesn't flicker, and a
+//! change only counts after it held for the debounce time. The ADC has no interrupt, so
+//! [`AnalogButtonLadder::wait_for_event`] samples every poll interval.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // 2k2 pull-up to 3V3, buttons to GND through 0, 330, 1k, 2k2 and 4k7
+//! let levels = [0, 330, 1000, 2200, 4700].map(|r| divider_level(2200, r));
+//! let input = AnalogInput::new(adc, adc::Channel::new_pin(p.PIN_26, Pull::None));
+//! let mut keys = AnalogButtonLadder::new(input, levels, AnalogLadderConfig::default());
+//! loop {
+//!     if let LadderEvent::Pressed(index) = keys.wait_for_event().await? {
+//!         menu.key(["right", "up", "down", "left", "select"][index]);
+//!     }
+//! }
+//! ```
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{ADC_MAX_RAW, AnalogInput, AnalogInputError};
+
+/// Largest distance in raw counts between a reading and a button's level
+pub const LADDER_DEFAULT_TOLERANCE: u16 = 100;
+/// Extra distance a recognised button may drift before it counts as released
+pub const LADDER_DEFAULT_HYSTERESIS: u16 = 40;
+/// Time a new reading must hold before it counts
+pub const LADDER_DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+/// Time between samples while waiting
+pub const LADDER_DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Configuration for [`AnalogButtonLadder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct AnalogLadderConfig {
+    pub tolerance: u16,
+    pub hysteresis: u16,
+    pub debounce: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for AnalogLadderConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: LADDER_DEFAULT_TOLERANCE,
+            hysteresis: LADDER_DEFAULT_HYSTERESIS,
+            debounce: LADDER_DEFAULT_DEBOUNCE,
+            poll_interval: LADDER_DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+/// A button of the ladder, by index into its levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LadderEvent {
+    Pressed(usize),
+    Released(usize),
+}
+
+/// Raw reading of a divider with `r_top` to 3V3 and `r_bottom` to GND, in any unit.
+pub const fn divider_level(r_top: u32, r_bottom: u32) -> u16 {
+    if r_top + r_bottom == 0 {
+        return 0;
+    }
+    (ADC_MAX_RAW as u32 * r_bottom / (r_top + r_bottom)) as u16
+}
+
+/// Classifies readings and debounces the result; no hardware, so it can be tested.
+#[derive(Debug, Clone, Copy)]
+struct LadderState<const N: usize> {
+    levels: [u16; N],
+    config: AnalogLadderConfig,
+    /// Debounced button
+    pressed: Option<usize>,
+    /// Button the readings point at, and since when
+    candidate: Option<usize>,
+    since: Instant,
+}
+
+impl<const N: usize> LadderState<N> {
+    fn new(levels: [u16; N], config: AnalogLadderConfig) -> Self {
+        Self {
+            levels,
+            config,
+            pressed: None,
+            candidate: None,
+            since: Instant::from_ticks(0),
+        }
+    }
+
+    /// Button a reading belongs to, keeping `current` inside its widened band.
+    fn classify(&self, raw: u16, current: Option<usize>) -> Option<usize> {
+        let distance = |i: usize| raw.abs_diff(self.levels[i]);
+        if let Some(i) = current
+            && distance(i) <= self.config.tolerance + self.config.hysteresis
+        {
+            return Some(i);
+        }
+        (0..N)
+            .filter(|&i| distance(i) <= self.config.tolerance)
+            .min_by_key(|&i| distance(i))
+    }
+
+    /// Feed one reading; returns a change once it held for the debounce time.
+    fn update(&mut self, raw: u16, now: Instant) -> Option<LadderEvent> {
+        let button = self.classify(raw, self.candidate);
+        if button != self.candidate {
+            self.candidate = button;
+            self.since = now;
+        }
+        if button == self.pressed
+            || now.saturating_duration_since(self.since) < self.config.debounce
+        {
+            return None;
+        }
+        // Going straight from one button to another reports the release first; the
+        // press follows on the next reading
+        let event = match self.pressed {
+            Some(old) => LadderEvent::Released(old),
+            None => LadderEvent::Pressed(button?),
+        };
+        self.pressed = match event {
+            LadderEvent::Released(_) => None,
+            LadderEvent::Pressed(i) => Some(i),
+        };
+        Some(event)
+    }
+}
+
+pub struct AnalogButtonLadder<'a, const N: usize> {
+    input: AnalogInput<'a>,
+    state: LadderState<N>,
+}
+
+impl<'a, const N: usize> AnalogButtonLadder<'a, N> {
+    /// `levels` holds the raw reading (0..=4095) of each button, e.g. from
+    /// [`divider_level`] or measured with [`AnalogInput::read_median`].
+    pub fn new(input: AnalogInput<'a>, levels: [u16; N], config: AnalogLadderConfig) -> Self {
+        Self {
+            input,
+            state: LadderState::new(levels, config),
+        }
+    }
+
+    pub fn set_config(&mut self, config: AnalogLadderConfig) {
+        self.state.config = config;
+    }
+
+    pub fn set_levels(&mut self, levels: [u16; N]) {
+        self.state.levels = levels;
+    }
+
+    /// Debounced button held down, as of the last sample
+    pub fn pressed(&self) -> Option<usize> {
+        self.state.pressed
+    }
+
+    /// Button the reading belongs to right now, without debouncing
+    pub async fn read_button(&mut self) -> Result<Option<usize>, AnalogInputError> {
+        let raw = self.input.read_raw().await?;
+        Ok(self.state.classify(raw, self.state.pressed))
+    }
+
+    /// Take one sample; returns a press or release once it is debounced. Call it at
+    /// least a few times per debounce period.
+    pub async fn poll(&mut self) -> Result<Option<LadderEvent>, AnalogInputError> {
+        let raw = self.input.read_raw().await?;
+        Ok(self.state.update(raw, Instant::now()))
+    }
+
+    /// Sample every poll interval until a button is pressed or released.
+    pub async fn wait_for_event(&mut self) -> Result<LadderEvent, AnalogInputError> {
+        loop {
+            if let Some(event) = self.poll().await? {
+                return Ok(event);
+            }
+            Timer::after(self.state.config.poll_interval).await;
+        }
+    }
+
+    /// Wait for the next press; returns the button index.
+    pub async fn wait_for_press(&mut self) -> Result<usize, AnalogInputError> {
+        loop {
+            if let LadderEvent::Pressed(index) = self.wait_for_event().await? {
+                return Ok(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [u16; 3] = [0, 1000, 2000];
+
+    #[test]
+    fn divider_levels() {
+        assert_eq!(divider_level(2200, 0), 0);
+        assert_eq!(divider_level(1000, 1000), ADC_MAX_RAW / 2);
+        assert_eq!(divider_level(0, 0), 0);
+    }
+
+    #[test]
+    fn classify_with_hysteresis() {
+        let state = LadderState::new(LEVELS, AnalogLadderConfig::default());
+        assert_eq!(state.classify(1050, None), Some(1));
+        assert_eq!(state.classify(1120, None), None);
+        assert_eq!(state.classify(1120, Some(1)), Some(1));
+        assert_eq!(state.classify(1200, Some(1)), None);
+        assert_eq!(state.classify(4095, None), None);
+    }
+
+    #[test]
+    fn debounced_press_and_release() {
+        let at = Instant::from_millis;
+        let mut state = LadderState::new(LEVELS, AnalogLadderConfig::default());
+        assert_eq!(state.update(4095, at(0)), None);
+        assert_eq!(state.update(990, at(5)), None);
+        assert_eq!(state.update(4095, at(10)), None);
+        assert_eq!(state.update(1010, at(15)), None);
+        assert_eq!(state.update(1000, at(35)), Some(LadderEvent::Pressed(1)));
+        assert_eq!(state.update(1000, at(60)), None);
+        assert_eq!(state.update(30, at(70)), None);
+        assert_eq!(state.update(20, at(90)), Some(LadderEvent::Released(1)));
+        assert_eq!(state.update(20, at(95)), Some(LadderEvent::Pressed(0)));
+        assert_eq!(state.update(4095, at(100)), None);
+        assert_eq!(state.update(4095, at(120)), Some(LadderEvent::Released(0)));
+    }
+}
@@ -0,0 +1,312 @@
+//! analog_input.rs — ADC channel wrapper sharing the single RP2040 ADC
+//!
+//! The RP2040 has one ADC, so it lives behind an async mutex ([`SharedAdc`]) and each
+//! [`AnalogInput`] owns just its channel.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static ADC: StaticCell<SharedAdc> = StaticCell::new();
+//! let adc = ADC.init(Mutex::new(Adc::new(p.ADC, Irqs, adc::Config::default())));
+//!
+//! let mut pot = AnalogInput::new(adc, adc::Channel::new_pin(p.PIN_26, Pull::None));
+//! let mv = pot.read_millivolts().await?;
+//! ```
+//!
+//! Raw RP2040 readings differ between boards by tens of millivolts. Calibrate once per
+//! board (input grounded, then at a known voltage), store the correction, and read through
+//! the median filter with the correction applied:
+//!
+//! ```ignore
+//! let mut vbat = AnalogInput::new(adc, adc::Channel::new_pin(p.PIN_28, Pull::None));
+//! if !vbat.load_calibration(&mut store, "adc/vbat")? {
+//!     vbat.calibrate_offset().await?; // input at 0 V
+//!     vbat.calibrate_gain(2500).await?; // input at a 2.500 V reference
+//!     vbat.save_calibration(&mut store, "adc/vbat")?;
+//! }
+//!
+//! let mut die = AnalogInput::new(adc, adc::Channel::new_temp_sensor(p.ADC_TEMP_SENSOR));
+//! vbat.set_die_temperature(die.read_die_temperature().await?);
+//! let mv = vbat.read_calibrated_millivolts().await?;
+//! ```
+
+use embassy_rp::adc::{self, Adc, Channel};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+
+use crate::{KvStore, KvStoreError};
+
+/// ADC reference voltage on the Pico (3V3 rail)
+pub const ADC_REFERENCE_MV: u32 = 3300;
+/// Largest raw ADC reading (12-bit)
+pub const ADC_MAX_RAW: u16 = 4095;
+/// Samples taken for one median-filtered reading
+pub const ADC_FILTER_SAMPLES: usize = 9;
+/// Gain factor of 1.0 in [`AdcCalibration::gain`]
+pub const ADC_GAIN_UNITY: u16 = 10_000;
+
+/// The RP2040 ADC shared between several [`AnalogInput`]s
+pub type SharedAdc = Mutex<CriticalSectionRawMutex, Adc<'static, adc::Async>>;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum AnalogInputError {
+    #[error("ADC conversion failed")]
+    Conversion,
+    #[error("Sample count must be greater than zero")]
+    NoSamples,
+    #[error("Calibration reading out of range")]
+    Calibration,
+}
+
+/// Per-board ADC correction: `(raw + offset + drift) * gain`
+///
+/// The drift term compensates for the offset moving with die temperature; it only applies
+/// once the die temperature is known (see [`AnalogInput::set_die_temperature`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct AdcCalibration {
+    /// Added to every raw reading, in counts
+    pub offset: i16,
+    /// Scale in 1/10000 ([`ADC_GAIN_UNITY`] = no correction)
+    pub gain: u16,
+    /// Offset change in 1/1000 counts per °C away from `reference_temp_c`
+    pub drift_per_c: i16,
+    /// Die temperature during calibration in °C
+    pub reference_temp_c: i8,
+}
+
+impl Default for AdcCalibration {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            gain: ADC_GAIN_UNITY,
+            drift_per_c: 0,
+            reference_temp_c: 27,
+        }
+    }
+}
+
+impl AdcCalibration {
+    pub fn to_bytes(&self) -> [u8; 7] {
+        let [o0, o1] = self.offset.to_le_bytes();
+        let [g0, g1] = self.gain.to_le_bytes();
+        let [d0, d1] = self.drift_per_c.to_le_bytes();
+        [o0, o1, g0, g1, d0, d1, self.reference_temp_c as u8]
+    }
+
+    pub fn from_bytes(bytes: [u8; 7]) -> Self {
+        Self {
+            offset: i16::from_le_bytes([bytes[0], bytes[1]]),
+            gain: u16::from_le_bytes([bytes[2], bytes[3]]),
+            drift_per_c: i16::from_le_bytes([bytes[4], bytes[5]]),
+            reference_temp_c: bytes[6] as i8,
+        }
+    }
+
+    /// Apply the correction to a raw reading taken at `die_temp_c` (if known).
+    pub fn correct(&self, raw: u16, die_temp_c: Option<i16>) -> u16 {
+        let drift = die_temp_c.map_or(0, |temp| {
+            (temp as i32 - self.reference_temp_c as i32) * self.drift_per_c as i32 / 1000
+        });
+        let offset_corrected = raw as i32 + self.offset as i32 - drift;
+        let corrected = offset_corrected * self.gain as i32 / ADC_GAIN_UNITY as i32;
+        corrected.clamp(0, ADC_MAX_RAW as i32) as u16
+    }
+}
+
+/// Single ADC channel
+pub struct AnalogInput<'a> {
+    adc: &'a SharedAdc,
+    channel: Channel<'static>,
+    calibration: AdcCalibration,
+    die_temp_c: Option<i16>,
+}
+
+impl<'a> AnalogInput<'a> {
+    pub fn new(adc: &'a SharedAdc, channel: Channel<'static>) -> Self {
+        Self {
+            adc,
+            channel,
+            calibration: AdcCalibration::default(),
+            die_temp_c: None,
+        }
+    }
+
+    pub fn calibration(&self) -> AdcCalibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: AdcCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Current die temperature for drift compensation, e.g. from
+    /// [`AnalogInput::read_die_temperature`] on the temperature sensor channel.
+    pub fn set_die_temperature(&mut self, die_temp_c: i16) {
+        self.die_temp_c = Some(die_temp_c);
+    }
+
+    /// Raw 12-bit reading (0..=4095)
+    pub async fn read_raw(&mut self) -> Result<u16, AnalogInputError> {
+        let mut adc = self.adc.lock().await;
+        adc.read(&mut self.channel)
+            .await
+            .map_err(|_| AnalogInputError::Conversion)
+    }
+
+    /// Mean of `samples` raw readings, to smooth out ADC noise.
+    pub async fn read_average(&mut self, samples: u16) -> Result<u16, AnalogInputError> {
+        if samples == 0 {
+            return Err(AnalogInputError::NoSamples);
+        }
+        let mut sum = 0u32;
+        for _ in 0..samples {
+            sum += self.read_raw().await? as u32;
+        }
+        Ok((sum / samples as u32) as u16)
+    }
+
+    /// Median of [`ADC_FILTER_SAMPLES`] raw readings: unlike the mean, single spikes (e.g.
+    /// from WiFi transmit bursts or SMPS ripple) don't move it.
+    pub async fn read_median(&mut self) -> Result<u16, AnalogInputError> {
+        let mut samples = [0u16; ADC_FILTER_SAMPLES];
+        for sample in samples.iter_mut() {
+            *sample = self.read_raw().await?;
+        }
+        Ok(median(&mut samples))
+    }
+
+    /// Reading converted to millivolts at the pin.
+    pub async fn read_millivolts(&mut self) -> Result<u32, AnalogInputError> {
+        let raw = self.read_raw().await?;
+        Ok(raw_to_millivolts(raw))
+    }
+
+    /// Median-filtered reading with the calibration applied
+    pub async fn read_calibrated(&mut self) -> Result<u16, AnalogInputError> {
+        let raw = self.read_median().await?;
+        Ok(self.calibration.correct(raw, self.die_temp_c))
+    }
+
+    pub async fn read_calibrated_millivolts(&mut self) -> Result<u32, AnalogInputError> {
+        let raw = self.read_calibrated().await?;
+        Ok(raw_to_millivolts(raw))
+    }
+
+    /// Interpret this channel as the internal temperature sensor and return °C.
+    pub async fn read_die_temperature(&mut self) -> Result<i16, AnalogInputError> {
+        let raw = self.read_median().await?;
+        Ok(raw_to_celsius(raw))
+    }
+
+    /// Zero the offset with the input at 0 V (grounded). Records the die temperature as
+    /// the reference if one was set. Returns the new offset.
+    pub async fn calibrate_offset(&mut self) -> Result<i16, AnalogInputError> {
+        let raw = self.read_median().await?;
+        if raw > ADC_MAX_RAW / 8 {
+            return Err(AnalogInputError::Calibration);
+        }
+        self.calibration.offset = -(raw as i16);
+        if let Some(temp) = self.die_temp_c {
+            self.calibration.reference_temp_c = temp.clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+        }
+        Ok(self.calibration.offset)
+    }
+
+    /// Set the gain with the input at a known `actual_mv` (after
+    /// [`AnalogInput::calibrate_offset`]). Returns the new gain.
+    pub async fn calibrate_gain(&mut self, actual_mv: u32) -> Result<u16, AnalogInputError> {
+        let raw = self.read_median().await?;
+        let measured = raw as i32 + self.calibration.offset as i32;
+        let expected = (actual_mv * ADC_MAX_RAW as u32 / ADC_REFERENCE_MV) as i32;
+        if measured <= 0 || expected <= 0 {
+            return Err(AnalogInputError::Calibration);
+        }
+        let gain = expected * ADC_GAIN_UNITY as i32 / measured;
+        // More than ±20 % off means the reference wasn't connected.
+        if !(8_000..=12_000).contains(&gain) {
+            return Err(AnalogInputError::Calibration);
+        }
+        self.calibration.gain = gain as u16;
+        Ok(self.calibration.gain)
+    }
+
+    /// Load calibration from `store`. Returns `false` (keeping the current calibration)
+    /// if nothing is stored under `key`.
+    pub fn load_calibration(
+        &mut self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<bool, KvStoreError> {
+        match store.get_array::<7>(key)? {
+            Some(bytes) => {
+                self.calibration = AdcCalibration::from_bytes(bytes);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn save_calibration(
+        &self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<(), KvStoreError> {
+        store.set(key, &self.calibration.to_bytes())
+    }
+}
+
+fn median(samples: &mut [u16]) -> u16 {
+    samples.sort_unstable();
+    samples[samples.len() / 2]
+}
+
+/// Convert a raw 12-bit ADC reading to millivolts.
+pub fn raw_to_millivolts(raw: u16) -> u32 {
+    raw.min(ADC_MAX_RAW) as u32 * ADC_REFERENCE_MV / ADC_MAX_RAW as u32
+}
+
+/// Convert a temperature sensor reading to °C (RP2040 datasheet: 0.706 V at 27 °C,
+/// -1.721 mV/°C).
+pub fn raw_to_celsius(raw: u16) -> i16 {
+    let microvolts =
+        raw.min(ADC_MAX_RAW) as i64 * ADC_REFERENCE_MV as i64 * 1000 / ADC_MAX_RAW as i64;
+    (27 - (microvolts - 706_000) / 1721) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_correction() {
+        let calibration = AdcCalibration {
+            offset: -20,
+            gain: 10_500,
+            drift_per_c: 500,
+            reference_temp_c: 25,
+        };
+        assert_eq!(calibration.correct(1020, None), 1050);
+        // 10 °C warmer: offset drifted up by 5 counts
+        assert_eq!(calibration.correct(1025, Some(35)), 1050);
+        assert_eq!(calibration.correct(0, None), 0);
+        assert_eq!(calibration.correct(ADC_MAX_RAW, None), ADC_MAX_RAW);
+        assert_eq!(
+            AdcCalibration::from_bytes(calibration.to_bytes()),
+            calibration
+        );
+        assert_eq!(AdcCalibration::default().correct(1234, Some(60)), 1234);
+    }
+
+    #[test]
+    fn median_ignores_spikes() {
+        let mut samples = [100, 101, 4095, 99, 100, 0, 102, 100, 98];
+        assert_eq!(median(&mut samples), 100);
+    }
+
+    #[test]
+    fn die_temperature() {
+        // 0.706 V -> 27 °C; lower voltage is hotter
+        assert_eq!(raw_to_celsius(876), 27);
+        assert!(raw_to_celsius(850) > 27);
+    }
+}
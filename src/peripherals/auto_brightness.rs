@@ -0,0 +1,246 @@
+//! auto_brightness.rs — SH1106 contrast that follows ambient light
+//!
+//! Contrast moves between [`AutoBrightnessConfig::min_contrast`] and `max_contrast` a few
+//! steps per poll. Below the night threshold the [`NightMode`] takes over and dims the
+//! panel or switches it off. There is no inverted night mode: the sh1106 driver has no
+//! inverse-display call, and on an OLED inverting lights more pixels, not fewer.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let sensor = Bh1750::new_with_default_address(i2c).await?;
+//! let mut auto = AutoBrightness::new(sensor, AutoBrightnessConfig::default());
+//!
+//! // In a dedicated task that owns the display:
+//! auto.run(&mut oled).await;
+//! ```
+
+use embassy_rp::spi;
+use embassy_time::{Duration, Ticker};
+
+use crate::{
+    ADC_MAX_RAW, AnalogInput, AnalogInputError, Bh1750, Bh1750Error, InlandSh1106OledDisplay,
+    InlandSh1106OledError,
+};
+
+/// Source of ambient light readings. Higher levels mean brighter surroundings.
+///
+/// Units are sensor specific (lux for the BH1750, raw ADC counts for a photoresistor), so
+/// [`AutoBrightnessConfig`] thresholds must use the same units.
+#[allow(async_fn_in_trait)]
+pub trait AmbientLightSensor {
+    type Error;
+
+    async fn read_level(&mut self) -> Result<u32, Self::Error>;
+}
+
+impl<I: embedded_hal::i2c::I2c> AmbientLightSensor for Bh1750<I> {
+    type Error = Bh1750Error;
+
+    async fn read_level(&mut self) -> Result<u32, Self::Error> {
+        self.read_lux()
+    }
+}
+
+/// Light dependent resistor in a voltage divider on an ADC pin.
+pub struct Photoresistor<'a> {
+    input: AnalogInput<'a>,
+    inverted: bool,
+}
+
+impl<'a> Photoresistor<'a> {
+    /// LDR between 3V3 and the pin, fixed resistor to GND (voltage rises with light).
+    pub fn new(input: AnalogInput<'a>) -> Self {
+        Self {
+            input,
+            inverted: false,
+        }
+    }
+
+    /// LDR between the pin and GND, fixed resistor to 3V3 (voltage falls with light).
+    pub fn new_inverted(input: AnalogInput<'a>) -> Self {
+        Self {
+            input,
+            inverted: true,
+        }
+    }
+}
+
+impl AmbientLightSensor for Photoresistor<'_> {
+    type Error = AnalogInputError;
+
+    async fn read_level(&mut self) -> Result<u32, Self::Error> {
+        let raw = self.input.read_average(4).await?;
+        Ok(if self.inverted {
+            (ADC_MAX_RAW - raw.min(ADC_MAX_RAW)) as u32
+        } else {
+            raw as u32
+        })
+    }
+}
+
+/// What the display does when it is dark enough for night mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum NightMode {
+    /// Never enter night mode
+    Disabled,
+    /// Drop to the lowest contrast
+    Dim,
+    /// Switch the panel off
+    Off,
+}
+
+/// Auto-brightness configuration
+///
+/// Light levels are in the units of the [`AmbientLightSensor`] in use; the defaults suit a
+/// BH1750 (lux).
+#[derive(Debug, Clone)]
+pub struct AutoBrightnessConfig {
+    /// How often the sensor is read
+    pub poll_interval: Duration,
+    /// Level at or below which `min_contrast` is used
+    pub dark_level: u32,
+    /// Level at or above which `max_contrast` is used
+    pub bright_level: u32,
+    pub min_contrast: u8,
+    pub max_contrast: u8,
+    /// Largest contrast change per poll, for smooth transitions
+    pub max_step: u8,
+    pub night_mode: NightMode,
+    /// Level at or below which night mode starts
+    pub night_enter_level: u32,
+    /// Level at or above which night mode ends (keep above `night_enter_level`)
+    pub night_exit_level: u32,
+}
+
+impl Default for AutoBrightnessConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            dark_level: 10,
+            bright_level: 500,
+            min_contrast: 0x10,
+            max_contrast: 0xFF,
+            max_step: 8,
+            night_mode: NightMode::Dim,
+            night_enter_level: 2,
+            night_exit_level: 8,
+        }
+    }
+}
+
+/// Drives SH1106 contrast from an [`AmbientLightSensor`].
+pub struct AutoBrightness<S: AmbientLightSensor> {
+    sensor: S,
+    config: AutoBrightnessConfig,
+    contrast: u8,
+    night: bool,
+}
+
+impl<S: AmbientLightSensor> AutoBrightness<S> {
+    pub fn new(sensor: S, config: AutoBrightnessConfig) -> Self {
+        let contrast = config.max_contrast;
+        Self {
+            sensor,
+            config,
+            contrast,
+            night: false,
+        }
+    }
+
+    /// Contrast currently applied (outside night mode)
+    pub fn contrast(&self) -> u8 {
+        self.contrast
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.night
+    }
+
+    /// Read the sensor once and adjust the display.
+    ///
+    /// Sensor errors are ignored so a flaky reading keeps the current brightness.
+    pub async fn update<T, M>(
+        &mut self,
+        display: &mut InlandSh1106OledDisplay<'_, T, M>,
+    ) -> Result<(), InlandSh1106OledError>
+    where
+        T: spi::Instance,
+        M: spi::Mode,
+    {
+        let Ok(level) = self.sensor.read_level().await else {
+            return Ok(());
+        };
+
+        let night = self.night_for(level);
+        if night != self.night {
+            self.night = night;
+            match self.config.night_mode {
+                NightMode::Off => display.set_display_on(!night)?,
+                NightMode::Dim if night => display.set_contrast(0)?,
+                _ => {}
+            }
+            if !night {
+                display.set_contrast(self.contrast)?;
+            }
+        }
+
+        if self.night {
+            return Ok(());
+        }
+
+        let target = self.target_contrast(level);
+        let next = step_towards(self.contrast, target, self.config.max_step);
+        if next != self.contrast {
+            self.contrast = next;
+            display.set_contrast(next)?;
+        }
+        Ok(())
+    }
+
+    /// Keep the display brightness in sync with ambient light forever.
+    pub async fn run<T, M>(&mut self, display: &mut InlandSh1106OledDisplay<'_, T, M>) -> !
+    where
+        T: spi::Instance,
+        M: spi::Mode,
+    {
+        let mut ticker = Ticker::every(self.config.poll_interval);
+        loop {
+            let _ = self.update(display).await;
+            ticker.next().await;
+        }
+    }
+
+    fn night_for(&self, level: u32) -> bool {
+        match self.config.night_mode {
+            NightMode::Disabled => false,
+            _ if self.night => level < self.config.night_exit_level,
+            _ => level <= self.config.night_enter_level,
+        }
+    }
+
+    fn target_contrast(&self, level: u32) -> u8 {
+        let (dark, bright) = (self.config.dark_level, self.config.bright_level);
+        let (min, max) = (
+            self.config.min_contrast as u32,
+            self.config.max_contrast as u32,
+        );
+        if level <= dark || bright <= dark {
+            return min as u8;
+        }
+        if level >= bright {
+            return max as u8;
+        }
+        let span = max.saturating_sub(min);
+        (min + span * (level - dark) / (bright - dark)) as u8
+    }
+}
+
+fn step_towards(current: u8, target: u8, max_step: u8) -> u8 {
+    let step = max_step.max(1);
+    if current < target {
+        current.saturating_add(step).min(target)
+    } else {
+        current.saturating_sub(step).max(target)
+    }
+}
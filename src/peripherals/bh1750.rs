@@ -0,0 +1,60 @@
+//! bh1750.rs — BH1750 ambient light sensor driver (I2C)
+
+use embassy_time::Timer;
+
+pub const BH1750_DEFAULT_I2C_ADDRESS: u8 = 0x23;
+pub const BH1750_ALT_I2C_ADDRESS: u8 = 0x5C;
+
+const CMD_POWER_ON: u8 = 0x01;
+const CMD_RESET: u8 = 0x07;
+const CMD_CONTINUOUS_HIGH_RES: u8 = 0x10;
+
+/// Worst-case conversion time in high resolution mode
+const MEASUREMENT_TIME_MS: u64 = 180;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum Bh1750Error {
+    #[error("BH1750 initialization failed")]
+    Initialization,
+    #[error("Failed to read BH1750 measurement")]
+    Read,
+}
+
+pub struct Bh1750<I: embedded_hal::i2c::I2c> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: embedded_hal::i2c::I2c> Bh1750<I> {
+    /// Power the sensor on and start continuous high-resolution measurements.
+    pub async fn new(i2c: I, address: u8) -> Result<Self, Bh1750Error> {
+        let mut sensor = Self { i2c, address };
+        sensor.command(CMD_POWER_ON)?;
+        sensor.command(CMD_RESET)?;
+        sensor.command(CMD_CONTINUOUS_HIGH_RES)?;
+        // First conversion is not ready until a full measurement cycle has passed.
+        Timer::after_millis(MEASUREMENT_TIME_MS).await;
+        Ok(sensor)
+    }
+
+    pub async fn new_with_default_address(i2c: I) -> Result<Self, Bh1750Error> {
+        Self::new(i2c, BH1750_DEFAULT_I2C_ADDRESS).await
+    }
+
+    /// Latest measurement in whole lux.
+    pub fn read_lux(&mut self) -> Result<u32, Bh1750Error> {
+        let mut buf = [0u8; 2];
+        self.i2c
+            .read(self.address, &mut buf)
+            .map_err(|_| Bh1750Error::Read)?;
+        let raw = u16::from_be_bytes(buf) as u32;
+        // Datasheet: lux = raw / 1.2
+        Ok(raw * 10 / 12)
+    }
+
+    fn command(&mut self, cmd: u8) -> Result<(), Bh1750Error> {
+        self.i2c
+            .write(self.address, &[cmd])
+            .map_err(|_| Bh1750Error::Initialization)
+    }
+}
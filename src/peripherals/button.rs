@@ -1,11 +1,163 @@
 //! button.rs — simple GPIO button driver for rp2040
+//!
+//! Any [`InputPin`] can be polled with [`Button::is_pressed`]. Buttons are active-low (to
+//! GND, with a pull-up) by default; [`Button::new_active_high`] is for buttons wired to VCC
+//! with a pull-down. Polarity and all timings can also be set at once with a
+//! [`ButtonConfig`] and [`Button::with_config`].
+//!
+//! Buttons on a pin that can await its edges ([`Wait`], like an embassy [`Input`]) can
+//! also be awaited: [`Button::wait_for_press`] and [`Button::wait_for_release`] sleep
+//! until the pin changes and return once it has stayed put for the debounce time.
+//! [`Button::wait_for_event`] waits for a whole press and reports it as a
+//! [`ButtonEvent::ShortPress`] or, when held past the long-press threshold, a
+//! [`ButtonEvent::LongPress`] with the time it was held;
+//! [`Button::measured_press`] returns the hold time itself, for "hold longer, step more".
+//! [`Button::wait_for_clicks`] counts presses that follow each other within the click
+//! window and reports a [`ClickEvent`].
+//!
+//! None of the waits poll. They sleep on the pin's edge or level interrupt, and a timer runs
+//! only for the debounce time and click window after an edge. Those timers and the hold
+//! time come from the button's [`Uptime`]: [`SystemUptime`] unless the button was made with
+//! [`Button::with_uptime`]. Between presses the executor
+//! has nothing to run and the core stays in WFE, which suits battery builds. A button
+//! behind an I/O expander gets the same waits if the expander driver implements [`Wait`]
+//! on top of its interrupt line; only pins without one need polling with
+//! [`Button::is_pressed`].
+//!
+//! [`Button::wait_for_press_allow_sleep`] also makes the button a wake source: after an
+//! idle time without a press it arms the pin as a dormant-mode wake and puts the whole chip
+//! into dormant sleep, with [`DormantHooks`] letting the power code veto it and re-init
+//! what doesn't survive. Dormant sleep stops the crystal, so every task is frozen, USB and
+//! WiFi links drop, and embassy time does not advance while asleep; the clocks are restored
+//! on wake, so UART, SPI, I2C and PWM keep working.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut button = Button::new(Input::new(p.PIN_15, Pull::Up));
+//! let mut keypad_button = Button::new_active_high(Input::new(p.PIN_16, Pull::Down));
+//! loop {
+//!     button.wait_for_press().await;
+//!     led.toggle();
+//!     button.wait_for_release().await;
+//! }
+//!
+//! button.set_long_press_threshold(Duration::from_secs(2));
+//! match button.wait_for_event().await {
+//!     ButtonEvent::ShortPress => next_page(),
+//!     ButtonEvent::LongPress(_) => enter_setup(),
+//! }
+//!
+//! // Bigger steps the longer the button is held
+//! let held = button.measured_press().await;
+//! volume += if held > Duration::from_millis(500) { 10 } else { 1 };
+//!
+//! match button.wait_for_clicks().await {
+//!     ClickEvent::Click => play_pause(),
+//!     ClickEvent::DoubleClick => next_track(),
+//!     ClickEvent::TripleClick => previous_track(),
+//! }
+//!
+//! // Sleep after 30 s without a press; `power` shuts down the radio and display first
+//! button.wait_for_press_allow_sleep(Duration::from_secs(30), &mut power).await;
+//! ```
 #![allow(dead_code)]
 
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::{DormantWakeConfig, Input};
+use embassy_time::{Duration, Instant};
 use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
 
-/// Simple button driver with pull-up configuration (active-low).
-pub struct Button<P> {
+use crate::{SystemUptime, Uptime};
+
+/// Time the contacts must stay put before a press or release counts
+pub const BUTTON_DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+/// Hold time from which a press counts as long
+pub const BUTTON_DEFAULT_LONG_PRESS: Duration = Duration::from_millis(1000);
+/// Longest gap between a release and the next press of the same multi-click
+pub const BUTTON_DEFAULT_CLICK_WINDOW: Duration = Duration::from_millis(300);
+
+/// A completed press, reported on release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ButtonEvent {
+    ShortPress,
+    /// Held at least the long-press threshold; carries the hold time
+    LongPress(Duration),
+}
+
+/// Logic level of a pressed button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum ButtonPolarity {
+    /// Button to GND with a pull-up
+    #[default]
+    ActiveLow,
+    /// Button to VCC with a pull-down
+    ActiveHigh,
+}
+
+/// Presses in quick succession
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ClickEvent {
+    Click,
+    DoubleClick,
+    TripleClick,
+}
+
+/// Power-management hooks around dormant sleep; `()` does nothing.
+pub trait DormantHooks {
+    /// Called before going dormant; return `false` to stay awake, e.g. while a transfer runs.
+    fn prepare_dormant(&mut self) -> bool {
+        true
+    }
+
+    /// Called after waking up, to re-init peripherals that lost state (USB, WiFi, displays).
+    fn resume(&mut self) {}
+}
+
+impl DormantHooks for () {}
+
+/// Configuration for [`Button`]
+///
+/// Uses struct literal update syntax, like [`UsbHidConfig`](crate::UsbHidConfig).
+///
+/// # Example
+///
+/// ```ignore
+/// let config = ButtonConfig {
+///     long_press_threshold: Duration::from_secs(2),
+///     polarity: ButtonPolarity::ActiveHigh,
+///     ..Default::default()
+/// };
+/// let button = Button::with_config(Input::new(p.PIN_16, Pull::Down), config);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ButtonConfig {
+    /// Time the contacts must stay put before a press or release counts
+    pub debounce: Duration,
+    /// Hold time from which a press counts as long
+    pub long_press_threshold: Duration,
+    /// Longest gap between a release and the next press of the same multi-click
+    pub multi_click_window: Duration,
+    pub polarity: ButtonPolarity,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce: BUTTON_DEFAULT_DEBOUNCE,
+            long_press_threshold: BUTTON_DEFAULT_LONG_PRESS,
+            multi_click_window: BUTTON_DEFAULT_CLICK_WINDOW,
+            polarity: ButtonPolarity::ActiveLow,
+        }
+    }
+}
+
+/// Simple button driver, active-low unless configured otherwise.
+pub struct Button<P, U: Uptime = SystemUptime> {
     pin: P,
+    config: ButtonConfig,
+    uptime: U,
 }
 
 impl<P> Button<P>
@@ -15,14 +167,79 @@ where
     /// Create a new button wrapper.
     /// Caller must configure the pin as pull-up input before calling this.
     pub fn new(pin: P) -> Self {
-        Self { pin }
+        Self::with_polarity(pin, ButtonPolarity::ActiveLow)
+    }
+
+    /// Button that reads high while pressed.
+    /// Caller must configure the pin as pull-down input (or wire an external pull-down).
+    pub fn new_active_high(pin: P) -> Self {
+        Self::with_polarity(pin, ButtonPolarity::ActiveHigh)
+    }
+
+    pub fn with_polarity(pin: P, polarity: ButtonPolarity) -> Self {
+        Self::with_config(
+            pin,
+            ButtonConfig {
+                polarity,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_config(pin: P, config: ButtonConfig) -> Self {
+        Self::with_uptime(pin, config, SystemUptime)
+    }
+}
+
+impl<P, U> Button<P, U>
+where
+    P: InputPin,
+    U: Uptime,
+{
+    /// Button that debounces and measures against `uptime`.
+    pub fn with_uptime(pin: P, config: ButtonConfig, uptime: U) -> Self {
+        Self {
+            pin,
+            config,
+            uptime,
+        }
+    }
+
+    pub fn uptime(&self) -> &U {
+        &self.uptime
+    }
+
+    pub fn config(&self) -> ButtonConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: ButtonConfig) {
+        self.config = config;
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.config.debounce = debounce;
+    }
+
+    pub fn set_long_press_threshold(&mut self, threshold: Duration) {
+        self.config.long_press_threshold = threshold;
+    }
+
+    /// Longer windows make double clicks easier but delay single clicks by as much.
+    pub fn set_click_window(&mut self, window: Duration) {
+        self.config.multi_click_window = window;
+    }
+
+    pub fn polarity(&self) -> ButtonPolarity {
+        self.config.polarity
     }
 
     /// Returns true if the button is currently pressed.
-    /// Assumes active-low wiring (button connects to GND).
     pub fn is_pressed(&mut self) -> bool {
-        // Active low - button pressed = low logic level
-        self.pin.is_low().unwrap_or(false)
+        match self.config.polarity {
+            ButtonPolarity::ActiveLow => self.pin.is_low().unwrap_or(false),
+            ButtonPolarity::ActiveHigh => self.pin.is_high().unwrap_or(false),
+        }
     }
 
     /// Returns true if the button is NOT pressed.
@@ -30,3 +247,168 @@ where
         !self.is_pressed()
     }
 }
+
+/// Interrupt-driven waits, for any pin that can await its edges. A pin error counts as an
+/// edge, so the button is read again.
+impl<P, U> Button<P, U>
+where
+    P: InputPin + Wait,
+    U: Uptime,
+{
+    /// Wait until the button goes down and stays down for the debounce time.
+    pub async fn wait_for_press(&mut self) {
+        loop {
+            Self::press_edge(&mut self.pin, self.config.polarity).await;
+            if self.settle().await {
+                return;
+            }
+        }
+    }
+
+    /// Like [`wait_for_press`](Self::wait_for_press), but gives up at `deadline` unless a
+    /// press has started by then. Returns whether the button was pressed.
+    pub async fn wait_for_press_until(&mut self, deadline: Instant) -> bool {
+        loop {
+            let edge = Self::press_edge(&mut self.pin, self.config.polarity);
+            if let Either::First(()) = select(self.uptime.sleep_until(deadline), edge).await {
+                return false;
+            }
+            if self.settle().await {
+                return true;
+            }
+        }
+    }
+
+    /// Wait for the next edge and return whether the button is pressed once the contacts
+    /// settled. A bounce can return the state the button already had.
+    pub async fn wait_for_change(&mut self) -> bool {
+        let _ = self.pin.wait_for_any_edge().await;
+        self.settle().await
+    }
+
+    /// Wait until the button is up and stays up for the debounce time. Returns at once
+    /// if it is already released.
+    pub async fn wait_for_release(&mut self) {
+        loop {
+            Self::released_level(&mut self.pin, self.config.polarity).await;
+            if !self.settle().await {
+                return;
+            }
+        }
+    }
+
+    /// Like [`wait_for_release`](Self::wait_for_release), but gives up at `deadline` while
+    /// the button is still held. Returns whether the button was released.
+    pub async fn wait_for_release_until(&mut self, deadline: Instant) -> bool {
+        loop {
+            let level = Self::released_level(&mut self.pin, self.config.polarity);
+            if let Either::First(()) = select(self.uptime.sleep_until(deadline), level).await {
+                return false;
+            }
+            if !self.settle().await {
+                return true;
+            }
+        }
+    }
+
+    /// Wait for a press and its release; returns how long the button was held. Both ends
+    /// are debounced, so the debounce delays cancel out of the measurement.
+    pub async fn measured_press(&mut self) -> Duration {
+        self.wait_for_press().await;
+        let pressed_at = self.uptime.now();
+        self.wait_for_release().await;
+        self.uptime.elapsed_since(pressed_at)
+    }
+
+    /// Wait for a press and its release; the hold time decides short or long.
+    pub async fn wait_for_event(&mut self) -> ButtonEvent {
+        let held = self.measured_press().await;
+        if held >= self.config.long_press_threshold {
+            ButtonEvent::LongPress(held)
+        } else {
+            ButtonEvent::ShortPress
+        }
+    }
+
+    /// Wait for one to three clicks. Returns once the click window passes without another
+    /// press, or right after the third release.
+    pub async fn wait_for_clicks(&mut self) -> ClickEvent {
+        self.wait_for_press().await;
+        self.wait_for_release().await;
+        let mut event = ClickEvent::Click;
+        loop {
+            let next = match event {
+                ClickEvent::Click => ClickEvent::DoubleClick,
+                ClickEvent::DoubleClick => ClickEvent::TripleClick,
+                ClickEvent::TripleClick => return event,
+            };
+            let window_end = self.uptime.now() + self.config.multi_click_window;
+            if !self.wait_for_press_until(window_end).await {
+                return event;
+            }
+            self.wait_for_release().await;
+            event = next;
+        }
+    }
+
+    /// Wait until no edge has been seen for the debounce time; returns whether the button
+    /// is pressed then.
+    async fn settle(&mut self) -> bool {
+        while let Either::Second(_) = select(
+            self.uptime.sleep(self.config.debounce),
+            self.pin.wait_for_any_edge(),
+        )
+        .await
+        {}
+        self.is_pressed()
+    }
+
+    async fn press_edge(pin: &mut P, polarity: ButtonPolarity) {
+        let _ = match polarity {
+            ButtonPolarity::ActiveLow => pin.wait_for_falling_edge().await,
+            ButtonPolarity::ActiveHigh => pin.wait_for_rising_edge().await,
+        };
+    }
+
+    async fn released_level(pin: &mut P, polarity: ButtonPolarity) {
+        let _ = match polarity {
+            ButtonPolarity::ActiveLow => pin.wait_for_high().await,
+            ButtonPolarity::ActiveHigh => pin.wait_for_low().await,
+        };
+    }
+}
+
+impl<U: Uptime> Button<Input<'_>, U> {
+    /// Like [`wait_for_press`](Self::wait_for_press), but after `idle` without a press the
+    /// chip goes dormant until the button is pressed. The waking press is returned as a
+    /// normal press once it is debounced; if it bounced away, the wait starts over.
+    pub async fn wait_for_press_allow_sleep<H: DormantHooks>(
+        &mut self,
+        idle: Duration,
+        hooks: &mut H,
+    ) {
+        loop {
+            if self.wait_for_press_until(self.uptime.now() + idle).await {
+                return;
+            }
+            if !hooks.prepare_dormant() {
+                continue;
+            }
+            {
+                let _wake = self.pin.dormant_wake(DormantWakeConfig {
+                    edge_low: self.config.polarity == ButtonPolarity::ActiveLow,
+                    edge_high: self.config.polarity == ButtonPolarity::ActiveHigh,
+                    ..Default::default()
+                });
+                // SAFETY: the wake source is armed; dormant_sleep restores the clock setup
+                // before returning, and no clock is reconfigured concurrently since every
+                // other task is suspended with the core.
+                unsafe { embassy_rp::clocks::dormant_sleep() };
+            }
+            hooks.resume();
+            if self.settle().await {
+                return;
+            }
+        }
+    }
+}
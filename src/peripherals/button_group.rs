@@ -0,0 +1,113 @@
+//! button_group.rs — several buttons read together, for chords like "A+B"
+//!
+//! A [`ButtonGroup`] owns up to 32 buttons; bit `i` of every mask it returns is button `i`.
+//! [`ButtonGroup::snapshot`] reads them all at once, [`ButtonGroup::wait_for_change`]
+//! sleeps until any of them changes. Fingers never hit two keys at exactly the same time,
+//! so [`ButtonGroup::wait_for_chord`] collects every button pressed within the chord window
+//! after the first one and reports them as one chord. The window runs on the group's
+//! [`Uptime`](crate::Uptime), which [`ButtonGroup::with_uptime`] sets.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut buttons = ButtonGroup::new([
+//!     Button::new(Input::new(p.PIN_13, Pull::Up)),
+//!     Button::new(Input::new(p.PIN_14, Pull::Up)),
+//!     Button::new(Input::new(p.PIN_15, Pull::Up)),
+//! ]);
+//! loop {
+//!     match buttons.wait_for_chord().await {
+//!         0b001 => previous(),
+//!         0b010 => next(),
+//!         0b011 => enter_setup(),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use embassy_futures::select::{Either, select, select_array};
+use embassy_rp::gpio::Input;
+use embassy_time::Duration;
+
+use crate::{Button, SystemUptime, Uptime};
+
+/// Time after the first press in which further presses join the chord
+pub const BUTTON_GROUP_DEFAULT_CHORD_WINDOW: Duration = Duration::from_millis(80);
+
+pub struct ButtonGroup<'d, const N: usize, U: Uptime = SystemUptime> {
+    buttons: [Button<Input<'d>, U>; N],
+    chord_window: Duration,
+    uptime: U,
+}
+
+impl<'d, const N: usize> ButtonGroup<'d, N> {
+    pub fn new(buttons: [Button<Input<'d>>; N]) -> Self {
+        Self::with_uptime(buttons, SystemUptime)
+    }
+}
+
+impl<'d, const N: usize, U: Uptime> ButtonGroup<'d, N, U> {
+    /// Group whose chord window runs on `uptime`; give the buttons the same source.
+    pub fn with_uptime(buttons: [Button<Input<'d>, U>; N], uptime: U) -> Self {
+        const { assert!(N <= 32, "a ButtonGroup holds at most 32 buttons") };
+        Self {
+            buttons,
+            chord_window: BUTTON_GROUP_DEFAULT_CHORD_WINDOW,
+            uptime,
+        }
+    }
+
+    pub fn set_chord_window(&mut self, window: Duration) {
+        self.chord_window = window;
+    }
+
+    pub fn button(&mut self, index: usize) -> Option<&mut Button<Input<'d>, U>> {
+        self.buttons.get_mut(index)
+    }
+
+    /// Mask of the buttons pressed right now, without debouncing
+    pub fn snapshot(&mut self) -> u32 {
+        self.buttons
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, button)| button.is_pressed().then_some(1 << i))
+            .fold(0, |mask, bit| mask | bit)
+    }
+
+    /// Wait until a button changes and settles; returns the new mask.
+    pub async fn wait_for_change(&mut self) -> u32 {
+        Self::any_change(&mut self.buttons).await;
+        self.snapshot()
+    }
+
+    /// Wait for a chord: all buttons released, then one or more pressed within the chord
+    /// window. Returns the mask of every button pressed in the window.
+    pub async fn wait_for_chord(&mut self) -> u32 {
+        while self.snapshot() != 0 {
+            self.wait_for_change().await;
+        }
+        let mut chord = 0;
+        while chord == 0 {
+            chord = self.wait_for_change().await;
+        }
+        let deadline = self.uptime.now() + self.chord_window;
+        while let Either::Second(()) = select(
+            self.uptime.sleep_until(deadline),
+            Self::any_change(&mut self.buttons),
+        )
+        .await
+        {
+            chord |= self.snapshot();
+        }
+        chord
+    }
+
+    /// Wait until exactly the buttons in `combo` are pressed together as a chord.
+    pub async fn wait_for_combo(&mut self, combo: u32) {
+        while self.wait_for_chord().await != combo {}
+    }
+
+    async fn any_change(buttons: &mut [Button<Input<'d>, U>; N]) {
+        select_array(buttons.each_mut().map(|button| button.wait_for_change())).await;
+    }
+}
@@ -0,0 +1,219 @@
+//! button_task.rs — background task publishing button events to subscribers
+//!
+//! Hand the buttons to a [`ButtonTask`] and spawn it; it waits on all of them at once and
+//! publishes every [`ButtonEvent`] to [`BUTTON_EVENTS`], tagged with the index the button
+//! got when it was added. Any task can subscribe without owning a pin, and several tasks can
+//! listen to the same buttons. Subscribers that fall more than [`BUTTON_EVENT_QUEUE_LEN`]
+//! events behind lose the oldest ones instead of stalling the buttons.
+//!
+//! For small sketches that just want a function called on every press,
+//! [`spawn_button_handler`] spawns a task per button that does exactly that. Async
+//! handlers can't be stored in a task, so [`run_button_handler`] and
+//! [`run_button_message_handler`] run an async closure in the caller's own task instead.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut buttons = ButtonTask::new();
+//! let select = buttons.add(Button::new(Input::new(p.PIN_14, Pull::Up)))?;
+//! let back = buttons.add(Button::new(Input::new(p.PIN_15, Pull::Up)))?;
+//! buttons.spawn(&spawner);
+//!
+//! let mut events = BUTTON_EVENTS.subscriber().unwrap();
+//! loop {
+//!     match events.next_message_pure().await {
+//!         ButtonMessage { button, event: ButtonEvent::ShortPress } if button == select => menu.enter(),
+//!         ButtonMessage { button, .. } if button == back => menu.back(),
+//!         _ => {}
+//!     }
+//! }
+//!
+//! // Plain function per button
+//! fn toggle_led(event: ButtonEvent) { LED_ON.fetch_xor(true, Ordering::Relaxed); }
+//! spawn_button_handler(&spawner, Button::new(Input::new(p.PIN_16, Pull::Up)), toggle_led)?;
+//!
+//! // Async closure in this task
+//! run_button_handler(&mut button, async |event| {
+//!     buzzer.beep(Duration::from_millis(50)).await;
+//! })
+//! .await
+//! ```
+
+use core::future::Future;
+
+use embassy_executor::{Spawner, task};
+use embassy_futures::join::join_array;
+use embassy_rp::gpio::Input;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{ImmediatePublisher, PubSubChannel, Subscriber};
+
+use crate::{Button, ButtonEvent};
+
+/// Buttons one [`ButtonTask`] can watch
+pub const BUTTON_TASK_MAX_BUTTONS: usize = 8;
+/// Events buffered per subscriber
+pub const BUTTON_EVENT_QUEUE_LEN: usize = 8;
+/// Tasks that can subscribe to [`BUTTON_EVENTS`] at the same time
+pub const BUTTON_EVENT_MAX_SUBSCRIBERS: usize = 4;
+/// Tasks [`spawn_button_handler`] can spawn in total
+pub const BUTTON_HANDLER_POOL_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum ButtonTaskError {
+    #[error("Too many buttons")]
+    Full,
+    #[error("All button handler tasks are in use")]
+    NoHandlerTask,
+}
+
+/// A button event and the index of the button it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ButtonMessage {
+    pub button: u8,
+    pub event: ButtonEvent,
+}
+
+pub type ButtonEventChannel = PubSubChannel<
+    CriticalSectionRawMutex,
+    ButtonMessage,
+    BUTTON_EVENT_QUEUE_LEN,
+    BUTTON_EVENT_MAX_SUBSCRIBERS,
+    0,
+>;
+
+pub type ButtonEventSubscriber = Subscriber<
+    'static,
+    CriticalSectionRawMutex,
+    ButtonMessage,
+    BUTTON_EVENT_QUEUE_LEN,
+    BUTTON_EVENT_MAX_SUBSCRIBERS,
+    0,
+>;
+
+/// Events of the spawned [`ButtonTask`]
+pub static BUTTON_EVENTS: ButtonEventChannel = PubSubChannel::new();
+
+type ButtonEventPublisher = ImmediatePublisher<
+    'static,
+    CriticalSectionRawMutex,
+    ButtonMessage,
+    BUTTON_EVENT_QUEUE_LEN,
+    BUTTON_EVENT_MAX_SUBSCRIBERS,
+    0,
+>;
+
+/// Up to [`BUTTON_TASK_MAX_BUTTONS`] buttons watched by one task
+pub struct ButtonTask {
+    buttons: [Option<Button<Input<'static>>>; BUTTON_TASK_MAX_BUTTONS],
+}
+
+impl ButtonTask {
+    pub fn new() -> Self {
+        Self {
+            buttons: [const { None }; BUTTON_TASK_MAX_BUTTONS],
+        }
+    }
+
+    /// Add a button; returns the index its events will carry.
+    pub fn add(&mut self, button: Button<Input<'static>>) -> Result<u8, ButtonTaskError> {
+        let (index, slot) = self
+            .buttons
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(ButtonTaskError::Full)?;
+        *slot = Some(button);
+        Ok(index as u8)
+    }
+
+    /// Spawn the task publishing to [`BUTTON_EVENTS`].
+    pub fn spawn(self, spawner: &Spawner) {
+        spawner.spawn(button_task(self).expect("failed to spawn button_task"));
+    }
+
+    /// Publish events to [`BUTTON_EVENTS`] forever; for callers running it in their own task.
+    pub async fn run(mut self) -> ! {
+        let publisher = BUTTON_EVENTS.immediate_publisher();
+        let mut index = 0;
+        let watchers = self.buttons.each_mut().map(|slot| {
+            let button = index;
+            index += 1;
+            watch(button, slot, &publisher)
+        });
+        // Watchers never return, so neither does the join
+        let [never, ..] = join_array(watchers).await;
+        never
+    }
+}
+
+impl Default for ButtonTask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn watch(
+    index: u8,
+    slot: &mut Option<Button<Input<'static>>>,
+    publisher: &ButtonEventPublisher,
+) -> ! {
+    let Some(button) = slot else {
+        return core::future::pending().await;
+    };
+    loop {
+        let event = button.wait_for_event().await;
+        publisher.publish_immediate(ButtonMessage {
+            button: index,
+            event,
+        });
+    }
+}
+
+#[task]
+async fn button_task(buttons: ButtonTask) {
+    buttons.run().await
+}
+
+/// Spawn a task that calls `handler` with every event of `button`.
+pub fn spawn_button_handler(
+    spawner: &Spawner,
+    button: Button<Input<'static>>,
+    handler: fn(ButtonEvent),
+) -> Result<(), ButtonTaskError> {
+    let token = button_handler_task(button, handler).map_err(|_| ButtonTaskError::NoHandlerTask)?;
+    spawner.spawn(token);
+    Ok(())
+}
+
+/// Wait for events of `button` forever, awaiting `handler` for each. Events that happen
+/// while the handler runs are missed.
+pub async fn run_button_handler<F, Fut>(button: &mut Button<Input<'_>>, mut handler: F) -> !
+where
+    F: FnMut(ButtonEvent) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        handler(button.wait_for_event().await).await;
+    }
+}
+
+/// Await `handler` for every message from a [`ButtonTask`], forever.
+pub async fn run_button_message_handler<F, Fut>(
+    events: &mut ButtonEventSubscriber,
+    mut handler: F,
+) -> !
+where
+    F: FnMut(ButtonMessage) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        handler(events.next_message_pure().await).await;
+    }
+}
+
+#[task(pool_size = BUTTON_HANDLER_POOL_SIZE)]
+async fn button_handler_task(mut button: Button<Input<'static>>, handler: fn(ButtonEvent)) {
+    loop {
+        handler(button.wait_for_event().await);
+    }
+}
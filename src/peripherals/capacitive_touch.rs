@@ -0,0 +1,315 @@
+//! capacitive_touch.rs — touch pads on a bare GPIO, measured by charge time
+//!
+//! A pad (a coin of copper, a strip of foil under the enclosure) is a small capacitor; a
+//! finger adds to it. [`CapacitiveTouchButton`] discharges the pin, lets the internal
+//! pull-up charge it and counts how long it takes to read high. A touch makes that count
+//! rise by tens of percent over the untouched *baseline*, so no extra parts are needed.
+//! Each reading is the shortest of several charges: an interrupt can only make a charge
+//! look longer.
+//!
+//! The baseline is taken from the first reading (or [`CapacitiveTouchButton::calibrate`])
+//! and then follows slow changes of humidity and temperature while the pad isn't touched.
+//! The pad is sampled every poll interval and reports the same presses as a [`Button`],
+//! debounced and timed by a [`ButtonConfig`] (the polarity is ignored).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut pad = CapacitiveTouchButton::new(Flex::new(p.PIN_18), TouchConfig::default());
+//! pad.calibrate().await; // hands off the pad
+//! loop {
+//!     match pad.wait_for_event().await {
+//!         ButtonEvent::ShortPress => lamp.toggle(),
+//!         ButtonEvent::LongPress(_) => lamp.dim(),
+//!     }
+//! }
+//! ```
+
+use embassy_rp::gpio::{Flex, Pull};
+use embassy_time::{Duration, Instant, Timer, block_for};
+
+use crate::{Button, ButtonConfig, ButtonEvent};
+
+/// Rise over the baseline, in percent, that counts as a touch
+pub const TOUCH_DEFAULT_THRESHOLD_PERCENT: u16 = 40;
+/// How far below the touch threshold a reading must fall to count as released, in percent
+/// of the baseline
+pub const TOUCH_DEFAULT_HYSTERESIS_PERCENT: u16 = 10;
+/// Charges per reading
+pub const TOUCH_DEFAULT_SAMPLES: u8 = 8;
+/// Time between readings while waiting
+pub const TOUCH_DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// The baseline moves 1/2^shift of the way to each untouched reading
+pub const TOUCH_DEFAULT_DRIFT_SHIFT: u8 = 6;
+
+/// Time the pin is held low to empty the pad
+const DISCHARGE_TIME: Duration = Duration::from_micros(5);
+/// Give up on a charge after this many loops (a pad shorted to GND)
+const MAX_CHARGE_COUNT: u32 = 10_000;
+/// Readings averaged by [`CapacitiveTouchButton::calibrate`]
+const CALIBRATION_READINGS: u32 = 16;
+/// Fractional bits of the stored baseline
+const BASELINE_FRACTION_BITS: u32 = 8;
+
+/// Configuration for [`CapacitiveTouchButton`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TouchConfig {
+    pub threshold_percent: u16,
+    pub hysteresis_percent: u16,
+    pub samples: u8,
+    pub poll_interval: Duration,
+    pub drift_shift: u8,
+}
+
+impl Default for TouchConfig {
+    fn default() -> Self {
+        Self {
+            threshold_percent: TOUCH_DEFAULT_THRESHOLD_PERCENT,
+            hysteresis_percent: TOUCH_DEFAULT_HYSTERESIS_PERCENT,
+            samples: TOUCH_DEFAULT_SAMPLES,
+            poll_interval: TOUCH_DEFAULT_POLL_INTERVAL,
+            drift_shift: TOUCH_DEFAULT_DRIFT_SHIFT,
+        }
+    }
+}
+
+/// Classifies readings, tracks the baseline and debounces; no hardware, so it can be tested.
+#[derive(Debug, Clone, Copy)]
+struct TouchState {
+    config: TouchConfig,
+    debounce: Duration,
+    /// Untouched reading with [`BASELINE_FRACTION_BITS`]; `None` until the first reading
+    baseline: Option<u32>,
+    /// Whether the readings say touched, and since when
+    touched: bool,
+    since: Instant,
+    /// Debounced state
+    pressed: bool,
+}
+
+impl TouchState {
+    fn new(config: TouchConfig, debounce: Duration) -> Self {
+        Self {
+            config,
+            debounce,
+            baseline: None,
+            touched: false,
+            since: Instant::from_ticks(0),
+            pressed: false,
+        }
+    }
+
+    fn set_baseline(&mut self, raw: u32) {
+        self.baseline = Some(raw << BASELINE_FRACTION_BITS);
+    }
+
+    /// Reading at `percent` above the baseline, at least one count above it
+    fn level(baseline: u32, percent: u16) -> u32 {
+        let base = baseline >> BASELINE_FRACTION_BITS;
+        let rise = (baseline as u64 * percent as u64 / 100) >> BASELINE_FRACTION_BITS;
+        base + (rise as u32).max(1)
+    }
+
+    /// Feed one reading; returns the new debounced state once a change held for the
+    /// debounce time.
+    fn update(&mut self, raw: u32, now: Instant) -> Option<bool> {
+        let Some(baseline) = self.baseline else {
+            self.set_baseline(raw);
+            return None;
+        };
+        let percent = if self.touched {
+            self.config
+                .threshold_percent
+                .saturating_sub(self.config.hysteresis_percent)
+        } else {
+            self.config.threshold_percent
+        };
+        let touched = raw >= Self::level(baseline, percent);
+        if !touched && !self.pressed {
+            // Follow drift only while nothing touches the pad
+            let target = (raw << BASELINE_FRACTION_BITS) as i64;
+            let step = (target - baseline as i64) >> self.config.drift_shift;
+            self.baseline = Some((baseline as i64 + step) as u32);
+        }
+        if touched != self.touched {
+            self.touched = touched;
+            self.since = now;
+        }
+        if touched == self.pressed || now.saturating_duration_since(self.since) < self.debounce {
+            return None;
+        }
+        self.pressed = touched;
+        Some(touched)
+    }
+}
+
+/// Touch pad with the press API of a [`Button`]
+pub struct CapacitiveTouchButton<'d> {
+    pin: Flex<'d>,
+    button: ButtonConfig,
+    state: TouchState,
+}
+
+impl<'d> CapacitiveTouchButton<'d> {
+    /// The pad goes straight on the pin; keep the trace short and away from other signals.
+    pub fn new(mut pin: Flex<'d>, config: TouchConfig) -> Self {
+        pin.set_pull(Pull::Up);
+        let button = ButtonConfig::default();
+        Self {
+            pin,
+            button,
+            state: TouchState::new(config, button.debounce),
+        }
+    }
+
+    pub fn set_config(&mut self, config: TouchConfig) {
+        self.state.config = config;
+    }
+
+    /// Debounce and press timings; see [`Button::set_config`].
+    pub fn set_button_config(&mut self, config: ButtonConfig) {
+        self.button = config;
+        self.state.debounce = config.debounce;
+    }
+
+    /// Untouched reading in charge counts, once known
+    pub fn baseline(&self) -> Option<u32> {
+        self.state
+            .baseline
+            .map(|baseline| baseline >> BASELINE_FRACTION_BITS)
+    }
+
+    /// Debounced state, as of the last reading
+    pub fn is_pressed(&self) -> bool {
+        self.state.pressed
+    }
+
+    /// One reading: the shortest charge time of the configured number of charges, in loop
+    /// counts. Blocks for a few microseconds per charge.
+    pub fn read_raw(&mut self) -> u32 {
+        (0..self.config().samples.max(1))
+            .map(|_| self.charge_time())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Take the baseline from the average of a few readings; the pad must not be touched.
+    pub async fn calibrate(&mut self) {
+        let mut sum = 0;
+        for _ in 0..CALIBRATION_READINGS {
+            sum += self.read_raw();
+            Timer::after(self.config().poll_interval).await;
+        }
+        self.state.set_baseline(sum / CALIBRATION_READINGS);
+    }
+
+    /// Take one reading; returns the new state once a touch or release is debounced.
+    pub fn poll(&mut self) -> Option<bool> {
+        let raw = self.read_raw();
+        self.state.update(raw, Instant::now())
+    }
+
+    /// Wait until the pad is touched, debounced.
+    pub async fn wait_for_press(&mut self) {
+        self.wait_for_state(true).await;
+    }
+
+    /// Wait until the pad is released, debounced. Returns at once if it isn't touched.
+    pub async fn wait_for_release(&mut self) {
+        self.wait_for_state(false).await;
+    }
+
+    /// Wait for a touch and its release; returns how long the pad was touched.
+    pub async fn measured_press(&mut self) -> Duration {
+        self.wait_for_press().await;
+        let pressed_at = Instant::now();
+        self.wait_for_release().await;
+        pressed_at.elapsed()
+    }
+
+    /// Wait for a touch and its release; the hold time decides short or long, like
+    /// [`Button::wait_for_event`].
+    pub async fn wait_for_event(&mut self) -> ButtonEvent {
+        let held = self.measured_press().await;
+        if held >= self.button.long_press_threshold {
+            ButtonEvent::LongPress(held)
+        } else {
+            ButtonEvent::ShortPress
+        }
+    }
+
+    fn config(&self) -> TouchConfig {
+        self.state.config
+    }
+
+    async fn wait_for_state(&mut self, pressed: bool) {
+        while self.state.pressed != pressed {
+            if self.poll() == Some(pressed) {
+                return;
+            }
+            Timer::after(self.config().poll_interval).await;
+        }
+    }
+
+    fn charge_time(&mut self) -> u32 {
+        self.pin.set_low();
+        self.pin.set_as_output();
+        block_for(DISCHARGE_TIME);
+        self.pin.set_as_input();
+        let mut count = 0;
+        while self.pin.is_low() && count < MAX_CHARGE_COUNT {
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> TouchState {
+        let mut state = TouchState::new(TouchConfig::default(), Duration::from_millis(20));
+        state.set_baseline(100);
+        state
+    }
+
+    #[test]
+    fn debounced_touch_with_hysteresis() {
+        let at = Instant::from_millis;
+        let mut state = state();
+        assert_eq!(state.update(150, at(0)), None);
+        assert_eq!(state.update(100, at(10)), None);
+        assert_eq!(state.update(150, at(15)), None);
+        assert_eq!(state.update(145, at(35)), Some(true));
+        // Still touched above the release level of 130
+        assert_eq!(state.update(132, at(60)), None);
+        assert_eq!(state.update(120, at(70)), None);
+        assert_eq!(state.update(100, at(90)), Some(false));
+    }
+
+    #[test]
+    fn baseline_follows_drift_only_when_untouched() {
+        let at = Instant::from_millis;
+        let mut state = state();
+        for t in 0..200 {
+            state.update(120, at(t));
+        }
+        assert_eq!(
+            state.baseline.map(|b| b >> BASELINE_FRACTION_BITS),
+            Some(119)
+        );
+        state.update(200, at(300));
+        state.update(200, at(400));
+        let touched = state.baseline;
+        state.update(200, at(500));
+        assert_eq!(state.baseline, touched);
+    }
+
+    #[test]
+    fn first_reading_sets_baseline() {
+        let mut state = TouchState::new(TouchConfig::default(), Duration::from_millis(20));
+        assert_eq!(state.update(80, Instant::from_millis(0)), None);
+        assert_eq!(state.baseline, Some(80 << BASELINE_FRACTION_BITS));
+    }
+}
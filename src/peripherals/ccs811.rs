@@ -0,0 +1,170 @@
+//! ccs811.rs — ams CCS811 eCO2/TVOC sensor driver (I2C)
+
+use embassy_time::Timer;
+
+use crate::{AirQualityReading, AirQualitySensor};
+
+pub const CCS811_DEFAULT_I2C_ADDRESS: u8 = 0x5A;
+pub const CCS811_ALT_I2C_ADDRESS: u8 = 0x5B;
+
+const REG_STATUS: u8 = 0x00;
+const REG_MEAS_MODE: u8 = 0x01;
+const REG_ALG_RESULT_DATA: u8 = 0x02;
+const REG_ENV_DATA: u8 = 0x05;
+const REG_BASELINE: u8 = 0x11;
+const REG_HW_ID: u8 = 0x20;
+const REG_ERROR_ID: u8 = 0xE0;
+const REG_APP_START: u8 = 0xF4;
+
+const HW_ID: u8 = 0x81;
+
+const STATUS_ERROR: u8 = 1 << 0;
+const STATUS_DATA_READY: u8 = 1 << 3;
+const STATUS_APP_VALID: u8 = 1 << 4;
+const STATUS_FW_MODE: u8 = 1 << 7;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum Ccs811Error {
+    #[error("I2C communication with CCS811 failed")]
+    Communication,
+    #[error("Unexpected CCS811 hardware ID: {0:#x}")]
+    WrongHardwareId(u8),
+    #[error("CCS811 has no valid application firmware")]
+    NoValidApp,
+    #[error("CCS811 reported error {0:#x}")]
+    Device(u8),
+}
+
+/// CCS811 measurement interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Ccs811DriveMode {
+    Idle = 0,
+    EverySecond = 1,
+    Every10Seconds = 2,
+    Every60Seconds = 3,
+}
+
+/// CCS811 driver
+pub struct Ccs811<I: embedded_hal::i2c::I2c> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: embedded_hal::i2c::I2c> Ccs811<I> {
+    /// Check the hardware, start the application firmware and begin measuring.
+    pub async fn new(i2c: I, address: u8, mode: Ccs811DriveMode) -> Result<Self, Ccs811Error> {
+        let mut sensor = Self { i2c, address };
+        // Boot time after power-up
+        Timer::after_millis(20).await;
+
+        let hw_id = sensor.read_register(REG_HW_ID)?;
+        if hw_id != HW_ID {
+            return Err(Ccs811Error::WrongHardwareId(hw_id));
+        }
+        if sensor.read_register(REG_STATUS)? & STATUS_APP_VALID == 0 {
+            return Err(Ccs811Error::NoValidApp);
+        }
+
+        sensor
+            .i2c
+            .write(sensor.address, &[REG_APP_START])
+            .map_err(|_| Ccs811Error::Communication)?;
+        Timer::after_millis(1).await;
+        if sensor.read_register(REG_STATUS)? & STATUS_FW_MODE == 0 {
+            return Err(Ccs811Error::NoValidApp);
+        }
+
+        sensor.set_drive_mode(mode)?;
+        Ok(sensor)
+    }
+
+    pub async fn new_with_default_address(
+        i2c: I,
+        mode: Ccs811DriveMode,
+    ) -> Result<Self, Ccs811Error> {
+        Self::new(i2c, CCS811_DEFAULT_I2C_ADDRESS, mode).await
+    }
+
+    pub fn set_drive_mode(&mut self, mode: Ccs811DriveMode) -> Result<(), Ccs811Error> {
+        self.write(&[REG_MEAS_MODE, (mode as u8) << 4])
+    }
+
+    /// Latest result, or `None` if no new sample is ready.
+    pub fn read(&mut self) -> Result<Option<AirQualityReading>, Ccs811Error> {
+        let status = self.read_register(REG_STATUS)?;
+        if status & STATUS_ERROR != 0 {
+            return Err(Ccs811Error::Device(self.read_register(REG_ERROR_ID)?));
+        }
+        if status & STATUS_DATA_READY == 0 {
+            return Ok(None);
+        }
+
+        let mut data = [0u8; 4];
+        self.read_registers(REG_ALG_RESULT_DATA, &mut data)?;
+        Ok(Some(AirQualityReading {
+            eco2_ppm: u16::from_be_bytes([data[0], data[1]]),
+            tvoc_ppb: u16::from_be_bytes([data[2], data[3]]),
+        }))
+    }
+
+    /// Encoded baseline (opaque to the host, restore it as-is)
+    pub fn baseline(&mut self) -> Result<u16, Ccs811Error> {
+        let mut data = [0u8; 2];
+        self.read_registers(REG_BASELINE, &mut data)?;
+        Ok(u16::from_be_bytes(data))
+    }
+
+    pub fn set_baseline(&mut self, baseline: u16) -> Result<(), Ccs811Error> {
+        let [hi, lo] = baseline.to_be_bytes();
+        self.write(&[REG_BASELINE, hi, lo])
+    }
+
+    /// Environmental compensation from a humidity/temperature sensor.
+    pub fn set_environment(
+        &mut self,
+        humidity_percent: f32,
+        temperature_c: f32,
+    ) -> Result<(), Ccs811Error> {
+        // Both values are 7.9 fixed point; temperature is offset by 25 °C.
+        let humidity = (humidity_percent.clamp(0.0, 100.0) * 512.0) as u16;
+        let temperature = ((temperature_c.clamp(-25.0, 100.0) + 25.0) * 512.0) as u16;
+        let [h_hi, h_lo] = humidity.to_be_bytes();
+        let [t_hi, t_lo] = temperature.to_be_bytes();
+        self.write(&[REG_ENV_DATA, h_hi, h_lo, t_hi, t_lo])
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Ccs811Error> {
+        let mut data = [0u8; 1];
+        self.read_registers(register, &mut data)?;
+        Ok(data[0])
+    }
+
+    fn read_registers(&mut self, register: u8, data: &mut [u8]) -> Result<(), Ccs811Error> {
+        self.i2c
+            .write_read(self.address, &[register], data)
+            .map_err(|_| Ccs811Error::Communication)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Ccs811Error> {
+        self.i2c
+            .write(self.address, data)
+            .map_err(|_| Ccs811Error::Communication)
+    }
+}
+
+impl<I: embedded_hal::i2c::I2c> AirQualitySensor for Ccs811<I> {
+    type Error = Ccs811Error;
+    type Baseline = u16;
+
+    async fn measure(&mut self) -> Result<Option<AirQualityReading>, Self::Error> {
+        self.read()
+    }
+
+    async fn baseline(&mut self) -> Result<Self::Baseline, Self::Error> {
+        Ccs811::baseline(self)
+    }
+
+    async fn set_baseline(&mut self, baseline: Self::Baseline) -> Result<(), Self::Error> {
+        Ccs811::set_baseline(self, baseline)
+    }
+}
@@ -0,0 +1,89 @@
+//! dc_motor.rs — brushed DC motor on a two-input H-bridge (DRV8833, TB6612, L9110S, …)
+//!
+//! Both bridge inputs are driven from channels A and B of one PWM slice. Forward drives A
+//! with the duty cycle and holds B low; reverse swaps them.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pwm = Pwm::new_output_ab(p.PWM_SLICE3, p.PIN_6, p.PIN_7, Default::default());
+//! let mut motor = DcMotor::new(pwm);
+//! motor.set_speed(0.5); // half speed forward
+//! motor.brake();
+//! ```
+
+use embassy_rp::pwm::{Config, Pwm};
+
+use crate::sys_clock_hz;
+
+/// PWM frequency: above the audible range, low enough for H-bridge switching losses
+pub const DC_MOTOR_PWM_HZ: u32 = 20_000;
+
+pub struct DcMotor<'d> {
+    pwm: Pwm<'d>,
+    config: Config,
+    top: u16,
+    reversed: bool,
+    speed: f32,
+}
+
+impl<'d> DcMotor<'d> {
+    /// `pwm` must be created with `Pwm::new_output_ab`. The motor starts coasting.
+    pub fn new(pwm: Pwm<'d>) -> Self {
+        let top = (sys_clock_hz() / DC_MOTOR_PWM_HZ).clamp(2, u16::MAX as u32) as u16 - 1;
+        let mut config = Config::default();
+        config.top = top;
+        config.compare_a = 0;
+        config.compare_b = 0;
+        let mut motor = Self {
+            pwm,
+            config,
+            top,
+            reversed: false,
+            speed: 0.0,
+        };
+        motor.apply();
+        motor
+    }
+
+    /// Swap forward and reverse, for motors wired the other way round.
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+        let speed = self.speed;
+        self.set_speed(speed);
+    }
+
+    /// Drive at `speed` from -1.0 (full reverse) to 1.0 (full forward); 0.0 coasts.
+    pub fn set_speed(&mut self, speed: f32) {
+        let speed = speed.clamp(-1.0, 1.0);
+        self.speed = speed;
+        let duty = (speed.abs() * (self.top as f32 + 1.0)) as u16;
+        let forward = (speed >= 0.0) != self.reversed;
+        let (a, b) = if forward { (duty, 0) } else { (0, duty) };
+        self.config.compare_a = a;
+        self.config.compare_b = b;
+        self.apply();
+    }
+
+    /// Last speed set with [`DcMotor::set_speed`]
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Let the motor spin freely (both inputs low).
+    pub fn coast(&mut self) {
+        self.set_speed(0.0);
+    }
+
+    /// Short the motor windings for fast stopping (both inputs high).
+    pub fn brake(&mut self) {
+        self.speed = 0.0;
+        self.config.compare_a = self.top + 1;
+        self.config.compare_b = self.top + 1;
+        self.apply();
+    }
+
+    fn apply(&mut self) {
+        self.pwm.set_config(&self.config);
+    }
+}
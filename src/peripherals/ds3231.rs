@@ -0,0 +1,137 @@
+//! ds3231.rs — DS3231 temperature-compensated real-time clock driver (I2C)
+//!
+//! The RTC keeps UTC in 24-hour mode; apply a [`crate::TimeZone`] for local time.
+
+use crate::DateTime;
+
+pub const DS3231_I2C_ADDRESS: u8 = 0x68;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_STATUS: u8 = 0x0F;
+const REG_TEMP_MSB: u8 = 0x11;
+
+/// Oscillator-stop flag: time is invalid after a power loss without a backup battery
+const STATUS_OSF: u8 = 0x80;
+/// 12-hour mode bit in the hours register
+const HOURS_12H: u8 = 0x40;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum Ds3231Error {
+    #[error("DS3231 I2C communication failed")]
+    I2c,
+    #[error("DS3231 returned an invalid date/time")]
+    InvalidTime,
+    #[error("DS3231 oscillator stopped; time must be set again")]
+    OscillatorStopped,
+}
+
+pub struct Ds3231<I: embedded_hal::i2c::I2c> {
+    i2c: I,
+}
+
+impl<I: embedded_hal::i2c::I2c> Ds3231<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Current date/time (UTC). Fails with [`Ds3231Error::OscillatorStopped`] if the clock
+    /// lost power since it was last set.
+    pub fn datetime(&mut self) -> Result<DateTime, Ds3231Error> {
+        if self.read_register(REG_STATUS)? & STATUS_OSF != 0 {
+            return Err(Ds3231Error::OscillatorStopped);
+        }
+
+        let mut regs = [0u8; 7];
+        self.i2c
+            .write_read(DS3231_I2C_ADDRESS, &[REG_SECONDS], &mut regs)
+            .map_err(|_| Ds3231Error::I2c)?;
+
+        let hour = if regs[2] & HOURS_12H != 0 {
+            let pm = regs[2] & 0x20 != 0;
+            let hour12 = bcd_to_bin(regs[2] & 0x1F) % 12;
+            if pm { hour12 + 12 } else { hour12 }
+        } else {
+            bcd_to_bin(regs[2] & 0x3F)
+        };
+        let century = if regs[5] & 0x80 != 0 { 100 } else { 0 };
+
+        let datetime = DateTime {
+            year: 2000 + century + bcd_to_bin(regs[6]) as u16,
+            month: bcd_to_bin(regs[5] & 0x1F),
+            day: bcd_to_bin(regs[4] & 0x3F),
+            hour,
+            minute: bcd_to_bin(regs[1] & 0x7F),
+            second: bcd_to_bin(regs[0] & 0x7F),
+        };
+        if !datetime.is_valid() {
+            return Err(Ds3231Error::InvalidTime);
+        }
+        Ok(datetime)
+    }
+
+    /// Set the date/time (UTC, years 2000–2199) and clear the oscillator-stop flag.
+    pub fn set_datetime(&mut self, datetime: &DateTime) -> Result<(), Ds3231Error> {
+        if !datetime.is_valid() || !(2000..2200).contains(&datetime.year) {
+            return Err(Ds3231Error::InvalidTime);
+        }
+        let year = datetime.year - 2000;
+        let century = if year >= 100 { 0x80 } else { 0 };
+
+        self.i2c
+            .write(
+                DS3231_I2C_ADDRESS,
+                &[
+                    REG_SECONDS,
+                    bin_to_bcd(datetime.second),
+                    bin_to_bcd(datetime.minute),
+                    bin_to_bcd(datetime.hour),
+                    // Day-of-week register is 1–7
+                    datetime.weekday().index() + 1,
+                    bin_to_bcd(datetime.day),
+                    bin_to_bcd(datetime.month) | century,
+                    bin_to_bcd((year % 100) as u8),
+                ],
+            )
+            .map_err(|_| Ds3231Error::I2c)?;
+
+        let status = self.read_register(REG_STATUS)?;
+        self.i2c
+            .write(DS3231_I2C_ADDRESS, &[REG_STATUS, status & !STATUS_OSF])
+            .map_err(|_| Ds3231Error::I2c)
+    }
+
+    /// Current time as seconds since the Unix epoch.
+    pub fn unix_time(&mut self) -> Result<u64, Ds3231Error> {
+        Ok(self.datetime()?.to_unix())
+    }
+
+    pub fn set_unix_time(&mut self, secs: u64) -> Result<(), Ds3231Error> {
+        self.set_datetime(&DateTime::from_unix(secs))
+    }
+
+    /// Die temperature in °C, 0.25 °C resolution (updated every 64 s).
+    pub fn temperature(&mut self) -> Result<f32, Ds3231Error> {
+        let mut buf = [0u8; 2];
+        self.i2c
+            .write_read(DS3231_I2C_ADDRESS, &[REG_TEMP_MSB], &mut buf)
+            .map_err(|_| Ds3231Error::I2c)?;
+        let raw = i16::from_be_bytes(buf) >> 6;
+        Ok(raw as f32 * 0.25)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Ds3231Error> {
+        let mut buf = [0u8; 1];
+        self.i2c
+            .write_read(DS3231_I2C_ADDRESS, &[register], &mut buf)
+            .map_err(|_| Ds3231Error::I2c)?;
+        Ok(buf[0])
+    }
+}
+
+fn bcd_to_bin(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0x0F)
+}
+
+fn bin_to_bcd(bin: u8) -> u8 {
+    ((bin / 10) << 4) | (bin % 10)
+}
@@ -0,0 +1,484 @@
+//! epaper.rs — Waveshare 2.13" / 2.9" e-paper driver (SSD1680 controller, SPI)
+//!
+//! Draw into a [`MonoFramebuffer`] with embedded-graphics ([`BinaryColor::On`] is black ink),
+//! then [`Epaper::refresh`] it. A full refresh flashes the panel and takes 2–3 s; a partial
+//! refresh only drives the changed pixels and takes ~0.4 s, but leaves ghosting, so
+//! every [`EpaperConfig::partials_before_full`]-th partial is upgraded to a full one.
+//! Refreshes await the BUSY pin instead of polling, and the panel keeps its image in
+//! [`Epaper::sleep`] at near-zero current.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static FRAME: StaticCell<[u8; EPAPER_2IN13_BUFFER_LEN]> = StaticCell::new();
+//!
+//! let spi = Spi::new_blocking_txonly(p.SPI1, p.PIN_10, p.PIN_11, epaper_default_spi_config());
+//! let cs = Output::new(p.PIN_9, Level::High);
+//! let dc = Output::new(p.PIN_8, Level::Low);
+//! let reset = Output::new(p.PIN_12, Level::High);
+//! let busy = Input::new(p.PIN_13, Pull::None);
+//! let frame = FRAME.init([0; EPAPER_2IN13_BUFFER_LEN]);
+//! let panel = EpaperPanel::WAVESHARE_2IN13_V3.with_rotation(EpaperRotation::Deg90);
+//! let mut epd = Epaper::new(spi, cs, dc, reset, busy, panel, frame).await?;
+//!
+//! Text::new("21.5 C", Point::new(10, 40), MonoTextStyle::new(&FONT_10X20, BinaryColor::On))
+//!     .draw(epd.framebuffer())?;
+//! epd.refresh(RefreshMode::Partial).await?;
+//! epd.sleep()?;
+//! ```
+
+use core::convert::Infallible;
+
+use embassy_rp::gpio::{Input, Output};
+use embassy_rp::spi::{self, Spi};
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+/// Framebuffer bytes for the 2.13" (122x250) panel
+pub const EPAPER_2IN13_BUFFER_LEN: usize = 16 * 250;
+/// Framebuffer bytes for the 2.9" (128x296) panel
+pub const EPAPER_2IN9_BUFFER_LEN: usize = 16 * 296;
+
+const CMD_DRIVER_OUTPUT: u8 = 0x01;
+const CMD_DEEP_SLEEP: u8 = 0x10;
+const CMD_DATA_ENTRY_MODE: u8 = 0x11;
+const CMD_SW_RESET: u8 = 0x12;
+const CMD_TEMPERATURE_SENSOR: u8 = 0x18;
+const CMD_MASTER_ACTIVATION: u8 = 0x20;
+const CMD_UPDATE_CONTROL_1: u8 = 0x21;
+const CMD_UPDATE_CONTROL_2: u8 = 0x22;
+const CMD_WRITE_RAM_BW: u8 = 0x24;
+const CMD_WRITE_RAM_PREVIOUS: u8 = 0x26;
+const CMD_BORDER_WAVEFORM: u8 = 0x3c;
+const CMD_RAM_X_RANGE: u8 = 0x44;
+const CMD_RAM_Y_RANGE: u8 = 0x45;
+const CMD_RAM_X_COUNTER: u8 = 0x4e;
+const CMD_RAM_Y_COUNTER: u8 = 0x4f;
+
+/// X and Y increment, X first
+const DATA_ENTRY_XY_INC: u8 = 0x03;
+/// Clock, analog, temperature, load LUT (mode 1), display, power off
+const UPDATE_FULL: u8 = 0xf7;
+/// Same sequence with the differential mode 2 waveform
+const UPDATE_PARTIAL: u8 = 0xfc;
+const BORDER_FULL: u8 = 0x05;
+const BORDER_PARTIAL: u8 = 0x80;
+const TEMPERATURE_INTERNAL: u8 = 0x80;
+
+pub fn epaper_default_spi_config() -> spi::Config {
+    let mut cfg = spi::Config::default();
+    cfg.frequency = 4_000_000;
+    cfg.phase = spi::Phase::CaptureOnFirstTransition;
+    cfg.polarity = spi::Polarity::IdleLow;
+    cfg
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum EpaperError {
+    #[error("SPI transfer with the e-paper panel failed")]
+    Communication,
+    #[error("E-paper panel stayed busy for too long")]
+    Timeout,
+    #[error("Framebuffer too small for the panel: {actual} < {needed} bytes")]
+    BufferTooSmall { actual: usize, needed: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RefreshMode {
+    /// Redraw every pixel; clears ghosting
+    Full,
+    /// Only drive pixels that changed since the last refresh
+    Partial,
+}
+
+/// Content orientation, clockwise from the panel's native portrait layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum EpaperRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpaperPanel {
+    /// Native (portrait) size in pixels
+    pub width: u16,
+    pub height: u16,
+    pub rotation: EpaperRotation,
+}
+
+impl EpaperPanel {
+    /// Waveshare 2.13" V3 / V4
+    pub const WAVESHARE_2IN13_V3: Self = Self {
+        width: 122,
+        height: 250,
+        rotation: EpaperRotation::Deg0,
+    };
+    /// Waveshare 2.9" V2
+    pub const WAVESHARE_2IN9_V2: Self = Self {
+        width: 128,
+        height: 296,
+        rotation: EpaperRotation::Deg0,
+    };
+
+    pub const fn with_rotation(self, rotation: EpaperRotation) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn framebuffer_len(&self) -> usize {
+        self.width.div_ceil(8) as usize * self.height as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpaperConfig {
+    /// Partial refreshes in a row before one is done as a full refresh; 0 disables this
+    pub partials_before_full: u16,
+    /// Longest time a refresh may keep the panel busy
+    pub busy_timeout: Duration,
+}
+
+impl Default for EpaperConfig {
+    fn default() -> Self {
+        Self {
+            partials_before_full: 10,
+            busy_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// 1 bit per pixel in the panel's native layout (set bit = white), drawn in rotated
+/// coordinates
+pub struct MonoFramebuffer<'b> {
+    buffer: &'b mut [u8],
+    width: u16,
+    height: u16,
+    rotation: EpaperRotation,
+}
+
+impl<'b> MonoFramebuffer<'b> {
+    /// `None` if `buffer` is smaller than [`EpaperPanel::framebuffer_len`]. Starts white.
+    pub fn new(buffer: &'b mut [u8], panel: EpaperPanel) -> Option<Self> {
+        let buffer = buffer.get_mut(..panel.framebuffer_len())?;
+        buffer.fill(0xff);
+        Some(Self {
+            buffer,
+            width: panel.width,
+            height: panel.height,
+            rotation: panel.rotation,
+        })
+    }
+
+    pub fn pixel(&self, x: u16, y: u16) -> Option<BinaryColor> {
+        let (index, mask) = self.locate(x, y)?;
+        Some(BinaryColor::from(self.buffer[index] & mask == 0))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buffer
+    }
+
+    /// Byte index and bit mask of a rotated coordinate
+    fn locate(&self, x: u16, y: u16) -> Option<(usize, u8)> {
+        let (w, h) = (self.width, self.height);
+        let (nx, ny) = match self.rotation {
+            EpaperRotation::Deg0 => (x < w && y < h).then_some((x, y)),
+            EpaperRotation::Deg90 => (x < h && y < w).then(|| (w - 1 - y, x)),
+            EpaperRotation::Deg180 => (x < w && y < h).then(|| (w - 1 - x, h - 1 - y)),
+            EpaperRotation::Deg270 => (x < h && y < w).then(|| (y, h - 1 - x)),
+        }?;
+        let row_bytes = w.div_ceil(8) as usize;
+        Some((ny as usize * row_bytes + nx as usize / 8, 0x80 >> (nx % 8)))
+    }
+}
+
+impl OriginDimensions for MonoFramebuffer<'_> {
+    fn size(&self) -> Size {
+        match self.rotation {
+            EpaperRotation::Deg0 | EpaperRotation::Deg180 => {
+                Size::new(self.width as u32, self.height as u32)
+            }
+            EpaperRotation::Deg90 | EpaperRotation::Deg270 => {
+                Size::new(self.height as u32, self.width as u32)
+            }
+        }
+    }
+}
+
+impl DrawTarget for MonoFramebuffer<'_> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u16::try_from(point.x), u16::try_from(point.y)) else {
+                continue;
+            };
+            let Some((index, mask)) = self.locate(x, y) else {
+                continue;
+            };
+            match color {
+                BinaryColor::On => self.buffer[index] &= !mask,
+                BinaryColor::Off => self.buffer[index] |= mask,
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.buffer.fill(match color {
+            BinaryColor::On => 0x00,
+            BinaryColor::Off => 0xff,
+        });
+        Ok(())
+    }
+}
+
+pub struct Epaper<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    spi: Spi<'d, T, M>,
+    cs: Output<'d>,
+    dc: Output<'d>,
+    reset: Output<'d>,
+    busy: Input<'d>,
+    panel: EpaperPanel,
+    config: EpaperConfig,
+    framebuffer: MonoFramebuffer<'d>,
+    partials_since_full: u16,
+    /// The controller's "previous image" RAM matches the panel (false after reset)
+    has_base_image: bool,
+}
+
+impl<'d, T, M> Epaper<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    /// Reset and initialize the panel. `framebuffer` needs
+    /// [`EpaperPanel::framebuffer_len`] bytes.
+    pub async fn new(
+        spi: Spi<'d, T, M>,
+        cs: Output<'d>,
+        dc: Output<'d>,
+        reset: Output<'d>,
+        busy: Input<'d>,
+        panel: EpaperPanel,
+        framebuffer: &'d mut [u8],
+    ) -> Result<Self, EpaperError> {
+        let actual = framebuffer.len();
+        let framebuffer =
+            MonoFramebuffer::new(framebuffer, panel).ok_or(EpaperError::BufferTooSmall {
+                actual,
+                needed: panel.framebuffer_len(),
+            })?;
+        let mut epaper = Self {
+            spi,
+            cs,
+            dc,
+            reset,
+            busy,
+            panel,
+            config: EpaperConfig::default(),
+            framebuffer,
+            partials_since_full: 0,
+            has_base_image: false,
+        };
+        epaper.init().await?;
+        Ok(epaper)
+    }
+
+    pub fn set_config(&mut self, config: EpaperConfig) {
+        self.config = config;
+    }
+
+    /// Draw here, then [`refresh`](Self::refresh).
+    pub fn framebuffer(&mut self) -> &mut MonoFramebuffer<'d> {
+        &mut self.framebuffer
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy.is_high()
+    }
+
+    /// Wait (without blocking other tasks) until the controller finishes its current
+    /// operation.
+    pub async fn wait_until_idle(&mut self) -> Result<(), EpaperError> {
+        with_timeout(self.config.busy_timeout, self.busy.wait_for_low())
+            .await
+            .map_err(|_| EpaperError::Timeout)
+    }
+
+    /// Show the framebuffer. A partial refresh becomes a full one when the panel has no
+    /// known previous image or too many partials were done in a row.
+    pub async fn refresh(&mut self, mode: RefreshMode) -> Result<(), EpaperError> {
+        let mode = effective_mode(
+            mode,
+            self.has_base_image,
+            self.partials_since_full,
+            self.config.partials_before_full,
+        );
+        let (border, update) = match mode {
+            RefreshMode::Full => (BORDER_FULL, UPDATE_FULL),
+            RefreshMode::Partial => (BORDER_PARTIAL, UPDATE_PARTIAL),
+        };
+        self.command(CMD_BORDER_WAVEFORM, &[border])?;
+        self.write_ram(CMD_WRITE_RAM_BW)?;
+        if mode == RefreshMode::Full {
+            self.write_ram(CMD_WRITE_RAM_PREVIOUS)?;
+        }
+        self.command(CMD_UPDATE_CONTROL_2, &[update])?;
+        self.command(CMD_MASTER_ACTIVATION, &[])?;
+        self.wait_until_idle().await?;
+        // The next partial refresh diffs against what is on the glass now
+        if mode == RefreshMode::Partial {
+            self.write_ram(CMD_WRITE_RAM_PREVIOUS)?;
+        }
+        self.has_base_image = true;
+        self.partials_since_full = match mode {
+            RefreshMode::Full => 0,
+            RefreshMode::Partial => self.partials_since_full + 1,
+        };
+        Ok(())
+    }
+
+    /// White out the framebuffer and the panel with a full refresh.
+    pub async fn clear(&mut self) -> Result<(), EpaperError> {
+        let _ = self.framebuffer.clear(BinaryColor::Off);
+        self.refresh(RefreshMode::Full).await
+    }
+
+    /// Enter deep sleep; the image stays visible. Call [`wake`](Self::wake) before the next
+    /// refresh.
+    pub fn sleep(&mut self) -> Result<(), EpaperError> {
+        self.command(CMD_DEEP_SLEEP, &[0x01])
+    }
+
+    /// Leave deep sleep (needs a hardware reset, which also clears the controller RAM).
+    pub async fn wake(&mut self) -> Result<(), EpaperError> {
+        self.init().await
+    }
+
+    async fn init(&mut self) -> Result<(), EpaperError> {
+        self.reset.set_low();
+        Timer::after_millis(10).await;
+        self.reset.set_high();
+        Timer::after_millis(10).await;
+        self.wait_until_idle().await?;
+        self.command(CMD_SW_RESET, &[])?;
+        Timer::after_millis(10).await;
+        self.wait_until_idle().await?;
+
+        let [last_row_lo, last_row_hi] = (self.panel.height - 1).to_le_bytes();
+        let last_col_byte = (self.panel.width.div_ceil(8) - 1) as u8;
+        self.command(CMD_DRIVER_OUTPUT, &[last_row_lo, last_row_hi, 0x00])?;
+        self.command(CMD_DATA_ENTRY_MODE, &[DATA_ENTRY_XY_INC])?;
+        self.command(CMD_RAM_X_RANGE, &[0, last_col_byte])?;
+        self.command(CMD_RAM_Y_RANGE, &[0, 0, last_row_lo, last_row_hi])?;
+        self.command(CMD_BORDER_WAVEFORM, &[BORDER_FULL])?;
+        self.command(CMD_UPDATE_CONTROL_1, &[0x00, 0x80])?;
+        self.command(CMD_TEMPERATURE_SENSOR, &[TEMPERATURE_INTERNAL])?;
+        self.wait_until_idle().await?;
+        self.has_base_image = false;
+        Ok(())
+    }
+
+    /// Write the whole framebuffer to one of the controller's RAMs.
+    fn write_ram(&mut self, ram: u8) -> Result<(), EpaperError> {
+        self.command(CMD_RAM_X_COUNTER, &[0])?;
+        self.command(CMD_RAM_Y_COUNTER, &[0, 0])?;
+        self.cs.set_low();
+        self.dc.set_low();
+        let mut result = self.spi.blocking_write(&[ram]);
+        if result.is_ok() {
+            self.dc.set_high();
+            result = self.spi.blocking_write(self.framebuffer.as_bytes());
+        }
+        self.cs.set_high();
+        result.map_err(|_| EpaperError::Communication)
+    }
+
+    fn command(&mut self, command: u8, params: &[u8]) -> Result<(), EpaperError> {
+        self.cs.set_low();
+        self.dc.set_low();
+        let mut result = self.spi.blocking_write(&[command]);
+        if result.is_ok() && !params.is_empty() {
+            self.dc.set_high();
+            result = self.spi.blocking_write(params);
+        }
+        self.cs.set_high();
+        result.map_err(|_| EpaperError::Communication)
+    }
+}
+
+fn effective_mode(
+    requested: RefreshMode,
+    has_base_image: bool,
+    partials_since_full: u16,
+    partials_before_full: u16,
+) -> RefreshMode {
+    let due_for_full = partials_before_full != 0 && partials_since_full >= partials_before_full;
+    if requested == RefreshMode::Full || !has_base_image || due_for_full {
+        RefreshMode::Full
+    } else {
+        RefreshMode::Partial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_lengths() {
+        assert_eq!(
+            EpaperPanel::WAVESHARE_2IN13_V3.framebuffer_len(),
+            EPAPER_2IN13_BUFFER_LEN
+        );
+        assert_eq!(
+            EpaperPanel::WAVESHARE_2IN9_V2.framebuffer_len(),
+            EPAPER_2IN9_BUFFER_LEN
+        );
+    }
+
+    #[test]
+    fn rotated_pixels_land_in_native_layout() {
+        let panel = EpaperPanel {
+            width: 16,
+            height: 4,
+            rotation: EpaperRotation::Deg90,
+        };
+        let mut buffer = [0u8; 8];
+        let mut fb = MonoFramebuffer::new(&mut buffer, panel).unwrap();
+        assert_eq!(fb.size(), Size::new(4, 16));
+        // Logical top-left is the native top-right corner
+        Pixel(Point::new(0, 0), BinaryColor::On)
+            .draw(&mut fb)
+            .unwrap();
+        assert_eq!(fb.as_bytes()[..2], [0xff, 0xfe]);
+        assert_eq!(fb.pixel(0, 0), Some(BinaryColor::On));
+        assert_eq!(fb.pixel(1, 0), Some(BinaryColor::Off));
+        assert_eq!(fb.pixel(4, 0), None);
+        Pixel(Point::new(0, 0), BinaryColor::Off)
+            .draw(&mut fb)
+            .unwrap();
+        assert!(fb.as_bytes().iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn partial_refreshes_are_upgraded() {
+        use RefreshMode::*;
+        assert_eq!(effective_mode(Partial, false, 0, 10), Full);
+        assert_eq!(effective_mode(Partial, true, 9, 10), Partial);
+        assert_eq!(effective_mode(Partial, true, 10, 10), Full);
+        assert_eq!(effective_mode(Partial, true, 100, 0), Partial);
+        assert_eq!(effective_mode(Full, true, 0, 10), Full);
+    }
+}
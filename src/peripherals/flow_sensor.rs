@@ -0,0 +1,111 @@
+//! flow_sensor.rs — hall-effect water flow sensor (YF-S201 and friends)
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pwm = Pwm::new_input(p.PWM_SLICE2, p.PIN_5, Pull::Up, InputMode::RisingEdge, Default::default());
+//! let mut flow = FlowSensor::new(PulseCounter::new(pwm), FlowSensorSpec::yf_s201());
+//!
+//! loop {
+//!     let reading = flow.measure(Duration::from_secs(1)).await;
+//!     info!("{} L/min, {} L total", reading.flow_lpm, reading.total_liters);
+//! }
+//! ```
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::PulseCounter;
+
+/// Flow sensor calibration: output frequency per unit of flow.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct FlowSensorSpec {
+    /// Pulse frequency in Hz at 1 L/min (the "F = k * Q" constant from the datasheet)
+    pub hz_per_lpm: f32,
+}
+
+impl FlowSensorSpec {
+    /// YF-S201 1/2" sensor (F = 7.5 * Q)
+    pub fn yf_s201() -> Self {
+        Self { hz_per_lpm: 7.5 }
+    }
+
+    /// YF-B1 / YF-B10 brass sensors (F = 11 * Q)
+    pub fn yf_b1() -> Self {
+        Self { hz_per_lpm: 11.0 }
+    }
+
+    /// YF-S401 small-bore sensor (F = 98 * Q)
+    pub fn yf_s401() -> Self {
+        Self { hz_per_lpm: 98.0 }
+    }
+
+    /// FS300A 3/4" sensor (F = 5.5 * Q)
+    pub fn fs300a() -> Self {
+        Self { hz_per_lpm: 5.5 }
+    }
+
+    pub fn pulses_per_liter(&self) -> f32 {
+        self.hz_per_lpm * 60.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub struct FlowReading {
+    /// Average flow over the sample window
+    pub flow_lpm: f32,
+    /// Volume since creation or the last reset
+    pub total_liters: f32,
+}
+
+/// Flow sensor on a [`PulseCounter`]
+pub struct FlowSensor<'d> {
+    counter: PulseCounter<'d>,
+    spec: FlowSensorSpec,
+    last_sample: Instant,
+}
+
+impl<'d> FlowSensor<'d> {
+    pub fn new(counter: PulseCounter<'d>, spec: FlowSensorSpec) -> Self {
+        Self {
+            counter,
+            spec,
+            last_sample: Instant::now(),
+        }
+    }
+
+    /// Flow since the previous call. Call regularly (at least every few seconds at full
+    /// flow) so the 16-bit hardware counter cannot wrap unnoticed.
+    pub fn sample(&mut self) -> FlowReading {
+        let now = Instant::now();
+        let elapsed_us = (now - self.last_sample).as_micros();
+        self.last_sample = now;
+
+        let pulses = self.counter.poll();
+        let flow_lpm = if elapsed_us == 0 {
+            0.0
+        } else {
+            let hz = pulses as f32 * 1_000_000.0 / elapsed_us as f32;
+            hz / self.spec.hz_per_lpm
+        };
+
+        FlowReading {
+            flow_lpm,
+            total_liters: self.total_liters(),
+        }
+    }
+
+    /// Wait for `window`, then return the flow measured over it.
+    pub async fn measure(&mut self, window: Duration) -> FlowReading {
+        self.sample();
+        Timer::after(window).await;
+        self.sample()
+    }
+
+    pub fn total_liters(&mut self) -> f32 {
+        self.counter.total() as f32 / self.spec.pulses_per_liter()
+    }
+
+    pub fn reset_total(&mut self) {
+        self.counter.reset();
+    }
+}
@@ -0,0 +1,411 @@
+//! haptics.rs — tactile feedback from a vibration motor or a DRV2605 haptic driver
+//!
+//! A [`HapticEffect`] names what the user should feel. [`VibrationMotor`] plays it as a
+//! pattern of PWM pulses on a small ERM motor behind a transistor; [`Drv2605`] plays the
+//! matching waveform from the chip's effect library, which feels crisper and also drives
+//! LRAs.
+//!
+//! Playing takes a while (a motor pattern runs up to half a second), so the device usually
+//! sits in its own task: [`Haptics::run`] plays whatever a [`HapticsControl`] asks for. A
+//! [`Ui`](crate::Ui) given the control with [`Ui::set_haptics`](crate::Ui::set_haptics)
+//! ticks on every turn and clicks on every press.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static HAPTICS: HapticsControl = HapticsControl::new();
+//!
+//! #[embassy_executor::task]
+//! async fn haptics_task(mut motor: VibrationMotor<'static>) -> ! {
+//!     motor.run(&HAPTICS).await
+//! }
+//!
+//! let pwm = Pwm::new_output_a(p.PWM_SLICE4, p.PIN_8, Default::default());
+//! spawner.must_spawn(haptics_task(VibrationMotor::new(pwm)));
+//! ui.set_haptics(Some(&HAPTICS));
+//!
+//! // Or with a DRV2605 on I2C
+//! let mut drv = Drv2605::new(i2c, HapticActuator::Erm)?;
+//! drv.play_effect(HapticEffect::DoubleClick.drv2605_id())?;
+//! ```
+
+use embassy_rp::pwm::{Config, Pwm};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::sys_clock_hz;
+
+/// Motor PWM frequency, above the audible range
+pub const VIBRATION_MOTOR_PWM_HZ: u32 = 20_000;
+
+pub const DRV2605_I2C_ADDRESS: u8 = 0x5A;
+
+const REG_STATUS: u8 = 0x00;
+const REG_MODE: u8 = 0x01;
+const REG_RTP_INPUT: u8 = 0x02;
+const REG_LIBRARY: u8 = 0x03;
+const REG_WAVEFORM_SEQUENCE: u8 = 0x04;
+const REG_GO: u8 = 0x0C;
+const REG_OVERDRIVE: u8 = 0x0D;
+const REG_FEEDBACK: u8 = 0x1A;
+const REG_CONTROL3: u8 = 0x1D;
+
+/// Device IDs in STATUS bits 7:5
+const DEVICE_ID_DRV2605: u8 = 3;
+const DEVICE_ID_DRV2605L: u8 = 7;
+
+/// MODE: internal trigger, out of standby
+const MODE_INTERNAL_TRIGGER: u8 = 0x00;
+const MODE_STANDBY: u8 = 1 << 6;
+/// FEEDBACK: LRA instead of ERM
+const FEEDBACK_LRA: u8 = 1 << 7;
+/// CONTROL3: ERM open loop, no auto-calibration needed
+const CONTROL3_ERM_OPEN_LOOP: u8 = 1 << 5;
+
+/// ERM library A (TS2200) and the LRA library
+const LIBRARY_ERM: u8 = 1;
+const LIBRARY_LRA: u8 = 6;
+
+/// Slots of the DRV2605 waveform sequencer
+const SEQUENCE_SLOTS: usize = 8;
+
+/// What the user should feel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HapticEffect {
+    /// Barely there, for scrolling through choices
+    Tick,
+    /// A firm press confirmation
+    Click,
+    DoubleClick,
+    /// A longer buzz, e.g. for an error
+    Buzz,
+    /// Three strong pulses, for something that needs attention
+    Alert,
+}
+
+impl HapticEffect {
+    /// Effect number in the DRV2605 ERM and LRA libraries
+    pub fn drv2605_id(&self) -> u8 {
+        match self {
+            HapticEffect::Tick => 24,        // Sharp Tick 1 – 100%
+            HapticEffect::Click => 1,        // Strong Click – 100%
+            HapticEffect::DoubleClick => 10, // Double Click – 100%
+            HapticEffect::Buzz => 47,        // Buzz 1 – 100%
+            HapticEffect::Alert => 52,       // Pulsing Strong 1 – 100%
+        }
+    }
+
+    /// The pulses a [`VibrationMotor`] plays for this effect
+    pub fn pattern(&self) -> &'static [VibrationPulse] {
+        match self {
+            HapticEffect::Tick => TICK,
+            HapticEffect::Click => CLICK,
+            HapticEffect::DoubleClick => DOUBLE_CLICK,
+            HapticEffect::Buzz => BUZZ,
+            HapticEffect::Alert => ALERT,
+        }
+    }
+}
+
+/// One burst of a vibration pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct VibrationPulse {
+    /// Percent of full drive, 0..=100
+    pub strength: u8,
+    pub on_ms: u16,
+    /// Pause after the burst
+    pub off_ms: u16,
+}
+
+impl VibrationPulse {
+    pub const fn new(strength: u8, on_ms: u16, off_ms: u16) -> Self {
+        Self {
+            strength,
+            on_ms,
+            off_ms,
+        }
+    }
+}
+
+const TICK: &[VibrationPulse] = &[VibrationPulse::new(60, 10, 0)];
+const CLICK: &[VibrationPulse] = &[VibrationPulse::new(100, 25, 0)];
+const DOUBLE_CLICK: &[VibrationPulse] = &[
+    VibrationPulse::new(100, 25, 80),
+    VibrationPulse::new(100, 25, 0),
+];
+const BUZZ: &[VibrationPulse] = &[VibrationPulse::new(70, 250, 0)];
+const ALERT: &[VibrationPulse] = &[
+    VibrationPulse::new(100, 120, 100),
+    VibrationPulse::new(100, 120, 100),
+    VibrationPulse::new(100, 120, 0),
+];
+
+/// Something that can play [`HapticEffect`]s
+#[allow(async_fn_in_trait)]
+pub trait Haptics {
+    type Error;
+
+    /// Play `effect`, returning once it is done or, for devices that play by themselves,
+    /// started.
+    async fn play(&mut self, effect: HapticEffect) -> Result<(), Self::Error>;
+
+    /// Play every effect requested through `control`, forever. Failed effects are dropped.
+    async fn run(&mut self, control: &HapticsControl) -> ! {
+        loop {
+            let effect = control.signal.wait().await;
+            let _ = self.play(effect).await;
+        }
+    }
+}
+
+/// Effect requests from other tasks to a [`Haptics::run`] loop. Requests made while an
+/// effect plays replace each other; only the latest plays next.
+pub struct HapticsControl {
+    signal: Signal<CriticalSectionRawMutex, HapticEffect>,
+}
+
+impl HapticsControl {
+    pub const fn new() -> Self {
+        Self {
+            signal: Signal::new(),
+        }
+    }
+
+    pub fn play(&self, effect: HapticEffect) {
+        self.signal.signal(effect);
+    }
+}
+
+impl Default for HapticsControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Coin or pager motor (ERM) switched by a transistor on one PWM output
+pub struct VibrationMotor<'d> {
+    pwm: Pwm<'d>,
+    config: Config,
+    /// Scales every pulse, in percent
+    intensity: u8,
+}
+
+impl<'d> VibrationMotor<'d> {
+    /// `pwm` drives the motor from channel A, e.g. made with `Pwm::new_output_a`. The motor
+    /// starts off.
+    pub fn new(pwm: Pwm<'d>) -> Self {
+        let top = (sys_clock_hz() / VIBRATION_MOTOR_PWM_HZ).clamp(2, u16::MAX as u32) as u16 - 1;
+        let mut config = Config::default();
+        config.top = top;
+        config.compare_a = 0;
+        let mut motor = Self {
+            pwm,
+            config,
+            intensity: 100,
+        };
+        motor.apply();
+        motor
+    }
+
+    /// Scale all effects to `percent` of their strength, e.g. for a user setting.
+    pub fn set_intensity(&mut self, percent: u8) {
+        self.intensity = percent.min(100);
+    }
+
+    /// Run the motor at `percent` of full drive until changed; 0 stops it.
+    pub fn set_strength(&mut self, percent: u8) {
+        self.config.compare_a = pulse_duty(percent, self.intensity, self.config.top);
+        self.apply();
+    }
+
+    pub fn stop(&mut self) {
+        self.set_strength(0);
+    }
+
+    /// Play `pattern` and stop the motor.
+    pub async fn play_pattern(&mut self, pattern: &[VibrationPulse]) {
+        for pulse in pattern {
+            self.set_strength(pulse.strength);
+            Timer::after(Duration::from_millis(pulse.on_ms as u64)).await;
+            self.stop();
+            Timer::after(Duration::from_millis(pulse.off_ms as u64)).await;
+        }
+    }
+
+    fn apply(&mut self) {
+        self.pwm.set_config(&self.config);
+    }
+}
+
+impl Haptics for VibrationMotor<'_> {
+    type Error = core::convert::Infallible;
+
+    async fn play(&mut self, effect: HapticEffect) -> Result<(), Self::Error> {
+        self.play_pattern(effect.pattern()).await;
+        Ok(())
+    }
+}
+
+/// Compare value for `strength` percent, scaled by `intensity` percent
+fn pulse_duty(strength: u8, intensity: u8, top: u16) -> u16 {
+    let percent = strength.min(100) as u32 * intensity.min(100) as u32;
+    ((top as u32 + 1) * percent / 10_000) as u16
+}
+
+/// Kind of motor behind a [`Drv2605`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HapticActuator {
+    /// Eccentric rotating mass (coin or pager motor), driven open loop
+    Erm,
+    /// Linear resonant actuator. Run the chip's auto-calibration for best results.
+    Lra,
+}
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum Drv2605Error {
+    #[error("I2C communication with DRV2605 failed")]
+    Communication,
+    #[error("Unexpected DRV2605 device ID: {0}")]
+    WrongDeviceId(u8),
+    #[error("Too many effects for the DRV2605 sequencer")]
+    SequenceTooLong,
+}
+
+/// TI DRV2605/DRV2605L haptic driver, playing effects from its built-in library
+pub struct Drv2605<I: embedded_hal::i2c::I2c> {
+    i2c: I,
+}
+
+impl<I: embedded_hal::i2c::I2c> Drv2605<I> {
+    /// Check the chip, wake it up and select the library for `actuator`.
+    pub fn new(i2c: I, actuator: HapticActuator) -> Result<Self, Drv2605Error> {
+        let mut driver = Self { i2c };
+        let device_id = driver.read_register(REG_STATUS)? >> 5;
+        if device_id != DEVICE_ID_DRV2605 && device_id != DEVICE_ID_DRV2605L {
+            return Err(Drv2605Error::WrongDeviceId(device_id));
+        }
+        driver.write_register(REG_MODE, MODE_INTERNAL_TRIGGER)?;
+        driver.write_register(REG_RTP_INPUT, 0)?;
+        // Overdrive, sustain and brake time offsets
+        for register in REG_OVERDRIVE..=REG_OVERDRIVE + 3 {
+            driver.write_register(register, 0)?;
+        }
+
+        let feedback = driver.read_register(REG_FEEDBACK)?;
+        let control3 = driver.read_register(REG_CONTROL3)?;
+        let (feedback, control3, library) = match actuator {
+            HapticActuator::Erm => (
+                feedback & !FEEDBACK_LRA,
+                control3 | CONTROL3_ERM_OPEN_LOOP,
+                LIBRARY_ERM,
+            ),
+            HapticActuator::Lra => (feedback | FEEDBACK_LRA, control3, LIBRARY_LRA),
+        };
+        driver.write_register(REG_FEEDBACK, feedback)?;
+        driver.write_register(REG_CONTROL3, control3)?;
+        driver.write_register(REG_LIBRARY, library)?;
+        Ok(driver)
+    }
+
+    /// Start library effect `id` (1..=123); see the datasheet for the list.
+    pub fn play_effect(&mut self, id: u8) -> Result<(), Drv2605Error> {
+        self.play_sequence(&[id])
+    }
+
+    /// Start up to 8 library effects back to back. Values with bit 7 set are pauses of
+    /// `(value & 0x7F) * 10` ms, as in the datasheet.
+    pub fn play_sequence(&mut self, effects: &[u8]) -> Result<(), Drv2605Error> {
+        if effects.len() > SEQUENCE_SLOTS {
+            return Err(Drv2605Error::SequenceTooLong);
+        }
+        let mut sequence = [0u8; SEQUENCE_SLOTS + 1];
+        sequence[0] = REG_WAVEFORM_SEQUENCE;
+        // A zero after the last effect ends the sequence
+        sequence[1..=effects.len()].copy_from_slice(effects);
+        self.write(&sequence)?;
+        self.write_register(REG_GO, 1)
+    }
+
+    /// An effect is still playing.
+    pub fn is_playing(&mut self) -> Result<bool, Drv2605Error> {
+        Ok(self.read_register(REG_GO)? & 1 != 0)
+    }
+
+    /// Cut the current effect short.
+    pub fn stop(&mut self) -> Result<(), Drv2605Error> {
+        self.write_register(REG_GO, 0)
+    }
+
+    /// Enter or leave low-power standby; effects don't play in standby.
+    pub fn set_standby(&mut self, standby: bool) -> Result<(), Drv2605Error> {
+        let mode = if standby {
+            MODE_STANDBY
+        } else {
+            MODE_INTERNAL_TRIGGER
+        };
+        self.write_register(REG_MODE, mode)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Drv2605Error> {
+        let mut buf = [0u8; 1];
+        self.i2c
+            .write_read(DRV2605_I2C_ADDRESS, &[register], &mut buf)
+            .map_err(|_| Drv2605Error::Communication)?;
+        Ok(buf[0])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Drv2605Error> {
+        self.write(&[register, value])
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Drv2605Error> {
+        self.i2c
+            .write(DRV2605_I2C_ADDRESS, data)
+            .map_err(|_| Drv2605Error::Communication)
+    }
+}
+
+/// Returns once the effect has started; the chip plays it by itself.
+impl<I: embedded_hal::i2c::I2c> Haptics for Drv2605<I> {
+    type Error = Drv2605Error;
+
+    async fn play(&mut self, effect: HapticEffect) -> Result<(), Self::Error> {
+        self.play_effect(effect.drv2605_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duty_scales_with_strength_and_intensity() {
+        assert_eq!(pulse_duty(100, 100, 6249), 6250);
+        assert_eq!(pulse_duty(50, 100, 6249), 3125);
+        assert_eq!(pulse_duty(50, 50, 6249), 1562);
+        assert_eq!(pulse_duty(0, 100, 6249), 0);
+        assert_eq!(pulse_duty(200, 255, 99), 100);
+    }
+
+    #[test]
+    fn patterns_are_short_and_end_off() {
+        let effects = [
+            HapticEffect::Tick,
+            HapticEffect::Click,
+            HapticEffect::DoubleClick,
+            HapticEffect::Buzz,
+            HapticEffect::Alert,
+        ];
+        for effect in effects {
+            let pattern = effect.pattern();
+            let total: u32 = pattern
+                .iter()
+                .map(|pulse| pulse.on_ms as u32 + pulse.off_ms as u32)
+                .sum();
+            assert!(total <= 600);
+            assert_eq!(pattern.last().map(|pulse| pulse.off_ms), Some(0));
+            assert!((1..=123).contains(&effect.drv2605_id()));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! hid_consent.rs — physical button sequence that unlocks HID output
+//!
+//! Firmware that types or clicks on its own should not start doing so just because it was
+//! plugged in. With [`UsbHidConfig::require_consent`](crate::UsbHidConfig::require_consent)
+//! the [`UsbHidDevice`](crate::UsbHidDevice) refuses reports until it is unlocked, and
+//! [`wait_for_consent`] waits for someone at the device to enter a short/long press
+//! sequence on a real button first. A bus reset (new host, replug) locks the device again.
+//!
+//! # Example
+//!
+//! ```ignore
+//! const CONSENT: [ConsentStep; 3] = [ConsentStep::Short, ConsentStep::Short, ConsentStep::Long];
+//!
+//! let config = UsbHidConfig { require_consent: true, ..Default::default() };
+//! let mut keyboard = UsbHidDevice::new_keyboard(p.USB, Irqs, &spawner, config).await?;
+//! let mut button = Button::new(Input::new(p.PIN_15, Pull::Up));
+//! loop {
+//!     if keyboard.is_locked() {
+//!         wait_for_consent(&mut button, &CONSENT).await;
+//!         keyboard.unlock();
+//!     }
+//!     match keyboard.send_report(&next_report()).await {
+//!         Err(UsbHidError::Locked) => continue,
+//!         result => result?,
+//!     }
+//! }
+//! ```
+
+use embassy_rp::gpio::Input;
+
+use crate::{Button, ButtonEvent};
+
+/// One press of a consent sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConsentStep {
+    Short,
+    /// Held at least the button's long-press threshold
+    Long,
+}
+
+impl From<ButtonEvent> for ConsentStep {
+    fn from(event: ButtonEvent) -> Self {
+        match event {
+            ButtonEvent::ShortPress => ConsentStep::Short,
+            ButtonEvent::LongPress(_) => ConsentStep::Long,
+        }
+    }
+}
+
+/// Follows presses through a sequence; a wrong press starts it over.
+#[derive(Debug, Clone, Copy)]
+struct ConsentMatcher<'a> {
+    sequence: &'a [ConsentStep],
+    matched: usize,
+}
+
+impl<'a> ConsentMatcher<'a> {
+    fn new(sequence: &'a [ConsentStep]) -> Self {
+        Self {
+            sequence,
+            matched: 0,
+        }
+    }
+
+    /// Feed one press; returns true when it completes the sequence.
+    fn update(&mut self, step: ConsentStep) -> bool {
+        // Longest tail of the presses so far that starts the sequence, so a wrong press
+        // can still begin (or continue) the next attempt
+        let pressed = &self.sequence[..self.matched];
+        self.matched = (1..=self.matched + 1)
+            .rev()
+            .find(|&len| {
+                self.sequence.get(len - 1) == Some(&step)
+                    && self.sequence[..len - 1] == pressed[self.matched + 1 - len..]
+            })
+            .unwrap_or(0);
+        self.matched == self.sequence.len()
+    }
+}
+
+/// Wait until `sequence` is pressed on `button`. An empty sequence returns at once.
+pub async fn wait_for_consent(button: &mut Button<Input<'_>>, sequence: &[ConsentStep]) {
+    if sequence.is_empty() {
+        return;
+    }
+    let mut matcher = ConsentMatcher::new(sequence);
+    while !matcher.update(button.wait_for_event().await.into()) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ConsentStep::{Long, Short};
+
+    #[test]
+    fn wrong_press_restarts_the_sequence() {
+        let mut matcher = ConsentMatcher::new(&[Short, Short, Long]);
+        assert!(!matcher.update(Short));
+        assert!(!matcher.update(Long));
+        assert_eq!(matcher.matched, 0);
+        assert!(!matcher.update(Short));
+        assert!(!matcher.update(Short));
+        // A third short press still leaves the last two as a valid start
+        assert!(!matcher.update(Short));
+        assert_eq!(matcher.matched, 2);
+        assert!(matcher.update(Long));
+    }
+}
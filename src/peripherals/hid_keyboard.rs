@@ -0,0 +1,190 @@
+//! hid_keyboard.rs — keyboard events as HID usage codes, shared by keyboard drivers
+//!
+//! Keyboard inputs (PS/2, USB host, matrices) report [`KeyEvent`]s carrying the USB HID
+//! usage of the key, so the same code can turn them into text ([`usage_to_char`]) or into
+//! boot-protocol reports for [`UsbHidDevice`](crate::UsbHidDevice) ([`HidKeyboardState`]).
+//!
+//! # Example
+//!
+//! ```ignore
+//! // PS/2 to USB converter
+//! let mut state = HidKeyboardState::new();
+//! loop {
+//!     let event = ps2.next_event().await?;
+//!     if state.update(event) {
+//!         usb.send_report(&state.report()).await?;
+//!     }
+//! }
+//! ```
+
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::KeyboardLayout;
+
+pub const HID_KEY_A: u8 = 0x04;
+pub const HID_KEY_Z: u8 = 0x1d;
+pub const HID_KEY_ENTER: u8 = 0x28;
+pub const HID_KEY_ESCAPE: u8 = 0x29;
+pub const HID_KEY_BACKSPACE: u8 = 0x2a;
+pub const HID_KEY_TAB: u8 = 0x2b;
+pub const HID_KEY_SPACE: u8 = 0x2c;
+pub const HID_KEY_CAPS_LOCK: u8 = 0x39;
+pub const HID_KEY_LEFT_CTRL: u8 = 0xe0;
+pub const HID_KEY_LEFT_SHIFT: u8 = 0xe1;
+pub const HID_KEY_RIGHT_SHIFT: u8 = 0xe5;
+pub const HID_KEY_RIGHT_GUI: u8 = 0xe7;
+
+/// Boot-report modifier bits for both shift keys
+pub const HID_MODIFIER_SHIFT: u8 = 0x22;
+
+/// "Too many keys pressed" code reported in every slot on overflow
+const HID_ERROR_ROLL_OVER: u8 = 0x01;
+
+/// A key going down or up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+pub struct KeyEvent {
+    /// USB HID usage (keyboard page)
+    pub usage: u8,
+    pub pressed: bool,
+}
+
+impl KeyEvent {
+    pub fn is_modifier(&self) -> bool {
+        (HID_KEY_LEFT_CTRL..=HID_KEY_RIGHT_GUI).contains(&self.usage)
+    }
+}
+
+/// Character a key produces on a US layout, if any. Caps Lock only affects letters. See
+/// [`KeyboardLayout::char_for`] for other layouts.
+pub fn usage_to_char(usage: u8, shift: bool, caps_lock: bool) -> Option<char> {
+    KeyboardLayout::Us.char_for(usage, shift, false, caps_lock)
+}
+
+/// Pressed keys and modifiers, as a 6-key-rollover boot keyboard report
+#[derive(Debug, Clone, Default)]
+pub struct HidKeyboardState {
+    modifiers: u8,
+    keys: [u8; 6],
+    /// Pressed keys that didn't fit in `keys`
+    overflow: u8,
+}
+
+impl HidKeyboardState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `event`; returns whether the report changed.
+    pub fn update(&mut self, event: KeyEvent) -> bool {
+        let before = self.report_keys();
+        let modifiers = self.modifiers;
+        if event.is_modifier() {
+            let bit = 1 << (event.usage - HID_KEY_LEFT_CTRL);
+            if event.pressed {
+                self.modifiers |= bit;
+            } else {
+                self.modifiers &= !bit;
+            }
+        } else if event.pressed {
+            if !self.keys.contains(&event.usage) {
+                match self.keys.iter_mut().find(|k| **k == 0) {
+                    Some(slot) => *slot = event.usage,
+                    None => self.overflow = self.overflow.saturating_add(1),
+                }
+            }
+        } else if let Some(index) = self.keys.iter().position(|&k| k == event.usage) {
+            self.keys.copy_within(index + 1.., index);
+            self.keys[5] = 0;
+        } else {
+            // Releasing a key that never made it into the report
+            self.overflow = self.overflow.saturating_sub(1);
+        }
+        modifiers != self.modifiers || before != self.report_keys()
+    }
+
+    /// Modifier bits (bit 0 left ctrl ... bit 7 right GUI)
+    pub fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    pub fn report(&self) -> KeyboardReport {
+        KeyboardReport {
+            modifier: self.modifiers,
+            reserved: 0,
+            leds: 0,
+            keycodes: self.report_keys(),
+        }
+    }
+
+    /// Release everything, e.g. when the source keyboard was unplugged.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn report_keys(&self) -> [u8; 6] {
+        if self.overflow > 0 {
+            [HID_ERROR_ROLL_OVER; 6]
+        } else {
+            self.keys
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(usage: u8) -> KeyEvent {
+        KeyEvent {
+            usage,
+            pressed: true,
+        }
+    }
+
+    fn release(usage: u8) -> KeyEvent {
+        KeyEvent {
+            usage,
+            pressed: false,
+        }
+    }
+
+    #[test]
+    fn us_layout_characters() {
+        assert_eq!(usage_to_char(HID_KEY_A, false, false), Some('a'));
+        assert_eq!(usage_to_char(HID_KEY_A, true, false), Some('A'));
+        assert_eq!(usage_to_char(HID_KEY_A, true, true), Some('a'));
+        assert_eq!(usage_to_char(0x1e, true, true), Some('!'));
+        assert_eq!(usage_to_char(0x27, false, false), Some('0'));
+        assert_eq!(usage_to_char(0x38, true, false), Some('?'));
+        assert_eq!(usage_to_char(HID_KEY_ESCAPE, false, false), None);
+    }
+
+    #[test]
+    fn report_tracks_keys_and_modifiers() {
+        let mut state = HidKeyboardState::new();
+        assert!(state.update(press(HID_KEY_LEFT_SHIFT)));
+        assert!(state.update(press(HID_KEY_A)));
+        assert!(!state.update(press(HID_KEY_A)));
+        assert!(state.update(press(0x05)));
+        let report = state.report();
+        assert_eq!(report.modifier, 0x02);
+        assert_eq!(report.keycodes, [0x04, 0x05, 0, 0, 0, 0]);
+        assert!(state.update(release(HID_KEY_A)));
+        assert_eq!(state.report().keycodes, [0x05, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rollover_overflow() {
+        let mut state = HidKeyboardState::new();
+        for usage in 0x04..0x0a {
+            state.update(press(usage));
+        }
+        assert!(state.update(press(0x0a)));
+        assert_eq!(state.report().keycodes, [HID_ERROR_ROLL_OVER; 6]);
+        assert!(state.update(release(0x0a)));
+        assert_eq!(
+            state.report().keycodes,
+            [0x04, 0x05, 0x06, 0x07, 0x08, 0x09]
+        );
+    }
+}
@@ -2,19 +2,22 @@ use core::convert::Infallible;
 
 use embassy_rp::gpio::Output;
 use embassy_rp::spi::{self, Spi};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::mono_font::{MonoTextStyle, ascii::FONT_4X6};
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::*;
 use embedded_graphics::text::Text;
 use sh1106::{Builder, prelude::*};
-use crate::HeaplessString;
+
+use crate::{HeaplessString, LogLevel, LogModule, LogSink, SystemUptime, Uptime};
 
 pub const INLAND_SH1106_WIDTH: u8 = 128;
 pub const INLAND_SH1106_HEIGHT: u8 = 64;
 pub const INLAND_SH1106_TEXT_LINE_HEIGHT: i32 = 6;
 pub const INLAND_SH1106_MAX_TEXT_LINES: usize = 10;
 pub const INLAND_SH1106_MAX_CHARS_PER_LINE: usize = 32;
+/// Shortest time between two redraws of a [`LogsDisplay`]
+pub const LOGS_DISPLAY_DEFAULT_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
 pub enum InlandSh1106OledError {
@@ -93,6 +96,20 @@ where
             .map_err(map_sh1106_error::<embassy_rp::spi::Error, Infallible>)
     }
 
+    /// Set panel contrast (0 = dimmest, 255 = brightest).
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), InlandSh1106OledError> {
+        self.display
+            .set_contrast(contrast)
+            .map_err(map_sh1106_error::<embassy_rp::spi::Error, Infallible>)
+    }
+
+    /// Turn the panel on or off. The framebuffer is kept while off.
+    pub fn set_display_on(&mut self, on: bool) -> Result<(), InlandSh1106OledError> {
+        self.display
+            .display_on(on)
+            .map_err(map_sh1106_error::<embassy_rp::spi::Error, Infallible>)
+    }
+
     /// Display multi-line text using the 4x6 mono font.
     ///
     /// Lines are separated by `\n`, up to 10 lines total and 32 chars per line.
@@ -173,13 +190,21 @@ fn map_sh1106_error<CommE, PinE>(err: sh1106::Error<CommE, PinE>) -> InlandSh110
     }
 }
 
-pub struct LogsDisplay<'d, T, M>
+/// The last ten log lines on the OLED. A burst of messages redraws the screen at most once
+/// per redraw interval; lines logged in between are drawn by the next [`log`](Self::log)
+/// after the interval, or by [`flush`](Self::flush).
+pub struct LogsDisplay<'d, T, M, U = SystemUptime>
 where
     T: spi::Instance,
     M: spi::Mode,
+    U: Uptime,
 {
     display: InlandSh1106OledDisplay<'d, T, M>,
     logs: [HeaplessString<32>; 10],
+    uptime: U,
+    redraw_interval: Duration,
+    last_redraw: Option<Instant>,
+    pending: bool,
 }
 
 impl<'d, T, M> LogsDisplay<'d, T, M>
@@ -188,8 +213,30 @@ where
     M: spi::Mode,
 {
     pub fn new(display: InlandSh1106OledDisplay<'d, T, M>) -> Self {
+        Self::with_uptime(display, SystemUptime)
+    }
+}
+
+impl<'d, T, M, U> LogsDisplay<'d, T, M, U>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+    U: Uptime,
+{
+    pub fn with_uptime(display: InlandSh1106OledDisplay<'d, T, M>, uptime: U) -> Self {
         let logs = [const { HeaplessString::new() }; 10];
-        Self { display, logs }
+        Self {
+            display,
+            logs,
+            uptime,
+            redraw_interval: LOGS_DISPLAY_DEFAULT_REDRAW_INTERVAL,
+            last_redraw: None,
+            pending: false,
+        }
+    }
+
+    pub fn set_redraw_interval(&mut self, interval: Duration) {
+        self.redraw_interval = interval;
     }
 
     pub fn log(&mut self, msg: &str) {
@@ -203,6 +250,23 @@ where
             let _ = last_log_str.push(c); // Truncate if message is too long
         }
         self.logs[9] = last_log_str;
+        self.pending = true;
+
+        let due = self
+            .last_redraw
+            .is_none_or(|at| self.uptime.elapsed_since(at) >= self.redraw_interval);
+        if due {
+            self.flush();
+        }
+    }
+
+    /// Draw lines the redraw limit held back; call it now and then, e.g. from an idle loop.
+    pub fn flush(&mut self) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+        self.last_redraw = Some(self.uptime.now());
 
         // Display logs on OLED
         let logs_arr: [&str; 10] = [
@@ -220,3 +284,15 @@ where
         let _ = self.display.display_str_arr(&logs_arr); // Ignore display errors
     }
 }
+
+/// Shows the crate's log messages, see [`set_log_sink`](crate::set_log_sink).
+impl<T, M, U> LogSink for LogsDisplay<'_, T, M, U>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+    U: Uptime,
+{
+    fn write_log(&mut self, _module: LogModule, _level: LogLevel, message: &str) {
+        self.log(message);
+    }
+}
@@ -0,0 +1,9 @@
+//! key_input.rs — common interface for character-producing input devices
+
+/// Device that produces characters from key presses (keypads, PS/2 keyboards, button
+/// arrays), so text-entry logic doesn't depend on a specific driver.
+#[allow(async_fn_in_trait)]
+pub trait KeyInput {
+    /// Wait for the next key press and return its character.
+    async fn wait_for_key(&mut self) -> char;
+}
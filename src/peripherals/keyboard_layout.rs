@@ -0,0 +1,382 @@
+//! keyboard_layout.rs — host keyboard layouts for typing text over USB HID
+//!
+//! A HID keyboard sends key positions, not characters; the host turns them into text with
+//! its configured layout. Typing "y" as the US `Y` key produces "z" on a German host, so
+//! [`type_str`] looks every character up in the [`KeyboardLayout`] the host uses and sends
+//! the key and modifiers (Shift, AltGr) that produce it there. Characters on dead keys
+//! (`^` on a German layout) are followed by a space so they appear on their own.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut keyboard = UsbHidDevice::new_keyboard(p.USB, Irqs, &spawner, config).await?;
+//! let skipped = type_str(&mut keyboard, KeyboardLayout::De, "Grüße, 100 € @home\n").await?;
+//! ```
+
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::{
+    HID_KEY_A, HID_KEY_BACKSPACE, HID_KEY_ENTER, HID_KEY_SPACE, HID_KEY_TAB, HID_KEY_Z,
+    UsbHidDevice, UsbHidError,
+};
+
+/// Boot-report modifier bit for the left Shift key
+pub const HID_MODIFIER_LEFT_SHIFT: u8 = 0x02;
+/// Boot-report modifier bit for the right Alt (AltGr) key
+pub const HID_MODIFIER_RIGHT_ALT: u8 = 0x40;
+
+/// "Non-US #" key, next to Enter on ISO keyboards
+const HID_KEY_NON_US_HASH: u8 = 0x32;
+/// "Non-US \" key, next to left Shift on ISO keyboards
+const HID_KEY_NON_US_BACKSLASH: u8 = 0x64;
+
+/// Layout the host is configured with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum KeyboardLayout {
+    /// US English (QWERTY)
+    #[default]
+    Us,
+    /// UK English (QWERTY, ISO)
+    Uk,
+    /// German (QWERTZ)
+    De,
+    /// French (AZERTY)
+    Fr,
+}
+
+/// Key and modifiers that type one character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct KeyStroke {
+    /// USB HID usage (keyboard page)
+    pub usage: u8,
+    /// Boot-report modifier bits
+    pub modifiers: u8,
+    /// Dead key: it only types the character when followed by a space
+    pub dead: bool,
+}
+
+/// Characters of one layout
+struct LayoutTable {
+    /// Lowercase letter on each of the keys `A`..`Z`, `0` for keys that aren't letters here
+    letters: &'static [u8; 26],
+    /// Plain, shifted and AltGr character of other keys, `'\0'` for none
+    keys: &'static [(u8, [char; 3])],
+    /// Dead keys as (usage, level): 0 plain, 1 shifted, 2 AltGr
+    dead: &'static [(u8, usize)],
+}
+
+const N: char = '\0';
+
+const US: LayoutTable = LayoutTable {
+    letters: b"abcdefghijklmnopqrstuvwxyz",
+    keys: &[
+        (0x1e, ['1', '!', N]),
+        (0x1f, ['2', '@', N]),
+        (0x20, ['3', '#', N]),
+        (0x21, ['4', '$', N]),
+        (0x22, ['5', '%', N]),
+        (0x23, ['6', '^', N]),
+        (0x24, ['7', '&', N]),
+        (0x25, ['8', '*', N]),
+        (0x26, ['9', '(', N]),
+        (0x27, ['0', ')', N]),
+        (0x2d, ['-', '_', N]),
+        (0x2e, ['=', '+', N]),
+        (0x2f, ['[', '{', N]),
+        (0x30, [']', '}', N]),
+        (0x31, ['\\', '|', N]),
+        (0x33, [';', ':', N]),
+        (0x34, ['\'', '"', N]),
+        (0x35, ['`', '~', N]),
+        (0x36, [',', '<', N]),
+        (0x37, ['.', '>', N]),
+        (0x38, ['/', '?', N]),
+    ],
+    dead: &[],
+};
+
+const UK: LayoutTable = LayoutTable {
+    letters: b"abcdefghijklmnopqrstuvwxyz",
+    keys: &[
+        (0x1e, ['1', '!', N]),
+        (0x1f, ['2', '"', N]),
+        (0x20, ['3', '£', N]),
+        (0x21, ['4', '$', '€']),
+        (0x22, ['5', '%', N]),
+        (0x23, ['6', '^', N]),
+        (0x24, ['7', '&', N]),
+        (0x25, ['8', '*', N]),
+        (0x26, ['9', '(', N]),
+        (0x27, ['0', ')', N]),
+        (0x2d, ['-', '_', N]),
+        (0x2e, ['=', '+', N]),
+        (0x2f, ['[', '{', N]),
+        (0x30, [']', '}', N]),
+        (HID_KEY_NON_US_HASH, ['#', '~', N]),
+        (0x33, [';', ':', N]),
+        (0x34, ['\'', '@', N]),
+        (0x35, ['`', '¬', '¦']),
+        (0x36, [',', '<', N]),
+        (0x37, ['.', '>', N]),
+        (0x38, ['/', '?', N]),
+        (HID_KEY_NON_US_BACKSLASH, ['\\', '|', N]),
+    ],
+    dead: &[],
+};
+
+const DE: LayoutTable = LayoutTable {
+    letters: b"abcdefghijklmnopqrstuvwxzy",
+    keys: &[
+        (0x08, [N, N, '€']),
+        (0x10, [N, N, 'µ']),
+        (0x14, [N, N, '@']),
+        (0x1e, ['1', '!', N]),
+        (0x1f, ['2', '"', '²']),
+        (0x20, ['3', '§', '³']),
+        (0x21, ['4', '$', N]),
+        (0x22, ['5', '%', N]),
+        (0x23, ['6', '&', N]),
+        (0x24, ['7', '/', '{']),
+        (0x25, ['8', '(', '[']),
+        (0x26, ['9', ')', ']']),
+        (0x27, ['0', '=', '}']),
+        (0x2d, ['ß', '?', '\\']),
+        (0x2e, ['´', '`', N]),
+        (0x2f, ['ü', 'Ü', N]),
+        (0x30, ['+', '*', '~']),
+        (HID_KEY_NON_US_HASH, ['#', '\'', N]),
+        (0x33, ['ö', 'Ö', N]),
+        (0x34, ['ä', 'Ä', N]),
+        (0x35, ['^', '°', N]),
+        (0x36, [',', ';', N]),
+        (0x37, ['.', ':', N]),
+        (0x38, ['-', '_', N]),
+        (HID_KEY_NON_US_BACKSLASH, ['<', '>', '|']),
+    ],
+    dead: &[(0x2e, 0), (0x2e, 1), (0x35, 0)],
+};
+
+const FR: LayoutTable = LayoutTable {
+    letters: b"qbcdefghijkl\0noparstuvzxyw",
+    keys: &[
+        (0x08, [N, N, '€']),
+        (0x10, [',', '?', N]),
+        (0x1e, ['&', '1', N]),
+        (0x1f, ['é', '2', '~']),
+        (0x20, ['"', '3', '#']),
+        (0x21, ['\'', '4', '{']),
+        (0x22, ['(', '5', '[']),
+        (0x23, ['-', '6', '|']),
+        (0x24, ['è', '7', '`']),
+        (0x25, ['_', '8', '\\']),
+        (0x26, ['ç', '9', '^']),
+        (0x27, ['à', '0', '@']),
+        (0x2d, [')', '°', ']']),
+        (0x2e, ['=', '+', '}']),
+        (0x2f, ['^', '¨', N]),
+        (0x30, ['$', '£', '¤']),
+        (HID_KEY_NON_US_HASH, ['*', 'µ', N]),
+        (0x33, ['m', 'M', N]),
+        (0x34, ['ù', '%', N]),
+        (0x35, ['²', N, N]),
+        (0x36, [';', '.', N]),
+        (0x37, [':', '/', N]),
+        (0x38, ['!', '§', N]),
+        (HID_KEY_NON_US_BACKSLASH, ['<', '>', N]),
+    ],
+    dead: &[(0x1f, 2), (0x24, 2), (0x2f, 0), (0x2f, 1)],
+};
+
+impl KeyboardLayout {
+    fn table(self) -> &'static LayoutTable {
+        match self {
+            KeyboardLayout::Us => &US,
+            KeyboardLayout::Uk => &UK,
+            KeyboardLayout::De => &DE,
+            KeyboardLayout::Fr => &FR,
+        }
+    }
+
+    /// Plain, shifted and AltGr character of a key, `'\0'` for none
+    fn key_chars(self, usage: u8) -> [char; 3] {
+        let table = self.table();
+        let mut chars = table
+            .keys
+            .iter()
+            .find(|(key, _)| *key == usage)
+            .map_or([N; 3], |(_, chars)| *chars);
+        if (HID_KEY_A..=HID_KEY_Z).contains(&usage) {
+            let letter = table.letters[(usage - HID_KEY_A) as usize] as char;
+            if letter != N {
+                chars[0] = letter;
+                chars[1] = letter.to_ascii_uppercase();
+            }
+        }
+        match usage {
+            HID_KEY_ENTER => ['\n', '\n', N],
+            HID_KEY_BACKSPACE => ['\x08', '\x08', N],
+            HID_KEY_TAB => ['\t', '\t', N],
+            HID_KEY_SPACE => [' ', ' ', N],
+            _ => chars,
+        }
+    }
+
+    /// Character a key produces on this layout, if any. Caps Lock only affects keys whose
+    /// shifted character is the capital of the plain one.
+    pub fn char_for(self, usage: u8, shift: bool, altgr: bool, caps_lock: bool) -> Option<char> {
+        let chars = self.key_chars(usage);
+        let c = if altgr {
+            chars[2]
+        } else if shift != (caps_lock && chars[0].to_uppercase().eq([chars[1]])) {
+            chars[1]
+        } else {
+            chars[0]
+        };
+        (c != N).then_some(c)
+    }
+
+    /// Key and modifiers that type `c` on this layout. Keys that type it directly are
+    /// preferred over dead keys.
+    pub fn key_for(self, c: char) -> Option<KeyStroke> {
+        if c == N {
+            return None;
+        }
+        let mut found = None;
+        for usage in HID_KEY_A..=HID_KEY_NON_US_BACKSLASH {
+            let chars = self.key_chars(usage);
+            let Some(level) = chars.iter().position(|&k| k == c) else {
+                continue;
+            };
+            let stroke = KeyStroke {
+                usage,
+                modifiers: [0, HID_MODIFIER_LEFT_SHIFT, HID_MODIFIER_RIGHT_ALT][level],
+                dead: self.table().dead.contains(&(usage, level)),
+            };
+            if !stroke.dead {
+                return Some(stroke);
+            }
+            found.get_or_insert(stroke);
+        }
+        found
+    }
+}
+
+/// Type `text` on a keyboard created with
+/// [`UsbHidDevice::new_keyboard`](crate::UsbHidDevice::new_keyboard), for a host set to
+/// `layout`. Characters the layout can't type are skipped; returns how many were.
+pub async fn type_str(
+    keyboard: &mut UsbHidDevice,
+    layout: KeyboardLayout,
+    text: &str,
+) -> Result<usize, UsbHidError> {
+    let mut skipped = 0;
+    for c in text.chars() {
+        let Some(stroke) = layout.key_for(c) else {
+            skipped += 1;
+            continue;
+        };
+        tap(keyboard, stroke.usage, stroke.modifiers).await?;
+        if stroke.dead {
+            tap(keyboard, HID_KEY_SPACE, 0).await?;
+        }
+    }
+    Ok(skipped)
+}
+
+/// Press and release one key.
+async fn tap(keyboard: &mut UsbHidDevice, usage: u8, modifiers: u8) -> Result<(), UsbHidError> {
+    let mut report = KeyboardReport {
+        modifier: modifiers,
+        reserved: 0,
+        leds: 0,
+        keycodes: [usage, 0, 0, 0, 0, 0],
+    };
+    keyboard.send_report(&report).await?;
+    report.modifier = 0;
+    report.keycodes = [0; 6];
+    keyboard.send_report(&report).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(usage: u8, modifiers: u8) -> Option<KeyStroke> {
+        Some(KeyStroke {
+            usage,
+            modifiers,
+            dead: false,
+        })
+    }
+
+    #[test]
+    fn same_character_different_keys() {
+        assert_eq!(KeyboardLayout::Us.key_for('y'), stroke(0x1c, 0));
+        assert_eq!(KeyboardLayout::De.key_for('y'), stroke(HID_KEY_Z, 0));
+        assert_eq!(KeyboardLayout::Fr.key_for('a'), stroke(0x14, 0));
+        assert_eq!(
+            KeyboardLayout::Us.key_for('@'),
+            stroke(0x1f, HID_MODIFIER_LEFT_SHIFT)
+        );
+        assert_eq!(
+            KeyboardLayout::Uk.key_for('@'),
+            stroke(0x34, HID_MODIFIER_LEFT_SHIFT)
+        );
+        assert_eq!(
+            KeyboardLayout::De.key_for('@'),
+            stroke(0x14, HID_MODIFIER_RIGHT_ALT)
+        );
+        assert_eq!(
+            KeyboardLayout::Fr.key_for('1'),
+            stroke(0x1e, HID_MODIFIER_LEFT_SHIFT)
+        );
+        assert_eq!(KeyboardLayout::Us.key_for('ü'), None);
+    }
+
+    #[test]
+    fn dead_keys_are_flagged_unless_avoidable() {
+        let caret = KeyboardLayout::De.key_for('^').unwrap();
+        assert_eq!((caret.usage, caret.dead), (0x35, true));
+        assert_eq!(
+            KeyboardLayout::Fr.key_for('^'),
+            stroke(0x26, HID_MODIFIER_RIGHT_ALT)
+        );
+    }
+
+    #[test]
+    fn characters_round_trip() {
+        for layout in [
+            KeyboardLayout::Us,
+            KeyboardLayout::Uk,
+            KeyboardLayout::De,
+            KeyboardLayout::Fr,
+        ] {
+            for c in (' '..='~').chain(['\n', '\t']) {
+                let stroke = layout.key_for(c).unwrap();
+                let shift = stroke.modifiers & HID_MODIFIER_LEFT_SHIFT != 0;
+                let altgr = stroke.modifiers & HID_MODIFIER_RIGHT_ALT != 0;
+                assert_eq!(layout.char_for(stroke.usage, shift, altgr, false), Some(c));
+            }
+        }
+    }
+
+    #[test]
+    fn caps_lock_affects_letters_only() {
+        assert_eq!(
+            KeyboardLayout::De.char_for(0x33, false, false, true),
+            Some('Ö')
+        );
+        assert_eq!(
+            KeyboardLayout::De.char_for(0x1e, false, false, true),
+            Some('1')
+        );
+        assert_eq!(
+            KeyboardLayout::De.char_for(0x2d, false, false, true),
+            Some('ß')
+        );
+        assert_eq!(
+            KeyboardLayout::Fr.char_for(0x10, false, false, true),
+            Some(',')
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! matrix_keypad.rs — row/column scanned keypads (4x4, 4x3 membrane keypads)
+//!
+//! Rows are driven open-drain style and columns are inputs with pull-ups. A scan drives one
+//! row low at a time, with the other rows left floating, and reads which column follows it;
+//! a row is never driven high, so two keys pressed in one column can't short a high row to
+//! a low one. Between scans all rows sit low, so any press pulls a column low and
+//! [`KeyInput::wait_for_key`] can sleep on the column edges instead of polling. Keys are mapped to characters through a user keymap; [`KEYPAD_4X4`] and
+//! [`KEYPAD_4X3`] match the common starter-kit keypads. When several keys are down, the
+//! first in scan order wins. Debouncing runs on the keypad's [`Uptime`], which
+//! [`MatrixKeypad::with_uptime`] sets.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let rows = [p.PIN_2, p.PIN_3, p.PIN_4, p.PIN_5].map(Flex::new);
+//! let cols = [p.PIN_6, p.PIN_7, p.PIN_8, p.PIN_9].map(|pin| Input::new(pin, Pull::Up));
+//! let mut keypad = MatrixKeypad::new(rows, cols, KEYPAD_4X4);
+//!
+//! // Async
+//! let key = keypad.wait_for_key().await;
+//!
+//! // Polling, e.g. from a loop that also does other work
+//! if let Some(key) = keypad.read_key() {
+//!     info!("pressed {}", key);
+//! }
+//! ```
+
+use embassy_futures::select::select_array;
+use embassy_rp::gpio::{Flex, Input};
+use embassy_time::{Duration, Instant, block_for};
+
+use crate::{KeyInput, SystemUptime, Uptime};
+
+/// Time a key must read the same before it counts
+pub const KEYPAD_DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+/// Time for a column to follow its row after the row is driven
+const ROW_SETTLE: Duration = Duration::from_micros(10);
+
+/// Layout of the common 4x4 membrane keypad
+pub const KEYPAD_4X4: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Layout of the common 4x3 (phone) keypad
+pub const KEYPAD_4X3: [[char; 3]; 4] = [
+    ['1', '2', '3'],
+    ['4', '5', '6'],
+    ['7', '8', '9'],
+    ['*', '0', '#'],
+];
+
+/// Row and column of a key
+type KeyPosition = (usize, usize);
+
+/// Reports a key once after it read the same for the debounce time.
+#[derive(Debug, Clone, Copy)]
+struct KeyDebounce {
+    candidate: Option<KeyPosition>,
+    since: Instant,
+    reported: bool,
+}
+
+impl KeyDebounce {
+    fn new() -> Self {
+        Self {
+            candidate: None,
+            since: Instant::from_ticks(0),
+            reported: false,
+        }
+    }
+
+    fn update(
+        &mut self,
+        key: Option<KeyPosition>,
+        now: Instant,
+        debounce: Duration,
+    ) -> Option<KeyPosition> {
+        if key != self.candidate {
+            self.candidate = key;
+            self.since = now;
+            self.reported = false;
+            return None;
+        }
+        if self.reported || now.saturating_duration_since(self.since) < debounce {
+            return None;
+        }
+        self.reported = true;
+        key
+    }
+
+    /// `key` was reported elsewhere; don't report it again while it stays down.
+    fn mark_reported(&mut self, key: KeyPosition, now: Instant) {
+        self.candidate = Some(key);
+        self.since = now;
+        self.reported = true;
+    }
+}
+
+pub struct MatrixKeypad<'d, const ROWS: usize, const COLS: usize, U: Uptime = SystemUptime> {
+    rows: [Flex<'d>; ROWS],
+    cols: [Input<'d>; COLS],
+    keymap: [[char; COLS]; ROWS],
+    debounce: Duration,
+    state: KeyDebounce,
+    uptime: U,
+}
+
+impl<'d, const ROWS: usize, const COLS: usize> MatrixKeypad<'d, ROWS, COLS> {
+    /// `cols` must be inputs with pull-ups (internal or external).
+    pub fn new(
+        rows: [Flex<'d>; ROWS],
+        cols: [Input<'d>; COLS],
+        keymap: [[char; COLS]; ROWS],
+    ) -> Self {
+        Self::with_uptime(rows, cols, keymap, SystemUptime)
+    }
+}
+
+impl<'d, const ROWS: usize, const COLS: usize, U: Uptime> MatrixKeypad<'d, ROWS, COLS, U> {
+    pub fn with_uptime(
+        rows: [Flex<'d>; ROWS],
+        cols: [Input<'d>; COLS],
+        keymap: [[char; COLS]; ROWS],
+        uptime: U,
+    ) -> Self {
+        let mut keypad = Self {
+            rows,
+            cols,
+            keymap,
+            debounce: KEYPAD_DEFAULT_DEBOUNCE,
+            state: KeyDebounce::new(),
+            uptime,
+        };
+        keypad.idle();
+        keypad
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    pub fn set_keymap(&mut self, keymap: [[char; COLS]; ROWS]) {
+        self.keymap = keymap;
+    }
+
+    /// Row and column of the key down right now, without debouncing
+    pub fn scan(&mut self) -> Option<(usize, usize)> {
+        let mut found = None;
+        for row in 0..ROWS {
+            for (i, pin) in self.rows.iter_mut().enumerate() {
+                if i == row {
+                    pin.set_as_output();
+                } else {
+                    pin.set_as_input();
+                }
+            }
+            block_for(ROW_SETTLE);
+            if let Some(col) = self.cols.iter().position(|pin| pin.is_low()) {
+                found = Some((row, col));
+                break;
+            }
+        }
+        self.idle();
+        found
+    }
+
+    /// Non-blocking: the key pressed since the last call, once it is debounced. Call it at
+    /// least a few times per debounce period.
+    pub fn read_key(&mut self) -> Option<char> {
+        let key = self.scan();
+        self.state
+            .update(key, self.uptime.now(), self.debounce)
+            .map(|(row, col)| self.keymap[row][col])
+    }
+
+    /// All rows low, so a press on any key shows up as a column edge. The output level
+    /// stays low; scans only switch rows between driving it and floating.
+    fn idle(&mut self) {
+        for pin in self.rows.iter_mut() {
+            pin.set_low();
+            pin.set_as_output();
+        }
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, U: Uptime> KeyInput for MatrixKeypad<'_, ROWS, COLS, U> {
+    /// Wait for a key that wasn't down yet; a key held from before is ignored.
+    async fn wait_for_key(&mut self) -> char {
+        loop {
+            while self.scan().is_some() {
+                self.uptime.sleep(self.debounce).await;
+            }
+            select_array(self.cols.each_mut().map(|pin| pin.wait_for_low())).await;
+            self.uptime.sleep(self.debounce).await;
+            if let Some((row, col)) = self.scan() {
+                self.state.mark_reported((row, col), self.uptime.now());
+                return self.keymap[row][col];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounce_reports_stable_key_once() {
+        let debounce = KEYPAD_DEFAULT_DEBOUNCE;
+        let at = Instant::from_millis;
+        let mut state = KeyDebounce::new();
+        assert_eq!(state.update(Some((1, 2)), at(0), debounce), None);
+        assert_eq!(state.update(None, at(5), debounce), None);
+        assert_eq!(state.update(Some((1, 2)), at(10), debounce), None);
+        assert_eq!(state.update(Some((1, 2)), at(25), debounce), None);
+        assert_eq!(state.update(Some((1, 2)), at(30), debounce), Some((1, 2)));
+        assert_eq!(state.update(Some((1, 2)), at(100), debounce), None);
+        assert_eq!(state.update(None, at(110), debounce), None);
+        assert_eq!(state.update(Some((0, 0)), at(120), debounce), None);
+        assert_eq!(state.update(Some((0, 0)), at(140), debounce), Some((0, 0)));
+    }
+}
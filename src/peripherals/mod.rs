@@ -1,11 +1,101 @@
+mod air_quality;
+mod analog_button_ladder;
+mod analog_input;
+mod auto_brightness;
+mod bh1750;
 mod button;
+mod button_group;
+mod button_task;
+mod capacitive_touch;
+mod ccs811;
+mod dc_motor;
+mod ds3231;
+mod epaper;
+mod flow_sensor;
+mod haptics;
+mod hid_consent;
+mod hid_keyboard;
 mod inland_ks0061_i2c_display;
 mod inland_sh1106_oled_display;
+mod key_input;
+mod keyboard_layout;
+mod matrix_keypad;
+mod nrf24;
+mod ov7670;
+mod pio_servo;
+mod pio_usb;
+mod ps2_keyboard;
+mod pulse_counter;
+mod quadrature_encoder;
+mod repeating_button;
+mod rotary_encoder;
+mod rs485;
+mod sensor;
+mod serial_io;
 mod servo;
+mod sgp30;
+mod shared_bus;
+mod soil_moisture;
+mod sx127x;
+mod text_display;
+mod tft_display;
+mod typematic;
+mod usb_absolute_mouse;
 mod usb_device;
+mod usb_digitizer;
+mod usb_hid_descriptor;
+mod usb_host;
+mod usb_mouse_coalescer;
+mod waterer;
+mod xpt2046;
 
+pub use air_quality::*;
+pub use analog_button_ladder::*;
+pub use analog_input::*;
+pub use auto_brightness::*;
+pub use bh1750::*;
 pub use button::*;
+pub use button_group::*;
+pub use button_task::*;
+pub use capacitive_touch::*;
+pub use ccs811::*;
+pub use dc_motor::*;
+pub use ds3231::*;
+pub use epaper::*;
+pub use flow_sensor::*;
+pub use haptics::*;
+pub use hid_consent::*;
+pub use hid_keyboard::*;
 pub use inland_ks0061_i2c_display::*;
 pub use inland_sh1106_oled_display::*;
+pub use key_input::*;
+pub use keyboard_layout::*;
+pub use matrix_keypad::*;
+pub use nrf24::*;
+pub use ov7670::*;
+pub use pio_servo::*;
+pub use pio_usb::*;
+pub use ps2_keyboard::*;
+pub use pulse_counter::*;
+pub use quadrature_encoder::*;
+pub use repeating_button::*;
+pub use rotary_encoder::*;
+pub use rs485::*;
+pub use sensor::*;
+pub use serial_io::*;
 pub use servo::*;
+pub use sgp30::*;
+pub use shared_bus::*;
+pub use soil_moisture::*;
+pub use sx127x::*;
+pub use text_display::*;
+pub use tft_display::*;
+pub use typematic::*;
+pub use usb_absolute_mouse::*;
 pub use usb_device::*;
+pub use usb_digitizer::*;
+pub use usb_hid_descriptor::*;
+pub use usb_host::*;
+pub use usb_mouse_coalescer::*;
+pub use waterer::*;
+pub use xpt2046::*;
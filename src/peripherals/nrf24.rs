@@ -0,0 +1,507 @@
+//! nrf24.rs — nRF24L01+ 2.4 GHz radio driver (SPI, IRQ pin)
+//!
+//! Payloads are always dynamic (1..=32 bytes). Up to six reading pipes can be opened, each
+//! with or without hardware auto-acknowledgment; [`Nrf24::send`] retransmits in hardware
+//! according to [`Nrf24Config`] and reports when no acknowledgment arrived. Sending and
+//! receiving wait on the active-low IRQ pin. The radio also implements
+//! [`PacketRadio`](crate::PacketRadio), sending to the current writing pipe.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let spi = Spi::new_blocking(p.SPI1, p.PIN_10, p.PIN_11, p.PIN_12, nrf24_default_spi_config());
+//! let csn = Output::new(p.PIN_13, Level::High);
+//! let ce = Output::new(p.PIN_14, Level::Low);
+//! let irq = Input::new(p.PIN_15, Pull::Up);
+//! let mut radio = Nrf24::new(spi, csn, ce, irq, Nrf24Config::default()).await?;
+//!
+//! radio.open_writing_pipe(*b"NODE1")?;
+//! radio.open_reading_pipe(1, b"BASE0", true)?;
+//! radio.send(b"ping").await?;
+//!
+//! let mut buf = [0u8; NRF24_MAX_PAYLOAD_LEN];
+//! let packet = radio.receive(&mut buf).await?;
+//! ```
+
+use embassy_rp::gpio::{Input, Output};
+use embassy_rp::spi::{self, Spi};
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::{PacketRadio, RadioLinkError};
+
+/// Largest payload in bytes
+pub const NRF24_MAX_PAYLOAD_LEN: usize = 32;
+/// Reading pipes 0..=5
+pub const NRF24_PIPES: u8 = 6;
+pub const NRF24_ADDRESS_LEN: usize = 5;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_EN_AA: u8 = 0x01;
+const REG_EN_RXADDR: u8 = 0x02;
+const REG_SETUP_AW: u8 = 0x03;
+const REG_SETUP_RETR: u8 = 0x04;
+const REG_RF_CH: u8 = 0x05;
+const REG_RF_SETUP: u8 = 0x06;
+const REG_STATUS: u8 = 0x07;
+const REG_RX_ADDR_P0: u8 = 0x0a;
+const REG_TX_ADDR: u8 = 0x10;
+const REG_FIFO_STATUS: u8 = 0x17;
+const REG_DYNPD: u8 = 0x1c;
+const REG_FEATURE: u8 = 0x1d;
+
+const CMD_W_REGISTER: u8 = 0x20;
+const CMD_R_RX_PL_WID: u8 = 0x60;
+const CMD_R_RX_PAYLOAD: u8 = 0x61;
+const CMD_W_TX_PAYLOAD: u8 = 0xa0;
+const CMD_W_TX_PAYLOAD_NOACK: u8 = 0xb0;
+const CMD_FLUSH_TX: u8 = 0xe1;
+const CMD_FLUSH_RX: u8 = 0xe2;
+
+const CONFIG_PRIM_RX: u8 = 0x01;
+const CONFIG_PWR_UP: u8 = 0x02;
+const CONFIG_CRCO: u8 = 0x04;
+const CONFIG_EN_CRC: u8 = 0x08;
+const STATUS_MAX_RT: u8 = 0x10;
+const STATUS_TX_DS: u8 = 0x20;
+const STATUS_RX_DR: u8 = 0x40;
+const FIFO_RX_EMPTY: u8 = 0x01;
+const FEATURE_EN_DYN_ACK: u8 = 0x01;
+const FEATURE_EN_DPL: u8 = 0x04;
+/// SETUP_AW value for 5-byte addresses
+const ADDRESS_WIDTH_5: u8 = 0x03;
+/// Longest a send can take: 15 retransmissions 4 ms apart, plus margin
+const SEND_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub fn nrf24_default_spi_config() -> spi::Config {
+    let mut cfg = spi::Config::default();
+    cfg.frequency = 8_000_000;
+    cfg.phase = spi::Phase::CaptureOnFirstTransition;
+    cfg.polarity = spi::Polarity::IdleLow;
+    cfg
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum Nrf24Error {
+    #[error("SPI transfer with nRF24 failed")]
+    Spi,
+    #[error("No nRF24L01+ responding")]
+    NotFound,
+    #[error("Pipe number must be 0..=5")]
+    InvalidPipe,
+    #[error("Payload must be 1..=32 bytes")]
+    InvalidPayload,
+    #[error("No acknowledgment after all retransmissions")]
+    MaxRetries,
+    #[error("Radio did not finish in time")]
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Nrf24DataRate {
+    Kbps250,
+    Mbps1,
+    Mbps2,
+}
+
+/// Transmit power: -18, -12, -6 or 0 dBm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Nrf24Power {
+    Min,
+    Low,
+    High,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Nrf24Config {
+    /// RF channel 0..=125, i.e. 2400 + channel MHz
+    pub channel: u8,
+    pub data_rate: Nrf24DataRate,
+    pub power: Nrf24Power,
+    /// 1 or 2 byte CRC
+    pub crc_len: u8,
+    /// Wait between hardware retransmissions, 250..=4000 µs in 250 µs steps
+    pub retransmit_delay_us: u16,
+    /// Hardware retransmissions, 0..=15
+    pub retransmit_count: u8,
+}
+
+impl Default for Nrf24Config {
+    fn default() -> Self {
+        Self {
+            channel: 76,
+            data_rate: Nrf24DataRate::Mbps1,
+            power: Nrf24Power::Max,
+            crc_len: 2,
+            retransmit_delay_us: 1500,
+            retransmit_count: 5,
+        }
+    }
+}
+
+impl Nrf24Config {
+    fn rf_setup(&self) -> u8 {
+        let rate = match self.data_rate {
+            Nrf24DataRate::Kbps250 => 0x20,
+            Nrf24DataRate::Mbps1 => 0x00,
+            Nrf24DataRate::Mbps2 => 0x08,
+        };
+        rate | ((self.power as u8) << 1)
+    }
+
+    fn setup_retr(&self) -> u8 {
+        let delay = (self.retransmit_delay_us.clamp(250, 4000) / 250 - 1) as u8;
+        (delay << 4) | self.retransmit_count.min(15)
+    }
+
+    fn config_register(&self) -> u8 {
+        let crc = if self.crc_len >= 2 {
+            CONFIG_EN_CRC | CONFIG_CRCO
+        } else {
+            CONFIG_EN_CRC
+        };
+        crc | CONFIG_PWR_UP
+    }
+}
+
+/// A received payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Nrf24Packet {
+    pub pipe: u8,
+    pub len: usize,
+}
+
+pub struct Nrf24<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    spi: Spi<'d, T, M>,
+    csn: Output<'d>,
+    ce: Output<'d>,
+    irq: Input<'d>,
+    config: Nrf24Config,
+    /// Pipe 0 doubles as the acknowledgment pipe while sending, so its reading address is
+    /// restored when listening again.
+    pipe0_address: Option<[u8; NRF24_ADDRESS_LEN]>,
+    listening: bool,
+}
+
+impl<'d, T, M> Nrf24<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    /// Check the radio responds, configure it and power it up (standby).
+    pub async fn new(
+        spi: Spi<'d, T, M>,
+        csn: Output<'d>,
+        ce: Output<'d>,
+        irq: Input<'d>,
+        config: Nrf24Config,
+    ) -> Result<Self, Nrf24Error> {
+        let mut radio = Self {
+            spi,
+            csn,
+            ce,
+            irq,
+            config,
+            pipe0_address: None,
+            listening: false,
+        };
+        radio.ce.set_low();
+        // Power-on reset takes up to 100 ms
+        Timer::after_millis(100).await;
+        radio.write_register(REG_SETUP_AW, ADDRESS_WIDTH_5)?;
+        if radio.read_register(REG_SETUP_AW)? != ADDRESS_WIDTH_5 {
+            return Err(Nrf24Error::NotFound);
+        }
+        radio.write_register(REG_FEATURE, FEATURE_EN_DPL | FEATURE_EN_DYN_ACK)?;
+        radio.write_register(REG_DYNPD, 0x3f)?;
+        radio.write_register(REG_EN_AA, 0)?;
+        radio.write_register(REG_EN_RXADDR, 0)?;
+        radio.apply_config()?;
+        radio.command(CMD_FLUSH_RX)?;
+        radio.command(CMD_FLUSH_TX)?;
+        radio.write_register(REG_STATUS, STATUS_RX_DR | STATUS_TX_DS | STATUS_MAX_RT)?;
+        // Oscillator start-up from power down
+        Timer::after_millis(5).await;
+        Ok(radio)
+    }
+
+    pub fn config(&self) -> &Nrf24Config {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: Nrf24Config) -> Result<(), Nrf24Error> {
+        self.config = config;
+        self.apply_config()
+    }
+
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Nrf24Error> {
+        self.config.channel = channel.min(125);
+        self.write_register(REG_RF_CH, self.config.channel)
+    }
+
+    /// Address that [`send`](Self::send) transmits to.
+    pub fn open_writing_pipe(
+        &mut self,
+        address: [u8; NRF24_ADDRESS_LEN],
+    ) -> Result<(), Nrf24Error> {
+        self.write_address(REG_TX_ADDR, &address)?;
+        // Acknowledgments come back on pipe 0 with the destination's address
+        self.write_address(REG_RX_ADDR_P0, &address)?;
+        self.update_register(REG_EN_AA, 0x01, true)?;
+        self.update_register(REG_EN_RXADDR, 0x01, true)
+    }
+
+    /// Listen on `pipe`. Pipes 0 and 1 take a full address; pipes 2..=5 share bytes 1..5 of
+    /// pipe 1 and only use the first byte of `address`.
+    pub fn open_reading_pipe(
+        &mut self,
+        pipe: u8,
+        address: &[u8; NRF24_ADDRESS_LEN],
+        auto_ack: bool,
+    ) -> Result<(), Nrf24Error> {
+        if pipe >= NRF24_PIPES {
+            return Err(Nrf24Error::InvalidPipe);
+        }
+        if pipe < 2 {
+            self.write_address(REG_RX_ADDR_P0 + pipe, address)?;
+        } else {
+            self.write_register(REG_RX_ADDR_P0 + pipe, address[0])?;
+        }
+        if pipe == 0 {
+            self.pipe0_address = Some(*address);
+        }
+        self.update_register(REG_EN_AA, 1 << pipe, auto_ack)?;
+        self.update_register(REG_EN_RXADDR, 1 << pipe, true)
+    }
+
+    pub fn close_reading_pipe(&mut self, pipe: u8) -> Result<(), Nrf24Error> {
+        if pipe >= NRF24_PIPES {
+            return Err(Nrf24Error::InvalidPipe);
+        }
+        if pipe == 0 {
+            self.pipe0_address = None;
+        }
+        self.update_register(REG_EN_RXADDR, 1 << pipe, false)
+    }
+
+    /// Send `payload` to the writing pipe and wait for the hardware acknowledgment.
+    pub async fn send(&mut self, payload: &[u8]) -> Result<(), Nrf24Error> {
+        self.transmit_with(CMD_W_TX_PAYLOAD, payload).await
+    }
+
+    /// Send `payload` without asking for an acknowledgment.
+    pub async fn send_no_ack(&mut self, payload: &[u8]) -> Result<(), Nrf24Error> {
+        self.transmit_with(CMD_W_TX_PAYLOAD_NOACK, payload).await
+    }
+
+    /// Switch to receive mode; [`receive`](Self::receive) does this on demand.
+    pub fn start_listening(&mut self) -> Result<(), Nrf24Error> {
+        if self.listening {
+            return Ok(());
+        }
+        match self.pipe0_address {
+            Some(address) => self.write_address(REG_RX_ADDR_P0, &address)?,
+            None => self.update_register(REG_EN_RXADDR, 0x01, false)?,
+        }
+        let config = self.config.config_register() | CONFIG_PRIM_RX;
+        self.write_register(REG_CONFIG, config)?;
+        self.ce.set_high();
+        self.listening = true;
+        Ok(())
+    }
+
+    /// Back to standby, the state for sending or reconfiguring.
+    pub fn stop_listening(&mut self) -> Result<(), Nrf24Error> {
+        self.ce.set_low();
+        self.listening = false;
+        self.update_register(REG_EN_RXADDR, 0x01, true)?;
+        self.write_register(REG_CONFIG, self.config.config_register())
+    }
+
+    /// Lowest power mode; the next send or receive wakes the radio.
+    pub fn power_down(&mut self) -> Result<(), Nrf24Error> {
+        self.ce.set_low();
+        self.listening = false;
+        self.write_register(REG_CONFIG, self.config.config_register() & !CONFIG_PWR_UP)
+    }
+
+    /// Wait for the next payload and copy it into `buf` (truncated if too short).
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<Nrf24Packet, Nrf24Error> {
+        self.start_listening()?;
+        loop {
+            self.write_register(REG_STATUS, STATUS_RX_DR)?;
+            if self.read_register(REG_FIFO_STATUS)? & FIFO_RX_EMPTY == 0 {
+                if let Some(packet) = self.read_payload(buf)? {
+                    return Ok(packet);
+                }
+                continue;
+            }
+            self.irq.wait_for_low().await;
+        }
+    }
+
+    async fn transmit_with(&mut self, command: u8, payload: &[u8]) -> Result<(), Nrf24Error> {
+        if payload.is_empty() || payload.len() > NRF24_MAX_PAYLOAD_LEN {
+            return Err(Nrf24Error::InvalidPayload);
+        }
+        self.stop_listening()?;
+        self.write_register(REG_STATUS, STATUS_TX_DS | STATUS_MAX_RT)?;
+        self.csn.set_low();
+        let result = self
+            .spi
+            .blocking_write(&[command])
+            .and_then(|_| self.spi.blocking_write(payload));
+        self.csn.set_high();
+        result.map_err(|_| Nrf24Error::Spi)?;
+
+        // A CE pulse of at least 10 µs starts one transmission
+        self.ce.set_high();
+        Timer::after_micros(15).await;
+        self.ce.set_low();
+        let done = with_timeout(SEND_TIMEOUT, self.irq.wait_for_low()).await;
+
+        let status = self.read_register(REG_STATUS)?;
+        self.write_register(REG_STATUS, STATUS_TX_DS | STATUS_MAX_RT)?;
+        if done.is_err() {
+            self.command(CMD_FLUSH_TX)?;
+            return Err(Nrf24Error::Timeout);
+        }
+        if status & STATUS_MAX_RT != 0 {
+            self.command(CMD_FLUSH_TX)?;
+            return Err(Nrf24Error::MaxRetries);
+        }
+        Ok(())
+    }
+
+    /// Top of the RX FIFO; `None` if it was corrupt and had to be flushed.
+    fn read_payload(&mut self, buf: &mut [u8]) -> Result<Option<Nrf24Packet>, Nrf24Error> {
+        let status = self.read_register(REG_STATUS)?;
+        let pipe = (status >> 1) & 0x07;
+        let mut width = [CMD_R_RX_PL_WID, 0];
+        self.transfer(&mut width)?;
+        let len = width[1] as usize;
+        if len == 0 || len > NRF24_MAX_PAYLOAD_LEN || pipe >= NRF24_PIPES {
+            self.command(CMD_FLUSH_RX)?;
+            return Ok(None);
+        }
+        let mut payload = [0u8; NRF24_MAX_PAYLOAD_LEN];
+        self.csn.set_low();
+        let result = self
+            .spi
+            .blocking_write(&[CMD_R_RX_PAYLOAD])
+            .and_then(|_| self.spi.blocking_read(&mut payload[..len]));
+        self.csn.set_high();
+        result.map_err(|_| Nrf24Error::Spi)?;
+        let copied = len.min(buf.len());
+        buf[..copied].copy_from_slice(&payload[..copied]);
+        Ok(Some(Nrf24Packet { pipe, len: copied }))
+    }
+
+    fn apply_config(&mut self) -> Result<(), Nrf24Error> {
+        let config = self.config;
+        self.write_register(REG_RF_CH, config.channel.min(125))?;
+        self.write_register(REG_RF_SETUP, config.rf_setup())?;
+        self.write_register(REG_SETUP_RETR, config.setup_retr())?;
+        let mode = if self.listening { CONFIG_PRIM_RX } else { 0 };
+        self.write_register(REG_CONFIG, config.config_register() | mode)
+    }
+
+    fn update_register(&mut self, register: u8, mask: u8, set: bool) -> Result<(), Nrf24Error> {
+        let value = self.read_register(register)?;
+        let value = if set { value | mask } else { value & !mask };
+        self.write_register(register, value)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Nrf24Error> {
+        let mut buf = [register, 0];
+        self.transfer(&mut buf)?;
+        Ok(buf[1])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Nrf24Error> {
+        self.transfer(&mut [CMD_W_REGISTER | register, value])
+    }
+
+    fn write_address(
+        &mut self,
+        register: u8,
+        address: &[u8; NRF24_ADDRESS_LEN],
+    ) -> Result<(), Nrf24Error> {
+        // Least significant byte first, as in the common RF24 libraries
+        let mut buf = [0u8; NRF24_ADDRESS_LEN + 1];
+        buf[0] = CMD_W_REGISTER | register;
+        buf[1..].copy_from_slice(address);
+        self.transfer(&mut buf)
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), Nrf24Error> {
+        self.transfer(&mut [command])
+    }
+
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), Nrf24Error> {
+        self.csn.set_low();
+        let result = self.spi.blocking_transfer_in_place(buf);
+        self.csn.set_high();
+        result.map_err(|_| Nrf24Error::Spi)
+    }
+}
+
+impl<T, M> PacketRadio for Nrf24<'_, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    const MAX_PACKET_LEN: usize = NRF24_MAX_PAYLOAD_LEN;
+
+    async fn transmit(&mut self, packet: &[u8]) -> Result<(), RadioLinkError> {
+        self.send(packet).await.map_err(|_| RadioLinkError::Radio)
+    }
+
+    async fn receive(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, RadioLinkError> {
+        let packet = Nrf24::receive(self, buf);
+        let result = match timeout {
+            Some(timeout) => match with_timeout(timeout, packet).await {
+                Ok(result) => result,
+                Err(_) => return Ok(None),
+            },
+            None => packet.await,
+        };
+        result
+            .map(|packet| Some(packet.len))
+            .map_err(|_| RadioLinkError::Radio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_rf_settings() {
+        let config = Nrf24Config::default();
+        assert_eq!(config.rf_setup(), 0x06);
+        // ARD = 1500 µs -> 5, ARC = 5
+        assert_eq!(config.setup_retr(), 0x55);
+        assert_eq!(config.config_register(), 0x0e);
+
+        let slow = Nrf24Config {
+            data_rate: Nrf24DataRate::Kbps250,
+            power: Nrf24Power::Min,
+            crc_len: 1,
+            retransmit_delay_us: 10_000,
+            retransmit_count: 20,
+            ..config
+        };
+        assert_eq!(slow.rf_setup(), 0x20);
+        assert_eq!(slow.setup_retr(), 0xff);
+        assert_eq!(slow.config_register(), 0x0a);
+    }
+}
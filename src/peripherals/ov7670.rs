@@ -0,0 +1,277 @@
+//! ov7670.rs — experimental OV7670 camera support: SCCB setup and PIO+DMA frame capture
+//!
+//! [`Ov7670`] configures the sensor over I2C (SCCB) for QQVGA (160x120) RGB565 output.
+//! [`Ov7670Capture`] samples the parallel bus with a PIO state machine and moves one
+//! frame by DMA into a caller buffer of [`OV7670_QQVGA_FRAME_LEN`] bytes (big-endian
+//! RGB565, row-major). The sensor needs a 10–24 MHz clock on XCLK; one PWM slice
+//! configured with [`ov7670_xclk_pwm_config`] provides it.
+//!
+//! Wiring: D0–D7, PCLK, HREF and VSYNC must be on 11 consecutive GPIOs in that order
+//! (the PIO program addresses them relative to D0).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let _xclk = Pwm::new_output_a(p.PWM_SLICE7, p.PIN_14, ov7670_xclk_pwm_config());
+//! let mut sensor = Ov7670::new(I2c::new_blocking(p.I2C0, p.PIN_21, p.PIN_20, Default::default()))?;
+//! sensor.init_qqvga_rgb565().await?;
+//!
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO1, Irqs);
+//! let pins = [
+//!     common.make_pio_pin(p.PIN_2), common.make_pio_pin(p.PIN_3), common.make_pio_pin(p.PIN_4),
+//!     common.make_pio_pin(p.PIN_5), common.make_pio_pin(p.PIN_6), common.make_pio_pin(p.PIN_7),
+//!     common.make_pio_pin(p.PIN_8), common.make_pio_pin(p.PIN_9), common.make_pio_pin(p.PIN_10),
+//!     common.make_pio_pin(p.PIN_11), common.make_pio_pin(p.PIN_12),
+//! ];
+//! let mut camera = Ov7670Capture::new(&mut common, sm0, p.DMA_CH2, pins)?;
+//!
+//! static FRAME: StaticCell<[u8; OV7670_QQVGA_FRAME_LEN]> = StaticCell::new();
+//! let frame = FRAME.init([0; OV7670_QQVGA_FRAME_LEN]);
+//! camera.capture(frame).await?;
+//! ```
+
+use embassy_rp::Peri;
+use embassy_rp::dma;
+use embassy_rp::pio::{
+    Common, Config, Direction, FifoJoin, Instance, Pin, ShiftConfig, ShiftDirection, StateMachine,
+};
+use embassy_rp::pwm;
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_hal::i2c::I2c;
+
+/// SCCB (I2C) address
+pub const OV7670_ADDRESS: u8 = 0x21;
+pub const OV7670_QQVGA_WIDTH: u16 = 160;
+pub const OV7670_QQVGA_HEIGHT: u16 = 120;
+/// Bytes in one QQVGA RGB565 frame
+pub const OV7670_QQVGA_FRAME_LEN: usize =
+    OV7670_QQVGA_WIDTH as usize * OV7670_QQVGA_HEIGHT as usize * 2;
+
+/// Longest wait for a complete frame
+const FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+const PRODUCT_ID: u8 = 0x76;
+
+const REG_PID: u8 = 0x0a;
+const REG_COM7: u8 = 0x12;
+const REG_MVFP: u8 = 0x1e;
+const COM7_RESET: u8 = 0x80;
+const MVFP_MIRROR: u8 = 0x20;
+const MVFP_VFLIP: u8 = 0x10;
+
+/// Register settings for QQVGA RGB565 with automatic exposure, gain and white balance
+const QQVGA_RGB565: &[(u8, u8)] = &[
+    (0x11, 0x01), // CLKRC: internal clock = XCLK / 2
+    (0x12, 0x04), // COM7: RGB output
+    (0x40, 0xd0), // COM15: RGB565, full output range
+    (0x8c, 0x00), // RGB444 off
+    (0x3a, 0x04), // TSLB
+    (0x0c, 0x04), // COM3: enable downsampling
+    (0x3e, 0x1a), // COM14: manual scaling, PCLK / 4
+    (0x70, 0x3a), // SCALING_XSC
+    (0x71, 0x35), // SCALING_YSC
+    (0x72, 0x22), // SCALING_DCWCTR: downsample by 4
+    (0x73, 0xf2), // SCALING_PCLK_DIV
+    (0xa2, 0x02), // SCALING_PCLK_DELAY
+    (0x17, 0x16), // HSTART
+    (0x18, 0x04), // HSTOP
+    (0x32, 0xa4), // HREF
+    (0x19, 0x02), // VSTART
+    (0x1a, 0x7a), // VSTOP
+    (0x03, 0x0a), // VREF
+    (0x15, 0x20), // COM10: no PCLK during horizontal blanking
+    (0x4f, 0xb3), // MTX1..MTX6: RGB colour matrix
+    (0x50, 0xb3),
+    (0x51, 0x00),
+    (0x52, 0x3d),
+    (0x53, 0xa7),
+    (0x54, 0xe4),
+    (0x58, 0x9e), // MTXS
+    (0x3d, 0xc0), // COM13: gamma, UV saturation
+    (0x41, 0x38), // COM16: edge enhancement, denoise
+    (0x13, 0xe7), // COM8: AGC, AEC, AWB
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum CameraError {
+    #[error("SCCB (I2C) communication with the camera failed")]
+    Communication,
+    #[error("No OV7670 found on the bus")]
+    NotDetected,
+    #[error("Camera pins must be D0-D7, PCLK, HREF, VSYNC on consecutive GPIOs")]
+    InvalidPins,
+    #[error("Frame buffer too small: {actual} < {needed} bytes")]
+    BufferTooSmall { actual: usize, needed: usize },
+    #[error("No complete frame received in time")]
+    Timeout,
+}
+
+/// A source of RGB565 frames (big-endian pixels, row-major)
+#[allow(async_fn_in_trait)]
+pub trait FrameCapture {
+    /// Width and height in pixels
+    fn frame_size(&self) -> (u16, u16);
+
+    /// Fill `frame` (at least `width * height * 2` bytes) with the next frame.
+    async fn capture(&mut self, frame: &mut [u8]) -> Result<(), CameraError>;
+}
+
+/// PWM settings for a 12.5 MHz XCLK on either channel, with the default 125 MHz system
+/// clock
+pub fn ov7670_xclk_pwm_config() -> pwm::Config {
+    let mut cfg = pwm::Config::default();
+    cfg.top = 9;
+    cfg.compare_a = 5;
+    cfg.compare_b = 5;
+    cfg
+}
+
+/// Sensor configuration over SCCB
+pub struct Ov7670<I: I2c> {
+    i2c: I,
+}
+
+impl<I: I2c> Ov7670<I> {
+    /// Check the product ID; XCLK must already be running.
+    pub fn new(i2c: I) -> Result<Self, CameraError> {
+        let mut sensor = Self { i2c };
+        if sensor.read_register(REG_PID)? != PRODUCT_ID {
+            return Err(CameraError::NotDetected);
+        }
+        Ok(sensor)
+    }
+
+    /// Reset the sensor and set it up for QQVGA RGB565.
+    pub async fn init_qqvga_rgb565(&mut self) -> Result<(), CameraError> {
+        self.write_register(REG_COM7, COM7_RESET)?;
+        Timer::after_millis(10).await;
+        for &(register, value) in QQVGA_RGB565 {
+            self.write_register(register, value)?;
+        }
+        // Let auto exposure settle
+        Timer::after_millis(300).await;
+        Ok(())
+    }
+
+    /// Mirror horizontally and/or flip vertically, for upside-down mounting.
+    pub fn set_orientation(&mut self, mirror: bool, flip: bool) -> Result<(), CameraError> {
+        let mut value = self.read_register(REG_MVFP)? & !(MVFP_MIRROR | MVFP_VFLIP);
+        if mirror {
+            value |= MVFP_MIRROR;
+        }
+        if flip {
+            value |= MVFP_VFLIP;
+        }
+        self.write_register(REG_MVFP, value)
+    }
+
+    pub fn write_register(&mut self, register: u8, value: u8) -> Result<(), CameraError> {
+        self.i2c
+            .write(OV7670_ADDRESS, &[register, value])
+            .map_err(|_| CameraError::Communication)
+    }
+
+    /// SCCB has no repeated start, so this is a write followed by a separate read.
+    pub fn read_register(&mut self, register: u8) -> Result<u8, CameraError> {
+        let mut value = [0u8];
+        self.i2c
+            .write(OV7670_ADDRESS, &[register])
+            .and_then(|_| self.i2c.read(OV7670_ADDRESS, &mut value))
+            .map_err(|_| CameraError::Communication)?;
+        Ok(value[0])
+    }
+
+    pub fn release(self) -> I {
+        self.i2c
+    }
+}
+
+/// QQVGA frame grabber on one PIO state machine and one DMA channel
+pub struct Ov7670Capture<'d, P: Instance, const S: usize, D: dma::Channel> {
+    sm: StateMachine<'d, P, S>,
+    dma: Peri<'d, D>,
+    origin: u8,
+    _pins: [Pin<'d, P>; 11],
+}
+
+impl<'d, P: Instance, const S: usize, D: dma::Channel> Ov7670Capture<'d, P, S, D> {
+    /// `pins` are D0–D7, PCLK, HREF, VSYNC, created with `common.make_pio_pin`.
+    pub fn new(
+        common: &mut Common<'d, P>,
+        mut sm: StateMachine<'d, P, S>,
+        dma: Peri<'d, D>,
+        pins: [Pin<'d, P>; 11],
+    ) -> Result<Self, CameraError> {
+        let base = pins[0].pin();
+        if pins.iter().zip(0..).any(|(pin, i)| pin.pin() != base + i) {
+            return Err(CameraError::InvalidPins);
+        }
+
+        // Pins relative to D0: PCLK = 8, HREF = 9, VSYNC = 10. One byte per PCLK rising
+        // edge while HREF is high, starting at the end of a VSYNC pulse.
+        let program = embassy_rp::pio::program::pio_asm!(
+            "wait 1 pin 10",
+            "wait 0 pin 10",
+            ".wrap_target",
+            "wait 1 pin 9",
+            "wait 1 pin 8",
+            "in pins, 8",
+            "wait 0 pin 8",
+            ".wrap",
+        );
+        let loaded = common.load_program(&program.program);
+        let pin_refs: [&Pin<'d, P>; 11] = core::array::from_fn(|i| &pins[i]);
+
+        let mut cfg = Config::default();
+        cfg.use_program(&loaded, &[]);
+        cfg.set_in_pins(&pin_refs);
+        // Every byte is pushed on its own and read by 8-bit DMA from the FIFO's low byte
+        cfg.shift_in = ShiftConfig {
+            auto_fill: true,
+            threshold: 8,
+            direction: ShiftDirection::Left,
+        };
+        cfg.fifo_join = FifoJoin::RxOnly;
+        sm.set_config(&cfg);
+        sm.set_pin_dirs(Direction::In, &pin_refs);
+
+        Ok(Self {
+            sm,
+            dma,
+            origin: loaded.origin,
+            _pins: pins,
+        })
+    }
+
+    /// Capture the next complete frame into `frame`.
+    pub async fn capture(&mut self, frame: &mut [u8]) -> Result<(), CameraError> {
+        let actual = frame.len();
+        let frame = frame
+            .get_mut(..OV7670_QQVGA_FRAME_LEN)
+            .ok_or(CameraError::BufferTooSmall {
+                actual,
+                needed: OV7670_QQVGA_FRAME_LEN,
+            })?;
+
+        self.sm.set_enable(false);
+        self.sm.clear_fifos();
+        self.sm.restart();
+        // SAFETY: jumps to the start of the program loaded in `new`
+        unsafe { self.sm.exec_jmp(self.origin) };
+        self.sm.set_enable(true);
+
+        let transfer = self.sm.rx().dma_pull(self.dma.reborrow(), frame, false);
+        let result = with_timeout(FRAME_TIMEOUT, transfer).await;
+        self.sm.set_enable(false);
+        result.map_err(|_| CameraError::Timeout)
+    }
+}
+
+impl<P: Instance, const S: usize, D: dma::Channel> FrameCapture for Ov7670Capture<'_, P, S, D> {
+    fn frame_size(&self) -> (u16, u16) {
+        (OV7670_QQVGA_WIDTH, OV7670_QQVGA_HEIGHT)
+    }
+
+    async fn capture(&mut self, frame: &mut [u8]) -> Result<(), CameraError> {
+        Ov7670Capture::capture(self, frame).await
+    }
+}
@@ -0,0 +1,347 @@
+//! pio_servo.rs — many hobby servos on arbitrary GPIOs from one PIO state machine
+//!
+//! A PWM slice drives at most two servos, on fixed pin pairs. [`PioServoBank`] drives any
+//! number of servos (up to every GPIO) on any pins, from a single PIO state machine and
+//! one DMA channel, and leaves the PWM slices free: enough for a hexapod's 18 joints.
+//!
+//! Each frame is a short list of (pin levels, duration) steps: every attached servo's pin
+//! goes high at the start of the frame and low when its pulse ends. The state machine
+//! plays the steps with 1 µs resolution while DMA feeds it the next frame, so timing
+//! doesn't depend on the CPU. [`PioServoBank::run`] rebuilds the list once per frame from
+//! a [`PioServoControl`], which other tasks update per channel; changes take effect from
+//! the next frame. Pulse ends less than 3 µs apart share a step.
+//!
+//! The state machine writes the output level of every GPIO driven by its PIO block, so the
+//! bank needs a PIO block of its own (PIO1 if the CYW43 WiFi uses PIO0).
+//!
+//! # Example
+//!
+//! ```ignore
+//! static SERVOS: PioServoControl<3> = PioServoControl::new(20_000);
+//!
+//! #[embassy_executor::task]
+//! async fn servo_task(mut bank: PioServoBank<'static, PIO1, 0, DMA_CH3, 3>) -> ! {
+//!     bank.run(&SERVOS).await
+//! }
+//!
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO1, Irqs);
+//! let pins = [
+//!     common.make_pio_pin(p.PIN_2),
+//!     common.make_pio_pin(p.PIN_9),
+//!     common.make_pio_pin(p.PIN_21),
+//! ];
+//! spawner.spawn(servo_task(PioServoBank::new(&mut common, sm0, p.DMA_CH3, pins)))?;
+//!
+//! SERVOS.set_pulse_us(0, 1500)?;
+//! SERVOS.set_angle(1, ServoSpec::inland_ks0209(), 45.0)?;
+//! let mut elbow = SERVOS.channel(2, ServoSpec::makerhawk_mg995());
+//! elbow.set_angle(120.0)?;
+//! ```
+
+use core::any::TypeId;
+use core::cell::RefCell;
+
+use embassy_rp::Peri;
+use embassy_rp::dma;
+use embassy_rp::gpio::Level;
+use embassy_rp::pac;
+use embassy_rp::peripherals::PIO0;
+use embassy_rp::pio::{
+    Common, Config, Direction, FifoJoin, Instance, Pin, ShiftConfig, ShiftDirection, StateMachine,
+};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use fixed::FixedU32;
+use fixed::types::extra::U8;
+
+use crate::{ServoChannel, ServoError, ServoSpec, sys_clock_hz};
+
+/// Most channels in a bank: one per GPIO
+pub const PIO_SERVO_MAX_CHANNELS: usize = 30;
+
+/// PIO clock; one tick is one microsecond
+const TICK_HZ: u32 = 1_000_000;
+/// Ticks each step takes on top of its delay count: the two `out`s and the final `jmp`
+const STEP_OVERHEAD_TICKS: u32 = 3;
+/// Two words per step; at most one step per channel plus the one that starts the frame
+const FRAME_WORDS: usize = 2 * (PIO_SERVO_MAX_CHANNELS + 1);
+
+/// Pulse widths for a [`PioServoBank`], shared with the tasks that move the servos
+pub struct PioServoControl<const N: usize> {
+    frame_us: u32,
+    /// `None` for detached channels
+    pulses: Mutex<CriticalSectionRawMutex, RefCell<[Option<u16>; N]>>,
+}
+
+impl<const N: usize> PioServoControl<N> {
+    /// All channels start detached (no pulses, servos limp). `frame_us` is the
+    /// [`ServoSpec::frame_us`] of the servos, usually 20 000.
+    pub const fn new(frame_us: u32) -> Self {
+        Self {
+            frame_us,
+            pulses: Mutex::new(RefCell::new([None; N])),
+        }
+    }
+
+    pub fn frame_us(&self) -> u32 {
+        self.frame_us
+    }
+
+    /// Pulse `pulse_us` on `channel` from the next frame on. The pulse must be shorter than
+    /// the frame.
+    pub fn set_pulse_us(&self, channel: usize, pulse_us: u32) -> Result<(), ServoError> {
+        if pulse_us >= self.frame_us || pulse_us > u16::MAX as u32 {
+            return Err(ServoError::PulseOutOfRange);
+        }
+        self.update(channel, Some(pulse_us as u16))
+    }
+
+    /// Move the servo on `channel` to `angle_deg`, mapped through `spec`.
+    pub fn set_angle(
+        &self,
+        channel: usize,
+        spec: &ServoSpec,
+        angle_deg: f32,
+    ) -> Result<(), ServoError> {
+        self.set_pulse_us(channel, spec.pulse_us(angle_deg))
+    }
+
+    /// Stop the pulses on `channel`; [`set_pulse_us`](Self::set_pulse_us) starts them again.
+    pub fn detach(&self, channel: usize) -> Result<(), ServoError> {
+        self.update(channel, None)
+    }
+
+    /// Last commanded pulse on `channel`, `None` if detached or out of range
+    pub fn pulse_us(&self, channel: usize) -> Option<u16> {
+        self.pulses
+            .lock(|pulses| pulses.borrow().get(channel).copied().flatten())
+    }
+
+    /// One channel as a [`ServoChannel`], for animations and G-code axes
+    pub fn channel<'c>(&'c self, channel: usize, spec: &'c ServoSpec) -> PioServo<'c, N> {
+        PioServo {
+            control: self,
+            channel,
+            spec,
+        }
+    }
+
+    fn update(&self, channel: usize, pulse_us: Option<u16>) -> Result<(), ServoError> {
+        self.pulses.lock(|pulses| {
+            let mut pulses = pulses.borrow_mut();
+            let slot = pulses.get_mut(channel).ok_or(ServoError::InvalidChannel)?;
+            *slot = pulse_us;
+            Ok(())
+        })
+    }
+
+    fn pulses(&self) -> [Option<u16>; N] {
+        self.pulses.lock(|pulses| *pulses.borrow())
+    }
+}
+
+impl<const N: usize> Default for PioServoControl<N> {
+    fn default() -> Self {
+        Self::new(ServoSpec::inland_ks0209().frame_us)
+    }
+}
+
+/// One channel of a [`PioServoControl`] with the spec of the servo on it
+pub struct PioServo<'c, const N: usize> {
+    control: &'c PioServoControl<N>,
+    channel: usize,
+    spec: &'c ServoSpec,
+}
+
+impl<const N: usize> PioServo<'_, N> {
+    pub fn set_pulse_us(&mut self, pulse_us: u32) -> Result<(), ServoError> {
+        self.control.set_pulse_us(self.channel, pulse_us)
+    }
+
+    pub fn detach(&mut self) -> Result<(), ServoError> {
+        self.control.detach(self.channel)
+    }
+}
+
+impl<const N: usize> ServoChannel for PioServo<'_, N> {
+    fn set_angle(&mut self, angle_deg: f32) -> Result<(), ServoError> {
+        self.control.set_angle(self.channel, self.spec, angle_deg)
+    }
+}
+
+/// One frame for the state machine: pairs of (levels of GPIO 0–31, delay count)
+#[derive(Debug, Clone)]
+struct FrameSteps {
+    words: [u32; FRAME_WORDS],
+    len: usize,
+}
+
+impl FrameSteps {
+    const fn new() -> Self {
+        Self {
+            words: [0; FRAME_WORDS],
+            len: 0,
+        }
+    }
+
+    fn words(&self) -> &[u32] {
+        &self.words[..self.len]
+    }
+
+    /// Steps for `pulses` (µs, per channel) on the GPIOs `gpios`, over `frame_us`
+    fn build(&mut self, gpios: &[u8], pulses: &[Option<u16>], frame_us: u32) {
+        let frame = frame_us.max(2 * STEP_OVERHEAD_TICKS);
+        // Pulse ends in time order; every step must last at least its overhead
+        let mut ends = [(0u32, 0u32); PIO_SERVO_MAX_CHANNELS];
+        let mut count = 0;
+        for (&gpio, pulse) in gpios.iter().zip(pulses) {
+            if let Some(pulse) = pulse {
+                let end = (*pulse as u32).clamp(STEP_OVERHEAD_TICKS, frame - STEP_OVERHEAD_TICKS);
+                ends[count] = (end, 1 << gpio);
+                count += 1;
+            }
+        }
+        let ends = &mut ends[..count];
+        ends.sort_unstable();
+
+        self.len = 0;
+        let mut levels = ends.iter().fold(0, |levels, &(_, mask)| levels | mask);
+        let mut start = 0;
+        for &(end, mask) in ends.iter() {
+            if end >= start + STEP_OVERHEAD_TICKS {
+                self.push(levels, end - start);
+                start = end;
+            }
+            // Too close to the previous end to get a step of its own: ends with it
+            levels &= !mask;
+        }
+        self.push(levels, frame - start);
+    }
+
+    fn push(&mut self, levels: u32, ticks: u32) {
+        self.words[self.len] = levels;
+        self.words[self.len + 1] = ticks - STEP_OVERHEAD_TICKS;
+        self.len += 2;
+    }
+}
+
+/// Up to [`PIO_SERVO_MAX_CHANNELS`] servos on one PIO state machine and one DMA channel
+pub struct PioServoBank<'d, P: Instance, const S: usize, D: dma::Channel, const N: usize> {
+    sm: StateMachine<'d, P, S>,
+    dma: Peri<'d, D>,
+    gpios: [u8; N],
+    steps: FrameSteps,
+    _pins: [Pin<'d, P>; N],
+}
+
+impl<'d, P: Instance + 'static, const S: usize, D: dma::Channel, const N: usize>
+    PioServoBank<'d, P, S, D, N>
+{
+    /// `pins` are the servo signals in channel order, on any GPIOs, created with
+    /// `common.make_pio_pin`. They stay low until [`run`](Self::run) starts.
+    pub fn new(
+        common: &mut Common<'d, P>,
+        mut sm: StateMachine<'d, P, S>,
+        dma: Peri<'d, D>,
+        pins: [Pin<'d, P>; N],
+    ) -> Self {
+        // Per step: set all pin levels, then wait for the delay count (+ 3 ticks)
+        let program = embassy_rp::pio::program::pio_asm!(
+            ".wrap_target",
+            "out pins, 32",
+            "out x, 32",
+            "delay:",
+            "jmp x-- delay",
+            ".wrap",
+        );
+        let loaded = common.load_program(&program.program);
+        let pin_refs: [&Pin<'d, P>; N] = core::array::from_fn(|i| &pins[i]);
+
+        let mut cfg = Config::default();
+        cfg.use_program(&loaded, &[]);
+        cfg.clock_divider = FixedU32::<U8>::from_bits(divider_bits(sys_clock_hz()));
+        cfg.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 32,
+            direction: ShiftDirection::Right,
+        };
+        cfg.fifo_join = FifoJoin::TxOnly;
+        sm.set_config(&cfg);
+        sm.set_pins(Level::Low, &pin_refs);
+        sm.set_pin_dirs(Direction::Out, &pin_refs);
+        // `Config` only takes consecutive out pins; a 32-bit `out pins` from GPIO 0 lets
+        // each mask bit be its GPIO, wherever the servos are.
+        pio_registers::<P>().sm(S).pinctrl().modify(|w| {
+            w.set_out_base(0);
+            w.set_out_count(32);
+        });
+
+        Self {
+            sm,
+            dma,
+            gpios: core::array::from_fn(|i| pins[i].pin()),
+            steps: FrameSteps::new(),
+            _pins: pins,
+        }
+    }
+
+    /// Generate the pulses set in `control`, forever. Run it in a task of its own.
+    pub async fn run(&mut self, control: &PioServoControl<N>) -> ! {
+        self.sm.set_enable(true);
+        loop {
+            self.steps
+                .build(&self.gpios, &control.pulses(), control.frame_us());
+            // Returns once the last step is queued, so the next frame is built while the
+            // current one still plays out.
+            self.sm
+                .tx()
+                .dma_push(self.dma.reborrow(), self.steps.words(), false)
+                .await;
+        }
+    }
+}
+
+/// The RP2040 has two PIO blocks
+fn pio_registers<P: Instance + 'static>() -> pac::pio::Pio {
+    if TypeId::of::<P>() == TypeId::of::<PIO0>() {
+        pac::PIO0
+    } else {
+        pac::PIO1
+    }
+}
+
+/// PIO clock divider for [`TICK_HZ`], 8 fractional bits, at least 1.0
+fn divider_bits(sys_hz: u32) -> u32 {
+    let bits = (sys_hz as u64 * 256 + TICK_HZ as u64 / 2) / TICK_HZ as u64;
+    bits.max(256) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_steps_end_pulses_in_order() {
+        let mut steps = FrameSteps::new();
+        steps.build(&[2, 5, 17], &[Some(1500), None, Some(1000)], 20_000);
+        let high = 1 << 2 | 1 << 17;
+        assert_eq!(steps.words(), &[high, 997, 1 << 2, 497, 0, 18_497]);
+        let total: u32 = steps.words().iter().skip(1).step_by(2).map(|d| d + 3).sum();
+        assert_eq!(total, 20_000);
+
+        // Equal and nearly equal ends share a step
+        steps.build(&[0, 1, 2], &[Some(1500), Some(1500), Some(1502)], 20_000);
+        assert_eq!(steps.words(), &[0b111, 1497, 0, 18_497]);
+
+        // Nothing attached: a low frame
+        steps.build(&[0, 1], &[None, None], 20_000);
+        assert_eq!(steps.words(), &[0, 19_997]);
+    }
+
+    #[test]
+    fn divider_gives_microsecond_ticks() {
+        assert_eq!(divider_bits(125_000_000), 125 << 8);
+        assert_eq!(divider_bits(133_000_000), 133 << 8);
+        assert_eq!(divider_bits(500_000), 256);
+    }
+}
@@ -0,0 +1,1014 @@
+//! pio_usb.rs — experimental USB host port bit-banged by PIO (PIO-USB)
+//!
+//! [`PioUsbBus`] implements [`UsbHostBus`] on two GPIOs with one PIO block, leaving the
+//! RP2040's own USB controller to the device side, so [`UsbHidHost`](crate::UsbHidHost)
+//! can read a keyboard, mouse or gamepad while the Pico is itself a USB device.
+//!
+//! Two state machines share the port. The transmitter sends packets the CPU has already
+//! NRZI-encoded and bit-stuffed, then watches the line: when the device answers with
+//! something longer than a handshake (a data packet) it sends the pre-encoded ACK itself,
+//! within the few bit times the protocol allows. The sampler records the line at four
+//! times the bit rate from the start of each transaction; DMA moves the samples to RAM
+//! and the CPU decodes them afterwards, resynchronizing on every edge. Data is
+//! acknowledged before its CRC is checked, so a corrupted report is dropped rather than
+//! resent.
+//!
+//! Full speed (12 Mbit/s) needs a PIO clock of exactly 48 MHz, so the system clock must be
+//! a multiple of 48 MHz (48, 96, 144, 192 or 240 MHz, see
+//! [`init_with_system_clock`](crate::init_with_system_clock)). Low-speed devices
+//! (1.5 Mbit/s, most keyboards) work at any clock. One device, no hubs. Frames are kept
+//! alive (SOF at full speed, a bare EOP at low speed) while the host waits between polls
+//! in [`UsbHostBus::wait_frames`].
+//!
+//! Wiring: D+ and D- on consecutive GPIOs (D+ first) through 22 Ω series resistors, with
+//! 15 kΩ pull-downs (the internal pull-downs are switched on but are weaker than the spec
+//! asks) and 5 V on VBUS. The two programs fill the PIO block, so it needs one of its own
+//! (PIO1 if the CYW43 WiFi uses PIO0).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let p = init_with_system_clock(SystemClock::Custom(144_000_000))?;
+//! let Pio { mut common, sm0, sm1, .. } = Pio::new(p.PIO1, Irqs);
+//! let pins = [common.make_pio_pin(p.PIN_16), common.make_pio_pin(p.PIN_17)];
+//! let bus = PioUsbBus::new(&mut common, sm0, sm1, p.DMA_CH4, p.DMA_CH5, pins)?;
+//! let mut host = UsbHidHost::new(bus);
+//! ```
+
+use embassy_futures::yield_now;
+use embassy_rp::Peri;
+use embassy_rp::dma;
+use embassy_rp::gpio::{Level, Pull};
+use embassy_rp::pac;
+use embassy_rp::pio::{
+    Common, Config, Direction, FifoJoin, Instance, LoadedProgram, Pin, ShiftConfig, ShiftDirection,
+    StateMachine,
+};
+use embassy_time::{Duration, Ticker, Timer, with_timeout};
+use fixed::FixedU32;
+use fixed::types::extra::U8;
+
+use crate::{SetupPacket, UsbHostBus, UsbHostError, sys_clock_hz};
+
+/// Largest data payload of a full-speed control or interrupt packet
+pub const PIO_USB_MAX_PACKET: usize = 64;
+
+/// PIO cycles (and sampler samples) per bit time
+const CYCLES_PER_BIT: u32 = 4;
+const FULL_SPEED_HZ: u32 = 12_000_000;
+const LOW_SPEED_HZ: u32 = 1_500_000;
+/// Line states as the transmitter shifts them out and the sampler records them: bit 0 is
+/// D+, bit 1 is D-
+const SE0: u32 = 0b00;
+/// Sampler words per capture: 16 samples (4 bit times) each, enough for an IN token, a
+/// full-size data packet and the ACK
+const CAPTURE_WORDS: usize = 256;
+/// Transmit words: a SETUP token and its 8-byte data packet, plus the response words
+const TX_WORDS: usize = 24;
+/// Bit times of idle between two packets the host sends back to back
+const INTER_PACKET_GAP: usize = 4;
+/// Bit times from the start of a device packet after which it can't be a handshake
+/// (16 bit times) and must be data (at least 32), in turns of the 5-cycle check loop
+const DATA_THRESHOLD_LOOPS: u32 = 24 * CYCLES_PER_BIT / 5;
+/// How long a transaction may take before the port is considered dead
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(5);
+/// NAKs tolerated in a control transfer, one frame apart
+const CONTROL_NAK_RETRIES: u16 = 500;
+/// Attach debounce: the line must show a device for this many 10 ms polls
+const ATTACH_POLLS: u8 = 10;
+
+const PID_OUT: u8 = 0xe1;
+const PID_IN: u8 = 0x69;
+const PID_SOF: u8 = 0xa5;
+const PID_SETUP: u8 = 0x2d;
+const PID_DATA0: u8 = 0xc3;
+const PID_DATA1: u8 = 0x4b;
+const PID_ACK: u8 = 0xd2;
+const PID_NAK: u8 = 0x5a;
+const PID_STALL: u8 = 0x1e;
+const SYNC: u8 = 0x80;
+const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum UsbSpeed {
+    Low,
+    Full,
+}
+
+impl UsbSpeed {
+    fn bit_rate(self) -> u32 {
+        match self {
+            Self::Low => LOW_SPEED_HZ,
+            Self::Full => FULL_SPEED_HZ,
+        }
+    }
+
+    /// Idle state: D+ high at full speed, D- high at low speed
+    fn j(self) -> u32 {
+        match self {
+            Self::Low => 0b10,
+            Self::Full => 0b01,
+        }
+    }
+}
+
+/// What a device answered to a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Response {
+    Ack,
+    Nak,
+    Data { data1: bool, len: usize },
+}
+
+/// USB host port on D+/D- (consecutive GPIOs) driven by two state machines of one PIO block
+pub struct PioUsbBus<'d, P: Instance, T: dma::Channel, R: dma::Channel> {
+    tx: StateMachine<'d, P, 0>,
+    rx: StateMachine<'d, P, 1>,
+    tx_dma: Peri<'d, T>,
+    rx_dma: Peri<'d, R>,
+    tx_program: LoadedProgram<'d, P>,
+    rx_program: LoadedProgram<'d, P>,
+    /// `irq clear 4`, run on the sampler before each transaction
+    clear_start: u16,
+    pins: [Pin<'d, P>; 2],
+    speed: UsbSpeed,
+    frame: u16,
+    /// DATA1 expected next, one bit per interrupt IN endpoint
+    in_toggles: u16,
+    samples: [u32; CAPTURE_WORDS],
+}
+
+impl<'d, P: Instance, T: dma::Channel, R: dma::Channel> PioUsbBus<'d, P, T, R> {
+    /// `pins` are D+ and D- (on the next GPIO), created with `common.make_pio_pin`.
+    pub fn new(
+        common: &mut Common<'d, P>,
+        tx: StateMachine<'d, P, 0>,
+        rx: StateMachine<'d, P, 1>,
+        tx_dma: Peri<'d, T>,
+        rx_dma: Peri<'d, R>,
+        mut pins: [Pin<'d, P>; 2],
+    ) -> Result<Self, UsbHostError> {
+        if pins[1].pin() != pins[0].pin() + 1 {
+            return Err(UsbHostError::InvalidPins);
+        }
+        for pin in &mut pins {
+            pin.set_pull(Pull::Down);
+        }
+
+        // Transmit the packets, then (if the last word says so) wait for the device's
+        // packet. One that is still going after the handshake length is data: wait for its
+        // EOP and send the ACK that follows in the FIFO; otherwise drop the ACK words.
+        // 4 cycles per bit time; `mov isr, null` + `in pins, 2` + `mov x, isr` reads the
+        // line state into x.
+        let tx_program = embassy_rp::pio::program::pio_asm!(
+            ".wrap_target",
+            "start:",
+            "    out x, 32",
+            "    set pindirs, 3",
+            "    irq nowait 4",
+            "send:",
+            "    out pins, 2 [2]",
+            "    jmp x-- send",
+            "    set pindirs, 0",
+            "    out y, 32",
+            "    jmp !y start",
+            "idle:",
+            "    mov isr, null",
+            "    in pins, 2",
+            "    mov x, isr",
+            "    jmp x!=y response",
+            "    jmp idle",
+            "response:",
+            "    out y, 32",
+            "short:",
+            "    mov isr, null",
+            "    in pins, 2",
+            "    mov x, isr",
+            "    jmp !x skip_ack",
+            "    jmp y-- short",
+            "data:",
+            "    mov isr, null",
+            "    in pins, 2",
+            "    mov x, isr",
+            "    jmp x-- data",
+            "    out x, 32 [11]",
+            "    set pindirs, 3",
+            "    jmp send",
+            "skip_ack:",
+            "    set x, 3",
+            "skip:",
+            "    out null, 32",
+            "    jmp x-- skip",
+            ".wrap",
+        );
+        // Record the line from the moment the transmitter starts
+        let rx_program = embassy_rp::pio::program::pio_asm!(
+            "    wait 1 irq 4",
+            ".wrap_target",
+            "    in pins, 2",
+            ".wrap",
+        );
+        let clear_start = embassy_rp::pio::program::pio_asm!("irq clear 4")
+            .program
+            .code[0];
+
+        let mut bus = Self {
+            tx,
+            rx,
+            tx_dma,
+            rx_dma,
+            tx_program: common.load_program(&tx_program.program),
+            rx_program: common.load_program(&rx_program.program),
+            clear_start,
+            pins,
+            speed: UsbSpeed::Full,
+            frame: 0,
+            in_toggles: 0,
+            samples: [0; CAPTURE_WORDS],
+        };
+        bus.configure(UsbSpeed::Full);
+        bus.release_line();
+        Ok(bus)
+    }
+
+    /// Speed of the attached device, as detected by the last reset
+    pub fn speed(&self) -> UsbSpeed {
+        self.speed
+    }
+
+    fn configure(&mut self, speed: UsbSpeed) {
+        self.speed = speed;
+        let divider = FixedU32::<U8>::from_bits(divider_bits(sys_clock_hz(), speed.bit_rate()));
+        let [dp, dm] = &self.pins;
+
+        let mut cfg = Config::default();
+        cfg.use_program(&self.tx_program, &[]);
+        cfg.clock_divider = divider;
+        cfg.set_out_pins(&[dp, dm]);
+        cfg.set_set_pins(&[dp, dm]);
+        cfg.set_in_pins(&[dp, dm]);
+        cfg.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 32,
+            direction: ShiftDirection::Right,
+        };
+        cfg.shift_in = ShiftConfig {
+            auto_fill: false,
+            threshold: 32,
+            direction: ShiftDirection::Left,
+        };
+        cfg.fifo_join = FifoJoin::TxOnly;
+        self.tx.set_config(&cfg);
+
+        let mut cfg = Config::default();
+        cfg.use_program(&self.rx_program, &[]);
+        cfg.clock_divider = divider;
+        cfg.set_in_pins(&[dp, dm]);
+        cfg.shift_in = ShiftConfig {
+            auto_fill: true,
+            threshold: 32,
+            direction: ShiftDirection::Left,
+        };
+        cfg.fifo_join = FifoJoin::RxOnly;
+        self.rx.set_config(&cfg);
+    }
+
+    /// Stop driving the line; the transmitter drives J when it starts again.
+    fn release_line(&mut self) {
+        let [dp, dm] = &self.pins;
+        let (j_high, j_low) = match self.speed {
+            UsbSpeed::Full => (dp, dm),
+            UsbSpeed::Low => (dm, dp),
+        };
+        self.tx.set_pins(Level::High, &[j_high]);
+        self.tx.set_pins(Level::Low, &[j_low]);
+        self.tx.set_pin_dirs(Direction::In, &[dp, dm]);
+    }
+
+    /// Current D+/D- levels
+    fn line_state(&self) -> u32 {
+        let levels = pac::SIO.gpio_in(0).read();
+        (levels >> self.pins[0].pin()) & 0b11
+    }
+
+    /// Send `tx` from the start of both programs, recording `capture` sampler words.
+    async fn run(&mut self, tx: &TxStream, capture: usize) -> Result<(), UsbHostError> {
+        self.rx.set_enable(false);
+        self.rx.clear_fifos();
+        self.rx.restart();
+        // SAFETY: clears the start flag and jumps to the sampler loaded in `new`
+        unsafe {
+            self.rx.exec_instr(self.clear_start);
+            self.rx.exec_jmp(self.rx_program.origin);
+        }
+        self.rx.set_enable(true);
+
+        self.tx.set_enable(false);
+        self.tx.clear_fifos();
+        self.tx.restart();
+        // SAFETY: jumps to the start of the transmitter loaded in `new`
+        unsafe { self.tx.exec_jmp(self.tx_program.origin) };
+        self.tx.set_enable(true);
+
+        if capture == 0 {
+            self.tx
+                .tx()
+                .dma_push(self.tx_dma.reborrow(), tx.words(), false)
+                .await;
+            // Let the last words go out before the next transaction restarts the program
+            while !self.tx.tx().empty() {
+                yield_now().await;
+            }
+            return Ok(());
+        }
+        let samples = &mut self.samples[..capture];
+        let recording = self
+            .rx
+            .rx()
+            .dma_pull(self.rx_dma.reborrow(), samples, false);
+        self.tx
+            .tx()
+            .dma_push(self.tx_dma.reborrow(), tx.words(), false)
+            .await;
+        with_timeout(CAPTURE_TIMEOUT, recording)
+            .await
+            .map_err(|_| UsbHostError::Timeout)
+    }
+
+    /// Send `packets` and decode the device's reply into `reply` (PID first).
+    async fn transaction(
+        &mut self,
+        packets: &[&[u8]],
+        reply: &mut [u8],
+    ) -> Result<Response, UsbHostError> {
+        let tx = TxStream::transaction(self.speed, packets, true);
+        let capture = capture_words(tx.bit_times(), reply.len());
+        self.run(&tx, capture).await?;
+        // The recording starts with the host's own packets
+        let len = decode_packet(
+            &self.samples[..capture],
+            self.speed.j(),
+            packets.len(),
+            reply,
+        )?;
+        parse_response(&reply[..len])
+    }
+
+    /// IN transaction; the payload goes to `buf` (truncated to its length).
+    async fn data_in(
+        &mut self,
+        address: u8,
+        endpoint: u8,
+        buf: &mut [u8],
+    ) -> Result<Response, UsbHostError> {
+        let token = token(PID_IN, address, endpoint);
+        let mut reply = [0u8; PIO_USB_MAX_PACKET + 3];
+        match self.transaction(&[&token], &mut reply).await? {
+            Response::Data { data1, len } => {
+                let len = len.min(buf.len());
+                buf[..len].copy_from_slice(&reply[1..1 + len]);
+                Ok(Response::Data { data1, len })
+            }
+            Response::Nak => Ok(Response::Nak),
+            Response::Ack => Err(UsbHostError::Protocol),
+        }
+    }
+
+    /// SETUP or OUT transaction with its data packet, repeated while the device NAKs.
+    async fn data_out(
+        &mut self,
+        pid: u8,
+        address: u8,
+        data_pid: u8,
+        payload: &[u8],
+    ) -> Result<(), UsbHostError> {
+        let token = token(pid, address, 0);
+        let mut data = [0u8; 8 + 3];
+        let data = data_packet(data_pid, payload, &mut data);
+        for _ in 0..CONTROL_NAK_RETRIES {
+            let mut reply = [0u8; 3];
+            match self.transaction(&[&token, data], &mut reply).await? {
+                Response::Ack => return Ok(()),
+                Response::Nak => self.wait_frames(1).await,
+                Response::Data { .. } => return Err(UsbHostError::Protocol),
+            }
+        }
+        Err(UsbHostError::Timeout)
+    }
+
+    /// IN transactions on endpoint 0 until a packet with `data1` arrives; returns its
+    /// length.
+    async fn control_data_in(
+        &mut self,
+        address: u8,
+        data1: bool,
+        buf: &mut [u8],
+    ) -> Result<usize, UsbHostError> {
+        for _ in 0..CONTROL_NAK_RETRIES {
+            match self.data_in(address, 0, buf).await? {
+                Response::Data { data1: got, len } if got == data1 => return Ok(len),
+                // A repeat of the previous packet: our ACK got lost
+                Response::Data { .. } => {}
+                _ => self.wait_frames(1).await,
+            }
+        }
+        Err(UsbHostError::Timeout)
+    }
+
+    /// SOF with the next frame number, or a keep-alive EOP at low speed
+    async fn keep_alive(&mut self) -> Result<(), UsbHostError> {
+        let tx = match self.speed {
+            UsbSpeed::Full => {
+                self.frame = (self.frame + 1) & 0x7ff;
+                let [lo, hi] = (self.frame | (crc5(self.frame) as u16) << 11).to_le_bytes();
+                TxStream::transaction(self.speed, &[&[PID_SOF, lo, hi]], false)
+            }
+            UsbSpeed::Low => TxStream::keep_alive(),
+        };
+        self.run(&tx, 0).await
+    }
+}
+
+impl<P: Instance, T: dma::Channel, R: dma::Channel> UsbHostBus for PioUsbBus<'_, P, T, R> {
+    async fn reset(&mut self) -> Result<(), UsbHostError> {
+        self.tx.set_enable(false);
+        self.rx.set_enable(false);
+        self.in_toggles = 0;
+        self.release_line();
+
+        // A device pulls D+ (full speed) or D- (low speed) up
+        let mut polls = 0;
+        while polls < ATTACH_POLLS {
+            Timer::after_millis(10).await;
+            polls = if self.line_state() == SE0 {
+                0
+            } else {
+                polls + 1
+            };
+        }
+        let speed = if self.line_state() & 0b01 != 0 {
+            UsbSpeed::Full
+        } else {
+            UsbSpeed::Low
+        };
+        if speed == UsbSpeed::Full && sys_clock_hz() % (FULL_SPEED_HZ * CYCLES_PER_BIT) != 0 {
+            return Err(UsbHostError::UnsupportedClock);
+        }
+        self.configure(speed);
+
+        let [dp, dm] = &self.pins;
+        self.tx.set_pins(Level::Low, &[dp, dm]);
+        self.tx.set_pin_dirs(Direction::Out, &[dp, dm]);
+        Timer::after_millis(20).await;
+        self.release_line();
+        // Reset recovery, with frames so the device doesn't suspend
+        self.wait_frames(10).await;
+        if self.line_state() == SE0 {
+            return Err(UsbHostError::NoDevice);
+        }
+        Ok(())
+    }
+
+    async fn control_in(
+        &mut self,
+        address: u8,
+        max_packet: u8,
+        setup: &SetupPacket,
+        buf: &mut [u8],
+    ) -> Result<usize, UsbHostError> {
+        self.data_out(PID_SETUP, address, PID_DATA0, &setup.to_bytes())
+            .await?;
+        let max_packet = (max_packet as usize).clamp(8, PIO_USB_MAX_PACKET);
+        let mut received = 0;
+        let mut data1 = true;
+        while received < buf.len() {
+            let len = self
+                .control_data_in(address, data1, &mut buf[received..])
+                .await?;
+            received += len;
+            data1 = !data1;
+            if len < max_packet {
+                break;
+            }
+        }
+        // Status stage: empty OUT
+        self.data_out(PID_OUT, address, PID_DATA1, &[]).await?;
+        Ok(received)
+    }
+
+    async fn control_out(
+        &mut self,
+        address: u8,
+        _max_packet: u8,
+        setup: &SetupPacket,
+    ) -> Result<(), UsbHostError> {
+        self.data_out(PID_SETUP, address, PID_DATA0, &setup.to_bytes())
+            .await?;
+        // Status stage: empty IN
+        self.control_data_in(address, true, &mut []).await?;
+        if setup.request_type == 0 && setup.request == REQUEST_SET_CONFIGURATION {
+            self.in_toggles = 0;
+        }
+        Ok(())
+    }
+
+    async fn interrupt_in(
+        &mut self,
+        address: u8,
+        endpoint: u8,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, UsbHostError> {
+        let bit = 1 << (endpoint & 0x0f);
+        match self.data_in(address, endpoint, buf).await? {
+            Response::Data { data1, len } if data1 == (self.in_toggles & bit != 0) => {
+                self.in_toggles ^= bit;
+                Ok(Some(len))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn wait_frames(&mut self, frames: u16) {
+        let mut ticker = Ticker::every(Duration::from_millis(1));
+        for _ in 0..frames {
+            ticker.next().await;
+            let _ = self.keep_alive().await;
+        }
+    }
+}
+
+/// Transmitter FIFO contents for one transaction: the bit count, the line states (two bits
+/// each, 16 per word), then the response words: the J state (0: no response expected),
+/// the data threshold, and the ACK to send after a data packet.
+struct TxStream {
+    words: [u32; TX_WORDS],
+    len: usize,
+}
+
+impl TxStream {
+    fn new() -> Self {
+        Self {
+            words: [0; TX_WORDS],
+            len: 0,
+        }
+    }
+
+    /// `packets` (PID first) back to back; `response` if the device answers the last one.
+    fn transaction(speed: UsbSpeed, packets: &[&[u8]], response: bool) -> Self {
+        let mut stream = Self::new();
+        let mut symbols = SymbolWriter::new(&mut stream, speed);
+        for (i, packet) in packets.iter().enumerate() {
+            if i > 0 {
+                symbols.idle(INTER_PACKET_GAP);
+            }
+            symbols.packet(packet);
+        }
+        symbols.finish();
+        if response {
+            stream.push(speed.j());
+            stream.push(DATA_THRESHOLD_LOOPS);
+            let mut ack = SymbolWriter::new(&mut stream, speed);
+            ack.idle(2);
+            ack.packet(&[PID_ACK]);
+            ack.finish();
+        }
+        stream.push(0);
+        stream
+    }
+
+    /// Low-speed keep-alive: an EOP on its own
+    fn keep_alive() -> Self {
+        let mut stream = Self::new();
+        let mut symbols = SymbolWriter::new(&mut stream, UsbSpeed::Low);
+        symbols.eop();
+        symbols.finish();
+        stream.push(0);
+        stream
+    }
+
+    fn push(&mut self, word: u32) {
+        self.words[self.len] = word;
+        self.len += 1;
+    }
+
+    fn words(&self) -> &[u32] {
+        &self.words[..self.len]
+    }
+
+    /// Bit times of the host's packets, from the first count word
+    fn bit_times(&self) -> usize {
+        self.words[0] as usize + 1
+    }
+}
+
+/// NRZI encoder with bit stuffing, appending one count word and its line states
+struct SymbolWriter<'a> {
+    stream: &'a mut TxStream,
+    speed: UsbSpeed,
+    /// Index of the count word
+    start: usize,
+    bits: usize,
+    level: u32,
+    ones: u8,
+}
+
+impl<'a> SymbolWriter<'a> {
+    fn new(stream: &'a mut TxStream, speed: UsbSpeed) -> Self {
+        let start = stream.len;
+        stream.push(0);
+        Self {
+            stream,
+            speed,
+            start,
+            bits: 0,
+            level: speed.j(),
+            ones: 0,
+        }
+    }
+
+    fn symbol(&mut self, state: u32) {
+        let word = self.start + 1 + self.bits / 16;
+        if word == self.stream.len {
+            self.stream.push(0);
+        }
+        self.stream.words[word] |= state << (2 * (self.bits % 16));
+        self.bits += 1;
+    }
+
+    fn idle(&mut self, bit_times: usize) {
+        for _ in 0..bit_times {
+            self.symbol(self.speed.j());
+        }
+        self.level = self.speed.j();
+    }
+
+    fn bit(&mut self, one: bool) {
+        if one {
+            self.ones += 1;
+        } else {
+            self.level ^= 0b11;
+            self.ones = 0;
+        }
+        self.symbol(self.level);
+        if self.ones == 6 {
+            self.level ^= 0b11;
+            self.ones = 0;
+            self.symbol(self.level);
+        }
+    }
+
+    /// SYNC, `bytes` (LSB first) and EOP
+    fn packet(&mut self, bytes: &[u8]) {
+        self.ones = 0;
+        for &byte in core::iter::once(&SYNC).chain(bytes) {
+            for i in 0..8 {
+                self.bit((byte >> i) & 1 != 0);
+            }
+        }
+        self.eop();
+    }
+
+    fn eop(&mut self) {
+        self.symbol(SE0);
+        self.symbol(SE0);
+        self.idle(1);
+    }
+
+    /// Pad to whole words with idle and store the count.
+    fn finish(mut self) {
+        while !self.bits.is_multiple_of(16) {
+            self.symbol(self.speed.j());
+        }
+        self.stream.words[self.start] = self.bits as u32 - 1;
+    }
+}
+
+/// Token packet (PID, 7-bit address, 4-bit endpoint, CRC5)
+fn token(pid: u8, address: u8, endpoint: u8) -> [u8; 3] {
+    let fields = (address & 0x7f) as u16 | ((endpoint & 0x0f) as u16) << 7;
+    let [lo, hi] = (fields | (crc5(fields) as u16) << 11).to_le_bytes();
+    [pid, lo, hi]
+}
+
+/// Data packet (PID, payload, CRC16) in `buf`
+fn data_packet<'b>(pid: u8, payload: &[u8], buf: &'b mut [u8]) -> &'b [u8] {
+    let len = payload.len();
+    buf[0] = pid;
+    buf[1..1 + len].copy_from_slice(payload);
+    buf[1 + len..3 + len].copy_from_slice(&crc16_usb(payload).to_le_bytes());
+    &buf[..3 + len]
+}
+
+fn parse_response(packet: &[u8]) -> Result<Response, UsbHostError> {
+    let (&pid, rest) = packet.split_first().ok_or(UsbHostError::Protocol)?;
+    if pid & 0x0f != (!pid) >> 4 {
+        return Err(UsbHostError::Protocol);
+    }
+    match pid {
+        PID_ACK => Ok(Response::Ack),
+        PID_NAK => Ok(Response::Nak),
+        PID_STALL => Err(UsbHostError::Stall),
+        PID_DATA0 | PID_DATA1 => {
+            let (payload, crc) = rest
+                .split_at_checked(rest.len().wrapping_sub(2))
+                .ok_or(UsbHostError::Protocol)?;
+            if crc16_usb(payload).to_le_bytes() != crc {
+                return Err(UsbHostError::Protocol);
+            }
+            Ok(Response::Data {
+                data1: pid == PID_DATA1,
+                len: payload.len(),
+            })
+        }
+        _ => Err(UsbHostError::Protocol),
+    }
+}
+
+/// Sampler words needed to record `tx_bits` of host packets, the turnaround, a reply of
+/// up to `reply_len` bytes (bit-stuffed in the worst case) and the ACK
+fn capture_words(tx_bits: usize, reply_len: usize) -> usize {
+    let reply_bits = (8 + reply_len * 8) * 7 / 6 + 3;
+    let bits = tx_bits + 16 + reply_bits + 32;
+    (bits.div_ceil(CYCLES_PER_BIT as usize)).min(CAPTURE_WORDS)
+}
+
+/// Decode packet number `index` of a recording into `out` (PID first, SYNC dropped) and
+/// return its length. Runs of equal samples are rounded to whole bit times, so every edge
+/// resynchronizes the decoder; a single SE0 sample at a crossover is not an EOP.
+fn decode_packet(
+    words: &[u32],
+    j: u32,
+    index: usize,
+    out: &mut [u8],
+) -> Result<usize, UsbHostError> {
+    let samples = words
+        .iter()
+        .flat_map(|&word| (0..16u32).rev().map(move |i| (word >> (2 * i)) & 0b11));
+    let mut bits = BitCollector::new(out);
+    let mut packet = 0;
+    let mut in_packet = false;
+    let mut level = j;
+    let mut run = 0;
+    let mut se0 = 0;
+    for sample in samples {
+        if !in_packet {
+            // SYNC starts with K
+            if sample != j && sample != SE0 {
+                in_packet = true;
+                level = sample;
+                run = 1;
+                se0 = 0;
+            }
+            continue;
+        }
+        let decoding = packet == index;
+        if sample == SE0 {
+            se0 += 1;
+            if se0 < 2 {
+                run += 1;
+                continue;
+            }
+            // EOP; the last run ended where the SE0 started
+            if decoding {
+                bits.run(run - 1)?;
+                return Ok(bits.len());
+            }
+            packet += 1;
+            in_packet = false;
+            continue;
+        }
+        se0 = 0;
+        if sample == level {
+            run += 1;
+            continue;
+        }
+        if decoding {
+            bits.run(run)?;
+        }
+        level = sample;
+        run = 1;
+    }
+    Err(UsbHostError::Timeout)
+}
+
+/// Turns NRZI runs into unstuffed bytes, checking and dropping the SYNC
+struct BitCollector<'b> {
+    out: &'b mut [u8],
+    bits: usize,
+    ones: u8,
+}
+
+impl<'b> BitCollector<'b> {
+    fn new(out: &'b mut [u8]) -> Self {
+        out.fill(0);
+        Self {
+            out,
+            bits: 0,
+            ones: 0,
+        }
+    }
+
+    /// A run of `samples` at one level: a transition (0) followed by ones
+    fn run(&mut self, samples: usize) -> Result<(), UsbHostError> {
+        let per_bit = CYCLES_PER_BIT as usize;
+        let bit_times = ((samples + per_bit / 2) / per_bit).max(1);
+        for i in 0..bit_times {
+            let one = i > 0;
+            if self.ones == 6 {
+                if one {
+                    return Err(UsbHostError::Protocol);
+                }
+                // Stuffed zero
+                self.ones = 0;
+                continue;
+            }
+            self.ones = if one { self.ones + 1 } else { 0 };
+            self.push(one)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, one: bool) -> Result<(), UsbHostError> {
+        let bit = self.bits;
+        self.bits += 1;
+        if bit < 8 {
+            // SYNC: seven zeros and a one
+            return if one == (bit == 7) {
+                Ok(())
+            } else {
+                Err(UsbHostError::Protocol)
+            };
+        }
+        let byte = self
+            .out
+            .get_mut((bit - 8) / 8)
+            .ok_or(UsbHostError::Protocol)?;
+        *byte |= (one as u8) << ((bit - 8) % 8);
+        Ok(())
+    }
+
+    /// Whole bytes after the SYNC
+    fn len(&self) -> usize {
+        self.bits.saturating_sub(8) / 8
+    }
+}
+
+/// CRC5 of an 11-bit token field (address and endpoint, or frame number)
+fn crc5(fields: u16) -> u8 {
+    let mut crc = 0x1f;
+    for i in 0..11 {
+        crc = if (crc ^ (fields >> i) as u8) & 1 != 0 {
+            (crc >> 1) ^ 0x14
+        } else {
+            crc >> 1
+        };
+    }
+    !crc & 0x1f
+}
+
+/// CRC16 of a data packet payload (CRC-16/USB, sent low byte first)
+fn crc16_usb(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// PIO clock divider for `CYCLES_PER_BIT` cycles per bit at `bit_rate`, 8 fractional bits
+fn divider_bits(sys_hz: u32, bit_rate: u32) -> u32 {
+    let pio_hz = (bit_rate * CYCLES_PER_BIT) as u64;
+    ((sys_hz as u64 * 256 + pio_hz / 2) / pio_hz).max(256) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the sampler would record for the transmitter's line states: four samples per
+    /// bit time, with every eighth bit `skew` samples longer (a device clock off by ~3%)
+    fn record(stream: &TxStream, count_word: usize, skew: isize) -> [u32; CAPTURE_WORDS] {
+        let bits = stream.words[count_word] as usize + 1;
+        let mut words = [0u32; CAPTURE_WORDS];
+        let mut n = 0;
+        for bit in 0..bits {
+            let state = (stream.words[count_word + 1 + bit / 16] >> (2 * (bit % 16))) & 0b11;
+            let samples = if bit % 8 == 7 { 4 + skew } else { 4 };
+            for _ in 0..samples {
+                words[n / 16] |= state << (30 - 2 * (n % 16));
+                n += 1;
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn crc_vectors() {
+        // Token CRC5s from the USB CRC application note, in transmission order reversed
+        assert_eq!(crc5(0x15 | 0x0e << 7), 0b11101);
+        assert_eq!(crc5(0x3a | 0x0a << 7), 0b00111);
+        assert_eq!(token(PID_SETUP, 0, 0), [0x2d, 0x00, 0x10]);
+        assert_eq!(crc16_usb(&[0x00, 0x01, 0x02, 0x03]), 0x7aef);
+        assert_eq!(crc16_usb(&[0x23, 0x45, 0x67, 0x89]), 0x1c0e);
+        let setup = [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x40, 0x00];
+        assert_eq!(crc16_usb(&setup).to_le_bytes(), [0xdd, 0x94]);
+    }
+
+    #[test]
+    fn encodes_sync_and_eop() {
+        let stream = TxStream::transaction(UsbSpeed::Full, &[&[PID_ACK]], false);
+        // SYNC KJKJKJKK, then ACK 0 1 0 0 1 0 1 1 as NRZI, SE0 SE0 J
+        let (j, k) = (0b01, 0b10);
+        let expected = [k, j, k, j, k, j, k, k, j, j, k, j, j, k, k, k, SE0, SE0, j];
+        assert_eq!(stream.bit_times(), 32);
+        for (bit, &state) in expected.iter().enumerate() {
+            assert_eq!(
+                (stream.words[1 + bit / 16] >> (2 * (bit % 16))) & 0b11,
+                state
+            );
+        }
+        // Padded with J; no response words
+        assert_eq!(stream.words[2] >> 6, 0x5555_5555 >> 6);
+        assert_eq!(stream.words().len(), 4);
+        assert_eq!(stream.words[3], 0);
+    }
+
+    #[test]
+    fn round_trips_through_the_line() {
+        let mut buf = [0u8; 11];
+        let payload = [0xff, 0xff, 0x00, 0x7e, 0x81, 0xff, 0x3f, 0x55];
+        let data = data_packet(PID_DATA1, &payload, &mut buf);
+        let token = token(PID_IN, 5, 1);
+        for speed in [UsbSpeed::Full, UsbSpeed::Low] {
+            let stream = TxStream::transaction(speed, &[&token, data], true);
+            // A slightly fast and a slightly slow device clock still decode
+            for skew in [0, -1, 1] {
+                let words = record(&stream, 0, skew);
+                let mut out = [0u8; 16];
+                assert_eq!(decode_packet(&words, speed.j(), 0, &mut out), Ok(3));
+                assert_eq!(&out[..3], &token);
+                assert_eq!(decode_packet(&words, speed.j(), 1, &mut out), Ok(11));
+                assert_eq!(&out[..11], data);
+                assert_eq!(
+                    parse_response(&out[..11]),
+                    Ok(Response::Data {
+                        data1: true,
+                        len: 8
+                    })
+                );
+                assert_eq!(
+                    decode_packet(&words, speed.j(), 2, &mut out),
+                    Err(UsbHostError::Timeout)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn appends_response_words() {
+        let stream = TxStream::transaction(UsbSpeed::Full, &[&token(PID_IN, 1, 1)], true);
+        let words = stream.words();
+        // count, 3 words of token, J, threshold, ACK count, 2 ACK words, final 0
+        assert_eq!(words.len(), 10);
+        assert_eq!(&words[4..6], &[0b01, DATA_THRESHOLD_LOOPS]);
+        assert_eq!(words[6], 31);
+        assert_eq!(words[9], 0);
+
+        let ack = record(&stream, 6, 0);
+        let mut out = [0u8; 4];
+        assert_eq!(decode_packet(&ack, 0b01, 0, &mut out), Ok(1));
+        assert_eq!(parse_response(&out[..1]), Ok(Response::Ack));
+    }
+
+    #[test]
+    fn rejects_bad_packets() {
+        assert_eq!(parse_response(&[0x5a]), Ok(Response::Nak));
+        assert_eq!(parse_response(&[0x1e]), Err(UsbHostError::Stall));
+        assert_eq!(parse_response(&[0x5b]), Err(UsbHostError::Protocol));
+        assert_eq!(
+            parse_response(&[PID_DATA0, 1, 2, 3]),
+            Err(UsbHostError::Protocol)
+        );
+        assert_eq!(
+            parse_response(&[PID_DATA0, 0x00, 0x00]),
+            Ok(Response::Data {
+                data1: false,
+                len: 0
+            })
+        );
+    }
+
+    #[test]
+    fn divider_for_both_speeds() {
+        assert_eq!(divider_bits(48_000_000, FULL_SPEED_HZ), 256);
+        assert_eq!(divider_bits(144_000_000, FULL_SPEED_HZ), 3 << 8);
+        assert_eq!(divider_bits(125_000_000, LOW_SPEED_HZ), 5333);
+    }
+}
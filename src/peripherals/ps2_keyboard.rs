@@ -0,0 +1,403 @@
+//! ps2_keyboard.rs — PS/2 keyboard input on two GPIOs (scancode set 2)
+//!
+//! The keyboard clocks out 11-bit frames (start, 8 data bits LSB first, odd parity, stop)
+//! at 10–17 kHz; the driver samples DATA on each falling CLOCK edge, so both lines need
+//! pull-ups (the internal ones work on short cables) and 5 V keyboards need level
+//! shifting. Scancodes are decoded to [`KeyEvent`]s with HID usage codes, ready for
+//! [`HidKeyboardState`](crate::HidKeyboardState) to bridge the keyboard to USB. Commands
+//! to the keyboard (lock LEDs) drive the lines open-drain.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut keyboard = Ps2Keyboard::new(Flex::new(p.PIN_16), Flex::new(p.PIN_17));
+//!
+//! loop {
+//!     let c = keyboard.wait_for_key().await;
+//!     info!("typed {}", c);
+//! }
+//! ```
+
+use embassy_rp::gpio::{Flex, Pull};
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::{
+    HID_KEY_CAPS_LOCK, HID_KEY_LEFT_CTRL, HID_MODIFIER_SHIFT, KeyEvent, KeyInput, usage_to_char,
+};
+
+/// Longest gap between two clock edges within a frame
+const BIT_TIMEOUT: Duration = Duration::from_millis(2);
+/// Time for the keyboard to start clocking after a request to send, plus the frame
+const SEND_TIMEOUT: Duration = Duration::from_millis(25);
+/// Time for the keyboard to answer a command
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(25);
+
+const CMD_SET_LEDS: u8 = 0xed;
+const RESPONSE_ACK: u8 = 0xfa;
+const PREFIX_EXTENDED: u8 = 0xe0;
+const PREFIX_PAUSE: u8 = 0xe1;
+const PREFIX_RELEASE: u8 = 0xf0;
+const HID_KEY_PAUSE: u8 = 0x48;
+
+const LED_SCROLL_LOCK: u8 = 0x01;
+const LED_NUM_LOCK: u8 = 0x02;
+const LED_CAPS_LOCK: u8 = 0x04;
+
+/// Scancode set 2 (index) to HID usage, 0 = unmapped
+#[rustfmt::skip]
+const SET2_TO_HID: [u8; 0x84] = [
+    0x00, 0x42, 0x00, 0x3e, 0x3c, 0x3a, 0x3b, 0x45, 0x00, 0x43, 0x41, 0x3f,
+    0x3d, 0x2b, 0x35, 0x00, 0x00, 0xe2, 0xe1, 0x00, 0xe0, 0x14, 0x1e, 0x00,
+    0x00, 0x00, 0x1d, 0x16, 0x04, 0x1a, 0x1f, 0x00, 0x00, 0x06, 0x1b, 0x07,
+    0x08, 0x21, 0x20, 0x00, 0x00, 0x2c, 0x19, 0x09, 0x17, 0x15, 0x22, 0x00,
+    0x00, 0x11, 0x05, 0x0b, 0x0a, 0x1c, 0x23, 0x00, 0x00, 0x00, 0x10, 0x0d,
+    0x18, 0x24, 0x25, 0x00, 0x00, 0x36, 0x0e, 0x0c, 0x12, 0x27, 0x26, 0x00,
+    0x00, 0x37, 0x38, 0x0f, 0x33, 0x13, 0x2d, 0x00, 0x00, 0x00, 0x34, 0x00,
+    0x2f, 0x2e, 0x00, 0x00, 0x39, 0xe5, 0x28, 0x30, 0x00, 0x31, 0x00, 0x00,
+    0x00, 0x64, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x59, 0x00, 0x5c,
+    0x5f, 0x00, 0x00, 0x00, 0x62, 0x63, 0x5a, 0x5d, 0x5e, 0x60, 0x29, 0x53,
+    0x44, 0x57, 0x5b, 0x56, 0x55, 0x61, 0x47, 0x00, 0x00, 0x00, 0x00, 0x40,
+];
+
+/// Extended (`E0`-prefixed) set 2 scancodes to HID usage
+const SET2_EXTENDED_TO_HID: &[(u8, u8)] = &[
+    (0x11, 0xe6), // right alt
+    (0x14, 0xe4), // right ctrl
+    (0x1f, 0xe3), // left GUI
+    (0x27, 0xe7), // right GUI
+    (0x2f, 0x65), // menu
+    (0x4a, 0x54), // keypad /
+    (0x5a, 0x58), // keypad enter
+    (0x69, 0x4d), // end
+    (0x6b, 0x50), // left
+    (0x6c, 0x4a), // home
+    (0x70, 0x49), // insert
+    (0x71, 0x4c), // delete
+    (0x72, 0x51), // down
+    (0x74, 0x4f), // right
+    (0x75, 0x52), // up
+    (0x7a, 0x4e), // page down
+    (0x7c, 0x46), // print screen (its E0 12 prefix maps to nothing)
+    (0x7d, 0x4b), // page up
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum Ps2Error {
+    #[error("PS/2 frame has a bad start or stop bit")]
+    Framing,
+    #[error("PS/2 frame failed the parity check")]
+    Parity,
+    #[error("PS/2 keyboard stopped clocking mid-frame")]
+    Timeout,
+    #[error("PS/2 keyboard did not acknowledge")]
+    NoAck,
+}
+
+pub struct Ps2Keyboard<'d> {
+    clock: Flex<'d>,
+    data: Flex<'d>,
+    decoder: Set2Decoder,
+    modifiers: u8,
+    caps_lock: bool,
+}
+
+impl<'d> Ps2Keyboard<'d> {
+    pub fn new(mut clock: Flex<'d>, mut data: Flex<'d>) -> Self {
+        for pin in [&mut clock, &mut data] {
+            pin.set_pull(Pull::Up);
+            pin.set_as_input();
+        }
+        Self {
+            clock,
+            data,
+            decoder: Set2Decoder::default(),
+            modifiers: 0,
+            caps_lock: false,
+        }
+    }
+
+    /// Next raw byte from the keyboard.
+    pub async fn read_byte(&mut self) -> Result<u8, Ps2Error> {
+        self.clock.wait_for_falling_edge().await;
+        if self.data.is_high() {
+            return Err(Ps2Error::Framing);
+        }
+        let mut frame = 0u16;
+        for bit in 0..10 {
+            with_timeout(BIT_TIMEOUT, self.clock.wait_for_falling_edge())
+                .await
+                .map_err(|_| Ps2Error::Timeout)?;
+            if self.data.is_high() {
+                frame |= 1 << bit;
+            }
+        }
+        decode_frame(frame)
+    }
+
+    /// Next key press or release. A garbled frame is returned as an error; the next call
+    /// resynchronizes on the following frame.
+    pub async fn next_event(&mut self) -> Result<KeyEvent, Ps2Error> {
+        loop {
+            let byte = match self.read_byte().await {
+                Ok(byte) => byte,
+                Err(e) => {
+                    self.decoder = Set2Decoder::default();
+                    return Err(e);
+                }
+            };
+            if let Some(event) = self.decoder.feed(byte) {
+                if event.is_modifier() {
+                    let bit = 1 << (event.usage - HID_KEY_LEFT_CTRL);
+                    if event.pressed {
+                        self.modifiers |= bit;
+                    } else {
+                        self.modifiers &= !bit;
+                    }
+                }
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Modifier keys held, as HID boot-report bits
+    pub fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Set the lock LEDs.
+    pub async fn set_leds(
+        &mut self,
+        scroll_lock: bool,
+        num_lock: bool,
+        caps_lock: bool,
+    ) -> Result<(), Ps2Error> {
+        let mut leds = 0;
+        if scroll_lock {
+            leds |= LED_SCROLL_LOCK;
+        }
+        if num_lock {
+            leds |= LED_NUM_LOCK;
+        }
+        if caps_lock {
+            leds |= LED_CAPS_LOCK;
+        }
+        self.command(CMD_SET_LEDS).await?;
+        self.command(leds).await
+    }
+
+    /// Send one command or argument byte and wait for the keyboard's ACK.
+    pub async fn command(&mut self, byte: u8) -> Result<(), Ps2Error> {
+        let sent = with_timeout(SEND_TIMEOUT, self.write_byte(byte)).await;
+        // Whatever happened, leave both lines to the keyboard
+        self.clock.set_as_input();
+        self.data.set_as_input();
+        sent.map_err(|_| Ps2Error::Timeout)??;
+        let response = with_timeout(RESPONSE_TIMEOUT, self.read_byte())
+            .await
+            .map_err(|_| Ps2Error::Timeout)??;
+        if response == RESPONSE_ACK {
+            Ok(())
+        } else {
+            Err(Ps2Error::NoAck)
+        }
+    }
+
+    /// Host-to-device frame: the host sets each bit while the keyboard holds CLOCK low.
+    async fn write_byte(&mut self, byte: u8) -> Result<(), Ps2Error> {
+        // Request to send: inhibit for 100 µs, then start bit and release the clock
+        self.clock.set_low();
+        self.clock.set_as_output();
+        Timer::after_micros(120).await;
+        self.data.set_low();
+        self.data.set_as_output();
+        self.clock.set_as_input();
+
+        for bit in 0..10 {
+            self.clock.wait_for_falling_edge().await;
+            if (encode_frame(byte) >> bit) & 1 == 1 {
+                self.data.set_as_input();
+            } else {
+                self.data.set_as_output();
+            }
+        }
+        // The keyboard acknowledges by pulling DATA low for one clock
+        self.clock.wait_for_falling_edge().await;
+        let acked = self.data.is_low();
+        self.clock.wait_for_high().await;
+        self.data.wait_for_high().await;
+        if acked { Ok(()) } else { Err(Ps2Error::NoAck) }
+    }
+}
+
+impl KeyInput for Ps2Keyboard<'_> {
+    /// Characters on a US layout; Caps Lock toggles its LED.
+    async fn wait_for_key(&mut self) -> char {
+        loop {
+            let Ok(event) = self.next_event().await else {
+                continue;
+            };
+            if !event.pressed {
+                continue;
+            }
+            if event.usage == HID_KEY_CAPS_LOCK {
+                self.caps_lock = !self.caps_lock;
+                let _ = self.set_leds(false, false, self.caps_lock).await;
+                continue;
+            }
+            let shift = self.modifiers & HID_MODIFIER_SHIFT != 0;
+            if let Some(c) = usage_to_char(event.usage, shift, self.caps_lock) {
+                return c;
+            }
+        }
+    }
+}
+
+/// Data byte of a received frame: bits 0–7 data, 8 odd parity, 9 stop (start already
+/// checked)
+fn decode_frame(frame: u16) -> Result<u8, Ps2Error> {
+    if frame & (1 << 9) == 0 {
+        return Err(Ps2Error::Framing);
+    }
+    if (frame & 0x1ff).count_ones() % 2 != 1 {
+        return Err(Ps2Error::Parity);
+    }
+    Ok(frame as u8)
+}
+
+/// Bits after the start bit of a host-to-device frame
+fn encode_frame(byte: u8) -> u16 {
+    let parity = byte.count_ones().is_multiple_of(2) as u16;
+    byte as u16 | parity << 8 | 1 << 9
+}
+
+/// Scancode set 2 byte stream to key events
+#[derive(Debug, Clone, Copy, Default)]
+struct Set2Decoder {
+    extended: bool,
+    release: bool,
+    /// Bytes of the pause sequence (`E1 14 77 E1 F0 14 F0 77`) still to swallow
+    pause_remaining: u8,
+}
+
+impl Set2Decoder {
+    fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+            // Pause has no break code; release it at the end of its sequence
+            return (self.pause_remaining == 0).then_some(KeyEvent {
+                usage: HID_KEY_PAUSE,
+                pressed: false,
+            });
+        }
+        match byte {
+            PREFIX_EXTENDED => {
+                self.extended = true;
+                None
+            }
+            PREFIX_RELEASE => {
+                self.release = true;
+                None
+            }
+            PREFIX_PAUSE => {
+                self.pause_remaining = 7;
+                Some(KeyEvent {
+                    usage: HID_KEY_PAUSE,
+                    pressed: true,
+                })
+            }
+            _ => {
+                let usage = if self.extended {
+                    SET2_EXTENDED_TO_HID
+                        .iter()
+                        .find(|&&(code, _)| code == byte)
+                        .map_or(0, |&(_, usage)| usage)
+                } else {
+                    SET2_TO_HID.get(byte as usize).copied().unwrap_or(0)
+                };
+                let pressed = !self.release;
+                self.extended = false;
+                self.release = false;
+                // Unmapped codes include the self-test result (AA) and ACKs (FA)
+                (usage != 0).then_some(KeyEvent { usage, pressed })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(decoder: &mut Set2Decoder, bytes: &[u8]) -> [Option<KeyEvent>; 8] {
+        let mut events = [None; 8];
+        for (event, &byte) in events.iter_mut().zip(bytes) {
+            *event = decoder.feed(byte);
+        }
+        events
+    }
+
+    #[test]
+    fn frames() {
+        // 0x1c ('A'): three bits set, so the parity bit is 0
+        assert_eq!(decode_frame(0x1c | 1 << 9), Ok(0x1c));
+        assert_eq!(decode_frame(0x1c | 1 << 8 | 1 << 9), Err(Ps2Error::Parity));
+        assert_eq!(decode_frame(0x1c), Err(Ps2Error::Framing));
+        assert_eq!(decode_frame(encode_frame(0xed)), Ok(0xed));
+        assert_eq!(encode_frame(0x00), 1 << 8 | 1 << 9);
+    }
+
+    #[test]
+    fn decodes_make_and_break_codes() {
+        let mut decoder = Set2Decoder::default();
+        let events = feed_all(
+            &mut decoder,
+            &[0x1c, 0xf0, 0x1c, 0xe0, 0x75, 0xe0, 0xf0, 0x75],
+        );
+        let key = |usage, pressed| Some(KeyEvent { usage, pressed });
+        assert_eq!(
+            events,
+            [
+                key(0x04, true),
+                None,
+                key(0x04, false),
+                None,
+                key(0x52, true),
+                None,
+                None,
+                key(0x52, false),
+            ]
+        );
+        assert_eq!(decoder.feed(0xaa), None);
+    }
+
+    #[test]
+    fn decodes_pause() {
+        let mut decoder = Set2Decoder::default();
+        let events = feed_all(
+            &mut decoder,
+            &[0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77],
+        );
+        assert_eq!(
+            events[0],
+            Some(KeyEvent {
+                usage: HID_KEY_PAUSE,
+                pressed: true
+            })
+        );
+        assert!(events[1..7].iter().all(Option::is_none));
+        assert_eq!(
+            events[7],
+            Some(KeyEvent {
+                usage: HID_KEY_PAUSE,
+                pressed: false
+            })
+        );
+        assert_eq!(
+            decoder.feed(0x1c),
+            Some(KeyEvent {
+                usage: 0x04,
+                pressed: true
+            })
+        );
+    }
+}
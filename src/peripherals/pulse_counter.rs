@@ -0,0 +1,54 @@
+//! pulse_counter.rs — hardware pulse counting with an RP2040 PWM slice
+//!
+//! A PWM slice in input mode counts edges on its channel B pin without CPU involvement.
+//! The hardware counter is 16 bits wide, so [`PulseCounter::poll`] must run at least once
+//! per 65535 pulses; it extends the count to 64 bits.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pwm = Pwm::new_input(p.PWM_SLICE2, p.PIN_5, Pull::Up, InputMode::RisingEdge, Default::default());
+//! let mut counter = PulseCounter::new(pwm);
+//! Timer::after_secs(1).await;
+//! let hz = counter.poll();
+//! ```
+
+use embassy_rp::pwm::Pwm;
+
+pub struct PulseCounter<'d> {
+    pwm: Pwm<'d>,
+    last_raw: u16,
+    total: u64,
+}
+
+impl<'d> PulseCounter<'d> {
+    /// `pwm` must be created with `Pwm::new_input` (rising or falling edge mode).
+    pub fn new(pwm: Pwm<'d>) -> Self {
+        let last_raw = pwm.counter();
+        Self {
+            pwm,
+            last_raw,
+            total: 0,
+        }
+    }
+
+    /// Pulses counted since the previous poll.
+    pub fn poll(&mut self) -> u32 {
+        let raw = self.pwm.counter();
+        let delta = raw.wrapping_sub(self.last_raw) as u32;
+        self.last_raw = raw;
+        self.total += delta as u64;
+        delta
+    }
+
+    /// Pulses counted since creation or the last [`PulseCounter::reset`].
+    pub fn total(&mut self) -> u64 {
+        self.poll();
+        self.total
+    }
+
+    pub fn reset(&mut self) {
+        self.poll();
+        self.total = 0;
+    }
+}
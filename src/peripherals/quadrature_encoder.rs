@@ -0,0 +1,88 @@
+//! quadrature_encoder.rs — PIO-decoded quadrature encoder with a shared position counter
+//!
+//! A PIO state machine decodes the A/B signals, and [`QuadratureEncoder::run`] (in its own
+//! task) folds each step into an [`EncoderPosition`] that any task can read.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static POSITION: EncoderPosition = EncoderPosition::new();
+//!
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO1, Irqs);
+//! let program = PioEncoderProgram::new(&mut common);
+//! let encoder = PioEncoder::new(&mut common, sm0, p.PIN_10, p.PIN_11, &program);
+//! spawner.spawn(encoder_task(QuadratureEncoder::new(encoder, &POSITION)).expect("failed to spawn encoder_task"));
+//!
+//! #[embassy_executor::task]
+//! async fn encoder_task(mut encoder: QuadratureEncoder<'static, PIO1, 0>) -> ! {
+//!     encoder.run().await
+//! }
+//! ```
+
+use embassy_rp::pio::Instance;
+use embassy_rp::pio_programs::rotary_encoder::{Direction, PioEncoder};
+use portable_atomic::{AtomicI32, Ordering};
+
+/// Encoder count shared between the decoding task and its readers
+pub struct EncoderPosition {
+    count: AtomicI32,
+}
+
+impl EncoderPosition {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicI32::new(0),
+        }
+    }
+
+    pub fn get(&self) -> i32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, count: i32) {
+        self.count.store(count, Ordering::Relaxed);
+    }
+
+    fn step(&self, delta: i32) {
+        self.count.fetch_add(delta, Ordering::Relaxed);
+    }
+}
+
+impl Default for EncoderPosition {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct QuadratureEncoder<'d, T: Instance, const SM: usize> {
+    encoder: PioEncoder<'d, T, SM>,
+    position: &'d EncoderPosition,
+    reversed: bool,
+}
+
+impl<'d, T: Instance, const SM: usize> QuadratureEncoder<'d, T, SM> {
+    pub fn new(encoder: PioEncoder<'d, T, SM>, position: &'d EncoderPosition) -> Self {
+        Self {
+            encoder,
+            position,
+            reversed: false,
+        }
+    }
+
+    /// Count the other way round, so positive motor speed means increasing position.
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    /// Update the shared position forever.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            let delta = match self.encoder.read().await {
+                Direction::Clockwise => 1,
+                Direction::CounterClockwise => -1,
+            };
+            self.position
+                .step(if self.reversed { -delta } else { delta });
+        }
+    }
+}
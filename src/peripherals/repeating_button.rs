@@ -0,0 +1,88 @@
+//! repeating_button.rs — hold-to-repeat presses, like the +/- keys of a setup menu
+//!
+//! [`RepeatingButton::next_press`] returns once when the button goes down and then again
+//! every repeat interval for as long as it is held, after an initial delay so a quick tap
+//! still counts once. Each press carries its repeat count, so callers can step faster the
+//! longer the key is held. The delay and interval run on the button's
+//! [`Uptime`](crate::Uptime).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut up = RepeatingButton::new(Button::new(Input::new(p.PIN_14, Pull::Up)));
+//! loop {
+//!     let repeat = up.next_press().await;
+//!     setpoint += if repeat < 10 { 1 } else { 10 };
+//!     lcd.display_str(&format_setpoint(setpoint))?;
+//! }
+//! ```
+
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant};
+
+use crate::{Button, SystemUptime, Uptime};
+
+/// Hold time before the first repeat
+pub const REPEAT_DEFAULT_DELAY: Duration = Duration::from_millis(500);
+/// Time between repeats
+pub const REPEAT_DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct RepeatingButton<'d, U: Uptime = SystemUptime> {
+    button: Button<Input<'d>, U>,
+    delay: Duration,
+    interval: Duration,
+    /// Repeats so far and when the next one is due, while held
+    held: Option<(u32, Instant)>,
+}
+
+impl<'d, U: Uptime> RepeatingButton<'d, U> {
+    pub fn new(button: Button<Input<'d>, U>) -> Self {
+        Self {
+            button,
+            delay: REPEAT_DEFAULT_DELAY,
+            interval: REPEAT_DEFAULT_INTERVAL,
+            held: None,
+        }
+    }
+
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn button(&mut self) -> &mut Button<Input<'d>, U> {
+        &mut self.button
+    }
+
+    pub fn into_inner(self) -> Button<Input<'d>, U> {
+        self.button
+    }
+
+    /// Wait for the next press or repeat; returns 0 for the press itself and 1, 2, ... for
+    /// the repeats while it is held.
+    pub async fn next_press(&mut self) -> u32 {
+        loop {
+            let Some((count, due)) = self.held else {
+                self.button.wait_for_press().await;
+                self.held = Some((0, self.now() + self.delay));
+                return 0;
+            };
+            if self.button.wait_for_release_until(due).await {
+                self.held = None;
+                continue;
+            }
+            let count = count.saturating_add(1);
+            // Don't burst to catch up if the caller was slow
+            let next = (due + self.interval).max(self.now());
+            self.held = Some((count, next));
+            return count;
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.button.uptime().now()
+    }
+}
@@ -0,0 +1,209 @@
+//! rotary_encoder.rs — GPIO rotary encoder with push button, counted in detents
+//!
+//! The common EC11-style knobs for menus: A/B contacts to GND with pull-ups and usually a
+//! push switch in the shaft. The A/B levels are decoded with a transition table, which
+//! ignores contact bounce because a bounce only steps back and forth, and four steps make
+//! one detent (one click of the knob). [`RotaryEncoder::wait_for_turn`] sleeps on the pin
+//! edges; [`RotaryEncoder::delta`] samples the pins without waiting, so it has to be
+//! called often enough to see every step. For fast spinning wheels use the PIO-based
+//! [`QuadratureEncoder`](crate::QuadratureEncoder) instead.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut knob = RotaryEncoder::with_button(
+//!     Input::new(p.PIN_10, Pull::Up),
+//!     Input::new(p.PIN_11, Pull::Up),
+//!     Button::new(Input::new(p.PIN_12, Pull::Up)),
+//! );
+//! loop {
+//!     match knob.wait_for_event().await {
+//!         EncoderEvent::Turn(detents) => menu.move_by(detents),
+//!         EncoderEvent::Press => menu.select(),
+//!     }
+//!     menu.render(&mut oled)?;
+//! }
+//! ```
+
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::Input;
+
+use crate::Button;
+
+/// Steps per click on EC11-style encoders
+pub const ENCODER_DEFAULT_STEPS_PER_DETENT: u8 = 4;
+
+/// Input from a [`RotaryEncoder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum EncoderEvent {
+    /// Detents turned, positive clockwise
+    Turn(i32),
+    Press,
+}
+
+/// Quadrature step for (previous AB << 2 | current AB); invalid jumps count as 0.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Turns A/B samples into detents.
+#[derive(Debug, Clone, Copy)]
+struct QuadratureDecoder {
+    last: u8,
+    steps: i8,
+    steps_per_detent: i8,
+}
+
+impl QuadratureDecoder {
+    fn new(a: bool, b: bool, steps_per_detent: u8) -> Self {
+        Self {
+            last: Self::bits(a, b),
+            steps: 0,
+            steps_per_detent: steps_per_detent.clamp(1, 4) as i8,
+        }
+    }
+
+    fn bits(a: bool, b: bool) -> u8 {
+        (a as u8) << 1 | b as u8
+    }
+
+    /// Feed one sample; returns the detents completed by it (-1, 0 or 1).
+    fn update(&mut self, a: bool, b: bool) -> i32 {
+        let current = Self::bits(a, b);
+        self.steps += TRANSITIONS[(self.last << 2 | current) as usize];
+        self.last = current;
+        if self.steps >= self.steps_per_detent {
+            self.steps -= self.steps_per_detent;
+            1
+        } else if self.steps <= -self.steps_per_detent {
+            self.steps += self.steps_per_detent;
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+pub struct RotaryEncoder<'d> {
+    a: Input<'d>,
+    b: Input<'d>,
+    button: Option<Button<Input<'d>>>,
+    decoder: QuadratureDecoder,
+    /// Detents decoded but not yet returned
+    pending: i32,
+    reversed: bool,
+}
+
+impl<'d> RotaryEncoder<'d> {
+    /// `a` and `b` need pull-ups (internal or external).
+    pub fn new(a: Input<'d>, b: Input<'d>) -> Self {
+        let decoder =
+            QuadratureDecoder::new(a.is_high(), b.is_high(), ENCODER_DEFAULT_STEPS_PER_DETENT);
+        Self {
+            a,
+            b,
+            button: None,
+            decoder,
+            pending: 0,
+            reversed: false,
+        }
+    }
+
+    pub fn with_button(a: Input<'d>, b: Input<'d>, button: Button<Input<'d>>) -> Self {
+        Self {
+            button: Some(button),
+            ..Self::new(a, b)
+        }
+    }
+
+    /// 4 for encoders that click once per full quadrature cycle, 2 or 1 for those that
+    /// click every half or quarter cycle.
+    pub fn set_steps_per_detent(&mut self, steps: u8) {
+        self.decoder = QuadratureDecoder::new(self.a.is_high(), self.b.is_high(), steps);
+    }
+
+    /// Swap the turn direction instead of swapping the A/B wires.
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    pub fn button(&mut self) -> Option<&mut Button<Input<'d>>> {
+        self.button.as_mut()
+    }
+
+    /// Detents turned since the last call, without waiting.
+    pub fn delta(&mut self) -> i32 {
+        self.sample();
+        core::mem::take(&mut self.pending)
+    }
+
+    /// Wait until the knob has turned at least one detent; returns the detents turned.
+    pub async fn wait_for_turn(&mut self) -> i32 {
+        loop {
+            self.sample();
+            if self.pending != 0 {
+                return core::mem::take(&mut self.pending);
+            }
+            select(self.a.wait_for_any_edge(), self.b.wait_for_any_edge()).await;
+        }
+    }
+
+    /// Wait for a turn or, if there is a button, a press.
+    pub async fn wait_for_event(&mut self) -> EncoderEvent {
+        let Some(button) = self.button.as_mut() else {
+            return EncoderEvent::Turn(self.wait_for_turn().await);
+        };
+        loop {
+            self.pending += sample(&self.a, &self.b, &mut self.decoder, self.reversed);
+            if self.pending != 0 {
+                return EncoderEvent::Turn(core::mem::take(&mut self.pending));
+            }
+            let edge = select(self.a.wait_for_any_edge(), self.b.wait_for_any_edge());
+            if let Either::Second(()) = select(edge, button.wait_for_press()).await {
+                return EncoderEvent::Press;
+            }
+        }
+    }
+
+    fn sample(&mut self) {
+        self.pending += sample(&self.a, &self.b, &mut self.decoder, self.reversed);
+    }
+}
+
+fn sample(a: &Input<'_>, b: &Input<'_>, decoder: &mut QuadratureDecoder, reversed: bool) -> i32 {
+    let detents = decoder.update(a.is_high(), b.is_high());
+    if reversed { -detents } else { detents }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One clockwise quadrature cycle starting and ending with both contacts open
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(false, true), (false, false), (true, false), (true, true)];
+
+    #[test]
+    fn full_cycle_is_one_detent() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+        let detents: i32 = CLOCKWISE.iter().map(|&(a, b)| decoder.update(a, b)).sum();
+        assert_eq!(detents, 1);
+        let detents: i32 = CLOCKWISE
+            .iter()
+            .rev()
+            .skip(1)
+            .chain([(true, true)].iter())
+            .map(|&(a, b)| decoder.update(a, b))
+            .sum();
+        assert_eq!(detents, -1);
+    }
+
+    #[test]
+    fn bounce_cancels_out() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+        for _ in 0..5 {
+            assert_eq!(decoder.update(false, true), 0);
+            assert_eq!(decoder.update(true, true), 0);
+        }
+        let detents: i32 = CLOCKWISE.iter().map(|&(a, b)| decoder.update(a, b)).sum();
+        assert_eq!(detents, 1);
+    }
+}
@@ -0,0 +1,209 @@
+//! rs485.rs — half-duplex RS-485 over a UART with a driver-enable pin
+//!
+//! Transceivers such as the MAX485 need their DE (and inverted /RE, usually tied together)
+//! pin high while sending and low otherwise. [`Rs485::write`] raises DE, sends, waits for
+//! the UART to report the stop bit of the last byte as sent (the FIFO being empty is not
+//! enough) and releases the bus. [`Rs485::read_frame`] collects bytes until the line is
+//! idle for the bus's inter-frame gap, as Modbus RTU delimits frames.
+//!
+//! [`Rs485`] also implements the `embedded_io_async` [`Read`] and [`Write`] traits, so
+//! stream protocols written against them run over the bus too.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut config = uart::Config::default();
+//! config.baudrate = 19_200;
+//! config.parity = uart::Parity::ParityEven;
+//! let uart = Uart::new(p.UART0, p.PIN_0, p.PIN_1, Irqs, p.DMA_CH0, p.DMA_CH1, config.clone());
+//! let de = Output::new(p.PIN_2, Level::Low);
+//! let mut bus = Rs485::new(uart, de, &config);
+//!
+//! bus.write(&request).await?;
+//! let len = bus.read_frame(&mut response, Duration::from_millis(200)).await?;
+//! ```
+
+use embassy_futures::yield_now;
+use embassy_rp::gpio::Output;
+use embassy_rp::uart::{self, Async, Uart, UartRx, UartTx};
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum Rs485Error {
+    #[error("UART transfer failed")]
+    Uart,
+    #[error("No data received in time")]
+    Timeout,
+    #[error("Frame longer than the buffer")]
+    Overflow,
+}
+
+impl embedded_io_async::Error for Rs485Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Rs485Error::Uart => ErrorKind::Other,
+            Rs485Error::Timeout => ErrorKind::TimedOut,
+            Rs485Error::Overflow => ErrorKind::OutOfMemory,
+        }
+    }
+}
+
+pub struct Rs485<'d, T: uart::Instance> {
+    tx: UartTx<'d, T, Async>,
+    rx: UartRx<'d, T, Async>,
+    de: Output<'d>,
+    char_time: Duration,
+    frame_gap: Duration,
+    turnaround: Duration,
+}
+
+impl<'d, T: uart::Instance> Rs485<'d, T> {
+    /// `config` must be the one `uart` was created with; bus timing is derived from it.
+    pub fn new(uart: Uart<'d, T, Async>, mut de: Output<'d>, config: &uart::Config) -> Self {
+        de.set_low();
+        let bits = char_bits(config);
+        let (tx, rx) = uart.split();
+        Self {
+            tx,
+            rx,
+            de,
+            char_time: Duration::from_micros(char_time_us(config.baudrate, bits)),
+            frame_gap: Duration::from_micros(frame_gap_us(config.baudrate, bits)),
+            turnaround: Duration::from_ticks(0),
+        }
+    }
+
+    /// Keep driving the bus this long after the last stop bit, for slow transceivers or
+    /// long cables. Defaults to zero.
+    pub fn set_turnaround(&mut self, turnaround: Duration) {
+        self.turnaround = turnaround;
+    }
+
+    /// Silence that ends a frame: 3.5 characters, at least 1.75 ms (the Modbus RTU rule).
+    pub fn frame_gap(&self) -> Duration {
+        self.frame_gap
+    }
+
+    /// Send `data`, driving the bus only for its duration.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), Rs485Error> {
+        self.de.set_high();
+        let result = self.tx.write(data).await.map_err(|_| Rs485Error::Uart);
+        if result.is_ok() {
+            // DMA completion only means the FIFO has the data; BUSY clears after the last
+            // stop bit is on the wire.
+            while self.tx.busy() {
+                yield_now().await;
+            }
+            if self.turnaround > Duration::from_ticks(0) {
+                Timer::after(self.turnaround).await;
+            }
+        }
+        self.de.set_low();
+        result
+    }
+
+    /// Fill `buf` exactly.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<(), Rs485Error> {
+        self.rx.read(buf).await.map_err(|_| Rs485Error::Uart)
+    }
+
+    /// Read one frame: wait up to `timeout` for its first byte, then take bytes until the
+    /// line stays idle for [`frame_gap`](Self::frame_gap). Returns the frame length.
+    pub async fn read_frame(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, Rs485Error> {
+        let mut byte = [0u8; 1];
+        with_timeout(timeout, self.rx.read(&mut byte))
+            .await
+            .map_err(|_| Rs485Error::Timeout)?
+            .map_err(|_| Rs485Error::Uart)?;
+        let mut len = 0;
+        // The gap is measured from the end of the last character
+        let gap = self.frame_gap + self.char_time;
+        loop {
+            *buf.get_mut(len).ok_or(Rs485Error::Overflow)? = byte[0];
+            len += 1;
+            match with_timeout(gap, self.rx.read(&mut byte)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => return Err(Rs485Error::Uart),
+                Err(_) => return Ok(len),
+            }
+        }
+    }
+}
+
+impl<T: uart::Instance> ErrorType for Rs485<'_, T> {
+    type Error = Rs485Error;
+}
+
+impl<T: uart::Instance> Read for Rs485<'_, T> {
+    /// Reads one byte per call, like [`UartIo`](crate::UartIo).
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some(first) = buf.first_mut() else {
+            return Ok(0);
+        };
+        self.rx
+            .read(core::slice::from_mut(first))
+            .await
+            .map_err(|_| Rs485Error::Uart)?;
+        Ok(1)
+    }
+}
+
+impl<T: uart::Instance> Write for Rs485<'_, T> {
+    /// Sends all of `buf` in one transmission, releasing the bus afterwards.
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Rs485::write(self, buf).await?;
+        Ok(buf.len())
+    }
+}
+
+/// Start, data, parity and stop bits of one character
+fn char_bits(config: &uart::Config) -> u32 {
+    let data = match config.data_bits {
+        uart::DataBits::DataBits5 => 5,
+        uart::DataBits::DataBits6 => 6,
+        uart::DataBits::DataBits7 => 7,
+        uart::DataBits::DataBits8 => 8,
+    };
+    let parity = match config.parity {
+        uart::Parity::ParityNone => 0,
+        _ => 1,
+    };
+    let stop = match config.stop_bits {
+        uart::StopBits::STOP1 => 1,
+        uart::StopBits::STOP2 => 2,
+    };
+    1 + data + parity + stop
+}
+
+fn char_time_us(baudrate: u32, bits: u32) -> u64 {
+    (bits as u64 * 1_000_000).div_ceil(baudrate.max(1) as u64)
+}
+
+/// 3.5 character times, but fixed at 1750 µs above 19200 baud where the timers would get
+/// impractically short
+fn frame_gap_us(baudrate: u32, bits: u32) -> u64 {
+    if baudrate > 19_200 {
+        1750
+    } else {
+        (bits as u64 * 3_500_000).div_ceil(baudrate.max(1) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bus_timing() {
+        // 8E1: 11 bits per character
+        assert_eq!(char_time_us(9600, 11), 1146);
+        assert_eq!(frame_gap_us(9600, 11), 4011);
+        assert_eq!(frame_gap_us(19_200, 11), 2006);
+        assert_eq!(frame_gap_us(115_200, 10), 1750);
+    }
+}
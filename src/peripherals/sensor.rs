@@ -0,0 +1,454 @@
+//! sensor.rs — one trait for all sensors and a poller that samples them on schedules
+//!
+//! A [`Sensor`] returns a [`Reading`]: a value and its [`SensorUnit`]. The crate's
+//! single-quantity drivers implement it (BH1750, DS3231 temperature, soil moisture, flow,
+//! plain ADC inputs), and so can application sensors, which then plug into the same code.
+//!
+//! A [`SensorPoller`] owns a tuple of [`PolledSensor`]s, each with a name and an interval,
+//! reads whichever is due next and hands every [`SensorSample`] to a [`SampleSink`]: the
+//! [`SENSOR_EVENTS`] channel, a [`MetricsRegistry`] for the dashboard, a
+//! [`TelemetryQueue`], or a tuple of those; for anything else, loop over
+//! [`SensorPoller::poll`]. A failed read is delivered as a sample too, so sinks can show
+//! it; the sensor is tried again at its next interval.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static LIGHT: Gauge = Gauge::new("light", "lx");
+//! static TELEMETRY: TelemetryQueue<32> = TelemetryQueue::new();
+//!
+//! metrics.register(&LIGHT)?;
+//! let mut poller = SensorPoller::new((
+//!     PolledSensor::new("light", Duration::from_secs(1), Bh1750::new_with_default_address(i2c).await?),
+//!     PolledSensor::new("soil", Duration::from_secs(60), SoilMoisture::new(input, calibration)),
+//! ));
+//! poller.run(&mut (&SENSOR_EVENTS, &mut metrics, &TELEMETRY)).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{PubSubChannel, Subscriber};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    AnalogInput, AnalogInputError, Bh1750, Bh1750Error, Delivery, Ds3231, Ds3231Error, FlowSensor,
+    HeaplessString, MetricsRegistry, SoilMoisture, TELEMETRY_MAX_PAYLOAD_LEN,
+    TELEMETRY_MAX_TOPIC_LEN, TelemetryQueue,
+};
+
+/// Most sensors in one [`SensorPoller`] (the largest [`SensorList`] tuple)
+pub const SENSOR_POLLER_MAX_SENSORS: usize = 8;
+pub const SENSOR_EVENT_QUEUE_LEN: usize = 8;
+pub const SENSOR_EVENT_MAX_SUBSCRIBERS: usize = 4;
+/// Telemetry topic of a sample: this prefix and the sensor name
+pub const SENSOR_TOPIC_PREFIX: &str = "sensors/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum SensorError {
+    #[error("Sensor read failed")]
+    Read,
+    #[error("No sensor at that index")]
+    NoSuchSensor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SensorUnit {
+    Celsius,
+    Percent,
+    Lux,
+    Millivolts,
+    LitersPerMinute,
+}
+
+impl SensorUnit {
+    /// Short symbol for displays and payloads
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            SensorUnit::Celsius => "C",
+            SensorUnit::Percent => "%",
+            SensorUnit::Lux => "lx",
+            SensorUnit::Millivolts => "mV",
+            SensorUnit::LitersPerMinute => "L/min",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Reading {
+    pub value: f32,
+    pub unit: SensorUnit,
+}
+
+impl Reading {
+    pub fn new(value: f32, unit: SensorUnit) -> Self {
+        Self { value, unit }
+    }
+}
+
+/// Anything that measures one quantity
+#[allow(async_fn_in_trait)]
+pub trait Sensor {
+    type Error;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error>;
+}
+
+impl<I: embedded_hal::i2c::I2c> Sensor for Bh1750<I> {
+    type Error = Bh1750Error;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error> {
+        Ok(Reading::new(self.read_lux()? as f32, SensorUnit::Lux))
+    }
+}
+
+/// The temperature of the clock's die, which follows the room within a degree or two
+impl<I: embedded_hal::i2c::I2c> Sensor for Ds3231<I> {
+    type Error = Ds3231Error;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error> {
+        Ok(Reading::new(self.temperature()?, SensorUnit::Celsius))
+    }
+}
+
+impl Sensor for SoilMoisture<'_> {
+    type Error = AnalogInputError;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error> {
+        let percent = self.read_percent().await?;
+        Ok(Reading::new(percent as f32, SensorUnit::Percent))
+    }
+}
+
+/// Calibrated voltage at the pin
+impl Sensor for AnalogInput<'_> {
+    type Error = AnalogInputError;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error> {
+        let millivolts = self.read_calibrated_millivolts().await?;
+        Ok(Reading::new(millivolts as f32, SensorUnit::Millivolts))
+    }
+}
+
+/// Flow since the previous read, so the poll interval is the measuring window
+impl Sensor for FlowSensor<'_> {
+    type Error = core::convert::Infallible;
+
+    async fn read(&mut self) -> Result<Reading, Self::Error> {
+        Ok(Reading::new(
+            self.sample().flow_lpm,
+            SensorUnit::LitersPerMinute,
+        ))
+    }
+}
+
+/// A sensor with the name its samples carry and how often it is read
+pub struct PolledSensor<S> {
+    pub name: &'static str,
+    pub interval: Duration,
+    pub sensor: S,
+}
+
+impl<S: Sensor> PolledSensor<S> {
+    pub fn new(name: &'static str, interval: Duration, sensor: S) -> Self {
+        Self {
+            name,
+            interval,
+            sensor,
+        }
+    }
+}
+
+/// Sensors of different types, addressed by index: tuples of up to
+/// [`SENSOR_POLLER_MAX_SENSORS`] [`PolledSensor`]s.
+#[allow(async_fn_in_trait)]
+pub trait SensorList {
+    const LEN: usize;
+
+    fn name(&self, index: usize) -> &'static str;
+
+    fn interval(&self, index: usize) -> Duration;
+
+    async fn read(&mut self, index: usize) -> Result<Reading, SensorError>;
+}
+
+macro_rules! sensor_list {
+    ($len:expr; $($sensor:ident $index:tt),+) => {
+        impl<$($sensor: Sensor),+> SensorList for ($(PolledSensor<$sensor>,)+) {
+            const LEN: usize = $len;
+
+            fn name(&self, index: usize) -> &'static str {
+                match index {
+                    $($index => self.$index.name,)+
+                    _ => "",
+                }
+            }
+
+            fn interval(&self, index: usize) -> Duration {
+                match index {
+                    $($index => self.$index.interval,)+
+                    _ => Duration::MAX,
+                }
+            }
+
+            async fn read(&mut self, index: usize) -> Result<Reading, SensorError> {
+                match index {
+                    $($index => self.$index.sensor.read().await.map_err(|_| SensorError::Read),)+
+                    _ => Err(SensorError::NoSuchSensor),
+                }
+            }
+        }
+    };
+}
+
+sensor_list!(1; A 0);
+sensor_list!(2; A 0, B 1);
+sensor_list!(3; A 0, B 1, C 2);
+sensor_list!(4; A 0, B 1, C 2, D 3);
+sensor_list!(5; A 0, B 1, C 2, D 3, E 4);
+sensor_list!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+sensor_list!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+sensor_list!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// One read of one sensor
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct SensorSample {
+    pub name: &'static str,
+    pub reading: Result<Reading, SensorError>,
+    pub at: Instant,
+}
+
+impl SensorSample {
+    /// `{"value":21.5,"unit":"C"}`, or `{"error":"..."}` for a failed read
+    pub fn json(&self) -> HeaplessString<TELEMETRY_MAX_PAYLOAD_LEN> {
+        let mut payload = HeaplessString::new();
+        let _ = match self.reading {
+            Ok(reading) => write!(
+                payload,
+                r#"{{"value":{},"unit":"{}"}}"#,
+                reading.value,
+                reading.unit.symbol()
+            ),
+            Err(error) => write!(payload, r#"{{"error":"{error}"}}"#),
+        };
+        payload
+    }
+}
+
+/// Where a [`SensorPoller`] delivers its samples
+pub trait SampleSink {
+    fn on_sample(&mut self, sample: &SensorSample);
+}
+
+pub type SensorEventChannel = PubSubChannel<
+    CriticalSectionRawMutex,
+    SensorSample,
+    SENSOR_EVENT_QUEUE_LEN,
+    SENSOR_EVENT_MAX_SUBSCRIBERS,
+    0,
+>;
+
+pub type SensorEventSubscriber = Subscriber<
+    'static,
+    CriticalSectionRawMutex,
+    SensorSample,
+    SENSOR_EVENT_QUEUE_LEN,
+    SENSOR_EVENT_MAX_SUBSCRIBERS,
+    0,
+>;
+
+/// Event bus for samples; subscribers that fall behind lose the oldest ones.
+pub static SENSOR_EVENTS: SensorEventChannel = PubSubChannel::new();
+
+impl SampleSink for &SensorEventChannel {
+    fn on_sample(&mut self, sample: &SensorSample) {
+        self.immediate_publisher().publish_immediate(*sample);
+    }
+}
+
+/// Sets the gauge named like the sensor to the rounded value. Gauges are unsigned, so
+/// negative values read 0; failed reads leave the gauge alone.
+impl<const N: usize> SampleSink for &mut MetricsRegistry<N> {
+    fn on_sample(&mut self, sample: &SensorSample) {
+        if let (Ok(reading), Some(gauge)) = (sample.reading, self.get(sample.name)) {
+            gauge.set(libm::roundf(reading.value) as u32);
+        }
+    }
+}
+
+/// Queues [`SensorSample::json`] on `sensors/<name>`, keeping only the newest per sensor
+impl<const N: usize> SampleSink for &TelemetryQueue<N> {
+    fn on_sample(&mut self, sample: &SensorSample) {
+        // Writing truncates, so a long name would publish on a wrong topic
+        if SENSOR_TOPIC_PREFIX.len() + sample.name.len() > TELEMETRY_MAX_TOPIC_LEN {
+            return;
+        }
+        let mut topic: HeaplessString<TELEMETRY_MAX_TOPIC_LEN> = HeaplessString::new();
+        let _ = write!(topic, "{SENSOR_TOPIC_PREFIX}{}", sample.name);
+        let _ = self.push(
+            topic.as_str(),
+            sample.json().as_str().as_bytes(),
+            Delivery::Latest,
+        );
+    }
+}
+
+impl<A: SampleSink, B: SampleSink> SampleSink for (A, B) {
+    fn on_sample(&mut self, sample: &SensorSample) {
+        self.0.on_sample(sample);
+        self.1.on_sample(sample);
+    }
+}
+
+impl<A: SampleSink, B: SampleSink, C: SampleSink> SampleSink for (A, B, C) {
+    fn on_sample(&mut self, sample: &SensorSample) {
+        self.0.on_sample(sample);
+        self.1.on_sample(sample);
+        self.2.on_sample(sample);
+    }
+}
+
+/// When each sensor is next due; no hardware, so it can be tested.
+#[derive(Debug, Clone, Copy)]
+struct Schedule {
+    due: [Instant; SENSOR_POLLER_MAX_SENSORS],
+}
+
+impl Schedule {
+    /// Every sensor due at `start`
+    fn new(start: Instant) -> Self {
+        Self {
+            due: [start; SENSOR_POLLER_MAX_SENSORS],
+        }
+    }
+
+    /// The first of `len` sensors due, and when; ties go to the lower index.
+    fn next(&self, len: usize) -> Option<(usize, Instant)> {
+        self.due[..len.min(SENSOR_POLLER_MAX_SENSORS)]
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|&(_, due)| due)
+    }
+
+    /// Sensor `index` was read at `now`. Keeps the interval's cadence, but a sensor that
+    /// fell more than an interval behind starts over from `now` instead of catching up.
+    fn done(&mut self, index: usize, interval: Duration, now: Instant) {
+        let after = |at: Instant| at.checked_add(interval).unwrap_or(Instant::MAX);
+        let next = after(self.due[index]);
+        self.due[index] = if next <= now { after(now) } else { next };
+    }
+}
+
+/// Reads a [`SensorList`], each sensor at its own interval
+pub struct SensorPoller<L: SensorList> {
+    sensors: L,
+    schedule: Schedule,
+}
+
+impl<L: SensorList> SensorPoller<L> {
+    /// Every sensor is read once right away, then at its interval.
+    pub fn new(sensors: L) -> Self {
+        Self {
+            sensors,
+            schedule: Schedule::new(Instant::now()),
+        }
+    }
+
+    pub fn sensors_mut(&mut self) -> &mut L {
+        &mut self.sensors
+    }
+
+    /// Wait until the next sensor is due and read it.
+    pub async fn poll(&mut self) -> SensorSample {
+        let Some((index, due)) = self.schedule.next(L::LEN) else {
+            // Only an empty list has nothing due
+            return core::future::pending().await;
+        };
+        Timer::at(due).await;
+        let reading = self.sensors.read(index).await;
+        let now = Instant::now();
+        self.schedule.done(index, self.sensors.interval(index), now);
+        SensorSample {
+            name: self.sensors.name(index),
+            reading,
+            at: now,
+        }
+    }
+
+    /// Poll forever, handing every sample to `sink`.
+    pub async fn run(&mut self, sink: &mut impl SampleSink) -> ! {
+        loop {
+            let sample = self.poll().await;
+            sink.on_sample(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    struct Fake(Option<f32>);
+
+    impl Sensor for Fake {
+        type Error = ();
+
+        async fn read(&mut self) -> Result<Reading, Self::Error> {
+            self.0
+                .map(|value| Reading::new(value, SensorUnit::Celsius))
+                .ok_or(())
+        }
+    }
+
+    #[test]
+    fn tuples_read_by_index() {
+        let second = Duration::from_secs(1);
+        let mut sensors = (
+            PolledSensor::new("ok", second, Fake(Some(21.5))),
+            PolledSensor::new("broken", second * 5, Fake(None)),
+        );
+        assert_eq!(<(PolledSensor<Fake>, PolledSensor<Fake>)>::LEN, 2);
+        assert_eq!(sensors.name(1), "broken");
+        assert_eq!(sensors.interval(1), second * 5);
+        assert_eq!(
+            block_on(sensors.read(0)),
+            Ok(Reading::new(21.5, SensorUnit::Celsius))
+        );
+        assert_eq!(block_on(sensors.read(1)), Err(SensorError::Read));
+        assert_eq!(block_on(sensors.read(2)), Err(SensorError::NoSuchSensor));
+    }
+
+    #[test]
+    fn schedule_keeps_cadence_and_skips_missed_reads() {
+        let at = Instant::from_millis;
+        let mut schedule = Schedule::new(at(0));
+        assert_eq!(schedule.next(2), Some((0, at(0))));
+        schedule.done(0, Duration::from_millis(100), at(3));
+        assert_eq!(schedule.next(2), Some((1, at(0))));
+        schedule.done(1, Duration::from_millis(250), at(5));
+        assert_eq!(schedule.next(2), Some((0, at(100))));
+        // Read late, but within the interval: the cadence holds
+        schedule.done(0, Duration::from_millis(100), at(150));
+        assert_eq!(schedule.next(2), Some((0, at(200))));
+        // Far behind: start over
+        schedule.done(0, Duration::from_millis(100), at(900));
+        assert_eq!(schedule.next(2), Some((1, at(250))));
+        assert_eq!(schedule.due[0], at(1000));
+        assert_eq!(schedule.next(0), None);
+    }
+
+    #[test]
+    fn sample_json() {
+        let mut sample = SensorSample {
+            name: "temp",
+            reading: Ok(Reading::new(21.5, SensorUnit::Celsius)),
+            at: Instant::from_millis(0),
+        };
+        assert_eq!(sample.json().as_str(), r#"{"value":21.5,"unit":"C"}"#);
+        sample.reading = Err(SensorError::Read);
+        assert_eq!(sample.json().as_str(), r#"{"error":"Sensor read failed"}"#);
+    }
+}
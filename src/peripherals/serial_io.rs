@@ -0,0 +1,182 @@
+//! serial_io.rs — UART and USB CDC as `embedded-io-async` streams
+//!
+//! Protocol code (framing, shells, Modbus RTU, NMEA parsers) written against the
+//! `embedded_io_async` [`Read`] and [`Write`] traits runs over any transport. embassy-net's
+//! `TcpSocket` and embassy-rp's `BufferedUart` implement them already; [`UartIo`] and
+//! [`CdcIo`] cover a DMA [`Uart`] and a USB CDC-ACM class, and [`Rs485`](crate::Rs485)
+//! implements them itself.
+//!
+//! [`UartIo`] reads one byte per call, since a DMA read only returns once its buffer is
+//! full; for high baud rates a `BufferedUart` is the better choice. [`CdcIo`] buffers one
+//! USB packet and sends a zero-length packet on flush when the last one was full, so the
+//! host sees the end of the transfer.
+//!
+//! # Example
+//!
+//! ```ignore
+//! async fn echo(io: &mut (impl Read + Write)) -> Result<(), impl core::fmt::Debug> {
+//!     let mut buf = [0u8; 64];
+//!     loop {
+//!         let len = io.read(&mut buf).await?;
+//!         io.write_all(&buf[..len]).await?;
+//!     }
+//! }
+//!
+//! echo(&mut UartIo::new(Uart::new(p.UART0, p.PIN_0, p.PIN_1, Irqs, p.DMA_CH0, p.DMA_CH1, config))).await;
+//! echo(&mut CdcIo::new(CdcAcmClass::new(&mut builder, cdc_state, 64))).await;
+//! ```
+
+use embassy_rp::uart::{self, Async, Uart};
+use embassy_usb::class::cdc_acm::CdcAcmClass;
+use embassy_usb::driver::{Driver, EndpointError};
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+/// Largest full-speed bulk packet
+const CDC_MAX_PACKET_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum SerialIoError {
+    #[error("UART transfer failed")]
+    Uart,
+    #[error("USB host disconnected")]
+    Disconnected,
+    #[error("USB packet too large")]
+    BufferOverflow,
+}
+
+impl embedded_io_async::Error for SerialIoError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SerialIoError::Uart => ErrorKind::Other,
+            SerialIoError::Disconnected => ErrorKind::NotConnected,
+            SerialIoError::BufferOverflow => ErrorKind::OutOfMemory,
+        }
+    }
+}
+
+impl From<EndpointError> for SerialIoError {
+    fn from(error: EndpointError) -> Self {
+        match error {
+            EndpointError::Disabled => SerialIoError::Disconnected,
+            EndpointError::BufferOverflow => SerialIoError::BufferOverflow,
+        }
+    }
+}
+
+/// DMA UART as a byte stream
+pub struct UartIo<'d, T: uart::Instance> {
+    uart: Uart<'d, T, Async>,
+}
+
+impl<'d, T: uart::Instance> UartIo<'d, T> {
+    pub fn new(uart: Uart<'d, T, Async>) -> Self {
+        Self { uart }
+    }
+
+    pub fn into_inner(self) -> Uart<'d, T, Async> {
+        self.uart
+    }
+}
+
+impl<T: uart::Instance> ErrorType for UartIo<'_, T> {
+    type Error = SerialIoError;
+}
+
+impl<T: uart::Instance> Read for UartIo<'_, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some(first) = buf.first_mut() else {
+            return Ok(0);
+        };
+        self.uart
+            .read(core::slice::from_mut(first))
+            .await
+            .map_err(|_| SerialIoError::Uart)?;
+        Ok(1)
+    }
+}
+
+impl<T: uart::Instance> Write for UartIo<'_, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.uart
+            .write(buf)
+            .await
+            .map_err(|_| SerialIoError::Uart)?;
+        Ok(buf.len())
+    }
+}
+
+/// USB CDC-ACM (virtual serial port) class as a byte stream
+pub struct CdcIo<'d, D: Driver<'d>> {
+    class: CdcAcmClass<'d, D>,
+    /// Received packet and how much of it was read
+    packet: [u8; CDC_MAX_PACKET_LEN],
+    len: usize,
+    pos: usize,
+    /// The last packet sent was full, so the transfer still needs ending
+    pending_zlp: bool,
+}
+
+impl<'d, D: Driver<'d>> CdcIo<'d, D> {
+    pub fn new(class: CdcAcmClass<'d, D>) -> Self {
+        Self {
+            class,
+            packet: [0; CDC_MAX_PACKET_LEN],
+            len: 0,
+            pos: 0,
+            pending_zlp: false,
+        }
+    }
+
+    /// Wait until the host opens the port.
+    pub async fn wait_connection(&mut self) {
+        self.class.wait_connection().await;
+    }
+
+    pub fn into_inner(self) -> CdcAcmClass<'d, D> {
+        self.class
+    }
+
+    fn packet_len(&self) -> usize {
+        (self.class.max_packet_size() as usize).min(CDC_MAX_PACKET_LEN)
+    }
+}
+
+impl<'d, D: Driver<'d>> ErrorType for CdcIo<'d, D> {
+    type Error = SerialIoError;
+}
+
+impl<'d, D: Driver<'d>> Read for CdcIo<'d, D> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Skip zero-length packets
+        while self.pos == self.len {
+            self.len = self.class.read_packet(&mut self.packet).await?;
+            self.pos = 0;
+        }
+        let count = buf.len().min(self.len - self.pos);
+        buf[..count].copy_from_slice(&self.packet[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+impl<'d, D: Driver<'d>> Write for CdcIo<'d, D> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let count = buf.len().min(self.packet_len());
+        if count == 0 {
+            return Ok(0);
+        }
+        self.class.write_packet(&buf[..count]).await?;
+        self.pending_zlp = count == self.packet_len();
+        Ok(count)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if core::mem::take(&mut self.pending_zlp) {
+            self.class.write_packet(&[]).await?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,78 @@
 //! servo.rs — hobby-servo driver for embassy-rp PWM
+//!
+//! [`Servo::set_angle`] jumps to the new position as fast as the servo can go.
+//! [`Servo::move_to`] ramps there at a given speed instead, updating the pulse once per
+//! PWM frame, for smooth motion without an application-side ramp loop;
+//! [`Servo::move_to_eased`] follows an [`Easing`] curve on the way.
+//! [`Servo::set_pulse_us`] bypasses the angle mapping altogether, e.g. to find a servo's
+//! real end points before writing its [`ServoSpec`].
+//!
+//! [`Servo::angle`] and [`Servo::duty`] report what was last commanded, e.g. for a status
+//! screen. Commanding the same pulse again doesn't touch the PWM, so a control loop can
+//! call [`Servo::set_angle`] every tick for free while nothing changes.
+//!
+//! [`Servo::detach`] stops the pulses so the servo goes limp (quiet, low power, movable by
+//! hand) and [`Servo::attach`] brings it back to its last position.
+//!
+//! Servos of one model still differ a little. A [`ServoCalibration`] trims the pulse at
+//! each end and in the centre of the range, per servo; it is kept in a [`KvStore`] so
+//! it survives re-flashing, and [`Servo::calibrate_interactive`] finds it by hand.
+//!
+//! A PWM slice has two outputs; [`ServoPair`] drives a servo on each, with separate duties
+//! over the shared frame.
+//!
+//! [`ServoGroup`] moves several servos together, e.g. the joints of an arm: every servo
+//! starts and arrives at the same time, however far it has to go.
+//!
+//! [`ServoSpec`] has the specs of common servos (SG90, MG90S, MG995, DS3218, the FS90R
+//! continuous rotation servo); [`ServoSpec::builder`] makes and checks one for any other.
+//!
+//! For more servos than there are PWM outputs, see [`PioServoBank`](crate::PioServoBank).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let config = ServoConfig::with_system_clock(&mut pwm, ServoSpec::makerhawk_mg995());
+//! let mut servo = Servo::new(pwm, config);
+//! servo.set_angle(0.0)?;
+//! servo.move_to(180.0, 60.0).await?; // three seconds
+//!
+//! if !servo.load_calibration(&mut store, "servo/arm")? {
+//!     servo.calibrate_interactive(&mut wizard, &mut store, "servo/arm").await?;
+//! }
+//!
+//! let mut pwm = Pwm::new_output_ab(p.PWM_SLICE1, p.PIN_2, p.PIN_3, Default::default());
+//! let config = ServoConfig::with_system_clock(&mut pwm, ServoSpec::inland_ks0209());
+//! let mut pan_tilt = ServoPair::new(pwm, config.clone(), config)?;
+//! pan_tilt.set_angle(PwmChannel::A, 45.0)?;
+//! pan_tilt.set_angle(PwmChannel::B, 10.0)?;
+//!
+//! let mut arm = ServoGroup::new([base, shoulder, elbow]);
+//! arm.move_all_to(&[90.0, 45.0, 120.0], Duration::from_secs(2)).await?;
+//! ```
 #![allow(dead_code)]
 
-use core::cmp::{max, min};
 use embassy_rp::pwm::Pwm;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal::pwm::SetDutyCycle;
 use fixed::FixedU16;
+use fixed::types::I16F16;
 use fixed::types::extra::U4;
 
+use crate::{
+    CalibrationError, CalibrationInput, CalibrationOutcome, CalibrationWizard, Easing, KvStore,
+    KvStoreError, ServoTrimCalibrator, TextDisplay, sys_clock_hz,
+};
+
+/// Slowest PWM divider, 255.9375 in Q4
+const MAX_DIVIDER_Q4: u32 = 255 * 16 + 15;
+
+/// Shortest frame [`ServoSpec::validate`] accepts, 400 Hz (fast digital servos run at up to
+/// 333 Hz)
+pub const SERVO_MIN_FRAME_US: u32 = 2_500;
+/// Longest frame [`ServoSpec::validate`] accepts, 20 Hz
+pub const SERVO_MAX_FRAME_US: u32 = 50_000;
+
 /// Servo signal specification (all in microseconds / degrees).
 #[derive(Copy, Clone, Debug)]
 pub struct ServoSpec {
@@ -28,7 +94,7 @@ pub struct ServoSpec {
 
 impl ServoSpec {
     /// Inland KS0209 Blue 9g Servo Motor
-    pub fn inland_ks0209() -> &'static Self {
+    pub const fn inland_ks0209() -> &'static Self {
         const KS0209: ServoSpec = ServoSpec {
             frame_us: 20_000,   // 20 ms
             pulse_min_us: 1000, // 1 ms (0 degree)
@@ -41,7 +107,7 @@ impl ServoSpec {
     }
 
     /// MakerHawk MG-995 DIGI Hi-Speed
-    pub fn makerhawk_mg995() -> &'static Self {
+    pub const fn makerhawk_mg995() -> &'static Self {
         const MG995: ServoSpec = ServoSpec {
             frame_us: 20_000,
             pulse_min_us: 500,  // 0.5 ms (0 degree)
@@ -52,6 +118,147 @@ impl ServoSpec {
 
         &MG995
     }
+
+    /// TowerPro SG90 Micro Servo
+    pub const fn towerpro_sg90() -> &'static Self {
+        const SG90: ServoSpec = ServoSpec {
+            frame_us: 20_000,
+            pulse_min_us: 500,  // 0.5 ms (0 degree)
+            pulse_max_us: 2400, // 2.4 ms (180 degree)
+            angle_min_deg: 0.0,
+            angle_max_deg: 180.0,
+        };
+
+        &SG90
+    }
+
+    /// TowerPro MG90S Metal Gear Micro Servo
+    pub const fn towerpro_mg90s() -> &'static Self {
+        const MG90S: ServoSpec = ServoSpec {
+            frame_us: 20_000,
+            pulse_min_us: 500,  // 0.5 ms (0 degree)
+            pulse_max_us: 2400, // 2.4 ms (180 degree)
+            angle_min_deg: 0.0,
+            angle_max_deg: 180.0,
+        };
+
+        &MG90S
+    }
+
+    /// DSServo DS3218 20 kg, 270 degree version (the 180 degree one takes the same pulses)
+    pub const fn dsservo_ds3218() -> &'static Self {
+        const DS3218: ServoSpec = ServoSpec {
+            frame_us: 20_000,
+            pulse_min_us: 500,  // 0.5 ms (0 degree)
+            pulse_max_us: 2500, // 2.5 ms (270 degree)
+            angle_min_deg: 0.0,
+            angle_max_deg: 270.0,
+        };
+
+        &DS3218
+    }
+
+    /// Feetech FS90R continuous rotation. The "angle" is speed in percent: 0 stops,
+    /// positive turns counter-clockwise, negative clockwise.
+    pub const fn feetech_fs90r() -> &'static Self {
+        const FS90R: ServoSpec = ServoSpec {
+            frame_us: 20_000,
+            pulse_min_us: 700,  // 0.7 ms (full speed clockwise)
+            pulse_max_us: 2300, // 2.3 ms (full speed counter-clockwise)
+            angle_min_deg: -100.0,
+            angle_max_deg: 100.0,
+        };
+
+        &FS90R
+    }
+
+    /// A checked spec for servos not in the list above. Starts from a 50 Hz frame,
+    /// 1000–2000 µs and 0–180 degrees.
+    pub const fn builder() -> ServoSpecBuilder {
+        ServoSpecBuilder {
+            spec: ServoSpec {
+                frame_us: 20_000,
+                pulse_min_us: 1000,
+                pulse_max_us: 2000,
+                angle_min_deg: 0.0,
+                angle_max_deg: 180.0,
+            },
+        }
+    }
+
+    /// Check that the pulses fit the frame, the frame is one servos accept
+    /// ([`SERVO_MIN_FRAME_US`] to [`SERVO_MAX_FRAME_US`]), and the angles span a range.
+    pub const fn validate(&self) -> Result<(), ServoSpecError> {
+        if self.frame_us < SERVO_MIN_FRAME_US || self.frame_us > SERVO_MAX_FRAME_US {
+            return Err(ServoSpecError::FrameOutOfRange);
+        }
+        if self.pulse_min_us == 0 || self.pulse_min_us >= self.pulse_max_us {
+            return Err(ServoSpecError::PulseRange);
+        }
+        if self.pulse_max_us >= self.frame_us {
+            return Err(ServoSpecError::PulseLongerThanFrame);
+        }
+        if !self.angle_min_deg.is_finite()
+            || !self.angle_max_deg.is_finite()
+            || self.angle_min_deg == self.angle_max_deg
+        {
+            return Err(ServoSpecError::AngleRange);
+        }
+        Ok(())
+    }
+
+    /// Pulse width for `angle_deg`, clamped to the spec's range. Drivers that time pulses
+    /// in microseconds (rather than PWM counts) can use it directly.
+    pub fn pulse_us(&self, angle_deg: f32) -> u32 {
+        let pulse_min_us = self.pulse_min_us.min(u16::MAX as u32) as u16;
+        let pulse_max_us = self.pulse_max_us.min(u16::MAX as u32) as u16;
+        angle_to_duty(
+            fixed_angle(angle_deg),
+            (
+                fixed_angle(self.angle_min_deg),
+                fixed_angle(self.angle_max_deg),
+            ),
+            (pulse_min_us, pulse_max_us),
+            u16::MAX,
+        ) as u32
+    }
+}
+
+/// Builds a [`ServoSpec`] and checks it with [`ServoSpec::validate`]. All methods are
+/// `const`, so a spec can be built and checked at compile time.
+#[derive(Debug, Clone, Copy)]
+pub struct ServoSpecBuilder {
+    spec: ServoSpec,
+}
+
+impl ServoSpecBuilder {
+    /// Full frame period, e.g. 20 000 for 50 Hz or 3 030 for 330 Hz digital servos
+    pub const fn frame_us(mut self, frame_us: u32) -> Self {
+        self.spec.frame_us = frame_us;
+        self
+    }
+
+    /// Shortest and longest pulse the servo accepts
+    pub const fn pulse_range_us(mut self, min_us: u32, max_us: u32) -> Self {
+        self.spec.pulse_min_us = min_us;
+        self.spec.pulse_max_us = max_us;
+        self
+    }
+
+    /// Angles at the shortest and longest pulse; `min_deg` may be the larger, for a servo
+    /// mounted the other way round.
+    pub const fn angle_range(mut self, min_deg: f32, max_deg: f32) -> Self {
+        self.spec.angle_min_deg = min_deg;
+        self.spec.angle_max_deg = max_deg;
+        self
+    }
+
+    pub const fn build(self) -> Result<ServoSpec, ServoSpecError> {
+        match self.spec.validate() {
+            Ok(()) => Ok(self.spec),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,16 +288,34 @@ impl ServoConfig {
     /// - `pwm` is the embassy-rp PWM instance
     /// - `pwm_clock_hz` is the source clock feeding the PWM peripheral (125_000_000)
     /// - This config uses edge-aligned PWM (recommended for servos)
+    ///
+    /// A clock the spec's frame doesn't fit is clamped to the slowest divider and the
+    /// longest frame; [`try_new`](Self::try_new) reports it instead.
     pub fn new(pwm: &mut Pwm<'_>, pwm_clock_hz: u32, spec: &ServoSpec) -> Self {
-        let config = Self::new_precomputed(pwm_clock_hz, spec);
+        let (config, _) = Self::precompute(pwm_clock_hz, spec);
+        config.configure(pwm)
+    }
 
-        // Configure PWM with embassy-rp API
-        let mut pwm_config = embassy_rp::pwm::Config::default();
-        pwm_config.top = config.top;
-        pwm_config.divider = config.divider;
-        pwm.set_config(&pwm_config);
+    /// [`new`](Self::new) that fails with [`ServoError::ClockOutOfRange`] instead of
+    /// clamping.
+    pub fn try_new(
+        pwm: &mut Pwm<'_>,
+        pwm_clock_hz: u32,
+        spec: &ServoSpec,
+    ) -> Result<Self, ServoError> {
+        Ok(Self::try_new_precomputed(pwm_clock_hz, spec)?.configure(pwm))
+    }
+
+    /// Like [`ServoConfig::new`], with the PWM clock read from the running system clock, so
+    /// the timing stays correct whatever clock the firmware selected at init.
+    pub fn with_system_clock(pwm: &mut Pwm<'_>, spec: &ServoSpec) -> Self {
+        Self::new(pwm, sys_clock_hz(), spec)
+    }
 
-        config
+    /// [`ServoConfig::new_precomputed`] for the running system clock, clamped like
+    /// [`new`](Self::new)
+    pub fn precomputed_for_system_clock(spec: &ServoSpec) -> Self {
+        Self::precompute(sys_clock_hz(), spec).0
     }
 
     /// Pre-compute servo configuration without needing a PWM instance.
@@ -98,121 +323,1022 @@ impl ServoConfig {
     ///
     /// - `pwm_clock_hz` is the source clock feeding the PWM peripheral (125_000_000)
     /// - This config uses edge-aligned PWM (recommended for servos)
-    pub fn new_precomputed(pwm_clock_hz: u32, spec: &ServoSpec) -> Self {
+    ///
+    /// This is a `const fn`, so configs for a known clock can live in statics and a bad spec
+    /// fails the build:
+    ///
+    /// ```ignore
+    /// static ARM: ServoConfig =
+    ///     ServoConfig::new_precomputed(125_000_000, ServoSpec::makerhawk_mg995());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Where [`try_new_precomputed`](Self::try_new_precomputed) fails; use that for clocks
+    /// only known at run time.
+    pub const fn new_precomputed(pwm_clock_hz: u32, spec: &ServoSpec) -> Self {
+        match Self::try_new_precomputed(pwm_clock_hz, spec) {
+            Ok(config) => config,
+            Err(_) => panic!("servo frame doesn't fit the PWM clock"),
+        }
+    }
+
+    /// [`new_precomputed`](Self::new_precomputed) without the panic: fails with
+    /// [`ServoError::ClockOutOfRange`] if `pwm_clock_hz` is zero, or the frame doesn't fit
+    /// the PWM counter even at the slowest divider.
+    pub const fn try_new_precomputed(
+        pwm_clock_hz: u32,
+        spec: &ServoSpec,
+    ) -> Result<Self, ServoError> {
+        match Self::precompute(pwm_clock_hz, spec) {
+            (config, true) => Ok(config),
+            (_, false) => Err(ServoError::ClockOutOfRange),
+        }
+    }
+
+    /// The config for `pwm_clock_hz`, with TOP clamped to the counter, and whether the
+    /// frame really fit
+    const fn precompute(pwm_clock_hz: u32, spec: &ServoSpec) -> (Self, bool) {
         // Sanity clamps
-        let frame_us = max(1, spec.frame_us);
-        let pulse_min_us = min(spec.pulse_min_us, frame_us.saturating_sub(1));
-        let pulse_max_us = min(max(spec.pulse_max_us, pulse_min_us + 1), frame_us);
+        let frame_us = max_u32(1, spec.frame_us);
+        let pulse_min_us = min_u32(spec.pulse_min_us, frame_us.saturating_sub(1));
+        let pulse_max_us = min_u32(max_u32(spec.pulse_max_us, pulse_min_us + 1), frame_us);
 
         // Choose a PWM tick rate and divider so that TOP fits in u16.
         // We prefer 1 MHz (1 tick = 1 us) when possible.
-        let mut divider_q4: u32;
-        let mut tick_hz: u32;
-        let mut top: u32;
-
-        // Start with a target tick rate, then adjust divider until TOP fits.
-        let mut target_tick_hz: u32 = 1_000_000;
-
+        //
         // If frame is longer than 65536us, 1MHz won't fit in u16 TOP.
         // Drop tick rate so frame_us * tick_hz <= 65536 * 1_000_000.
         // (i.e. TOP <= 65535)
         let max_tick_hz_for_top = ((u16::MAX as u64 + 1) * 1_000_000u64 / frame_us as u64) as u32;
-        target_tick_hz = min(target_tick_hz, max(1, max_tick_hz_for_top));
+        let target_tick_hz = min_u32(1_000_000, max_u32(1, max_tick_hz_for_top));
 
         // Compute an initial divider (Q4 fixed-point: int.frac/16)
         // divider_q4 ~= clock_hz * 16 / target_tick_hz
-        divider_q4 = (((pwm_clock_hz as u64) * 16u64 + (target_tick_hz as u64 / 2))
+        let divider_q4 = (((pwm_clock_hz as u64) * 16u64 + (target_tick_hz as u64 / 2))
             / (target_tick_hz as u64)) as u32;
 
         // Divider must be at least 1.0 (16 in Q4) and at most 255.9375 (255*16 + 15).
-        divider_q4 = min(max(divider_q4, 16), 255 * 16 + 15);
+        let mut divider_q4 = min_u32(max_u32(divider_q4, 16), MAX_DIVIDER_Q4);
 
         // Now bump divider upward until TOP fits (or we hit max divider).
-        loop {
-            tick_hz = ((pwm_clock_hz as u64) * 16u64 / divider_q4 as u64) as u32;
+        let (tick_hz, top) = loop {
+            let tick_hz = ((pwm_clock_hz as u64) * 16u64 / divider_q4 as u64) as u32;
             // period_ticks = frame_us * tick_hz / 1_000_000
             let period_ticks = (frame_us as u64) * (tick_hz as u64) / 1_000_000u64;
-            top = period_ticks.saturating_sub(1) as u32;
+            let top = period_ticks.saturating_sub(1);
 
-            if top <= u16::MAX as u32 || divider_q4 >= (255 * 16 + 15) {
-                break;
+            if top <= u16::MAX as u64 || divider_q4 >= MAX_DIVIDER_Q4 {
+                break (tick_hz, top);
             }
             divider_q4 += 1; // slightly slower tick -> smaller TOP
-        }
-
-        let div_int = (divider_q4 / 16) as u8;
-        let div_frac = (divider_q4 % 16) as u8;
-
-        let top_u16 = min(top, u16::MAX as u32) as u16;
+        };
+        let fits = pwm_clock_hz > 0 && top <= u16::MAX as u64;
+        let top = if top > u16::MAX as u64 {
+            u16::MAX
+        } else {
+            top as u16
+        };
 
-        // divider is a FixedU16 representing the divider value
-        let divider_val = FixedU16::<U4>::from_bits(((div_int as u16) << 4) | (div_frac as u16));
+        // divider is a FixedU16 representing the divider value (int.frac in Q4)
+        let divider = FixedU16::<U4>::from_bits(divider_q4 as u16);
 
         // Convert pulse widths to duty counts.
-        let duty_min = us_to_counts(pulse_min_us, tick_hz, top_u16);
-        let duty_max = us_to_counts(pulse_max_us, tick_hz, top_u16);
+        let duty_min = us_to_counts(pulse_min_us, tick_hz, top);
+        let duty_max = us_to_counts(pulse_max_us, tick_hz, top);
 
-        Self {
-            top: top_u16,
-            divider: divider_val,
+        let config = Self {
+            top,
+            divider,
             tick_hz,
             angle_min: spec.angle_min_deg,
             angle_max: spec.angle_max_deg,
             duty_min,
             duty_max,
+        };
+        (config, fits)
+    }
+
+    /// Configure the PWM slice with embassy-rp API
+    fn configure(self, pwm: &mut Pwm<'_>) -> Self {
+        let mut pwm_config = embassy_rp::pwm::Config::default();
+        pwm_config.top = self.top;
+        pwm_config.divider = self.divider;
+        pwm.set_config(&pwm_config);
+        self
+    }
+}
+
+/// Per-servo pulse corrections on top of its [`ServoSpec`], in microseconds. Positive
+/// trims lengthen the pulse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct ServoCalibration {
+    /// Added to the pulse of the spec's min angle
+    pub min_trim_us: i16,
+    /// Added to the pulse of the spec's max angle
+    pub max_trim_us: i16,
+    /// Added to the pulse of the middle angle, after the end trims. Angles in between
+    /// follow two straight lines through the three points.
+    pub center_offset_us: i16,
+}
+
+impl ServoCalibration {
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let [a0, a1] = self.min_trim_us.to_le_bytes();
+        let [b0, b1] = self.max_trim_us.to_le_bytes();
+        let [c0, c1] = self.center_offset_us.to_le_bytes();
+        [a0, a1, b0, b1, c0, c1]
+    }
+
+    pub fn from_bytes(bytes: [u8; 6]) -> Self {
+        Self {
+            min_trim_us: i16::from_le_bytes([bytes[0], bytes[1]]),
+            max_trim_us: i16::from_le_bytes([bytes[2], bytes[3]]),
+            center_offset_us: i16::from_le_bytes([bytes[4], bytes[5]]),
+        }
+    }
+}
+
+/// Angle mapping and last commanded angle of one servo, whatever drives its pulse
+#[derive(Debug, Clone)]
+struct ServoState {
+    config: ServoConfig,
+    angle_min: I16F16,
+    angle_max: I16F16,
+    calibration: ServoCalibration,
+    /// Calibrated pulses of the end angles, in PWM counts
+    duty_min: u16,
+    duty_max: u16,
+    /// Calibrated pulse of the middle angle, if it is off the straight line
+    duty_center: Option<u16>,
+    /// Last commanded angle, clamped to the spec; `None` until the first one
+    angle: Option<I16F16>,
+    /// Last commanded pulse in PWM counts, kept while detached
+    duty: Option<u16>,
+    attached: bool,
+}
+
+impl ServoState {
+    fn new(config: ServoConfig) -> Self {
+        Self {
+            angle_min: I16F16::saturating_from_num(config.angle_min),
+            angle_max: I16F16::saturating_from_num(config.angle_max),
+            calibration: ServoCalibration::default(),
+            duty_min: config.duty_min,
+            duty_max: config.duty_max,
+            duty_center: None,
+            angle: None,
+            duty: None,
+            attached: true,
+            config,
+        }
+    }
+
+    fn set_calibration(&mut self, calibration: ServoCalibration) {
+        let trim = |duty: u16, trim_us: i16| {
+            let counts = trim_us as i64 * self.config.tick_hz as i64;
+            let counts = (counts + counts.signum() * 500_000) / 1_000_000;
+            (duty as i64 + counts).clamp(0, self.config.top as i64) as u16
+        };
+        self.duty_min = trim(self.config.duty_min, calibration.min_trim_us);
+        self.duty_max = trim(self.config.duty_max, calibration.max_trim_us);
+        self.duty_center = (calibration.center_offset_us != 0).then(|| {
+            let middle = (self.duty_min as u32 + self.duty_max as u32).div_ceil(2) as u16;
+            trim(middle, calibration.center_offset_us)
+        });
+        self.calibration = calibration;
+    }
+
+    fn angle_duty(&self, angle_deg: I16F16) -> u16 {
+        let (a0, a1) = (self.angle_min, self.angle_max);
+        let (d0, d1) = (self.duty_min, self.duty_max);
+        let top = self.config.top;
+        let Some(center) = self.duty_center else {
+            return angle_to_duty(angle_deg, (a0, a1), (d0, d1), top);
+        };
+        let middle = a0 + (a1 - a0) / 2;
+        let angle = self.clamp(angle_deg);
+        let lower_half = if a0 <= a1 {
+            angle <= middle
+        } else {
+            angle >= middle
+        };
+        if lower_half {
+            angle_to_duty(angle, (a0, middle), (d0, center), top)
+        } else {
+            angle_to_duty(angle, (middle, a1), (center, d1), top)
         }
     }
+
+    fn pulse_duty(&self, pulse_us: u32) -> Result<u16, ServoError> {
+        if pulse_us as u64 >= self.frame().as_micros() {
+            return Err(ServoError::PulseOutOfRange);
+        }
+        Ok(us_to_counts(pulse_us, self.config.tick_hz, self.config.top))
+    }
+
+    fn pulse_range_us(&self) -> (u32, u32) {
+        let to_us = |duty| counts_to_us(duty, self.config.tick_hz);
+        (to_us(self.duty_min), to_us(self.duty_max))
+    }
+
+    fn clamp(&self, angle: I16F16) -> I16F16 {
+        angle.clamp(
+            self.angle_min.min(self.angle_max),
+            self.angle_min.max(self.angle_max),
+        )
+    }
+
+    /// `duty` isn't what is being output already
+    fn is_new_duty(&self, duty: u16) -> bool {
+        !self.attached || self.duty != Some(duty)
+    }
+
+    /// PWM period; a new duty takes effect at the next one
+    fn frame(&self) -> Duration {
+        let ticks = self.config.top as u64 + 1;
+        Duration::from_micros(ticks * 1_000_000 / self.config.tick_hz.max(1) as u64)
+    }
 }
 
 /// Servo driver
 pub struct Servo<'a> {
     pwm: Pwm<'a>,
-    config: ServoConfig,
+    state: ServoState,
 }
 
 impl<'a> Servo<'a> {
     pub fn new(pwm: Pwm<'a>, config: ServoConfig) -> Self {
-        Self { pwm, config }
+        Self {
+            pwm,
+            state: ServoState::new(config),
+        }
     }
 
     /// Set the servo angle in degrees. Values outside the spec are clamped.
+    ///
+    /// Thin wrapper over [`Servo::set_angle_fixed`]; prefer that in animation loops.
     pub fn set_angle(&mut self, angle_deg: f32) -> Result<(), ServoError> {
-        // Handle weird specs safely.
-        let (a0, a1) = (self.config.angle_min, self.config.angle_max);
-        if (a1 - a0).abs() < f32::EPSILON {
-            self.pwm
-                .set_duty_cycle(self.config.duty_min)
-                .map_err(|_| ServoError::SetDutyCycle)?;
-            return Ok(());
+        self.set_angle_fixed(fixed_angle(angle_deg))
+    }
+
+    /// Set the servo angle in fixed-point degrees, using integer math only.
+    pub fn set_angle_fixed(&mut self, angle_deg: I16F16) -> Result<(), ServoError> {
+        self.output(self.state.angle_duty(angle_deg))?;
+        self.state.angle = Some(self.state.clamp(angle_deg));
+        Ok(())
+    }
+
+    /// Move to `angle_deg` at `deg_per_sec`, stepping once per PWM frame; returns once the
+    /// target is commanded. The servo may take a moment longer to get there.
+    ///
+    /// Jumps straight to the target if no angle was set before or the servo is detached
+    /// (the start is unknown either way), or if the speed isn't positive and finite.
+    pub async fn move_to(&mut self, angle_deg: f32, deg_per_sec: f32) -> Result<(), ServoError> {
+        self.move_to_eased(angle_deg, deg_per_sec, Easing::Linear)
+            .await
+    }
+
+    /// [`move_to`](Self::move_to) along an [`Easing`] curve, taking as long as the linear
+    /// move would: `deg_per_sec` is the average speed, and the peak is higher.
+    pub async fn move_to_eased(
+        &mut self,
+        angle_deg: f32,
+        deg_per_sec: f32,
+        easing: Easing,
+    ) -> Result<(), ServoError> {
+        let target = self.state.clamp(fixed_angle(angle_deg));
+        let Some(start) = self.state.angle.filter(|_| self.state.attached) else {
+            return self.set_angle_fixed(target);
+        };
+        if !(deg_per_sec > 0.0 && deg_per_sec.is_finite()) {
+            return self.set_angle_fixed(target);
+        }
+        let started = Instant::now();
+        loop {
+            let angle = ramp_angle(start, target, deg_per_sec, easing, started.elapsed());
+            self.set_angle_fixed(angle)?;
+            if angle == target {
+                return Ok(());
+            }
+            Timer::after(self.state.frame()).await;
+        }
+    }
+
+    /// Pulse widths of the spec's end angles in microseconds, calibrated and rounded to
+    /// PWM counts
+    pub fn pulse_range_us(&self) -> (u32, u32) {
+        self.state.pulse_range_us()
+    }
+
+    /// The spec's min and max angle in degrees
+    pub fn angle_range(&self) -> (f32, f32) {
+        (self.state.config.angle_min, self.state.config.angle_max)
+    }
+
+    pub fn calibration(&self) -> ServoCalibration {
+        self.state.calibration
+    }
+
+    /// Apply `calibration`, moving the servo to its corrected pulse for the current angle.
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) -> Result<(), ServoError> {
+        self.state.set_calibration(calibration);
+        match self.state.angle {
+            Some(angle) if self.state.attached => self.output(self.state.angle_duty(angle)),
+            Some(angle) => {
+                self.state.duty = Some(self.state.angle_duty(angle));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the calibration stored under `key`; `false` if there is none, leaving the
+    /// current one.
+    pub fn load_calibration(
+        &mut self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<bool, KvStoreError> {
+        match store.get_array::<6>(key)? {
+            Some(bytes) => {
+                // The duty only fails to change on a PWM error, which the next angle retries
+                let _ = self.set_calibration(ServoCalibration::from_bytes(bytes));
+                Ok(true)
+            }
+            None => Ok(false),
         }
+    }
+
+    pub fn save_calibration(
+        &self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<(), KvStoreError> {
+        store.set(key, &self.state.calibration.to_bytes())
+    }
 
-        // Clamp + normalize
-        let a = angle_deg.clamp(a0.min(a1), a0.max(a1));
-        let t = (a - a0) / (a1 - a0); // 0..1, works even if a1 < a0
+    /// Trim the ends and centre by hand with a [`ServoTrimCalibrator`] and save the result
+    /// under `key`. The servo moves while this runs. If cancelled or failed, the previous
+    /// calibration is put back.
+    pub async fn calibrate_interactive<D: TextDisplay, I: CalibrationInput>(
+        &mut self,
+        wizard: &mut CalibrationWizard<D, I>,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<CalibrationOutcome, CalibrationError> {
+        let previous = self.calibration();
+        let outcome = wizard
+            .run(&mut ServoTrimCalibrator::new(self, key), store)
+            .await;
+        if !matches!(outcome, Ok(CalibrationOutcome::Saved)) {
+            let _ = self.set_calibration(previous);
+        }
+        outcome
+    }
 
-        // Interpolate duty
-        let d0 = self.config.duty_min as i32;
-        let d1 = self.config.duty_max as i32;
-        let duty = libm::round((d0 as f32 + t * ((d1 - d0) as f32)) as f64) as i32;
+    /// Output a pulse of `pulse_us`, ignoring the angle mapping and the spec's pulse range.
+    /// Fails if the pulse doesn't fit in the PWM frame. A later [`move_to`](Self::move_to)
+    /// jumps, since the angle is unknown after this.
+    pub fn set_pulse_us(&mut self, pulse_us: u32) -> Result<(), ServoError> {
+        self.output(self.state.pulse_duty(pulse_us)?)?;
+        self.state.angle = None;
+        Ok(())
+    }
 
-        // Clamp to [0..TOP] just in case
-        let duty = duty.clamp(0, self.config.top as i32) as u16;
+    /// Stop sending pulses: the servo no longer holds its position, stops buzzing and
+    /// draws only idle current, and the horn can be turned by hand.
+    pub fn detach(&mut self) -> Result<(), ServoError> {
+        self.pwm
+            .set_duty_cycle(0)
+            .map_err(|_| ServoError::SetDutyCycle)?;
+        self.state.attached = false;
+        Ok(())
+    }
+
+    /// Resume the last pulse after [`detach`](Self::detach), so the servo returns to where
+    /// it was. Any new angle or pulse attaches as well.
+    pub fn attach(&mut self) -> Result<(), ServoError> {
+        if let Some(duty) = self.state.duty {
+            self.output(duty)?;
+        }
+        self.state.attached = true;
+        Ok(())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.state.attached
+    }
+
+    /// Last commanded angle in degrees, clamped to the spec. `None` before the first angle
+    /// and after a raw [`set_pulse_us`](Self::set_pulse_us).
+    pub fn angle(&self) -> Option<f32> {
+        self.state.angle.map(|angle| angle.to_num())
+    }
+
+    /// [`angle`](Self::angle) in fixed-point degrees
+    pub fn angle_fixed(&self) -> Option<I16F16> {
+        self.state.angle
+    }
+
+    /// Last commanded pulse in PWM counts (see [`ServoConfig::top`]), kept while detached.
+    /// `None` before the first angle or pulse.
+    pub fn duty(&self) -> Option<u16> {
+        self.state.duty
+    }
+
+    /// Skipped if `duty` is already being output
+    fn output(&mut self, duty: u16) -> Result<(), ServoError> {
+        if !self.state.is_new_duty(duty) {
+            return Ok(());
+        }
         self.pwm
             .set_duty_cycle(duty)
             .map_err(|_| ServoError::SetDutyCycle)?;
+        self.state.duty = Some(duty);
+        self.state.attached = true;
+        Ok(())
+    }
+}
+
+/// Output of a PWM slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PwmChannel {
+    A,
+    B,
+}
+
+/// Two servos on channels A and B of one PWM slice, for twice the servos per slice.
+///
+/// The slice has one counter, so both servos share the frame: TOP and the divider are
+/// set once, and each channel only gets its own duty. The two may still be different
+/// models, as long as their [`ServoSpec`]s have the same `frame_us`.
+pub struct ServoPair<'a> {
+    pwm: Pwm<'a>,
+    pwm_config: embassy_rp::pwm::Config,
+    a: ServoState,
+    b: ServoState,
+}
+
+impl<'a> ServoPair<'a> {
+    /// `pwm` must be created with `Pwm::new_output_ab`. Both channels start with no pulse,
+    /// which leaves the servos limp until their first angle.
+    ///
+    /// Fails with [`ServoError::FrameMismatch`] if the configs disagree on TOP or the
+    /// divider, i.e. were made for different frames or clocks.
+    pub fn new(
+        pwm: Pwm<'a>,
+        config_a: ServoConfig,
+        config_b: ServoConfig,
+    ) -> Result<Self, ServoError> {
+        if config_a.top != config_b.top || config_a.divider != config_b.divider {
+            return Err(ServoError::FrameMismatch);
+        }
+        let mut pwm_config = embassy_rp::pwm::Config::default();
+        pwm_config.top = config_a.top;
+        pwm_config.divider = config_a.divider;
+        pwm_config.compare_a = 0;
+        pwm_config.compare_b = 0;
+        let mut pair = Self {
+            pwm,
+            pwm_config,
+            a: ServoState::new(config_a),
+            b: ServoState::new(config_b),
+        };
+        pair.apply();
+        Ok(pair)
+    }
+
+    /// Set the angle of one servo in degrees. Values outside its spec are clamped.
+    pub fn set_angle(&mut self, channel: PwmChannel, angle_deg: f32) -> Result<(), ServoError> {
+        self.set_angle_fixed(channel, fixed_angle(angle_deg))
+    }
+
+    /// Set the angle of one servo in fixed-point degrees, using integer math only.
+    pub fn set_angle_fixed(
+        &mut self,
+        channel: PwmChannel,
+        angle_deg: I16F16,
+    ) -> Result<(), ServoError> {
+        let state = self.state_mut(channel);
+        let duty = state.angle_duty(angle_deg);
+        state.angle = Some(state.clamp(angle_deg));
+        self.output(channel, duty);
+        Ok(())
+    }
+
+    /// Pulse widths of one servo's end angles in microseconds; see [`Servo::pulse_range_us`].
+    pub fn pulse_range_us(&self, channel: PwmChannel) -> (u32, u32) {
+        self.state(channel).pulse_range_us()
+    }
+
+    pub fn calibration(&self, channel: PwmChannel) -> ServoCalibration {
+        self.state(channel).calibration
+    }
+
+    /// Calibrate one servo; see [`Servo::set_calibration`].
+    pub fn set_calibration(&mut self, channel: PwmChannel, calibration: ServoCalibration) {
+        let state = self.state_mut(channel);
+        state.set_calibration(calibration);
+        let Some(duty) = state.angle.map(|angle| state.angle_duty(angle)) else {
+            return;
+        };
+        if state.attached {
+            self.output(channel, duty);
+        } else {
+            state.duty = Some(duty);
+        }
+    }
+
+    /// Output a raw pulse on one channel; see [`Servo::set_pulse_us`].
+    pub fn set_pulse_us(&mut self, channel: PwmChannel, pulse_us: u32) -> Result<(), ServoError> {
+        let state = self.state_mut(channel);
+        let duty = state.pulse_duty(pulse_us)?;
+        state.angle = None;
+        self.output(channel, duty);
+        Ok(())
+    }
+
+    /// Stop the pulses of one servo; see [`Servo::detach`].
+    pub fn detach(&mut self, channel: PwmChannel) {
+        self.set_compare(channel, 0);
+        self.state_mut(channel).attached = false;
+    }
+
+    /// Resume the last pulse of one servo; see [`Servo::attach`].
+    pub fn attach(&mut self, channel: PwmChannel) {
+        if let Some(duty) = self.state(channel).duty {
+            self.output(channel, duty);
+        }
+        self.state_mut(channel).attached = true;
+    }
+
+    pub fn is_attached(&self, channel: PwmChannel) -> bool {
+        self.state(channel).attached
+    }
+
+    /// Last commanded angle of one servo; see [`Servo::angle`].
+    pub fn angle(&self, channel: PwmChannel) -> Option<f32> {
+        self.state(channel).angle.map(|angle| angle.to_num())
+    }
+
+    /// Last commanded pulse of one servo in PWM counts; see [`Servo::duty`].
+    pub fn duty(&self, channel: PwmChannel) -> Option<u16> {
+        self.state(channel).duty
+    }
+
+    fn state(&self, channel: PwmChannel) -> &ServoState {
+        match channel {
+            PwmChannel::A => &self.a,
+            PwmChannel::B => &self.b,
+        }
+    }
+
+    fn state_mut(&mut self, channel: PwmChannel) -> &mut ServoState {
+        match channel {
+            PwmChannel::A => &mut self.a,
+            PwmChannel::B => &mut self.b,
+        }
+    }
+
+    /// Skipped if `duty` is already being output
+    fn output(&mut self, channel: PwmChannel, duty: u16) {
+        if !self.state(channel).is_new_duty(duty) {
+            return;
+        }
+        self.set_compare(channel, duty);
+        let state = self.state_mut(channel);
+        state.duty = Some(duty);
+        state.attached = true;
+    }
+
+    fn set_compare(&mut self, channel: PwmChannel, duty: u16) {
+        match channel {
+            PwmChannel::A => self.pwm_config.compare_a = duty,
+            PwmChannel::B => self.pwm_config.compare_b = duty,
+        }
+        self.apply();
+    }
+
+    fn apply(&mut self) {
+        self.pwm.set_config(&self.pwm_config);
+    }
+}
+
+/// Servos that move as one, each to its own angle over the same time
+pub struct ServoGroup<'a, const N: usize> {
+    servos: [Servo<'a>; N],
+}
+
+impl<'a, const N: usize> ServoGroup<'a, N> {
+    pub fn new(servos: [Servo<'a>; N]) -> Self {
+        Self { servos }
+    }
+
+    pub fn servos(&self) -> &[Servo<'a>; N] {
+        &self.servos
+    }
+
+    /// The servos, e.g. to move one of them alone
+    pub fn servos_mut(&mut self) -> &mut [Servo<'a>; N] {
+        &mut self.servos
+    }
+
+    pub fn into_inner(self) -> [Servo<'a>; N] {
+        self.servos
+    }
+
+    /// Set every servo's angle at once, in group order.
+    pub fn set_all(&mut self, angles: &[f32; N]) -> Result<(), ServoError> {
+        for (servo, &angle) in self.servos.iter_mut().zip(angles) {
+            servo.set_angle(angle)?;
+        }
         Ok(())
     }
+
+    /// Move every servo to its angle in `angles`, all arriving after `duration`, and return
+    /// once they are commanded there. Each servo's speed is its distance over `duration`.
+    ///
+    /// Servos with no known start (no angle set yet, or detached) jump to their target at
+    /// the beginning.
+    pub async fn move_all_to(
+        &mut self,
+        angles: &[f32; N],
+        duration: Duration,
+    ) -> Result<(), ServoError> {
+        self.move_all_to_eased(angles, duration, Easing::Linear)
+            .await
+    }
+
+    /// [`move_all_to`](Self::move_all_to) with every servo following `easing`.
+    pub async fn move_all_to_eased(
+        &mut self,
+        angles: &[f32; N],
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), ServoError> {
+        let targets: [I16F16; N] =
+            core::array::from_fn(|i| self.servos[i].state.clamp(fixed_angle(angles[i])));
+        let starts: [Option<I16F16>; N] = core::array::from_fn(|i| {
+            let state = &self.servos[i].state;
+            state.angle.filter(|_| state.attached)
+        });
+        let Some(frame) = self.servos.iter().map(|servo| servo.state.frame()).min() else {
+            return Ok(());
+        };
+        let started = Instant::now();
+        loop {
+            let progress = move_progress(started.elapsed(), duration);
+            let eased = easing.apply(progress);
+            for ((servo, start), target) in self.servos.iter_mut().zip(starts).zip(targets) {
+                let angle = match start {
+                    Some(start) => start + (target - start).saturating_mul(eased),
+                    None => target,
+                };
+                servo.set_angle_fixed(angle)?;
+            }
+            if progress == I16F16::ONE {
+                return Ok(());
+            }
+            Timer::after(frame).await;
+        }
+    }
+
+    /// Stop the pulses of every servo; see [`Servo::detach`].
+    pub fn detach_all(&mut self) -> Result<(), ServoError> {
+        self.servos.iter_mut().try_for_each(Servo::detach)
+    }
+
+    /// Resume every servo; see [`Servo::attach`].
+    pub fn attach_all(&mut self) -> Result<(), ServoError> {
+        self.servos.iter_mut().try_for_each(Servo::attach)
+    }
+}
+
+/// Anything that can be positioned by angle: a [`Servo`] or one channel of a servo
+/// controller. Lets higher-level code (animations, groups) drive mixed hardware.
+pub trait ServoChannel {
+    fn set_angle(&mut self, angle_deg: f32) -> Result<(), ServoError>;
+}
+
+impl ServoChannel for Servo<'_> {
+    fn set_angle(&mut self, angle_deg: f32) -> Result<(), ServoError> {
+        Servo::set_angle(self, angle_deg)
+    }
 }
 
 #[derive(Debug, defmt::Format, thiserror::Error)]
 pub enum ServoError {
     #[error("Failed to set duty cycle")]
     SetDutyCycle,
+    #[error("Pulse longer than the PWM frame")]
+    PulseOutOfRange,
+    #[error("Servos on one PWM slice need the same frame")]
+    FrameMismatch,
+    #[error("No servo on this channel")]
+    InvalidChannel,
+    #[error("Servo frame doesn't fit the PWM clock")]
+    ClockOutOfRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum ServoSpecError {
+    #[error("Servo frame must be 2.5-50 ms")]
+    FrameOutOfRange,
+    #[error("Minimum pulse must be above zero and below the maximum")]
+    PulseRange,
+    #[error("Maximum pulse must be shorter than the frame")]
+    PulseLongerThanFrame,
+    #[error("Angle range must be finite and not empty")]
+    AngleRange,
+}
+
+/// NaN and out-of-range values saturate, so they end up clamped to the spec.
+fn fixed_angle(angle_deg: f32) -> I16F16 {
+    I16F16::checked_from_num(angle_deg).unwrap_or(if angle_deg > 0.0 {
+        I16F16::MAX
+    } else {
+        I16F16::MIN
+    })
+}
+
+/// Angle `elapsed` into a ramp from `start` to `target` at an average of `deg_per_sec`
+fn ramp_angle(
+    start: I16F16,
+    target: I16F16,
+    deg_per_sec: f32,
+    easing: Easing,
+    elapsed: Duration,
+) -> I16F16 {
+    let travel = deg_per_sec * elapsed.as_micros() as f32 / 1_000_000.0;
+    let distance = (target - start).to_num::<f32>();
+    if travel >= distance.abs() {
+        return target;
+    }
+    if easing == Easing::Linear {
+        return start + I16F16::saturating_from_num(travel.copysign(distance));
+    }
+    let progress = I16F16::saturating_from_num(travel / distance.abs());
+    start + (target - start).saturating_mul(easing.apply(progress))
+}
+
+/// Fraction of a `duration` long move done after `elapsed`, from 0 to 1
+fn move_progress(elapsed: Duration, duration: Duration) -> I16F16 {
+    let total = duration.as_micros();
+    if total == 0 {
+        return I16F16::ONE;
+    }
+    let bits = (elapsed.as_micros().min(total) << 16) / total;
+    I16F16::from_bits(bits as i32)
+}
+
+/// Interpolate the duty for `angle` between the spec's end points, rounded to the nearest
+/// count and clamped to `[0..=top]`.
+fn angle_to_duty(angle: I16F16, angles: (I16F16, I16F16), duties: (u16, u16), top: u16) -> u16 {
+    let (a0, a1) = angles;
+    let (d0, d1) = duties;
+    // Handle weird specs safely.
+    if a0 == a1 {
+        return d0.min(top);
+    }
+
+    // Clamp + normalize: t = num / den in 0..=1, works even if a1 < a0
+    let a = angle.clamp(a0.min(a1), a0.max(a1));
+    let mut num = (a - a0).to_bits() as i64;
+    let mut den = (a1 - a0).to_bits() as i64;
+    if den < 0 {
+        num = -num;
+        den = -den;
+    }
+
+    // Interpolate duty, rounding half away from zero
+    let delta = (d1 as i64 - d0 as i64) * num;
+    let step = if delta >= 0 {
+        (delta + den / 2) / den
+    } else {
+        (delta - den / 2) / den
+    };
+    (d0 as i64 + step).clamp(0, top as i64) as u16
 }
 
-fn us_to_counts(pulse_us: u32, tick_hz: u32, top: u16) -> u16 {
+const fn us_to_counts(pulse_us: u32, tick_hz: u32, top: u16) -> u16 {
     // counts = pulse_us * tick_hz / 1_000_000, rounded
     let counts = ((pulse_us as u64) * (tick_hz as u64) + 500_000u64) / 1_000_000u64;
-    let counts = min(counts as u32, top as u32);
+    let counts = min_u32(counts as u32, top as u32);
     counts as u16
 }
+
+/// Inverse of [`us_to_counts`], rounded
+fn counts_to_us(counts: u16, tick_hz: u32) -> u32 {
+    let tick_hz = tick_hz.max(1) as u64;
+    ((counts as u64 * 1_000_000 + tick_hz / 2) / tick_hz) as u32
+}
+
+// `core::cmp::{min, max}` aren't const
+const fn min_u32(a: u32, b: u32) -> u32 {
+    if a < b { a } else { b }
+}
+
+const fn max_u32(a: u32, b: u32) -> u32 {
+    if a > b { a } else { b }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deg(value: i32) -> I16F16 {
+        I16F16::from_num(value)
+    }
+
+    #[test]
+    fn angle_to_duty_interpolates() {
+        let angles = (deg(0), deg(180));
+        let duties = (500, 2500);
+        assert_eq!(angle_to_duty(deg(0), angles, duties, 19_999), 500);
+        assert_eq!(angle_to_duty(deg(90), angles, duties, 19_999), 1500);
+        assert_eq!(angle_to_duty(deg(180), angles, duties, 19_999), 2500);
+        assert_eq!(
+            angle_to_duty(I16F16::from_num(45.5), angles, duties, 19_999),
+            1006
+        );
+        assert_eq!(angle_to_duty(deg(-30), angles, duties, 19_999), 500);
+        assert_eq!(angle_to_duty(deg(400), angles, duties, 19_999), 2500);
+        assert_eq!(angle_to_duty(deg(400), angles, duties, 2000), 2000);
+    }
+
+    #[test]
+    fn precomputed_at_compile_time() {
+        const KS0209: ServoConfig =
+            ServoConfig::new_precomputed(125_000_000, ServoSpec::inland_ks0209());
+        assert_eq!(KS0209.tick_hz, 1_000_000);
+        assert_eq!(KS0209.top, 19_999);
+        assert_eq!(KS0209.divider, FixedU16::<U4>::from_num(125));
+        assert_eq!((KS0209.duty_min, KS0209.duty_max), (1000, 2000));
+        assert_eq!(counts_to_us(KS0209.duty_max, KS0209.tick_hz), 2000);
+
+        let slow = ServoSpec {
+            frame_us: 100_000,
+            ..*ServoSpec::inland_ks0209()
+        };
+        let config = ServoConfig::new_precomputed(125_000_000, &slow);
+        assert!(config.tick_hz < 655_360);
+        assert_eq!(
+            config.duty_max,
+            us_to_counts(2000, config.tick_hz, config.top)
+        );
+        assert!(counts_to_us(config.duty_max, config.tick_hz).abs_diff(2000) <= 1);
+    }
+
+    #[test]
+    fn unusable_clock_is_an_error() {
+        let spec = ServoSpec::inland_ks0209();
+        assert!(matches!(
+            ServoConfig::try_new_precomputed(0, spec),
+            Err(ServoError::ClockOutOfRange)
+        ));
+        // Even the slowest divider ticks too fast for a 20 ms frame in 16 bits
+        assert!(matches!(
+            ServoConfig::try_new_precomputed(u32::MAX, spec),
+            Err(ServoError::ClockOutOfRange)
+        ));
+        // ServoConfig::new clamps it to the longest frame instead
+        let (clamped, fits) = ServoConfig::precompute(u32::MAX, spec);
+        assert!(!fits);
+        assert_eq!(clamped.top, u16::MAX);
+    }
+
+    #[test]
+    fn catalog_specs_are_valid_and_builder_checks() {
+        for spec in [
+            ServoSpec::inland_ks0209(),
+            ServoSpec::makerhawk_mg995(),
+            ServoSpec::towerpro_sg90(),
+            ServoSpec::towerpro_mg90s(),
+            ServoSpec::dsservo_ds3218(),
+            ServoSpec::feetech_fs90r(),
+        ] {
+            assert_eq!(spec.validate(), Ok(()));
+        }
+        assert_eq!(ServoSpec::feetech_fs90r().pulse_us(0.0), 1500);
+
+        const FAST: Result<ServoSpec, ServoSpecError> = ServoSpec::builder()
+            .frame_us(3_030)
+            .pulse_range_us(900, 2100)
+            .angle_range(90.0, -90.0)
+            .build();
+        let fast = FAST.unwrap();
+        assert_eq!((fast.frame_us, fast.pulse_min_us), (3_030, 900));
+        assert_eq!(fast.pulse_us(-90.0), 2100);
+
+        let builder = ServoSpec::builder();
+        assert_eq!(
+            builder.pulse_range_us(2000, 1000).build().err(),
+            Some(ServoSpecError::PulseRange)
+        );
+        assert_eq!(
+            builder.frame_us(2_000).build().err(),
+            Some(ServoSpecError::FrameOutOfRange)
+        );
+        assert_eq!(
+            builder
+                .frame_us(2_500)
+                .pulse_range_us(500, 2500)
+                .build()
+                .err(),
+            Some(ServoSpecError::PulseLongerThanFrame)
+        );
+        assert_eq!(
+            builder.angle_range(45.0, 45.0).build().err(),
+            Some(ServoSpecError::AngleRange)
+        );
+        assert_eq!(
+            builder.angle_range(0.0, f32::NAN).build().err(),
+            Some(ServoSpecError::AngleRange)
+        );
+    }
+
+    #[test]
+    fn ramp_limits_speed() {
+        let ms = Duration::from_millis;
+        let linear = Easing::Linear;
+        assert_eq!(ramp_angle(deg(0), deg(90), 60.0, linear, ms(0)), deg(0));
+        assert_eq!(ramp_angle(deg(0), deg(90), 60.0, linear, ms(500)), deg(30));
+        assert_eq!(ramp_angle(deg(90), deg(0), 60.0, linear, ms(1000)), deg(30));
+        assert_eq!(ramp_angle(deg(0), deg(90), 60.0, linear, ms(1600)), deg(90));
+        assert_eq!(ramp_angle(deg(45), deg(45), 60.0, linear, ms(0)), deg(45));
+    }
+
+    #[test]
+    fn eased_ramp_takes_as_long() {
+        let ms = Duration::from_millis;
+        let sine = Easing::EaseInOutSine;
+        assert_eq!(ramp_angle(deg(0), deg(90), 60.0, sine, ms(0)), deg(0));
+        // Slow start, the middle on time
+        assert!(ramp_angle(deg(0), deg(90), 60.0, sine, ms(300)) < deg(18));
+        let middle = ramp_angle(deg(90), deg(0), 60.0, sine, ms(750));
+        assert!((middle - deg(45)).abs() < I16F16::from_num(0.01));
+        assert_eq!(ramp_angle(deg(0), deg(90), 60.0, sine, ms(1500)), deg(90));
+    }
+
+    #[test]
+    fn unchanged_duty_is_not_output_again() {
+        const KS0209: ServoConfig =
+            ServoConfig::new_precomputed(125_000_000, ServoSpec::inland_ks0209());
+        let mut state = ServoState::new(KS0209);
+        assert!(state.is_new_duty(1500));
+        state.duty = Some(1500);
+        assert!(!state.is_new_duty(1500));
+        assert!(state.is_new_duty(1501));
+        state.attached = false;
+        assert!(state.is_new_duty(1500));
+    }
+
+    #[test]
+    fn group_moves_share_progress() {
+        let ms = Duration::from_millis;
+        assert_eq!(move_progress(ms(0), ms(2000)), I16F16::ZERO);
+        assert_eq!(move_progress(ms(500), ms(2000)), I16F16::from_num(0.25));
+        assert_eq!(move_progress(ms(2000), ms(2000)), I16F16::ONE);
+        assert_eq!(move_progress(ms(5000), ms(2000)), I16F16::ONE);
+        assert_eq!(move_progress(ms(0), ms(0)), I16F16::ONE);
+    }
+
+    #[test]
+    fn calibration_trims_ends_and_centre() {
+        const KS0209: ServoConfig =
+            ServoConfig::new_precomputed(125_000_000, ServoSpec::inland_ks0209());
+        let mut state = ServoState::new(KS0209);
+        let calibration = ServoCalibration {
+            min_trim_us: -100,
+            max_trim_us: 50,
+            center_offset_us: 20,
+        };
+        state.set_calibration(calibration);
+        assert_eq!(state.pulse_range_us(), (900, 2050));
+        assert_eq!(state.angle_duty(deg(0)), 900);
+        assert_eq!(state.angle_duty(deg(45)), 1495);
+        assert_eq!(state.angle_duty(I16F16::from_num(22.5)), 1198);
+        assert_eq!(state.angle_duty(deg(90)), 2050);
+        assert_eq!(state.angle_duty(deg(120)), 2050);
+        assert_eq!(
+            ServoCalibration::from_bytes(calibration.to_bytes()),
+            calibration
+        );
+
+        state.set_calibration(ServoCalibration::default());
+        assert_eq!(state.angle_duty(deg(45)), 1500);
+        assert_eq!(state.pulse_range_us(), (1000, 2000));
+    }
+
+    #[test]
+    fn angle_to_duty_handles_reversed_and_empty_ranges() {
+        let reversed = (deg(90), deg(0));
+        assert_eq!(angle_to_duty(deg(0), reversed, (1000, 2000), 19_999), 2000);
+        assert_eq!(angle_to_duty(deg(60), reversed, (1000, 2000), 19_999), 1333);
+        assert_eq!(
+            angle_to_duty(deg(30), (deg(10), deg(10)), (1000, 2000), 19_999),
+            1000
+        );
+    }
+}
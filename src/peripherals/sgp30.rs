@@ -0,0 +1,173 @@
+//! sgp30.rs — Sensirion SGP30 eCO2/TVOC sensor driver (I2C)
+
+use embassy_time::Timer;
+
+use crate::{AIR_QUALITY_BASELINE_LEN, AirQualityBaseline, AirQualityReading, AirQualitySensor};
+
+pub const SGP30_I2C_ADDRESS: u8 = 0x58;
+
+const CMD_INIT_AIR_QUALITY: u16 = 0x2003;
+const CMD_MEASURE_AIR_QUALITY: u16 = 0x2008;
+const CMD_GET_BASELINE: u16 = 0x2015;
+const CMD_SET_BASELINE: u16 = 0x201E;
+const CMD_SET_HUMIDITY: u16 = 0x2061;
+const CMD_GET_SERIAL_ID: u16 = 0x3682;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum Sgp30Error {
+    #[error("I2C communication with SGP30 failed")]
+    Communication,
+    #[error("SGP30 response failed CRC check")]
+    Crc,
+}
+
+/// SGP30 baseline compensation values, as returned by the sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Sgp30Baseline {
+    pub eco2: u16,
+    pub tvoc: u16,
+}
+
+impl AirQualityBaseline for Sgp30Baseline {
+    fn to_bytes(&self) -> [u8; AIR_QUALITY_BASELINE_LEN] {
+        let [e0, e1] = self.eco2.to_le_bytes();
+        let [t0, t1] = self.tvoc.to_le_bytes();
+        [e0, e1, t0, t1]
+    }
+
+    fn from_bytes(bytes: [u8; AIR_QUALITY_BASELINE_LEN]) -> Self {
+        Self {
+            eco2: u16::from_le_bytes([bytes[0], bytes[1]]),
+            tvoc: u16::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// SGP30 driver
+///
+/// The sensor's baseline algorithm expects [`Sgp30::measure`] to be called once per second.
+/// For the first ~15 s after init it reports fixed 400 ppm / 0 ppb.
+pub struct Sgp30<I: embedded_hal::i2c::I2c> {
+    i2c: I,
+}
+
+impl<I: embedded_hal::i2c::I2c> Sgp30<I> {
+    /// Start the on-chip air-quality algorithm.
+    pub async fn new(i2c: I) -> Result<Self, Sgp30Error> {
+        let mut sensor = Self { i2c };
+        sensor.command(CMD_INIT_AIR_QUALITY, &[])?;
+        Timer::after_millis(10).await;
+        Ok(sensor)
+    }
+
+    /// 48-bit unique serial number
+    pub async fn serial_id(&mut self) -> Result<u64, Sgp30Error> {
+        let mut words = [0u16; 3];
+        self.command(CMD_GET_SERIAL_ID, &[])?;
+        Timer::after_millis(1).await;
+        self.read_words(&mut words)?;
+        Ok(((words[0] as u64) << 32) | ((words[1] as u64) << 16) | words[2] as u64)
+    }
+
+    pub async fn measure(&mut self) -> Result<AirQualityReading, Sgp30Error> {
+        let mut words = [0u16; 2];
+        self.command(CMD_MEASURE_AIR_QUALITY, &[])?;
+        Timer::after_millis(12).await;
+        self.read_words(&mut words)?;
+        Ok(AirQualityReading {
+            eco2_ppm: words[0],
+            tvoc_ppb: words[1],
+        })
+    }
+
+    pub async fn baseline(&mut self) -> Result<Sgp30Baseline, Sgp30Error> {
+        let mut words = [0u16; 2];
+        self.command(CMD_GET_BASELINE, &[])?;
+        Timer::after_millis(10).await;
+        self.read_words(&mut words)?;
+        Ok(Sgp30Baseline {
+            eco2: words[0],
+            tvoc: words[1],
+        })
+    }
+
+    pub async fn set_baseline(&mut self, baseline: Sgp30Baseline) -> Result<(), Sgp30Error> {
+        // Written in the reverse order of get_baseline (datasheet).
+        self.command(CMD_SET_BASELINE, &[baseline.tvoc, baseline.eco2])?;
+        Timer::after_millis(10).await;
+        Ok(())
+    }
+
+    /// Enable humidity compensation. `absolute_humidity_g_m3` is in g/m³, 8.8 fixed point.
+    pub async fn set_absolute_humidity(
+        &mut self,
+        absolute_humidity_g_m3: u16,
+    ) -> Result<(), Sgp30Error> {
+        self.command(CMD_SET_HUMIDITY, &[absolute_humidity_g_m3])?;
+        Timer::after_millis(10).await;
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: u16, args: &[u16]) -> Result<(), Sgp30Error> {
+        let mut buf = [0u8; 8];
+        buf[..2].copy_from_slice(&cmd.to_be_bytes());
+        let mut len = 2;
+        for arg in args.iter().take(2) {
+            let bytes = arg.to_be_bytes();
+            buf[len..len + 2].copy_from_slice(&bytes);
+            buf[len + 2] = sensirion_crc8(&bytes);
+            len += 3;
+        }
+        self.i2c
+            .write(SGP30_I2C_ADDRESS, &buf[..len])
+            .map_err(|_| Sgp30Error::Communication)
+    }
+
+    fn read_words(&mut self, words: &mut [u16]) -> Result<(), Sgp30Error> {
+        let mut buf = [0u8; 9];
+        let len = words.len() * 3;
+        self.i2c
+            .read(SGP30_I2C_ADDRESS, &mut buf[..len])
+            .map_err(|_| Sgp30Error::Communication)?;
+        for (word, chunk) in words.iter_mut().zip(buf[..len].chunks_exact(3)) {
+            if sensirion_crc8(&chunk[..2]) != chunk[2] {
+                return Err(Sgp30Error::Crc);
+            }
+            *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(())
+    }
+}
+
+impl<I: embedded_hal::i2c::I2c> AirQualitySensor for Sgp30<I> {
+    type Error = Sgp30Error;
+    type Baseline = Sgp30Baseline;
+
+    async fn measure(&mut self) -> Result<Option<AirQualityReading>, Self::Error> {
+        Sgp30::measure(self).await.map(Some)
+    }
+
+    async fn baseline(&mut self) -> Result<Self::Baseline, Self::Error> {
+        Sgp30::baseline(self).await
+    }
+
+    async fn set_baseline(&mut self, baseline: Self::Baseline) -> Result<(), Self::Error> {
+        Sgp30::set_baseline(self, baseline).await
+    }
+}
+
+/// CRC-8 used by Sensirion sensors (poly 0x31, init 0xFF)
+fn sensirion_crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
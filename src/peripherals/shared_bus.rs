@@ -0,0 +1,40 @@
+//! shared_bus.rs — one I2C or SPI bus shared by several async drivers
+//!
+//! Like the ADC ([`SharedAdc`](crate::SharedAdc)), a bus lives behind an async mutex
+//! ([`SharedI2cBus`], [`SharedSpiBus`]) and each device gets a handle. The handles are
+//! `embassy-embedded-hal`'s shared-bus devices and implement the `embedded-hal-async`
+//! device traits (`I2c`, `SpiDevice`), so any third-party async sensor driver can take one
+//! directly. A handle holds the bus only for one transaction; an SPI handle also drives its
+//! chip select around it, releasing it only once the last byte has left.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static I2C_BUS: StaticCell<SharedI2cBus<I2c<'static, I2C0, i2c::Async>>> = StaticCell::new();
+//! let bus = I2C_BUS.init(Mutex::new(I2c::new_async(p.I2C0, p.PIN_5, p.PIN_4, Irqs, Default::default())));
+//!
+//! let mut bme280 = Bme280::new(SharedI2cDevice::new(bus));
+//! let mut rtc = SomeAsyncRtc::new(SharedI2cDevice::new(bus));
+//!
+//! static SPI_BUS: StaticCell<SharedSpiBus<Spi<'static, SPI0, spi::Async>>> = StaticCell::new();
+//! let spi = SPI_BUS.init(Mutex::new(Spi::new(p.SPI0, p.PIN_18, p.PIN_19, p.PIN_16, p.DMA_CH0, p.DMA_CH1, config)));
+//! let flash = SharedSpiDevice::new(spi, Output::new(p.PIN_17, Level::High));
+//! ```
+
+use embassy_embedded_hal::shared_bus::asynch::{i2c::I2cDevice, spi::SpiDevice};
+use embassy_rp::gpio::Output;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+
+/// An I2C bus shared between several [`SharedI2cDevice`]s
+pub type SharedI2cBus<B> = Mutex<CriticalSectionRawMutex, B>;
+
+/// An SPI bus shared between several [`SharedSpiDevice`]s
+pub type SharedSpiBus<B> = Mutex<CriticalSectionRawMutex, B>;
+
+/// One device on a [`SharedI2cBus`]
+pub type SharedI2cDevice<'a, B> = I2cDevice<'a, CriticalSectionRawMutex, B>;
+
+/// One device on a [`SharedSpiBus`], selected by its own chip-select pin (active low).
+/// `cs` should start high, so the device isn't selected before its first transaction.
+pub type SharedSpiDevice<'a, B> = SpiDevice<'a, CriticalSectionRawMutex, B, Output<'a>>;
@@ -0,0 +1,136 @@
+//! soil_moisture.rs — calibrated soil moisture sensor on an ADC pin
+//!
+//! Works with resistive and capacitive probes: calibrate once in dry air and once in
+//! water, save the result in the key/value store, and read moisture as a percentage.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut soil = SoilMoisture::new(AnalogInput::new(adc, channel), Default::default());
+//! soil.load_calibration(&mut store, "soil/cal")?;
+//!
+//! // once, e.g. from a setup menu:
+//! soil.calibrate_dry().await?;
+//! soil.calibrate_wet().await?;
+//! soil.save_calibration(&mut store, "soil/cal")?;
+//!
+//! let percent = soil.read_percent().await?;
+//! ```
+
+use crate::{AnalogInput, AnalogInputError, KvStore, KvStoreError};
+
+/// Samples averaged for each reading
+const SAMPLES: u16 = 16;
+
+/// Raw ADC readings at the two calibration points
+///
+/// Capacitive probes read lower when wet, resistive ones higher; both work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct SoilMoistureCalibration {
+    pub dry_raw: u16,
+    pub wet_raw: u16,
+}
+
+impl Default for SoilMoistureCalibration {
+    /// Typical capacitive probe v1.2 on 3V3
+    fn default() -> Self {
+        Self {
+            dry_raw: 2800,
+            wet_raw: 1300,
+        }
+    }
+}
+
+impl SoilMoistureCalibration {
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let [d0, d1] = self.dry_raw.to_le_bytes();
+        let [w0, w1] = self.wet_raw.to_le_bytes();
+        [d0, d1, w0, w1]
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            dry_raw: u16::from_le_bytes([bytes[0], bytes[1]]),
+            wet_raw: u16::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Map a raw reading to 0..=100 %.
+    pub fn percent(&self, raw: u16) -> u8 {
+        let (dry, wet) = (self.dry_raw as i32, self.wet_raw as i32);
+        if dry == wet {
+            return 0;
+        }
+        let percent = (raw as i32 - dry) * 100 / (wet - dry);
+        percent.clamp(0, 100) as u8
+    }
+}
+
+/// Soil moisture probe on an [`AnalogInput`]
+pub struct SoilMoisture<'a> {
+    input: AnalogInput<'a>,
+    calibration: SoilMoistureCalibration,
+}
+
+impl<'a> SoilMoisture<'a> {
+    pub fn new(input: AnalogInput<'a>, calibration: SoilMoistureCalibration) -> Self {
+        Self { input, calibration }
+    }
+
+    pub fn calibration(&self) -> SoilMoistureCalibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: SoilMoistureCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Averaged raw reading
+    pub async fn read_raw(&mut self) -> Result<u16, AnalogInputError> {
+        self.input.read_average(SAMPLES).await
+    }
+
+    /// Moisture in percent (0 = dry calibration point, 100 = wet calibration point)
+    pub async fn read_percent(&mut self) -> Result<u8, AnalogInputError> {
+        let raw = self.read_raw().await?;
+        Ok(self.calibration.percent(raw))
+    }
+
+    /// Record the current reading as the dry point (probe in air or dry soil).
+    pub async fn calibrate_dry(&mut self) -> Result<u16, AnalogInputError> {
+        let raw = self.read_raw().await?;
+        self.calibration.dry_raw = raw;
+        Ok(raw)
+    }
+
+    /// Record the current reading as the wet point (probe in water).
+    pub async fn calibrate_wet(&mut self) -> Result<u16, AnalogInputError> {
+        let raw = self.read_raw().await?;
+        self.calibration.wet_raw = raw;
+        Ok(raw)
+    }
+
+    /// Load calibration from `store`. Returns `false` (keeping the current calibration)
+    /// if nothing is stored under `key`.
+    pub fn load_calibration(
+        &mut self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<bool, KvStoreError> {
+        match store.get_array::<4>(key)? {
+            Some(bytes) => {
+                self.calibration = SoilMoistureCalibration::from_bytes(bytes);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn save_calibration(
+        &self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<(), KvStoreError> {
+        store.set(key, &self.calibration.to_bytes())
+    }
+}
@@ -0,0 +1,523 @@
+//! sx127x.rs — Semtech SX1276/77/78/79 LoRa radio driver (SPI, DIO0 interrupt)
+//!
+//! Covers the common RFM95/96/98 modules in LoRa mode: frequency, spreading factor,
+//! bandwidth, coding rate and PA_BOOST power are set from an [`Sx127xConfig`]; sending and
+//! receiving wait on the DIO0 pin instead of polling. The radio implements
+//! [`PacketRadio`](crate::PacketRadio), so [`RadioLink`](crate::RadioLink) can add
+//! addressing and acknowledgments on top.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let spi = Spi::new_blocking(p.SPI0, p.PIN_18, p.PIN_19, p.PIN_16, spi::Config::default());
+//! let cs = Output::new(p.PIN_17, Level::High);
+//! let reset = Output::new(p.PIN_20, Level::High);
+//! let dio0 = Input::new(p.PIN_21, Pull::Down);
+//! let mut radio = Sx127x::new(spi, cs, reset, dio0, Sx127xConfig::default()).await?;
+//!
+//! radio.transmit(b"hello").await?;
+//! let mut buf = [0u8; SX127X_MAX_PAYLOAD_LEN];
+//! let packet = radio.receive(&mut buf).await?;
+//! info!("{} bytes, RSSI {} dBm", packet.len, packet.rssi_dbm);
+//! ```
+
+use embassy_rp::gpio::{Input, Output};
+use embassy_rp::spi::{self, Spi};
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::{PacketRadio, RadioLinkError};
+
+/// Largest LoRa payload in bytes
+pub const SX127X_MAX_PAYLOAD_LEN: usize = 255;
+
+const REG_FIFO: u8 = 0x00;
+const REG_OP_MODE: u8 = 0x01;
+const REG_FRF_MSB: u8 = 0x06;
+const REG_PA_CONFIG: u8 = 0x09;
+const REG_OCP: u8 = 0x0b;
+const REG_LNA: u8 = 0x0c;
+const REG_FIFO_ADDR_PTR: u8 = 0x0d;
+const REG_FIFO_TX_BASE_ADDR: u8 = 0x0e;
+const REG_FIFO_RX_BASE_ADDR: u8 = 0x0f;
+const REG_FIFO_RX_CURRENT_ADDR: u8 = 0x10;
+const REG_IRQ_FLAGS: u8 = 0x12;
+const REG_RX_NB_BYTES: u8 = 0x13;
+const REG_PKT_SNR_VALUE: u8 = 0x19;
+const REG_PKT_RSSI_VALUE: u8 = 0x1a;
+const REG_MODEM_CONFIG_1: u8 = 0x1d;
+const REG_MODEM_CONFIG_2: u8 = 0x1e;
+const REG_PREAMBLE_MSB: u8 = 0x20;
+const REG_PAYLOAD_LENGTH: u8 = 0x22;
+const REG_MODEM_CONFIG_3: u8 = 0x26;
+const REG_DETECTION_OPTIMIZE: u8 = 0x31;
+const REG_DETECTION_THRESHOLD: u8 = 0x37;
+const REG_SYNC_WORD: u8 = 0x39;
+const REG_DIO_MAPPING_1: u8 = 0x40;
+const REG_VERSION: u8 = 0x42;
+const REG_PA_DAC: u8 = 0x4d;
+
+const SPI_WRITE: u8 = 0x80;
+const SX127X_VERSION: u8 = 0x12;
+const MODE_LONG_RANGE: u8 = 0x80;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_STANDBY: u8 = 0x01;
+const MODE_TX: u8 = 0x03;
+const MODE_RX_CONTINUOUS: u8 = 0x05;
+const IRQ_TX_DONE: u8 = 0x08;
+const IRQ_PAYLOAD_CRC_ERROR: u8 = 0x20;
+const IRQ_RX_DONE: u8 = 0x40;
+const DIO0_RX_DONE: u8 = 0x00;
+const DIO0_TX_DONE: u8 = 0x40;
+const FXOSC_HZ: u64 = 32_000_000;
+/// Above this the high-frequency port is used, which changes the RSSI offset
+const HF_PORT_MIN_HZ: u32 = 779_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum Sx127xError {
+    #[error("SPI transfer with SX127x failed")]
+    Spi,
+    #[error("No SX127x found (version register reads {0:#04x})")]
+    NotFound(u8),
+    #[error("Invalid SX127x configuration")]
+    InvalidConfig,
+    #[error("Payload longer than 255 bytes")]
+    PayloadTooLarge,
+    #[error("Received packet failed its CRC check")]
+    Crc,
+    #[error("Radio did not finish in time")]
+    Timeout,
+}
+
+/// Channel bandwidth; wider is faster but less sensitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LoRaBandwidth {
+    Khz7_8,
+    Khz10_4,
+    Khz15_6,
+    Khz20_8,
+    Khz31_25,
+    Khz41_7,
+    Khz62_5,
+    Khz125,
+    Khz250,
+    Khz500,
+}
+
+impl LoRaBandwidth {
+    pub fn hz(self) -> u32 {
+        match self {
+            Self::Khz7_8 => 7_800,
+            Self::Khz10_4 => 10_400,
+            Self::Khz15_6 => 15_600,
+            Self::Khz20_8 => 20_800,
+            Self::Khz31_25 => 31_250,
+            Self::Khz41_7 => 41_700,
+            Self::Khz62_5 => 62_500,
+            Self::Khz125 => 125_000,
+            Self::Khz250 => 250_000,
+            Self::Khz500 => 500_000,
+        }
+    }
+}
+
+/// Chips per symbol as a power of two; higher reaches further but takes longer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SpreadingFactor {
+    Sf7 = 7,
+    Sf8,
+    Sf9,
+    Sf10,
+    Sf11,
+    Sf12,
+}
+
+/// Forward error correction overhead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CodingRate {
+    Cr4_5 = 1,
+    Cr4_6,
+    Cr4_7,
+    Cr4_8,
+}
+
+/// Both ends of a link need the same frequency, bandwidth, spreading factor, coding rate
+/// and sync word.
+#[derive(Debug, Clone, Copy)]
+pub struct Sx127xConfig {
+    pub frequency_hz: u32,
+    pub bandwidth: LoRaBandwidth,
+    pub spreading_factor: SpreadingFactor,
+    pub coding_rate: CodingRate,
+    /// PA_BOOST output power, 2..=20 dBm
+    pub tx_power_dbm: i8,
+    pub preamble_len: u16,
+    /// 0x12 for private networks, 0x34 is reserved for LoRaWAN
+    pub sync_word: u8,
+    pub crc: bool,
+}
+
+impl Default for Sx127xConfig {
+    fn default() -> Self {
+        Self {
+            frequency_hz: 868_100_000,
+            bandwidth: LoRaBandwidth::Khz125,
+            spreading_factor: SpreadingFactor::Sf7,
+            coding_rate: CodingRate::Cr4_5,
+            tx_power_dbm: 14,
+            preamble_len: 8,
+            sync_word: 0x12,
+            crc: true,
+        }
+    }
+}
+
+impl Sx127xConfig {
+    /// Airtime of a packet with `payload_len` bytes (explicit header)
+    pub fn time_on_air(&self, payload_len: usize) -> Duration {
+        let sf = self.spreading_factor as i64;
+        let low_data_rate = self.low_data_rate_optimize() as i64;
+        let bits = 8 * payload_len as i64 - 4 * sf + 28 + 16 * self.crc as i64;
+        let per_block = 4 * (sf - 2 * low_data_rate);
+        let blocks = if bits > 0 {
+            (bits + per_block - 1) / per_block
+        } else {
+            0
+        };
+        let payload_symbols = 8 + blocks * (self.coding_rate as i64 + 4);
+        // The preamble lasts preamble_len + 4.25 symbols, so count quarter symbols
+        let quarter_symbols = 4 * (self.preamble_len as i64 + payload_symbols) + 17;
+        let micros = quarter_symbols * (1i64 << sf) * 1_000_000 / (4 * self.bandwidth.hz() as i64);
+        Duration::from_micros(micros as u64)
+    }
+
+    /// Required when a symbol lasts longer than 16 ms
+    fn low_data_rate_optimize(&self) -> bool {
+        (1u64 << self.spreading_factor as u32) * 1_000_000 / self.bandwidth.hz() as u64 > 16_000
+    }
+
+    fn validate(&self) -> Result<(), Sx127xError> {
+        let valid = (137_000_000..=1_020_000_000).contains(&self.frequency_hz)
+            && (2..=20).contains(&self.tx_power_dbm)
+            && self.preamble_len >= 6;
+        if valid {
+            Ok(())
+        } else {
+            Err(Sx127xError::InvalidConfig)
+        }
+    }
+}
+
+/// A received packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct LoRaPacket {
+    pub len: usize,
+    pub rssi_dbm: i16,
+    /// Signal to noise ratio in quarter dB
+    pub snr_quarter_db: i8,
+}
+
+pub struct Sx127x<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    spi: Spi<'d, T, M>,
+    cs: Output<'d>,
+    reset: Output<'d>,
+    dio0: Input<'d>,
+    config: Sx127xConfig,
+    receiving: bool,
+}
+
+impl<'d, T, M> Sx127x<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    /// Reset the radio, check it is an SX127x and configure it for LoRa.
+    pub async fn new(
+        spi: Spi<'d, T, M>,
+        cs: Output<'d>,
+        reset: Output<'d>,
+        dio0: Input<'d>,
+        config: Sx127xConfig,
+    ) -> Result<Self, Sx127xError> {
+        config.validate()?;
+        let mut radio = Self {
+            spi,
+            cs,
+            reset,
+            dio0,
+            config,
+            receiving: false,
+        };
+        radio.hardware_reset().await;
+        let version = radio.read_register(REG_VERSION)?;
+        if version != SX127X_VERSION {
+            return Err(Sx127xError::NotFound(version));
+        }
+        // LoRa mode can only be selected while asleep
+        radio.write_register(REG_OP_MODE, MODE_SLEEP)?;
+        radio.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_SLEEP)?;
+        radio.write_register(REG_FIFO_TX_BASE_ADDR, 0)?;
+        radio.write_register(REG_FIFO_RX_BASE_ADDR, 0)?;
+        let lna = radio.read_register(REG_LNA)?;
+        radio.write_register(REG_LNA, lna | 0x03)?;
+        radio.apply_config()?;
+        radio.standby()?;
+        Ok(radio)
+    }
+
+    pub fn config(&self) -> &Sx127xConfig {
+        &self.config
+    }
+
+    /// Change the radio settings; leaves the radio in standby.
+    pub fn set_config(&mut self, config: Sx127xConfig) -> Result<(), Sx127xError> {
+        config.validate()?;
+        self.config = config;
+        self.standby()?;
+        self.apply_config()
+    }
+
+    pub fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), Sx127xError> {
+        self.set_config(Sx127xConfig {
+            frequency_hz,
+            ..self.config
+        })
+    }
+
+    /// Lowest power mode; the next transmit or receive wakes the radio.
+    pub fn sleep(&mut self) -> Result<(), Sx127xError> {
+        self.receiving = false;
+        self.set_mode(MODE_SLEEP)
+    }
+
+    pub fn standby(&mut self) -> Result<(), Sx127xError> {
+        self.receiving = false;
+        self.set_mode(MODE_STANDBY)
+    }
+
+    /// Send `data` and wait until it has left the antenna. Leaves the radio in standby.
+    pub async fn transmit(&mut self, data: &[u8]) -> Result<(), Sx127xError> {
+        if data.len() > SX127X_MAX_PAYLOAD_LEN {
+            return Err(Sx127xError::PayloadTooLarge);
+        }
+        self.standby()?;
+        self.write_register(REG_DIO_MAPPING_1, DIO0_TX_DONE)?;
+        self.write_register(REG_FIFO_ADDR_PTR, 0)?;
+        self.write_fifo(data)?;
+        self.write_register(REG_PAYLOAD_LENGTH, data.len() as u8)?;
+        self.write_register(REG_IRQ_FLAGS, 0xff)?;
+        self.set_mode(MODE_TX)?;
+
+        let limit = self.config.time_on_air(data.len()) * 2 + Duration::from_millis(100);
+        let done = with_timeout(limit, self.dio0.wait_for_high()).await;
+        self.write_register(REG_IRQ_FLAGS, IRQ_TX_DONE)?;
+        self.standby()?;
+        done.map_err(|_| Sx127xError::Timeout)
+    }
+
+    /// Wait for the next packet and copy its payload into `buf` (truncated if too short).
+    /// The radio keeps listening afterwards.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<LoRaPacket, Sx127xError> {
+        if !self.receiving {
+            self.write_register(REG_DIO_MAPPING_1, DIO0_RX_DONE)?;
+            self.write_register(REG_FIFO_ADDR_PTR, 0)?;
+            self.write_register(REG_IRQ_FLAGS, 0xff)?;
+            self.set_mode(MODE_RX_CONTINUOUS)?;
+            self.receiving = true;
+        }
+        self.dio0.wait_for_high().await;
+
+        let flags = self.read_register(REG_IRQ_FLAGS)?;
+        self.write_register(REG_IRQ_FLAGS, flags)?;
+        if flags & IRQ_PAYLOAD_CRC_ERROR != 0 {
+            return Err(Sx127xError::Crc);
+        }
+        if flags & IRQ_RX_DONE == 0 {
+            return Err(Sx127xError::Timeout);
+        }
+        let start = self.read_register(REG_FIFO_RX_CURRENT_ADDR)?;
+        let received = self.read_register(REG_RX_NB_BYTES)? as usize;
+        self.write_register(REG_FIFO_ADDR_PTR, start)?;
+        let len = received.min(buf.len());
+        self.read_fifo(&mut buf[..len])?;
+
+        let snr_quarter_db = self.read_register(REG_PKT_SNR_VALUE)? as i8;
+        let rssi = self.read_register(REG_PKT_RSSI_VALUE)? as i16;
+        let rssi_offset = if self.config.frequency_hz >= HF_PORT_MIN_HZ {
+            -157
+        } else {
+            -164
+        };
+        Ok(LoRaPacket {
+            len,
+            rssi_dbm: rssi + rssi_offset,
+            snr_quarter_db,
+        })
+    }
+
+    async fn hardware_reset(&mut self) {
+        self.reset.set_low();
+        Timer::after_millis(1).await;
+        self.reset.set_high();
+        Timer::after_millis(10).await;
+    }
+
+    fn apply_config(&mut self) -> Result<(), Sx127xError> {
+        let config = self.config;
+        let frf = ((config.frequency_hz as u64) << 19) / FXOSC_HZ;
+        self.write_register(REG_FRF_MSB, (frf >> 16) as u8)?;
+        self.write_register(REG_FRF_MSB + 1, (frf >> 8) as u8)?;
+        self.write_register(REG_FRF_MSB + 2, frf as u8)?;
+
+        let bandwidth = config.bandwidth as u8;
+        self.write_register(
+            REG_MODEM_CONFIG_1,
+            (bandwidth << 4) | ((config.coding_rate as u8) << 1),
+        )?;
+        self.write_register(
+            REG_MODEM_CONFIG_2,
+            ((config.spreading_factor as u8) << 4) | ((config.crc as u8) << 2),
+        )?;
+        // AGC on, plus low data rate optimization for long symbols
+        self.write_register(
+            REG_MODEM_CONFIG_3,
+            0x04 | ((config.low_data_rate_optimize() as u8) << 3),
+        )?;
+        self.write_register(REG_DETECTION_OPTIMIZE, 0xc3)?;
+        self.write_register(REG_DETECTION_THRESHOLD, 0x0a)?;
+        self.write_register(REG_PREAMBLE_MSB, (config.preamble_len >> 8) as u8)?;
+        self.write_register(REG_PREAMBLE_MSB + 1, config.preamble_len as u8)?;
+        self.write_register(REG_SYNC_WORD, config.sync_word)?;
+
+        // PA_BOOST; above 17 dBm the high power DAC adds 3 dB and needs more current
+        let (pa_dac, level, ocp_trim) = if config.tx_power_dbm > 17 {
+            (0x87, config.tx_power_dbm - 5, 0x11) // 140 mA
+        } else {
+            (0x84, config.tx_power_dbm - 2, 0x0b) // 100 mA
+        };
+        self.write_register(REG_PA_DAC, pa_dac)?;
+        self.write_register(REG_OCP, 0x20 | ocp_trim)?;
+        self.write_register(REG_PA_CONFIG, 0x80 | level as u8)
+    }
+
+    fn set_mode(&mut self, mode: u8) -> Result<(), Sx127xError> {
+        self.write_register(REG_OP_MODE, MODE_LONG_RANGE | mode)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Sx127xError> {
+        let mut buf = [register & !SPI_WRITE, 0];
+        self.cs.set_low();
+        let result = self.spi.blocking_transfer_in_place(&mut buf);
+        self.cs.set_high();
+        result.map_err(|_| Sx127xError::Spi)?;
+        Ok(buf[1])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Sx127xError> {
+        self.cs.set_low();
+        let result = self.spi.blocking_write(&[register | SPI_WRITE, value]);
+        self.cs.set_high();
+        result.map_err(|_| Sx127xError::Spi)
+    }
+
+    fn write_fifo(&mut self, data: &[u8]) -> Result<(), Sx127xError> {
+        self.cs.set_low();
+        let result = self
+            .spi
+            .blocking_write(&[REG_FIFO | SPI_WRITE])
+            .and_then(|_| self.spi.blocking_write(data));
+        self.cs.set_high();
+        result.map_err(|_| Sx127xError::Spi)
+    }
+
+    fn read_fifo(&mut self, buf: &mut [u8]) -> Result<(), Sx127xError> {
+        self.cs.set_low();
+        let result = self
+            .spi
+            .blocking_write(&[REG_FIFO])
+            .and_then(|_| self.spi.blocking_read(buf));
+        self.cs.set_high();
+        result.map_err(|_| Sx127xError::Spi)
+    }
+}
+
+impl<T, M> PacketRadio for Sx127x<'_, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    const MAX_PACKET_LEN: usize = SX127X_MAX_PAYLOAD_LEN;
+
+    async fn transmit(&mut self, packet: &[u8]) -> Result<(), RadioLinkError> {
+        Sx127x::transmit(self, packet)
+            .await
+            .map_err(|_| RadioLinkError::Radio)
+    }
+
+    async fn receive(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, RadioLinkError> {
+        let packet = async {
+            loop {
+                match Sx127x::receive(self, buf).await {
+                    Ok(packet) => return Ok(packet.len),
+                    Err(Sx127xError::Crc) => continue,
+                    Err(_) => return Err(RadioLinkError::Radio),
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => with_timeout(timeout, packet).await.ok().transpose(),
+            None => packet.await.map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_time_on_air() {
+        let config = Sx127xConfig::default();
+        // SF7/125 kHz/4:5, 8 symbol preamble, CRC on: 12.25 + 28 symbols of 1.024 ms
+        assert_eq!(config.time_on_air(10), Duration::from_micros(41_216));
+
+        let slow = Sx127xConfig {
+            spreading_factor: SpreadingFactor::Sf12,
+            ..config
+        };
+        assert!(slow.low_data_rate_optimize());
+        assert!(!config.low_data_rate_optimize());
+        assert!(slow.time_on_air(10) > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn low_data_rate_above_16_ms_symbols() {
+        let config = |spreading_factor, bandwidth| Sx127xConfig {
+            spreading_factor,
+            bandwidth,
+            ..Sx127xConfig::default()
+        };
+        // 16.384 ms symbols
+        assert!(config(SpreadingFactor::Sf11, LoRaBandwidth::Khz125).low_data_rate_optimize());
+        assert!(!config(SpreadingFactor::Sf11, LoRaBandwidth::Khz250).low_data_rate_optimize());
+        assert!(!config(SpreadingFactor::Sf10, LoRaBandwidth::Khz125).low_data_rate_optimize());
+    }
+
+    #[test]
+    fn validates_config() {
+        let config = Sx127xConfig::default();
+        assert!(config.validate().is_ok());
+        let loud = Sx127xConfig {
+            tx_power_dbm: 23,
+            ..config
+        };
+        assert_eq!(loud.validate(), Err(Sx127xError::InvalidConfig));
+    }
+}
@@ -0,0 +1,150 @@
+//! text_display.rs — common interface for the crate's character/text displays
+
+use embassy_rp::spi;
+
+use crate::{
+    INLAND_KS0061_MAX_CHARS_PER_LINE, INLAND_KS0061_ROWS, INLAND_SH1106_MAX_CHARS_PER_LINE,
+    INLAND_SH1106_MAX_TEXT_LINES, InlandKs0061I2cDisplay, InlandKs0061I2cDisplayError,
+    InlandSh1106OledDisplay, InlandSh1106OledError, TftDisplay, TftError,
+};
+
+/// A display that can show a few lines of plain text.
+///
+/// Lets helpers (banners, status screens) target the 16x2 LCD, the SH1106 OLED and the colour
+/// TFTs alike.
+pub trait TextDisplay {
+    type Error;
+
+    /// Number of text lines the display can show at once.
+    fn max_lines(&self) -> usize;
+
+    /// Number of characters that fit on one line.
+    fn max_chars_per_line(&self) -> usize;
+
+    fn clear(&mut self) -> Result<(), Self::Error>;
+
+    /// Replace the display content. Lines are separated by `\n`.
+    fn display_str(&mut self, content: &str) -> Result<(), Self::Error>;
+}
+
+impl<I: embedded_hal::i2c::I2c> TextDisplay for InlandKs0061I2cDisplay<I> {
+    type Error = InlandKs0061I2cDisplayError;
+
+    fn max_lines(&self) -> usize {
+        INLAND_KS0061_ROWS
+    }
+
+    fn max_chars_per_line(&self) -> usize {
+        INLAND_KS0061_MAX_CHARS_PER_LINE
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        InlandKs0061I2cDisplay::clear(self)
+    }
+
+    fn display_str(&mut self, content: &str) -> Result<(), Self::Error> {
+        InlandKs0061I2cDisplay::display_str(self, content)
+    }
+}
+
+impl<'d, T, M> TextDisplay for InlandSh1106OledDisplay<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    type Error = InlandSh1106OledError;
+
+    fn max_lines(&self) -> usize {
+        INLAND_SH1106_MAX_TEXT_LINES
+    }
+
+    fn max_chars_per_line(&self) -> usize {
+        INLAND_SH1106_MAX_CHARS_PER_LINE
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        InlandSh1106OledDisplay::clear(self)
+    }
+
+    fn display_str(&mut self, content: &str) -> Result<(), Self::Error> {
+        InlandSh1106OledDisplay::display_str(self, content)
+    }
+}
+
+impl<'d, T, M> TextDisplay for TftDisplay<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    type Error = TftError;
+
+    fn max_lines(&self) -> usize {
+        TftDisplay::max_lines(self)
+    }
+
+    fn max_chars_per_line(&self) -> usize {
+        TftDisplay::max_chars_per_line(self)
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        TftDisplay::clear(self)
+    }
+
+    fn display_str(&mut self, content: &str) -> Result<(), Self::Error> {
+        TftDisplay::display_str(self, content)
+    }
+}
+
+/// Text display for host tests. Keeps what was last shown and, like the character LCD,
+/// refuses lines wider than it is.
+#[cfg(test)]
+pub(crate) struct MockTextDisplay {
+    lines: usize,
+    columns: usize,
+    content: crate::HeaplessString<255>,
+}
+
+#[cfg(test)]
+impl MockTextDisplay {
+    pub(crate) fn new(lines: usize, columns: usize) -> Self {
+        Self {
+            lines,
+            columns,
+            content: crate::HeaplessString::new(),
+        }
+    }
+
+    /// What the display currently shows
+    pub(crate) fn content(&self) -> &str {
+        self.content.as_str()
+    }
+}
+
+#[cfg(test)]
+impl TextDisplay for MockTextDisplay {
+    type Error = ();
+
+    fn max_lines(&self) -> usize {
+        self.lines
+    }
+
+    fn max_chars_per_line(&self) -> usize {
+        self.columns
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        self.content.clear();
+        Ok(())
+    }
+
+    fn display_str(&mut self, content: &str) -> Result<(), ()> {
+        if content
+            .lines()
+            .any(|line| line.chars().count() > self.columns)
+        {
+            return Err(());
+        }
+        self.content = content.try_into().map_err(|_| ())?;
+        Ok(())
+    }
+}
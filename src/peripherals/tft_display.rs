@@ -0,0 +1,562 @@
+//! tft_display.rs — ST7735/ST7789 colour TFT driver (SPI) with an RGB565 framebuffer
+//!
+//! Drawing goes through embedded-graphics into a caller-provided framebuffer
+//! ([`Rgb565Framebuffer`]); [`TftDisplay::flush`] then sends the rows that changed, either
+//! blocking or, with an async SPI, by DMA ([`TftDisplay::flush_async`]). A full frame takes
+//! `width * height * 2` bytes: 40 KiB for a 1.8" 128x160 panel, 112.5 KiB for a 1.3"
+//! 240x240 one.
+//!
+//! The display also implements [`TextDisplay`](crate::TextDisplay) with the 6x10 font, so
+//! status screens written for the SH1106 run unchanged, with more lines to fill.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static FRAME: StaticCell<[u8; 240 * 240 * 2]> = StaticCell::new();
+//!
+//! let spi = Spi::new_txonly(p.SPI0, p.PIN_18, p.PIN_19, p.DMA_CH0, tft_default_spi_config());
+//! let dc = Output::new(p.PIN_16, Level::Low);
+//! let cs = Output::new(p.PIN_17, Level::High);
+//! let reset = Output::new(p.PIN_20, Level::High);
+//! let frame = FRAME.init([0; 240 * 240 * 2]);
+//! let mut tft = TftDisplay::new(spi, dc, cs, Some(reset), TftPanel::ST7789_240X240, frame).await?;
+//!
+//! Circle::new(Point::new(80, 80), 80)
+//!     .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+//!     .draw(tft.framebuffer())?;
+//! tft.flush_async().await?;
+//! ```
+
+use core::convert::Infallible;
+
+use embassy_rp::gpio::Output;
+use embassy_rp::spi::{self, Async, Spi};
+use embassy_time::Timer;
+use embedded_graphics::mono_font::{MonoTextStyle, ascii::FONT_6X10};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+
+/// Height of a text line with the 6x10 font
+pub const TFT_TEXT_LINE_HEIGHT: u16 = 10;
+/// Width of one character with the 6x10 font
+pub const TFT_CHAR_WIDTH: u16 = 6;
+
+const CMD_SWRESET: u8 = 0x01;
+const CMD_SLPOUT: u8 = 0x11;
+const CMD_NORON: u8 = 0x13;
+const CMD_INVOFF: u8 = 0x20;
+const CMD_INVON: u8 = 0x21;
+const CMD_DISPOFF: u8 = 0x28;
+const CMD_DISPON: u8 = 0x29;
+const CMD_CASET: u8 = 0x2a;
+const CMD_RASET: u8 = 0x2b;
+const CMD_RAMWR: u8 = 0x2c;
+const CMD_MADCTL: u8 = 0x36;
+const CMD_COLMOD: u8 = 0x3a;
+
+const MADCTL_MY: u8 = 0x80;
+const MADCTL_MX: u8 = 0x40;
+const MADCTL_MV: u8 = 0x20;
+const MADCTL_BGR: u8 = 0x08;
+/// 16 bits per pixel
+const COLMOD_RGB565: u8 = 0x05;
+
+pub fn tft_default_spi_config() -> spi::Config {
+    let mut cfg = spi::Config::default();
+    cfg.frequency = 62_500_000;
+    cfg.phase = spi::Phase::CaptureOnFirstTransition;
+    cfg.polarity = spi::Polarity::IdleLow;
+    cfg
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum TftError {
+    #[error("SPI transfer with the TFT failed")]
+    Communication,
+    #[error("Framebuffer too small for the panel: {actual} < {needed} bytes")]
+    BufferTooSmall { actual: usize, needed: usize },
+    #[error("String contains too many lines for the TFT: {actual_lines} > {max_lines}")]
+    TooManyLines {
+        actual_lines: usize,
+        max_lines: usize,
+    },
+    #[error("Line {line_index} is too long for the TFT: {actual_chars} > {max_chars}")]
+    LineTooLong {
+        line_index: usize,
+        actual_chars: usize,
+        max_chars: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TftController {
+    St7735,
+    St7789,
+}
+
+/// Panel orientation, clockwise from the controller's native portrait layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TftRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// Geometry and quirks of a particular module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TftPanel {
+    pub controller: TftController,
+    /// Visible size in the native (unrotated) orientation
+    pub width: u16,
+    pub height: u16,
+    /// Where the visible area starts in controller RAM
+    pub x_offset: u16,
+    pub y_offset: u16,
+    pub invert_colors: bool,
+    pub bgr: bool,
+    pub rotation: TftRotation,
+}
+
+impl TftPanel {
+    /// 1.8" ST7735S modules
+    pub const ST7735_128X160: Self = Self {
+        controller: TftController::St7735,
+        width: 128,
+        height: 160,
+        x_offset: 0,
+        y_offset: 0,
+        invert_colors: false,
+        bgr: false,
+        rotation: TftRotation::Deg0,
+    };
+    /// 1.44" ST7735 "green tab" modules
+    pub const ST7735_128X128: Self = Self {
+        controller: TftController::St7735,
+        width: 128,
+        height: 128,
+        x_offset: 2,
+        y_offset: 3,
+        invert_colors: false,
+        bgr: true,
+        rotation: TftRotation::Deg0,
+    };
+    /// 1.3" and 1.54" square ST7789 modules
+    pub const ST7789_240X240: Self = Self {
+        controller: TftController::St7789,
+        width: 240,
+        height: 240,
+        x_offset: 0,
+        y_offset: 0,
+        invert_colors: true,
+        bgr: false,
+        rotation: TftRotation::Deg0,
+    };
+    /// 2.0" ST7789 modules
+    pub const ST7789_240X320: Self = Self {
+        controller: TftController::St7789,
+        width: 240,
+        height: 320,
+        x_offset: 0,
+        y_offset: 0,
+        invert_colors: true,
+        bgr: false,
+        rotation: TftRotation::Deg0,
+    };
+    /// 1.14" ST7789 modules (e.g. on the Pico display packs)
+    pub const ST7789_135X240: Self = Self {
+        controller: TftController::St7789,
+        width: 135,
+        height: 240,
+        x_offset: 52,
+        y_offset: 40,
+        invert_colors: true,
+        bgr: false,
+        rotation: TftRotation::Deg0,
+    };
+
+    pub const fn with_rotation(self, rotation: TftRotation) -> Self {
+        Self { rotation, ..self }
+    }
+
+    /// Visible size after rotation
+    pub fn size(&self) -> (u16, u16) {
+        if self.swaps_axes() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    pub fn framebuffer_len(&self) -> usize {
+        self.width as usize * self.height as usize * 2
+    }
+
+    fn swaps_axes(&self) -> bool {
+        matches!(self.rotation, TftRotation::Deg90 | TftRotation::Deg270)
+    }
+
+    fn madctl(&self) -> u8 {
+        let rotation = match self.rotation {
+            TftRotation::Deg0 => 0,
+            TftRotation::Deg90 => MADCTL_MX | MADCTL_MV,
+            TftRotation::Deg180 => MADCTL_MX | MADCTL_MY,
+            TftRotation::Deg270 => MADCTL_MY | MADCTL_MV,
+        };
+        rotation | if self.bgr { MADCTL_BGR } else { 0 }
+    }
+
+    /// Offsets in the rotated coordinate system
+    fn offsets(&self) -> (u16, u16) {
+        if self.swaps_axes() {
+            (self.y_offset, self.x_offset)
+        } else {
+            (self.x_offset, self.y_offset)
+        }
+    }
+}
+
+/// RGB565 pixels in panel byte order (big endian), with the range of changed rows
+pub struct Rgb565Framebuffer<'b> {
+    buffer: &'b mut [u8],
+    width: u16,
+    height: u16,
+    dirty: Option<(u16, u16)>,
+}
+
+impl<'b> Rgb565Framebuffer<'b> {
+    /// `None` if `buffer` holds fewer than `width * height * 2` bytes.
+    pub fn new(buffer: &'b mut [u8], width: u16, height: u16) -> Option<Self> {
+        let len = width as usize * height as usize * 2;
+        let buffer = buffer.get_mut(..len)?;
+        Some(Self {
+            buffer,
+            width,
+            height,
+            dirty: Some((0, height.saturating_sub(1))),
+        })
+    }
+
+    pub fn pixel(&self, x: u16, y: u16) -> Option<Rgb565> {
+        let index = self.index(x, y)?;
+        let raw = u16::from_be_bytes([self.buffer[index], self.buffer[index + 1]]);
+        Some(RawU16::new(raw).into())
+    }
+
+    /// First and last row changed since the last [`take_dirty`](Self::take_dirty)
+    pub fn dirty_rows(&self) -> Option<(u16, u16)> {
+        self.dirty
+    }
+
+    /// Bytes of the changed rows and the first of them; clears the dirty range.
+    pub fn take_dirty(&mut self) -> Option<(u16, u16, &[u8])> {
+        let (first, last) = self.dirty.take()?;
+        let row_bytes = self.width as usize * 2;
+        let bytes = &self.buffer[first as usize * row_bytes..(last as usize + 1) * row_bytes];
+        Some((first, last, bytes))
+    }
+
+    /// Mark everything as changed, e.g. after the panel was reset.
+    pub fn invalidate(&mut self) {
+        self.dirty = Some((0, self.height.saturating_sub(1)));
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        (x < self.width && y < self.height)
+            .then(|| (y as usize * self.width as usize + x as usize) * 2)
+    }
+
+    fn mark_dirty(&mut self, first: u16, last: u16) {
+        self.dirty = Some(match self.dirty {
+            Some((a, b)) => (a.min(first), b.max(last)),
+            None => (first, last),
+        });
+    }
+}
+
+impl OriginDimensions for Rgb565Framebuffer<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl DrawTarget for Rgb565Framebuffer<'_> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u16::try_from(point.x), u16::try_from(point.y)) else {
+                continue;
+            };
+            let Some(index) = self.index(x, y) else {
+                continue;
+            };
+            let raw = RawU16::from(color).into_inner().to_be_bytes();
+            self.buffer[index..index + 2].copy_from_slice(&raw);
+            self.mark_dirty(y, y);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let raw = RawU16::from(color).into_inner().to_be_bytes();
+        for pixel in self.buffer.chunks_exact_mut(2) {
+            pixel.copy_from_slice(&raw);
+        }
+        self.invalidate();
+        Ok(())
+    }
+}
+
+pub struct TftDisplay<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    spi: Spi<'d, T, M>,
+    dc: Output<'d>,
+    cs: Output<'d>,
+    reset: Option<Output<'d>>,
+    panel: TftPanel,
+    framebuffer: Rgb565Framebuffer<'d>,
+    text_color: Rgb565,
+    background: Rgb565,
+}
+
+impl<'d, T, M> TftDisplay<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    /// Reset and initialize the panel. `framebuffer` needs
+    /// [`TftPanel::framebuffer_len`] bytes.
+    pub async fn new(
+        spi: Spi<'d, T, M>,
+        dc: Output<'d>,
+        cs: Output<'d>,
+        reset: Option<Output<'d>>,
+        panel: TftPanel,
+        framebuffer: &'d mut [u8],
+    ) -> Result<Self, TftError> {
+        let (width, height) = panel.size();
+        let actual = framebuffer.len();
+        let framebuffer =
+            Rgb565Framebuffer::new(framebuffer, width, height).ok_or(TftError::BufferTooSmall {
+                actual,
+                needed: panel.framebuffer_len(),
+            })?;
+        let mut display = Self {
+            spi,
+            dc,
+            cs,
+            reset,
+            panel,
+            framebuffer,
+            text_color: Rgb565::WHITE,
+            background: Rgb565::BLACK,
+        };
+        display.init().await?;
+        Ok(display)
+    }
+
+    pub fn panel(&self) -> &TftPanel {
+        &self.panel
+    }
+
+    /// Draw here, then [`flush`](Self::flush).
+    pub fn framebuffer(&mut self) -> &mut Rgb565Framebuffer<'d> {
+        &mut self.framebuffer
+    }
+
+    /// Colours used by [`display_str`](Self::display_str)
+    pub fn set_text_colors(&mut self, text: Rgb565, background: Rgb565) {
+        self.text_color = text;
+        self.background = background;
+    }
+
+    pub fn set_display_on(&mut self, on: bool) -> Result<(), TftError> {
+        self.command(if on { CMD_DISPON } else { CMD_DISPOFF }, &[])
+    }
+
+    /// Send the changed rows, blocking until done.
+    pub fn flush(&mut self) -> Result<(), TftError> {
+        let Some((first, last)) = self.framebuffer.dirty_rows() else {
+            return Ok(());
+        };
+        self.set_window(first, last)?;
+        let Some((_, _, bytes)) = self.framebuffer.take_dirty() else {
+            return Ok(());
+        };
+        self.dc.set_high();
+        self.cs.set_low();
+        let result = self.spi.blocking_write(bytes);
+        self.cs.set_high();
+        result.map_err(|_| TftError::Communication)
+    }
+
+    pub fn max_lines(&self) -> usize {
+        (self.framebuffer.height / TFT_TEXT_LINE_HEIGHT) as usize
+    }
+
+    pub fn max_chars_per_line(&self) -> usize {
+        (self.framebuffer.width / TFT_CHAR_WIDTH) as usize
+    }
+
+    pub fn clear(&mut self) -> Result<(), TftError> {
+        let _ = self.framebuffer.clear(self.background);
+        self.flush()
+    }
+
+    /// Show multi-line text (lines separated by `\n`) with the 6x10 font.
+    pub fn display_str(&mut self, content: &str) -> Result<(), TftError> {
+        let max_lines = self.max_lines();
+        let max_chars = self.max_chars_per_line();
+        for (line_index, line) in content.split('\n').enumerate() {
+            if line_index >= max_lines {
+                return Err(TftError::TooManyLines {
+                    actual_lines: content.split('\n').count(),
+                    max_lines,
+                });
+            }
+            let chars = line.chars().count();
+            if chars > max_chars {
+                return Err(TftError::LineTooLong {
+                    line_index,
+                    actual_chars: chars,
+                    max_chars,
+                });
+            }
+        }
+
+        let _ = self.framebuffer.clear(self.background);
+        let style = MonoTextStyle::new(&FONT_6X10, self.text_color);
+        for (line_index, line) in content.split('\n').enumerate() {
+            let y = line_index as i32 * TFT_TEXT_LINE_HEIGHT as i32;
+            let _ = Text::with_baseline(line, Point::new(0, y), style, Baseline::Top)
+                .draw(&mut self.framebuffer);
+        }
+        self.flush()
+    }
+
+    async fn init(&mut self) -> Result<(), TftError> {
+        if let Some(reset) = self.reset.as_mut() {
+            reset.set_low();
+            Timer::after_millis(10).await;
+            reset.set_high();
+            Timer::after_millis(120).await;
+        }
+        self.command(CMD_SWRESET, &[])?;
+        Timer::after_millis(150).await;
+        self.command(CMD_SLPOUT, &[])?;
+        // The ST7735 needs longer to wake up than the ST7789
+        let wake_ms = match self.panel.controller {
+            TftController::St7735 => 500,
+            TftController::St7789 => 120,
+        };
+        Timer::after_millis(wake_ms).await;
+        self.command(CMD_COLMOD, &[COLMOD_RGB565])?;
+        self.command(CMD_MADCTL, &[self.panel.madctl()])?;
+        let invert = if self.panel.invert_colors {
+            CMD_INVON
+        } else {
+            CMD_INVOFF
+        };
+        self.command(invert, &[])?;
+        self.command(CMD_NORON, &[])?;
+        Timer::after_millis(10).await;
+        self.command(CMD_DISPON, &[])?;
+        Timer::after_millis(20).await;
+        self.framebuffer.invalidate();
+        Ok(())
+    }
+
+    /// Full-width window over rows `first..=last`, ready for pixel data
+    fn set_window(&mut self, first: u16, last: u16) -> Result<(), TftError> {
+        let (x_offset, y_offset) = self.panel.offsets();
+        let x_end = x_offset + self.framebuffer.width - 1;
+        let [xs_hi, xs_lo] = x_offset.to_be_bytes();
+        let [xe_hi, xe_lo] = x_end.to_be_bytes();
+        let [ys_hi, ys_lo] = (y_offset + first).to_be_bytes();
+        let [ye_hi, ye_lo] = (y_offset + last).to_be_bytes();
+        self.command(CMD_CASET, &[xs_hi, xs_lo, xe_hi, xe_lo])?;
+        self.command(CMD_RASET, &[ys_hi, ys_lo, ye_hi, ye_lo])?;
+        self.command(CMD_RAMWR, &[])
+    }
+
+    fn command(&mut self, command: u8, params: &[u8]) -> Result<(), TftError> {
+        self.cs.set_low();
+        self.dc.set_low();
+        let mut result = self.spi.blocking_write(&[command]);
+        if result.is_ok() && !params.is_empty() {
+            self.dc.set_high();
+            result = self.spi.blocking_write(params);
+        }
+        self.cs.set_high();
+        result.map_err(|_| TftError::Communication)
+    }
+}
+
+impl<'d, T: spi::Instance> TftDisplay<'d, T, Async> {
+    /// Send the changed rows by DMA; other tasks run meanwhile.
+    pub async fn flush_async(&mut self) -> Result<(), TftError> {
+        let Some((first, last)) = self.framebuffer.dirty_rows() else {
+            return Ok(());
+        };
+        self.set_window(first, last)?;
+        let Some((_, _, bytes)) = self.framebuffer.take_dirty() else {
+            return Ok(());
+        };
+        self.dc.set_high();
+        self.cs.set_low();
+        let result = self.spi.write(bytes).await;
+        self.cs.set_high();
+        result.map_err(|_| TftError::Communication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+    #[test]
+    fn rotation_and_offsets() {
+        let panel = TftPanel::ST7789_135X240.with_rotation(TftRotation::Deg90);
+        assert_eq!(panel.size(), (240, 135));
+        assert_eq!(panel.offsets(), (40, 52));
+        assert_eq!(panel.madctl(), MADCTL_MX | MADCTL_MV);
+        assert_eq!(TftPanel::ST7735_128X128.madctl(), MADCTL_BGR);
+        assert_eq!(TftPanel::ST7735_128X160.framebuffer_len(), 40_960);
+    }
+
+    #[test]
+    fn framebuffer_tracks_dirty_rows() {
+        let mut buffer = [0u8; 8 * 4 * 2];
+        let mut fb = Rgb565Framebuffer::new(&mut buffer, 8, 4).unwrap();
+        assert!(Rgb565Framebuffer::new(&mut [0u8; 10], 8, 4).is_none());
+        assert_eq!(fb.take_dirty().map(|(a, b, _)| (a, b)), Some((0, 3)));
+        assert_eq!(fb.dirty_rows(), None);
+
+        Rectangle::new(Point::new(2, 1), Size::new(3, 2))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+            .draw(&mut fb)
+            .unwrap();
+        assert_eq!(fb.pixel(2, 1), Some(Rgb565::RED));
+        assert_eq!(fb.pixel(1, 1), Some(Rgb565::BLACK));
+        let (first, last, bytes) = fb.take_dirty().unwrap();
+        assert_eq!((first, last), (1, 2));
+        assert_eq!(bytes.len(), 2 * 8 * 2);
+        // Big endian RGB565 red
+        assert_eq!(&bytes[4..6], &[0xf8, 0x00]);
+
+        // Off-screen pixels are ignored
+        Pixel(Point::new(-1, 9), Rgb565::RED).draw(&mut fb).unwrap();
+        assert_eq!(fb.dirty_rows(), None);
+    }
+}
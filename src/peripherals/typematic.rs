@@ -0,0 +1,204 @@
+//! typematic.rs — auto-repeat for held keys in HID keyboard reports
+//!
+//! A boot keyboard report only says which keys are down, so a held key types once.
+//! [`TypematicKeyboard`] wraps a [`HidKeyboardState`] and, once the most recently pressed
+//! key has been held for the configured delay, schedules a lifted report followed by a
+//! pressed one every repeat interval, which the host types as one more character each.
+//! Modifiers stay down, so holding Shift+A repeats capitals. Only the newest key repeats,
+//! like on a real keyboard; releasing it stops the repeat.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut keyboard = TypematicKeyboard::new(TypematicConfig::default());
+//! loop {
+//!     let deadline = keyboard.next_repeat().unwrap_or(Instant::MAX);
+//!     match select(matrix.next_event(), Timer::at(deadline)).await {
+//!         Either::First(event) => {
+//!             if let Some(report) = keyboard.update(event, Instant::now()) {
+//!                 usb.send_report(&report).await?;
+//!             }
+//!         }
+//!         Either::Second(()) => {
+//!             while let Some(report) = keyboard.poll(Instant::now()) {
+//!                 usb.send_report(&report).await?;
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use embassy_time::{Duration, Instant};
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::{HidKeyboardState, KeyEvent};
+
+/// Hold time before a key starts repeating
+pub const TYPEMATIC_DEFAULT_DELAY: Duration = Duration::from_millis(500);
+/// Time between repeats (about 30 characters per second)
+pub const TYPEMATIC_DEFAULT_INTERVAL: Duration = Duration::from_millis(33);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TypematicConfig {
+    pub delay: Duration,
+    pub interval: Duration,
+}
+
+impl Default for TypematicConfig {
+    fn default() -> Self {
+        Self {
+            delay: TYPEMATIC_DEFAULT_DELAY,
+            interval: TYPEMATIC_DEFAULT_INTERVAL,
+        }
+    }
+}
+
+/// The key being repeated
+#[derive(Debug, Clone, Copy)]
+struct Repeat {
+    usage: u8,
+    due: Instant,
+    /// A report without the key was sent; the next one presses it again
+    lifted: bool,
+}
+
+/// Keyboard report state with typematic repeat
+#[derive(Debug, Clone)]
+pub struct TypematicKeyboard {
+    state: HidKeyboardState,
+    config: TypematicConfig,
+    repeat: Option<Repeat>,
+}
+
+impl TypematicKeyboard {
+    pub fn new(config: TypematicConfig) -> Self {
+        Self {
+            state: HidKeyboardState::new(),
+            config,
+            repeat: None,
+        }
+    }
+
+    /// Takes effect from the next key press.
+    pub fn set_config(&mut self, config: TypematicConfig) {
+        self.config = config;
+    }
+
+    pub fn state(&self) -> &HidKeyboardState {
+        &self.state
+    }
+
+    /// Apply `event` at `now`; returns the report to send if it changed.
+    pub fn update(&mut self, event: KeyEvent, now: Instant) -> Option<KeyboardReport> {
+        let changed = self.state.update(event);
+        if !event.is_modifier() {
+            if event.pressed {
+                // Keys lost to rollover can't repeat
+                self.repeat = self
+                    .state
+                    .report()
+                    .keycodes
+                    .contains(&event.usage)
+                    .then_some(Repeat {
+                        usage: event.usage,
+                        due: now + self.config.delay,
+                        lifted: false,
+                    });
+            } else if self.repeat.is_some_and(|r| r.usage == event.usage) {
+                self.repeat = None;
+            }
+        }
+        if !changed {
+            return None;
+        }
+        if let Some(repeat) = self.repeat.as_mut().filter(|r| r.lifted) {
+            // This report presses the repeating key again
+            repeat.lifted = false;
+            repeat.due = now + self.config.interval;
+        }
+        Some(self.state.report())
+    }
+
+    /// When [`poll`](Self::poll) has the next repeat report
+    pub fn next_repeat(&self) -> Option<Instant> {
+        self.repeat.map(|r| r.due)
+    }
+
+    /// The repeat report due at `now`, if any. A repeat takes two reports, the second due
+    /// at once, so call this until it returns `None`.
+    pub fn poll(&mut self, now: Instant) -> Option<KeyboardReport> {
+        let repeat = self.repeat.as_mut().filter(|r| r.due <= now)?;
+        let mut report = self.state.report();
+        if repeat.lifted {
+            repeat.lifted = false;
+            repeat.due = now + self.config.interval;
+        } else {
+            repeat.lifted = true;
+            repeat.due = now;
+            if let Some(index) = report.keycodes.iter().position(|&k| k == repeat.usage) {
+                report.keycodes.copy_within(index + 1.., index);
+                report.keycodes[5] = 0;
+            }
+        }
+        Some(report)
+    }
+
+    /// Release everything and stop repeating.
+    pub fn clear(&mut self) {
+        self.state.clear();
+        self.repeat = None;
+    }
+}
+
+impl Default for TypematicKeyboard {
+    fn default() -> Self {
+        Self::new(TypematicConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HID_KEY_A, HID_KEY_LEFT_SHIFT};
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    fn event(usage: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { usage, pressed }
+    }
+
+    #[test]
+    fn held_key_repeats_after_delay() {
+        let mut keyboard = TypematicKeyboard::default();
+        keyboard.update(event(HID_KEY_LEFT_SHIFT, true), at(0));
+        let report = keyboard.update(event(HID_KEY_A, true), at(0)).unwrap();
+        assert_eq!(report.keycodes[0], HID_KEY_A);
+        assert_eq!(keyboard.next_repeat(), Some(at(500)));
+        assert!(keyboard.poll(at(499)).is_none());
+
+        let lifted = keyboard.poll(at(500)).unwrap();
+        assert_eq!((lifted.modifier, lifted.keycodes[0]), (0x02, 0));
+        let pressed = keyboard.poll(at(500)).unwrap();
+        assert_eq!(pressed.keycodes[0], HID_KEY_A);
+        assert!(keyboard.poll(at(500)).is_none());
+        assert_eq!(keyboard.next_repeat(), Some(at(533)));
+
+        let released = keyboard.update(event(HID_KEY_A, false), at(540)).unwrap();
+        assert_eq!(released.keycodes, [0; 6]);
+        assert_eq!(keyboard.next_repeat(), None);
+    }
+
+    #[test]
+    fn newest_key_takes_over() {
+        let mut keyboard = TypematicKeyboard::default();
+        keyboard.update(event(HID_KEY_A, true), at(0));
+        keyboard.update(event(0x05, true), at(300));
+        assert_eq!(keyboard.next_repeat(), Some(at(800)));
+        keyboard.update(event(HID_KEY_A, false), at(400));
+        assert_eq!(keyboard.next_repeat(), Some(at(800)));
+        keyboard.update(event(0x05, false), at(450));
+        assert_eq!(keyboard.next_repeat(), None);
+    }
+}
@@ -0,0 +1,195 @@
+//! usb_absolute_mouse.rs — absolute pointer (tablet-style) USB HID device
+//!
+//! A relative [`MouseReport`](usbd_hid::descriptor::MouseReport) can only nudge the cursor,
+//! and host pointer acceleration makes the result unpredictable. This device reports X/Y
+//! in `0..=`[`ABSOLUTE_MOUSE_MAX`] across the whole screen instead, so a test rig can click
+//! exact coordinates whatever the resolution or acceleration settings. Windows, macOS and
+//! Linux map the range to the full screen (the primary one on multi-monitor setups).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut mouse = UsbAbsoluteMouse::new(p.USB, Irqs, &spawner, UsbHidConfig::default()).await?;
+//! // Click the center of the screen
+//! let (x, y) = (ABSOLUTE_MOUSE_MAX / 2, ABSOLUTE_MOUSE_MAX / 2);
+//! mouse.move_abs(x, y).await?;
+//! mouse.click(MOUSE_BUTTON_LEFT).await?;
+//! // Or in pixels of a 1920x1080 screen
+//! mouse.move_to_pixel(100, 200, 1920, 1080).await?;
+//! ```
+
+use embassy_executor::Spawner;
+use embassy_rp::interrupt::typelevel::Binding;
+use embassy_rp::peripherals::USB;
+use embassy_time::Timer;
+use usbd_hid::descriptor::SerializedDescriptor;
+
+use crate::{
+    HID_USAGE_MOUSE, HID_USAGE_PAGE_BUTTON, HID_USAGE_PAGE_GENERIC_DESKTOP, HID_USAGE_POINTER,
+    HID_USAGE_WHEEL, HID_USAGE_X, HID_USAGE_Y, HidCollection, HidDescriptor, HidItemFlags,
+    UsbHidConfig, UsbHidDevice, UsbHidError,
+};
+
+/// Largest coordinate; 0 is the left/top edge, this the right/bottom edge
+pub const ABSOLUTE_MOUSE_MAX: u16 = 32767;
+
+pub const MOUSE_BUTTON_LEFT: u8 = 0x01;
+pub const MOUSE_BUTTON_RIGHT: u8 = 0x02;
+pub const MOUSE_BUTTON_MIDDLE: u8 = 0x04;
+
+crate::hid_report! {
+    /// Absolute pointer report: three buttons, 15-bit X/Y and a relative wheel
+    pub struct AbsoluteMouseReport {
+        pub buttons: u8,
+        pub x: u16,
+        pub y: u16,
+        pub wheel: i8,
+    }
+    descriptor = HidDescriptor::new()
+        .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+        .usage(HID_USAGE_MOUSE)
+        .collection(HidCollection::Application)
+            .usage(HID_USAGE_POINTER)
+            .collection(HidCollection::Physical)
+                .usage_page(HID_USAGE_PAGE_BUTTON)
+                .usage_minimum(1)
+                .usage_maximum(3)
+                .logical_minimum(0)
+                .logical_maximum(1)
+                .report_size(1)
+                .report_count(3)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+                .report_size(5)
+                .report_count(1)
+                .input(HidItemFlags::CONSTANT)
+                .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+                .usage(HID_USAGE_X)
+                .usage(HID_USAGE_Y)
+                .logical_minimum(0)
+                .logical_maximum(ABSOLUTE_MOUSE_MAX as i32)
+                .report_size(16)
+                .report_count(2)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+                .usage(HID_USAGE_WHEEL)
+                .logical_minimum(-127)
+                .logical_maximum(127)
+                .report_size(8)
+                .report_count(1)
+                .input(HidItemFlags::DATA_VARIABLE_RELATIVE)
+            .end_collection()
+        .end_collection();
+}
+
+/// Map pixel `position` on an axis `size` pixels long to the absolute range.
+pub fn pixel_to_absolute(position: u32, size: u32) -> u16 {
+    if size <= 1 {
+        return 0;
+    }
+    let position = position.min(size - 1) as u64;
+    // Round to nearest so every pixel, including the last, is reachable
+    ((position * ABSOLUTE_MOUSE_MAX as u64 + (size as u64 - 1) / 2) / (size as u64 - 1)) as u16
+}
+
+/// USB absolute pointer
+pub struct UsbAbsoluteMouse {
+    device: UsbHidDevice,
+    report: AbsoluteMouseReport,
+}
+
+impl UsbAbsoluteMouse {
+    pub async fn new<I>(
+        usb: embassy_rp::Peri<'static, USB>,
+        irqs: I,
+        spawner: &Spawner,
+        config: UsbHidConfig,
+    ) -> Result<Self, UsbHidError>
+    where
+        I: Binding<
+                <USB as embassy_rp::usb::Instance>::Interrupt,
+                embassy_rp::usb::InterruptHandler<USB>,
+            >,
+    {
+        let device =
+            UsbHidDevice::new(usb, irqs, spawner, config, AbsoluteMouseReport::desc()).await?;
+        Ok(Self {
+            device,
+            report: AbsoluteMouseReport::default(),
+        })
+    }
+
+    /// Last position and buttons sent
+    pub fn report(&self) -> AbsoluteMouseReport {
+        self.report
+    }
+
+    /// Move to `x`, `y` in `0..=ABSOLUTE_MOUSE_MAX`; larger values are clamped.
+    pub async fn move_abs(&mut self, x: u16, y: u16) -> Result<(), UsbHidError> {
+        self.report.x = x.min(ABSOLUTE_MOUSE_MAX);
+        self.report.y = y.min(ABSOLUTE_MOUSE_MAX);
+        self.send().await
+    }
+
+    /// Move to pixel `x`, `y` of a `width` x `height` screen.
+    pub async fn move_to_pixel(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), UsbHidError> {
+        self.move_abs(pixel_to_absolute(x, width), pixel_to_absolute(y, height))
+            .await
+    }
+
+    /// Hold `buttons` (`MOUSE_BUTTON_*` bits) down.
+    pub async fn press(&mut self, buttons: u8) -> Result<(), UsbHidError> {
+        self.report.buttons |= buttons;
+        self.send().await
+    }
+
+    pub async fn release(&mut self, buttons: u8) -> Result<(), UsbHidError> {
+        self.report.buttons &= !buttons;
+        self.send().await
+    }
+
+    /// Press and release `buttons` at the current position.
+    pub async fn click(&mut self, buttons: u8) -> Result<(), UsbHidError> {
+        self.press(buttons).await?;
+        // Hosts drop clicks shorter than one poll interval
+        Timer::after_millis(self.device.poll_ms().max(1) as u64 * 2).await;
+        self.release(buttons).await
+    }
+
+    /// Turn the wheel `steps` notches, positive away from the user.
+    pub async fn scroll(&mut self, steps: i8) -> Result<(), UsbHidError> {
+        self.report.wheel = steps;
+        let result = self.send().await;
+        self.report.wheel = 0;
+        result?;
+        // Back to a still wheel, so the next identical scroll isn't taken for a duplicate
+        self.send().await
+    }
+
+    async fn send(&mut self) -> Result<(), UsbHidError> {
+        self.device.send_report(&self.report).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_layout_matches_descriptor() {
+        assert_eq!(AbsoluteMouseReport::DESCRIPTOR.input_report_len(), 6);
+    }
+
+    #[test]
+    fn pixels_span_the_full_range() {
+        assert_eq!(pixel_to_absolute(0, 1920), 0);
+        assert_eq!(pixel_to_absolute(1919, 1920), ABSOLUTE_MOUSE_MAX);
+        assert_eq!(pixel_to_absolute(5000, 1920), ABSOLUTE_MOUSE_MAX);
+        assert_eq!(pixel_to_absolute(540, 1081), ABSOLUTE_MOUSE_MAX / 2 + 1);
+        assert_eq!(pixel_to_absolute(3, 0), 0);
+    }
+}
@@ -19,19 +19,24 @@
 //! keyboard.send_report(&report).await?;
 //! ```
 
-use core::sync::atomic::{AtomicBool, Ordering};
-use defmt::info;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use embassy_executor::Spawner;
 use embassy_executor::task;
+use embassy_futures::select::{Either, select};
 use embassy_rp::interrupt::typelevel::Binding;
 use embassy_rp::peripherals::USB;
 use embassy_rp::usb::Driver;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 use embassy_usb::class::hid::{HidBootProtocol, HidReaderWriter, HidSubclass, RequestHandler};
 use embassy_usb::control::OutResponse;
 use embassy_usb::{Builder, Config, Handler};
 use static_cell::StaticCell;
 use usbd_hid::descriptor::{AsInputReport, SerializedDescriptor};
 
+use crate::LogModule;
+
 // ============================================================================
 // ERROR HANDLING
 // ============================================================================
@@ -43,49 +48,102 @@ pub enum UsbHidError {
     TaskSpawnFailed,
     #[error("Failed to write HID report")]
     WriteFailed,
+    #[error("HID output is locked until the user consents")]
+    Locked,
 }
 
 // ============================================================================
 // USB DEVICE HANDLERS
 // ============================================================================
 
+/// Largest report [`UsbHidDevice::send_report`] can send
+const MAX_REPORT_LEN: usize = 64;
+
+/// Set while [`UsbHidDevice::send_report`] refuses reports, see [`UsbHidConfig::require_consent`]
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Idle rate of one HID interface, set by the host with SET_IDLE (0 = only report
+/// changes). Shared by the interface's request handler and its [`UsbHidDevice`].
+struct IdleRate {
+    ms: AtomicU32,
+    /// Signalled when the rate changes, so a pending idle repeat is rescheduled
+    changed: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl IdleRate {
+    const fn new() -> Self {
+        Self {
+            ms: AtomicU32::new(0),
+            changed: Signal::new(),
+        }
+    }
+
+    fn get(&self) -> u32 {
+        self.ms.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, ms: u32) {
+        self.ms.store(ms, Ordering::Relaxed);
+        self.changed.signal(());
+    }
+}
+
 /// Default HID request handler
 ///
-/// Provides empty implementations for all request handler methods.
-/// This is sufficient for most HID devices.
-struct DefaultRequestHandler;
+/// Records the idle rate from SET_IDLE for [`UsbHidDevice`], answers feature report
+/// requests with [`UsbHidConfig::feature_report`] and ignores reports from the host. This
+/// is sufficient for most HID devices.
+struct DefaultRequestHandler {
+    feature_report: &'static [u8],
+    idle: &'static IdleRate,
+}
 
 impl RequestHandler for DefaultRequestHandler {
     fn get_report(
         &mut self,
-        _id: embassy_usb::class::hid::ReportId,
-        _buf: &mut [u8],
+        id: embassy_usb::class::hid::ReportId,
+        buf: &mut [u8],
     ) -> Option<usize> {
-        None
+        match id {
+            embassy_usb::class::hid::ReportId::Feature(_) if !self.feature_report.is_empty() => {
+                let len = self.feature_report.len().min(buf.len());
+                buf[..len].copy_from_slice(&self.feature_report[..len]);
+                Some(len)
+            }
+            _ => None,
+        }
     }
 
     fn set_report(&mut self, _id: embassy_usb::class::hid::ReportId, _data: &[u8]) -> OutResponse {
         OutResponse::Accepted
     }
 
-    fn set_idle_ms(&mut self, _id: Option<embassy_usb::class::hid::ReportId>, _dur: u32) {}
+    fn set_idle_ms(&mut self, _id: Option<embassy_usb::class::hid::ReportId>, dur: u32) {
+        self.idle.set(dur);
+    }
 
     fn get_idle_ms(&mut self, _id: Option<embassy_usb::class::hid::ReportId>) -> Option<u32> {
-        None
+        Some(self.idle.get())
     }
 }
 
 /// Default USB device handler
 ///
-/// Tracks USB device state and logs state transitions.
+/// Tracks USB device state and logs state transitions. A bus reset clears the HID idle
+/// rate, and locks HID output again when consent is required, so every new host session
+/// needs its own consent.
 struct DefaultHandler {
     configured: AtomicBool,
+    require_consent: bool,
+    idle: &'static IdleRate,
 }
 
 impl DefaultHandler {
-    fn new() -> Self {
+    fn new(require_consent: bool, idle: &'static IdleRate) -> Self {
         Self {
             configured: AtomicBool::new(false),
+            require_consent,
+            idle,
         }
     }
 }
@@ -93,26 +151,30 @@ impl DefaultHandler {
 impl Handler for DefaultHandler {
     fn enabled(&mut self, enabled: bool) {
         if enabled {
-            info!("USB Device enabled");
+            crate::log!(LogModule::Usb, Info, "USB Device enabled");
         } else {
-            info!("USB Device disabled");
+            crate::log!(LogModule::Usb, Info, "USB Device disabled");
         }
     }
 
     fn reset(&mut self) {
-        info!("USB Bus reset");
+        crate::log!(LogModule::Usb, Info, "USB Bus reset");
+        self.idle.set(0);
+        if self.require_consent {
+            LOCKED.store(true, Ordering::Relaxed);
+        }
     }
 
     fn addressed(&mut self, _addr: u8) {
-        info!("USB Address set");
+        crate::log!(LogModule::Usb, Info, "USB Address set");
     }
 
     fn configured(&mut self, configured: bool) {
         self.configured.store(configured, Ordering::Relaxed);
         if configured {
-            info!("USB Device configured");
+            crate::log!(LogModule::Usb, Info, "USB Device configured");
         } else {
-            info!("USB Device deconfigured");
+            crate::log!(LogModule::Usb, Info, "USB Device deconfigured");
         }
     }
 }
@@ -163,6 +225,18 @@ pub struct UsbHidConfig {
     pub max_power: u8,
     /// Maximum packet size for endpoint 0
     pub max_packet_size: u8,
+    /// HID interrupt endpoint polling interval (in ms)
+    pub poll_ms: u8,
+    /// Skip reports identical to the previous one and repeat the last report at the
+    /// host's idle rate. Right for state reports (keyboards), wrong for relative mice,
+    /// so [`UsbHidDevice::new_mouse`] turns it off.
+    pub deduplicate: bool,
+    /// Answer to GET_REPORT requests for feature reports (empty: not supported)
+    pub feature_report: &'static [u8],
+    /// Start locked: reports are refused with [`UsbHidError::Locked`] until
+    /// [`UsbHidDevice::unlock`], e.g. after [`wait_for_consent`](crate::wait_for_consent).
+    /// A USB bus reset locks again.
+    pub require_consent: bool,
 }
 
 impl Default for UsbHidConfig {
@@ -175,6 +249,10 @@ impl Default for UsbHidConfig {
             serial_number: None,
             max_power: 100,
             max_packet_size: 64,
+            poll_ms: 60,
+            deduplicate: true,
+            feature_report: &[],
+            require_consent: false,
         }
     }
 }
@@ -200,6 +278,13 @@ impl Default for UsbHidConfig {
 /// ```
 pub struct UsbHidDevice {
     writer: embassy_usb::class::hid::HidWriter<'static, Driver<'static, USB>, 8>,
+    idle: &'static IdleRate,
+    poll_ms: u8,
+    deduplicate: bool,
+    /// Last report sent, for duplicate suppression and idle repeats
+    last_report: [u8; MAX_REPORT_LEN],
+    last_len: usize,
+    last_sent_at: Instant,
 }
 
 impl UsbHidDevice {
@@ -238,7 +323,7 @@ impl UsbHidDevice {
                 embassy_rp::usb::InterruptHandler<USB>,
             >,
     {
-        info!("Initializing USB HID device...");
+        crate::log!(LogModule::Usb, Info, "Initializing USB HID device...");
 
         // Initialize USB driver
         let driver = Driver::new(usb, irqs);
@@ -275,15 +360,20 @@ impl UsbHidDevice {
         // Static storage for HID state and request handler
         static HID_STATE: StaticCell<embassy_usb::class::hid::State<'static>> = StaticCell::new();
         static REQUEST_HANDLER: StaticCell<DefaultRequestHandler> = StaticCell::new();
+        static IDLE_RATE: StaticCell<IdleRate> = StaticCell::new();
 
         let hid_state = HID_STATE.init(embassy_usb::class::hid::State::new());
-        let request_handler = REQUEST_HANDLER.init(DefaultRequestHandler);
+        let idle: &'static IdleRate = IDLE_RATE.init(IdleRate::new());
+        let request_handler = REQUEST_HANDLER.init(DefaultRequestHandler {
+            feature_report: config.feature_report,
+            idle,
+        });
 
         // HID class configuration
         let hid_config = embassy_usb::class::hid::Config {
             report_descriptor,
             request_handler: Some(request_handler),
-            poll_ms: 60,
+            poll_ms: config.poll_ms,
             max_packet_size: 64,
             hid_subclass: HidSubclass::No,
             hid_boot_protocol: HidBootProtocol::None,
@@ -293,7 +383,9 @@ impl UsbHidDevice {
         let hid = HidReaderWriter::<_, 1, 8>::new(&mut builder, hid_state, hid_config);
 
         // Create USB handler
-        let _handler = DefaultHandler::new();
+        static HANDLER: StaticCell<DefaultHandler> = StaticCell::new();
+        builder.handler(HANDLER.init(DefaultHandler::new(config.require_consent, idle)));
+        LOCKED.store(config.require_consent, Ordering::Relaxed);
 
         // Build USB device
         let usb_device = builder.build();
@@ -304,9 +396,17 @@ impl UsbHidDevice {
         // Split HID into reader and writer
         let (_reader, writer) = hid.split();
 
-        info!("USB HID device initialized");
-
-        Ok(Self { writer })
+        crate::log!(LogModule::Usb, Info, "USB HID device initialized");
+
+        Ok(Self {
+            writer,
+            idle,
+            poll_ms: config.poll_ms,
+            deduplicate: config.deduplicate,
+            last_report: [0; MAX_REPORT_LEN],
+            last_len: 0,
+            last_sent_at: Instant::now(),
+        })
     }
 
     /// Create a new USB HID keyboard device
@@ -389,6 +489,11 @@ impl UsbHidDevice {
                 embassy_rp::usb::InterruptHandler<USB>,
             >,
     {
+        // Identical relative reports are separate movements, not duplicates
+        let config = UsbHidConfig {
+            deduplicate: false,
+            ..config
+        };
         Self::new(
             usb,
             irqs,
@@ -399,10 +504,23 @@ impl UsbHidDevice {
         .await
     }
 
+    /// HID interrupt endpoint polling interval (in ms) advertised to the host
+    pub fn poll_ms(&self) -> u8 {
+        self.poll_ms
+    }
+
+    /// Idle rate requested by the host (in ms); 0 means reports are only sent on change
+    pub fn idle_ms(&self) -> u32 {
+        self.idle.get()
+    }
+
     /// Send a HID report
     ///
     /// Low-level API that sends a HID report. The report type must implement
-    /// the `AsInputReport` trait (e.g., KeyboardReport, MouseReport).
+    /// the `AsInputReport` trait (e.g., KeyboardReport, MouseReport). With
+    /// [`UsbHidConfig::deduplicate`], a report equal to the previous one is skipped
+    /// unless the idle period has passed. Fails with [`UsbHidError::Locked`] while the
+    /// device is locked.
     ///
     /// # Arguments
     ///
@@ -421,9 +539,90 @@ impl UsbHidDevice {
     /// keyboard.send_report(&report).await?;
     /// ```
     pub async fn send_report<R: AsInputReport>(&mut self, report: &R) -> Result<(), UsbHidError> {
+        let mut buf = [0u8; MAX_REPORT_LEN];
+        if self.is_locked() {
+            return Err(UsbHidError::Locked);
+        }
+        let len = ssmarshal::serialize(&mut buf, report).map_err(|_| UsbHidError::WriteFailed)?;
+        if self.deduplicate
+            && buf[..len] == self.last_report[..self.last_len]
+            && self
+                .idle_deadline()
+                .is_none_or(|deadline| Instant::now() < deadline)
+        {
+            return Ok(());
+        }
+        self.write(&buf[..len]).await
+    }
+
+    /// Wait for the host's idle period to pass and send the last report again, as the HID
+    /// spec asks of a device whose state didn't change. Never completes while the idle
+    /// rate is 0, before the first report or without [`UsbHidConfig::deduplicate`], so it
+    /// can be selected against the input source:
+    ///
+    /// ```ignore
+    /// match select(matrix.next_event(), keyboard.repeat_on_idle()).await {
+    ///     Either::First(event) => { /* update and send_report */ }
+    ///     Either::Second(result) => result?,
+    /// }
+    /// ```
+    pub async fn repeat_on_idle(&mut self) -> Result<(), UsbHidError> {
+        if !self.deduplicate || self.last_len == 0 {
+            return core::future::pending().await;
+        }
+        loop {
+            // SET_IDLE or a bus reset may change the rate while waiting; start over then
+            let Some(deadline) = self.idle_deadline() else {
+                self.idle.changed.wait().await;
+                continue;
+            };
+            match select(Timer::at(deadline), self.idle.changed.wait()).await {
+                Either::First(()) => {}
+                Either::Second(()) => continue,
+            }
+            if self.is_locked() {
+                // Nothing goes out while locked; try again one idle period later
+                self.last_sent_at = Instant::now();
+                continue;
+            }
+            let report = self.last_report;
+            return self.write(&report[..self.last_len]).await;
+        }
+    }
+
+    /// Whether reports are refused until [`unlock`](Self::unlock)
+    pub fn is_locked(&self) -> bool {
+        LOCKED.load(Ordering::Relaxed)
+    }
+
+    /// Let reports through, once the user has consented.
+    pub fn unlock(&mut self) {
+        LOCKED.store(false, Ordering::Relaxed);
+    }
+
+    /// Refuse reports until the next [`unlock`](Self::unlock). Send a report with nothing
+    /// pressed first, or keys held now stay down on the host.
+    pub fn lock(&mut self) {
+        LOCKED.store(true, Ordering::Relaxed);
+        self.last_len = 0;
+    }
+
+    /// When the last report is due again, if the host set an idle rate
+    fn idle_deadline(&self) -> Option<Instant> {
+        match self.idle.get() {
+            0 => None,
+            ms => Some(self.last_sent_at + Duration::from_millis(ms as u64)),
+        }
+    }
+
+    async fn write(&mut self, report: &[u8]) -> Result<(), UsbHidError> {
         self.writer
-            .write_serialize(report)
+            .write(report)
             .await
-            .map_err(|_| UsbHidError::WriteFailed)
+            .map_err(|_| UsbHidError::WriteFailed)?;
+        self.last_report[..report.len()].copy_from_slice(report);
+        self.last_len = report.len();
+        self.last_sent_at = Instant::now();
+        Ok(())
     }
 }
@@ -0,0 +1,329 @@
+//! usb_digitizer.rs — multi-touch touchscreen (digitizer) USB HID device
+//!
+//! Presents the Pico to the host as a touchscreen with up to [`DIGITIZER_MAX_CONTACTS`]
+//! fingers, so a resistive XPT2046 panel or a capacitive sensor can drive the host UI
+//! directly, taps and gestures included. Coordinates are absolute in
+//! `0..=`[`DIGITIZER_MAX`] across the screen the host maps the digitizer to.
+//!
+//! Every report carries all contacts. A contact that disappears from the list passed to
+//! [`UsbTouchscreen::send_contacts`] is sent once more with its tip switch off, which is
+//! how the host learns the finger was lifted rather than lost.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let config = UsbHidConfig { poll_ms: 10, ..Default::default() };
+//! let mut screen = UsbTouchscreen::new(p.USB, Irqs, &spawner, config).await?;
+//!
+//! // Single touch from an XPT2046 panel
+//! loop {
+//!     let event = touch.wait_for_event().await?;
+//!     screen.send_touch_event(event, 320, 240).await?;
+//! }
+//!
+//! // Two-finger pinch from a capacitive controller
+//! screen
+//!     .send_contacts(&[
+//!         DigitizerContact { id: 0, x: 12000, y: 16000 },
+//!         DigitizerContact { id: 1, x: 20000, y: 16000 },
+//!     ])
+//!     .await?;
+//! ```
+
+use embassy_executor::Spawner;
+use embassy_rp::interrupt::typelevel::Binding;
+use embassy_rp::peripherals::USB;
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+use usbd_hid::descriptor::{AsInputReport, SerializedDescriptor};
+
+use crate::{
+    HID_USAGE_CONTACT_COUNT, HID_USAGE_CONTACT_COUNT_MAXIMUM, HID_USAGE_CONTACT_IDENTIFIER,
+    HID_USAGE_FINGER, HID_USAGE_PAGE_DIGITIZER, HID_USAGE_PAGE_GENERIC_DESKTOP,
+    HID_USAGE_TIP_SWITCH, HID_USAGE_TOUCH_SCREEN, HID_USAGE_X, HID_USAGE_Y, HidCollection,
+    HidDescriptor, HidItemFlags, TouchEvent, UsbHidConfig, UsbHidDevice, UsbHidError,
+    pixel_to_absolute,
+};
+
+/// Contacts in each report
+pub const DIGITIZER_MAX_CONTACTS: usize = 5;
+/// Largest coordinate; 0 is the left/top edge, this the right/bottom edge
+pub const DIGITIZER_MAX: u16 = 32767;
+
+/// `TouchContact::flags` bit for a finger on the surface
+pub const TOUCH_TIP: u8 = 0x01;
+
+/// Descriptor size; five finger collections don't fit the default capacity
+const DIGITIZER_DESCRIPTOR_CAPACITY: usize = 320;
+
+/// Answer to the host's GET_REPORT(Feature) for Contact Count Maximum
+static CONTACT_COUNT_MAXIMUM: [u8; 1] = [DIGITIZER_MAX_CONTACTS as u8];
+
+/// One finger slot of a [`TouchscreenReport`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TouchContact {
+    /// [`TOUCH_TIP`] while the finger is down
+    pub flags: u8,
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Serialize for TouchContact {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(4)?;
+        tuple.serialize_element(&self.flags)?;
+        tuple.serialize_element(&self.id)?;
+        tuple.serialize_element(&self.x)?;
+        tuple.serialize_element(&self.y)?;
+        tuple.end()
+    }
+}
+
+/// Touchscreen input report: every contact slot, then the number of slots in use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TouchscreenReport {
+    pub contacts: [TouchContact; DIGITIZER_MAX_CONTACTS],
+    pub contact_count: u8,
+}
+
+impl TouchscreenReport {
+    pub const DESCRIPTOR: HidDescriptor<DIGITIZER_DESCRIPTOR_CAPACITY> = touchscreen_descriptor();
+    pub const REPORT_LEN: usize = DIGITIZER_MAX_CONTACTS * core::mem::size_of::<TouchContact>() + 1;
+
+    /// Contacts in use
+    pub fn contacts(&self) -> &[TouchContact] {
+        &self.contacts[..self.contact_count as usize]
+    }
+}
+
+const _: () = assert!(
+    TouchscreenReport::DESCRIPTOR.input_report_len() == TouchscreenReport::REPORT_LEN,
+    "report struct size does not match the HID descriptor input report length"
+);
+
+impl Serialize for TouchscreenReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(DIGITIZER_MAX_CONTACTS + 1)?;
+        for contact in &self.contacts {
+            tuple.serialize_element(contact)?;
+        }
+        tuple.serialize_element(&self.contact_count)?;
+        tuple.end()
+    }
+}
+
+impl AsInputReport for TouchscreenReport {}
+
+impl SerializedDescriptor for TouchscreenReport {
+    fn desc() -> &'static [u8] {
+        static DESCRIPTOR: HidDescriptor<DIGITIZER_DESCRIPTOR_CAPACITY> =
+            TouchscreenReport::DESCRIPTOR;
+        DESCRIPTOR.as_bytes()
+    }
+}
+
+#[rustfmt::skip]
+const fn touchscreen_descriptor() -> HidDescriptor<DIGITIZER_DESCRIPTOR_CAPACITY> {
+    let mut desc = HidDescriptor::new()
+        .usage_page(HID_USAGE_PAGE_DIGITIZER)
+        .usage(HID_USAGE_TOUCH_SCREEN)
+        .collection(HidCollection::Application);
+    let mut i = 0;
+    while i < DIGITIZER_MAX_CONTACTS {
+        desc = desc
+            .usage_page(HID_USAGE_PAGE_DIGITIZER)
+            .usage(HID_USAGE_FINGER)
+            .collection(HidCollection::Logical)
+                .usage(HID_USAGE_TIP_SWITCH)
+                .logical_minimum(0)
+                .logical_maximum(1)
+                .report_size(1)
+                .report_count(1)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+                .report_size(7)
+                .input(HidItemFlags::CONSTANT)
+                .usage(HID_USAGE_CONTACT_IDENTIFIER)
+                .logical_maximum(u8::MAX as i32)
+                .report_size(8)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+                .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+                .usage(HID_USAGE_X)
+                .usage(HID_USAGE_Y)
+                .logical_maximum(DIGITIZER_MAX as i32)
+                .report_size(16)
+                .report_count(2)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+            .end_collection();
+        i += 1;
+    }
+    desc.usage_page(HID_USAGE_PAGE_DIGITIZER)
+        .usage(HID_USAGE_CONTACT_COUNT)
+        .logical_maximum(DIGITIZER_MAX_CONTACTS as i32)
+        .report_size(8)
+        .report_count(1)
+        .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+        .usage(HID_USAGE_CONTACT_COUNT_MAXIMUM)
+        .feature(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+        .end_collection()
+}
+
+/// A finger on the surface, in `0..=DIGITIZER_MAX` coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct DigitizerContact {
+    /// Stays the same while the finger stays down
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Report for `contacts` after `previous`: contacts beyond the slot count are dropped, and
+/// contacts that were down in `previous` but are gone now are reported lifted.
+pub fn touchscreen_report(
+    previous: &TouchscreenReport,
+    contacts: &[DigitizerContact],
+) -> TouchscreenReport {
+    let mut report = TouchscreenReport::default();
+    let mut count = 0;
+    for contact in contacts.iter().take(DIGITIZER_MAX_CONTACTS) {
+        report.contacts[count] = TouchContact {
+            flags: TOUCH_TIP,
+            id: contact.id,
+            x: contact.x.min(DIGITIZER_MAX),
+            y: contact.y.min(DIGITIZER_MAX),
+        };
+        count += 1;
+    }
+    let lifted = previous
+        .contacts()
+        .iter()
+        .filter(|old| old.flags & TOUCH_TIP != 0)
+        .filter(|old| {
+            !contacts
+                .iter()
+                .take(DIGITIZER_MAX_CONTACTS)
+                .any(|new| new.id == old.id)
+        });
+    for old in lifted {
+        if count == DIGITIZER_MAX_CONTACTS {
+            break;
+        }
+        report.contacts[count] = TouchContact { flags: 0, ..*old };
+        count += 1;
+    }
+    report.contact_count = count as u8;
+    report
+}
+
+/// USB multi-touch touchscreen
+pub struct UsbTouchscreen {
+    device: UsbHidDevice,
+    report: TouchscreenReport,
+}
+
+impl UsbTouchscreen {
+    /// `config.feature_report` is replaced with the contact count maximum.
+    pub async fn new<I>(
+        usb: embassy_rp::Peri<'static, USB>,
+        irqs: I,
+        spawner: &Spawner,
+        config: UsbHidConfig,
+    ) -> Result<Self, UsbHidError>
+    where
+        I: Binding<
+                <USB as embassy_rp::usb::Instance>::Interrupt,
+                embassy_rp::usb::InterruptHandler<USB>,
+            >,
+    {
+        let config = UsbHidConfig {
+            feature_report: &CONTACT_COUNT_MAXIMUM,
+            ..config
+        };
+        let device =
+            UsbHidDevice::new(usb, irqs, spawner, config, TouchscreenReport::desc()).await?;
+        Ok(Self {
+            device,
+            report: TouchscreenReport::default(),
+        })
+    }
+
+    /// Last report sent
+    pub fn report(&self) -> TouchscreenReport {
+        self.report
+    }
+
+    /// Report the fingers down right now; an empty slice lifts them all.
+    pub async fn send_contacts(
+        &mut self,
+        contacts: &[DigitizerContact],
+    ) -> Result<(), UsbHidError> {
+        self.report = touchscreen_report(&self.report, contacts);
+        self.device.send_report(&self.report).await
+    }
+
+    /// Forward a single-touch panel event, in pixels of a `width` x `height` screen, as
+    /// contact 0.
+    pub async fn send_touch_event(
+        &mut self,
+        event: TouchEvent,
+        width: u32,
+        height: u32,
+    ) -> Result<(), UsbHidError> {
+        match event {
+            TouchEvent::Down(point) | TouchEvent::Move(point) => {
+                let contact = DigitizerContact {
+                    id: 0,
+                    x: pixel_to_absolute(point.x as u32, width),
+                    y: pixel_to_absolute(point.y as u32, height),
+                };
+                self.send_contacts(&[contact]).await
+            }
+            TouchEvent::Up(_) => self.send_contacts(&[]).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_layout_matches_descriptor() {
+        assert_eq!(TouchscreenReport::DESCRIPTOR.input_report_len(), 31);
+    }
+
+    #[test]
+    fn lifted_contacts_are_reported_once() {
+        let a = DigitizerContact {
+            id: 1,
+            x: 100,
+            y: 200,
+        };
+        let b = DigitizerContact {
+            id: 2,
+            x: 40000,
+            y: 300,
+        };
+        let both = touchscreen_report(&TouchscreenReport::default(), &[a, b]);
+        assert_eq!(both.contact_count, 2);
+        assert_eq!(both.contacts[1].x, DIGITIZER_MAX);
+
+        let one = touchscreen_report(&both, &[b]);
+        assert_eq!(one.contact_count, 2);
+        assert_eq!(one.contacts[0].id, 2);
+        assert_eq!(one.contacts[0].flags, TOUCH_TIP);
+        assert_eq!(
+            one.contacts[1],
+            TouchContact {
+                flags: 0,
+                id: 1,
+                x: 100,
+                y: 200
+            }
+        );
+
+        let none = touchscreen_report(&one, &[]);
+        assert_eq!(none.contact_count, 1);
+        assert_eq!(none.contacts[0].flags, 0);
+        assert_eq!(touchscreen_report(&none, &[]).contact_count, 0);
+    }
+}
@@ -0,0 +1,464 @@
+//! usb_hid_descriptor.rs — const builder for custom HID report descriptors
+//!
+//! Descriptors are assembled with `const fn` calls, so the bytes are produced at compile
+//! time and overflowing the buffer or leaving a collection open is a compile error.
+//! [`hid_report!`](crate::hid_report) pairs a descriptor with a typed report struct that can
+//! be passed straight to [`UsbHidDevice::send_report`](crate::UsbHidDevice::send_report).
+//!
+//! # Example
+//!
+//! ```ignore
+//! hid_report! {
+//!     /// Two-axis joystick with eight buttons
+//!     pub struct JoystickReport {
+//!         pub buttons: u8,
+//!         pub x: i8,
+//!         pub y: i8,
+//!     }
+//!     descriptor = HidDescriptor::new()
+//!         .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+//!         .usage(HID_USAGE_JOYSTICK)
+//!         .collection(HidCollection::Application)
+//!             .usage_page(HID_USAGE_PAGE_BUTTON)
+//!             .usage_minimum(1)
+//!             .usage_maximum(8)
+//!             .logical_minimum(0)
+//!             .logical_maximum(1)
+//!             .report_size(1)
+//!             .report_count(8)
+//!             .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+//!             .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+//!             .usage(HID_USAGE_X)
+//!             .usage(HID_USAGE_Y)
+//!             .logical_minimum(-127)
+//!             .logical_maximum(127)
+//!             .report_size(8)
+//!             .report_count(2)
+//!             .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+//!         .end_collection();
+//! }
+//!
+//! let mut joystick = UsbHidDevice::new(p.USB, Irqs, &spawner, config, JoystickReport::desc()).await?;
+//! joystick.send_report(&JoystickReport { buttons: 0b1, x: 10, y: -3 }).await?;
+//! ```
+
+#[doc(hidden)]
+pub use serde as __serde;
+#[doc(hidden)]
+pub use usbd_hid as __usbd_hid;
+
+/// Default capacity of a [`HidDescriptor`] in bytes
+pub const HID_DESCRIPTOR_DEFAULT_CAPACITY: usize = 128;
+
+pub const HID_USAGE_PAGE_GENERIC_DESKTOP: u16 = 0x01;
+pub const HID_USAGE_PAGE_SIMULATION: u16 = 0x02;
+pub const HID_USAGE_PAGE_KEYBOARD: u16 = 0x07;
+pub const HID_USAGE_PAGE_LEDS: u16 = 0x08;
+pub const HID_USAGE_PAGE_BUTTON: u16 = 0x09;
+pub const HID_USAGE_PAGE_DIGITIZER: u16 = 0x0D;
+pub const HID_USAGE_PAGE_CONSUMER: u16 = 0x0C;
+pub const HID_USAGE_PAGE_VENDOR: u16 = 0xFF00;
+
+pub const HID_USAGE_POINTER: u16 = 0x01;
+pub const HID_USAGE_MOUSE: u16 = 0x02;
+pub const HID_USAGE_JOYSTICK: u16 = 0x04;
+pub const HID_USAGE_GAMEPAD: u16 = 0x05;
+pub const HID_USAGE_KEYBOARD: u16 = 0x06;
+pub const HID_USAGE_X: u16 = 0x30;
+pub const HID_USAGE_Y: u16 = 0x31;
+pub const HID_USAGE_Z: u16 = 0x32;
+pub const HID_USAGE_WHEEL: u16 = 0x38;
+
+// Digitizer page usages
+pub const HID_USAGE_TOUCH_SCREEN: u16 = 0x04;
+pub const HID_USAGE_FINGER: u16 = 0x22;
+pub const HID_USAGE_TIP_SWITCH: u16 = 0x42;
+pub const HID_USAGE_CONTACT_IDENTIFIER: u16 = 0x51;
+pub const HID_USAGE_CONTACT_COUNT: u16 = 0x54;
+pub const HID_USAGE_CONTACT_COUNT_MAXIMUM: u16 = 0x55;
+
+/// HID collection types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HidCollection {
+    Physical = 0x00,
+    Application = 0x01,
+    Logical = 0x02,
+    Report = 0x03,
+}
+
+/// Flags for Input/Output/Feature main items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct HidItemFlags(pub u8);
+
+impl HidItemFlags {
+    /// Variable data, absolute values (buttons, absolute axes)
+    pub const DATA_VARIABLE_ABSOLUTE: Self = Self(0x02);
+    /// Variable data, relative values (mouse movement, wheels)
+    pub const DATA_VARIABLE_RELATIVE: Self = Self(0x06);
+    /// Array data, absolute values (keyboard keycode arrays)
+    pub const DATA_ARRAY_ABSOLUTE: Self = Self(0x00);
+    /// Constant padding bits
+    pub const CONSTANT: Self = Self(0x01);
+}
+
+// Short item prefixes (tag | type), size bits are added on encoding.
+const ITEM_INPUT: u8 = 0x80;
+const ITEM_OUTPUT: u8 = 0x90;
+const ITEM_FEATURE: u8 = 0xB0;
+const ITEM_COLLECTION: u8 = 0xA0;
+const ITEM_END_COLLECTION: u8 = 0xC0;
+const ITEM_USAGE_PAGE: u8 = 0x04;
+const ITEM_LOGICAL_MINIMUM: u8 = 0x14;
+const ITEM_LOGICAL_MAXIMUM: u8 = 0x24;
+const ITEM_REPORT_SIZE: u8 = 0x74;
+const ITEM_REPORT_ID: u8 = 0x84;
+const ITEM_REPORT_COUNT: u8 = 0x94;
+const ITEM_USAGE: u8 = 0x08;
+const ITEM_USAGE_MINIMUM: u8 = 0x18;
+const ITEM_USAGE_MAXIMUM: u8 = 0x28;
+
+/// HID report descriptor assembled at compile time
+///
+/// Also tracks the total size of input and output reports so typed reports can be checked
+/// against the descriptor.
+#[derive(Debug, Clone, Copy)]
+pub struct HidDescriptor<const N: usize = HID_DESCRIPTOR_DEFAULT_CAPACITY> {
+    bytes: [u8; N],
+    len: usize,
+    depth: u8,
+    report_size: u32,
+    report_count: u32,
+    report_id: Option<u8>,
+    input_bits: u32,
+    output_bits: u32,
+}
+
+impl<const N: usize> Default for HidDescriptor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> HidDescriptor<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+            depth: 0,
+            report_size: 0,
+            report_count: 0,
+            report_id: None,
+            input_bits: 0,
+            output_bits: 0,
+        }
+    }
+
+    pub const fn usage_page(self, page: u16) -> Self {
+        self.item_unsigned(ITEM_USAGE_PAGE, page as u32)
+    }
+
+    pub const fn usage(self, usage: u16) -> Self {
+        self.item_unsigned(ITEM_USAGE, usage as u32)
+    }
+
+    pub const fn usage_minimum(self, usage: u16) -> Self {
+        self.item_unsigned(ITEM_USAGE_MINIMUM, usage as u32)
+    }
+
+    pub const fn usage_maximum(self, usage: u16) -> Self {
+        self.item_unsigned(ITEM_USAGE_MAXIMUM, usage as u32)
+    }
+
+    pub const fn logical_minimum(self, value: i32) -> Self {
+        self.item_signed(ITEM_LOGICAL_MINIMUM, value)
+    }
+
+    pub const fn logical_maximum(self, value: i32) -> Self {
+        self.item_signed(ITEM_LOGICAL_MAXIMUM, value)
+    }
+
+    /// Size of each following field in bits
+    pub const fn report_size(mut self, bits: u32) -> Self {
+        self.report_size = bits;
+        self.item_unsigned(ITEM_REPORT_SIZE, bits)
+    }
+
+    /// Number of fields in each following main item
+    pub const fn report_count(mut self, count: u32) -> Self {
+        self.report_count = count;
+        self.item_unsigned(ITEM_REPORT_COUNT, count)
+    }
+
+    /// Prefix reports with an ID byte. Only a single report ID per descriptor is supported.
+    pub const fn report_id(mut self, id: u8) -> Self {
+        assert!(id != 0, "HID report ID 0 is reserved");
+        assert!(
+            self.report_id.is_none(),
+            "only one HID report ID is supported"
+        );
+        self.report_id = Some(id);
+        self.item_unsigned(ITEM_REPORT_ID, id as u32)
+    }
+
+    pub const fn collection(mut self, kind: HidCollection) -> Self {
+        self.depth += 1;
+        self.item_unsigned(ITEM_COLLECTION, kind as u32)
+    }
+
+    pub const fn end_collection(mut self) -> Self {
+        assert!(self.depth > 0, "end_collection without matching collection");
+        self.depth -= 1;
+        self.push(ITEM_END_COLLECTION)
+    }
+
+    pub const fn input(mut self, flags: HidItemFlags) -> Self {
+        self.input_bits += self.report_size * self.report_count;
+        self.item_unsigned(ITEM_INPUT, flags.0 as u32)
+    }
+
+    pub const fn output(mut self, flags: HidItemFlags) -> Self {
+        self.output_bits += self.report_size * self.report_count;
+        self.item_unsigned(ITEM_OUTPUT, flags.0 as u32)
+    }
+
+    pub const fn feature(self, flags: HidItemFlags) -> Self {
+        self.item_unsigned(ITEM_FEATURE, flags.0 as u32)
+    }
+
+    /// The ID set with [`report_id`](Self::report_id); reports then start with this byte.
+    pub const fn id(&self) -> Option<u8> {
+        self.report_id
+    }
+
+    /// Input report length in bytes, including the report ID byte if any
+    pub const fn input_report_len(&self) -> usize {
+        self.report_len(self.input_bits)
+    }
+
+    /// Output report length in bytes, including the report ID byte if any
+    pub const fn output_report_len(&self) -> usize {
+        self.report_len(self.output_bits)
+    }
+
+    /// Descriptor bytes. Panics (at compile time when used in a `const`) if a collection is
+    /// left open.
+    pub const fn as_bytes(&self) -> &[u8] {
+        assert!(self.depth == 0, "HID descriptor has unclosed collections");
+        self.bytes.split_at(self.len).0
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    const fn report_len(&self, bits: u32) -> usize {
+        let id_len = if self.report_id.is_some() { 1 } else { 0 };
+        bits.div_ceil(8) as usize + id_len
+    }
+
+    const fn item_unsigned(self, prefix: u8, value: u32) -> Self {
+        if value <= u8::MAX as u32 {
+            self.push(prefix | 1).push(value as u8)
+        } else if value <= u16::MAX as u32 {
+            self.push(prefix | 2).push_le(value, 2)
+        } else {
+            self.push(prefix | 3).push_le(value, 4)
+        }
+    }
+
+    const fn item_signed(self, prefix: u8, value: i32) -> Self {
+        if value >= i8::MIN as i32 && value <= i8::MAX as i32 {
+            self.push(prefix | 1).push(value as u8)
+        } else if value >= i16::MIN as i32 && value <= i16::MAX as i32 {
+            self.push(prefix | 2).push_le(value as u32, 2)
+        } else {
+            self.push(prefix | 3).push_le(value as u32, 4)
+        }
+    }
+
+    const fn push_le(mut self, value: u32, len: usize) -> Self {
+        let mut i = 0;
+        while i < len {
+            self = self.push((value >> (8 * i)) as u8);
+            i += 1;
+        }
+        self
+    }
+
+    const fn push(mut self, byte: u8) -> Self {
+        assert!(self.len < N, "HID descriptor capacity exceeded");
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        self
+    }
+}
+
+/// Declare a typed HID input report together with its descriptor.
+///
+/// Generates the struct, its `Serialize`/`AsInputReport` impls (fields are sent packed, in
+/// declaration order, after the report ID byte if the descriptor sets one) and a
+/// `SerializedDescriptor` impl, so `Report::desc()` can be passed to
+/// [`UsbHidDevice::new`](crate::UsbHidDevice::new). The struct layout is checked against the
+/// descriptor's input report length at compile time.
+///
+/// See the `usb_hid_descriptor.rs` module docs for an example.
+#[macro_export]
+macro_rules! hid_report {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+        descriptor = $descriptor:expr;
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $name {
+            pub const DESCRIPTOR: $crate::HidDescriptor = $descriptor;
+            pub const REPORT_ID: ::core::option::Option<u8> = Self::DESCRIPTOR.id();
+            /// Bytes on the wire: the report ID, if any, then the fields
+            pub const REPORT_LEN: usize =
+                Self::REPORT_ID.is_some() as usize $(+ ::core::mem::size_of::<$ty>())*;
+        }
+
+        const _: () = {
+            assert!(!$name::DESCRIPTOR.as_bytes().is_empty());
+            assert!(
+                $name::DESCRIPTOR.input_report_len() == $name::REPORT_LEN,
+                "report struct size does not match the HID descriptor input report length"
+            );
+        };
+
+        impl $crate::__serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__serde::Serializer,
+            {
+                use $crate::__serde::ser::SerializeTuple;
+                let fields = 0 $(+ { let _ = stringify!($field); 1 })*;
+                let mut tuple =
+                    serializer.serialize_tuple($name::REPORT_ID.is_some() as usize + fields)?;
+                if let ::core::option::Option::Some(id) = $name::REPORT_ID {
+                    tuple.serialize_element(&id)?;
+                }
+                $(tuple.serialize_element(&self.$field)?;)*
+                tuple.end()
+            }
+        }
+
+        impl $crate::__usbd_hid::descriptor::AsInputReport for $name {}
+
+        impl $crate::__usbd_hid::descriptor::SerializedDescriptor for $name {
+            fn desc() -> &'static [u8] {
+                static DESCRIPTOR: $crate::HidDescriptor = $name::DESCRIPTOR;
+                DESCRIPTOR.as_bytes()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_encoding() {
+        const DESC: HidDescriptor<32> = HidDescriptor::new()
+            .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+            .usage(HID_USAGE_MOUSE)
+            .collection(HidCollection::Application)
+            .logical_minimum(-127)
+            .logical_maximum(255)
+            .usage_page(HID_USAGE_PAGE_VENDOR)
+            .end_collection();
+
+        assert_eq!(
+            DESC.as_bytes(),
+            &[
+                0x05, 0x01, // Usage Page (Generic Desktop)
+                0x09, 0x02, // Usage (Mouse)
+                0xA1, 0x01, // Collection (Application)
+                0x15, 0x81, // Logical Minimum (-127)
+                0x26, 0xFF, 0x00, // Logical Maximum (255)
+                0x06, 0x00, 0xFF, // Usage Page (Vendor)
+                0xC0, // End Collection
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_length_tracking() {
+        const DESC: HidDescriptor<64> = HidDescriptor::new()
+            .report_id(2)
+            .report_size(1)
+            .report_count(3)
+            .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+            .report_size(5)
+            .report_count(1)
+            .input(HidItemFlags::CONSTANT)
+            .report_size(8)
+            .report_count(2)
+            .input(HidItemFlags::DATA_VARIABLE_RELATIVE)
+            .report_size(1)
+            .report_count(5)
+            .output(HidItemFlags::DATA_VARIABLE_ABSOLUTE);
+
+        assert_eq!(DESC.input_report_len(), 4);
+        assert_eq!(DESC.output_report_len(), 2);
+    }
+
+    crate::hid_report! {
+        struct PlainReport {
+            buttons: u8,
+            x: i8,
+        }
+        descriptor = HidDescriptor::new()
+            .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+            .usage(HID_USAGE_JOYSTICK)
+            .collection(HidCollection::Application)
+                .report_size(8)
+                .report_count(2)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+            .end_collection();
+    }
+
+    crate::hid_report! {
+        struct NumberedReport {
+            buttons: u8,
+            x: i8,
+        }
+        descriptor = HidDescriptor::new()
+            .usage_page(HID_USAGE_PAGE_GENERIC_DESKTOP)
+            .usage(HID_USAGE_JOYSTICK)
+            .collection(HidCollection::Application)
+                .report_id(3)
+                .report_size(8)
+                .report_count(2)
+                .input(HidItemFlags::DATA_VARIABLE_ABSOLUTE)
+            .end_collection();
+    }
+
+    #[test]
+    fn test_report_id_counts_in_report_len() {
+        assert_eq!(PlainReport::REPORT_ID, None);
+        assert_eq!(PlainReport::REPORT_LEN, 2);
+        assert_eq!(NumberedReport::REPORT_ID, Some(3));
+        assert_eq!(NumberedReport::REPORT_LEN, 3);
+
+        let mut buf = [0u8; 4];
+        let plain = PlainReport { buttons: 1, x: -1 };
+        let len = ssmarshal::serialize(&mut buf, &plain).unwrap();
+        assert_eq!(&buf[..len], &[0x01, 0xFF]);
+        let numbered = NumberedReport { buttons: 1, x: -1 };
+        let len = ssmarshal::serialize(&mut buf, &numbered).unwrap();
+        assert_eq!(&buf[..len], &[0x03, 0x01, 0xFF]);
+    }
+}
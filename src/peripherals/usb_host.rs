@@ -0,0 +1,1004 @@
+//! usb_host.rs — experimental USB host for keyboards, mice and gamepads
+//!
+//! [`UsbHidHost`] enumerates one device and polls its first boot keyboard or mouse
+//! interface, reporting keys as the [`KeyEvent`]s the PS/2 driver produces and mouse
+//! movement as the same `MouseReport` the device side sends. That makes a USB-to-USB
+//! bridge (KVM switch, key remapper, mouse jiggler) a loop of `next_event` and
+//! `send_report`. A device without a boot interface is read through its HID report
+//! descriptor; if that describes a gamepad or joystick, its buttons, axes and hat come
+//! out as [`GamepadReport`]s whenever they change.
+//!
+//! The wire is behind [`UsbHostBus`]: bus reset, control transfers, interrupt IN polling
+//! and frame keep-alive. The RP2040's own USB block is taken by the device side, so the
+//! backend is [`PioUsbBus`](crate::PioUsbBus), bit-banged on two GPIOs by PIO; anything
+//! else implementing the trait works too.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut host = UsbHidHost::new(pio_usb_bus);
+//! loop {
+//!     match host.next_event().await {
+//!         Ok(HostHidEvent::Key(event)) => {
+//!             if keyboard_state.update(event) {
+//!                 usb_keyboard.send_report(&keyboard_state.report()).await?;
+//!             }
+//!         }
+//!         Ok(HostHidEvent::Mouse(report)) => usb_mouse.send_report(&report).await?,
+//!         Ok(HostHidEvent::Gamepad(pad)) => robot.drive(pad.axes[0], pad.axes[1]),
+//!         Err(_) => Timer::after_millis(500).await, // unplugged: retry enumeration
+//!     }
+//! }
+//! ```
+
+use embassy_time::{Duration, Timer};
+use usbd_hid::descriptor::MouseReport;
+
+use crate::{
+    HID_KEY_LEFT_CTRL, HID_USAGE_GAMEPAD, HID_USAGE_JOYSTICK, HID_USAGE_PAGE_BUTTON,
+    HID_USAGE_PAGE_GENERIC_DESKTOP, HeaplessVec, KeyEvent,
+};
+
+/// Largest configuration descriptor read during enumeration
+pub const USB_HOST_MAX_CONFIG_LEN: usize = 256;
+/// Largest HID report descriptor read during enumeration
+pub const USB_HOST_MAX_REPORT_DESCRIPTOR_LEN: usize = 256;
+/// Longest input report read from a device
+pub const USB_HOST_MAX_REPORT_LEN: usize = 64;
+
+/// Address given to the (single) attached device
+const DEVICE_ADDRESS: u8 = 1;
+const DESCRIPTOR_DEVICE: u8 = 1;
+const DESCRIPTOR_CONFIGURATION: u8 = 2;
+const DESCRIPTOR_INTERFACE: u8 = 4;
+const DESCRIPTOR_ENDPOINT: u8 = 5;
+const DESCRIPTOR_HID: u8 = 0x21;
+const DESCRIPTOR_REPORT: u8 = 0x22;
+const CLASS_HID: u8 = 3;
+const SUBCLASS_BOOT: u8 = 1;
+const PROTOCOL_KEYBOARD: u8 = 1;
+const PROTOCOL_MOUSE: u8 = 2;
+/// Boot keyboard "too many keys" marker
+const KEY_ROLL_OVER: u8 = 0x01;
+
+// HID report descriptor items (prefix without the size bits) and usages
+const ITEM_INPUT: u8 = 0x80;
+const ITEM_COLLECTION: u8 = 0xa0;
+const ITEM_END_COLLECTION: u8 = 0xc0;
+const ITEM_USAGE_PAGE: u8 = 0x04;
+const ITEM_LOGICAL_MINIMUM: u8 = 0x14;
+const ITEM_LOGICAL_MAXIMUM: u8 = 0x24;
+const ITEM_REPORT_SIZE: u8 = 0x74;
+const ITEM_REPORT_ID: u8 = 0x84;
+const ITEM_REPORT_COUNT: u8 = 0x94;
+const ITEM_USAGE: u8 = 0x08;
+const ITEM_USAGE_MINIMUM: u8 = 0x18;
+const ITEM_USAGE_MAXIMUM: u8 = 0x28;
+const ITEM_LONG: u8 = 0xfe;
+const COLLECTION_APPLICATION: u32 = 0x01;
+const INPUT_CONSTANT: u32 = 0x01;
+const INPUT_VARIABLE: u32 = 0x02;
+/// Generic desktop axes in [`GamepadReport::axes`] order: X, Y, Z, Rx, Ry, Rz
+const GAMEPAD_AXES: [u16; 6] = [0x30, 0x31, 0x32, 0x33, 0x34, 0x35];
+const USAGE_HAT_SWITCH: u16 = 0x39;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum UsbHostError {
+    #[error("No USB device attached")]
+    NoDevice,
+    #[error("USB device stalled the request")]
+    Stall,
+    #[error("USB device did not respond")]
+    Timeout,
+    #[error("Malformed USB descriptor or report")]
+    Protocol,
+    #[error("USB device is not a keyboard, mouse or gamepad")]
+    Unsupported,
+    #[error("USB D- must be on the GPIO after D+")]
+    InvalidPins,
+    #[error("Full-speed USB needs a system clock that is a multiple of 48 MHz")]
+    UnsupportedClock,
+}
+
+/// Standard 8-byte SETUP packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    pub fn get_descriptor(descriptor_type: u8, length: u16) -> Self {
+        Self {
+            request_type: 0x80,
+            request: 0x06,
+            value: (descriptor_type as u16) << 8,
+            index: 0,
+            length,
+        }
+    }
+
+    pub fn set_address(address: u8) -> Self {
+        Self {
+            request_type: 0x00,
+            request: 0x05,
+            value: address as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    pub fn set_configuration(value: u8) -> Self {
+        Self {
+            request_type: 0x00,
+            request: 0x09,
+            value: value as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    /// HID class request selecting the boot protocol on `interface`
+    pub fn set_boot_protocol(interface: u8) -> Self {
+        Self {
+            request_type: 0x21,
+            request: 0x0b,
+            value: 0,
+            index: interface as u16,
+            length: 0,
+        }
+    }
+
+    /// Standard request for the HID report descriptor of `interface`
+    pub fn get_report_descriptor(interface: u8, length: u16) -> Self {
+        Self {
+            request_type: 0x81,
+            request: 0x06,
+            value: (DESCRIPTOR_REPORT as u16) << 8,
+            index: interface as u16,
+            length,
+        }
+    }
+
+    /// HID class request: only report on change
+    pub fn set_idle(interface: u8) -> Self {
+        Self {
+            request_type: 0x21,
+            request: 0x0a,
+            value: 0,
+            index: interface as u16,
+            length: 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let [v0, v1] = self.value.to_le_bytes();
+        let [i0, i1] = self.index.to_le_bytes();
+        let [l0, l1] = self.length.to_le_bytes();
+        [self.request_type, self.request, v0, v1, i0, i1, l0, l1]
+    }
+}
+
+/// Physical layer of a USB host port (e.g. PIO-USB)
+#[allow(async_fn_in_trait)]
+pub trait UsbHostBus {
+    /// Wait for a device, reset the bus and leave the device at address 0.
+    async fn reset(&mut self) -> Result<(), UsbHostError>;
+
+    /// Control transfer with an IN data stage; returns the bytes received.
+    async fn control_in(
+        &mut self,
+        address: u8,
+        max_packet: u8,
+        setup: &SetupPacket,
+        buf: &mut [u8],
+    ) -> Result<usize, UsbHostError>;
+
+    /// Control transfer without a data stage.
+    async fn control_out(
+        &mut self,
+        address: u8,
+        max_packet: u8,
+        setup: &SetupPacket,
+    ) -> Result<(), UsbHostError>;
+
+    /// One interrupt IN transaction; `None` if the device NAKed (nothing new).
+    async fn interrupt_in(
+        &mut self,
+        address: u8,
+        endpoint: u8,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, UsbHostError>;
+
+    /// Let `frames` 1 ms frames pass, keeping the device from suspending if the bus needs
+    /// the host to (SOF or keep-alive).
+    async fn wait_frames(&mut self, frames: u16) {
+        Timer::after(Duration::from_millis(frames as u64)).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HidBootDevice {
+    Keyboard,
+    Mouse,
+}
+
+/// A boot-protocol HID interface found in a configuration descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct HidBootInterface {
+    pub device: HidBootDevice,
+    pub configuration: u8,
+    pub interface: u8,
+    /// Interrupt IN endpoint number
+    pub endpoint: u8,
+    pub max_packet: u16,
+    /// Polling interval in ms
+    pub interval: u8,
+}
+
+/// A HID interface without boot protocol (gamepads, joysticks) found in a configuration
+/// descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct HidReportInterface {
+    pub configuration: u8,
+    pub interface: u8,
+    /// Interrupt IN endpoint number
+    pub endpoint: u8,
+    pub max_packet: u16,
+    /// Polling interval in ms
+    pub interval: u8,
+    /// Length of the interface's report descriptor
+    pub report_descriptor_len: u16,
+}
+
+/// What [`UsbHidHost::connect`] set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HostHidDevice {
+    Boot(HidBootInterface),
+    Gamepad(HidReportInterface, GamepadLayout),
+}
+
+impl HostHidDevice {
+    fn endpoint(&self) -> u8 {
+        match self {
+            Self::Boot(interface) => interface.endpoint,
+            Self::Gamepad(interface, _) => interface.endpoint,
+        }
+    }
+
+    fn interval(&self) -> u8 {
+        match self {
+            Self::Boot(interface) => interface.interval,
+            Self::Gamepad(interface, _) => interface.interval,
+        }
+    }
+}
+
+/// Gamepad or joystick input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+pub struct GamepadReport {
+    /// Bit n set while button n + 1 is held
+    pub buttons: u32,
+    /// X, Y, Z, Rx, Ry, Rz scaled to -32767..=32767; 0 for axes the gamepad lacks
+    pub axes: [i16; 6],
+    /// Hat switch direction, 0 (up) to 7 clockwise; `None` when centred or absent
+    pub hat: Option<u8>,
+}
+
+/// Where one value sits in an input report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct HidField {
+    pub bit_offset: u16,
+    pub bits: u8,
+    pub logical_min: i32,
+    pub logical_max: i32,
+}
+
+impl HidField {
+    /// The field's value in `data`, sign-extended if its logical range is signed
+    fn read(&self, data: &[u8]) -> Option<i32> {
+        let bits = self.bits.min(32) as usize;
+        let mut raw = 0u32;
+        for i in 0..bits {
+            let bit = self.bit_offset as usize + i;
+            if data.get(bit / 8)? & (1 << (bit % 8)) != 0 {
+                raw |= 1 << i;
+            }
+        }
+        if self.logical_min < 0 && (1..32).contains(&bits) && raw & (1 << (bits - 1)) != 0 {
+            raw |= u32::MAX << bits;
+        }
+        Some(raw as i32)
+    }
+}
+
+/// Input report layout of a gamepad or joystick, read from its HID report descriptor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+pub struct GamepadLayout {
+    /// Leading report ID byte, if the device uses them
+    pub report_id: Option<u8>,
+    pub axes: [Option<HidField>; 6],
+    pub hat: Option<HidField>,
+    /// First button; the others follow bit by bit
+    pub buttons: Option<HidField>,
+    pub button_count: u8,
+}
+
+impl GamepadLayout {
+    /// Fields of the first joystick or gamepad application collection in `descriptor`;
+    /// `None` if there is none or it has no buttons and axes.
+    pub fn parse(descriptor: &[u8]) -> Option<Self> {
+        let mut layout = Self::default();
+        let mut usage_page = 0u16;
+        let mut logical_min = 0i32;
+        let mut logical_max = 0i32;
+        let mut report_size = 0u32;
+        let mut report_count = 0u32;
+        let mut report_id: Option<u8> = None;
+        let mut usages: HeaplessVec<(u16, u16), 16> = HeaplessVec::new();
+        let mut usage_range: (Option<u16>, Option<u16>) = (None, None);
+        // Bit offsets so far, per report ID
+        let mut offsets: HeaplessVec<(Option<u8>, u32), 8> = HeaplessVec::new();
+        let mut depth = 0u8;
+        let mut gamepad_depth: Option<u8> = None;
+        let mut done = false;
+
+        let mut pos = 0;
+        while pos < descriptor.len() && !done {
+            let prefix = descriptor[pos];
+            if prefix == ITEM_LONG {
+                pos += 3 + *descriptor.get(pos + 1)? as usize;
+                continue;
+            }
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            let data = descriptor.get(pos + 1..pos + 1 + size)?;
+            pos += 1 + size;
+            let unsigned = data
+                .iter()
+                .rev()
+                .fold(0u32, |value, &byte| value << 8 | byte as u32);
+            let signed = match size {
+                1 => data[0] as i8 as i32,
+                2 => i16::from_le_bytes([data[0], data[1]]) as i32,
+                4 => unsigned as i32,
+                _ => 0,
+            };
+            // A 4-byte usage carries its own page
+            let usage = match size {
+                4 => ((unsigned >> 16) as u16, unsigned as u16),
+                _ => (usage_page, unsigned as u16),
+            };
+
+            match prefix & 0xfc {
+                ITEM_USAGE_PAGE => usage_page = unsigned as u16,
+                ITEM_LOGICAL_MINIMUM => logical_min = signed,
+                ITEM_LOGICAL_MAXIMUM => {
+                    // A non-negative minimum means the maximum is unsigned
+                    logical_max = if logical_min >= 0 {
+                        unsigned as i32
+                    } else {
+                        signed
+                    };
+                }
+                ITEM_REPORT_SIZE => report_size = unsigned,
+                ITEM_REPORT_COUNT => report_count = unsigned,
+                ITEM_REPORT_ID => report_id = Some(unsigned as u8),
+                ITEM_USAGE => {
+                    let _ = usages.push(usage);
+                }
+                ITEM_USAGE_MINIMUM => usage_range.0 = Some(unsigned as u16),
+                ITEM_USAGE_MAXIMUM => usage_range.1 = Some(unsigned as u16),
+                ITEM_COLLECTION => {
+                    depth = depth.saturating_add(1);
+                    let application = matches!(
+                        usages.first(),
+                        Some(&(HID_USAGE_PAGE_GENERIC_DESKTOP, HID_USAGE_JOYSTICK))
+                            | Some(&(HID_USAGE_PAGE_GENERIC_DESKTOP, HID_USAGE_GAMEPAD))
+                    );
+                    if gamepad_depth.is_none() && application && unsigned == COLLECTION_APPLICATION
+                    {
+                        gamepad_depth = Some(depth);
+                    }
+                }
+                ITEM_END_COLLECTION => {
+                    done = gamepad_depth == Some(depth);
+                    depth = depth.saturating_sub(1);
+                }
+                ITEM_INPUT => {
+                    let index = match offsets.iter().position(|(id, _)| *id == report_id) {
+                        Some(index) => index,
+                        None => {
+                            offsets.push((report_id, 0)).ok()?;
+                            offsets.len() - 1
+                        }
+                    };
+                    let offset = &mut offsets[index].1;
+                    let in_gamepad = gamepad_depth.is_some()
+                        && layout.report_id.is_none_or(|id| Some(id) == report_id);
+                    let variable = unsigned & INPUT_CONSTANT == 0 && unsigned & INPUT_VARIABLE != 0;
+                    for i in 0..report_count {
+                        let field_usage = match usage_range {
+                            (Some(min), _) => Some((usage_page, min.saturating_add(i as u16))),
+                            _ => usages.get(i as usize).or(usages.last()).copied(),
+                        };
+                        if in_gamepad && variable {
+                            let field = HidField {
+                                bit_offset: *offset as u16,
+                                bits: report_size as u8,
+                                logical_min,
+                                logical_max,
+                            };
+                            if layout.add(field_usage, field) {
+                                layout.report_id = report_id;
+                            }
+                        }
+                        *offset += report_size;
+                    }
+                }
+                _ => {}
+            }
+            // Local items only apply to the next main item
+            if prefix & 0x0c == 0 {
+                usages.clear();
+                usage_range = (None, None);
+            }
+        }
+
+        let found = layout.buttons.is_some() || layout.axes.iter().any(Option::is_some);
+        found.then_some(layout)
+    }
+
+    /// Record `field` if it's a button, axis or hat; `true` if it was used.
+    fn add(&mut self, usage: Option<(u16, u16)>, field: HidField) -> bool {
+        match usage {
+            Some((HID_USAGE_PAGE_BUTTON, _)) if field.bits == 1 => {
+                let next = self
+                    .buttons
+                    .map(|first| first.bit_offset + self.button_count as u16);
+                match next {
+                    None => self.buttons = Some(field),
+                    Some(offset) if offset == field.bit_offset && self.button_count < 32 => {}
+                    Some(_) => return false,
+                }
+                self.button_count += 1;
+                true
+            }
+            Some((HID_USAGE_PAGE_GENERIC_DESKTOP, USAGE_HAT_SWITCH)) => {
+                self.hat.get_or_insert(field);
+                true
+            }
+            Some((HID_USAGE_PAGE_GENERIC_DESKTOP, usage)) => {
+                match GAMEPAD_AXES.iter().position(|&axis| axis == usage) {
+                    Some(index) => {
+                        self.axes[index].get_or_insert(field);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Decode an input report; `None` if it's too short or has another report ID.
+    pub fn decode(&self, report: &[u8]) -> Option<GamepadReport> {
+        let data = match self.report_id {
+            Some(id) => match report.split_first()? {
+                (&first, rest) if first == id => rest,
+                _ => return None,
+            },
+            None => report,
+        };
+        let mut state = GamepadReport::default();
+        if let Some(first) = self.buttons {
+            for i in 0..self.button_count {
+                let button = HidField {
+                    bit_offset: first.bit_offset + i as u16,
+                    ..first
+                };
+                if button.read(data)? != 0 {
+                    state.buttons |= 1 << i;
+                }
+            }
+        }
+        for (axis, field) in state.axes.iter_mut().zip(&self.axes) {
+            if let Some(field) = field {
+                *axis = scale_axis(field.read(data)?, field);
+            }
+        }
+        if let Some(field) = self.hat {
+            let value = field.read(data)?;
+            let positions = field.logical_max - field.logical_min + 1;
+            if (field.logical_min..=field.logical_max).contains(&value) && positions > 0 {
+                state.hat = Some(((value - field.logical_min) * 8 / positions) as u8);
+            }
+        }
+        Some(state)
+    }
+}
+
+/// `value` from the field's logical range to -32767..=32767
+fn scale_axis(value: i32, field: &HidField) -> i16 {
+    let range = field.logical_max as i64 - field.logical_min as i64;
+    if range <= 0 {
+        return 0;
+    }
+    let value = (value as i64).clamp(field.logical_min as i64, field.logical_max as i64);
+    ((value - field.logical_min as i64) * 65_534 / range - 32_767) as i16
+}
+
+pub enum HostHidEvent {
+    Key(KeyEvent),
+    Mouse(MouseReport),
+    Gamepad(GamepadReport),
+}
+
+pub struct UsbHidHost<B: UsbHostBus> {
+    bus: B,
+    device: Option<HostHidDevice>,
+    last_keys: [u8; 8],
+    last_gamepad: GamepadReport,
+    /// Events from the last keyboard report: up to 8 modifiers, 6 releases, 6 presses
+    pending: HeaplessVec<KeyEvent, 20>,
+}
+
+impl<B: UsbHostBus> UsbHidHost<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            device: None,
+            last_keys: [0; 8],
+            last_gamepad: GamepadReport::default(),
+            pending: HeaplessVec::new(),
+        }
+    }
+
+    /// The enumerated device, if one is connected.
+    pub fn device(&self) -> Option<&HostHidDevice> {
+        self.device.as_ref()
+    }
+
+    /// Reset the port and set up the attached device: boot protocol for a keyboard or
+    /// mouse, otherwise its report descriptor is read for a gamepad layout.
+    pub async fn connect(&mut self) -> Result<HostHidDevice, UsbHostError> {
+        self.device = None;
+        self.last_keys = [0; 8];
+        self.last_gamepad = GamepadReport::default();
+        self.pending.clear();
+        self.bus.reset().await?;
+
+        let mut header = [0u8; 8];
+        let setup = SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 8);
+        if self.bus.control_in(0, 8, &setup, &mut header).await? < 8 {
+            return Err(UsbHostError::Protocol);
+        }
+        let max_packet = header[7];
+        self.bus
+            .control_out(0, max_packet, &SetupPacket::set_address(DEVICE_ADDRESS))
+            .await?;
+        // Set-address recovery time
+        self.bus.wait_frames(2).await;
+
+        let mut config = [0u8; USB_HOST_MAX_CONFIG_LEN];
+        let setup = SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 9);
+        self.bus
+            .control_in(DEVICE_ADDRESS, max_packet, &setup, &mut config[..9])
+            .await?;
+        let total =
+            (u16::from_le_bytes([config[2], config[3]]) as usize).min(USB_HOST_MAX_CONFIG_LEN);
+        let setup = SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, total as u16);
+        let len = self
+            .bus
+            .control_in(DEVICE_ADDRESS, max_packet, &setup, &mut config[..total])
+            .await?;
+        let config = &config[..len];
+
+        let boot = find_boot_interface(config);
+        let report = find_report_interface(config);
+        let (configuration, interface) = match (boot, report) {
+            (Some(boot), _) => (boot.configuration, boot.interface),
+            (None, Some(report)) => (report.configuration, report.interface),
+            (None, None) => return Err(UsbHostError::Unsupported),
+        };
+        self.bus
+            .control_out(
+                DEVICE_ADDRESS,
+                max_packet,
+                &SetupPacket::set_configuration(configuration),
+            )
+            .await?;
+
+        let device = match boot {
+            Some(boot) => {
+                self.bus
+                    .control_out(
+                        DEVICE_ADDRESS,
+                        max_packet,
+                        &SetupPacket::set_boot_protocol(interface),
+                    )
+                    .await?;
+                HostHidDevice::Boot(boot)
+            }
+            None => {
+                let report = report.ok_or(UsbHostError::Unsupported)?;
+                let mut descriptor = [0u8; USB_HOST_MAX_REPORT_DESCRIPTOR_LEN];
+                let wanted =
+                    (report.report_descriptor_len as usize).min(USB_HOST_MAX_REPORT_DESCRIPTOR_LEN);
+                let setup = SetupPacket::get_report_descriptor(interface, wanted as u16);
+                let len = self
+                    .bus
+                    .control_in(
+                        DEVICE_ADDRESS,
+                        max_packet,
+                        &setup,
+                        &mut descriptor[..wanted],
+                    )
+                    .await?;
+                let layout =
+                    GamepadLayout::parse(&descriptor[..len]).ok_or(UsbHostError::Unsupported)?;
+                HostHidDevice::Gamepad(report, layout)
+            }
+        };
+        // Optional; some mice stall it
+        let _ = self
+            .bus
+            .control_out(
+                DEVICE_ADDRESS,
+                max_packet,
+                &SetupPacket::set_idle(interface),
+            )
+            .await;
+        self.device = Some(device);
+        Ok(device)
+    }
+
+    /// Next key, mouse or gamepad event, enumerating the device first if needed. On an
+    /// error the device is considered gone and the next call enumerates again.
+    pub async fn next_event(&mut self) -> Result<HostHidEvent, UsbHostError> {
+        loop {
+            if let Some(event) = self.pending.dequeue_front() {
+                return Ok(HostHidEvent::Key(event));
+            }
+            let device = match self.device {
+                Some(device) => device,
+                None => self.connect().await?,
+            };
+            self.bus.wait_frames(device.interval().max(1) as u16).await;
+            let mut report = [0u8; USB_HOST_MAX_REPORT_LEN];
+            let received = self
+                .bus
+                .interrupt_in(DEVICE_ADDRESS, device.endpoint(), &mut report)
+                .await;
+            let len = match received {
+                Ok(Some(len)) => len,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.device = None;
+                    return Err(e);
+                }
+            };
+            match device {
+                HostHidDevice::Boot(HidBootInterface {
+                    device: HidBootDevice::Keyboard,
+                    ..
+                }) => {
+                    if len < 8 {
+                        continue;
+                    }
+                    let mut keys = [0u8; 8];
+                    keys.copy_from_slice(&report[..8]);
+                    diff_keyboard_reports(&self.last_keys, &keys, |event| {
+                        let _ = self.pending.push(event);
+                    });
+                    if !keys[2..].contains(&KEY_ROLL_OVER) {
+                        self.last_keys = keys;
+                    }
+                }
+                HostHidDevice::Boot(HidBootInterface {
+                    device: HidBootDevice::Mouse,
+                    ..
+                }) => {
+                    if len >= 3 {
+                        return Ok(HostHidEvent::Mouse(parse_mouse_report(&report[..len])));
+                    }
+                }
+                HostHidDevice::Gamepad(_, layout) => {
+                    if let Some(state) = layout.decode(&report[..len])
+                        && state != self.last_gamepad
+                    {
+                        self.last_gamepad = state;
+                        return Ok(HostHidEvent::Gamepad(state));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+/// First HID boot keyboard or mouse interface with an interrupt IN endpoint
+pub fn find_boot_interface(config: &[u8]) -> Option<HidBootInterface> {
+    let configuration = *config.get(5)?;
+    let mut current: Option<(HidBootDevice, u8)> = None;
+    let mut offset = 0;
+    while offset + 2 <= config.len() {
+        let len = config[offset] as usize;
+        if len < 2 || offset + len > config.len() {
+            return None;
+        }
+        let descriptor = &config[offset..offset + len];
+        match descriptor[1] {
+            DESCRIPTOR_INTERFACE if len >= 9 => {
+                current = match (descriptor[5], descriptor[6], descriptor[7]) {
+                    (CLASS_HID, SUBCLASS_BOOT, PROTOCOL_KEYBOARD) => {
+                        Some((HidBootDevice::Keyboard, descriptor[2]))
+                    }
+                    (CLASS_HID, SUBCLASS_BOOT, PROTOCOL_MOUSE) => {
+                        Some((HidBootDevice::Mouse, descriptor[2]))
+                    }
+                    _ => None,
+                };
+            }
+            DESCRIPTOR_ENDPOINT if len >= 7 => {
+                let is_interrupt_in = descriptor[2] & 0x80 != 0 && descriptor[3] & 0x03 == 0x03;
+                if let (Some((device, interface)), true) = (current, is_interrupt_in) {
+                    return Some(HidBootInterface {
+                        device,
+                        configuration,
+                        interface,
+                        endpoint: descriptor[2] & 0x0f,
+                        max_packet: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+                        interval: descriptor[6],
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+    None
+}
+
+/// First HID interface without boot protocol that has an interrupt IN endpoint
+pub fn find_report_interface(config: &[u8]) -> Option<HidReportInterface> {
+    let configuration = *config.get(5)?;
+    // Interface number and report descriptor length
+    let mut current: Option<(u8, u16)> = None;
+    let mut offset = 0;
+    while offset + 2 <= config.len() {
+        let len = config[offset] as usize;
+        if len < 2 || offset + len > config.len() {
+            return None;
+        }
+        let descriptor = &config[offset..offset + len];
+        match descriptor[1] {
+            DESCRIPTOR_INTERFACE if len >= 9 => {
+                current = (descriptor[5] == CLASS_HID && descriptor[6] != SUBCLASS_BOOT)
+                    .then_some((descriptor[2], 0));
+            }
+            DESCRIPTOR_HID if len >= 9 && descriptor[6] == DESCRIPTOR_REPORT => {
+                if let Some((_, report_len)) = current.as_mut() {
+                    *report_len = u16::from_le_bytes([descriptor[7], descriptor[8]]);
+                }
+            }
+            DESCRIPTOR_ENDPOINT if len >= 7 => {
+                let is_interrupt_in = descriptor[2] & 0x80 != 0 && descriptor[3] & 0x03 == 0x03;
+                if let (Some((interface, report_descriptor_len)), true) = (current, is_interrupt_in)
+                {
+                    return Some(HidReportInterface {
+                        configuration,
+                        interface,
+                        endpoint: descriptor[2] & 0x0f,
+                        max_packet: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+                        interval: descriptor[6],
+                        report_descriptor_len,
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+    None
+}
+
+/// Key events turning boot keyboard report `old` into `new`: modifiers first, then
+/// releases, then presses. A rollover report (`01` in the key slots) changes nothing.
+fn diff_keyboard_reports(old: &[u8; 8], new: &[u8; 8], mut emit: impl FnMut(KeyEvent)) {
+    let changed = old[0] ^ new[0];
+    for bit in 0..8 {
+        if changed & (1 << bit) != 0 {
+            emit(KeyEvent {
+                usage: HID_KEY_LEFT_CTRL + bit,
+                pressed: new[0] & (1 << bit) != 0,
+            });
+        }
+    }
+    if new[2..].contains(&KEY_ROLL_OVER) {
+        return;
+    }
+    for &usage in old[2..].iter().filter(|&&k| k > KEY_ROLL_OVER) {
+        if !new[2..].contains(&usage) {
+            emit(KeyEvent {
+                usage,
+                pressed: false,
+            });
+        }
+    }
+    for &usage in new[2..].iter().filter(|&&k| k > KEY_ROLL_OVER) {
+        if !old[2..].contains(&usage) {
+            emit(KeyEvent {
+                usage,
+                pressed: true,
+            });
+        }
+    }
+}
+
+/// Boot mouse report: buttons, x, y and an optional wheel byte
+fn parse_mouse_report(report: &[u8]) -> MouseReport {
+    MouseReport {
+        buttons: report[0],
+        x: report[1] as i8,
+        y: report[2] as i8,
+        wheel: report.get(3).map_or(0, |&w| w as i8),
+        pan: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_packet_bytes() {
+        assert_eq!(
+            SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0x22).to_bytes(),
+            [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0x22, 0x00]
+        );
+        assert_eq!(
+            SetupPacket::set_boot_protocol(1).to_bytes(),
+            [0x21, 0x0b, 0, 0, 1, 0, 0, 0]
+        );
+    }
+
+    #[rustfmt::skip]
+    const CONFIG: [u8; 59] = [
+        9, 2, 59, 0, 2, 1, 0, 0xa0, 50,
+        // Interface 0: HID, no boot protocol, with an interrupt IN endpoint
+        9, 4, 0, 0, 1, 3, 0, 0, 0,
+        9, 0x21, 0x11, 1, 0, 1, 0x22, 50, 0,
+        7, 5, 0x81, 3, 8, 0, 10,
+        // Interface 1: boot mouse
+        9, 4, 1, 0, 1, 3, 1, 2, 0,
+        9, 0x21, 0x11, 1, 0, 1, 0x22, 52, 0,
+        7, 5, 0x82, 3, 4, 0, 8,
+    ];
+
+    /// Report ID 1: 16 buttons, a hat switch plus padding, then X, Y, Z and Rz bytes
+    #[rustfmt::skip]
+    const GAMEPAD_DESCRIPTOR: [u8; 64] = [
+        0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, 0x85, 0x01,
+        0x05, 0x09, 0x19, 0x01, 0x29, 0x10, 0x15, 0x00, 0x25, 0x01,
+        0x75, 0x01, 0x95, 0x10, 0x81, 0x02,
+        0x05, 0x01, 0x09, 0x39, 0x15, 0x00, 0x25, 0x07,
+        0x75, 0x04, 0x95, 0x01, 0x81, 0x42,
+        0x75, 0x04, 0x95, 0x01, 0x81, 0x03,
+        0x09, 0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35, 0x15, 0x00, 0x26, 0xff, 0x00,
+        0x75, 0x08, 0x95, 0x04, 0x81, 0x02,
+        0xc0,
+    ];
+
+    #[test]
+    fn finds_boot_mouse_after_other_interfaces() {
+        assert_eq!(
+            find_boot_interface(&CONFIG),
+            Some(HidBootInterface {
+                device: HidBootDevice::Mouse,
+                configuration: 1,
+                interface: 1,
+                endpoint: 2,
+                max_packet: 4,
+                interval: 8,
+            })
+        );
+        assert_eq!(find_boot_interface(&CONFIG[..40]), None);
+    }
+
+    #[test]
+    fn finds_report_interface_before_boot_mouse() {
+        assert_eq!(
+            find_report_interface(&CONFIG),
+            Some(HidReportInterface {
+                configuration: 1,
+                interface: 0,
+                endpoint: 1,
+                max_packet: 8,
+                interval: 10,
+                report_descriptor_len: 50,
+            })
+        );
+        assert_eq!(find_report_interface(&CONFIG[..30]), None);
+    }
+
+    #[test]
+    fn parses_gamepad_layout() {
+        let field = |bit_offset, bits, logical_min, logical_max| HidField {
+            bit_offset,
+            bits,
+            logical_min,
+            logical_max,
+        };
+        let axis = |bit_offset| Some(field(bit_offset, 8, 0, 255));
+        assert_eq!(
+            GamepadLayout::parse(&GAMEPAD_DESCRIPTOR),
+            Some(GamepadLayout {
+                report_id: Some(1),
+                axes: [axis(24), axis(32), axis(40), None, None, axis(48)],
+                hat: Some(field(16, 4, 0, 7)),
+                buttons: Some(field(0, 1, 0, 1)),
+                button_count: 16,
+            })
+        );
+        // A mouse collection has no gamepad fields
+        assert_eq!(
+            GamepadLayout::parse(&[0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0xc0]),
+            None
+        );
+    }
+
+    #[test]
+    fn decodes_gamepad_reports() {
+        let layout = GamepadLayout::parse(&GAMEPAD_DESCRIPTOR).unwrap();
+        assert_eq!(
+            layout.decode(&[1, 0x05, 0x80, 0x02, 0, 255, 128, 255]),
+            Some(GamepadReport {
+                buttons: 0x8005,
+                axes: [-32_767, 32_767, 128, 0, 0, 32_767],
+                hat: Some(2),
+            })
+        );
+        let centred = layout.decode(&[1, 0, 0, 0x0f, 0, 0, 0, 0]).unwrap();
+        assert_eq!(centred.hat, None);
+        assert_eq!(layout.decode(&[2, 0, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!(layout.decode(&[1, 0x05]), None);
+    }
+
+    #[test]
+    fn keyboard_report_diff() {
+        let mut events: HeaplessVec<KeyEvent, 20> = HeaplessVec::new();
+        let old = [0x00, 0, 0x04, 0x05, 0, 0, 0, 0];
+        let new = [0x02, 0, 0x05, 0x06, 0, 0, 0, 0];
+        diff_keyboard_reports(&old, &new, |e| {
+            let _ = events.push(e);
+        });
+        let key = |usage, pressed| KeyEvent { usage, pressed };
+        assert_eq!(
+            events.as_slice(),
+            &[key(0xe1, true), key(0x04, false), key(0x06, true)]
+        );
+
+        let mut count = 0;
+        diff_keyboard_reports(&new, &[0x02, 0, 1, 1, 1, 1, 1, 1], |_| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn mouse_report() {
+        let report = parse_mouse_report(&[0x01, 0xfe, 0x03]);
+        assert_eq!(
+            (report.buttons, report.x, report.y, report.wheel),
+            (1, -2, 3, 0)
+        );
+        assert_eq!(parse_mouse_report(&[0, 0, 0, 0xff]).wheel, -1);
+    }
+}
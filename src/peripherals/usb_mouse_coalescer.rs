@@ -0,0 +1,203 @@
+//! usb_mouse_coalescer.rs — batches relative mouse movement into HID poll-sized reports
+//!
+//! Producers call [`MouseCoalescer::move_by`] / [`MouseCoalescer::scroll`] without awaiting
+//! USB; a single task running [`MouseCoalescer::run`] flushes the accumulated deltas once per
+//! HID poll interval.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static MOUSE: MouseCoalescer = MouseCoalescer::new();
+//!
+//! #[embassy_executor::task]
+//! async fn mouse_task(mut mouse: UsbHidDevice) {
+//!     let _ = MOUSE.run(&mut mouse).await;
+//! }
+//!
+//! // Anywhere else, without awaiting:
+//! MOUSE.move_by(3, -1);
+//! ```
+
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Ticker};
+use usbd_hid::descriptor::MouseReport;
+
+use crate::{UsbHidDevice, UsbHidError};
+
+/// Largest relative step a single mouse report can carry on each axis.
+const MAX_REPORT_STEP: i32 = 127;
+
+/// Movement, scroll and button state gathered since the last flushed report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct MouseAccumulator {
+    x: i32,
+    y: i32,
+    wheel: i32,
+    pan: i32,
+    buttons: u8,
+    buttons_dirty: bool,
+}
+
+impl MouseAccumulator {
+    fn is_idle(&self) -> bool {
+        self.x == 0 && self.y == 0 && self.wheel == 0 && self.pan == 0 && !self.buttons_dirty
+    }
+
+    /// Take as much of the pending movement as fits into one report.
+    /// Anything beyond the per-report range stays queued for the next poll.
+    fn take_report(&mut self) -> MouseReport {
+        self.buttons_dirty = false;
+        MouseReport {
+            buttons: self.buttons,
+            x: take_step(&mut self.x),
+            y: take_step(&mut self.y),
+            wheel: take_step(&mut self.wheel),
+            pan: take_step(&mut self.pan),
+        }
+    }
+}
+
+fn take_step(pending: &mut i32) -> i8 {
+    let step = (*pending).clamp(-MAX_REPORT_STEP, MAX_REPORT_STEP);
+    *pending -= step;
+    step as i8
+}
+
+/// Coalesces relative mouse input and flushes it at the HID poll interval.
+///
+/// Intended to live in a `static` so any task can feed it.
+pub struct MouseCoalescer {
+    state: Mutex<CriticalSectionRawMutex, Cell<MouseAccumulator>>,
+    pending: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl Default for MouseCoalescer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MouseCoalescer {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(Cell::new(MouseAccumulator {
+                x: 0,
+                y: 0,
+                wheel: 0,
+                pan: 0,
+                buttons: 0,
+                buttons_dirty: false,
+            })),
+            pending: Signal::new(),
+        }
+    }
+
+    /// Queue a relative pointer movement. Never waits for USB.
+    pub fn move_by(&self, dx: i32, dy: i32) {
+        self.update(|acc| {
+            acc.x = acc.x.saturating_add(dx);
+            acc.y = acc.y.saturating_add(dy);
+        });
+    }
+
+    /// Queue vertical (`wheel`) and horizontal (`pan`) scroll steps.
+    pub fn scroll(&self, wheel: i32, pan: i32) {
+        self.update(|acc| {
+            acc.wheel = acc.wheel.saturating_add(wheel);
+            acc.pan = acc.pan.saturating_add(pan);
+        });
+    }
+
+    /// Set the button bitmask (bit 0 = left, bit 1 = right, bit 2 = middle).
+    /// A change is always reported, even without movement.
+    pub fn set_buttons(&self, buttons: u8) {
+        self.update(|acc| {
+            if acc.buttons != buttons {
+                acc.buttons = buttons;
+                acc.buttons_dirty = true;
+            }
+        });
+    }
+
+    /// Flush queued input to `device`, one report per poll interval.
+    ///
+    /// Runs forever; only returns if writing a report fails.
+    pub async fn run(&self, device: &mut UsbHidDevice) -> Result<(), UsbHidError> {
+        let interval = Duration::from_millis(device.poll_ms().max(1) as u64);
+        let mut ticker = Ticker::every(interval);
+
+        loop {
+            self.pending.wait().await;
+            ticker.reset();
+
+            while let Some(report) = self.take_report() {
+                device.send_report(&report).await?;
+                ticker.next().await;
+            }
+        }
+    }
+
+    fn take_report(&self) -> Option<MouseReport> {
+        self.state.lock(|cell| {
+            let mut acc = cell.get();
+            if acc.is_idle() {
+                return None;
+            }
+            let report = acc.take_report();
+            cell.set(acc);
+            Some(report)
+        })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut MouseAccumulator)) {
+        self.state.lock(|cell| {
+            let mut acc = cell.get();
+            f(&mut acc);
+            cell.set(acc);
+        });
+        self.pending.signal(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_report_splits_large_moves() {
+        let mut acc = MouseAccumulator {
+            x: 300,
+            y: -5,
+            ..Default::default()
+        };
+
+        let report = acc.take_report();
+        assert_eq!((report.x, report.y), (127, -5));
+        assert!(!acc.is_idle());
+
+        let report = acc.take_report();
+        assert_eq!((report.x, report.y), (127, 0));
+
+        let report = acc.take_report();
+        assert_eq!((report.x, report.y), (46, 0));
+        assert!(acc.is_idle());
+    }
+
+    #[test]
+    fn test_button_change_is_reported_once() {
+        let mut acc = MouseAccumulator {
+            buttons: 0b001,
+            buttons_dirty: true,
+            ..Default::default()
+        };
+        assert!(!acc.is_idle());
+
+        let report = acc.take_report();
+        assert_eq!(report.buttons, 0b001);
+        assert_eq!(report.x, 0);
+        assert!(acc.is_idle());
+    }
+}
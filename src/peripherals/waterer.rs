@@ -0,0 +1,171 @@
+//! waterer.rs — relay/pump driver with run-time and cooldown limits
+//!
+//! The pump is always switched off when a watering call returns *or is cancelled*, and a
+//! single run can never exceed `max_run_time`, so a stuck sensor or logic bug cannot flood
+//! the plant.
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+
+use crate::SoilMoisture;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum WatererError {
+    #[error("Pump pin operation failed")]
+    Pin,
+    #[error("Pump is cooling down for another {remaining_ms} ms")]
+    CoolingDown { remaining_ms: u64 },
+    #[error("Failed to read soil moisture")]
+    Sensor,
+    #[error("Burst time must not be zero")]
+    ZeroBurst,
+}
+
+/// Watering safety limits
+#[derive(Debug, Clone)]
+pub struct WatererConfig {
+    /// Longest single pump run
+    pub max_run_time: Duration,
+    /// Minimum pause between the end of one run and the start of the next
+    pub cooldown: Duration,
+    /// Relay/MOSFET input is active-high (most MOSFET boards) or active-low (most relay boards)
+    pub active_high: bool,
+}
+
+impl Default for WatererConfig {
+    fn default() -> Self {
+        Self {
+            max_run_time: Duration::from_secs(10),
+            cooldown: Duration::from_secs(60 * 30),
+            active_high: true,
+        }
+    }
+}
+
+/// Pump driven through a relay or MOSFET
+pub struct Waterer<P: OutputPin> {
+    pump: P,
+    config: WatererConfig,
+    last_run_end: Option<Instant>,
+}
+
+impl<P: OutputPin> Waterer<P> {
+    /// The pump is switched off immediately.
+    pub fn new(pump: P, config: WatererConfig) -> Result<Self, WatererError> {
+        let mut waterer = Self {
+            pump,
+            config,
+            last_run_end: None,
+        };
+        waterer.set_pump(false)?;
+        Ok(waterer)
+    }
+
+    /// Time left until the pump may run again
+    pub fn cooldown_remaining(&self) -> Duration {
+        let Some(end) = self.last_run_end else {
+            return Duration::MIN;
+        };
+        self.config
+            .cooldown
+            .checked_sub(end.elapsed())
+            .unwrap_or(Duration::MIN)
+    }
+
+    /// Run the pump for `duration` (capped at `max_run_time`). Returns the actual run time.
+    pub async fn water(&mut self, duration: Duration) -> Result<Duration, WatererError> {
+        self.check_cooldown()?;
+        let run_time = duration.min(self.config.max_run_time);
+
+        let mut run = PumpRun::start(self)?;
+        Timer::after(run_time).await;
+        run.stop()?;
+        Ok(run_time)
+    }
+
+    /// Pump in short bursts until `sensor` reports at least `target_percent`, giving the water
+    /// `soak_time` to spread between bursts. Total pump time is capped at `max_run_time`.
+    /// A zero `burst` would never add up to it and is rejected.
+    pub async fn water_until(
+        &mut self,
+        sensor: &mut SoilMoisture<'_>,
+        target_percent: u8,
+        burst: Duration,
+        soak_time: Duration,
+    ) -> Result<Duration, WatererError> {
+        if burst == Duration::MIN {
+            return Err(WatererError::ZeroBurst);
+        }
+        self.check_cooldown()?;
+        let mut total = Duration::MIN;
+
+        while total < self.config.max_run_time {
+            let moisture = sensor
+                .read_percent()
+                .await
+                .map_err(|_| WatererError::Sensor)?;
+            if moisture >= target_percent {
+                break;
+            }
+
+            let run_time = burst.min(self.config.max_run_time - total);
+            let mut run = PumpRun::start(self)?;
+            Timer::after(run_time).await;
+            run.stop()?;
+            total += run_time;
+
+            Timer::after(soak_time).await;
+        }
+        Ok(total)
+    }
+
+    fn check_cooldown(&self) -> Result<(), WatererError> {
+        let remaining = self.cooldown_remaining();
+        if remaining > Duration::MIN {
+            return Err(WatererError::CoolingDown {
+                remaining_ms: remaining.as_millis(),
+            });
+        }
+        Ok(())
+    }
+
+    fn set_pump(&mut self, on: bool) -> Result<(), WatererError> {
+        let result = if on == self.config.active_high {
+            self.pump.set_high()
+        } else {
+            self.pump.set_low()
+        };
+        result.map_err(|_| WatererError::Pin)
+    }
+}
+
+/// Keeps the pump on while alive; switches it off on drop so a cancelled future can't leave
+/// it running.
+struct PumpRun<'a, P: OutputPin> {
+    waterer: &'a mut Waterer<P>,
+    running: bool,
+}
+
+impl<'a, P: OutputPin> PumpRun<'a, P> {
+    fn start(waterer: &'a mut Waterer<P>) -> Result<Self, WatererError> {
+        waterer.set_pump(true)?;
+        Ok(Self {
+            waterer,
+            running: true,
+        })
+    }
+
+    fn stop(&mut self) -> Result<(), WatererError> {
+        self.running = false;
+        self.waterer.last_run_end = Some(Instant::now());
+        self.waterer.set_pump(false)
+    }
+}
+
+impl<P: OutputPin> Drop for PumpRun<'_, P> {
+    fn drop(&mut self) {
+        if self.running {
+            let _ = self.stop();
+        }
+    }
+}
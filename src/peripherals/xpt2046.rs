@@ -0,0 +1,500 @@
+//! xpt2046.rs — XPT2046 / ADS7843 resistive touchscreen controller (SPI)
+//!
+//! Found on most SPI TFT modules next to the ST7789/ILI9341. Each sample is the median of
+//! several conversions; samples with too little pressure, or whose conversions disagree
+//! (the plate contact is still settling), are dropped. [`Xpt2046::next_event`] sleeps on
+//! the PENIRQ pin while nothing touches the screen and reports debounced down, move and
+//! up events in screen coordinates.
+//!
+//! Raw readings map to pixels through an affine [`TouchCalibration`], which also absorbs
+//! rotated or mirrored mounting. Compute it once from three touched targets with
+//! [`TouchCalibration::from_points`] and keep it in a [`KvStore`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! let spi = Spi::new_blocking(p.SPI1, p.PIN_10, p.PIN_11, p.PIN_12, xpt2046_default_spi_config());
+//! let cs = Output::new(p.PIN_13, Level::High);
+//! let irq = Input::new(p.PIN_14, Pull::Up);
+//! let mut touch = Xpt2046::new(spi, cs, irq, Xpt2046Config::new(240, 320));
+//! touch.load_calibration(&mut store, "touch/cal")?;
+//!
+//! loop {
+//!     match touch.next_event().await? {
+//!         TouchEvent::Down(p) => info!("down at {},{}", p.x, p.y),
+//!         TouchEvent::Move(p) => info!("move to {},{}", p.x, p.y),
+//!         TouchEvent::Up(_) => info!("up"),
+//!     }
+//! }
+//! ```
+
+use embassy_rp::gpio::{Input, Output};
+use embassy_rp::spi::{self, Spi};
+use embassy_time::{Duration, Timer};
+
+use crate::{KvStore, KvStoreError};
+
+/// Largest raw conversion result (12 bits)
+pub const XPT2046_MAX_RAW: u16 = 4095;
+/// Fixed-point scale of the [`TouchCalibration`] coefficients
+pub const TOUCH_CALIBRATION_SCALE: i64 = 1 << 16;
+
+const CMD_X: u8 = 0xd0;
+const CMD_Y: u8 = 0x90;
+const CMD_Z1: u8 = 0xb0;
+const CMD_Z2: u8 = 0xc0;
+/// Conversions per axis in one sample
+const OVERSAMPLING: usize = 5;
+
+pub fn xpt2046_default_spi_config() -> spi::Config {
+    let mut cfg = spi::Config::default();
+    cfg.frequency = 2_000_000;
+    cfg.phase = spi::Phase::CaptureOnFirstTransition;
+    cfg.polarity = spi::Polarity::IdleLow;
+    cfg
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum Xpt2046Error {
+    #[error("SPI transfer with the touch controller failed")]
+    Communication,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TouchPoint {
+    pub x: u16,
+    pub y: u16,
+    /// Larger is harder; compare with [`Xpt2046Config::pressure_threshold`]
+    pub pressure: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TouchEvent {
+    Down(TouchPoint),
+    Move(TouchPoint),
+    /// Carries the last position seen before the release
+    Up(TouchPoint),
+}
+
+/// Raw readings to screen pixels:
+/// `x = (a·raw_x + b·raw_y + c) / SCALE`, `y = (d·raw_x + e·raw_y + f) / SCALE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TouchCalibration {
+    pub a: i32,
+    pub b: i32,
+    pub c: i32,
+    pub d: i32,
+    pub e: i32,
+    pub f: i32,
+}
+
+impl TouchCalibration {
+    /// Axis-aligned mapping of the raw range `min..=max` onto `width` x `height`, for use
+    /// until a proper calibration is stored.
+    pub fn from_raw_range(min: (u16, u16), max: (u16, u16), width: u16, height: u16) -> Self {
+        let span_x = (max.0.saturating_sub(min.0)).max(1) as i64;
+        let span_y = (max.1.saturating_sub(min.1)).max(1) as i64;
+        let a = (width as i64 * TOUCH_CALIBRATION_SCALE + span_x / 2) / span_x;
+        let e = (height as i64 * TOUCH_CALIBRATION_SCALE + span_y / 2) / span_y;
+        Self {
+            a: a as i32,
+            b: 0,
+            c: (-a * min.0 as i64) as i32,
+            d: 0,
+            e: e as i32,
+            f: (-e * min.1 as i64) as i32,
+        }
+    }
+
+    /// Solve the transform from three `(raw, screen)` pairs. The targets should be far
+    /// apart and not on one line (e.g. 10 % in from three corners); returns `None` if
+    /// they are collinear.
+    pub fn from_points(points: [((u16, u16), (u16, u16)); 3]) -> Option<Self> {
+        let [(r1, s1), (r2, s2), (r3, s3)] =
+            points.map(|(r, s)| ((r.0 as i64, r.1 as i64), (s.0 as i64, s.1 as i64)));
+        let det = (r1.0 - r3.0) * (r2.1 - r3.1) - (r2.0 - r3.0) * (r1.1 - r3.1);
+        if det == 0 {
+            return None;
+        }
+        // Cramer's rule on the differences to point 3, per screen axis
+        let solve = |v1: i64, v2: i64, v3: i64| {
+            let a = ((v1 - v3) * (r2.1 - r3.1) - (v2 - v3) * (r1.1 - r3.1))
+                * TOUCH_CALIBRATION_SCALE
+                / det;
+            let b = ((r1.0 - r3.0) * (v2 - v3) - (r2.0 - r3.0) * (v1 - v3))
+                * TOUCH_CALIBRATION_SCALE
+                / det;
+            let c = v3 * TOUCH_CALIBRATION_SCALE - a * r3.0 - b * r3.1;
+            (a as i32, b as i32, c as i32)
+        };
+        let (a, b, c) = solve(s1.0, s2.0, s3.0);
+        let (d, e, f) = solve(s1.1, s2.1, s3.1);
+        Some(Self { a, b, c, d, e, f })
+    }
+
+    /// Screen position of a raw reading, clamped to `width` x `height`.
+    pub fn apply(&self, raw_x: u16, raw_y: u16, width: u16, height: u16) -> (u16, u16) {
+        let (rx, ry) = (raw_x as i64, raw_y as i64);
+        let x = (self.a as i64 * rx + self.b as i64 * ry + self.c as i64) / TOUCH_CALIBRATION_SCALE;
+        let y = (self.d as i64 * rx + self.e as i64 * ry + self.f as i64) / TOUCH_CALIBRATION_SCALE;
+        (
+            x.clamp(0, width.saturating_sub(1) as i64) as u16,
+            y.clamp(0, height.saturating_sub(1) as i64) as u16,
+        )
+    }
+
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0u8; 24];
+        let values = [self.a, self.b, self.c, self.d, self.e, self.f];
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 24]) -> Self {
+        let value = |i: usize| {
+            i32::from_le_bytes([
+                bytes[i * 4],
+                bytes[i * 4 + 1],
+                bytes[i * 4 + 2],
+                bytes[i * 4 + 3],
+            ])
+        };
+        Self {
+            a: value(0),
+            b: value(1),
+            c: value(2),
+            d: value(3),
+            e: value(4),
+            f: value(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xpt2046Config {
+    /// Screen size the calibration maps onto
+    pub width: u16,
+    pub height: u16,
+    /// Minimum pressure for a sample to count as a touch
+    pub pressure_threshold: u16,
+    /// Largest spread between the conversions of one sample, in raw counts
+    pub max_jitter: u16,
+    /// Consecutive touched (or released) samples needed for a down (or up) event
+    pub debounce_samples: u8,
+    /// Movement in pixels below which no move event is reported
+    pub move_threshold: u16,
+    /// Time between samples while touched
+    pub sample_interval: Duration,
+}
+
+impl Xpt2046Config {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            pressure_threshold: 400,
+            max_jitter: 60,
+            debounce_samples: 2,
+            move_threshold: 2,
+            sample_interval: Duration::from_millis(10),
+        }
+    }
+}
+
+pub struct Xpt2046<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    spi: Spi<'d, T, M>,
+    cs: Output<'d>,
+    irq: Input<'d>,
+    config: Xpt2046Config,
+    calibration: TouchCalibration,
+    tracker: TouchTracker,
+}
+
+impl<'d, T, M> Xpt2046<'d, T, M>
+where
+    T: spi::Instance,
+    M: spi::Mode,
+{
+    /// `irq` is the active-low PENIRQ output (pull-up enabled). Starts with a rough
+    /// calibration covering most panels; load or compute a real one for accuracy.
+    pub fn new(spi: Spi<'d, T, M>, cs: Output<'d>, irq: Input<'d>, config: Xpt2046Config) -> Self {
+        Self {
+            spi,
+            cs,
+            irq,
+            calibration: TouchCalibration::from_raw_range(
+                (200, 200),
+                (3900, 3900),
+                config.width,
+                config.height,
+            ),
+            tracker: TouchTracker::new(config.debounce_samples, config.move_threshold),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &Xpt2046Config {
+        &self.config
+    }
+
+    pub fn calibration(&self) -> &TouchCalibration {
+        &self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: TouchCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Load calibration from `store`. Returns `false` (keeping the current calibration)
+    /// if nothing is stored under `key`.
+    pub fn load_calibration(
+        &mut self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<bool, KvStoreError> {
+        match store.get_array::<24>(key)? {
+            Some(bytes) => {
+                self.calibration = TouchCalibration::from_bytes(bytes);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn save_calibration(
+        &self,
+        store: &mut impl KvStore,
+        key: &str,
+    ) -> Result<(), KvStoreError> {
+        store.set(key, &self.calibration.to_bytes())
+    }
+
+    /// PENIRQ is asserted; cheap, but not filtered.
+    pub fn is_touched(&self) -> bool {
+        self.irq.is_low()
+    }
+
+    /// One filtered sample in raw coordinates `(x, y, pressure)`, or `None` if the screen
+    /// isn't pressed firmly or the reading was unstable. Use this for calibration.
+    pub fn read_raw(&mut self) -> Result<Option<(u16, u16, u16)>, Xpt2046Error> {
+        let z1 = self.convert(CMD_Z1)?;
+        let z2 = self.convert(CMD_Z2)?;
+        let pressure = pressure(z1, z2);
+        if pressure < self.config.pressure_threshold {
+            return Ok(None);
+        }
+        let mut xs = [0u16; OVERSAMPLING];
+        let mut ys = [0u16; OVERSAMPLING];
+        // The first conversion after switching the drivers is discarded
+        self.convert(CMD_X)?;
+        for x in xs.iter_mut() {
+            *x = self.convert(CMD_X)?;
+        }
+        self.convert(CMD_Y)?;
+        for y in ys.iter_mut() {
+            *y = self.convert(CMD_Y)?;
+        }
+        let (Some(x), Some(y)) = (
+            stable_median(&mut xs, self.config.max_jitter),
+            stable_median(&mut ys, self.config.max_jitter),
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some((x, y, pressure)))
+    }
+
+    /// One filtered sample in screen coordinates.
+    pub fn read(&mut self) -> Result<Option<TouchPoint>, Xpt2046Error> {
+        Ok(self.read_raw()?.map(|(raw_x, raw_y, pressure)| {
+            let (x, y) =
+                self.calibration
+                    .apply(raw_x, raw_y, self.config.width, self.config.height);
+            TouchPoint { x, y, pressure }
+        }))
+    }
+
+    /// Wait for the next debounced touch event; sleeps on PENIRQ while untouched.
+    pub async fn next_event(&mut self) -> Result<TouchEvent, Xpt2046Error> {
+        loop {
+            if self.tracker.is_idle() && self.irq.is_high() {
+                self.irq.wait_for_low().await;
+            } else {
+                Timer::after(self.config.sample_interval).await;
+            }
+            let sample = self.read()?;
+            if let Some(event) = self.tracker.update(sample) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// 12-bit conversion; PENIRQ stays enabled between conversions.
+    fn convert(&mut self, command: u8) -> Result<u16, Xpt2046Error> {
+        let mut buf = [command, 0, 0];
+        self.cs.set_low();
+        let result = self.spi.blocking_transfer_in_place(&mut buf);
+        self.cs.set_high();
+        result.map_err(|_| Xpt2046Error::Communication)?;
+        Ok((u16::from_be_bytes([buf[1], buf[2]]) >> 3) & XPT2046_MAX_RAW)
+    }
+}
+
+/// Turns filtered samples into down/move/up events
+#[derive(Debug, Clone, Copy)]
+struct TouchTracker {
+    debounce_samples: u8,
+    move_threshold: u16,
+    /// Last reported position while touched
+    touched: Option<TouchPoint>,
+    /// Consecutive samples disagreeing with the current state
+    streak: u8,
+}
+
+impl TouchTracker {
+    fn new(debounce_samples: u8, move_threshold: u16) -> Self {
+        Self {
+            debounce_samples: debounce_samples.max(1),
+            move_threshold,
+            touched: None,
+            streak: 0,
+        }
+    }
+
+    /// Untouched with no press pending
+    fn is_idle(&self) -> bool {
+        self.touched.is_none() && self.streak == 0
+    }
+
+    fn update(&mut self, sample: Option<TouchPoint>) -> Option<TouchEvent> {
+        match (self.touched, sample) {
+            (None, None) => {
+                self.streak = 0;
+                None
+            }
+            (None, Some(point)) => {
+                self.streak += 1;
+                if self.streak < self.debounce_samples {
+                    return None;
+                }
+                self.streak = 0;
+                self.touched = Some(point);
+                Some(TouchEvent::Down(point))
+            }
+            (Some(last), None) => {
+                self.streak += 1;
+                if self.streak < self.debounce_samples {
+                    return None;
+                }
+                self.streak = 0;
+                self.touched = None;
+                Some(TouchEvent::Up(last))
+            }
+            (Some(last), Some(point)) => {
+                self.streak = 0;
+                let moved = last.x.abs_diff(point.x).max(last.y.abs_diff(point.y));
+                if moved < self.move_threshold.max(1) {
+                    return None;
+                }
+                self.touched = Some(point);
+                Some(TouchEvent::Move(point))
+            }
+        }
+    }
+}
+
+/// Touch pressure estimate from the Z1/Z2 plate measurements; 0 when untouched.
+fn pressure(z1: u16, z2: u16) -> u16 {
+    (z1 + XPT2046_MAX_RAW).saturating_sub(z2)
+}
+
+/// Median of `samples`, or `None` if they spread more than `max_jitter`.
+fn stable_median(samples: &mut [u16], max_jitter: u16) -> Option<u16> {
+    samples.sort_unstable();
+    let spread = samples.last()? - samples.first()?;
+    (spread <= max_jitter).then(|| samples[samples.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: u16, y: u16) -> TouchPoint {
+        TouchPoint {
+            x,
+            y,
+            pressure: 1000,
+        }
+    }
+
+    #[test]
+    fn three_point_calibration() {
+        // Panel mounted rotated: raw X runs along screen Y, raw Y against screen X
+        let to_screen = |rx: i32, ry: i32| ((3900 - ry) * 240 / 3700, (rx - 200) * 320 / 3700);
+        let raw = [(570, 570), (3530, 2050), (1310, 3530)];
+        let points = raw.map(|(rx, ry)| {
+            let (sx, sy) = to_screen(rx, ry);
+            ((rx as u16, ry as u16), (sx as u16, sy as u16))
+        });
+        let calibration = TouchCalibration::from_points(points).unwrap();
+        for (rx, ry) in [(2000, 2000), (300, 3800), (3800, 300)] {
+            let (sx, sy) = to_screen(rx, ry);
+            let (x, y) = calibration.apply(rx as u16, ry as u16, 240, 320);
+            assert!((x as i32 - sx).abs() <= 1 && (y as i32 - sy).abs() <= 1);
+        }
+        assert_eq!(
+            TouchCalibration::from_bytes(calibration.to_bytes()),
+            calibration
+        );
+        assert!(TouchCalibration::from_points([((0, 0), (0, 0)); 3]).is_none());
+    }
+
+    #[test]
+    fn raw_range_calibration_clamps() {
+        let calibration = TouchCalibration::from_raw_range((200, 200), (3900, 3900), 240, 320);
+        assert_eq!(calibration.apply(200, 200, 240, 320), (0, 0));
+        assert_eq!(calibration.apply(2050, 2050, 240, 320), (120, 160));
+        assert_eq!(calibration.apply(4095, 4095, 240, 320), (239, 319));
+    }
+
+    #[test]
+    fn sample_filtering() {
+        assert_eq!(
+            stable_median(&mut [1000, 1010, 990, 1005, 995], 60),
+            Some(1000)
+        );
+        assert_eq!(stable_median(&mut [1000, 1010, 990, 1005, 1500], 60), None);
+        assert_eq!(pressure(0, XPT2046_MAX_RAW), 0);
+        assert_eq!(pressure(600, 3000), 1695);
+    }
+
+    #[test]
+    fn events_are_debounced() {
+        let mut tracker = TouchTracker::new(2, 3);
+        assert_eq!(tracker.update(Some(point(10, 10))), None);
+        assert_eq!(tracker.update(None), None);
+        assert!(tracker.is_idle());
+        assert_eq!(tracker.update(Some(point(10, 10))), None);
+        assert_eq!(
+            tracker.update(Some(point(11, 10))),
+            Some(TouchEvent::Down(point(11, 10)))
+        );
+        // Jitter below the move threshold is swallowed
+        assert_eq!(tracker.update(Some(point(13, 11))), None);
+        assert_eq!(
+            tracker.update(Some(point(15, 10))),
+            Some(TouchEvent::Move(point(15, 10)))
+        );
+        // A single dropped sample doesn't end the touch
+        assert_eq!(tracker.update(None), None);
+        assert_eq!(tracker.update(Some(point(15, 10))), None);
+        assert_eq!(tracker.update(None), None);
+        assert_eq!(tracker.update(None), Some(TouchEvent::Up(point(15, 10))));
+        assert!(tracker.is_idle());
+    }
+}
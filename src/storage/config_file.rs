@@ -0,0 +1,387 @@
+//! config_file.rs — `config.txt` loader for an INI / TOML subset
+//!
+//! Lets a device be reconfigured by editing a text file instead of reflashing. The
+//! format is sections and `key = value` lines:
+//!
+//! ```text
+//! # Greenhouse controller
+//! [wifi]
+//! ssid = "Greenhouse"
+//! password = hunter22   ; inline comments need a space before them
+//!
+//! [pump]
+//! enabled = yes
+//! interval_s = 600
+//! ```
+//!
+//! Keys before the first section belong to section `""`. Values may be quoted to keep
+//! `#`, `;` or surrounding spaces. [`load_config`] feeds every entry to a
+//! [`ConfigSettings`] implementation, which parses it into typed fields, and stops at the
+//! first problem with its line number so [`show_config_error`] can put it on screen.
+//! The loader works on the file contents, so it doesn't care whether they came from an
+//! SD card, flash or an HTTP upload.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Default)]
+//! struct Settings {
+//!     ssid: HeaplessString<32>,
+//!     pump_interval_s: u32,
+//! }
+//!
+//! impl ConfigSettings for Settings {
+//!     fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<(), ConfigError> {
+//!         match (section, key) {
+//!             ("wifi", "ssid") => self.ssid = value.as_str().try_into().map_err(|_| ConfigError::InvalidValue)?,
+//!             ("pump", "interval_s") => self.pump_interval_s = value.parse_in_range(10..=86_400)?,
+//!             _ => return Err(ConfigError::UnknownKey),
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut settings = Settings::default();
+//! if let Err(e) = load_config(contents, &mut settings) {
+//!     show_config_error(&mut display, &e)?;
+//! }
+//! ```
+
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+
+use crate::{HeaplessString, TextDisplay};
+
+/// Conventional name of the configuration file
+pub const CONFIG_FILE_NAME: &str = "config.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum ConfigError {
+    #[error("File is not valid UTF-8")]
+    Encoding,
+    #[error("Expected [section] or key = value")]
+    Syntax,
+    #[error("Unknown setting")]
+    UnknownKey,
+    #[error("Invalid value")]
+    InvalidValue,
+    #[error("Value out of range")]
+    OutOfRange,
+    #[error("Required setting missing")]
+    Missing,
+}
+
+/// A [`ConfigError`] and the 1-based line it was found on (`None` for whole-file checks)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+#[error("{error}")]
+pub struct ConfigFileError {
+    pub line: Option<usize>,
+    pub error: ConfigError,
+}
+
+/// Raw text of one value, with typed accessors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigValue<'a>(&'a str);
+
+impl<'a> ConfigValue<'a> {
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// `true`/`yes`/`on`/`1` or `false`/`no`/`off`/`0`, case-insensitive
+    pub fn as_bool(&self) -> Result<bool, ConfigError> {
+        const TRUE: [&str; 4] = ["true", "yes", "on", "1"];
+        const FALSE: [&str; 4] = ["false", "no", "off", "0"];
+        if TRUE.iter().any(|t| self.0.eq_ignore_ascii_case(t)) {
+            Ok(true)
+        } else if FALSE.iter().any(|f| self.0.eq_ignore_ascii_case(f)) {
+            Ok(false)
+        } else {
+            Err(ConfigError::InvalidValue)
+        }
+    }
+
+    pub fn parse<T: FromStr>(&self) -> Result<T, ConfigError> {
+        self.0.parse().map_err(|_| ConfigError::InvalidValue)
+    }
+
+    pub fn parse_in_range<T: FromStr + PartialOrd>(
+        &self,
+        range: RangeInclusive<T>,
+    ) -> Result<T, ConfigError> {
+        let value = self.parse()?;
+        if range.contains(&value) {
+            Ok(value)
+        } else {
+            Err(ConfigError::OutOfRange)
+        }
+    }
+}
+
+/// Typed settings filled from a configuration file
+pub trait ConfigSettings {
+    /// Store one entry. Return [`ConfigError::UnknownKey`] for keys you don't know.
+    fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<(), ConfigError>;
+
+    /// Cross-field checks once the whole file has been read.
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+/// One `key = value` line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigEntry<'a> {
+    pub line: usize,
+    pub section: &'a str,
+    pub key: &'a str,
+    pub value: ConfigValue<'a>,
+}
+
+/// Iterator over the entries of a configuration file, see [`config_entries`]
+pub struct ConfigEntries<'a> {
+    lines: core::iter::Enumerate<core::str::Lines<'a>>,
+    section: &'a str,
+}
+
+impl<'a> Iterator for ConfigEntries<'a> {
+    type Item = Result<ConfigEntry<'a>, ConfigFileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, line) in self.lines.by_ref() {
+            let line_no = index + 1;
+            let syntax = ConfigFileError {
+                line: Some(line_no),
+                error: ConfigError::Syntax,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                match strip_comment(rest).strip_suffix(']') {
+                    Some(name) => self.section = name.trim(),
+                    None => return Some(Err(syntax)),
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Some(Err(syntax));
+            };
+            let key = key.trim();
+            let Some(value) = unquote(strip_comment(value.trim())) else {
+                return Some(Err(syntax));
+            };
+            if key.is_empty() {
+                return Some(Err(syntax));
+            }
+            return Some(Ok(ConfigEntry {
+                line: line_no,
+                section: self.section,
+                key,
+                value: ConfigValue(value),
+            }));
+        }
+        None
+    }
+}
+
+/// Entries of `text` in file order
+pub fn config_entries(text: &str) -> ConfigEntries<'_> {
+    ConfigEntries {
+        lines: text.lines().enumerate(),
+        section: "",
+    }
+}
+
+/// Apply every entry of `contents` to `settings`, then run its validation. Stops at the
+/// first error; entries before it have already been applied.
+pub fn load_config<S: ConfigSettings>(
+    contents: &[u8],
+    settings: &mut S,
+) -> Result<(), ConfigFileError> {
+    let text = core::str::from_utf8(contents).map_err(|_| ConfigFileError {
+        line: None,
+        error: ConfigError::Encoding,
+    })?;
+    for entry in config_entries(text) {
+        let entry = entry?;
+        settings
+            .set(entry.section, entry.key, entry.value)
+            .map_err(|error| ConfigFileError {
+                line: Some(entry.line),
+                error,
+            })?;
+    }
+    settings
+        .validate()
+        .map_err(|error| ConfigFileError { line: None, error })
+}
+
+/// Show `error` on `display`, e.g. "config.txt:12" above "Invalid value".
+pub fn show_config_error<D: TextDisplay>(
+    display: &mut D,
+    error: &ConfigFileError,
+) -> Result<(), D::Error> {
+    let mut text: HeaplessString<64> = HeaplessString::new();
+    let _ = match error.line {
+        Some(line) => write!(text, "{CONFIG_FILE_NAME}:{line}\n{}", error.error),
+        None => write!(text, "{CONFIG_FILE_NAME}\n{}", error.error),
+    };
+    // Drivers reject or wrap lines wider than the display
+    let max_chars = display.max_chars_per_line();
+    let mut content: HeaplessString<64> = HeaplessString::new();
+    for (i, line) in text.as_str().lines().enumerate() {
+        if i > 0 {
+            let _ = content.push('\n');
+        }
+        for c in line.chars().take(max_chars) {
+            let _ = content.push(c);
+        }
+    }
+    display.display_str(content.as_str())
+}
+
+/// Drop an inline comment: `#` or `;` preceded by whitespace and outside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut in_quotes = false;
+    let mut previous_space = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if previous_space && !in_quotes => return text[..i].trim_end(),
+            _ => {}
+        }
+        previous_space = c.is_whitespace();
+    }
+    text
+}
+
+/// Remove surrounding double quotes; `None` if only one side has them.
+fn unquote(value: &str) -> Option<&str> {
+    let opened = value.starts_with('"');
+    let closed = value.len() >= 2 && value.ends_with('"');
+    match (opened, closed) {
+        (true, true) => Some(&value[1..value.len() - 1]),
+        (false, false) if !value.ends_with('"') => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockTextDisplay;
+
+    #[derive(Default)]
+    struct Settings {
+        ssid: HeaplessString<32>,
+        enabled: bool,
+        interval_s: u32,
+    }
+
+    impl ConfigSettings for Settings {
+        fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<(), ConfigError> {
+            match (section, key) {
+                ("wifi", "ssid") => {
+                    self.ssid = value
+                        .as_str()
+                        .try_into()
+                        .map_err(|_| ConfigError::InvalidValue)?
+                }
+                ("pump", "enabled") => self.enabled = value.as_bool()?,
+                ("pump", "interval_s") => self.interval_s = value.parse_in_range(10..=3600)?,
+                _ => return Err(ConfigError::UnknownKey),
+            }
+            Ok(())
+        }
+
+        fn validate(&self) -> Result<(), ConfigError> {
+            if self.ssid.is_empty() {
+                return Err(ConfigError::Missing);
+            }
+            Ok(())
+        }
+    }
+
+    fn load(text: &str) -> Result<Settings, ConfigFileError> {
+        let mut settings = Settings::default();
+        load_config(text.as_bytes(), &mut settings).map(|_| settings)
+    }
+
+    fn load_error(text: &str) -> (Option<usize>, ConfigError) {
+        let e = load(text).err().unwrap();
+        (e.line, e.error)
+    }
+
+    #[test]
+    fn parses_sections_quotes_and_comments() {
+        let settings = load(
+            "# comment\n\n[wifi]\nssid = \"Home # 2\" # trailing\n; ini comment\n\
+             [ pump ]\nenabled = Yes\ninterval_s=600 ; seconds\n",
+        )
+        .unwrap();
+        assert_eq!(settings.ssid.as_str(), "Home # 2");
+        assert!(settings.enabled);
+        assert_eq!(settings.interval_s, 600);
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        assert_eq!(
+            load_error("[wifi]\nssid = x\n[pump]\ninterval_s = 5\n"),
+            (Some(4), ConfigError::OutOfRange)
+        );
+        assert_eq!(
+            load_error("[pump]\nenabled = maybe\n"),
+            (Some(2), ConfigError::InvalidValue)
+        );
+        assert_eq!(load_error("[wifi\n"), (Some(1), ConfigError::Syntax));
+        assert_eq!(
+            load_error("\n\njust text\n"),
+            (Some(3), ConfigError::Syntax)
+        );
+        assert_eq!(
+            load_error("color = red\n"),
+            (Some(1), ConfigError::UnknownKey)
+        );
+        assert_eq!(
+            load_error("ssid = \"open\n"),
+            (Some(1), ConfigError::Syntax)
+        );
+        assert_eq!(
+            load_error("[pump]\nenabled = off\n"),
+            (None, ConfigError::Missing)
+        );
+        assert_eq!(
+            load_config(&[0xff], &mut Settings::default())
+                .err()
+                .unwrap()
+                .error,
+            ConfigError::Encoding
+        );
+    }
+
+    #[test]
+    fn error_fits_the_display() {
+        let mut screen = MockTextDisplay::new(2, 16);
+        let error = ConfigFileError {
+            line: Some(12),
+            error: ConfigError::Syntax,
+        };
+        show_config_error(&mut screen, &error).unwrap();
+        assert_eq!(screen.content(), "config.txt:12\nExpected [sectio");
+    }
+
+    #[test]
+    fn entries_without_section() {
+        let mut entries = config_entries("a = 1\n[s]\nb = \"\"\n").map(|e| {
+            let e = e.unwrap();
+            (e.line, e.section, e.key, e.value.as_str())
+        });
+        assert_eq!(entries.next(), Some((1, "", "a", "1")));
+        assert_eq!(entries.next(), Some((3, "s", "b", "")));
+        assert_eq!(entries.next(), None);
+    }
+}
@@ -0,0 +1,315 @@
+//! credential_store.rs — encrypted credentials namespace on top of a [`KvStore`]
+//!
+//! Values live under `cred/<name>`, encrypted with AES-256-CTR and authenticated with
+//! HMAC-SHA256 under keys derived from a device key (see [`CredentialStore::for_device`]),
+//! so a flash dump shows neither WiFi passwords nor API tokens, and a store copied to another
+//! board doesn't decrypt.
+//!
+//! The nonce is the first half of the MAC over name and plaintext (a synthetic IV), so no
+//! random number source is needed; the only leak is whether two writes had the same value.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let id = DeviceId::read(&mut flash)?;
+//! let mut creds = CredentialStore::for_device(&mut store, &id, device_secret!());
+//! creds.set("wifi", b"hunter22")?;
+//! creds.protect();
+//!
+//! let mut buf = [0u8; CREDENTIAL_MAX_LEN];
+//! if let Some(len) = creds.get("wifi", &mut buf)? {
+//!     wifi.join(ssid, &buf[..len]).await?;
+//! }
+//!
+//! // Factory reset
+//! creds.wipe()?;
+//! ```
+
+use crate::{
+    AES_BLOCK_LEN, DeviceId, HeaplessString, HmacSha256, KV_MAX_KEY_LEN, KV_MAX_VALUE_LEN, KvStore,
+    KvStoreError, aes256_ctr, hmac_sha256,
+};
+
+/// Key prefix of the credentials namespace
+pub const CREDENTIAL_PREFIX: &str = "cred/";
+/// Longest credential name in bytes
+pub const CREDENTIAL_MAX_NAME_LEN: usize = KV_MAX_KEY_LEN - CREDENTIAL_PREFIX.len();
+/// Longest credential value in bytes
+pub const CREDENTIAL_MAX_LEN: usize = KV_MAX_VALUE_LEN - AES_BLOCK_LEN;
+
+#[derive(Debug, defmt::Format, thiserror::Error)]
+pub enum CredentialError {
+    #[error("Storage error: {0}")]
+    Store(#[from] KvStoreError),
+    #[error("Credential name must be 1 to 27 bytes long")]
+    InvalidName,
+    #[error("Credential must be at most 240 bytes long")]
+    TooLong,
+    #[error("Buffer too small for credential of {0} bytes")]
+    BufferTooSmall(usize),
+    #[error("Credential is corrupt or was written with another key")]
+    Corrupt,
+    #[error("Credentials are write protected")]
+    WriteProtected,
+}
+
+pub struct CredentialStore<'s, S: KvStore> {
+    store: &'s mut S,
+    enc_key: [u8; 32],
+    mac_key: [u8; 32],
+    protected: bool,
+}
+
+impl<'s, S: KvStore> CredentialStore<'s, S> {
+    /// Credentials encrypted under `master_key`, writable until [`CredentialStore::protect`].
+    pub fn new(store: &'s mut S, master_key: &[u8; 32]) -> Self {
+        Self {
+            store,
+            enc_key: hmac_sha256(master_key, b"credentials/enc"),
+            mac_key: hmac_sha256(master_key, b"credentials/mac"),
+            protected: false,
+        }
+    }
+
+    /// Credentials keyed to this board: the master key is derived from `id` and `secret`.
+    pub fn for_device(store: &'s mut S, id: &DeviceId, secret: &[u8]) -> Self {
+        Self::new(store, &id.derive_key(secret, "credentials"))
+    }
+
+    /// Refuse [`CredentialStore::set`] and [`CredentialStore::remove`] until
+    /// [`CredentialStore::unprotect`], guarding provisioned secrets against stray writes.
+    pub fn protect(&mut self) {
+        self.protected = true;
+    }
+
+    pub fn unprotect(&mut self) {
+        self.protected = false;
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+
+    /// Decrypt credential `name` into `buf` and return its length, or `None` if absent.
+    pub fn get(&mut self, name: &str, buf: &mut [u8]) -> Result<Option<usize>, CredentialError> {
+        let key = storage_key(name)?;
+        let mut stored = [0u8; KV_MAX_VALUE_LEN];
+        let Some(len) = self.store.get(key.as_str(), &mut stored)? else {
+            return Ok(None);
+        };
+        if len < AES_BLOCK_LEN {
+            return Err(CredentialError::Corrupt);
+        }
+
+        let (nonce, ciphertext) = stored[..len].split_at_mut(AES_BLOCK_LEN);
+        let value_len = ciphertext.len();
+        if buf.len() < value_len {
+            return Err(CredentialError::BufferTooSmall(value_len));
+        }
+        let mut iv = [0u8; AES_BLOCK_LEN];
+        iv.copy_from_slice(nonce);
+        aes256_ctr(&self.enc_key, &iv, ciphertext);
+
+        if !self.tag(name, ciphertext).verify(&iv) {
+            ciphertext.fill(0);
+            return Err(CredentialError::Corrupt);
+        }
+        buf[..value_len].copy_from_slice(ciphertext);
+        ciphertext.fill(0);
+        Ok(Some(value_len))
+    }
+
+    pub fn set(&mut self, name: &str, value: &[u8]) -> Result<(), CredentialError> {
+        self.check_writable()?;
+        let key = storage_key(name)?;
+        if value.len() > CREDENTIAL_MAX_LEN {
+            return Err(CredentialError::TooLong);
+        }
+
+        let mut nonce = [0u8; AES_BLOCK_LEN];
+        nonce.copy_from_slice(&self.tag(name, value).finish()[..AES_BLOCK_LEN]);
+        let mut stored = [0u8; KV_MAX_VALUE_LEN];
+        let len = AES_BLOCK_LEN + value.len();
+        stored[..AES_BLOCK_LEN].copy_from_slice(&nonce);
+        stored[AES_BLOCK_LEN..len].copy_from_slice(value);
+        aes256_ctr(&self.enc_key, &nonce, &mut stored[AES_BLOCK_LEN..len]);
+
+        self.store.set(key.as_str(), &stored[..len])?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), CredentialError> {
+        self.check_writable()?;
+        let key = storage_key(name)?;
+        self.store.remove(key.as_str())?;
+        Ok(())
+    }
+
+    /// Remove every credential and erase the stale copies from flash. Works even when
+    /// write protected, since it is always a deliberate action (e.g. factory reset).
+    pub fn wipe(&mut self) -> Result<usize, CredentialError> {
+        let removed = self.store.remove_prefix(CREDENTIAL_PREFIX)?;
+        self.store.purge()?;
+        Ok(removed)
+    }
+
+    fn check_writable(&self) -> Result<(), CredentialError> {
+        if self.protected {
+            return Err(CredentialError::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// MAC over name and plaintext; its first block is the nonce.
+    fn tag(&self, name: &str, value: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new(&self.mac_key);
+        mac.update(name.as_bytes());
+        mac.update(&[0]);
+        mac.update(value);
+        mac
+    }
+}
+
+fn storage_key(name: &str) -> Result<HeaplessString<KV_MAX_KEY_LEN>, CredentialError> {
+    if name.is_empty() || name.len() > CREDENTIAL_MAX_NAME_LEN {
+        return Err(CredentialError::InvalidName);
+    }
+    let mut key = HeaplessString::new();
+    let _ = key.push_str(CREDENTIAL_PREFIX);
+    let _ = key.push_str(name);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unordered in-memory store, enough to exercise the namespace
+    struct MemStore {
+        entries: [(
+            HeaplessString<KV_MAX_KEY_LEN>,
+            [u8; KV_MAX_VALUE_LEN],
+            usize,
+        ); 4],
+    }
+
+    impl MemStore {
+        fn new() -> Self {
+            Self {
+                entries: core::array::from_fn(|_| {
+                    (HeaplessString::new(), [0; KV_MAX_VALUE_LEN], 0)
+                }),
+            }
+        }
+
+        fn slot(&self, key: &str) -> Option<usize> {
+            self.entries.iter().position(|(k, _, _)| k.as_str() == key)
+        }
+    }
+
+    impl KvStore for MemStore {
+        fn get(&mut self, key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvStoreError> {
+            Ok(self.slot(key).map(|i| {
+                let (_, value, len) = &self.entries[i];
+                buf[..*len].copy_from_slice(&value[..*len]);
+                *len
+            }))
+        }
+
+        fn set(&mut self, key: &str, value: &[u8]) -> Result<(), KvStoreError> {
+            let i = self
+                .slot(key)
+                .or_else(|| self.slot(""))
+                .ok_or(KvStoreError::Full)?;
+            let entry = &mut self.entries[i];
+            entry.0 = HeaplessString::try_from(key).map_err(|_| KvStoreError::InvalidKey)?;
+            entry.1[..value.len()].copy_from_slice(value);
+            entry.2 = value.len();
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), KvStoreError> {
+            if let Some(i) = self.slot(key) {
+                self.entries[i].0.clear();
+            }
+            Ok(())
+        }
+
+        fn remove_prefix(&mut self, prefix: &str) -> Result<usize, KvStoreError> {
+            let mut removed = 0;
+            for (key, _, _) in self.entries.iter_mut() {
+                if !key.is_empty() && key.as_str().starts_with(prefix) {
+                    key.clear();
+                    removed += 1;
+                }
+            }
+            Ok(removed)
+        }
+    }
+
+    #[test]
+    fn round_trip_is_encrypted() {
+        let mut store = MemStore::new();
+        let mut creds = CredentialStore::new(&mut store, &[7; 32]);
+        creds.set("wifi", b"hunter22").unwrap();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(creds.get("wifi", &mut buf).unwrap(), Some(8));
+        assert_eq!(&buf[..8], b"hunter22");
+        assert_eq!(creds.get("mqtt", &mut buf).unwrap(), None);
+
+        let mut raw = [0u8; KV_MAX_VALUE_LEN];
+        let len = store.get("cred/wifi", &mut raw).unwrap().unwrap();
+        assert_eq!(len, AES_BLOCK_LEN + 8);
+        assert!(!raw[..len].windows(8).any(|w| w == b"hunter22"));
+    }
+
+    #[test]
+    fn wrong_key_or_tampering_is_detected() {
+        let mut store = MemStore::new();
+        CredentialStore::new(&mut store, &[7; 32])
+            .set("token", b"abc123")
+            .unwrap();
+
+        let mut buf = [0u8; 16];
+        let mut other = CredentialStore::new(&mut store, &[8; 32]);
+        assert!(matches!(
+            other.get("token", &mut buf),
+            Err(CredentialError::Corrupt)
+        ));
+
+        let mut raw = [0u8; KV_MAX_VALUE_LEN];
+        let len = store.get("cred/token", &mut raw).unwrap().unwrap();
+        raw[len - 1] ^= 1;
+        store.set("cred/token", &raw[..len]).unwrap();
+        let mut creds = CredentialStore::new(&mut store, &[7; 32]);
+        assert!(matches!(
+            creds.get("token", &mut buf),
+            Err(CredentialError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn protection_and_wipe() {
+        let mut store = MemStore::new();
+        store.set("other", b"x").unwrap();
+        let mut creds = CredentialStore::new(&mut store, &[7; 32]);
+        creds.set("a", b"1").unwrap();
+        creds.set("b", b"2").unwrap();
+
+        creds.protect();
+        assert!(matches!(
+            creds.set("a", b"3"),
+            Err(CredentialError::WriteProtected)
+        ));
+        assert!(matches!(
+            creds.remove("a"),
+            Err(CredentialError::WriteProtected)
+        ));
+
+        assert_eq!(creds.wipe().unwrap(), 2);
+        let mut buf = [0u8; 4];
+        assert_eq!(creds.get("a", &mut buf).unwrap(), None);
+        assert_eq!(store.get("other", &mut buf).unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,454 @@
+//! data_logger.rs — periodic CSV logging of metrics gauges with file rotation
+//!
+//! [`DataLogger`] samples every gauge of a [`MetricsRegistry`] on a fixed interval and
+//! appends one CSV row per sample (`2026-10-16 14:05:00,23,1017`). Rows are batched in RAM
+//! and written every [`DataLoggerConfig::flush_rows`] rows to spare the card or flash.
+//! Files rotate daily and when they reach [`DataLoggerConfig::max_file_len`]; names are
+//! 8.3-safe (`YYMMDDNN.CSV`, `NN` = part of the day) and each file starts with a header
+//! row. After a reboot logging resumes in the newest file of the day, or the part after it
+//! if that one is full.
+//!
+//! Files go through [`LogStorage`], implemented by whatever holds them (an SD card, a
+//! flash region). [`serve_log_file`](crate::serve_log_file) lets a browser or script
+//! download them over HTTP.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static TEMPERATURE: Gauge = Gauge::new("temp", "dC");
+//! static PRESSURE: Gauge = Gauge::new("pressure", "hPa");
+//!
+//! let mut metrics = MetricsRegistry::<4>::new();
+//! metrics.register(&TEMPERATURE)?;
+//! metrics.register(&PRESSURE)?;
+//!
+//! let mut logger = DataLogger::new(&metrics, DataLoggerConfig::default());
+//! logger.run(&mut sd_logs, || rtc.now().ok()).await;
+//! ```
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Ticker};
+
+use crate::{DateTime, HeaplessString, HeaplessVec, LogModule, MetricsRegistry};
+
+/// Bytes of rows buffered between writes
+pub const DATA_LOG_BATCH_LEN: usize = 512;
+/// Longest CSV row, including the timestamp and the header row
+pub const DATA_LOG_MAX_ROW_LEN: usize = 192;
+/// Parts per day before logging gives up (`NN` in the file name)
+const MAX_PARTS_PER_DAY: u8 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum DataLogError {
+    #[error("Log storage read/write failed")]
+    Storage,
+    #[error("Log storage is full")]
+    NoSpace,
+    #[error("Row longer than DATA_LOG_MAX_ROW_LEN")]
+    RowTooLong,
+}
+
+/// Append-only file storage for logs
+pub trait LogStorage {
+    /// Append `data` to file `name`, creating it if needed.
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<(), DataLogError>;
+
+    /// Size of file `name` in bytes, 0 if it doesn't exist.
+    fn file_len(&mut self, name: &str) -> Result<usize, DataLogError>;
+
+    /// Copy bytes of `name` starting at `offset` into `buf`; returns how many were read
+    /// (0 at the end of the file or if it doesn't exist).
+    fn read(&mut self, name: &str, offset: usize, buf: &mut [u8]) -> Result<usize, DataLogError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct DataLoggerConfig {
+    /// Time between rows
+    pub interval: Duration,
+    /// Start a new part once a file would grow beyond this
+    pub max_file_len: usize,
+    /// Rows kept in RAM before writing them out
+    pub flush_rows: usize,
+}
+
+impl Default for DataLoggerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            max_file_len: 1024 * 1024,
+            flush_rows: 10,
+        }
+    }
+}
+
+/// The file currently written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct LogFile {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub part: u8,
+    /// Bytes already in storage
+    pub len: usize,
+}
+
+impl LogFile {
+    /// `YYMMDDNN.CSV`
+    pub fn name(&self) -> HeaplessString<12> {
+        let mut name = HeaplessString::new();
+        let _ = write!(
+            name,
+            "{:02}{:02}{:02}{:02}.CSV",
+            self.year % 100,
+            self.month,
+            self.day,
+            self.part
+        );
+        name
+    }
+
+    fn is_day_of(&self, time: &DateTime) -> bool {
+        (self.year, self.month, self.day) == (time.year, time.month, time.day)
+    }
+}
+
+pub struct DataLogger<'r, const N: usize> {
+    metrics: &'r MetricsRegistry<N>,
+    config: DataLoggerConfig,
+    file: Option<LogFile>,
+    batch: HeaplessVec<u8, DATA_LOG_BATCH_LEN>,
+    batch_rows: usize,
+}
+
+impl<'r, const N: usize> DataLogger<'r, N> {
+    pub fn new(metrics: &'r MetricsRegistry<N>, config: DataLoggerConfig) -> Self {
+        Self {
+            metrics,
+            config,
+            file: None,
+            batch: HeaplessVec::const_new(),
+            batch_rows: 0,
+        }
+    }
+
+    /// The file rows currently go to; `None` before the first row.
+    pub fn current_file(&self) -> Option<&LogFile> {
+        self.file.as_ref()
+    }
+
+    /// Rows sampled but not written yet
+    pub fn pending_rows(&self) -> usize {
+        self.batch_rows
+    }
+
+    /// Sample all gauges into one row stamped `now`, rotating and flushing as needed.
+    pub fn log(
+        &mut self,
+        storage: &mut impl LogStorage,
+        now: DateTime,
+    ) -> Result<(), DataLogError> {
+        let mut row: HeaplessString<DATA_LOG_MAX_ROW_LEN> = HeaplessString::new();
+        write!(
+            row,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            now.year, now.month, now.day, now.hour, now.minute, now.second
+        )
+        .map_err(|_| DataLogError::RowTooLong)?;
+        for gauge in self.metrics.iter() {
+            write!(row, ",{}", gauge.get()).map_err(|_| DataLogError::RowTooLong)?;
+        }
+        row.push('\n').map_err(|_| DataLogError::RowTooLong)?;
+
+        let rotate = match &self.file {
+            Some(file) => {
+                !file.is_day_of(&now)
+                    || file.len + self.batch.len() + row.len() > self.config.max_file_len
+            }
+            None => true,
+        };
+        if rotate {
+            self.flush(storage)?;
+            self.open(storage, &now, row.len())?;
+        }
+        if self.batch.len() + row.len() > DATA_LOG_BATCH_LEN {
+            self.flush(storage)?;
+        }
+        self.batch
+            .extend_from_slice(row.as_str().as_bytes())
+            .map_err(|_| DataLogError::RowTooLong)?;
+        self.batch_rows += 1;
+        if self.batch_rows >= self.config.flush_rows {
+            self.flush(storage)?;
+        }
+        Ok(())
+    }
+
+    /// Write buffered rows out, e.g. before power-down. On failure they stay buffered.
+    pub fn flush(&mut self, storage: &mut impl LogStorage) -> Result<(), DataLogError> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        storage.append(file.name().as_str(), self.batch.as_slice())?;
+        file.len += self.batch.len();
+        self.batch.clear();
+        self.batch_rows = 0;
+        Ok(())
+    }
+
+    /// Log a row every [`DataLoggerConfig::interval`]. `now` returns the wall-clock time,
+    /// or `None` while it isn't known yet (those samples are skipped).
+    pub async fn run(
+        &mut self,
+        storage: &mut impl LogStorage,
+        mut now: impl FnMut() -> Option<DateTime>,
+    ) -> ! {
+        let mut ticker = Ticker::every(self.config.interval);
+        loop {
+            ticker.next().await;
+            let Some(time) = now() else {
+                continue;
+            };
+            if let Err(e) = self.log(storage, time) {
+                crate::log!(LogModule::Storage, Warn, "Data logger: {}", e);
+            }
+        }
+    }
+
+    /// Pick the file for `now`: the newest part of the day if it has room for `row_len`
+    /// more bytes, else the part after it. A new file gets the header row.
+    fn open(
+        &mut self,
+        storage: &mut impl LogStorage,
+        now: &DateTime,
+        row_len: usize,
+    ) -> Result<(), DataLogError> {
+        let file_at = |part| LogFile {
+            year: now.year,
+            month: now.month,
+            day: now.day,
+            part,
+            len: 0,
+        };
+        let first_part = match &self.file {
+            Some(file) if file.is_day_of(now) => file.part + 1,
+            _ => 0,
+        };
+        self.file = None;
+        if first_part >= MAX_PARTS_PER_DAY {
+            return Err(DataLogError::NoSpace);
+        }
+
+        // The newest part is the last one before the first missing part
+        let mut file = file_at(first_part);
+        file.len = storage.file_len(file.name().as_str())?;
+        while file.len > 0 && file.part + 1 < MAX_PARTS_PER_DAY {
+            let mut next = file_at(file.part + 1);
+            next.len = storage.file_len(next.name().as_str())?;
+            if next.len == 0 {
+                break;
+            }
+            file = next;
+        }
+        if file.len > 0 && file.len + row_len > self.config.max_file_len {
+            if file.part + 1 >= MAX_PARTS_PER_DAY {
+                return Err(DataLogError::NoSpace);
+            }
+            file = file_at(file.part + 1);
+        }
+
+        if file.len == 0 {
+            self.batch
+                .extend_from_slice(self.header()?.as_str().as_bytes())
+                .map_err(|_| DataLogError::RowTooLong)?;
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// `time,temp [dC],pressure [hPa]`
+    fn header(&self) -> Result<HeaplessString<DATA_LOG_MAX_ROW_LEN>, DataLogError> {
+        let mut header = HeaplessString::new();
+        let _ = header.push_str("time");
+        for gauge in self.metrics.iter() {
+            write!(header, ",{} [{}]", gauge.name(), gauge.unit())
+                .map_err(|_| DataLogError::RowTooLong)?;
+        }
+        header.push('\n').map_err(|_| DataLogError::RowTooLong)?;
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Gauge;
+
+    static TEMPERATURE: Gauge = Gauge::new("temp", "dC");
+    static PRESSURE: Gauge = Gauge::new("pressure", "hPa");
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: [(HeaplessString<12>, HeaplessVec<u8, 256>); 4],
+        appends: usize,
+    }
+
+    impl MemoryStorage {
+        fn file(&self, name: &str) -> Option<&HeaplessVec<u8, 256>> {
+            self.files
+                .iter()
+                .find(|(n, _)| n.as_str() == name)
+                .map(|(_, data)| data)
+        }
+
+        fn text(&self, name: &str) -> &str {
+            core::str::from_utf8(self.file(name).unwrap().as_slice()).unwrap()
+        }
+
+        fn file_count(&self) -> usize {
+            self.files.iter().filter(|(n, _)| !n.is_empty()).count()
+        }
+    }
+
+    impl LogStorage for MemoryStorage {
+        fn append(&mut self, name: &str, data: &[u8]) -> Result<(), DataLogError> {
+            self.appends += 1;
+            let index = match self.files.iter().position(|(n, _)| n.as_str() == name) {
+                Some(index) => index,
+                None => {
+                    let index = self.file_count();
+                    self.files[index].0 = name.try_into().unwrap();
+                    index
+                }
+            };
+            self.files[index]
+                .1
+                .extend_from_slice(data)
+                .map_err(|_| DataLogError::NoSpace)
+        }
+
+        fn file_len(&mut self, name: &str) -> Result<usize, DataLogError> {
+            Ok(self.file(name).map_or(0, |f| f.len()))
+        }
+
+        fn read(
+            &mut self,
+            name: &str,
+            offset: usize,
+            buf: &mut [u8],
+        ) -> Result<usize, DataLogError> {
+            let file = self.file(name).map_or(&[][..], |f| f.as_slice());
+            let data = file.get(offset..).unwrap_or(&[]);
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+    }
+
+    fn at(day: u8, hour: u8) -> DateTime {
+        DateTime {
+            year: 2026,
+            month: 10,
+            day,
+            hour,
+            minute: 0,
+            second: 0,
+        }
+    }
+
+    fn registry() -> MetricsRegistry<2> {
+        let mut metrics = MetricsRegistry::new();
+        metrics.register(&TEMPERATURE).unwrap();
+        metrics.register(&PRESSURE).unwrap();
+        metrics
+    }
+
+    #[test]
+    fn batches_rows_and_rotates_daily() {
+        let metrics = registry();
+        let config = DataLoggerConfig {
+            flush_rows: 2,
+            ..Default::default()
+        };
+        let mut logger = DataLogger::new(&metrics, config);
+        let mut storage = MemoryStorage::default();
+        TEMPERATURE.set(231);
+        PRESSURE.set(1017);
+
+        logger.log(&mut storage, at(16, 10)).unwrap();
+        assert_eq!(storage.appends, 0);
+        assert_eq!(logger.pending_rows(), 1);
+        logger.log(&mut storage, at(16, 11)).unwrap();
+        assert_eq!(storage.appends, 1);
+        logger.log(&mut storage, at(17, 0)).unwrap();
+        logger.flush(&mut storage).unwrap();
+
+        assert_eq!(
+            storage.text("26101600.CSV"),
+            "time,temp [dC],pressure [hPa]\n\
+             2026-10-16 10:00:00,231,1017\n\
+             2026-10-16 11:00:00,231,1017\n"
+        );
+        assert!(
+            storage
+                .text("26101700.CSV")
+                .ends_with("2026-10-17 00:00:00,231,1017\n")
+        );
+        assert_eq!(logger.current_file().map(|f| f.part), Some(0));
+    }
+
+    #[test]
+    fn rotates_by_size_and_resumes_after_restart() {
+        let metrics = registry();
+        let config = DataLoggerConfig {
+            max_file_len: 80,
+            flush_rows: 1,
+            ..Default::default()
+        };
+        let mut storage = MemoryStorage::default();
+        let mut logger = DataLogger::new(&metrics, config.clone());
+        // Header (30) + row (29) fit, a second row doesn't
+        logger.log(&mut storage, at(16, 1)).unwrap();
+        logger.log(&mut storage, at(16, 2)).unwrap();
+        assert_eq!(storage.file_count(), 2);
+        assert_eq!(
+            logger.current_file().unwrap().name().as_str(),
+            "26101601.CSV"
+        );
+
+        let mut logger = DataLogger::new(&metrics, config);
+        logger.log(&mut storage, at(16, 3)).unwrap();
+        assert_eq!(storage.file_count(), 3);
+        assert_eq!(logger.current_file().unwrap().part, 2);
+        assert!(!storage.text("26101602.CSV").contains("01:00"));
+    }
+
+    #[test]
+    fn resumes_newest_part_not_first_with_room() {
+        let metrics = registry();
+        let config = DataLoggerConfig {
+            max_file_len: 80,
+            flush_rows: 1,
+            ..Default::default()
+        };
+        let mut storage = MemoryStorage::default();
+        TEMPERATURE.set(231);
+        PRESSURE.set(1017);
+        let header = b"time,temp [dC],pressure [hPa]\n";
+        storage.append("26101600.CSV", header).unwrap();
+        storage.append("26101601.CSV", header).unwrap();
+
+        let mut logger = DataLogger::new(&metrics, config);
+        logger.log(&mut storage, at(16, 4)).unwrap();
+        assert_eq!(logger.current_file().unwrap().part, 1);
+        assert_eq!(storage.text("26101600.CSV").len(), header.len());
+        assert!(
+            storage
+                .text("26101601.CSV")
+                .ends_with("04:00:00,231,1017\n")
+        );
+    }
+}
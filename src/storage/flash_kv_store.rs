@@ -0,0 +1,634 @@
+//! flash_kv_store.rs — small log-structured key/value store in on-board flash
+//!
+//! Uses two equally sized flash pages. Records are appended to the active page; when it
+//! fills up the latest value of every key is copied to the other page, which then becomes
+//! active. The page header is written last, so a power loss during compaction leaves the
+//! old page in charge. Records carry a checksum and a torn write ends the log.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // Last 8 KiB of the Pico's 2 MiB flash
+//! const FLASH_SIZE: usize = 2 * 1024 * 1024;
+//! let flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(p.FLASH);
+//! let mut store = FlashKvStore::new(flash, (FLASH_SIZE - 8192) as u32, 4096)?;
+//!
+//! store.set("wifi/ssid", b"MyNetwork")?;
+//! let mut buf = [0u8; 32];
+//! if let Some(len) = store.get("wifi/ssid", &mut buf)? {
+//!     info!("ssid: {}", core::str::from_utf8(&buf[..len]).unwrap_or(""));
+//! }
+//! ```
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::Crc16;
+
+/// Longest key in bytes
+pub const KV_MAX_KEY_LEN: usize = 32;
+/// Longest value in bytes
+pub const KV_MAX_VALUE_LEN: usize = 256;
+
+const PAGE_MAGIC: u32 = 0x3253_564B; // "KVS2"
+const PAGE_HEADER_LEN: u32 = 8;
+// key_len: u8, kind: u8, value_len: u16, checksum: u16
+const RECORD_HEADER_LEN: usize = 6;
+const RECORD_ALIGN: u32 = 4;
+const MAX_RECORD_LEN: usize = RECORD_HEADER_LEN + KV_MAX_KEY_LEN + KV_MAX_VALUE_LEN + 3;
+
+const KIND_VALUE: u8 = 0x01;
+const KIND_TOMBSTONE: u8 = 0x02;
+const ERASED: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format, thiserror::Error)]
+pub enum KvStoreError {
+    #[error("Flash operation failed")]
+    Flash,
+    #[error("Flash region is not aligned to erase sectors")]
+    InvalidRegion,
+    #[error("Key must be 1 to 32 bytes long")]
+    InvalidKey,
+    #[error("Value must be at most 256 bytes long")]
+    ValueTooLong,
+    #[error("Buffer too small for stored value of {0} bytes")]
+    BufferTooSmall(usize),
+    #[error("Key/value store is full")]
+    Full,
+}
+
+/// Persistent key/value storage
+///
+/// Implemented by [`FlashKvStore`]; drivers that persist calibration or settings take
+/// `&mut impl KvStore` so any backend can be used.
+pub trait KvStore {
+    /// Copy the value of `key` into `buf` and return its length, or `None` if absent.
+    fn get(&mut self, key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvStoreError>;
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), KvStoreError>;
+
+    fn remove(&mut self, key: &str) -> Result<(), KvStoreError>;
+
+    /// Remove every key starting with `prefix` and return how many were removed.
+    fn remove_prefix(&mut self, prefix: &str) -> Result<usize, KvStoreError>;
+
+    /// Physically erase removed and overwritten values. Backends that don't keep stale
+    /// copies have nothing to do.
+    fn purge(&mut self) -> Result<(), KvStoreError> {
+        Ok(())
+    }
+
+    /// Read a value that must be exactly `N` bytes long. Values of any other size are
+    /// treated as absent.
+    fn get_array<const N: usize>(&mut self, key: &str) -> Result<Option<[u8; N]>, KvStoreError> {
+        let mut buf = [0u8; KV_MAX_VALUE_LEN];
+        match self.get(key, &mut buf)? {
+            Some(len) if len == N => {
+                let mut value = [0u8; N];
+                value.copy_from_slice(&buf[..N]);
+                Ok(Some(value))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RecordInfo {
+    key_len: usize,
+    kind: u8,
+    value_len: usize,
+    /// Total size including header and padding
+    len: u32,
+}
+
+/// Key/value store on any [`NorFlash`] (e.g. `embassy_rp::flash::Flash`)
+pub struct FlashKvStore<F: NorFlash> {
+    flash: F,
+    start: u32,
+    page_size: u32,
+    active: u32,
+    seq: u32,
+    free: u32,
+    buf: [u8; MAX_RECORD_LEN],
+}
+
+impl<F: NorFlash> FlashKvStore<F> {
+    /// Open (or format) a store using two pages of `page_size` bytes starting at `start`.
+    ///
+    /// Both values must be multiples of the flash erase size.
+    pub fn new(flash: F, start: u32, page_size: u32) -> Result<Self, KvStoreError> {
+        let erase = F::ERASE_SIZE as u32;
+        if !start.is_multiple_of(erase)
+            || !page_size.is_multiple_of(erase)
+            || page_size < PAGE_HEADER_LEN + MAX_RECORD_LEN as u32
+            || !RECORD_ALIGN.is_multiple_of(F::WRITE_SIZE as u32)
+            || start as usize + 2 * page_size as usize > flash.capacity()
+        {
+            return Err(KvStoreError::InvalidRegion);
+        }
+
+        let mut store = Self {
+            flash,
+            start,
+            page_size,
+            active: 0,
+            seq: 0,
+            free: PAGE_HEADER_LEN,
+            buf: [0; MAX_RECORD_LEN],
+        };
+
+        let seq0 = store.read_page_seq(0)?;
+        let seq1 = store.read_page_seq(1)?;
+        match (seq0, seq1) {
+            (Some(a), Some(b)) if (b.wrapping_sub(a) as i32) > 0 => store.select_page(1, b)?,
+            (Some(a), _) => store.select_page(0, a)?,
+            (None, Some(b)) => store.select_page(1, b)?,
+            (None, None) => store.format()?,
+        }
+        Ok(store)
+    }
+
+    /// Erase everything.
+    pub fn format(&mut self) -> Result<(), KvStoreError> {
+        self.erase_page(1)?;
+        self.erase_page(0)?;
+        self.write_page_header(0, 1)?;
+        self.active = 0;
+        self.seq = 1;
+        self.free = PAGE_HEADER_LEN;
+        Ok(())
+    }
+
+    /// Bytes left in the active page before the next compaction
+    pub fn free_bytes(&self) -> u32 {
+        self.page_size - self.free
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    fn select_page(&mut self, page: u32, seq: u32) -> Result<(), KvStoreError> {
+        self.active = page;
+        self.seq = seq;
+        self.free = self.scan_end(page)?;
+        Ok(())
+    }
+
+    /// Offset just past the last valid record of `page`
+    fn scan_end(&mut self, page: u32) -> Result<u32, KvStoreError> {
+        let mut offset = PAGE_HEADER_LEN;
+        loop {
+            match self.read_record(page, offset)? {
+                Some(Ok(info)) => offset += info.len,
+                Some(Err(())) => {
+                    // Torn write: nothing can be appended after it, compact on next write.
+                    return Ok(self.page_size);
+                }
+                None => return Ok(offset),
+            }
+        }
+    }
+
+    /// Read and verify the record at `offset` into `self.buf`.
+    ///
+    /// `None` marks the end of the log, `Some(Err(()))` a corrupt record.
+    fn read_record(
+        &mut self,
+        page: u32,
+        offset: u32,
+    ) -> Result<Option<Result<RecordInfo, ()>>, KvStoreError> {
+        if offset + RECORD_HEADER_LEN as u32 > self.page_size {
+            return Ok(None);
+        }
+        let base = self.page_addr(page) + offset;
+        self.flash
+            .read(base, &mut self.buf[..RECORD_HEADER_LEN])
+            .map_err(|_| KvStoreError::Flash)?;
+
+        let key_len = self.buf[0] as usize;
+        let kind = self.buf[1];
+        let value_len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+        let checksum = u16::from_le_bytes([self.buf[4], self.buf[5]]);
+        if key_len == ERASED as usize && kind == ERASED {
+            return Ok(None);
+        }
+
+        let len = record_len(key_len, value_len);
+        if key_len == 0
+            || key_len > KV_MAX_KEY_LEN
+            || value_len > KV_MAX_VALUE_LEN
+            || (kind != KIND_VALUE && kind != KIND_TOMBSTONE)
+            || offset + len > self.page_size
+        {
+            return Ok(Some(Err(())));
+        }
+
+        let body = RECORD_HEADER_LEN + key_len + value_len;
+        self.flash
+            .read(
+                base + RECORD_HEADER_LEN as u32,
+                &mut self.buf[RECORD_HEADER_LEN..body],
+            )
+            .map_err(|_| KvStoreError::Flash)?;
+        if record_checksum(&self.buf[..body]) != checksum {
+            return Ok(Some(Err(())));
+        }
+
+        Ok(Some(Ok(RecordInfo {
+            key_len,
+            kind,
+            value_len,
+            len,
+        })))
+    }
+
+    fn record_key(&self, info: &RecordInfo) -> &[u8] {
+        &self.buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + info.key_len]
+    }
+
+    /// Offset and info of the newest record for `key` in `page`, searching from `from`
+    fn find_latest(
+        &mut self,
+        page: u32,
+        from: u32,
+        key: &[u8],
+    ) -> Result<Option<(u32, RecordInfo)>, KvStoreError> {
+        let end = if page == self.active {
+            self.free
+        } else {
+            self.page_size
+        };
+        let mut offset = from;
+        let mut found = None;
+        while offset < end {
+            match self.read_record(page, offset)? {
+                Some(Ok(info)) => {
+                    if self.record_key(&info) == key {
+                        found = Some((offset, info));
+                    }
+                    offset += info.len;
+                }
+                _ => break,
+            }
+        }
+        Ok(found)
+    }
+
+    fn append(&mut self, key: &[u8], kind: u8, value: &[u8]) -> Result<(), KvStoreError> {
+        let len = record_len(key.len(), value.len());
+        if self.free + len > self.page_size {
+            self.compact()?;
+            if self.free + len > self.page_size {
+                return Err(KvStoreError::Full);
+            }
+        }
+
+        let body = RECORD_HEADER_LEN + key.len() + value.len();
+        self.buf[0] = key.len() as u8;
+        self.buf[1] = kind;
+        self.buf[2..4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        self.buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + key.len()].copy_from_slice(key);
+        self.buf[RECORD_HEADER_LEN + key.len()..body].copy_from_slice(value);
+        let checksum = record_checksum(&self.buf[..body]);
+        self.buf[4..6].copy_from_slice(&checksum.to_le_bytes());
+        self.buf[body..len as usize].fill(ERASED);
+
+        let addr = self.page_addr(self.active) + self.free;
+        self.flash
+            .write(addr, &self.buf[..len as usize])
+            .map_err(|_| KvStoreError::Flash)?;
+        self.free += len;
+        Ok(())
+    }
+
+    /// Newest live key starting with `prefix`, copied into `key`, returning its length
+    fn find_live_with_prefix(
+        &mut self,
+        prefix: &[u8],
+        key: &mut [u8; KV_MAX_KEY_LEN],
+    ) -> Result<Option<usize>, KvStoreError> {
+        let mut offset = PAGE_HEADER_LEN;
+        while offset < self.free {
+            let info = match self.read_record(self.active, offset)? {
+                Some(Ok(info)) => info,
+                _ => break,
+            };
+            let next = offset + info.len;
+            if info.kind == KIND_VALUE && self.record_key(&info).starts_with(prefix) {
+                key[..info.key_len].copy_from_slice(self.record_key(&info));
+                let superseded = self
+                    .find_latest(self.active, next, &key[..info.key_len])?
+                    .is_some();
+                if !superseded {
+                    return Ok(Some(info.key_len));
+                }
+            }
+            offset = next;
+        }
+        Ok(None)
+    }
+
+    /// Copy the newest live record of every key into the other page and switch to it.
+    fn compact(&mut self) -> Result<(), KvStoreError> {
+        let from = self.active;
+        let to = 1 - from;
+        self.erase_page(to)?;
+
+        let mut src = PAGE_HEADER_LEN;
+        let mut dst = PAGE_HEADER_LEN;
+        while src < self.free {
+            let info = match self.read_record(from, src)? {
+                Some(Ok(info)) => info,
+                _ => break,
+            };
+            let next = src + info.len;
+
+            if info.kind == KIND_VALUE {
+                let mut key = [0u8; KV_MAX_KEY_LEN];
+                key[..info.key_len].copy_from_slice(self.record_key(&info));
+                let superseded = self
+                    .find_latest(from, next, &key[..info.key_len])?
+                    .is_some();
+                if !superseded {
+                    // find_latest clobbered the buffer, reload the record
+                    self.read_record(from, src)?;
+                    let addr = self.page_addr(to) + dst;
+                    self.flash
+                        .write(addr, &self.buf[..info.len as usize])
+                        .map_err(|_| KvStoreError::Flash)?;
+                    dst += info.len;
+                }
+            }
+            src = next;
+        }
+
+        let seq = self.seq.wrapping_add(1);
+        self.write_page_header(to, seq)?;
+        self.active = to;
+        self.seq = seq;
+        self.free = dst;
+        Ok(())
+    }
+
+    fn read_page_seq(&mut self, page: u32) -> Result<Option<u32>, KvStoreError> {
+        let mut header = [0u8; PAGE_HEADER_LEN as usize];
+        self.flash
+            .read(self.page_addr(page), &mut header)
+            .map_err(|_| KvStoreError::Flash)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let seq = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        Ok((magic == PAGE_MAGIC).then_some(seq))
+    }
+
+    fn write_page_header(&mut self, page: u32, seq: u32) -> Result<(), KvStoreError> {
+        let mut header = [0u8; PAGE_HEADER_LEN as usize];
+        header[..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&seq.to_le_bytes());
+        self.flash
+            .write(self.page_addr(page), &header)
+            .map_err(|_| KvStoreError::Flash)
+    }
+
+    fn erase_page(&mut self, page: u32) -> Result<(), KvStoreError> {
+        let addr = self.page_addr(page);
+        self.flash
+            .erase(addr, addr + self.page_size)
+            .map_err(|_| KvStoreError::Flash)
+    }
+
+    fn page_addr(&self, page: u32) -> u32 {
+        self.start + page * self.page_size
+    }
+}
+
+impl<F: NorFlash> KvStore for FlashKvStore<F> {
+    fn get(&mut self, key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvStoreError> {
+        validate_key(key)?;
+        let Some((offset, info)) =
+            self.find_latest(self.active, PAGE_HEADER_LEN, key.as_bytes())?
+        else {
+            return Ok(None);
+        };
+        if info.kind == KIND_TOMBSTONE {
+            return Ok(None);
+        }
+        if buf.len() < info.value_len {
+            return Err(KvStoreError::BufferTooSmall(info.value_len));
+        }
+
+        self.read_record(self.active, offset)?;
+        let value_start = RECORD_HEADER_LEN + info.key_len;
+        buf[..info.value_len].copy_from_slice(&self.buf[value_start..value_start + info.value_len]);
+        Ok(Some(info.value_len))
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), KvStoreError> {
+        validate_key(key)?;
+        if value.len() > KV_MAX_VALUE_LEN {
+            return Err(KvStoreError::ValueTooLong);
+        }
+        self.append(key.as_bytes(), KIND_VALUE, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), KvStoreError> {
+        validate_key(key)?;
+        match self.find_latest(self.active, PAGE_HEADER_LEN, key.as_bytes())? {
+            Some((_, info)) if info.kind == KIND_VALUE => {
+                self.append(key.as_bytes(), KIND_TOMBSTONE, &[])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_prefix(&mut self, prefix: &str) -> Result<usize, KvStoreError> {
+        let mut key = [0u8; KV_MAX_KEY_LEN];
+        let mut removed = 0;
+        while let Some(len) = self.find_live_with_prefix(prefix.as_bytes(), &mut key)? {
+            self.append(&key[..len], KIND_TOMBSTONE, &[])?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Compact twice, so both pages are erased and only live records are left in flash.
+    fn purge(&mut self) -> Result<(), KvStoreError> {
+        self.compact()?;
+        self.compact()
+    }
+}
+
+fn validate_key(key: &str) -> Result<(), KvStoreError> {
+    if key.is_empty() || key.len() > KV_MAX_KEY_LEN {
+        return Err(KvStoreError::InvalidKey);
+    }
+    Ok(())
+}
+
+fn record_len(key_len: usize, value_len: usize) -> u32 {
+    let len = (RECORD_HEADER_LEN + key_len + value_len) as u32;
+    len.div_ceil(RECORD_ALIGN) * RECORD_ALIGN
+}
+
+/// CRC-16 over the record, skipping the checksum field itself
+fn record_checksum(record: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(&record[..4]);
+    crc.update(&record[RECORD_HEADER_LEN..]);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+    const PAGE: u32 = 1024;
+
+    #[derive(Debug)]
+    struct MockFlashError;
+
+    impl NorFlashError for MockFlashError {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    struct MockFlash {
+        data: [u8; 2 * PAGE as usize],
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            Self {
+                data: [0xFF; 2 * PAGE as usize],
+            }
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = MockFlashError;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            bytes.copy_from_slice(&self.data[start..start + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 512;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            for (dst, src) in self.data[start..start + bytes.len()].iter_mut().zip(bytes) {
+                // NOR flash can only clear bits
+                *dst &= *src;
+            }
+            Ok(())
+        }
+    }
+
+    fn get_str<'a, F: NorFlash>(
+        store: &mut FlashKvStore<F>,
+        key: &str,
+        buf: &'a mut [u8],
+    ) -> Option<&'a str> {
+        let len = store.get(key, buf).unwrap()?;
+        Some(core::str::from_utf8(&buf[..len]).unwrap())
+    }
+
+    #[test]
+    fn test_set_get_overwrite_remove() {
+        let mut store = FlashKvStore::new(MockFlash::new(), 0, PAGE).unwrap();
+        let mut buf = [0u8; 16];
+
+        assert_eq!(get_str(&mut store, "name", &mut buf), None);
+        store.set("name", b"pico").unwrap();
+        store.set("other", b"x").unwrap();
+        store.set("name", b"pico-w").unwrap();
+        assert_eq!(get_str(&mut store, "name", &mut buf), Some("pico-w"));
+
+        store.remove("name").unwrap();
+        assert_eq!(get_str(&mut store, "name", &mut buf), None);
+        assert_eq!(get_str(&mut store, "other", &mut buf), Some("x"));
+    }
+
+    #[test]
+    fn test_persists_across_reopen() {
+        let mut store = FlashKvStore::new(MockFlash::new(), 0, PAGE).unwrap();
+        store.set("a", &[1, 2, 3]).unwrap();
+        let flash = store.release();
+
+        let mut store = FlashKvStore::new(flash, 0, PAGE).unwrap();
+        assert_eq!(store.get_array::<3>("a").unwrap(), Some([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_compaction_keeps_latest_values() {
+        let mut store = FlashKvStore::new(MockFlash::new(), 0, PAGE).unwrap();
+        store.set("keep", b"value").unwrap();
+        for i in 0..200u32 {
+            store.set("counter", &i.to_le_bytes()).unwrap();
+        }
+        assert_eq!(
+            store.get_array::<4>("counter").unwrap(),
+            Some(199u32.to_le_bytes())
+        );
+
+        let flash = store.release();
+        let mut store = FlashKvStore::new(flash, 0, PAGE).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(get_str(&mut store, "keep", &mut buf), Some("value"));
+        assert_eq!(
+            store.get_array::<4>("counter").unwrap(),
+            Some(199u32.to_le_bytes())
+        );
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut store = FlashKvStore::new(MockFlash::new(), 0, PAGE).unwrap();
+        store.set("k", b"long value").unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            store.get("k", &mut buf),
+            Err(KvStoreError::BufferTooSmall(10))
+        );
+    }
+
+    #[test]
+    fn test_remove_prefix_and_purge() {
+        let mut store = FlashKvStore::new(MockFlash::new(), 0, PAGE).unwrap();
+        store.set("cred/a", b"secret-a").unwrap();
+        store.set("cred/b", b"old").unwrap();
+        store.set("cred/b", b"secret-b").unwrap();
+        store.set("other", b"x").unwrap();
+
+        assert_eq!(store.remove_prefix("cred/").unwrap(), 2);
+        assert_eq!(store.remove_prefix("cred/").unwrap(), 0);
+        let mut buf = [0u8; 16];
+        assert_eq!(get_str(&mut store, "cred/a", &mut buf), None);
+        assert_eq!(get_str(&mut store, "cred/b", &mut buf), None);
+
+        store.purge().unwrap();
+        assert_eq!(get_str(&mut store, "other", &mut buf), Some("x"));
+        let flash = store.release();
+        let secret = b"secret";
+        assert!(!flash.data.windows(secret.len()).any(|w| w == secret));
+    }
+}
@@ -0,0 +1,9 @@
+mod config_file;
+mod credential_store;
+mod data_logger;
+mod flash_kv_store;
+
+pub use config_file::*;
+pub use credential_store::*;
+pub use data_logger::*;
+pub use flash_kv_store::*;
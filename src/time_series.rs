@@ -0,0 +1,332 @@
+//! time_series.rs — fixed-size buffer of timestamped samples for charts and uploads
+//!
+//! [`TimeSeries`] keeps the last `N` samples, overwriting the oldest when full. For a chart,
+//! [`TimeSeries::downsample`] folds them into one [`Bucket`] (min, max, average) per
+//! column, so a 128-pixel plot of 2000 samples still shows spikes. For uploads, it tracks
+//! which samples haven't been sent: [`TimeSeries::write_batch`] writes the oldest of them
+//! as compact JSON and [`TimeSeries::mark_sent`] drops them from the backlog once the
+//! broker or server accepted them, so readings taken during an outage go out afterwards.
+//!
+//! Timestamps are `u64` in whatever unit the caller picks; Unix seconds suit uploads,
+//! uptime milliseconds suit local charts.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut series = TimeSeries::<1440>::new();
+//! series.push(now_unix, celsius);
+//!
+//! let mut columns = [None; 128];
+//! series.downsample(&mut columns);
+//!
+//! let mut payload: HeaplessString<255> = HeaplessString::new();
+//! let sent = series.write_batch(&mut payload, 32)?;
+//! if mqtt.publish("greenhouse/temp", payload.as_str().as_bytes()).await.is_ok() {
+//!     series.mark_sent(sent);
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+
+use crate::HeaplessString;
+
+/// One reading
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub struct Sample {
+    pub timestamp: u64,
+    pub value: f32,
+}
+
+/// Summary of the samples falling into one time slot
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Bucket {
+    /// Timestamp of the first sample in the bucket
+    pub start: u64,
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+    pub count: u16,
+}
+
+/// The last `N` samples, oldest first
+pub struct TimeSeries<const N: usize> {
+    samples: [Sample; N],
+    /// Index of the oldest sample
+    head: usize,
+    len: usize,
+    /// Newest samples not yet uploaded
+    unsent: usize,
+    /// Unsent samples overwritten before they could be uploaded
+    lost: u32,
+}
+
+impl<const N: usize> TimeSeries<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: [Sample {
+                timestamp: 0,
+                value: 0.0,
+            }; N],
+            head: 0,
+            len: 0,
+            unsent: 0,
+            lost: 0,
+        }
+    }
+
+    /// Append a sample. Timestamps should not go backwards.
+    pub fn push(&mut self, timestamp: u64, value: f32) {
+        if N == 0 {
+            return;
+        }
+        let sample = Sample { timestamp, value };
+        if self.len < N {
+            self.samples[(self.head + self.len) % N] = sample;
+            self.len += 1;
+        } else {
+            self.samples[self.head] = sample;
+            self.head = (self.head + 1) % N;
+        }
+        if self.unsent == N {
+            self.lost = self.lost.saturating_add(1);
+        } else {
+            self.unsent += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.unsent = 0;
+    }
+
+    /// Sample `index` counted from the oldest
+    pub fn get(&self, index: usize) -> Option<Sample> {
+        (index < self.len).then(|| self.samples[(self.head + index) % N])
+    }
+
+    pub fn latest(&self) -> Option<Sample> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = Sample> + '_ {
+        (0..self.len).map(|i| self.samples[(self.head + i) % N])
+    }
+
+    /// Fold the samples into `buckets.len()` equal time slots spanning the oldest to the
+    /// newest timestamp. Slots without samples are `None` (gaps in the chart).
+    pub fn downsample(&self, buckets: &mut [Option<Bucket>]) {
+        match (self.get(0), self.latest()) {
+            (Some(first), Some(last)) => {
+                self.downsample_range(first.timestamp, last.timestamp.saturating_add(1), buckets)
+            }
+            _ => buckets.fill(None),
+        }
+    }
+
+    /// Like [`downsample`](Self::downsample) for timestamps in `from..to`, e.g. a fixed
+    /// "last hour" window.
+    pub fn downsample_range(&self, from: u64, to: u64, buckets: &mut [Option<Bucket>]) {
+        buckets.fill(None);
+        let span = to.saturating_sub(from) as u128;
+        if buckets.is_empty() || span == 0 {
+            return;
+        }
+        for sample in self.iter().filter(|s| (from..to).contains(&s.timestamp)) {
+            let slot = ((sample.timestamp - from) as u128 * buckets.len() as u128 / span) as usize;
+            let bucket = buckets[slot].get_or_insert(Bucket {
+                start: sample.timestamp,
+                min: sample.value,
+                max: sample.value,
+                avg: 0.0,
+                count: 0,
+            });
+            bucket.min = bucket.min.min(sample.value);
+            bucket.max = bucket.max.max(sample.value);
+            // Running mean, so no separate sum has to be kept per bucket
+            bucket.count = bucket.count.saturating_add(1);
+            bucket.avg += (sample.value - bucket.avg) / bucket.count as f32;
+        }
+    }
+
+    /// Samples waiting to be uploaded
+    pub fn unsent(&self) -> usize {
+        self.unsent
+    }
+
+    /// Unsent samples that were overwritten because the backlog was full
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Oldest unsent samples first
+    pub fn unsent_iter(&self) -> impl Iterator<Item = Sample> + '_ {
+        (self.len - self.unsent..self.len).map(|i| self.samples[(self.head + i) % N])
+    }
+
+    /// Write up to `max_samples` of the oldest unsent samples as
+    /// `{"t0":1760620800,"dt":[0,60,60],"v":[21.5,21.6,21.4]}` (each timestamp as the
+    /// delta to the previous one) and return how many were written. The batch stops at the
+    /// last sample that fits in `out`, so the count always matches what was written. Fails
+    /// if not even one sample fits. Nothing is marked sent; call
+    /// [`mark_sent`](Self::mark_sent) once the upload succeeded.
+    pub fn write_batch<const M: usize>(
+        &self,
+        out: &mut HeaplessString<M>,
+        max_samples: usize,
+    ) -> Result<usize, fmt::Error> {
+        let wanted = self.unsent.min(max_samples);
+        let t0 = match self.unsent_iter().next() {
+            Some(first) if wanted > 0 => first.timestamp,
+            _ => 0,
+        };
+
+        // `HeaplessString` drops what doesn't fit without an error, so measure first
+        let room = M.min(u8::MAX as usize).saturating_sub(out.len());
+        let mut len = text_len(format_args!("{{\"t0\":{t0},\"dt\":[],\"v\":[]}}"));
+        let mut count = 0;
+        let mut previous = t0;
+        for sample in self.unsent_iter().take(wanted) {
+            let separators = if count > 0 { 2 } else { 0 };
+            let delta = sample.timestamp.saturating_sub(previous);
+            let sample_len = separators + text_len(format_args!("{delta}{}", sample.value));
+            if len + sample_len > room {
+                break;
+            }
+            len += sample_len;
+            count += 1;
+            previous = sample.timestamp;
+        }
+        if len > room || (count == 0 && wanted > 0) {
+            return Err(fmt::Error);
+        }
+
+        write!(out, "{{\"t0\":{t0},\"dt\":[")?;
+        let mut previous = t0;
+        for (i, sample) in self.unsent_iter().take(count).enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            write!(out, "{}", sample.timestamp.saturating_sub(previous))?;
+            previous = sample.timestamp;
+        }
+        out.write_str("],\"v\":[")?;
+        for (i, sample) in self.unsent_iter().take(count).enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            write!(out, "{}", sample.value)?;
+        }
+        out.write_str("]}")?;
+        Ok(count)
+    }
+
+    /// Drop the `count` oldest unsent samples from the upload backlog.
+    pub fn mark_sent(&mut self, count: usize) {
+        self.unsent -= count.min(self.unsent);
+    }
+}
+
+impl<const N: usize> Default for TimeSeries<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Length of the formatted text
+fn text_len(args: fmt::Arguments<'_>) -> usize {
+    struct Counter(usize);
+
+    impl Write for Counter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = counter.write_fmt(args);
+    counter.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_keeps_newest_samples() {
+        let mut series = TimeSeries::<3>::new();
+        for t in 0..5 {
+            series.push(t, t as f32);
+        }
+        assert_eq!(series.len(), 3);
+        assert_eq!(series.get(0).map(|s| s.timestamp), Some(2));
+        assert_eq!(series.latest().map(|s| s.value), Some(4.0));
+        assert_eq!(series.lost(), 2);
+    }
+
+    #[test]
+    fn downsample_keeps_extremes_and_gaps() {
+        let mut series = TimeSeries::<16>::new();
+        for (t, v) in [(0, 1.0), (1, 9.0), (2, 2.0), (3, 4.0), (7, 5.0)] {
+            series.push(t, v);
+        }
+        let mut buckets = [None; 4];
+        series.downsample(&mut buckets);
+        let first = buckets[0].unwrap();
+        assert_eq!(
+            (first.min, first.max, first.avg, first.count),
+            (1.0, 9.0, 5.0, 2)
+        );
+        assert_eq!(buckets[1].unwrap().count, 2);
+        assert_eq!(buckets[2], None);
+        assert_eq!(buckets[3].unwrap().start, 7);
+    }
+
+    #[test]
+    fn upload_batches() {
+        let mut series = TimeSeries::<8>::new();
+        for (t, v) in [(100, 21.5), (160, 21.75), (220, 22.0)] {
+            series.push(t, v);
+        }
+        let mut out: HeaplessString<64> = HeaplessString::new();
+        assert_eq!(series.write_batch(&mut out, 2), Ok(2));
+        assert_eq!(out.as_str(), r#"{"t0":100,"dt":[0,60],"v":[21.5,21.75]}"#);
+        series.mark_sent(2);
+
+        out.clear();
+        series.push(280, 22.25);
+        assert_eq!(series.write_batch(&mut out, 10), Ok(2));
+        assert_eq!(out.as_str(), r#"{"t0":220,"dt":[0,60],"v":[22,22.25]}"#);
+        series.mark_sent(2);
+        assert_eq!(series.unsent(), 0);
+    }
+
+    #[test]
+    fn batch_stops_at_what_fits() {
+        let mut series = TimeSeries::<8>::new();
+        for (t, v) in [(100, 21.5), (160, 21.75), (220, 22.0)] {
+            series.push(t, v);
+        }
+        let mut out: HeaplessString<32> = HeaplessString::new();
+        assert_eq!(series.write_batch(&mut out, 10), Ok(1));
+        assert_eq!(out.as_str(), r#"{"t0":100,"dt":[0],"v":[21.5]}"#);
+
+        let mut tiny: HeaplessString<16> = HeaplessString::new();
+        assert_eq!(series.write_batch(&mut tiny, 10), Err(fmt::Error));
+        assert_eq!(series.unsent(), 3);
+    }
+}
@@ -0,0 +1,144 @@
+//! uptime.rs — swappable monotonic time source for timing logic
+//!
+//! Code that measures time asks an [`Uptime`] instead of calling `Instant::now()` directly,
+//! and code that waits sleeps on it instead of on an embassy `Timer`. On the device that is
+//! [`SystemUptime`] (the embassy time driver); in host tests it is a [`MockUptime`] that
+//! only moves when the test says so, which makes timeouts, laps and rate limits
+//! reproducible without hardware. A sleep on the mock jumps its time to the deadline and
+//! returns at once. `&MockUptime` is an [`Uptime`] too, so the test keeps the mock and
+//! hands out references.
+//!
+//! [`Stopwatch`](crate::Stopwatch), [`CountdownTimer`](crate::CountdownTimer), the
+//! [`Ui`](crate::Ui) screensaver, [`LoopMonitor`](crate::LoopMonitor),
+//! [`ExecutorMonitor`](crate::ExecutorMonitor) and the [`LogsDisplay`](crate::LogsDisplay)
+//! redraw limit read their time this way; [`Button`](crate::Button) debouncing and click
+//! windows, [`RepeatingButton`](crate::RepeatingButton) key repeat,
+//! [`ButtonGroup`](crate::ButtonGroup) chords, [`MatrixKeypad`](crate::MatrixKeypad) scans
+//! and the [`Clock`](crate::Clock) tick and alarms also sleep on it. Logic that takes the
+//! time as an argument, like [`TypematicKeyboard`](crate::TypematicKeyboard), needs no
+//! source.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let time = MockUptime::new();
+//! let mut stopwatch = Stopwatch::with_uptime(&time);
+//! stopwatch.start();
+//! time.advance(Duration::from_millis(1500));
+//! assert_eq!(stopwatch.elapsed(), Duration::from_millis(1500));
+//! ```
+
+use core::cell::Cell;
+
+use embassy_time::{Duration, Instant, Timer};
+
+/// Source of monotonic timestamps, and sleeps measured against them
+#[allow(async_fn_in_trait)]
+pub trait Uptime {
+    fn now(&self) -> Instant;
+
+    /// Return once `now()` has reached `deadline`; at once if it already has.
+    async fn sleep_until(&self, deadline: Instant);
+
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await
+    }
+}
+
+impl<U: Uptime + ?Sized> Uptime for &U {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        (**self).sleep_until(deadline).await
+    }
+}
+
+/// The embassy time driver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemUptime;
+
+impl Uptime for SystemUptime {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        Timer::at(deadline).await
+    }
+}
+
+/// Manually driven time for tests and replays; starts at zero.
+#[derive(Debug)]
+pub struct MockUptime {
+    now: Cell<Instant>,
+}
+
+impl MockUptime {
+    pub const fn new() -> Self {
+        Self::starting_at(Instant::from_ticks(0))
+    }
+
+    pub const fn starting_at(start: Instant) -> Self {
+        Self {
+            now: Cell::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    /// Jump to `instant`; going backwards is allowed, so replays can rewind.
+    pub fn set(&self, instant: Instant) {
+        self.now.set(instant);
+    }
+}
+
+impl Default for MockUptime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uptime for MockUptime {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        if deadline > self.now.get() {
+            self.now.set(deadline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_moves_only_when_told() {
+        let time = MockUptime::new();
+        let start = time.now();
+        assert_eq!(time.elapsed_since(start), Duration::MIN);
+        time.advance(Duration::from_millis(250));
+        assert_eq!(time.elapsed_since(start), Duration::from_millis(250));
+        time.set(start);
+        assert_eq!(time.now(), start);
+    }
+
+    #[test]
+    fn mock_sleeps_jump_to_the_deadline() {
+        let time = MockUptime::new();
+        embassy_futures::block_on(time.sleep(Duration::from_millis(20)));
+        assert_eq!(time.now(), Instant::from_millis(20));
+        embassy_futures::block_on((&time).sleep_until(Instant::from_millis(5)));
+        assert_eq!(time.now(), Instant::from_millis(20));
+    }
+}